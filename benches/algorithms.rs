@@ -0,0 +1,204 @@
+//! Benchmarks of the core algorithms, run in a `VirtualNet`.
+//!
+//! Each benchmark builds a network of _N_ nodes and measures the wall time until every node has
+//! output. Before timing it, every configuration is run once and its number of cranks and
+//! messages, and the total size of the messages in bytes, are printed, so that changes in the
+//! message complexity show up next to changes in the running time.
+//!
+//! Run with `cargo bench`; filter with e.g. `cargo bench -- honey_badger`.
+
+extern crate bincode;
+#[macro_use]
+extern crate criterion;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+#[path = "../tests/net/mod.rs"]
+pub mod net;
+
+use std::fmt;
+use std::sync::Arc;
+
+use criterion::Criterion;
+use serde::Serialize;
+
+use hbbft::binary_agreement::BinaryAgreement;
+use hbbft::broadcast::Broadcast;
+use hbbft::honey_badger::HoneyBadger;
+use hbbft::DistAlgorithm;
+use net::{NetBuilder, VirtualNet};
+
+type UsizeHoneyBadger = HoneyBadger<Vec<usize>, usize>;
+
+/// The network sizes to benchmark.
+const NET_SIZES: &[usize] = &[4, 16, 64];
+
+/// The payload sizes to benchmark: bytes for Broadcast, numbers of `usize`s for Honey Badger.
+const PAYLOAD_SIZES: &[usize] = &[16, 1024];
+
+/// The maximum number of cranks any single run is allowed to take.
+const MAX_CRANKS: usize = 100_000_000;
+
+/// A benchmark configuration: the number of nodes and the payload size.
+#[derive(Clone, Copy)]
+struct Params {
+    size: usize,
+    payload: usize,
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "N={}/payload={}", self.size, self.payload)
+    }
+}
+
+/// Returns all combinations of network and payload sizes.
+fn all_params() -> Vec<Params> {
+    let mut params = Vec::new();
+    for &size in NET_SIZES {
+        for &payload in PAYLOAD_SIZES {
+            params.push(Params { size, payload });
+        }
+    }
+    params
+}
+
+/// Returns a network builder with statistics and no limits, and without writing traces.
+fn net_builder<D>(size: usize) -> NetBuilder<D, ::std::ops::Range<usize>>
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone + Serialize,
+    D::Output: Clone + fmt::Debug,
+{
+    NetBuilder::new(0..size)
+        .no_time_limit()
+        .trace(false)
+        .stats()
+}
+
+/// Cranks the network until all nodes have output, and returns the number of cranks.
+fn run<D>(mut net: VirtualNet<D>) -> (usize, VirtualNet<D>)
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    let cranks = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("network did not terminate");
+    (cranks, net)
+}
+
+/// Runs the network once and prints its message statistics.
+fn report<D>(name: &str, params: Params, net: VirtualNet<D>)
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    let (cranks, net) = run(net);
+    let total = net.stats().total();
+    println!(
+        "{} {:?}: {} cranks, {} messages, {} bytes",
+        name, params, cranks, total.messages, total.bytes
+    );
+}
+
+/// Creates a network of Binary Agreement instances with mixed inputs.
+fn new_binary_agreement_net(params: Params) -> VirtualNet<BinaryAgreement<usize>> {
+    let mut net = net_builder(params.size)
+        .using(|node| {
+            BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+    for id in 0..params.size {
+        let _ = net
+            .send_input(id, id % 2 == 0)
+            .expect("could not send input");
+    }
+    net
+}
+
+/// Creates a network of Broadcast instances, in which node 0 proposes a value.
+fn new_broadcast_net(params: Params) -> VirtualNet<Broadcast<usize>> {
+    let mut net = net_builder(params.size)
+        .using(|node| {
+            Broadcast::new(Arc::new(node.netinfo), 0).expect("could not create broadcast")
+        }).build()
+        .expect("could not construct test network");
+    let _ = net
+        .send_input(0, vec![0xAB; params.payload])
+        .expect("could not send input");
+    net
+}
+
+/// Creates a network of Honey Badger instances that have all proposed, for a single epoch.
+fn new_honey_badger_net(params: Params) -> VirtualNet<UsizeHoneyBadger> {
+    let mut net = net_builder(params.size)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+    for id in 0..params.size {
+        let _ = net
+            .send_input(id, vec![id; params.payload])
+            .expect("could not send input");
+    }
+    net
+}
+
+fn binary_agreement(c: &mut Criterion) {
+    let params: Vec<Params> = NET_SIZES
+        .iter()
+        .map(|&size| Params { size, payload: 1 })
+        .collect();
+    for &p in &params {
+        report("binary_agreement", p, new_binary_agreement_net(p));
+    }
+    c.bench_function_over_inputs(
+        "binary_agreement",
+        |b, &p| b.iter_with_setup(|| new_binary_agreement_net(p), run),
+        params,
+    );
+}
+
+fn broadcast(c: &mut Criterion) {
+    for p in all_params() {
+        report("broadcast", p, new_broadcast_net(p));
+    }
+    c.bench_function_over_inputs(
+        "broadcast",
+        |b, &p| b.iter_with_setup(|| new_broadcast_net(p), run),
+        all_params(),
+    );
+}
+
+fn honey_badger(c: &mut Criterion) {
+    for p in all_params() {
+        report("honey_badger", p, new_honey_badger_net(p));
+    }
+    c.bench_function_over_inputs(
+        "honey_badger",
+        |b, &p| b.iter_with_setup(|| new_honey_badger_net(p), run),
+        all_params(),
+    );
+}
+
+criterion_group! {
+    name = benches;
+    // Large networks take seconds per run, so we take fewer samples than the default.
+    config = Criterion::default().sample_size(10);
+    targets = binary_agreement, broadcast, honey_badger
+}
+criterion_main!(benches);
@@ -122,6 +122,20 @@ impl<M: Send> Messaging<M> {
                                         }
                                     }).map_err(Error::from);
                             },
+                            Target::AllExcept(exclude) => {
+                                // Send the message to all remote nodes that are not
+                                // excluded, stopping at the first error.
+                                result = txs_to_comms.iter()
+                                    .enumerate()
+                                    .filter(|&(i, _)| !exclude.contains(&i))
+                                    .fold(Ok(()), |result, (_, tx)| {
+                                        if result.is_ok() {
+                                            tx.send(tm.message.clone())
+                                        } else {
+                                            result
+                                        }
+                                    }).map_err(Error::from);
+                            },
                             Target::Node(i) => {
                                 result = if i < txs_to_comms.len() {
                                     txs_to_comms[i].send(tm.message)
@@ -130,6 +144,23 @@ impl<M: Send> Messaging<M> {
                                     Err(Error::NoSuchTarget)
                                 };
                             }
+                            Target::Nodes(ids) => {
+                                // Send the message to the given nodes, stopping at the
+                                // first error.
+                                for i in ids {
+                                    result = if i < txs_to_comms.len() {
+                                        txs_to_comms[i].send(tm.message.clone())
+                                            .map_err(Error::from)
+                                    } else {
+                                        Err(Error::NoSuchTarget)
+                                    };
+                                    if result.is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                            // All nodes in the example network are validators.
+                            Target::Observers => (),
                         }
                     },
                     recv(rx_from_comms, message) => {
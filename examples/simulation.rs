@@ -5,8 +5,6 @@ extern crate env_logger;
 extern crate hbbft;
 extern crate itertools;
 extern crate rand;
-#[macro_use]
-extern crate rand_derive;
 extern crate serde;
 #[macro_use(Deserialize, Serialize)]
 extern crate serde_derive;
@@ -44,6 +42,8 @@ Options:
   -f <f>, --faulty <f>    The number of faulty nodes [default: 0]
   -t <txs>, --txs <txs>   The number of transactions to process [default: 1000]
   -b <b>, --batch <b>     The batch size, i.e. txs per epoch [default: 100]
+  --window <w>            The number of queued txs to sample proposals from, if not b
+  --min-queue <q>         The number of queued txs needed to propose [default: 1]
   -l <lag>, --lag <lag>   The network lag between sending and receiving [default: 100]
   --bw <bw>               The bandwidth, in kbit/s [default: 2000]
   --cpu <cpu>             The CPU speed, in percent of this machine's [default: 100]
@@ -56,6 +56,8 @@ struct Args {
     flag_f: usize,
     flag_txs: usize,
     flag_b: usize,
+    flag_window: Option<usize>,
+    flag_min_queue: usize,
     flag_lag: u64,
     flag_bw: u32,
     flag_cpu: f32,
@@ -63,7 +65,7 @@ struct Args {
 }
 
 /// A node identifier. In the simulation, nodes are simply numbered.
-#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy, Rand)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy)]
 pub struct NodeId(pub usize);
 
 /// A transaction.
@@ -297,11 +299,26 @@ where
                         }
                     }
                 }
+                Target::AllExcept(ref exclude) => {
+                    for node in self.nodes.values_mut() {
+                        if node.id != ts_msg.sender_id && !exclude.contains(&node.id) {
+                            node.add_message(ts_msg.clone())
+                        }
+                    }
+                }
                 Target::Node(to_id) => {
                     if let Some(node) = self.nodes.get_mut(&to_id) {
                         node.add_message(ts_msg);
                     }
                 }
+                Target::Nodes(ref ids) => {
+                    for to_id in ids {
+                        if let Some(node) = self.nodes.get_mut(to_id) {
+                            node.add_message(ts_msg.clone());
+                        }
+                    }
+                }
+                Target::Observers => (), // All simulated nodes are validators.
             }
         }
     }
@@ -396,7 +413,7 @@ fn simulate_honey_badger(
     let mut epochs = Vec::new();
     while let Some(id) = network.step() {
         for &(time, ref batch) in &network.nodes[&id].outputs {
-            let epoch = batch.epoch() as usize;
+            let epoch = batch.epoch().get() as usize;
             if epochs.len() <= epoch {
                 epochs.resize(epoch + 1, EpochInfo::default());
             }
@@ -437,8 +454,13 @@ fn main() {
         .collect();
     let new_honey_badger = |netinfo: NetworkInfo<NodeId>| {
         let dyn_hb = DynamicHoneyBadger::builder().build(netinfo);
-        QueueingHoneyBadger::builder(dyn_hb)
+        let mut builder = QueueingHoneyBadger::builder(dyn_hb)
             .batch_size(args.flag_b)
+            .min_queue_depth(args.flag_min_queue);
+        if let Some(window) = args.flag_window {
+            builder = builder.sample_window(window);
+        }
+        builder
             .build_with_transactions(txs.clone(), rand::thread_rng().gen::<Isaac64Rng>())
             .expect("instantiate QueueingHoneyBadger")
     };
@@ -0,0 +1,280 @@
+//! # Asynchronous driver
+//!
+//! A wrapper that runs a `DistAlgorithm` instance and communicates with the application via
+//! channels, so that networking code doesn't need to share the instance.
+//!
+//! The `Driver` owns the instance, so no locking is required. The application sends inputs and
+//! incoming messages via the `DriverHandle`'s `events` channel, and receives the outgoing
+//! messages, outputs and detected faults from its other channels. The driver doesn't depend on
+//! any executor: `Driver::poll` handles all pending events without blocking and returns the time
+//! at which it needs to be polled again for its timers, and `Driver::run` does the same in a loop,
+//! blocking the current thread.
+//!
+//! A timer can be set to periodically provide input, e.g. to propose a new contribution to Honey
+//! Badger at regular intervals. The driver also handles the timers requested by the instance in
+//! its steps, with a configurable tick duration.
+//!
+//! If the receiving end of one of the outgoing channels has been dropped, the corresponding
+//! messages, outputs or faults are discarded.
+
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+use fault_log::Fault;
+use {DistAlgorithm, Step, TargetedMessage};
+
+/// The default duration of a tick of the timers requested by the instance.
+pub const DEFAULT_TICK_DURATION: Duration = Duration::from_millis(100);
+
+/// An event to be handled by the algorithm instance.
+pub enum Event<D: DistAlgorithm> {
+    /// An input provided by the application.
+    Input(D::Input),
+    /// A message received from the given node.
+    Message(D::NodeId, D::Message),
+}
+
+/// The state of a `Driver` after polling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// All pending events have been handled. If this contains a deadline, the driver needs to be
+    /// polled again at that time for its timers.
+    Pending(Option<Instant>),
+    /// The algorithm instance has terminated.
+    Terminated,
+    /// All senders of events have been dropped.
+    Disconnected,
+}
+
+/// A function that is called by the timer and returns the input to provide, if any.
+type TimerFn<D> = Box<dyn FnMut(&D) -> Option<<D as DistAlgorithm>::Input> + Send>;
+
+/// A periodic timer that provides input to the instance.
+struct Timer<D: DistAlgorithm> {
+    /// The time between two invocations.
+    interval: Duration,
+    /// The time of the next invocation.
+    deadline: Instant,
+    /// Returns the input to provide when the timer expires.
+    make_input: TimerFn<D>,
+}
+
+/// The application's end of the channels of a `Driver`.
+pub struct DriverHandle<D: DistAlgorithm> {
+    /// Sends inputs and incoming messages to the driver.
+    pub events: Sender<Event<D>>,
+    /// Receives the outgoing messages.
+    pub messages: Receiver<TargetedMessage<D::Message, D::NodeId>>,
+    /// Receives the instance's outputs.
+    pub outputs: Receiver<D::Output>,
+    /// Receives the faults detected in other nodes.
+    pub faults: Receiver<Fault<D::NodeId, D::FaultKind>>,
+}
+
+/// A wrapper around a `DistAlgorithm` instance that handles events from a channel and sends the
+/// results to other channels.
+pub struct Driver<D: DistAlgorithm> {
+    /// The algorithm instance.
+    algo: D,
+    /// Receives inputs and incoming messages.
+    events: Receiver<Event<D>>,
+    /// Sends the outgoing messages.
+    messages: Sender<TargetedMessage<D::Message, D::NodeId>>,
+    /// Sends the instance's outputs.
+    outputs: Sender<D::Output>,
+    /// Sends the faults detected in other nodes.
+    faults: Sender<Fault<D::NodeId, D::FaultKind>>,
+    /// The timer providing periodic input, if any.
+    timer: Option<Timer<D>>,
+    /// The duration of a tick of the timers requested by the instance.
+    tick_duration: Duration,
+    /// The expiry times and tokens of the timers requested by the instance.
+    timeouts: Vec<(Instant, u64)>,
+}
+
+impl<D> fmt::Debug for Driver<D>
+where
+    D: DistAlgorithm + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Driver")
+            .field("algo", &self.algo)
+            .field("deadline", &self.deadline())
+            .finish()
+    }
+}
+
+impl<D: DistAlgorithm> Driver<D> {
+    /// Creates a driver for the given instance, and returns it together with the application's
+    /// end of its channels.
+    pub fn new(algo: D) -> (Self, DriverHandle<D>) {
+        let (events_tx, events_rx) = mpsc::channel();
+        let (messages_tx, messages_rx) = mpsc::channel();
+        let (outputs_tx, outputs_rx) = mpsc::channel();
+        let (faults_tx, faults_rx) = mpsc::channel();
+        let driver = Driver {
+            algo,
+            events: events_rx,
+            messages: messages_tx,
+            outputs: outputs_tx,
+            faults: faults_tx,
+            timer: None,
+            tick_duration: DEFAULT_TICK_DURATION,
+            timeouts: Vec::new(),
+        };
+        let handle = DriverHandle {
+            events: events_tx,
+            messages: messages_rx,
+            outputs: outputs_rx,
+            faults: faults_rx,
+        };
+        (driver, handle)
+    }
+
+    /// Sets a timer that calls `make_input` every `interval`, starting one interval from now, and
+    /// inputs the returned value, if any. This replaces any previously set timer.
+    pub fn set_timer<F>(&mut self, interval: Duration, make_input: F)
+    where
+        F: FnMut(&D) -> Option<D::Input> + Send + 'static,
+    {
+        self.timer = Some(Timer {
+            interval,
+            deadline: Instant::now() + interval,
+            make_input: Box::new(make_input),
+        });
+    }
+
+    /// Sets the duration of a tick of the timers requested by the instance. This only affects
+    /// timers requested later.
+    pub fn set_tick_duration(&mut self, tick_duration: Duration) {
+        self.tick_duration = tick_duration;
+    }
+
+    /// Returns a reference to the algorithm instance.
+    pub fn algo(&self) -> &D {
+        &self.algo
+    }
+
+    /// Returns the algorithm instance, dropping the driver and its channels.
+    pub fn into_algo(self) -> D {
+        self.algo
+    }
+
+    /// Returns the time at which the next timer expires, if any are set.
+    pub fn deadline(&self) -> Option<Instant> {
+        let timer_deadline = self.timer.as_ref().map(|timer| timer.deadline);
+        let timeouts = self.timeouts.iter().map(|&(deadline, _)| deadline);
+        timer_deadline.into_iter().chain(timeouts).min()
+    }
+
+    /// Fires the timers that have expired at time `now`, and handles all pending events, without
+    /// blocking.
+    pub fn poll(&mut self, now: Instant) -> Result<Status, D::Error> {
+        self.fire_timer(now)?;
+        self.fire_timeouts(now)?;
+        loop {
+            if self.algo.terminated() {
+                return Ok(Status::Terminated);
+            }
+            match self.events.try_recv() {
+                Ok(event) => self.handle_event(event, now)?,
+                Err(TryRecvError::Empty) => return Ok(Status::Pending(self.deadline())),
+                Err(TryRecvError::Disconnected) => return Ok(Status::Disconnected),
+            }
+        }
+    }
+
+    /// Handles events and fires the timer, blocking the current thread, until the instance has
+    /// terminated or all senders of events have been dropped.
+    pub fn run(&mut self) -> Result<Status, D::Error> {
+        loop {
+            let deadline = match self.poll(Instant::now())? {
+                Status::Pending(deadline) => deadline,
+                status => return Ok(status),
+            };
+            let result = match deadline {
+                None => self.events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    let timeout = if deadline > now {
+                        deadline - now
+                    } else {
+                        Duration::from_secs(0)
+                    };
+                    self.events.recv_timeout(timeout)
+                }
+            };
+            match result {
+                Ok(event) => self.handle_event(event, Instant::now())?,
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Ok(Status::Disconnected),
+            }
+        }
+    }
+
+    /// Calls the timer function and handles its input, if the timer has expired at time `now`.
+    fn fire_timer(&mut self, now: Instant) -> Result<(), D::Error> {
+        let opt_input = match self.timer {
+            Some(ref mut timer) if timer.deadline <= now => {
+                timer.deadline = now + timer.interval;
+                (timer.make_input)(&self.algo)
+            }
+            _ => None,
+        };
+        if let Some(input) = opt_input {
+            self.handle_event(Event::Input(input), now)?;
+        }
+        Ok(())
+    }
+
+    /// Passes the expired timeouts' tokens to the instance, in the order of their expiry times.
+    fn fire_timeouts(&mut self, now: Instant) -> Result<(), D::Error> {
+        let (mut expired, pending) = self
+            .timeouts
+            .drain(..)
+            .partition(|&(deadline, _)| deadline <= now);
+        self.timeouts = pending;
+        expired.sort();
+        for (_, token) in expired {
+            let step = self.algo.handle_timeout(token)?;
+            self.send_step(step, now);
+        }
+        Ok(())
+    }
+
+    /// Passes the event to the instance at time `now` and sends out the results.
+    fn handle_event(&mut self, event: Event<D>, now: Instant) -> Result<(), D::Error> {
+        let step = match event {
+            Event::Input(input) => self.algo.handle_input(input)?,
+            Event::Message(sender_id, msg) => self.algo.handle_message(&sender_id, msg)?,
+        };
+        self.send_step(step, now);
+        Ok(())
+    }
+
+    /// Sends the step's messages, outputs and faults to the respective channels, and sets the
+    /// requested timers, starting at time `now`.
+    fn send_step(&mut self, step: Step<D>, now: Instant) {
+        for timer in step.timers {
+            // Timers too far in the future to be represented never expire.
+            let ticks = timer.ticks.min(u64::from(u32::max_value())) as u32;
+            let opt_deadline = self
+                .tick_duration
+                .checked_mul(ticks)
+                .and_then(|duration| now.checked_add(duration));
+            if let Some(deadline) = opt_deadline {
+                self.timeouts.push((deadline, timer.token));
+            }
+        }
+        for msg in step.messages {
+            let _ = self.messages.send(msg);
+        }
+        for output in step.output {
+            let _ = self.outputs.send(output);
+        }
+        for fault in step.fault_log.0 {
+            let _ = self.faults.send(fault);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! # Verifying signed batches
+//!
+//! If Honey Badger is configured to `sign_batches`, every batch it outputs carries a threshold
+//! signature of its hash by the validators. A light client that doesn't take part in consensus
+//! can use this module to check such a batch, knowing only the validators' IDs and their
+//! `PublicKeySet`, e.g. from the genesis configuration:
+//!
+//! * The batch must belong to the epoch the client expects next.
+//! * All contributions must be attributed to validators.
+//! * The signature must be a valid signature of the batch's hash by the public master key.
+//!
+//! Since at most _f_ validators are faulty, and _f + 1_ signature shares are needed, a valid
+//! signature proves that at least one correct validator has output the batch.
+
+use std::collections::BTreeSet;
+use std::ops::Deref;
+
+use bincode;
+use crypto::PublicKeySet;
+use serde::Serialize;
+
+use epoch::HbEpoch;
+use honey_badger::Batch;
+use NodeIdT;
+
+/// A batch verification error.
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Expected epoch {}, but the batch is from epoch {}", _0, _1)]
+    WrongEpoch(HbEpoch, HbEpoch),
+    #[fail(display = "Batch contains a contribution by a node that is not a validator")]
+    UnknownProposer,
+    #[fail(display = "Batch is not signed")]
+    Unsigned,
+    #[fail(display = "Batch signature is invalid")]
+    InvalidSignature,
+    #[fail(display = "Failed to hash batch: {}", _0)]
+    Hash(bincode::ErrorKind),
+}
+
+/// A batch verification result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A batch whose epoch, proposers and signature have been checked by `verify_batch`.
+#[derive(Clone, Debug)]
+pub struct VerifiedBatch<C, N>(Batch<C, N>);
+
+impl<C, N> VerifiedBatch<C, N> {
+    /// Returns the verified batch.
+    pub fn batch(&self) -> &Batch<C, N> {
+        &self.0
+    }
+
+    /// Returns the verified batch, consuming `self`.
+    pub fn into_batch(self) -> Batch<C, N> {
+        self.0
+    }
+}
+
+impl<C, N> Deref for VerifiedBatch<C, N> {
+    type Target = Batch<C, N>;
+
+    fn deref(&self) -> &Batch<C, N> {
+        &self.0
+    }
+}
+
+/// Checks that the batch belongs to the given `epoch`, that all its contributions were proposed
+/// by one of the `validators`, and that it carries their valid threshold signature.
+pub fn verify_batch<C, N>(
+    batch: Batch<C, N>,
+    epoch: HbEpoch,
+    validators: &BTreeSet<N>,
+    pk_set: &PublicKeySet,
+) -> Result<VerifiedBatch<C, N>>
+where
+    C: Serialize,
+    N: NodeIdT + Serialize,
+{
+    if batch.epoch != epoch {
+        return Err(Error::WrongEpoch(epoch, batch.epoch));
+    }
+    if batch
+        .contributions
+        .keys()
+        .any(|id| !validators.contains(id))
+    {
+        return Err(Error::UnknownProposer);
+    }
+    let hash = batch.hash().map_err(|err| Error::Hash(*err))?;
+    let is_valid = match batch.signature {
+        None => return Err(Error::Unsigned),
+        Some(ref sig) => pk_set.public_key().verify(sig, hash),
+    };
+    if !is_valid {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(VerifiedBatch(batch))
+}
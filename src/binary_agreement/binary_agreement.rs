@@ -1,24 +1,35 @@
 use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::Arc;
 
+use crypto::{Signature, SignatureShare};
+
 use super::bool_multimap::BoolMultimap;
 use super::bool_set::BoolSet;
-use super::sbv_broadcast::{self, SbvBroadcast};
-use super::{Error, Message, MessageContent, Nonce, Result, Step};
-use coin::{self, Coin, CoinMessage};
+use super::sbv_broadcast::{self, SbvBroadcast, SbvBroadcastSnapshot};
+use super::{
+    encode_document, CoinSchedule, Error, FaultKind, Message, MessageContent, Nonce,
+    ReceivedCounts, Result, SessionId, SlowAgreementReport, Step, LOG_TARGET, TERM_DOMAIN,
+};
+use coin::{Coin, CoinCache, CoinMessage, CoinSnapshot, CoinSource};
+use environment::{self, Environment, Restore, RestoreError};
+use epoch::AgreementEpoch;
+use fault_log::Fault;
+use metrics::{self, Metrics, NoMetrics};
+use validation::Validity;
 use {DistAlgorithm, NetworkInfo, NodeIdT, Target};
 
 /// The state of the current epoch's coin. In some epochs this is fixed, in others it starts
 /// with in `InProgress`.
-#[derive(Debug)]
-enum CoinState<N> {
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum CoinState<S> {
     /// The value was fixed in the current epoch, or the coin has already terminated.
     Decided(bool),
     /// The coin value is not known yet.
-    InProgress(Coin<N, Nonce>),
+    InProgress(S),
 }
 
-impl<N> CoinState<N> {
+impl<S> CoinState<S> {
     /// Returns the value, if this coin has already decided.
     fn value(&self) -> Option<bool> {
         match self {
@@ -28,23 +39,72 @@ impl<N> CoinState<N> {
     }
 }
 
-impl<N> From<bool> for CoinState<N> {
+impl<S> From<bool> for CoinState<S> {
     fn from(value: bool) -> Self {
         CoinState::Decided(value)
     }
 }
 
+/// The default maximum number of epochs ahead of the current one for which messages are queued.
+pub const DEFAULT_MAX_FUTURE_EPOCHS: u64 = 100;
+
+/// The default maximum number of messages queued per sender for any single future epoch. A
+/// correct node sends at most six messages per epoch: two `BVal`s, an `Aux`, a `Conf`, a coin
+/// share and a `Term`.
+pub const DEFAULT_MAX_QUEUED_PER_EPOCH: usize = 10;
+
+/// The default maximum number of epochs after which an instance without a decision aborts. With
+/// correct nodes forming a quorum, the probability of reaching this is negligible.
+pub const DEFAULT_MAX_EPOCHS: u64 = 1_000_000;
+
+/// The default number of epochs without a decision after which an instance logs a warning.
+pub const DEFAULT_SLOW_EPOCHS: u64 = 16;
+
+/// A function that is called with a report whenever a `BinaryAgreement` instance completes another
+/// `set_slow_epochs` epochs without a decision.
+pub type SlowEpochsHook<N> = Arc<dyn Fn(&SlowAgreementReport<N>) + Send + Sync>;
+
+/// The optional `SlowEpochsHook`, wrapped so that `BinaryAgreement` can derive `Debug`.
+struct OptionalHook<N>(Option<SlowEpochsHook<N>>);
+
+impl<N> Clone for OptionalHook<N> {
+    fn clone(&self) -> Self {
+        OptionalHook(self.0.clone())
+    }
+}
+
+impl<N> Default for OptionalHook<N> {
+    fn default() -> Self {
+        OptionalHook(None)
+    }
+}
+
+impl<N> fmt::Debug for OptionalHook<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Some(<SlowEpochsHook>)"),
+            None => write!(f, "None"),
+        }
+    }
+}
+
 /// Binary Agreement instance
-#[derive(Debug)]
-pub struct BinaryAgreement<N> {
+///
+/// The pseudorandom coin values are produced by instances of `S`, by default the threshold
+/// signature based `Coin`. If `S` is `Clone`, so is the instance.
+///
+/// If handling an input or message fails with a fatal error, e.g. because the epoch limit is
+/// reached, the instance aborts, so it is never left partially updated.
+#[derive(Clone, Debug)]
+pub struct BinaryAgreement<N, S = Coin<N, Nonce>> {
     /// Shared network information.
     netinfo: Arc<NetworkInfo<N>>,
-    /// Session ID, e.g, the Honey Badger algorithm epoch.
-    session_id: u64,
+    /// The byte representation of the session ID, e.g. of the Honey Badger algorithm epoch.
+    session_id: Vec<u8>,
     /// The ID of the proposer of the value for this Binary Agreement instance.
     proposer_id: N,
     /// Binary Agreement algorithm epoch.
-    epoch: u32,
+    epoch: AgreementEpoch,
     /// This epoch's Synchronized Binary Value Broadcast instance.
     sbv_broadcast: SbvBroadcast<N>,
     /// Received `Conf` messages. Reset on every epoch update.
@@ -60,45 +120,115 @@ pub struct BinaryAgreement<N> {
     /// ever there at all. While the output value will still be required in a later epoch to decide
     /// the termination state.
     decision: Option<bool>,
-    /// A cache for messages for future epochs that cannot be handled yet.
-    // TODO: Find a better solution for this; defend against spam.
-    incoming_queue: BTreeMap<u32, Vec<(N, MessageContent)>>,
+    /// Whether the instance has been aborted without a decision.
+    aborted: bool,
+    /// A cache for messages for future epochs that cannot be handled yet, by sender and epoch.
+    incoming_queue: BTreeMap<N, BTreeMap<AgreementEpoch, Vec<MessageContent>>>,
+    /// The maximum number of epochs ahead of the current one for which messages are queued.
+    max_future_epochs: u64,
+    /// The maximum number of messages queued per sender for any single future epoch.
+    max_queued_per_epoch: usize,
+    /// The maximum number of epochs. If the last one ends without a decision, the instance aborts
+    /// with an error.
+    max_epochs: u64,
+    /// Whether `Term` messages must contain a signature share on the decision.
+    justified_termination: bool,
+    /// The received valid signature shares on `Term` messages, with the terminating values.
+    term_shares: BTreeMap<N, (bool, SignatureShare)>,
     /// The values we found in the first _N - f_ `Aux` messages that were in `bin_values`.
     conf_values: Option<BoolSet>,
     /// The state of this epoch's coin.
-    coin_state: CoinState<N>,
+    coin_state: CoinState<S>,
+    /// Receiver of measurements.
+    metrics: Arc<dyn Metrics>,
+    /// The cache of combined coin signatures shared with other instances, if any.
+    coin_cache: Option<Arc<CoinCache>>,
+    /// The number of epochs without a decision after which a warning is logged, and again after
+    /// each further such number. `0` disables the warnings.
+    slow_epochs: u64,
+    /// The function that is called whenever a warning about slow epochs is logged.
+    slow_epochs_hook: OptionalHook<N>,
 }
 
-impl<N: NodeIdT> DistAlgorithm for BinaryAgreement<N> {
+/// A serializable snapshot of a `BinaryAgreement` instance, without its environment. `C` is the
+/// snapshot type of the coin source.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(
+    deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>, C: ::serde::Deserialize<'de>"
+))]
+pub struct BinaryAgreementSnapshot<N, C = CoinSnapshot<N, Nonce>> {
+    session_id: Vec<u8>,
+    proposer_id: N,
+    epoch: AgreementEpoch,
+    sbv_broadcast: SbvBroadcastSnapshot<N>,
+    received_conf: BTreeMap<N, BoolSet>,
+    received_term: BoolMultimap<N>,
+    estimated: Option<bool>,
+    decision: Option<bool>,
+    aborted: bool,
+    incoming_queue: BTreeMap<N, BTreeMap<AgreementEpoch, Vec<MessageContent>>>,
+    max_future_epochs: u64,
+    max_queued_per_epoch: usize,
+    max_epochs: u64,
+    justified_termination: bool,
+    term_shares: BTreeMap<N, (bool, SignatureShare)>,
+    conf_values: Option<BoolSet>,
+    coin_state: CoinState<C>,
+    slow_epochs: u64,
+    /// Whether the instance had a slow epochs hook, which the `Environment` must provide.
+    has_slow_epochs_hook: bool,
+}
+
+impl<N: NodeIdT, S: CoinSource<N, Nonce>> DistAlgorithm for BinaryAgreement<N, S> {
     type NodeId = N;
     type Input = bool;
     type Output = bool;
     type Message = Message;
     type Error = Error;
-
-    fn handle_input(&mut self, input: Self::Input) -> Result<Step<N>> {
-        self.handle_input(input)
+    type FaultKind = FaultKind;
+
+    fn handle_input(&mut self, input: Self::Input) -> Result<Step<N, S>> {
+        enter_span!(
+            "binary_agreement",
+            node = self.netinfo.our_id(),
+            session = self.session_id,
+            proposer = self.proposer_id,
+        );
+        let result = self.handle_input(input);
+        self.abort_on_error(result)
     }
 
     /// Receive input from a remote node.
-    fn handle_message(&mut self, sender_id: &Self::NodeId, msg: Message) -> Result<Step<N>> {
+    fn handle_message(&mut self, sender_id: &Self::NodeId, msg: Message) -> Result<Step<N, S>> {
+        enter_span!(
+            "binary_agreement",
+            node = self.netinfo.our_id(),
+            session = self.session_id,
+            proposer = self.proposer_id,
+        );
+        let validity = self.validate_message(sender_id, &msg);
+        let mut step = Step::validated(sender_id.clone(), validity);
         let Message { epoch, content } = msg;
-        if self.decision.is_some() || (epoch < self.epoch && content.can_expire()) {
-            // Message is obsolete: We are already in a later epoch or terminated.
-            Ok(Step::default())
-        } else if epoch > self.epoch {
-            // Message is for a later epoch. We can't handle that yet.
-            let queue = self.incoming_queue.entry(epoch).or_insert_with(Vec::new);
-            queue.push((sender_id.clone(), content));
-            Ok(Step::default())
-        } else {
-            self.handle_message_content(sender_id, content)
-        }
+        let result = match validity {
+            Validity::Valid if self.decision.is_some() => match content {
+                MessageContent::SignedTerm(b, share) => {
+                    self.handle_signed_term(sender_id, b, *share)
+                }
+                _ => Ok(Step::default()),
+            },
+            Validity::Valid => self.handle_message_content(sender_id, content),
+            Validity::Future => self.enqueue(sender_id, epoch, content),
+            Validity::Obsolete | Validity::Faulty(_) | Validity::Incompatible => {
+                Ok(Step::default())
+            }
+        };
+        step.extend(self.abort_on_error(result)?);
+        Ok(step)
     }
 
     /// Whether the algorithm has terminated.
     fn terminated(&self) -> bool {
-        self.decision.is_some()
+        self.decision.is_some() || self.aborted
     }
 
     fn our_id(&self) -> &Self::NodeId {
@@ -106,42 +236,323 @@ impl<N: NodeIdT> DistAlgorithm for BinaryAgreement<N> {
     }
 }
 
+impl<N, S> Restore<N> for BinaryAgreement<N, S>
+where
+    N: NodeIdT,
+    S: CoinSource<N, Nonce> + Restore<N>,
+{
+    type Snapshot = BinaryAgreementSnapshot<N, S::Snapshot>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        let coin_state = match self.coin_state {
+            CoinState::Decided(value) => CoinState::Decided(value),
+            CoinState::InProgress(ref coin) => CoinState::InProgress(coin.snapshot()),
+        };
+        BinaryAgreementSnapshot {
+            session_id: self.session_id.clone(),
+            proposer_id: self.proposer_id.clone(),
+            epoch: self.epoch,
+            sbv_broadcast: self.sbv_broadcast.snapshot(),
+            received_conf: self.received_conf.clone(),
+            received_term: self.received_term.clone(),
+            estimated: self.estimated,
+            decision: self.decision,
+            aborted: self.aborted,
+            incoming_queue: self.incoming_queue.clone(),
+            max_future_epochs: self.max_future_epochs,
+            max_queued_per_epoch: self.max_queued_per_epoch,
+            max_epochs: self.max_epochs,
+            justified_termination: self.justified_termination,
+            term_shares: self.term_shares.clone(),
+            conf_values: self.conf_values,
+            coin_state,
+            slow_epochs: self.slow_epochs,
+            has_slow_epochs_hook: self.slow_epochs_hook.0.is_some(),
+        }
+    }
+
+    fn restore(snapshot: Self::Snapshot, env: &Environment<N>) -> environment::Result<Self> {
+        let slow_epochs_hook = if snapshot.has_slow_epochs_hook {
+            let hook = env.slow_epochs_hook.clone();
+            Some(hook.ok_or(RestoreError::MissingSlowEpochsHook)?)
+        } else {
+            None
+        };
+        let coin_state = match snapshot.coin_state {
+            CoinState::Decided(value) => CoinState::Decided(value),
+            CoinState::InProgress(coin) => CoinState::InProgress(S::restore(coin, env)?),
+        };
+        Ok(BinaryAgreement {
+            netinfo: env.netinfo.clone(),
+            session_id: snapshot.session_id,
+            proposer_id: snapshot.proposer_id,
+            epoch: snapshot.epoch,
+            sbv_broadcast: SbvBroadcast::restore(snapshot.sbv_broadcast, env)?,
+            received_conf: snapshot.received_conf,
+            received_term: snapshot.received_term,
+            estimated: snapshot.estimated,
+            decision: snapshot.decision,
+            aborted: snapshot.aborted,
+            incoming_queue: snapshot.incoming_queue,
+            max_future_epochs: snapshot.max_future_epochs,
+            max_queued_per_epoch: snapshot.max_queued_per_epoch,
+            max_epochs: snapshot.max_epochs,
+            justified_termination: snapshot.justified_termination,
+            term_shares: snapshot.term_shares,
+            conf_values: snapshot.conf_values,
+            coin_state,
+            metrics: env.metrics.clone(),
+            coin_cache: env.coin_cache.clone(),
+            slow_epochs: snapshot.slow_epochs,
+            slow_epochs_hook: OptionalHook(slow_epochs_hook),
+        })
+    }
+}
+
 impl<N: NodeIdT> BinaryAgreement<N> {
-    pub fn new(netinfo: Arc<NetworkInfo<N>>, session_id: u64, proposer_id: N) -> Result<Self> {
+    /// Creates a new instance that uses the threshold signature based `Coin`.
+    pub fn new<T: SessionId>(
+        netinfo: Arc<NetworkInfo<N>>,
+        session_id: T,
+        proposer_id: N,
+    ) -> Result<Self> {
+        BinaryAgreement::new_with_coin(netinfo, session_id, proposer_id)
+    }
+}
+
+impl<N: NodeIdT, S: CoinSource<N, Nonce>> BinaryAgreement<N, S> {
+    /// Creates a new instance that uses the coin source `S`.
+    pub fn new_with_coin<T: SessionId>(
+        netinfo: Arc<NetworkInfo<N>>,
+        session_id: T,
+        proposer_id: N,
+    ) -> Result<Self> {
         if !netinfo.is_node_validator(&proposer_id) {
             return Err(Error::UnknownProposer);
         }
         Ok(BinaryAgreement {
             netinfo: netinfo.clone(),
-            session_id,
+            session_id: session_id.as_ref().to_vec(),
             proposer_id,
-            epoch: 0,
+            epoch: AgreementEpoch::new(0),
             sbv_broadcast: SbvBroadcast::new(netinfo),
             received_conf: BTreeMap::new(),
             received_term: BoolMultimap::default(),
             estimated: None,
             decision: None,
+            aborted: false,
             incoming_queue: BTreeMap::new(),
+            max_future_epochs: DEFAULT_MAX_FUTURE_EPOCHS,
+            max_queued_per_epoch: DEFAULT_MAX_QUEUED_PER_EPOCH,
+            max_epochs: DEFAULT_MAX_EPOCHS,
+            justified_termination: false,
+            term_shares: BTreeMap::new(),
             conf_values: None,
             coin_state: CoinState::Decided(true),
+            metrics: Arc::new(NoMetrics),
+            coin_cache: None,
+            slow_epochs: DEFAULT_SLOW_EPOCHS,
+            slow_epochs_hook: OptionalHook(None),
         })
     }
 
+    /// Sets the limits for queueing messages that belong to future epochs: Messages more than
+    /// `max_future_epochs` epochs ahead, and messages exceeding `max_queued_per_epoch` for a single
+    /// sender and epoch, are dropped and their senders reported as faulty. `Term` messages are
+    /// exempt from the first limit, but only one per sender is queued beyond it.
+    pub fn set_queue_limits(&mut self, max_future_epochs: u64, max_queued_per_epoch: usize) {
+        self.max_future_epochs = max_future_epochs;
+        self.max_queued_per_epoch = max_queued_per_epoch;
+    }
+
+    /// Sets the maximum number of epochs. If the instance hasn't decided when it would have to
+    /// move beyond that, it aborts with `Error::EpochLimitReached`.
+    pub fn set_max_epochs(&mut self, max_epochs: u64) {
+        self.max_epochs = max_epochs;
+    }
+
+    /// Enables or disables justified termination: If enabled, our `Term` messages contain a
+    /// signature share on the decision, and we reject `Term` messages without one. All nodes must
+    /// use the same setting.
+    pub fn set_justified_termination(&mut self, justified_termination: bool) {
+        self.justified_termination = justified_termination;
+    }
+
+    /// Sets the receiver of measurements, which is also passed on to the coin of every epoch. By
+    /// default, measurements are ignored.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        if let CoinState::InProgress(ref mut coin) = self.coin_state {
+            coin.set_metrics(metrics.clone());
+        }
+        self.metrics = metrics;
+    }
+
+    /// Sets a cache of combined coin signatures that is passed on to the coin of every epoch, so
+    /// that instances restored from a snapshot don't compute the same coins again. By default,
+    /// there is no cache.
+    pub fn set_coin_cache(&mut self, coin_cache: Arc<CoinCache>) {
+        self.coin_cache = Some(coin_cache);
+    }
+
+    /// Sets the number of epochs without a decision after which the instance logs a warning with
+    /// its tallies, and calls the slow epochs hook. That repeats after every further
+    /// `slow_epochs` epochs. `0` disables the warnings. The default is `DEFAULT_SLOW_EPOCHS`.
+    pub fn set_slow_epochs(&mut self, slow_epochs: u64) {
+        self.slow_epochs = slow_epochs;
+    }
+
+    /// Sets a function that receives a report whenever the instance completes another
+    /// `slow_epochs` epochs without a decision, e.g. to raise an alert or collect diagnostics.
+    pub fn set_slow_epochs_hook(&mut self, hook: SlowEpochsHook<N>) {
+        self.slow_epochs_hook = OptionalHook(Some(hook));
+    }
+
+    /// Returns a threshold signature on the decision, if we have decided and received at least
+    /// _f + 1_ signature shares on it. Only `SignedTerm` messages contain signature shares, so
+    /// this requires justified termination.
+    pub fn decision_proof(&self) -> Option<Signature> {
+        self.term_signature(self.decision?)
+    }
+
+    /// Terminates the instance without a decision, and drops its state. All further messages are
+    /// ignored.
+    ///
+    /// Other nodes may depend on our messages to decide, so this must only be used if the outcome
+    /// of this instance is not needed anymore by anyone, e.g. if the enclosing algorithm has
+    /// already terminated.
+    pub fn abort(&mut self) {
+        if self.terminated() {
+            return;
+        }
+        self.aborted = true;
+        self.sbv_broadcast.clear(&BoolMultimap::default());
+        self.received_conf.clear();
+        self.received_term = BoolMultimap::default();
+        self.incoming_queue.clear();
+        self.conf_values = None;
+        self.coin_state = CoinState::Decided(false);
+        debug_event!(
+            "Aborted",
+            node = self.netinfo.our_id(),
+            proposer = self.proposer_id,
+            epoch = self.epoch,
+        );
+    }
+
+    /// Aborts the instance if the result is a fatal error. The non-fatal errors are detected
+    /// before the state is changed, so the instance is never left partially updated.
+    fn abort_on_error(&mut self, result: Result<Step<N, S>>) -> Result<Step<N, S>> {
+        if let Err(ref err) = result {
+            if err.is_fatal() {
+                self.abort();
+            }
+        }
+        result
+    }
+
+    /// Returns `true` if the instance has been aborted without a decision.
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Returns the current Binary Agreement epoch.
+    pub fn epoch(&self) -> AgreementEpoch {
+        self.epoch
+    }
+
+    /// Returns the set of values for which _2 f + 1_ `BVal`s were received in the current epoch.
+    pub fn bin_values(&self) -> BoolSet {
+        self.sbv_broadcast.bin_values()
+    }
+
+    /// Returns the numbers of validators from which we received each kind of message in the
+    /// current epoch.
+    pub fn received_counts(&self) -> ReceivedCounts {
+        let count_conf = |b| {
+            self.received_conf
+                .values()
+                .filter(|vals| vals.contains(b))
+                .count()
+        };
+        ReceivedCounts {
+            bval: [
+                self.sbv_broadcast.count_bval(false),
+                self.sbv_broadcast.count_bval(true),
+            ],
+            aux: [
+                self.sbv_broadcast.count_aux(false),
+                self.sbv_broadcast.count_aux(true),
+            ],
+            conf: [count_conf(false), count_conf(true)],
+            term: [
+                self.received_term[false].len(),
+                self.received_term[true].len(),
+            ],
+        }
+    }
+
+    /// Returns the decided value, if the instance has terminated with a decision.
+    pub fn decision(&self) -> Option<bool> {
+        self.decision
+    }
+
+    /// Returns how the coin value of the current epoch is determined, and whether it is known.
+    pub fn coin_schedule(&self) -> CoinSchedule {
+        match self.fixed_coin() {
+            Some(value) => CoinSchedule::Fixed(value),
+            None => CoinSchedule::Random(self.coin_state.value()),
+        }
+    }
+
     /// Sets the input value for Binary Agreement.
-    fn handle_input(&mut self, input: bool) -> Result<Step<N>> {
-        if self.epoch != 0 || self.estimated.is_some() {
+    fn handle_input(&mut self, input: bool) -> Result<Step<N, S>> {
+        if self.aborted || self.epoch.get() != 0 || self.estimated.is_some() {
             return Err(Error::InputNotAccepted);
         }
         // Set the initial estimated value to the input value.
         self.estimated = Some(input);
-        debug!("{:?}/{:?} Input {}", self.our_id(), self.proposer_id, input);
+        debug_event!(
+            "Input",
+            node = self.our_id(),
+            proposer = self.proposer_id,
+            value = input,
+        );
         let sbvb_step = self.sbv_broadcast.handle_input(input)?;
         self.handle_sbvb_step(sbvb_step)
     }
 
     /// Acceptance check to be performed before setting the input value.
     pub fn accepts_input(&self) -> bool {
-        self.epoch == 0 && self.estimated.is_none()
+        !self.aborted && self.epoch.get() == 0 && self.estimated.is_none()
+    }
+
+    /// Classifies a message before it is dispatched.
+    fn validate_message(&self, sender_id: &N, msg: &Message) -> Validity<FaultKind> {
+        if !self.netinfo.is_node_validator(sender_id) {
+            return Validity::Faulty(FaultKind::UnknownSender);
+        }
+        if self.decision.is_some() && !self.aborted {
+            // Keep collecting signature shares for the decision proof.
+            return match msg.content {
+                MessageContent::SignedTerm(..) => Validity::Valid,
+                _ => Validity::Obsolete,
+            };
+        }
+        if self.terminated() || (msg.epoch < self.epoch && msg.content.can_expire()) {
+            // We are already in a later epoch or terminated.
+            Validity::Obsolete
+        } else if msg.epoch > self.epoch.saturating_add(self.max_future_epochs)
+            && msg.content.can_expire()
+        {
+            // `Term` messages remain valid in all later epochs: A correct node that decided early
+            // can legitimately send one from beyond our horizon.
+            Validity::Faulty(FaultKind::AgreementEpochTooFarAhead)
+        } else if msg.epoch > self.epoch {
+            // We can't handle that yet.
+            Validity::Future
+        } else {
+            Validity::Valid
+        }
     }
 
     /// Dispatches the message content to the corresponding handling method.
@@ -149,30 +560,62 @@ impl<N: NodeIdT> BinaryAgreement<N> {
         &mut self,
         sender_id: &N,
         content: MessageContent,
-    ) -> Result<Step<N>> {
+    ) -> Result<Step<N, S>> {
         match content {
             MessageContent::SbvBroadcast(msg) => self.handle_sbv_broadcast(sender_id, msg),
             MessageContent::Conf(v) => self.handle_conf(sender_id, v),
+            MessageContent::Term(_) if self.justified_termination => {
+                Ok(Fault::new(sender_id.clone(), FaultKind::UnsignedTerm).into())
+            }
             MessageContent::Term(v) => self.handle_term(sender_id, v),
+            MessageContent::SignedTerm(v, share) => self.handle_signed_term(sender_id, v, *share),
             MessageContent::Coin(msg) => self.handle_coin(sender_id, *msg),
         }
     }
 
+    /// Queues a message for a future epoch, or reports the sender if it exceeds the limit.
+    ///
+    /// Beyond the horizon of `max_future_epochs`, only a single `Term` message per sender is
+    /// queued: A correct node sends only one.
+    fn enqueue(
+        &mut self,
+        sender_id: &N,
+        epoch: AgreementEpoch,
+        content: MessageContent,
+    ) -> Result<Step<N, S>> {
+        let horizon = self.epoch.saturating_add(self.max_future_epochs);
+        let sender_queue = self
+            .incoming_queue
+            .entry(sender_id.clone())
+            .or_insert_with(BTreeMap::new);
+        if epoch > horizon && sender_queue.keys().any(|queued| *queued > horizon) {
+            let fault_kind = FaultKind::TooManyAgreementMessages;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        let queue = sender_queue.entry(epoch).or_insert_with(Vec::new);
+        if queue.len() >= self.max_queued_per_epoch {
+            let fault_kind = FaultKind::TooManyAgreementMessages;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        queue.push(content);
+        Ok(Step::default())
+    }
+
     /// Handles a Synchroniced Binary Value Broadcast message.
     fn handle_sbv_broadcast(
         &mut self,
         sender_id: &N,
         msg: sbv_broadcast::Message,
-    ) -> Result<Step<N>> {
+    ) -> Result<Step<N, S>> {
         let sbvb_step = self.sbv_broadcast.handle_message(sender_id, msg)?;
         self.handle_sbvb_step(sbvb_step)
     }
 
     /// Handles a Synchronized Binary Value Broadcast step. On output, starts the `Conf` round or
     /// decides.
-    fn handle_sbvb_step(&mut self, sbvb_step: sbv_broadcast::Step<N>) -> Result<Step<N>> {
+    fn handle_sbvb_step(&mut self, sbvb_step: sbv_broadcast::Step<N>) -> Result<Step<N, S>> {
         let mut step = Step::default();
-        let output = step.extend_with(sbvb_step, |msg| {
+        let (output, _) = step.extend_with(sbvb_step, |msg| {
             MessageContent::SbvBroadcast(msg).with_epoch(self.epoch)
         });
         if self.conf_values.is_some() {
@@ -196,7 +639,7 @@ impl<N: NodeIdT> BinaryAgreement<N> {
 
     /// Handles a `Conf` message. When _N - f_ `Conf` messages with values in `bin_values` have
     /// been received, updates the epoch or decides.
-    fn handle_conf(&mut self, sender_id: &N, v: BoolSet) -> Result<Step<N>> {
+    fn handle_conf(&mut self, sender_id: &N, v: BoolSet) -> Result<Step<N, S>> {
         self.received_conf.insert(sender_id.clone(), v);
         self.try_finish_conf_round()
     }
@@ -204,26 +647,97 @@ impl<N: NodeIdT> BinaryAgreement<N> {
     /// Handles a `Term(v)` message. If we haven't yet decided on a value and there are more than
     /// _f_ such messages with the same value from different nodes, performs expedite termination:
     /// decides on `v`, broadcasts `Term(v)` and terminates the instance.
-    fn handle_term(&mut self, sender_id: &N, b: bool) -> Result<Step<N>> {
+    fn handle_term(&mut self, sender_id: &N, b: bool) -> Result<Step<N, S>> {
         self.received_term[b].insert(sender_id.clone());
         // Check for the expedite termination condition.
         if self.decision.is_some() {
             Ok(Step::default())
-        } else if self.received_term[b].len() > self.netinfo.num_faulty() {
+        } else if self.netinfo.weight_of(&self.received_term[b]) > self.netinfo.faulty_weight() {
             Ok(self.decide(b))
         } else {
-            // Otherwise handle the `Term` as a `BVal`, `Aux` and `Conf`.
-            let mut sbvb_step = self.sbv_broadcast.handle_bval(sender_id, b)?;
-            sbvb_step.extend(self.sbv_broadcast.handle_aux(sender_id, b)?);
-            let mut step = self.handle_sbvb_step(sbvb_step)?;
-            step.extend(self.handle_conf(sender_id, BoolSet::from(b))?);
-            Ok(step)
+            self.handle_term_as_votes(sender_id, b)
         }
     }
 
+    /// Handles a `SignedTerm(v, share)` message. If the signature share is valid, stores it.
+    ///
+    /// Expedite termination requires evidence here: Only once the shares on `v` combine into a
+    /// valid threshold signature, which proves that at least one correct node has decided `v`,
+    /// do we decide `v`, too. Until then, the message counts as a `BVal`, `Aux` and `Conf`.
+    fn handle_signed_term(
+        &mut self,
+        sender_id: &N,
+        b: bool,
+        share: SignatureShare,
+    ) -> Result<Step<N, S>> {
+        let is_valid = match self.netinfo.public_key_share(sender_id) {
+            Some(pk_share) => pk_share.verify(&share, self.term_document(b)),
+            None => false,
+        };
+        if !is_valid {
+            let fault_kind = FaultKind::InvalidTermSignature;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        self.term_shares.insert(sender_id.clone(), (b, share));
+        self.received_term[b].insert(sender_id.clone());
+        if self.decision.is_some() {
+            Ok(Step::default())
+        } else if self.term_signature(b).is_some() {
+            Ok(self.decide(b))
+        } else {
+            self.handle_term_as_votes(sender_id, b)
+        }
+    }
+
+    /// Handles a `Term(v)` from a node as if it had sent `BVal(v)`, `Aux(v)` and `Conf({v})`.
+    fn handle_term_as_votes(&mut self, sender_id: &N, b: bool) -> Result<Step<N, S>> {
+        let mut sbvb_step = self.sbv_broadcast.handle_bval(sender_id, b)?;
+        sbvb_step.extend(self.sbv_broadcast.handle_aux(sender_id, b)?);
+        let mut step = self.handle_sbvb_step(sbvb_step)?;
+        step.extend(self.handle_conf(sender_id, BoolSet::from(b))?);
+        Ok(step)
+    }
+
+    /// Combines the received signature shares on `Term(b)`, and returns the threshold signature
+    /// if there are enough shares and it is valid.
+    fn term_signature(&self, b: bool) -> Option<Signature> {
+        let shares: BTreeMap<_, _> = self
+            .term_shares
+            .iter()
+            .filter(|&(_, &(v, _))| v == b)
+            .filter_map(|(id, &(_, ref share))| Some((self.netinfo.node_index(id)?, share)))
+            .collect();
+        let pk_set = self.netinfo.public_key_set();
+        if shares.len() <= pk_set.threshold() {
+            return None;
+        }
+        let sig = pk_set.combine_signatures(shares).ok()?;
+        if pk_set.public_key().verify(&sig, self.term_document(b)) {
+            Some(sig)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the document that is signed in `SignedTerm(b, _)` messages.
+    fn term_document(&self, b: bool) -> Vec<u8> {
+        encode_document(
+            TERM_DOMAIN,
+            &self.netinfo.invocation_id(),
+            &self.session_id,
+            self.netinfo.node_index(&self.proposer_id).unwrap(),
+            b as u64,
+        )
+    }
+
     /// Handles a Coin message. If there is output from Coin, starts the next
     /// epoch. The function may output a decision value.
-    fn handle_coin(&mut self, sender_id: &N, msg: CoinMessage) -> Result<Step<N>> {
+    fn handle_coin(&mut self, sender_id: &N, msg: CoinMessage) -> Result<Step<N, S>> {
+        if !self.has_random_coin() {
+            // In this epoch the coin value is fixed: No correct node sends coin shares.
+            let fault_kind = FaultKind::UnexpectedCoinMessage;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
         let coin_step = match self.coin_state {
             CoinState::Decided(_) => return Ok(Step::default()), // Coin value is already decided.
             CoinState::InProgress(ref mut coin) => coin
@@ -234,7 +748,7 @@ impl<N: NodeIdT> BinaryAgreement<N> {
     }
 
     /// Multicasts a `Conf(values)` message, and handles it.
-    fn send_conf(&mut self, values: BoolSet) -> Result<Step<N>> {
+    fn send_conf(&mut self, values: BoolSet) -> Result<Step<N, S>> {
         if self.conf_values.is_some() {
             // Only one `Conf` message is allowed in an epoch.
             return Ok(Step::default());
@@ -251,11 +765,11 @@ impl<N: NodeIdT> BinaryAgreement<N> {
     }
 
     /// Multicasts and handles a message. Does nothing if we are only an observer.
-    fn send(&mut self, content: MessageContent) -> Result<Step<N>> {
+    fn send(&mut self, content: MessageContent) -> Result<Step<N, S>> {
         if !self.netinfo.is_validator() {
             return Ok(Step::default());
         }
-        let mut step: Step<_> = Target::All
+        let mut step: Step<N, S> = Target::All
             .message(content.clone().with_epoch(self.epoch))
             .into();
         let our_id = &self.netinfo.our_id().clone();
@@ -264,11 +778,11 @@ impl<N: NodeIdT> BinaryAgreement<N> {
     }
 
     /// Handles a step returned from the `Coin`.
-    fn on_coin_step(&mut self, coin_step: coin::Step<N, Nonce>) -> Result<Step<N>> {
+    fn on_coin_step(&mut self, coin_step: ::Step<S>) -> Result<Step<N, S>> {
         let mut step = Step::default();
         let epoch = self.epoch;
         let to_msg = |c_msg| MessageContent::Coin(Box::new(c_msg)).with_epoch(epoch);
-        let coin_output = step.extend_with(coin_step, to_msg);
+        let (coin_output, _) = step.extend_with(coin_step, to_msg);
         if let Some(coin) = coin_output.into_iter().next() {
             self.coin_state = coin.into();
             step.extend(self.try_update_epoch()?);
@@ -282,7 +796,7 @@ impl<N: NodeIdT> BinaryAgreement<N> {
     /// With two conf values, the next epoch's estimate is the coin value. If there is only one conf
     /// value and that disagrees with the coin, the conf value is the next epoch's estimate. If
     /// the unique conf value agrees with the coin, terminates and decides on that value.
-    fn try_update_epoch(&mut self) -> Result<Step<N>> {
+    fn try_update_epoch(&mut self) -> Result<Step<N, S>> {
         if self.decision.is_some() {
             // Avoid an infinite regression without making a Binary Agreement step.
             return Ok(Step::default());
@@ -305,24 +819,44 @@ impl<N: NodeIdT> BinaryAgreement<N> {
 
     /// Creates the initial coin state for the current epoch, i.e. sets it to the predetermined
     /// value, or initializes a `Coin` instance.
-    fn coin_state(&self) -> CoinState<N> {
-        match self.epoch % 3 {
-            0 => CoinState::Decided(true),
-            1 => CoinState::Decided(false),
-            _ => {
+    fn coin_state(&self) -> CoinState<S> {
+        match self.fixed_coin() {
+            Some(value) => CoinState::Decided(value),
+            None => {
                 let nonce = Nonce::new(
                     self.netinfo.invocation_id().as_ref(),
-                    self.session_id,
+                    &self.session_id,
                     self.netinfo.node_index(&self.proposer_id).unwrap(),
                     self.epoch,
                 );
-                CoinState::InProgress(Coin::new(self.netinfo.clone(), nonce))
+                let mut coin = S::new_coin(self.netinfo.clone(), nonce);
+                coin.set_metrics(self.metrics.clone());
+                if let Some(ref coin_cache) = self.coin_cache {
+                    coin.set_cache(coin_cache.clone());
+                }
+                CoinState::InProgress(coin)
             }
         }
     }
 
+    /// Returns the current epoch's predetermined coin value: `true` in epochs that are 0 modulo
+    /// 3, `false` in 1 modulo 3, and `None` in 2 modulo 3, where it is pseudorandom.
+    fn fixed_coin(&self) -> Option<bool> {
+        match self.epoch.get() % 3 {
+            0 => Some(true),
+            1 => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the current epoch's coin value is pseudorandom, i.e. determined by a
+    /// `Coin` instance.
+    fn has_random_coin(&self) -> bool {
+        self.fixed_coin().is_none()
+    }
+
     /// Decides on a value and broadcasts a `Term` message with that value.
-    fn decide(&mut self, b: bool) -> Step<N> {
+    fn decide(&mut self, b: bool) -> Step<N, S> {
         if self.decision.is_some() {
             return Step::default();
         }
@@ -331,23 +865,36 @@ impl<N: NodeIdT> BinaryAgreement<N> {
         step.output.push_back(b);
         // Latch the decided state.
         self.decision = Some(b);
-        debug!(
-            "{:?}/{:?} (is_validator: {}) decision: {}",
-            self.netinfo.our_id(),
-            self.proposer_id,
-            self.netinfo.is_validator(),
-            b
+        debug_event!(
+            "Decision",
+            node = self.netinfo.our_id(),
+            proposer = self.proposer_id,
+            is_validator = self.netinfo.is_validator(),
+            value = b,
         );
+        let value = if b { "true" } else { "false" };
+        self.metrics
+            .counter(metrics::AGREEMENT_DECISIONS, &[("value", value)], 1);
         if self.netinfo.is_validator() {
-            let msg = MessageContent::Term(b).with_epoch(self.epoch + 1);
+            let content = if self.justified_termination {
+                let doc = self.term_document(b);
+                let sk_share = self.netinfo.secret_key_share();
+                let share = metrics::time_crypto(&*self.metrics, "sign", || sk_share.sign(doc));
+                let our_id = self.netinfo.our_id().clone();
+                self.term_shares.insert(our_id, (b, share.clone()));
+                MessageContent::SignedTerm(b, Box::new(share))
+            } else {
+                MessageContent::Term(b)
+            };
+            let msg = content.with_epoch(self.epoch.next());
             step.messages.push_back(Target::All.message(msg));
         }
         step
     }
 
     /// Checks whether the _N - f_ `Conf` messages have arrived, and if so, activates the coin.
-    fn try_finish_conf_round(&mut self) -> Result<Step<N>> {
-        if self.conf_values.is_none() || self.count_conf() < self.netinfo.num_correct() {
+    fn try_finish_conf_round(&mut self) -> Result<Step<N, S>> {
+        if self.conf_values.is_none() || self.conf_weight() < self.netinfo.correct_weight() {
             return Ok(Step::default());
         }
 
@@ -363,38 +910,92 @@ impl<N: NodeIdT> BinaryAgreement<N> {
         Ok(step)
     }
 
-    /// Counts the number of received `Conf` messages with values in `bin_values`.
-    fn count_conf(&self) -> usize {
-        let is_bin_val = |conf: &&BoolSet| conf.is_subset(self.sbv_broadcast.bin_values());
-        self.received_conf.values().filter(is_bin_val).count()
+    /// Returns the total weight of the senders of `Conf` messages with values in `bin_values`.
+    fn conf_weight(&self) -> u64 {
+        let bin_values = self.sbv_broadcast.bin_values();
+        let senders = self
+            .received_conf
+            .iter()
+            .filter(|(_, conf)| conf.is_subset(bin_values))
+            .map(|(id, _)| id);
+        self.netinfo.weight_of(senders)
+    }
+
+    /// Logs a warning with the current epoch's tallies, and passes them to the slow epochs hook.
+    fn report_slow_epochs(&self) {
+        let report = SlowAgreementReport {
+            proposer_id: self.proposer_id.clone(),
+            epoch: self.epoch,
+            estimated: self.estimated,
+            bin_values: self.bin_values(),
+            received_counts: self.received_counts(),
+            coin_schedule: self.coin_schedule(),
+            queued: self
+                .incoming_queue
+                .values()
+                .flat_map(|queue| queue.values())
+                .map(Vec::len)
+                .sum(),
+        };
+        warn_event!(
+            "No decision after many epochs",
+            node = self.netinfo.our_id(),
+            proposer = report.proposer_id,
+            epoch = report.epoch,
+            estimated = report.estimated,
+            bin_values = report.bin_values,
+            received = report.received_counts,
+            coin = report.coin_schedule,
+            queued = report.queued,
+        );
+        if let Some(ref hook) = self.slow_epochs_hook.0 {
+            hook(&report);
+        }
     }
 
     /// Increments the epoch, sets the new estimate and handles queued messages.
-    fn update_epoch(&mut self, b: bool) -> Result<Step<N>> {
+    fn update_epoch(&mut self, b: bool) -> Result<Step<N, S>> {
+        let ended = self.epoch.get() + 1;
+        if ended >= self.max_epochs {
+            return Err(Error::EpochLimitReached(self.max_epochs));
+        }
+        if self.slow_epochs > 0 && ended % self.slow_epochs == 0 {
+            self.report_slow_epochs();
+        }
         self.sbv_broadcast.clear(&self.received_term);
         self.received_conf.clear();
         for (v, id) in &self.received_term {
             self.received_conf.insert(id.clone(), BoolSet::from(v));
         }
         self.conf_values = None;
-        self.epoch += 1;
+        self.epoch = self.epoch.next();
         self.coin_state = self.coin_state();
-        debug!(
-            "{:?} BinaryAgreement instance {:?} started epoch {}, {} terminated",
-            self.netinfo.our_id(),
-            self.proposer_id,
-            self.epoch,
-            self.received_conf.len(),
+        self.metrics.counter(metrics::AGREEMENT_EPOCHS, &[], 1);
+        debug_event!(
+            "Started epoch",
+            node = self.netinfo.our_id(),
+            proposer = self.proposer_id,
+            epoch = self.epoch,
+            terminated = self.received_conf.len(),
         );
 
         self.estimated = Some(b);
         let sbvb_step = self.sbv_broadcast.handle_input(b)?;
         let mut step = self.handle_sbvb_step(sbvb_step)?;
-        let queued_msgs = self
-            .incoming_queue
-            .remove(&self.epoch)
-            .into_iter()
-            .flatten();
+        let epoch = self.epoch;
+        let mut queued_msgs = Vec::new();
+        let mut emptied = Vec::new();
+        for (sender_id, queue) in &mut self.incoming_queue {
+            for content in queue.remove(&epoch).into_iter().flatten() {
+                queued_msgs.push((sender_id.clone(), content));
+            }
+            if queue.is_empty() {
+                emptied.push(sender_id.clone());
+            }
+        }
+        for sender_id in emptied {
+            self.incoming_queue.remove(&sender_id);
+        }
         for (sender_id, content) in queued_msgs {
             step.extend(self.handle_message_content(&sender_id, content)?);
             if self.decision.is_some() {
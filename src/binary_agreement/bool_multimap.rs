@@ -2,7 +2,8 @@ use std::collections::{btree_set, BTreeSet};
 use std::ops::{Index, IndexMut};
 
 /// A map from `bool` to `BTreeSet<N>`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + ::serde::Deserialize<'de>"))]
 pub struct BoolMultimap<N>([BTreeSet<N>; 2]);
 
 impl<N: Ord> Default for BoolMultimap<N> {
@@ -11,9 +11,8 @@ pub const TRUE: BoolSet = BoolSet(0b10);
 pub const BOTH: BoolSet = BoolSet(0b11);
 
 /// A set of `bool` values, represented as a single byte in memory.
-#[derive(
-    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Rand, Default,
-)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "testing", derive(Rand))]
 pub struct BoolSet(u8);
 
 impl BoolSet {
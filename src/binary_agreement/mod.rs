@@ -62,18 +62,50 @@
 //!
 //! * After _f + 1_ nodes have sent us their coin shares, we receive the coin output and assign it
 //! to `s`.
+//!
+//! With _justified termination_, `Term` messages additionally contain a signature share on the
+//! decision, and unsigned ones are rejected. Any _f + 1_ of these shares can be combined into a
+//! threshold signature, which proves the decision to third parties: see
+//! `BinaryAgreement::decision_proof`. All nodes must use the same setting.
+//!
+//! The coin is pluggable: `BinaryAgreement` is generic over its `CoinSource`. By default, it uses
+//! the threshold signature based `Coin` described above. The `InsecureCoin` instead derives the
+//! value from the nonce alone, which is only safe if the adversary doesn't control the network.
+//!
+//! Each instance is bound to a _session ID_, which can be any `SessionId`, i.e. any cloneable value
+//! with a byte representation. It should contain everything that identifies the instance in the
+//! embedding protocol, e.g. a chain ID, a block hash or a `Subset` epoch. The coin nonces and
+//! signed `Term` documents include the session ID, so instances in different sessions don't
+//! accept each other's signature shares. Both are domain-separated, length-prefixed encodings of
+//! their components, so they can't collide across differently parameterized instances.
+//!
+//! With correct nodes forming a quorum, an instance decides after a few epochs in expectation. If
+//! one takes much longer, that usually points to a scheduling problem or a bug. So every time an
+//! instance completes another `BinaryAgreement::set_slow_epochs` epochs without a decision, it logs
+//! a warning with its tallies, and passes them to the hook set with
+//! `BinaryAgreement::set_slow_epochs_hook` as a `SlowAgreementReport`.
 
 mod binary_agreement;
 mod bool_multimap;
 pub mod bool_set;
 mod sbv_broadcast;
 
+use std::fmt;
+
+use byteorder::{BigEndian, ByteOrder};
+use crypto::SignatureShare;
+#[cfg(feature = "testing")]
 use rand;
 
 use self::bool_set::BoolSet;
-use coin::{self, CoinMessage};
+use coin::{self, Coin, CoinMessage};
+use epoch::AgreementEpoch;
 
-pub use self::binary_agreement::BinaryAgreement;
+pub use self::binary_agreement::{
+    BinaryAgreement, BinaryAgreementSnapshot, SlowEpochsHook, DEFAULT_MAX_EPOCHS,
+    DEFAULT_MAX_FUTURE_EPOCHS, DEFAULT_MAX_QUEUED_PER_EPOCH, DEFAULT_SLOW_EPOCHS,
+};
+pub use self::sbv_broadcast::Message as SbvMessage;
 
 /// An Binary Agreement error.
 #[derive(Clone, Eq, PartialEq, Debug, Fail)]
@@ -86,12 +118,55 @@ pub enum Error {
     UnknownProposer,
     #[fail(display = "Input not accepted")]
     InputNotAccepted,
+    #[fail(display = "No decision after {} epochs", _0)]
+    EpochLimitReached(u64),
 }
 
 /// An Binary Agreement result.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
-pub type Step<N> = ::Step<BinaryAgreement<N>>;
+impl Error {
+    /// Returns `true` if the instance cannot decide anymore. Otherwise only the input was
+    /// rejected.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::HandleCoin(ref err) | Error::TryFinishConfRoundCoin(ref err) => err.is_fatal(),
+            Error::InputNotAccepted => false,
+            Error::UnknownProposer | Error::EpochLimitReached(_) => true,
+        }
+    }
+}
+
+/// Represents each reason why a Binary Agreement message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message was received from a node that is not a validator.
+    UnknownSender,
+    /// `BinaryAgreement` received a duplicate `BVal` message.
+    DuplicateBVal,
+    /// `BinaryAgreement` received a duplicate `Aux` message.
+    DuplicateAux,
+    /// `BinaryAgreement` received a coin message in an epoch with a fixed coin value.
+    UnexpectedCoinMessage,
+    /// `BinaryAgreement` received a message for an epoch too far in the future.
+    AgreementEpochTooFarAhead,
+    /// `BinaryAgreement` received more messages for a future epoch than a correct node sends.
+    TooManyAgreementMessages,
+    /// `BinaryAgreement` received a `Term` message with an invalid signature share.
+    InvalidTermSignature,
+    /// `BinaryAgreement` received an unsigned `Term` message with justified termination enabled.
+    UnsignedTerm,
+    /// The coin of an epoch received a faulty message.
+    Coin(coin::FaultKind),
+}
+
+impl From<coin::FaultKind> for FaultKind {
+    fn from(fault_kind: coin::FaultKind) -> Self {
+        FaultKind::Coin(fault_kind)
+    }
+}
+
+pub type Step<N, S = Coin<N, Nonce>> = ::Step<BinaryAgreement<N, S>>;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum MessageContent {
@@ -103,11 +178,13 @@ pub enum MessageContent {
     Term(bool),
     /// Coin message,
     Coin(Box<CoinMessage>),
+    /// `Term` message with a signature share on the decision, sent with justified termination.
+    SignedTerm(bool, Box<SignatureShare>),
 }
 
 impl MessageContent {
     /// Creates an message with a given epoch number.
-    pub fn with_epoch(self, epoch: u32) -> Message {
+    pub fn with_epoch(self, epoch: AgreementEpoch) -> Message {
         Message {
             epoch,
             content: self,
@@ -117,51 +194,148 @@ impl MessageContent {
     /// Returns `true` if this message can be ignored if its epoch has already passed.
     pub fn can_expire(&self) -> bool {
         match *self {
-            MessageContent::Term(_) => false,
+            MessageContent::Term(_) | MessageContent::SignedTerm(..) => false,
             _ => true,
         }
     }
 }
 
 /// Messages sent during the Binary Agreement stage.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Rand)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "testing", derive(Rand))]
 pub struct Message {
-    pub epoch: u32,
+    pub epoch: AgreementEpoch,
     pub content: MessageContent,
 }
 
 // NOTE: Extending rand_derive to correctly generate random values from boxes would make this
 // implementation obsolete; however at the time of this writing, `rand::Rand` is already deprecated
 // with no replacement in sight.
+#[cfg(feature = "testing")]
 impl rand::Rand for MessageContent {
     fn rand<R: rand::Rng>(rng: &mut R) -> Self {
-        let message_type = *rng.choose(&["sbvb", "conf", "term", "coin"]).unwrap();
+        let message_type = *rng
+            .choose(&["sbvb", "conf", "term", "coin", "signed_term"])
+            .unwrap();
 
         match message_type {
             "sbvb" => MessageContent::SbvBroadcast(rng.gen()),
             "conf" => MessageContent::Conf(rng.gen()),
             "term" => MessageContent::Term(rng.gen()),
             "coin" => MessageContent::Coin(Box::new(rng.gen())),
+            "signed_term" => MessageContent::SignedTerm(rng.gen(), Box::new(rng.gen())),
             _ => unreachable!(),
         }
     }
 }
 
-#[derive(Clone, Debug)]
-struct Nonce(Vec<u8>);
+/// An identifier that binds a Binary Agreement instance to its context in the embedding protocol.
+/// Its byte representation is included in the coin nonces and signed documents.
+pub trait SessionId: AsRef<[u8]> + Clone + fmt::Debug + Send + Sync {}
+
+impl<T> SessionId for T where T: AsRef<[u8]> + Clone + fmt::Debug + Send + Sync {}
+
+/// The numbers of validators from which a `BinaryAgreement` instance received each kind of message
+/// in its current epoch. Each array contains the counts for `false` and `true`, in that order.
+///
+/// Validators that sent `Term(b)` count as having sent `BVal(b)` and `Aux(b)` in every epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReceivedCounts {
+    /// The numbers of `BVal(false)` and `BVal(true)` senders.
+    pub bval: [usize; 2],
+    /// The numbers of `Aux(false)` and `Aux(true)` senders.
+    pub aux: [usize; 2],
+    /// The numbers of `Conf` senders whose value set contains `false` and `true`.
+    pub conf: [usize; 2],
+    /// The numbers of `Term(false)` and `Term(true)` senders.
+    pub term: [usize; 2],
+}
+
+/// The state of a `BinaryAgreement` instance at the end of an epoch without a decision, reported
+/// to the `SlowEpochsHook` if the instance takes unusually many epochs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlowAgreementReport<N> {
+    /// The ID of the instance's proposer.
+    pub proposer_id: N,
+    /// The epoch that just ended. It is the `epoch + 1`-th one without a decision.
+    pub epoch: AgreementEpoch,
+    /// Our estimate of the decision value in that epoch.
+    pub estimated: Option<bool>,
+    /// The values for which _2 f + 1_ `BVal`s were received in that epoch.
+    pub bin_values: BoolSet,
+    /// The numbers of validators from which each kind of message was received in that epoch.
+    pub received_counts: ReceivedCounts,
+    /// How the epoch's coin value was determined.
+    pub coin_schedule: CoinSchedule,
+    /// The number of messages queued for later epochs.
+    pub queued: usize,
+}
+
+/// How the coin value of a `BinaryAgreement` epoch is determined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoinSchedule {
+    /// The value is fixed: `true` in epochs that are 0 modulo 3, `false` in 1 modulo 3.
+    Fixed(bool),
+    /// The value is pseudorandom, produced by the coin source in epochs that are 2 modulo 3. This
+    /// contains the value once the coin has output.
+    Random(Option<bool>),
+}
+
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::agreement";
+
+/// The domain tag of coin nonces. It distinguishes them from other signed documents.
+const NONCE_DOMAIN: &[u8] = b"hbbft binary agreement coin nonce";
+
+/// The domain tag of the documents signed in `SignedTerm` messages.
+const TERM_DOMAIN: &[u8] = b"hbbft binary agreement term";
+
+/// The nonce of an epoch's coin. It is unique for each instance and epoch.
+///
+/// It consists of the domain tag, the invocation ID and the session ID, each prefixed with its
+/// length as a big-endian `u64`, followed by the proposer index and the epoch as big-endian `u64`s.
+/// So two nonces are only equal if all their components are.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Nonce(Vec<u8>);
 
 impl Nonce {
     pub fn new(
         invocation_id: &[u8],
-        session_id: u64,
+        session_id: &[u8],
         proposer_id: usize,
-        binary_agreement_epoch: u32,
+        binary_agreement_epoch: AgreementEpoch,
     ) -> Self {
-        Nonce(Vec::from(format!(
-            "Nonce for Honey Badger {:?}@{}:{}:{}",
-            invocation_id, session_id, binary_agreement_epoch, proposer_id
-        )))
+        Nonce(encode_document(
+            NONCE_DOMAIN,
+            invocation_id,
+            session_id,
+            proposer_id,
+            binary_agreement_epoch.get(),
+        ))
+    }
+}
+
+/// Returns an unambiguous encoding of the given fields: The byte strings are prefixed with their
+/// lengths, and the integers have a fixed size, so different fields never produce the same bytes.
+pub(crate) fn encode_document(
+    domain: &[u8],
+    invocation_id: &[u8],
+    session_id: &[u8],
+    proposer_id: usize,
+    value: u64,
+) -> Vec<u8> {
+    let mut doc = Vec::new();
+    for field in &[domain, invocation_id, session_id] {
+        let mut len = [0; 8];
+        BigEndian::write_u64(&mut len, field.len() as u64);
+        doc.extend_from_slice(&len);
+        doc.extend_from_slice(field);
     }
+    let mut ints = [0; 16];
+    BigEndian::write_u64(&mut ints[..8], proposer_id as u64);
+    BigEndian::write_u64(&mut ints[8..], value);
+    doc.extend_from_slice(&ints);
+    doc
 }
 
 impl AsRef<[u8]> for Nonce {
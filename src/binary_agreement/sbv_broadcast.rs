@@ -8,14 +8,19 @@
 //!
 //! It will only output once, but can continue handling messages and will keep track of the set
 //! `bin_values` of values for which _2 f + 1_ `BVal`s were received.
+//!
+//! Whenever a step multicasts both a `BVal` and an `Aux` message, they are merged into a single
+//! `BValAux` message, which the recipients handle like the two separate ones.
 
+#[cfg(feature = "testing")]
 use rand;
 use std::sync::Arc;
 
 use super::bool_multimap::BoolMultimap;
 use super::bool_set::{self, BoolSet};
-use super::{Error, Result};
-use fault_log::{Fault, FaultKind};
+use super::{Error, FaultKind, Result};
+use environment::{self, Environment, Restore};
+use fault_log::Fault;
 use {DistAlgorithm, NetworkInfo, NodeIdT, Target};
 
 pub type Step<N> = ::Step<SbvBroadcast<N>>;
@@ -24,24 +29,28 @@ pub type Step<N> = ::Step<SbvBroadcast<N>>;
 pub enum Message {
     BVal(bool),
     Aux(bool),
+    /// A `BVal` and an `Aux` message, in this order, sent together.
+    BValAux(bool, bool),
 }
 
 // NOTE: Extending rand_derive to correctly generate random values from boxes would make this
 // implementation obsolete; however at the time of this writing, `rand::Rand` is already deprecated
 // with no replacement in sight.
+#[cfg(feature = "testing")]
 impl rand::Rand for Message {
     fn rand<R: rand::Rng>(rng: &mut R) -> Self {
-        let message_type = *rng.choose(&["bval", "aux"]).unwrap();
+        let message_type = *rng.choose(&["bval", "aux", "bval_aux"]).unwrap();
 
         match message_type {
             "bval" => Message::BVal(rng.gen()),
             "aux" => Message::Aux(rng.gen()),
+            "bval_aux" => Message::BValAux(rng.gen(), rng.gen()),
             _ => unreachable!(),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SbvBroadcast<N> {
     /// Shared network information.
     netinfo: Arc<NetworkInfo<N>>,
@@ -57,12 +66,24 @@ pub struct SbvBroadcast<N> {
     terminated: bool,
 }
 
+/// A serializable snapshot of an `SbvBroadcast` instance, without its environment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+pub struct SbvBroadcastSnapshot<N> {
+    bin_values: BoolSet,
+    received_bval: BoolMultimap<N>,
+    sent_bval: BoolSet,
+    received_aux: BoolMultimap<N>,
+    terminated: bool,
+}
+
 impl<N: NodeIdT> DistAlgorithm for SbvBroadcast<N> {
     type NodeId = N;
     type Input = bool;
     type Output = BoolSet;
     type Message = Message;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<N>> {
         self.send_bval(input)
@@ -72,6 +93,11 @@ impl<N: NodeIdT> DistAlgorithm for SbvBroadcast<N> {
         match msg {
             Message::BVal(b) => self.handle_bval(sender_id, b),
             Message::Aux(b) => self.handle_aux(sender_id, b),
+            Message::BValAux(bval, aux) => {
+                let mut step = self.handle_bval(sender_id, bval)?;
+                step.extend(self.handle_aux(sender_id, aux)?);
+                Ok(step)
+            }
         }
     }
 
@@ -84,6 +110,34 @@ impl<N: NodeIdT> DistAlgorithm for SbvBroadcast<N> {
     }
 }
 
+impl<N: NodeIdT> Restore<N> for SbvBroadcast<N> {
+    type Snapshot = SbvBroadcastSnapshot<N>;
+
+    fn snapshot(&self) -> SbvBroadcastSnapshot<N> {
+        SbvBroadcastSnapshot {
+            bin_values: self.bin_values,
+            received_bval: self.received_bval.clone(),
+            sent_bval: self.sent_bval,
+            received_aux: self.received_aux.clone(),
+            terminated: self.terminated,
+        }
+    }
+
+    fn restore(
+        snapshot: SbvBroadcastSnapshot<N>,
+        env: &Environment<N>,
+    ) -> environment::Result<Self> {
+        Ok(SbvBroadcast {
+            netinfo: env.netinfo.clone(),
+            bin_values: snapshot.bin_values,
+            received_bval: snapshot.received_bval,
+            sent_bval: snapshot.sent_bval,
+            received_aux: snapshot.received_aux,
+            terminated: snapshot.terminated,
+        })
+    }
+}
+
 impl<N: NodeIdT> SbvBroadcast<N> {
     pub fn new(netinfo: Arc<NetworkInfo<N>>) -> Self {
         SbvBroadcast {
@@ -109,18 +163,19 @@ impl<N: NodeIdT> SbvBroadcast<N> {
     /// Handles a `BVal(b)` message.
     ///
     /// Upon receiving _f + 1_ `BVal(b)`, multicasts `BVal(b)`. Upon receiving _2 f + 1_ `BVal(b)`,
-    /// updates `bin_values`. When `bin_values` gets its first entry, multicasts `Aux(b)`.
+    /// updates `bin_values`. When `bin_values` gets its first entry, multicasts `Aux(b)`. With
+    /// weighted validators, the senders' weights are counted instead of their number.
     pub fn handle_bval(&mut self, sender_id: &N, b: bool) -> Result<Step<N>> {
-        let count_bval = {
+        let bval_weight = {
             if !self.received_bval[b].insert(sender_id.clone()) {
                 return Ok(Fault::new(sender_id.clone(), FaultKind::DuplicateBVal).into());
             }
-            self.received_bval[b].len()
+            self.netinfo.weight_of(&self.received_bval[b])
         };
 
         let mut step = Step::default();
 
-        if count_bval == 2 * self.netinfo.num_faulty() + 1 {
+        if bval_weight > 2 * self.netinfo.faulty_weight() && !self.bin_values.contains(b) {
             self.bin_values.insert(b);
 
             if self.bin_values != bool_set::BOTH {
@@ -130,10 +185,11 @@ impl<N: NodeIdT> SbvBroadcast<N> {
             }
         }
 
-        if count_bval == self.netinfo.num_faulty() + 1 {
+        if bval_weight > self.netinfo.faulty_weight() {
             step.extend(self.send_bval(b)?);
         }
 
+        Self::coalesce(&mut step);
         Ok(step)
     }
 
@@ -142,6 +198,16 @@ impl<N: NodeIdT> SbvBroadcast<N> {
         self.bin_values
     }
 
+    /// Returns the number of nodes that sent us `BVal(b)`.
+    pub fn count_bval(&self, b: bool) -> usize {
+        self.received_bval[b].len()
+    }
+
+    /// Returns the number of nodes that sent us `Aux(b)`.
+    pub fn count_aux(&self, b: bool) -> usize {
+        self.received_aux[b].len()
+    }
+
     /// Multicasts and handles a message. Does nothing if we are only an observer.
     fn send(&mut self, msg: Message) -> Result<Step<N>> {
         if !self.netinfo.is_validator() {
@@ -150,9 +216,29 @@ impl<N: NodeIdT> SbvBroadcast<N> {
         let mut step: Step<_> = Target::All.message(msg.clone()).into();
         let our_id = &self.netinfo.our_id().clone();
         step.extend(self.handle_message(our_id, msg)?);
+        Self::coalesce(&mut step);
         Ok(step)
     }
 
+    /// Merges pairs of multicast `BVal` and `Aux` messages in the step into `BValAux` messages.
+    fn coalesce(step: &mut Step<N>) {
+        let find = |step: &Step<N>, is_bval: bool| {
+            step.messages
+                .iter()
+                .enumerate()
+                .filter(|(_, tm)| tm.target == Target::All)
+                .filter_map(|(i, tm)| match tm.message {
+                    Message::BVal(b) if is_bval => Some((i, b)),
+                    Message::Aux(b) if !is_bval => Some((i, b)),
+                    _ => None,
+                }).next()
+        };
+        while let (Some((i, bval)), Some((j, aux))) = (find(step, true), find(step, false)) {
+            step.messages[i].message = Message::BValAux(bval, aux);
+            step.messages.remove(j);
+        }
+    }
+
     /// Multicasts a `BVal(b)` message, and handles it.
     fn send_bval(&mut self, b: bool) -> Result<Step<N>> {
         // Record the value `b` as sent. If it was already there, don't send it again.
@@ -175,29 +261,29 @@ impl<N: NodeIdT> SbvBroadcast<N> {
         if self.terminated || self.bin_values == bool_set::NONE {
             return Ok(Step::default());
         }
-        let (aux_count, aux_vals) = self.count_aux();
-        if aux_count < self.netinfo.num_correct() {
+        let (aux_weight, aux_vals) = self.count_bin_values_aux();
+        if aux_weight < self.netinfo.correct_weight() {
             return Ok(Step::default());
         }
         self.terminated = true;
         Ok(Step::default().with_output(aux_vals))
     }
 
-    /// The count of `Aux` messages such that the set of values carried by those messages is a
-    /// subset of `bin_values`.
+    /// The total weight of the senders of `Aux` messages such that the set of values carried by
+    /// those messages is a subset of `bin_values`.
     ///
     /// In general, we can't expect every good node to send the same `Aux` value, so waiting for
     /// _N - f_ agreeing messages would not always terminate. We can, however, expect every good
     /// node to send an `Aux` value that will eventually end up in our `bin_values`.
-    fn count_aux(&self) -> (usize, BoolSet) {
+    fn count_bin_values_aux(&self) -> (u64, BoolSet) {
         let mut values = bool_set::NONE;
-        let mut count = 0;
+        let mut weight = 0;
         for b in self.bin_values {
             if !self.received_aux[b].is_empty() {
                 values.insert(b);
-                count += self.received_aux[b].len();
+                weight += self.netinfo.weight_of(&self.received_aux[b]);
             }
         }
-        (count, values)
+        (weight, values)
     }
 }
@@ -1,36 +1,95 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
 use byteorder::{BigEndian, ByteOrder};
-use hex_fmt::{HexFmt, HexList};
-use reed_solomon_erasure as rse;
-use reed_solomon_erasure::ReedSolomon;
+use tiny_keccak::sha3_256;
 
+use super::codec::{Codec, ReedSolomonCodec};
 use super::merkle::{Digest, MerkleTree, Proof};
 use super::message::HexProof;
-use super::{Error, Message, Result};
-use fault_log::{Fault, FaultKind};
+use super::{Error, FaultKind, Message, Result, LOG_TARGET};
+use environment::{self, Environment, Restore, RestoreError};
+use validation::Validity;
 use {DistAlgorithm, NetworkInfo, NodeIdT, Target};
 
 /// Broadcast algorithm instance.
-#[derive(Debug)]
+///
+/// Instances can be cloned. Clones share the codec, which is immutable.
+///
+/// If handling an input or message fails with a fatal error, the instance is abandoned, so it is
+/// never left partially updated.
+#[derive(Clone, Debug)]
 pub struct Broadcast<N> {
     /// Shared network data.
     netinfo: Arc<NetworkInfo<N>>,
     /// The ID of the sending node.
     proposer_id: N,
-    data_shard_num: usize,
-    coding: Coding,
+    /// The erasure code used to split the value into shards.
+    codec: Arc<dyn Codec>,
+    /// Whether the codec was set with `set_codec`, rather than being a `ReedSolomonCodec`.
+    custom_codec: bool,
+    /// Values shorter than this many bytes are sent as a whole instead of erasure-coded.
+    small_value_threshold: usize,
+    /// The maximum size of a value in bytes. Shards implying larger values are rejected.
+    max_value_size: usize,
     /// Whether we have already multicast `Echo`.
     echo_sent: bool,
     /// Whether we have already multicast `Ready`.
     ready_sent: bool,
+    /// Whether we have already multicast `CanDecode`.
+    can_decode_sent: bool,
     /// Whether we have already output a value.
     decided: bool,
+    /// Whether we gave up on the proposer. If so, we don't output and ignore all messages.
+    abandoned: bool,
     /// The proofs we have received via `Echo` messages, by sender ID.
     echos: BTreeMap<N, Proof<Vec<u8>>>,
+    /// The root hashes we have received via `EchoHash` messages, by sender ID.
+    echo_hashes: BTreeMap<N, Digest>,
+    /// The values and their hashes we have received via `FullEcho` messages, by sender ID.
+    full_echos: BTreeMap<N, (Digest, Vec<u8>)>,
     /// The root hashes we received via `Ready` messages, by sender ID.
     readys: BTreeMap<N, Vec<u8>>,
+    /// The root hashes for which nodes told us via `CanDecode` that they have enough shards, by
+    /// sender ID. These nodes don't need our `Echo` anymore.
+    can_decodes: BTreeMap<N, Digest>,
+    /// The root hashes for which nodes asked us for our `Echo` via `EchoRequest`, by sender ID.
+    echo_requests: BTreeMap<N, Digest>,
+    /// The nodes we have asked for their `Echo` via `EchoRequest`.
+    echos_requested: BTreeSet<N>,
+    /// The leaf hashes of the proofs we have accepted, by root hash and index. They are reused
+    /// when reconstructing the Merkle tree, instead of hashing the shards again.
+    leaf_hashes: BTreeMap<(Digest, usize), Digest>,
+    /// The leaf hash of the proof in the message that is being validated. It is only added to
+    /// `leaf_hashes` if the message is accepted, so that every sender adds at most one entry.
+    validated_leaf_hash: Option<((Digest, usize), Digest)>,
+}
+
+/// A serializable snapshot of a `Broadcast` instance, without its environment. Instead of the
+/// codec, it contains its numbers of shards: A `ReedSolomonCodec` is restored with these, but a
+/// custom codec must be provided by the `Environment`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+pub struct BroadcastSnapshot<N> {
+    proposer_id: N,
+    data_shard_num: usize,
+    parity_shard_num: usize,
+    custom_codec: bool,
+    small_value_threshold: usize,
+    max_value_size: usize,
+    echo_sent: bool,
+    ready_sent: bool,
+    can_decode_sent: bool,
+    decided: bool,
+    abandoned: bool,
+    echos: BTreeMap<N, Proof<Vec<u8>>>,
+    echo_hashes: BTreeMap<N, Digest>,
+    full_echos: BTreeMap<N, (Digest, Vec<u8>)>,
+    readys: BTreeMap<N, Vec<u8>>,
+    can_decodes: BTreeMap<N, Digest>,
+    echo_requests: BTreeMap<N, Digest>,
+    echos_requested: BTreeSet<N>,
+    leaf_hashes: BTreeMap<(Digest, usize), Digest>,
 }
 
 pub type Step<N> = ::Step<Broadcast<N>>;
@@ -43,33 +102,65 @@ impl<N: NodeIdT> DistAlgorithm for Broadcast<N> {
     type Output = Self::Input;
     type Message = Message;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<N>> {
+        enter_span!(
+            "broadcast",
+            node = self.netinfo.our_id(),
+            proposer = self.proposer_id,
+        );
         if *self.netinfo.our_id() != self.proposer_id {
             return Err(Error::InstanceCannotPropose);
         }
+        if self.abandoned {
+            return Ok(Step::default());
+        }
+        if input.len() > self.max_value_size {
+            return Err(Error::InputTooLarge);
+        }
+        if input.len() < self.small_value_threshold {
+            // Small values are sent as a whole, without erasure coding and Merkle proofs.
+            return self.send_full_value(input);
+        }
         // Split the value into chunks/shards, encode them with erasure codes.
         // Assemble a Merkle tree from data and parity shards. Take all proofs
         // from this tree and send them, each to its own node.
-        let (proof, mut step) = self.send_shards(input)?;
-        let our_id = &self.netinfo.our_id().clone();
-        step.extend(self.handle_value(our_id, proof)?);
-        Ok(step)
+        let result = self.send_shards(input).and_then(|(proof, mut step)| {
+            step.extend(self.handle_value(proof)?);
+            Ok(step)
+        });
+        self.abandon_on_error(result)
     }
 
     fn handle_message(&mut self, sender_id: &N, message: Self::Message) -> Result<Step<N>> {
-        if !self.netinfo.is_node_validator(sender_id) {
-            return Err(Error::UnknownSender);
-        }
-        match message {
-            Message::Value(p) => self.handle_value(sender_id, p),
-            Message::Echo(p) => self.handle_echo(sender_id, p),
-            Message::Ready(ref hash) => self.handle_ready(sender_id, hash),
+        enter_span!(
+            "broadcast",
+            node = self.netinfo.our_id(),
+            proposer = self.proposer_id,
+        );
+        let validity = self.validate_message(sender_id, &message);
+        let validated_leaf_hash = self.validated_leaf_hash.take();
+        let mut step = Step::validated(sender_id.clone(), validity);
+        if validity.is_valid() {
+            self.leaf_hashes.extend(validated_leaf_hash);
+            let result = match message {
+                Message::Value(p) => self.handle_value(p),
+                Message::Echo(p) => self.handle_echo(sender_id, p),
+                Message::Ready(ref hash) => self.handle_ready(sender_id, hash),
+                Message::EchoHash(ref hash) => self.handle_echo_hash(sender_id, hash),
+                Message::CanDecode(ref hash) => Ok(self.handle_can_decode(sender_id, hash)),
+                Message::EchoRequest(ref hash) => Ok(self.handle_echo_request(sender_id, hash)),
+                Message::FullValue(value) => self.handle_full_value(value),
+                Message::FullEcho(value) => self.handle_full_echo(sender_id, value),
+            };
+            step.extend(self.abandon_on_error(result)?);
         }
+        Ok(step)
     }
 
     fn terminated(&self) -> bool {
-        self.decided
+        self.decided || self.abandoned
     }
 
     fn our_id(&self) -> &N {
@@ -77,40 +168,236 @@ impl<N: NodeIdT> DistAlgorithm for Broadcast<N> {
     }
 }
 
+impl<N: NodeIdT> Restore<N> for Broadcast<N> {
+    type Snapshot = BroadcastSnapshot<N>;
+
+    fn snapshot(&self) -> BroadcastSnapshot<N> {
+        BroadcastSnapshot {
+            proposer_id: self.proposer_id.clone(),
+            data_shard_num: self.codec.data_shard_count(),
+            parity_shard_num: self.codec.parity_shard_count(),
+            custom_codec: self.custom_codec,
+            small_value_threshold: self.small_value_threshold,
+            max_value_size: self.max_value_size,
+            echo_sent: self.echo_sent,
+            ready_sent: self.ready_sent,
+            can_decode_sent: self.can_decode_sent,
+            decided: self.decided,
+            abandoned: self.abandoned,
+            echos: self.echos.clone(),
+            echo_hashes: self.echo_hashes.clone(),
+            full_echos: self.full_echos.clone(),
+            readys: self.readys.clone(),
+            can_decodes: self.can_decodes.clone(),
+            echo_requests: self.echo_requests.clone(),
+            echos_requested: self.echos_requested.clone(),
+            leaf_hashes: self.leaf_hashes.clone(),
+        }
+    }
+
+    fn restore(snapshot: BroadcastSnapshot<N>, env: &Environment<N>) -> environment::Result<Self> {
+        let (data_shard_num, parity_shard_num) =
+            (snapshot.data_shard_num, snapshot.parity_shard_num);
+        let codec: Arc<dyn Codec> = if snapshot.custom_codec {
+            let codec = env.codec.clone().ok_or(RestoreError::MissingCodec)?;
+            let shard_nums = (codec.data_shard_count(), codec.parity_shard_count());
+            if shard_nums != (data_shard_num, parity_shard_num) {
+                return Err(RestoreError::CodecMismatch(shard_nums.0, shard_nums.1));
+            }
+            codec
+        } else {
+            let codec = ReedSolomonCodec::new(data_shard_num, parity_shard_num)
+                .map_err(RestoreError::NewCodec)?;
+            Arc::new(codec)
+        };
+        Ok(Broadcast {
+            netinfo: env.netinfo.clone(),
+            proposer_id: snapshot.proposer_id,
+            codec,
+            custom_codec: snapshot.custom_codec,
+            small_value_threshold: snapshot.small_value_threshold,
+            max_value_size: snapshot.max_value_size,
+            echo_sent: snapshot.echo_sent,
+            ready_sent: snapshot.ready_sent,
+            can_decode_sent: snapshot.can_decode_sent,
+            decided: snapshot.decided,
+            abandoned: snapshot.abandoned,
+            echos: snapshot.echos,
+            echo_hashes: snapshot.echo_hashes,
+            full_echos: snapshot.full_echos,
+            readys: snapshot.readys,
+            can_decodes: snapshot.can_decodes,
+            echo_requests: snapshot.echo_requests,
+            echos_requested: snapshot.echos_requested,
+            leaf_hashes: snapshot.leaf_hashes,
+            validated_leaf_hash: None,
+        })
+    }
+}
+
 impl<N: NodeIdT> Broadcast<N> {
     /// Creates a new broadcast instance to be used by node `our_id` which expects a value proposal
     /// from node `proposer_id`.
     pub fn new(netinfo: Arc<NetworkInfo<N>>, proposer_id: N) -> Result<Self> {
         let parity_shard_num = 2 * netinfo.num_faulty();
         let data_shard_num = netinfo.num_nodes() - parity_shard_num;
-        let coding = Coding::new(data_shard_num, parity_shard_num)?;
+        let codec = ReedSolomonCodec::new(data_shard_num, parity_shard_num)?;
 
         Ok(Broadcast {
             netinfo,
             proposer_id,
-            data_shard_num,
-            coding,
+            codec: Arc::new(codec),
+            custom_codec: false,
+            small_value_threshold: 0,
+            max_value_size: usize::max_value(),
             echo_sent: false,
             ready_sent: false,
+            can_decode_sent: false,
             decided: false,
+            abandoned: false,
             echos: BTreeMap::new(),
+            echo_hashes: BTreeMap::new(),
+            full_echos: BTreeMap::new(),
             readys: BTreeMap::new(),
+            can_decodes: BTreeMap::new(),
+            echo_requests: BTreeMap::new(),
+            echos_requested: BTreeSet::new(),
+            leaf_hashes: BTreeMap::new(),
+            validated_leaf_hash: None,
         })
     }
 
+    /// Sets the number of data shards the value is split into. The remaining shards, one per
+    /// validator, are Reed-Solomon parity shards.
+    ///
+    /// The number must be between 1 and _N - 2 f_, which is the default. Fewer data shards mean
+    /// more redundancy: larger `Value` and `Echo` messages, but less reliance on other nodes'
+    /// echos. All nodes must use the same parameters, and they must be set before any input or
+    /// message is handled.
+    pub fn set_data_shard_num(&mut self, data_shard_num: usize) -> Result<()> {
+        let parity_shard_num = self.netinfo.num_nodes().saturating_sub(data_shard_num);
+        let codec = ReedSolomonCodec::new(data_shard_num, parity_shard_num)?;
+        self.replace_codec(Box::new(codec))?;
+        self.custom_codec = false;
+        Ok(())
+    }
+
+    /// Sets the erasure code used to split the value into shards.
+    ///
+    /// The codec must produce exactly one shard per validator, and must not require more than
+    /// _N - 2 f_ data shards for reconstruction. All nodes must use the same codec, and it must be
+    /// set before any input or message is handled.
+    pub fn set_codec(&mut self, codec: Box<dyn Codec>) -> Result<()> {
+        self.replace_codec(codec)?;
+        self.custom_codec = true;
+        Ok(())
+    }
+
+    /// Checks the codec's numbers of shards, and replaces the current codec with it.
+    fn replace_codec(&mut self, codec: Box<dyn Codec>) -> Result<()> {
+        let data_shard_num = codec.data_shard_count();
+        let shard_num = data_shard_num + codec.parity_shard_count();
+        let max_data_shard_num = self.netinfo.num_nodes() - 2 * self.netinfo.num_faulty();
+        if data_shard_num == 0
+            || data_shard_num > max_data_shard_num
+            || shard_num != self.netinfo.num_nodes()
+        {
+            return Err(Error::InvalidCodecParameters);
+        }
+        self.codec = codec.into();
+        Ok(())
+    }
+
+    /// Sets the size in bytes below which values are sent as a whole, in `FullValue` and
+    /// `FullEcho` messages, instead of being split into erasure-coded shards with Merkle proofs.
+    ///
+    /// For small values, this saves the cost of encoding and hashing, and the overhead of the
+    /// proofs. The `Echo` round is still needed: it guarantees that every correct node eventually
+    /// receives the value, even if the proposer is faulty. The default is 0, i.e. every value is
+    /// erasure-coded. All nodes must use the same threshold, since any `FullValue` or `FullEcho`
+    /// that is not below it is rejected.
+    pub fn set_small_value_threshold(&mut self, small_value_threshold: usize) {
+        self.small_value_threshold = small_value_threshold;
+    }
+
+    /// Gives up on the proposer: The instance terminates, and won't output a value if it hasn't
+    /// done so yet. All shards and hashes received so far are dropped, and all messages that
+    /// arrive later are ignored as obsolete.
+    ///
+    /// A faulty proposer can stay silent, in which case the instance would never terminate and
+    /// keep everything it received in memory. The caller should abandon the instance once it knows
+    /// that the value is not needed anymore, e.g. because a timeout expired, or because `Subset`
+    /// decided not to include the value. All nodes that abandon an instance before it outputs
+    /// behave as if it had output `None`.
+    pub fn abandon(&mut self) {
+        if self.abandoned {
+            return;
+        }
+        debug_event!(
+            "Broadcast abandoned",
+            node = self.netinfo.our_id(),
+            proposer = self.proposer_id,
+            decided = self.decided,
+        );
+        self.abandoned = true;
+        self.echos.clear();
+        self.echo_hashes.clear();
+        self.full_echos.clear();
+        self.readys.clear();
+        self.can_decodes.clear();
+        self.echo_requests.clear();
+        self.echos_requested.clear();
+        self.leaf_hashes.clear();
+    }
+
+    /// Returns `true` if the instance was abandoned.
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned
+    }
+
+    /// Abandons the instance if the result is a fatal error. The non-fatal errors are detected
+    /// before the state is changed, so the instance is never left partially updated.
+    fn abandon_on_error(&mut self, result: Result<Step<N>>) -> Result<Step<N>> {
+        if let Err(ref err) = result {
+            if err.is_fatal() {
+                self.abandon();
+            }
+        }
+        result
+    }
+
+    /// Returns `true` if we have multicast `Ready`, i.e. we know that every correct node will
+    /// eventually be able to decode the value.
+    pub fn has_sent_ready(&self) -> bool {
+        self.ready_sent
+    }
+
+    /// Returns the total size in bytes of the shards and values received in `Echo` and `FullEcho`
+    /// messages that the instance currently keeps in memory.
+    pub fn buffered_bytes(&self) -> usize {
+        let shard_bytes: usize = self.echos.values().map(|p| p.value().len()).sum();
+        let value_bytes: usize = self.full_echos.values().map(|&(_, ref v)| v.len()).sum();
+        shard_bytes + value_bytes
+    }
+
+    /// Sets the maximum size of a value in bytes. By default, the size is unlimited.
+    ///
+    /// `Value` and `Echo` messages whose shard is too long for a value of that size are rejected
+    /// and their sender is reported, so that a faulty proposer can't make us store arbitrarily
+    /// large shards. All nodes must use the same maximum.
+    pub fn set_max_value_size(&mut self, max_value_size: usize) {
+        self.max_value_size = max_value_size;
+    }
+
     /// Breaks the input value into shards of equal length and encodes them --
-    /// and some extra parity shards -- with a Reed-Solomon erasure coding
-    /// scheme. The returned value contains the shard assigned to this
+    /// and some extra parity shards -- with the erasure code, by default a
+    /// Reed-Solomon coding scheme. The returned value contains the shard assigned to this
     /// node. That shard doesn't need to be sent anywhere. It gets recorded in
     /// the broadcast instance.
     fn send_shards(&mut self, mut value: Vec<u8>) -> Result<(Proof<Vec<u8>>, Step<N>)> {
-        let data_shard_num = self.coding.data_shard_count();
-        let parity_shard_num = self.coding.parity_shard_count();
+        let data_shard_num = self.codec.data_shard_count();
+        let parity_shard_num = self.codec.parity_shard_count();
 
-        debug!(
-            "Data shards: {}, parity shards: {}",
-            self.data_shard_num, parity_shard_num
-        );
         // Insert the length of `v` so it can be decoded without the padding.
         let payload_len = value.len() as u32;
         value.splice(0..0, 0..4); // Insert four bytes at the beginning.
@@ -126,33 +413,40 @@ impl<N: NodeIdT> Broadcast<N> {
         // zeros.
         value.resize(shard_len * (data_shard_num + parity_shard_num), 0);
 
-        debug!("value_len {}, shard_len {}", value_len, shard_len);
-
-        // Divide the vector into chunks/shards.
-        let shards_iter = value.chunks_mut(shard_len);
-        // Convert the iterator over slices into a vector of slices.
-        let mut shards: Vec<&mut [u8]> = shards_iter.collect();
+        debug_event!(
+            "Encoding shards",
+            node = self.netinfo.our_id(),
+            data_shards = data_shard_num,
+            parity_shards = parity_shard_num,
+            value_len = value_len,
+            shard_len = shard_len,
+        );
 
-        debug!("Shards before encoding: {:?}", HexList(&shards));
+        // Divide the vector into chunks/shards, and drop the original, so that we don't keep a
+        // second copy of the value in memory.
+        let mut shards: Vec<Vec<u8>> = value.chunks(shard_len).map(<[u8]>::to_vec).collect();
+        drop(value);
 
         // Construct the parity chunks/shards
-        self.coding
-            .encode(&mut shards)
-            .expect("the size and number of shards is correct");
-
-        debug!("Shards: {:?}", HexList(&shards));
+        {
+            let mut shard_slices: Vec<&mut [u8]> =
+                shards.iter_mut().map(|shard| &mut shard[..]).collect();
+            self.codec
+                .encode(&mut shard_slices)
+                .expect("the size and number of shards is correct");
+        }
 
         // Create a Merkle tree from the shards.
-        let mtree = MerkleTree::from_vec(shards.into_iter().map(|shard| shard.to_vec()).collect());
+        let mtree = MerkleTree::from_vec(shards);
 
         // Default result in case of `proof` error.
         let mut result = Err(Error::ProofConstructionFailed);
         assert_eq!(self.netinfo.num_nodes(), mtree.values().len());
 
         let mut step = Step::default();
-        // Send each proof to a node.
-        for (index, id) in self.netinfo.all_ids().enumerate() {
-            let proof = mtree.proof(index).ok_or(Error::ProofConstructionFailed)?;
+        // Send each proof to a node. The proofs are created one at a time, and the shards are
+        // moved into them.
+        for (id, proof) in self.netinfo.all_ids().zip(mtree.into_proofs()) {
             if *id == *self.netinfo.our_id() {
                 // The proof is addressed to this node.
                 result = Ok(proof);
@@ -166,103 +460,303 @@ impl<N: NodeIdT> Broadcast<N> {
         result.map(|proof| (proof, step))
     }
 
-    /// Handles a received echo and verifies the proof it contains.
-    fn handle_value(&mut self, sender_id: &N, p: Proof<Vec<u8>>) -> Result<Step<N>> {
-        // If the sender is not the proposer or if this is not the first `Value`, ignore.
-        if *sender_id != self.proposer_id {
-            info!(
-                "Node {:?} received Value from {:?} instead of {:?}.",
-                self.netinfo.our_id(),
-                sender_id,
-                self.proposer_id
-            );
-            let fault_kind = FaultKind::ReceivedValueFromNonProposer;
-            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+    /// Checks a message from a validator before it is handled: Only the proposer sends a single
+    /// `Value` or `FullValue`, every validator sends a single `Echo`, `FullEcho` or `EchoHash`,
+    /// possibly followed by the matching `Echo`, a single `Ready`, a single `CanDecode` and a
+    /// single `EchoRequest`, proofs must be valid and values must not exceed the size limits.
+    ///
+    /// An `Echo` or `FullEcho` that is identical to one we already have is obsolete: It may have
+    /// been resent in response to our `EchoRequest`.
+    fn validate_message(&mut self, sender_id: &N, message: &Message) -> Validity<FaultKind> {
+        if self.abandoned {
+            return Validity::Obsolete;
         }
-        if self.echo_sent {
-            info!("Node {:?} received multiple Values.", self.netinfo.our_id());
-            // TODO: should receiving two Values from a node be considered
-            // a fault? If so, return a `Fault` here. For now, ignore.
-            return Ok(Step::default());
+        if !self.netinfo.is_node_validator(sender_id) {
+            return Validity::Faulty(FaultKind::UnknownSender);
         }
-
-        // If the proof is invalid, log the faulty node behavior and ignore.
-        if !self.validate_proof(&p, &self.netinfo.our_id()) {
-            return Ok(Fault::new(sender_id.clone(), FaultKind::InvalidProof).into());
+        let is_duplicate = match *message {
+            Message::Echo(ref p) => self.echos.get(sender_id) == Some(p),
+            Message::FullEcho(ref value) => self
+                .full_echos
+                .get(sender_id)
+                .map_or(false, |&(_, ref v)| v == value),
+            _ => false,
+        };
+        if is_duplicate {
+            return Validity::Obsolete;
         }
+        let fault_kind = match *message {
+            Message::Value(_) | Message::FullValue(_) if *sender_id != self.proposer_id => {
+                FaultKind::ReceivedValueFromNonProposer
+            }
+            // The proposer sends only a single `Value` to each node.
+            Message::Value(_) | Message::FullValue(_) if self.echo_sent => {
+                FaultKind::MultipleValues
+            }
+            Message::Echo(_) | Message::FullEcho(_) | Message::EchoHash(_)
+                if self.echos.contains_key(sender_id) || self.full_echos.contains_key(sender_id) =>
+            {
+                FaultKind::MultipleEchos
+            }
+            // After an `EchoHash`, the sender may only send the `Echo` with that root hash.
+            Message::FullEcho(_) | Message::EchoHash(_)
+                if self.echo_hashes.contains_key(sender_id) =>
+            {
+                FaultKind::MultipleEchos
+            }
+            Message::Echo(ref p)
+                if self
+                    .echo_hashes
+                    .get(sender_id)
+                    .map_or(false, |hash| hash != p.root_hash()) =>
+            {
+                FaultKind::MultipleEchos
+            }
+            Message::Ready(_) if self.readys.contains_key(sender_id) => FaultKind::MultipleReadys,
+            Message::CanDecode(_) if self.can_decodes.contains_key(sender_id) => {
+                FaultKind::MultipleCanDecodes
+            }
+            Message::EchoRequest(_) if self.echo_requests.contains_key(sender_id) => {
+                FaultKind::MultipleEchoRequests
+            }
+            // A correct node doesn't send or echo a shard that is too large.
+            Message::Value(ref p) | Message::Echo(ref p)
+                if p.value().len() > self.max_shard_len() =>
+            {
+                FaultKind::ShardTooLarge
+            }
+            Message::Value(ref p) if !self.validate_proof(p, self.netinfo.our_id()) => {
+                FaultKind::InvalidProof
+            }
+            Message::Echo(ref p) if !self.validate_proof(p, sender_id) => FaultKind::InvalidProof,
+            Message::FullValue(ref value) | Message::FullEcho(ref value)
+                if value.len() >= self.small_value_threshold =>
+            {
+                FaultKind::FullValueTooLarge
+            }
+            _ => return Validity::Valid,
+        };
+        Validity::Faulty(fault_kind)
+    }
 
-        // Otherwise multicast the proof in an `Echo` message, and handle it ourselves.
+    /// Handles a received `Value` message: multicasts the proof in an `Echo` message, and handles
+    /// it ourselves.
+    fn handle_value(&mut self, p: Proof<Vec<u8>>) -> Result<Step<N>> {
         self.send_echo(p)
     }
 
-    /// Handles a received `Echo` message.
-    fn handle_echo(&mut self, sender_id: &N, p: Proof<Vec<u8>>) -> Result<Step<N>> {
-        // If the sender has already sent `Echo`, ignore.
-        if self.echos.contains_key(sender_id) {
-            info!(
-                "Node {:?} received multiple Echos from {:?}.",
-                self.netinfo.our_id(),
-                sender_id,
-            );
-            return Ok(Step::default());
-        }
+    /// Handles a received `FullValue` message: multicasts the value in a `FullEcho` message, and
+    /// handles it ourselves.
+    fn handle_full_value(&mut self, value: Vec<u8>) -> Result<Step<N>> {
+        self.send_full_echo(value)
+    }
 
-        // If the proof is invalid, log the faulty-node behavior, and ignore.
-        if !self.validate_proof(&p, sender_id) {
-            return Ok(Fault::new(sender_id.clone(), FaultKind::InvalidProof).into());
-        }
+    /// Handles a received `FullEcho` message.
+    fn handle_full_echo(&mut self, sender_id: &N, value: Vec<u8>) -> Result<Step<N>> {
+        let hash = full_value_hash(&value);
+        self.full_echos.insert(sender_id.clone(), (hash, value));
+        self.handle_echo_weight(&hash)
+    }
 
+    /// Handles a received `Echo` message.
+    fn handle_echo(&mut self, sender_id: &N, p: Proof<Vec<u8>>) -> Result<Step<N>> {
         let hash = *p.root_hash();
 
         // Save the proof for reconstructing the tree later.
         self.echos.insert(sender_id.clone(), p);
 
-        if self.ready_sent || self.count_echos(&hash) < self.netinfo.num_correct() {
-            return self.compute_output(&hash);
+        let mut step = Step::default();
+        if !self.can_decode_sent && self.count_echos(&hash) >= self.codec.data_shard_count() {
+            step.extend(self.send_can_decode(&hash));
         }
+        step.extend(self.handle_echo_weight(&hash)?);
+        Ok(step)
+    }
 
-        // Upon receiving `N - f` `Echo`s with this root hash, multicast `Ready`.
-        self.send_ready(&hash)
+    /// Handles a received `EchoHash` message: It counts like an `Echo` towards the `Ready`
+    /// threshold, but doesn't contain a shard.
+    fn handle_echo_hash(&mut self, sender_id: &N, hash: &Digest) -> Result<Step<N>> {
+        self.echo_hashes.insert(sender_id.clone(), *hash);
+        let mut step = self.handle_echo_weight(hash)?;
+        step.extend(self.request_echos(hash));
+        Ok(step)
     }
 
-    /// Handles a received `Ready` message.
-    fn handle_ready(&mut self, sender_id: &N, hash: &Digest) -> Result<Step<N>> {
-        // If the sender has already sent a `Ready` before, ignore.
-        if self.readys.contains_key(sender_id) {
-            info!(
-                "Node {:?} received multiple Readys from {:?}.",
-                self.netinfo.our_id(),
-                sender_id
-            );
-            return Ok(Step::default());
+    /// Handles a received `CanDecode` message: The sender doesn't need our `Echo` anymore.
+    fn handle_can_decode(&mut self, sender_id: &N, hash: &Digest) -> Step<N> {
+        self.can_decodes.insert(sender_id.clone(), *hash);
+        Step::default()
+    }
+
+    /// Handles a received `EchoRequest` message: If we have already echoed the value with that
+    /// hash, we send our `Echo` or `FullEcho` to the sender again, since the first one may have
+    /// been lost. Otherwise we will include the sender when we echo it.
+    fn handle_echo_request(&mut self, sender_id: &N, hash: &Digest) -> Step<N> {
+        self.echo_requests.insert(sender_id.clone(), *hash);
+        let our_id = self.netinfo.our_id();
+        let msg = match (self.echos.get(our_id), self.full_echos.get(our_id)) {
+            (Some(p), _) if p.root_hash() == hash => Message::Echo(p.clone()),
+            (_, Some(&(ref h, ref value))) if h == hash => Message::FullEcho(value.clone()),
+            _ => return Step::default(),
+        };
+        Target::Node(sender_id.clone()).message(msg).into()
+    }
+
+    /// Upon receiving `N - f` echos of any kind with this hash, multicasts `Ready`. Otherwise
+    /// checks whether the conditions for output are met.
+    fn handle_echo_weight(&mut self, hash: &Digest) -> Result<Step<N>> {
+        if self.ready_sent || self.echo_weight(hash) < self.netinfo.correct_weight() {
+            return self.compute_output(hash);
         }
+        self.send_ready(hash)
+    }
 
+    /// Handles a received `Ready` message.
+    fn handle_ready(&mut self, sender_id: &N, hash: &Digest) -> Result<Step<N>> {
         self.readys.insert(sender_id.clone(), hash.to_vec());
 
         let mut step = Step::default();
         // Upon receiving f + 1 matching Ready(h) messages, if Ready
         // has not yet been sent, multicast Ready(h).
-        if self.count_readys(hash) == self.netinfo.num_faulty() + 1 && !self.ready_sent {
+        if self.ready_weight(hash) > self.netinfo.faulty_weight() && !self.ready_sent {
             // Enqueue a broadcast of a Ready message.
             step.extend(self.send_ready(hash)?);
         }
         step.extend(self.compute_output(hash)?);
+        step.extend(self.request_echos(hash));
         Ok(step)
     }
 
-    /// Sends an `Echo` message and handles it. Does nothing if we are only an observer.
+    /// Asks the nodes that have a shard of the value with this root hash, according to their
+    /// `Ready` or `EchoHash`, for their `Echo`, if we have received enough `Ready`s to output but
+    /// not enough shards to decode. Every node is asked at most once.
+    ///
+    /// Without message loss, the shards would arrive anyway, so this only helps if some of them
+    /// were lost.
+    fn request_echos(&mut self, hash: &Digest) -> Step<N> {
+        if self.decided
+            || !self.netinfo.is_validator()
+            || self.ready_weight(hash) <= 2 * self.netinfo.faulty_weight()
+            || self.count_echos(hash) >= self.codec.data_shard_count()
+        {
+            return Step::default();
+        }
+        let ready_ids = self
+            .readys
+            .iter()
+            .filter(|(_, h)| h.as_slice() == hash)
+            .map(|(id, _)| id);
+        let echo_hash_ids = self
+            .echo_hashes
+            .iter()
+            .filter(|(_, h)| *h == hash)
+            .map(|(id, _)| id);
+        let our_id = self.netinfo.our_id();
+        let ids: BTreeSet<N> = ready_ids
+            .chain(echo_hash_ids)
+            .filter(|id| *id != our_id && !self.echos.contains_key(*id))
+            .filter(|id| !self.echos_requested.contains(*id))
+            .cloned()
+            .collect();
+        if ids.is_empty() {
+            return Step::default();
+        }
+        self.echos_requested.extend(ids.iter().cloned());
+        Target::Nodes(ids).message(Message::EchoRequest(*hash)).into()
+    }
+
+    /// Sends an `Echo` message to the observers and the validators that need our shard, an
+    /// `EchoHash` to the other validators, and handles it. Does nothing if we are only an observer.
+    ///
+    /// Until we have sent `Ready`, only our `echo_successors` and the nodes that sent us an
+    /// `EchoRequest` need the shard. Afterwards, everyone who hasn't sent `CanDecode` does.
+    /// Observers can't tell us whether they need it, so they always receive the `Echo`.
     fn send_echo(&mut self, p: Proof<Vec<u8>>) -> Result<Step<N>> {
         self.echo_sent = true;
         if !self.netinfo.is_validator() {
             return Ok(Step::default());
         }
-        let echo_msg = Message::Echo(p.clone());
-        let mut step: Step<_> = Target::All.message(echo_msg).into();
+        let hash = *p.root_hash();
         let our_id = &self.netinfo.our_id().clone();
+        let successors = self.echo_successors();
+        let hash_ids: BTreeSet<N> = self
+            .netinfo
+            .all_ids()
+            .filter(|id| *id != our_id)
+            .filter(|id| {
+                let needs_shard = self.ready_sent
+                    || successors.contains(*id)
+                    || self.echo_requests.get(*id) == Some(&hash);
+                !needs_shard || self.can_decodes.get(*id) == Some(&hash)
+            }).cloned()
+            .collect();
+        let echo_msg = Target::AllExcept(hash_ids.clone()).message(Message::Echo(p.clone()));
+        let mut step: Step<_> = echo_msg.into();
+        if !hash_ids.is_empty() {
+            let msg = Target::Nodes(hash_ids).message(Message::EchoHash(hash));
+            step.messages.push_back(msg);
+        }
         step.extend(self.handle_echo(our_id, p)?);
         Ok(step)
     }
 
+    /// Sends our `Echo` to the validators that have only received our `EchoHash`, unless they told
+    /// us that they can decode already.
+    ///
+    /// This is called when we send `Ready`. If any correct node outputs, every correct node
+    /// eventually sends `Ready`, so every correct node eventually receives the shards of all the
+    /// correct nodes that echoed the value, even if the ones preceding it are faulty.
+    fn send_echo_remaining(&self) -> Step<N> {
+        let our_id = self.netinfo.our_id();
+        let p = match self.echos.get(our_id) {
+            Some(p) => p,
+            None => return Step::default(), // We haven't echoed yet.
+        };
+        let successors = self.echo_successors();
+        let ids: BTreeSet<N> = self
+            .netinfo
+            .all_ids()
+            .filter(|id| *id != our_id && !successors.contains(*id))
+            .filter(|id| self.can_decodes.get(*id) != Some(p.root_hash()))
+            // Nodes that requested our `Echo` have received it already.
+            .filter(|id| self.echo_requests.get(*id) != Some(p.root_hash()))
+            .cloned()
+            .collect();
+        if ids.is_empty() {
+            return Step::default();
+        }
+        Target::Nodes(ids).message(Message::Echo(p.clone())).into()
+    }
+
+    /// Tells the other nodes that we have enough shards to decode the value with this root hash.
+    /// Does nothing if we are only an observer.
+    fn send_can_decode(&mut self, hash: &Digest) -> Step<N> {
+        self.can_decode_sent = true;
+        if !self.netinfo.is_validator() {
+            return Step::default();
+        }
+        Target::All.message(Message::CanDecode(*hash)).into()
+    }
+
+    /// Sends the complete value to every node in a `FullValue` message, and handles it ourselves.
+    fn send_full_value(&mut self, value: Vec<u8>) -> Result<Step<N>> {
+        let mut step: Step<_> = Target::All.message(Message::FullValue(value.clone())).into();
+        step.extend(self.handle_full_value(value)?);
+        Ok(step)
+    }
+
+    /// Sends a `FullEcho` message and handles it. Does nothing if we are only an observer.
+    fn send_full_echo(&mut self, value: Vec<u8>) -> Result<Step<N>> {
+        self.echo_sent = true;
+        if !self.netinfo.is_validator() {
+            return Ok(Step::default());
+        }
+        let mut step: Step<_> = Target::All.message(Message::FullEcho(value.clone())).into();
+        let our_id = &self.netinfo.our_id().clone();
+        step.extend(self.handle_full_echo(our_id, value)?);
+        Ok(step)
+    }
+
     /// Sends a `Ready` message and handles it. Does nothing if we are only an observer.
     fn send_ready(&mut self, hash: &Digest) -> Result<Step<N>> {
         self.ready_sent = true;
@@ -271,6 +765,7 @@ impl<N: NodeIdT> Broadcast<N> {
         }
         let ready_msg = Message::Ready(*hash);
         let mut step: Step<_> = Target::All.message(ready_msg).into();
+        step.extend(self.send_echo_remaining());
         let our_id = &self.netinfo.our_id().clone();
         step.extend(self.handle_ready(our_id, hash)?);
         Ok(step)
@@ -279,10 +774,22 @@ impl<N: NodeIdT> Broadcast<N> {
     /// Checks whether the conditions for output are met for this hash, and if so, sets the output
     /// value.
     fn compute_output(&mut self, hash: &Digest) -> Result<Step<N>> {
-        if self.decided
-            || self.count_readys(hash) <= 2 * self.netinfo.num_faulty()
-            || self.count_echos(hash) < self.coding.data_shard_count()
-        {
+        if self.decided || self.ready_weight(hash) <= 2 * self.netinfo.faulty_weight() {
+            return Ok(Step::default());
+        }
+
+        // A value that was sent as a whole only needs a single `FullEcho` with a matching hash.
+        let full_value = self
+            .full_echos
+            .values()
+            .find(|&&(ref h, _)| h == hash)
+            .map(|&(_, ref value)| value.clone());
+        if let Some(value) = full_value {
+            self.decided = true;
+            return Ok(Step::default().with_output(value));
+        }
+
+        if self.count_echos(hash) < self.codec.data_shard_count() {
             return Ok(Step::default());
         }
 
@@ -299,9 +806,16 @@ impl<N: NodeIdT> Broadcast<N> {
                     }
                 })
             }).collect();
-        if let Some(value) =
-            decode_from_shards(&mut leaf_values, &self.coding, self.data_shard_num, hash)
-        {
+        // The shards we received with verified proofs don't need to be hashed again.
+        let known_hashes: Vec<Option<Digest>> = leaf_values
+            .iter()
+            .enumerate()
+            .map(|(index, leaf)| {
+                let key = (*hash, index);
+                leaf.as_ref().and_then(|_| self.leaf_hashes.get(&key).cloned())
+            }).collect();
+        let codec = &*self.codec;
+        if let Some(value) = decode_from_shards(&mut leaf_values, &known_hashes, codec, hash) {
             self.decided = true;
             Ok(Step::default().with_output(value))
         } else {
@@ -309,120 +823,123 @@ impl<N: NodeIdT> Broadcast<N> {
         }
     }
 
-    /// Returns `true` if the proof is valid and has the same index as the node ID. Otherwise
-    /// logs an info message.
-    fn validate_proof(&self, p: &Proof<Vec<u8>>, id: &N) -> bool {
-        if !p.validate(self.netinfo.num_nodes()) {
-            info!(
-                "Node {:?} received invalid proof: {:?}",
-                self.netinfo.our_id(),
-                HexProof(&p)
+    /// Returns `true` if the proof is valid and has the same index as the node ID, and keeps its
+    /// leaf hash in `validated_leaf_hash`. Otherwise logs an info message.
+    fn validate_proof(&mut self, p: &Proof<Vec<u8>>, id: &N) -> bool {
+        if self.netinfo.node_index(id) != Some(p.index()) {
+            info_event!(
+                "Proof for wrong position",
+                node = self.netinfo.our_id(),
+                proposer = self.proposer_id,
+                sender = id,
+                proof = HexProof(&p),
             );
-            false
-        } else if self.netinfo.node_index(id) != Some(p.index()) {
-            info!(
-                "Node {:?} received proof for wrong position: {:?}.",
-                self.netinfo.our_id(),
-                HexProof(&p)
-            );
-            false
-        } else {
-            true
+            return false;
+        }
+        match p.validated_leaf_hash(self.netinfo.num_nodes()) {
+            Some(leaf_hash) => {
+                let key = (*p.root_hash(), p.index());
+                self.validated_leaf_hash = Some((key, leaf_hash));
+                true
+            }
+            None => {
+                info_event!(
+                    "Invalid proof",
+                    node = self.netinfo.our_id(),
+                    proposer = self.proposer_id,
+                    sender = id,
+                    proof = HexProof(&p),
+                );
+                false
+            }
         }
     }
 
-    /// Returns the number of nodes that have sent us an `Echo` message with this hash.
+    /// Returns the maximum length of a shard of a value of at most `max_value_size` bytes,
+    /// including the four bytes encoding its length.
+    fn max_shard_len(&self) -> usize {
+        let data_shard_num = self.codec.data_shard_count();
+        let max_len = self.max_value_size.saturating_add(4);
+        max_len / data_shard_num + if max_len % data_shard_num > 0 { 1 } else { 0 }
+    }
+
+    /// Returns the number of nodes that have sent us an `Echo` or `FullEcho` message with this
+    /// hash.
     fn count_echos(&self, hash: &Digest) -> usize {
+        let full_echo_count = self
+            .full_echos
+            .values()
+            .filter(|&&(ref h, _)| h == hash)
+            .count();
         self.echos
             .values()
             .filter(|p| p.root_hash() == hash)
             .count()
+            + full_echo_count
     }
 
-    /// Returns the number of nodes that have sent us a `Ready` message with this hash.
-    fn count_readys(&self, hash: &Digest) -> usize {
-        self.readys
-            .values()
-            .filter(|h| h.as_slice() == hash)
-            .count()
-    }
-}
-
-/// A wrapper for `ReedSolomon` that doesn't panic if there are no parity shards.
-#[derive(Debug)]
-enum Coding {
-    /// A `ReedSolomon` instance with at least one parity shard.
-    ReedSolomon(Box<ReedSolomon>),
-    /// A no-op replacement that doesn't encode or decode anything.
-    Trivial(usize),
-}
-
-impl Coding {
-    /// Creates a new `Coding` instance with the given number of shards.
-    fn new(data_shard_num: usize, parity_shard_num: usize) -> Result<Self> {
-        Ok(if parity_shard_num > 0 {
-            let rs = ReedSolomon::new(data_shard_num, parity_shard_num)
-                .map_err(Error::CodingNewReedSolomon)?;
-            Coding::ReedSolomon(Box::new(rs))
-        } else {
-            Coding::Trivial(data_shard_num)
-        })
-    }
-
-    /// Returns the number of data shards.
-    fn data_shard_count(&self) -> usize {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs.data_shard_count(),
-            Coding::Trivial(dsc) => dsc,
-        }
-    }
-
-    /// Returns the number of parity shards.
-    fn parity_shard_count(&self) -> usize {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs.parity_shard_count(),
-            Coding::Trivial(_) => 0,
-        }
+    /// Returns the total weight of the nodes that have sent us an `Echo`, `EchoHash` or
+    /// `FullEcho` message with this hash.
+    fn echo_weight(&self, hash: &Digest) -> u64 {
+        let full_echo_ids = self
+            .full_echos
+            .iter()
+            .filter(|(_, (h, _))| h == hash)
+            .map(|(id, _)| id);
+        let echo_ids = self
+            .echos
+            .iter()
+            .filter(|(_, p)| p.root_hash() == hash)
+            .map(|(id, _)| id);
+        // Nodes that sent both an `EchoHash` and the matching `Echo` are only counted once.
+        let echo_hash_ids = self
+            .echo_hashes
+            .iter()
+            .filter(|(id, h)| *h == hash && !self.echos.contains_key(*id))
+            .map(|(id, _)| id);
+        self.netinfo.weight_of(echo_ids.chain(full_echo_ids).chain(echo_hash_ids))
     }
 
-    /// Constructs (and overwrites) the parity shards.
-    fn encode(&self, slices: &mut [&mut [u8]]) -> Result<()> {
-        match *self {
-            Coding::ReedSolomon(ref rs) => {
-                rs.encode(slices).map_err(Error::CodingEncodeReedSolomon)?
-            }
-            Coding::Trivial(_) => (),
-        }
-        Ok(())
+    /// Returns the validators that receive our `Echo` right away: the _N - 2 f - 1_ ones, or one
+    /// less than the number of data shards, that follow us in the order of the IDs, wrapping
+    /// around. Together with its own `Value`, every node thus receives enough shards to decode,
+    /// unless some of the nodes preceding it are faulty.
+    fn echo_successors(&self) -> BTreeSet<N> {
+        let num = self.codec.data_shard_count().saturating_sub(1);
+        let our_id = self.netinfo.our_id();
+        self.netinfo
+            .all_ids()
+            .skip_while(|id| *id != our_id)
+            .skip(1)
+            .chain(self.netinfo.all_ids())
+            .take_while(|id| *id != our_id)
+            .take(num)
+            .cloned()
+            .collect()
     }
 
-    /// If enough shards are present, reconstructs the missing ones.
-    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<()> {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs
-                .reconstruct_shards(shards)
-                .map_err(Error::CodingReconstructShardsReedSolomon)?,
-            Coding::Trivial(_) => {
-                if shards.iter().any(Option::is_none) {
-                    return Err(Error::CodingReconstructShardsTrivialReedSolomon(
-                        rse::Error::TooFewShardsPresent,
-                    ));
-                }
-            }
-        }
-        Ok(())
+    /// Returns the total weight of the nodes that have sent us a `Ready` message with this hash.
+    fn ready_weight(&self, hash: &Digest) -> u64 {
+        let ready_ids = self
+            .readys
+            .iter()
+            .filter(|(_, h)| h.as_slice() == hash)
+            .map(|(id, _)| id);
+        self.netinfo.weight_of(ready_ids)
     }
 }
 
+/// Reconstructs the value from the shards. The leaf hashes in `known_hashes` are used instead of
+/// hashing the corresponding shards again.
 fn decode_from_shards(
     leaf_values: &mut [Option<Box<[u8]>>],
-    coding: &Coding,
-    data_shard_num: usize,
+    known_hashes: &[Option<Digest>],
+    codec: &dyn Codec,
     root_hash: &Digest,
 ) -> Option<Vec<u8>> {
-    // Try to interpolate the Merkle tree using the Reed-Solomon erasure coding scheme.
-    if let Err(err) = coding.reconstruct_shards(leaf_values) {
-        error!("Shard reconstruction failed: {:?}", err); // Faulty proposer
+    // Try to interpolate the Merkle tree using the erasure coding scheme.
+    if let Err(err) = codec.reconstruct_shards(leaf_values) {
+        error_event!("Shard reconstruction failed", error = err); // Faulty proposer
         return None;
     }
 
@@ -434,17 +951,16 @@ fn decode_from_shards(
         .filter_map(|l| l.as_ref().map(|v| v.to_vec()))
         .collect();
 
-    debug!("Reconstructed shards: {:?}", HexList(&shards));
-
-    // Construct the Merkle tree.
-    let mtree = MerkleTree::from_vec(shards);
+    // Construct the Merkle tree. The received shards were not modified by the reconstruction, so
+    // their cached hashes are still valid.
+    let mtree = MerkleTree::from_vec_with_hashes(shards, known_hashes);
     // If the root hash of the reconstructed tree does not match the one
     // received with proofs then abort.
     if mtree.root_hash() != root_hash {
         None // The proposer is faulty.
     } else {
         // Reconstruct the value from the data shards.
-        glue_shards(mtree, data_shard_num)
+        glue_shards(mtree, codec.data_shard_count())
     }
 }
 
@@ -459,6 +975,15 @@ fn glue_shards(m: MerkleTree<Vec<u8>>, n: usize) -> Option<Vec<u8>> {
         _ => return None, // The proposing node is faulty: no payload size.
     };
     let payload: Vec<u8> = bytes.take(payload_len).collect();
-    debug!("Glued data shards {:?}", HexFmt(&payload));
+    debug_event!("Glued data shards", payload_len = payload.len());
     Some(payload)
 }
+
+/// Returns the hash identifying a value sent in `FullValue` and `FullEcho` messages. The value is
+/// prefixed, to separate these hashes from the Merkle tree ones.
+fn full_value_hash(value: &[u8]) -> Digest {
+    let mut bytes = Vec::with_capacity(value.len() + 1);
+    bytes.push(0xff);
+    bytes.extend_from_slice(value);
+    sha3_256(&bytes)
+}
@@ -0,0 +1,166 @@
+use std::fmt::Debug;
+use std::result;
+use std::sync::Arc;
+
+use reed_solomon_erasure as rse;
+use reed_solomon_erasure::ReedSolomon;
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Error, Result};
+
+/// An erasure code used by `Broadcast` to split a value into data and parity shards.
+///
+/// The proposer encodes the value into `data_shard_count() + parity_shard_count()` shards, one per
+/// validator, and any `data_shard_count()` of them must suffice to reconstruct the others.
+pub trait Codec: Debug + Send + Sync {
+    /// Returns the number of data shards.
+    fn data_shard_count(&self) -> usize;
+
+    /// Returns the number of parity shards.
+    fn parity_shard_count(&self) -> usize;
+
+    /// Constructs (and overwrites) the parity shards from the data shards, which come first.
+    fn encode(&self, slices: &mut [&mut [u8]]) -> Result<()>;
+
+    /// If enough shards are present, reconstructs the missing ones.
+    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<()>;
+}
+
+/// A Reed-Solomon erasure code. This is the default `Codec`. Unlike `ReedSolomon` itself, it
+/// doesn't panic if there are no parity shards.
+#[derive(Debug)]
+pub enum ReedSolomonCodec {
+    /// A `ReedSolomon` instance with at least one parity shard.
+    ReedSolomon(Box<ReedSolomon>),
+    /// A no-op replacement that doesn't encode or decode anything.
+    Trivial(usize),
+}
+
+impl ReedSolomonCodec {
+    /// Creates a new `ReedSolomonCodec` instance with the given number of shards.
+    pub fn new(data_shard_num: usize, parity_shard_num: usize) -> Result<Self> {
+        Ok(if parity_shard_num > 0 {
+            let rs = ReedSolomon::new(data_shard_num, parity_shard_num)
+                .map_err(Error::CodingNewReedSolomon)?;
+            ReedSolomonCodec::ReedSolomon(Box::new(rs))
+        } else {
+            ReedSolomonCodec::Trivial(data_shard_num)
+        })
+    }
+}
+
+impl Codec for ReedSolomonCodec {
+    fn data_shard_count(&self) -> usize {
+        match *self {
+            ReedSolomonCodec::ReedSolomon(ref rs) => rs.data_shard_count(),
+            ReedSolomonCodec::Trivial(dsc) => dsc,
+        }
+    }
+
+    fn parity_shard_count(&self) -> usize {
+        match *self {
+            ReedSolomonCodec::ReedSolomon(ref rs) => rs.parity_shard_count(),
+            ReedSolomonCodec::Trivial(_) => 0,
+        }
+    }
+
+    fn encode(&self, slices: &mut [&mut [u8]]) -> Result<()> {
+        match *self {
+            ReedSolomonCodec::ReedSolomon(ref rs) => {
+                rs.encode(slices).map_err(Error::CodingEncodeReedSolomon)?
+            }
+            ReedSolomonCodec::Trivial(_) => (),
+        }
+        Ok(())
+    }
+
+    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<()> {
+        match *self {
+            ReedSolomonCodec::ReedSolomon(ref rs) => rs
+                .reconstruct_shards(shards)
+                .map_err(Error::CodingReconstructShardsReedSolomon)?,
+            ReedSolomonCodec::Trivial(_) => {
+                if shards.iter().any(Option::is_none) {
+                    return Err(Error::CodingReconstructShardsTrivialReedSolomon(
+                        rse::Error::TooFewShardsPresent,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A code with a single data shard, of which every parity shard is a copy.
+///
+/// Every node receives the complete value, so the total traffic grows with the square of the
+/// number of nodes, but encoding and decoding are trivial. This is only worthwhile for tiny
+/// payloads, where the Reed-Solomon overhead dominates.
+#[derive(Debug)]
+pub struct ReplicationCodec {
+    /// The total number of shards.
+    shard_num: usize,
+}
+
+impl ReplicationCodec {
+    /// Creates a new `ReplicationCodec` producing the given total number of shards.
+    pub fn new(shard_num: usize) -> Self {
+        ReplicationCodec { shard_num }
+    }
+}
+
+impl Codec for ReplicationCodec {
+    fn data_shard_count(&self) -> usize {
+        1
+    }
+
+    fn parity_shard_count(&self) -> usize {
+        self.shard_num.saturating_sub(1)
+    }
+
+    fn encode(&self, slices: &mut [&mut [u8]]) -> Result<()> {
+        if let Some((data, parity)) = slices.split_first_mut() {
+            for slice in parity {
+                slice.copy_from_slice(data);
+            }
+        }
+        Ok(())
+    }
+
+    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<()> {
+        let data = match shards.iter().filter_map(Option::as_ref).next() {
+            Some(shard) => shard.clone(),
+            None => {
+                return Err(Error::CodingReconstructShardsTrivialReedSolomon(
+                    rse::Error::TooFewShardsPresent,
+                ))
+            }
+        };
+        for shard in shards.iter_mut().filter(|shard| shard.is_none()) {
+            *shard = Some(data.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Serializes a codec as its numbers of data and parity shards.
+pub(super) fn serialize_codec<S>(
+    codec: &Arc<dyn Codec>,
+    serializer: S,
+) -> result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    (codec.data_shard_count(), codec.parity_shard_count()).serialize(serializer)
+}
+
+/// Deserializes a codec as a `ReedSolomonCodec` with the serialized numbers of shards.
+pub(super) fn deserialize_codec<'de, D>(deserializer: D) -> result::Result<Arc<dyn Codec>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let (data_shard_num, parity_shard_num) = <(usize, usize)>::deserialize(deserializer)?;
+    let codec = ReedSolomonCodec::new(data_shard_num, parity_shard_num).map_err(D::Error::custom)?;
+    Ok(Arc::new(codec))
+}
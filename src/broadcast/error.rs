@@ -14,6 +14,10 @@ pub enum Error {
         _0
     )]
     CodingReconstructShardsTrivialReedSolomon(#[cause] rse::Error),
+    #[fail(display = "Invalid codec parameters")]
+    InvalidCodecParameters,
+    #[fail(display = "Input value too large")]
+    InputTooLarge,
     #[fail(display = "Instance cannot propose")]
     InstanceCannotPropose,
     #[fail(display = "Not implemented")]
@@ -24,9 +28,55 @@ pub enum Error {
     RootHashMismatch,
     #[fail(display = "Threading")]
     Threading,
-    #[fail(display = "Unknown sender")]
-    UnknownSender,
 }
 
 /// A broadcast result.
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// Returns `true` if the instance cannot continue after this error. Rejected inputs and
+    /// settings leave it unchanged.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::InvalidCodecParameters | Error::InputTooLarge | Error::InstanceCannotPropose => {
+                false
+            }
+            Error::CodingNewReedSolomon(_)
+            | Error::CodingEncodeReedSolomon(_)
+            | Error::CodingReconstructShardsReedSolomon(_)
+            | Error::CodingReconstructShardsTrivialReedSolomon(_)
+            | Error::NotImplemented
+            | Error::ProofConstructionFailed
+            | Error::RootHashMismatch
+            | Error::Threading => true,
+        }
+    }
+}
+
+/// Represents each reason why a broadcast message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message was received from a node that is not a validator.
+    UnknownSender,
+    /// `Broadcast` received a `Value` from a node other than the proposer.
+    ReceivedValueFromNonProposer,
+    /// `Broadcast` recevied an Echo message containing an invalid proof.
+    InvalidProof,
+    /// `Broadcast` received multiple `Value` messages from the proposer.
+    MultipleValues,
+    /// `Broadcast` received multiple `Echo` messages from the same sender, or an `Echo` that
+    /// doesn't match the sender's `EchoHash`.
+    MultipleEchos,
+    /// `Broadcast` received multiple `Ready` messages from the same sender.
+    MultipleReadys,
+    /// `Broadcast` received multiple `CanDecode` messages from the same sender.
+    MultipleCanDecodes,
+    /// `Broadcast` received multiple `EchoRequest` messages from the same sender.
+    MultipleEchoRequests,
+    /// `Broadcast` received a `FullValue` or `FullEcho` message with a value that is not below the
+    /// small value threshold.
+    FullValueTooLarge,
+    /// `Broadcast` received a `Value` or `Echo` message with a shard that implies a value larger
+    /// than the maximum.
+    ShardTooLarge,
+}
@@ -17,8 +17,21 @@ pub struct MerkleTree<T> {
 impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
     /// Creates a new Merkle tree with the given values.
     pub fn from_vec(values: Vec<T>) -> Self {
+        Self::from_vec_with_hashes(values, &[])
+    }
+
+    /// Creates a new Merkle tree with the given values. The leaf hashes that are already known,
+    /// e.g. from verified proofs, are taken from `known_hashes` instead of being recomputed: If
+    /// `known_hashes[i]` is `Some`, it must be the hash of `values[i]`.
+    pub fn from_vec_with_hashes(values: Vec<T>, known_hashes: &[Option<Digest>]) -> Self {
         let mut levels = Vec::new();
-        let mut cur_lvl: Vec<Digest> = values.iter().map(hash).collect();
+        let mut cur_lvl: Vec<Digest> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match known_hashes.get(i) {
+                Some(&Some(digest)) => digest,
+                _ => hash(value),
+            }).collect();
         while cur_lvl.len() > 1 {
             let next_lvl = cur_lvl.chunks(2).map(hash_chunk).collect();
             levels.push(mem::replace(&mut cur_lvl, next_lvl));
@@ -31,24 +44,25 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
         }
     }
 
-    /// Returns the proof for entry `index`, if that is a valid index.
-    pub fn proof(&self, index: usize) -> Option<Proof<T>> {
-        let value = self.values.get(index)?.clone();
-        let mut lvl_i = index;
-        let mut digests = Vec::new();
-        for level in &self.levels {
-            // Insert the sibling hash if there is one.
-            if let Some(digest) = level.get(lvl_i ^ 1) {
-                digests.push(*digest);
-            }
-            lvl_i /= 2;
-        }
-        Some(Proof {
-            index,
-            digests,
-            value,
-            root_hash: self.root_hash,
-        })
+    /// Consumes the tree and returns an iterator over the proofs for all entries, in order.
+    ///
+    /// Each proof is only created when it is needed, and the values are moved into the proofs
+    /// instead of being cloned, so this never holds more than one copy of the values in memory.
+    pub fn into_proofs(self) -> impl Iterator<Item = Proof<T>> {
+        let MerkleTree {
+            levels,
+            values,
+            root_hash,
+        } = self;
+        values
+            .into_iter()
+            .enumerate()
+            .map(move |(index, value)| Proof {
+                index,
+                digests: sibling_digests(&levels, index),
+                value,
+                root_hash,
+            })
     }
 
     /// Returns the root hash of the tree.
@@ -77,17 +91,18 @@ pub struct Proof<T> {
 }
 
 impl<T: AsRef<[u8]>> Proof<T> {
-    /// Returns `true` if the digests in this proof constitute a valid branch in a Merkle tree with
-    /// the root hash.
-    pub fn validate(&self, n: usize) -> bool {
-        let mut digest = hash(&self.value);
+    /// Returns the hash of the leaf value if the digests in this proof constitute a valid branch
+    /// in a Merkle tree with the root hash, or `None` otherwise.
+    pub fn validated_leaf_hash(&self, n: usize) -> Option<Digest> {
+        let leaf_hash = hash(&self.value);
+        let mut digest = leaf_hash;
         let mut lvl_i = self.index;
         let mut lvl_n = n;
         let mut digest_itr = self.digests.iter();
         while lvl_n > 1 {
             if lvl_i ^ 1 < lvl_n {
                 digest = match digest_itr.next() {
-                    None => return false, // Not enough levels in the proof.
+                    None => return None, // Not enough levels in the proof.
                     Some(sibling) if lvl_i & 1 == 1 => hash_pair(&sibling, &digest),
                     Some(sibling) => hash_pair(&digest, &sibling),
                 };
@@ -95,10 +110,10 @@ impl<T: AsRef<[u8]>> Proof<T> {
             lvl_i /= 2; // Our index on the next level.
             lvl_n = (lvl_n + 1) / 2; // The next level's size.
         }
-        if digest_itr.next().is_some() {
-            return false; // Too many levels in the proof.
+        if digest_itr.next().is_some() || digest != self.root_hash {
+            return None; // Too many levels in the proof, or the wrong root.
         }
-        digest == self.root_hash
+        Some(leaf_hash)
     }
 
     /// Returns the index of this proof's value in the tree.
@@ -122,6 +137,20 @@ impl<T: AsRef<[u8]>> Proof<T> {
     }
 }
 
+/// Returns the sibling digests on the path from leaf `index` to the root, starting at the leaf.
+fn sibling_digests(levels: &[Vec<Digest>], index: usize) -> Vec<Digest> {
+    let mut lvl_i = index;
+    let mut digests = Vec::new();
+    for level in levels {
+        // Insert the sibling hash if there is one.
+        if let Some(digest) = level.get(lvl_i ^ 1) {
+            digests.push(*digest);
+        }
+        lvl_i /= 2;
+    }
+    digests
+}
+
 /// Takes a chunk of one or two digests. In the former case, returns the digest itself, in the
 /// latter, it returns the hash of the two digests.
 fn hash_chunk(chunk: &[Digest]) -> Digest {
@@ -149,13 +178,25 @@ mod tests {
 
     #[test]
     fn test_merkle() {
-        for &n in &[4, 7, 8, 9, 17] {
-            let tree = MerkleTree::from_vec((0..n).map(|i| vec![i as u8]).collect());
-            for i in 0..n {
-                let proof = tree.proof(i).expect("couldn't get proof");
-                assert!(proof.validate(n));
+        for &n in &[1, 4, 7, 8, 9, 17] {
+            let values: Vec<_> = (0..n).map(|i| vec![i as u8]).collect();
+            let tree = MerkleTree::from_vec(values.clone());
+            let root_hash = *tree.root_hash();
+            let proofs: Vec<_> = tree.into_proofs().collect();
+            assert_eq!(n, proofs.len());
+            for (i, proof) in proofs.iter().enumerate() {
+                assert_eq!(i, proof.index());
+                assert_eq!(root_hash, *proof.root_hash());
             }
-            assert!(tree.proof(n).is_none());
+
+            // Known leaf hashes are used instead of recomputing them.
+            let known: Vec<_> = proofs.iter().map(|p| p.validated_leaf_hash(n)).collect();
+            assert!(known.iter().all(Option::is_some));
+            let rebuilt = MerkleTree::from_vec_with_hashes(values, &known);
+            assert_eq!(root_hash, *rebuilt.root_hash());
+
+            // A proof for a different number of leaves is invalid.
+            assert!(n == 1 || proofs[0].validated_leaf_hash(n * 2).is_none());
         }
     }
 }
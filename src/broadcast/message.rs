@@ -1,24 +1,51 @@
 use std::fmt::{self, Debug};
 
 use hex_fmt::HexFmt;
+#[cfg(feature = "testing")]
 use rand;
 
-use super::merkle::{Digest, MerkleTree, Proof};
+#[cfg(feature = "testing")]
+use super::merkle::MerkleTree;
+use super::merkle::{Digest, Proof};
 
-/// The three kinds of message sent during the reliable broadcast stage of the
+/// The kinds of message sent during the reliable broadcast stage of the
 /// consensus algorithm.
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub enum Message {
     Value(Proof<Vec<u8>>),
     Echo(Proof<Vec<u8>>),
     Ready(Digest),
+    /// The root hash of an `Echo`, sent instead of the `Echo` to the nodes that don't need our
+    /// shard to decode.
+    EchoHash(Digest),
+    /// Tells the other nodes that the sender has enough shards to decode the value with this root
+    /// hash, so that it doesn't need their `Echo` anymore.
+    CanDecode(Digest),
+    /// Asks the recipient for its `Echo` with this root hash. Sent by a node that has received
+    /// enough `Ready`s to output, but not enough shards to decode, e.g. because messages were lost.
+    EchoRequest(Digest),
+    /// The complete value, sent by the proposer instead of `Value` if it is small.
+    FullValue(Vec<u8>),
+    /// The complete value, sent instead of `Echo` if it is small.
+    FullEcho(Vec<u8>),
 }
 
-// A random generation impl is provided for test cases. Unfortunately `#[cfg(test)]` does not work
-// for integration tests.
+// A random generation impl is provided for test cases, with the `testing` feature. Unfortunately
+// `#[cfg(test)]` does not work for integration tests.
+#[cfg(feature = "testing")]
 impl rand::Rand for Message {
     fn rand<R: rand::Rng>(rng: &mut R) -> Self {
-        let message_type = *rng.choose(&["value", "echo", "ready"]).unwrap();
+        let message_type = *rng
+            .choose(&[
+                "value",
+                "echo",
+                "ready",
+                "echo_hash",
+                "can_decode",
+                "echo_request",
+                "full_value",
+                "full_echo",
+            ]).unwrap();
 
         // Create a random buffer for our proof.
         let mut buffer: [u8; 32] = [0; 32];
@@ -26,12 +53,17 @@ impl rand::Rand for Message {
 
         // Generate a dummy proof to fill broadcast messages with.
         let tree = MerkleTree::from_vec(vec![buffer.to_vec()]);
-        let proof = tree.proof(0).unwrap();
+        let proof = tree.into_proofs().next().unwrap();
 
         match message_type {
             "value" => Message::Value(proof),
             "echo" => Message::Echo(proof),
             "ready" => Message::Ready([b'r'; 32]),
+            "echo_hash" => Message::EchoHash([b'e'; 32]),
+            "can_decode" => Message::CanDecode([b'c'; 32]),
+            "echo_request" => Message::EchoRequest([b'q'; 32]),
+            "full_value" => Message::FullValue(buffer.to_vec()),
+            "full_echo" => Message::FullEcho(buffer.to_vec()),
             _ => unreachable!(),
         }
     }
@@ -43,6 +75,11 @@ impl Debug for Message {
             Message::Value(ref v) => f.debug_tuple("Value").field(&HexProof(v)).finish(),
             Message::Echo(ref v) => f.debug_tuple("Echo").field(&HexProof(v)).finish(),
             Message::Ready(ref b) => f.debug_tuple("Ready").field(&HexFmt(b)).finish(),
+            Message::EchoHash(ref b) => f.debug_tuple("EchoHash").field(&HexFmt(b)).finish(),
+            Message::CanDecode(ref b) => f.debug_tuple("CanDecode").field(&HexFmt(b)).finish(),
+            Message::EchoRequest(ref b) => f.debug_tuple("EchoRequest").field(&HexFmt(b)).finish(),
+            Message::FullValue(ref v) => f.debug_tuple("FullValue").field(&HexFmt(v)).finish(),
+            Message::FullEcho(ref v) => f.debug_tuple("FullEcho").field(&HexFmt(v)).finish(),
         }
     }
 }
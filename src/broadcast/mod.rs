@@ -41,6 +41,34 @@
 //! * So a node with _2 f + 1_ `Ready`s and _N - 2 f_ `Echos` will decode and _output_ the value,
 //! knowing that every other correct node will eventually do the same.
 //!
+//! To save bandwidth, a node doesn't actually send its shard to everyone: Together with its own
+//! `Value`, the `Echo`s from the _N - 2 f - 1_ nodes preceding it in the order of the IDs suffice
+//! to decode. So every node sends its `Echo` only to the _N - 2 f - 1_ nodes following it, and to
+//! the observers, and an `EchoHash(h)` without the shard to everyone else. That still counts
+//! towards the _N - f_ echos needed for `Ready`. A node that has enough shards multicasts
+//! `CanDecode(h)`. As a fallback, in case some of a node's predecessors are faulty, every node that
+//! sends `Ready` also sends its `Echo` to everyone who has only received the `EchoHash` so far and
+//! hasn't sent `CanDecode`. If any correct node outputs, all of them eventually send `Ready`, so
+//! every correct node eventually receives enough shards.
+//!
+//! That relies on every message eventually being delivered. If messages can get lost, a node may
+//! end up with _2 f + 1_ `Ready`s but too few shards. It then sends an `EchoRequest(h)` to every
+//! node that has sent it `Ready(h)` or `EchoHash(h)`, but not its `Echo`, and they resend it.
+//!
+//! The erasure code is configurable: `Broadcast::set_data_shard_num` trades bandwidth for
+//! redundancy by using fewer than _N - 2 f_ data shards, and `Broadcast::set_codec` replaces the
+//! Reed-Solomon code with any other `Codec`, e.g. a `ReplicationCodec` for tiny payloads.
+//!
+//! Values below the threshold set with `Broadcast::set_small_value_threshold` skip the coding and
+//! the Merkle proofs entirely: The proposer sends the complete value to everyone in a `FullValue`
+//! message, and the nodes echo it in a `FullEcho`. The `Ready` messages then contain the value's
+//! hash, and a single matching `FullEcho` suffices to output it.
+//!
+//! If the proposer is faulty, it may never send its value, and the instance would wait forever.
+//! `Broadcast::abandon` lets the caller give up on it, e.g. after a timeout: The instance then
+//! terminates without output and drops everything it received. `Subset` does that automatically
+//! for every proposer whose value it has decided not to include.
+//!
 //! ## Example
 //!
 //! In this example, we manually pass messages between instantiated nodes to simulate a network. The
@@ -133,6 +161,12 @@
 //!                     on_step(*id, step, &mut messages, &mut finished_nodes);
 //!                 }
 //!             }
+//!             Target::AllExcept(exclude) => {
+//!                 for (id, node) in nodes.iter_mut().filter(|&(id, _)| !exclude.contains(id)) {
+//!                     let step = node.handle_message(&source, message.clone())?;
+//!                     on_step(*id, step, &mut messages, &mut finished_nodes);
+//!                 }
+//!             }
 //!             Target::Node(id) => {
 //!                 let step = {
 //!                     let node = nodes.get_mut(&id).unwrap();
@@ -140,6 +174,16 @@
 //!                 };
 //!                 on_step(id, step, &mut messages, &mut finished_nodes);
 //!             }
+//!             Target::Nodes(ids) => {
+//!                 for id in ids {
+//!                     let step = {
+//!                         let node = nodes.get_mut(&id).unwrap();
+//!                         node.handle_message(&source, message.clone())?
+//!                     };
+//!                     on_step(id, step, &mut messages, &mut finished_nodes);
+//!                 }
+//!             }
+//!             Target::Observers => (), // All our nodes are validators.
 //!         };
 //!     }
 //!     // Every node should output exactly once. Here we check the second half of this statement,
@@ -150,10 +194,15 @@
 //! ```
 
 mod broadcast;
+mod codec;
 mod error;
 pub(crate) mod merkle;
 mod message;
 
-pub use self::broadcast::{Broadcast, Step};
-pub use self::error::{Error, Result};
+pub use self::broadcast::{Broadcast, BroadcastSnapshot, Step};
+pub use self::codec::{Codec, ReedSolomonCodec, ReplicationCodec};
+pub use self::error::{Error, FaultKind, Result};
 pub use self::message::Message;
+
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::broadcast";
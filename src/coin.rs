@@ -20,12 +20,34 @@
 //! * On input, a node signs the nonce and sends its signature share to everyone else.
 //! * When a node has received _2 f + 1_ shares, it computes the main signature and outputs the XOR
 //! of its bits.
+//!
+//! The output is a single bit, but the combined signature contains much more randomness: Once the
+//! coin has terminated, `Coin::signature_bytes` returns the serialized signature, and
+//! `Coin::random_value` a uniformly distributed 32-byte value derived from it, e.g. for leader
+//! election. All correct nodes obtain the same bytes.
+//!
+//! Verifying the individual signature shares is expensive. With deferred verification, shares are
+//! only verified if the combined signature turns out to be invalid: Then the invalid shares are
+//! discarded and their senders reported as faulty. These pending shares are verified in a single
+//! batch by the configured `CryptoOffload`.
+//!
+//! A `CoinCache` can be shared by many coin instances to store the combined signatures by nonce.
+//! A coin whose signature is already in the cache, e.g. because it was restored from a snapshot
+//! after its original instance had terminated, outputs it right away on input, without signing
+//! and sending a share again.
 
-use std::collections::BTreeMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
 
+use bincode;
 use crypto::{self, Signature, SignatureShare};
-use fault_log::{Fault, FaultKind};
+use crypto_offload::{CryptoOffload, InlineOffload, Verification};
+use environment::{self, Environment, Restore};
+use tiny_keccak::sha3_256;
+use fault_log::Fault;
+use metrics::{self, Metrics, NoMetrics};
+use validation::Validity;
 use {DistAlgorithm, NetworkInfo, NodeIdT, Target};
 
 /// A coin error.
@@ -33,8 +55,6 @@ use {DistAlgorithm, NetworkInfo, NodeIdT, Target};
 pub enum Error {
     #[fail(display = "CombineAndVerifySigCrypto error: {}", _0)]
     CombineAndVerifySigCrypto(crypto::error::Error),
-    #[fail(display = "Unknown sender")]
-    UnknownSender,
     #[fail(display = "Signature verification failed")]
     VerificationFailed,
 }
@@ -42,7 +62,31 @@ pub enum Error {
 /// A coin result.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Rand)]
+impl Error {
+    /// Returns `true` if the coin cannot produce a value anymore. Currently, all errors are fatal.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::CombineAndVerifySigCrypto(_) | Error::VerificationFailed => true,
+        }
+    }
+}
+
+/// Represents each reason why a coin message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message was received from a node that is not a validator.
+    UnknownSender,
+    /// `Coin` received a signature share from an unverified sender.
+    UnverifiedSignatureShareSender,
+    /// `Coin` received multiple signature shares from the same sender.
+    MultipleSignatureShares,
+}
+
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::coin";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "testing", derive(Rand))]
 pub struct CoinMessage(SignatureShare);
 
 impl CoinMessage {
@@ -58,21 +102,202 @@ impl CoinMessage {
 /// A coin algorithm instance. On input, broadcasts our threshold signature share. Upon
 /// receiving at least `num_faulty + 1` shares, attempts to combine them into a signature. If that
 /// signature is valid, the instance outputs it and terminates; otherwise the instance aborts.
-#[derive(Debug)]
+///
+/// The number of shares needed is determined by the threshold of the public key set. With
+/// weighted validators, this is the largest number of lightest validators whose total weight is
+/// at most the faulty weight.
+#[derive(Clone, Debug)]
 pub struct Coin<N, T> {
     netinfo: Arc<NetworkInfo<N>>,
     /// The name of this coin. It is required to be unique for each coin round.
     nonce: T,
     /// All received threshold signature shares.
     received_shares: BTreeMap<N, SignatureShare>,
+    /// Whether to verify the shares only if the combined signature is invalid.
+    deferred_verification: bool,
+    /// The senders of the received shares that have not been verified yet.
+    unverified: BTreeSet<N>,
+    /// The senders whose shares turned out to be invalid and were discarded.
+    rejected: BTreeSet<N>,
     /// Whether we provided input to the coin.
     had_input: bool,
+    /// The combined threshold signature, once it is known.
+    signature: Option<Signature>,
     /// Termination flag.
     terminated: bool,
+    /// The executor for batches of share verifications.
+    offload: Arc<dyn CryptoOffload>,
+    /// The shared cache of combined signatures, if any.
+    cache: Option<Arc<CoinCache>>,
+    /// Receiver of the durations of signing, verifying and combining shares.
+    metrics: Arc<dyn Metrics>,
+}
+
+/// A serializable snapshot of a `Coin` instance, without its environment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(
+    deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>, T: ::serde::Deserialize<'de>"
+))]
+pub struct CoinSnapshot<N, T> {
+    nonce: T,
+    received_shares: BTreeMap<N, SignatureShare>,
+    deferred_verification: bool,
+    unverified: BTreeSet<N>,
+    rejected: BTreeSet<N>,
+    had_input: bool,
+    signature: Option<Signature>,
+    terminated: bool,
 }
 
 pub type Step<N, T> = ::Step<Coin<N, T>>;
 
+/// A cache of combined coin signatures by nonce, that can be shared by any number of `Coin`
+/// instances, also across threads.
+///
+/// A nonce determines its signature only together with the public key set, so a cache must only
+/// be shared by coins with the same public key set. Entries are never removed automatically: Use
+/// `CoinCache::clear` once the cached coins are not needed anymore.
+#[derive(Debug, Default)]
+pub struct CoinCache {
+    signatures: Mutex<BTreeMap<Vec<u8>, Signature>>,
+}
+
+impl CoinCache {
+    /// Creates a new empty cache.
+    pub fn new() -> Self {
+        CoinCache::default()
+    }
+
+    /// Returns the combined signature for the given nonce, if it is in the cache.
+    pub fn get(&self, nonce: &[u8]) -> Option<Signature> {
+        let signatures = self.signatures.lock().expect("coin cache lock poisoned");
+        signatures.get(nonce).cloned()
+    }
+
+    /// Stores the combined signature for the given nonce.
+    pub fn insert(&self, nonce: &[u8], sig: Signature) {
+        let mut signatures = self.signatures.lock().expect("coin cache lock poisoned");
+        signatures.insert(nonce.to_vec(), sig);
+    }
+
+    /// Returns the number of cached signatures.
+    pub fn len(&self) -> usize {
+        self.signatures.lock().expect("coin cache lock poisoned").len()
+    }
+
+    /// Returns `true` if no signatures are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached signatures.
+    pub fn clear(&self) {
+        self.signatures.lock().expect("coin cache lock poisoned").clear();
+    }
+}
+
+/// A source of pseudorandom coin values, e.g. for `BinaryAgreement`. Each instance produces a
+/// single value for the given nonce. It outputs it after receiving its input, and possibly
+/// exchanging messages with the other nodes.
+///
+/// All correct nodes must output the same value for the same nonce, and the value must not be
+/// predictable before at least one correct node has provided input.
+pub trait CoinSource<N: NodeIdT, T>:
+    DistAlgorithm<
+        NodeId = N,
+        Input = (),
+        Output = bool,
+        Message = CoinMessage,
+        Error = Error,
+        FaultKind = FaultKind,
+    > + fmt::Debug
+{
+    /// Creates a new coin instance for the given nonce.
+    fn new_coin(netinfo: Arc<NetworkInfo<N>>, nonce: T) -> Self;
+
+    /// Sets a cache of combined signatures shared with other coins. Coin sources that don't
+    /// combine signatures ignore it.
+    fn set_cache(&mut self, _cache: Arc<CoinCache>) {}
+
+    /// Sets the receiver of measurements. Coin sources without cryptographic operations ignore
+    /// it.
+    fn set_metrics(&mut self, _metrics: Arc<dyn Metrics>) {}
+}
+
+impl<N, T> CoinSource<N, T> for Coin<N, T>
+where
+    N: NodeIdT,
+    T: Clone + AsRef<[u8]> + Send + Sync + fmt::Debug,
+{
+    fn new_coin(netinfo: Arc<NetworkInfo<N>>, nonce: T) -> Self {
+        Coin::new(netinfo, nonce)
+    }
+
+    fn set_cache(&mut self, cache: Arc<CoinCache>) {
+        Coin::set_cache(self, cache)
+    }
+
+    fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        Coin::set_metrics(self, metrics)
+    }
+}
+
+/// An _insecure_ coin source, whose value is the parity of the nonce's SHA3 hash. Since it is
+/// predictable, an adversary that controls the network can use it to prevent Binary Agreement
+/// from terminating.
+///
+/// It doesn't exchange any messages and requires no cryptographic keys, so it is only suitable
+/// for tests, or for networks where all nodes are trusted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InsecureCoin<N> {
+    /// Our node ID.
+    our_id: N,
+    /// The coin value.
+    value: bool,
+    /// Whether the value has been output already.
+    terminated: bool,
+}
+
+impl<N: NodeIdT> DistAlgorithm for InsecureCoin<N> {
+    type NodeId = N;
+    type Input = ();
+    type Output = bool;
+    type Message = CoinMessage;
+    type Error = Error;
+    type FaultKind = FaultKind;
+
+    fn handle_input(&mut self, _input: ()) -> Result<::Step<Self>> {
+        if self.terminated {
+            return Ok(::Step::default());
+        }
+        self.terminated = true;
+        let step: ::Step<Self> = ::Step::default();
+        Ok(step.with_output(self.value))
+    }
+
+    fn handle_message(&mut self, _sender_id: &N, _message: CoinMessage) -> Result<::Step<Self>> {
+        Ok(::Step::default())
+    }
+
+    fn terminated(&self) -> bool {
+        self.terminated
+    }
+
+    fn our_id(&self) -> &N {
+        &self.our_id
+    }
+}
+
+impl<N: NodeIdT, T: AsRef<[u8]>> CoinSource<N, T> for InsecureCoin<N> {
+    fn new_coin(netinfo: Arc<NetworkInfo<N>>, nonce: T) -> Self {
+        InsecureCoin {
+            our_id: netinfo.our_id().clone(),
+            value: sha3_256(nonce.as_ref())[0] & 1 == 1,
+            terminated: false,
+        }
+    }
+}
+
 impl<N, T> DistAlgorithm for Coin<N, T>
 where
     N: NodeIdT,
@@ -83,29 +308,43 @@ where
     type Output = bool;
     type Message = CoinMessage;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     /// Sends our threshold signature share if not yet sent.
     fn handle_input(&mut self, _input: Self::Input) -> Result<Step<N, T>> {
         if !self.had_input {
             self.had_input = true;
-            self.get_coin()
+            let result = self.get_coin();
+            self.abort_on_error(result)
         } else {
             Ok(Step::default())
         }
     }
 
-    /// Receives input from a remote node.
+    /// Receives input from a remote node. Only validators can send a share, and only one each: Any
+    /// further share from the same sender is rejected, even if it is identical.
     fn handle_message(
         &mut self,
         sender_id: &Self::NodeId,
         message: Self::Message,
     ) -> Result<Step<N, T>> {
-        if !self.terminated {
-            let CoinMessage(share) = message;
-            self.handle_share(sender_id, share)
+        let validity = if self.terminated {
+            Validity::Obsolete
+        } else if !self.netinfo.is_node_validator(sender_id) {
+            Validity::Faulty(FaultKind::UnknownSender)
+        } else if self.received_shares.contains_key(sender_id) || self.rejected.contains(sender_id)
+        {
+            Validity::Faulty(FaultKind::MultipleSignatureShares)
         } else {
-            Ok(Step::default())
+            Validity::Valid
+        };
+        let mut step = Step::validated(sender_id.clone(), validity);
+        if validity.is_valid() {
+            let CoinMessage(share) = message;
+            let result = self.handle_share(sender_id, share);
+            step.extend(self.abort_on_error(result)?);
         }
+        Ok(step)
     }
 
     /// Whether the algorithm has terminated.
@@ -118,6 +357,57 @@ where
     }
 }
 
+/// The insecure coin has no environment, so it is its own snapshot.
+impl<N: NodeIdT> Restore<N> for InsecureCoin<N> {
+    type Snapshot = Self;
+
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    fn restore(snapshot: Self, _env: &Environment<N>) -> environment::Result<Self> {
+        Ok(snapshot)
+    }
+}
+
+impl<N, T> Restore<N> for Coin<N, T>
+where
+    N: NodeIdT,
+    T: Clone,
+{
+    type Snapshot = CoinSnapshot<N, T>;
+
+    fn snapshot(&self) -> CoinSnapshot<N, T> {
+        CoinSnapshot {
+            nonce: self.nonce.clone(),
+            received_shares: self.received_shares.clone(),
+            deferred_verification: self.deferred_verification,
+            unverified: self.unverified.clone(),
+            rejected: self.rejected.clone(),
+            had_input: self.had_input,
+            signature: self.signature.clone(),
+            terminated: self.terminated,
+        }
+    }
+
+    fn restore(snapshot: CoinSnapshot<N, T>, env: &Environment<N>) -> environment::Result<Self> {
+        Ok(Coin {
+            netinfo: env.netinfo.clone(),
+            nonce: snapshot.nonce,
+            received_shares: snapshot.received_shares,
+            deferred_verification: snapshot.deferred_verification,
+            unverified: snapshot.unverified,
+            rejected: snapshot.rejected,
+            had_input: snapshot.had_input,
+            signature: snapshot.signature,
+            terminated: snapshot.terminated,
+            offload: env.crypto_offload.clone(),
+            cache: env.coin_cache.clone(),
+            metrics: env.metrics.clone(),
+        })
+    }
+}
+
 impl<N, T> Coin<N, T>
 where
     N: NodeIdT,
@@ -128,48 +418,150 @@ where
             netinfo,
             nonce,
             received_shares: BTreeMap::new(),
+            deferred_verification: false,
+            unverified: BTreeSet::new(),
+            rejected: BTreeSet::new(),
             had_input: false,
+            signature: None,
             terminated: false,
+            offload: Arc::new(InlineOffload),
+            cache: None,
+            metrics: Arc::new(NoMetrics),
         }
     }
 
+    /// Returns the combined threshold signature of the nonce, once the coin has terminated. Its
+    /// parity is the coin's output value.
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
+    /// Returns the serialized combined threshold signature of the nonce, once the coin has
+    /// terminated.
+    pub fn signature_bytes(&self) -> Option<Vec<u8>> {
+        self.signature
+            .as_ref()
+            .map(|sig| bincode::serialize(sig).expect("failed to serialize signature"))
+    }
+
+    /// Returns a random value derived from the SHA3-256 hash of the combined threshold signature,
+    /// once the coin has terminated. Like the output, it is the same in all correct nodes, and
+    /// cannot be predicted before at least one correct node has provided input.
+    pub fn random_value<R: From<[u8; 32]>>(&self) -> Option<R> {
+        self.signature_bytes().map(|bytes| R::from(sha3_256(&bytes)))
+    }
+
+    /// Sets whether the received shares should only be verified if combining them fails. By
+    /// default, every share is verified on receipt.
+    pub fn set_deferred_verification(&mut self, deferred_verification: bool) {
+        self.deferred_verification = deferred_verification;
+    }
+
+    /// Sets the executor that verifies the pending shares if deferred verification is enabled.
+    /// By default, they are verified sequentially on the calling thread.
+    pub fn set_crypto_offload(&mut self, offload: Arc<dyn CryptoOffload>) {
+        self.offload = offload;
+    }
+
+    /// Sets a cache of combined signatures shared with other coins. The combined signature is
+    /// stored in it once it is known, and if it is already there on input, the coin outputs it
+    /// right away, without sending its share. That is only correct if the cached signature was
+    /// computed by a coin that has sent our share, i.e. by this node. By default, there is no
+    /// cache.
+    pub fn set_cache(&mut self, cache: Arc<CoinCache>) {
+        self.cache = Some(cache);
+    }
+
+    /// Sets the receiver of the durations of signing, verifying and combining the shares, as
+    /// `CRYPTO_DURATION` measurements. By default, measurements are ignored.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.metrics = metrics;
+    }
+
     fn get_coin(&mut self) -> Result<Step<N, T>> {
+        let cached = match self.cache {
+            Some(ref cache) => cache.get(self.nonce.as_ref()),
+            None => None,
+        };
+        if let Some(sig) = cached {
+            let parity = sig.parity();
+            debug_event!("Coin cached", node = self.netinfo.our_id(), value = parity);
+            self.signature = Some(sig);
+            self.terminated = true;
+            return Ok(Step::default().with_output(parity));
+        }
         if !self.netinfo.is_validator() {
             return self.try_output();
         }
-        let share = self.netinfo.secret_key_share().sign(&self.nonce);
+        let share = {
+            let (sk_share, nonce) = (self.netinfo.secret_key_share(), &self.nonce);
+            metrics::time_crypto(&*self.metrics, "coin_sign", || sk_share.sign(nonce))
+        };
         let mut step: Step<_, _> = Target::All.message(CoinMessage(share.clone())).into();
         let id = self.netinfo.our_id().clone();
         step.extend(self.handle_share(&id, share)?);
         Ok(step)
     }
 
+    /// Terminates the instance without a value if the result is a fatal error, so that it is
+    /// never left partially updated.
+    fn abort_on_error(&mut self, result: Result<Step<N, T>>) -> Result<Step<N, T>> {
+        if let Err(ref err) = result {
+            if err.is_fatal() {
+                self.terminated = true;
+                self.received_shares.clear();
+                self.unverified.clear();
+            }
+        }
+        result
+    }
+
     fn handle_share(&mut self, sender_id: &N, share: SignatureShare) -> Result<Step<N, T>> {
         if let Some(pk_i) = self.netinfo.public_key_share(sender_id) {
-            if !pk_i.verify(&share, &self.nonce) {
+            if self.deferred_verification && sender_id != self.netinfo.our_id() {
+                self.unverified.insert(sender_id.clone());
+            } else if !metrics::time_crypto(&*self.metrics, "coin_verify", || {
+                pk_i.verify(&share, &self.nonce)
+            }) {
                 // Log the faulty node and ignore the invalid share.
+                self.rejected.insert(sender_id.clone());
                 let fault_kind = FaultKind::UnverifiedSignatureShareSender;
                 return Ok(Fault::new(sender_id.clone(), fault_kind).into());
             }
             self.received_shares.insert(sender_id.clone(), share);
         } else {
-            return Err(Error::UnknownSender);
+            // Messages from unknown senders are rejected by `handle_message`.
+            return Ok(Fault::new(sender_id.clone(), FaultKind::UnknownSender).into());
         }
         self.try_output()
     }
 
     fn try_output(&mut self) -> Result<Step<N, T>> {
-        debug!(
-            "{:?} received {} shares, had_input = {}",
-            self.netinfo.our_id(),
-            self.received_shares.len(),
-            self.had_input
+        debug_event!(
+            "Coin shares received",
+            node = self.netinfo.our_id(),
+            shares = self.received_shares.len(),
+            had_input = self.had_input,
         );
-        if self.had_input && self.received_shares.len() > self.netinfo.num_faulty() {
-            let sig = self.combine_and_verify_sig()?;
+        let threshold = self.netinfo.public_key_set().threshold();
+        if self.had_input && self.received_shares.len() > threshold {
+            let sig = match self.combine_and_verify_sig() {
+                Ok(sig) => sig,
+                Err(_) if !self.unverified.is_empty() => {
+                    // Some shares are invalid: Discard them and try again.
+                    let mut step = self.verify_pending_shares();
+                    step.extend(self.try_output()?);
+                    return Ok(step);
+                }
+                Err(err) => return Err(err),
+            };
             // Output the parity of the verified signature.
             let parity = sig.parity();
-            debug!("{:?} output {}", self.netinfo.our_id(), parity);
+            debug_event!("Coin output", node = self.netinfo.our_id(), value = parity);
+            if let Some(ref cache) = self.cache {
+                cache.insert(self.nonce.as_ref(), sig.clone());
+            }
+            self.signature = Some(sig);
             self.terminated = true;
             let step = self.handle_input(())?; // Before terminating, make sure we sent our share.
             Ok(step.with_output(parity))
@@ -178,7 +570,54 @@ where
         }
     }
 
+    /// Verifies all shares that have not been verified yet. Discards the invalid ones and reports
+    /// their senders.
+    fn verify_pending_shares(&mut self) -> Step<N, T> {
+        let doc = Arc::new(self.nonce.as_ref().to_vec());
+        let mut faulty_senders = Vec::new();
+        let mut ids = Vec::new();
+        let mut tasks = Vec::new();
+        for id in mem::replace(&mut self.unverified, BTreeSet::new()) {
+            match (
+                self.netinfo.public_key_share(&id),
+                self.received_shares.get(&id),
+            ) {
+                (Some(pk_share), Some(share)) => {
+                    tasks.push(Verification::SignatureShare {
+                        pk_share: pk_share.clone(),
+                        share: share.clone(),
+                        doc: doc.clone(),
+                    });
+                    ids.push(id);
+                }
+                _ => faulty_senders.push(id),
+            }
+        }
+        let offload = &self.offload;
+        let results =
+            metrics::time_crypto(&*self.metrics, "coin_verify", || offload.verify_all(tasks));
+        for (id, is_valid) in ids.into_iter().zip(results) {
+            if !is_valid {
+                faulty_senders.push(id);
+            }
+        }
+        let mut step = Step::default();
+        for id in faulty_senders {
+            self.received_shares.remove(&id);
+            self.rejected.insert(id.clone());
+            let fault_kind = FaultKind::UnverifiedSignatureShareSender;
+            step.fault_log.append(id, fault_kind);
+        }
+        step
+    }
+
     fn combine_and_verify_sig(&self) -> Result<Signature> {
+        metrics::time_crypto(&*self.metrics, "coin_combine", || {
+            self.combine_and_verify_sig_untimed()
+        })
+    }
+
+    fn combine_and_verify_sig_untimed(&self) -> Result<Signature> {
         // Pass the indices of sender nodes to `combine_signatures`.
         let to_idx = |(id, share)| (self.netinfo.node_index(id).unwrap(), share);
         let shares = self.received_shares.iter().map(to_idx);
@@ -194,9 +633,9 @@ where
             .verify(&sig, &self.nonce)
         {
             // Abort
-            error!(
-                "{:?} main public key verification failed",
-                self.netinfo.our_id()
+            error_event!(
+                "Main public key verification failed",
+                node = self.netinfo.our_id(),
             );
             Err(Error::VerificationFailed)
         } else {
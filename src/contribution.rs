@@ -0,0 +1,92 @@
+use std::fmt;
+use std::ops::Deref;
+
+use hex_fmt::HexFmt;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// A contribution that the application has already serialized.
+///
+/// `HoneyBadger` and its descendants serialize every contribution before encrypting and
+/// broadcasting it. If the application already holds its transactions in encoded form, wrapping
+/// the bytes in a `SerializedContribution` avoids decoding and re-encoding them: They are written
+/// as a single length-prefixed byte string, and the batch output contains the same raw bytes.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SerializedContribution(Vec<u8>);
+
+impl SerializedContribution {
+    /// Creates a new contribution from the given bytes.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        SerializedContribution(bytes)
+    }
+
+    /// Returns the raw bytes of the contribution.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the raw bytes of the contribution, consuming it.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl fmt::Debug for SerializedContribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SerializedContribution({:0.10})", HexFmt(&self.0))
+    }
+}
+
+impl Deref for SerializedContribution {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SerializedContribution {
+    fn from(bytes: Vec<u8>) -> Self {
+        SerializedContribution(bytes)
+    }
+}
+
+impl Serialize for SerializedContribution {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializedContribution {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+/// A visitor that accepts the byte string written by `SerializedContribution::serialize`.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = SerializedContribution;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a byte string")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Ok(SerializedContribution(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(SerializedContribution(bytes))
+    }
+
+    // Formats without native byte strings encode them as sequences.
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(SerializedContribution(bytes))
+    }
+}
@@ -0,0 +1,68 @@
+//! # Offloading threshold cryptography
+//!
+//! Verifying threshold decryption and signature shares is the dominant computational cost of an
+//! epoch. Where an algorithm has several shares to verify at once — e.g. the decryption shares
+//! that arrived before the ciphertext, or the coin shares whose verification was deferred — it
+//! collects the checks as `Verification` tasks and hands them to a `CryptoOffload` in a single
+//! call. The default, `InlineOffload`, simply runs them one after another, but an application can
+//! provide an implementation that distributes them over its own thread pool.
+//!
+//! The algorithms themselves remain synchronous and deterministic: `verify_all` returns once all
+//! tasks are done, and the results are applied in the order the tasks were created.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crypto::{Ciphertext, DecryptionShare, PublicKeyShare, SignatureShare};
+
+/// A single share verification.
+#[derive(Clone, Debug)]
+pub enum Verification {
+    /// Checks that a decryption share matches the sender's public key share and the ciphertext.
+    DecryptionShare {
+        pk_share: PublicKeyShare,
+        share: DecryptionShare,
+        ciphertext: Arc<Ciphertext>,
+    },
+    /// Checks that a signature share of the given document matches the sender's public key share.
+    SignatureShare {
+        pk_share: PublicKeyShare,
+        share: SignatureShare,
+        doc: Arc<Vec<u8>>,
+    },
+}
+
+impl Verification {
+    /// Performs the verification, and returns `true` if the share is valid.
+    pub fn verify(&self) -> bool {
+        match *self {
+            Verification::DecryptionShare {
+                ref pk_share,
+                ref share,
+                ref ciphertext,
+            } => pk_share.verify_decryption_share(share, ciphertext),
+            Verification::SignatureShare {
+                ref pk_share,
+                ref share,
+                ref doc,
+            } => pk_share.verify(share, &doc[..]),
+        }
+    }
+}
+
+/// An executor for batches of share verifications.
+pub trait CryptoOffload: fmt::Debug + Send + Sync {
+    /// Performs all verifications, possibly in parallel, and returns their results in the same
+    /// order.
+    fn verify_all(&self, tasks: Vec<Verification>) -> Vec<bool>;
+}
+
+/// Runs all verifications sequentially, on the calling thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InlineOffload;
+
+impl CryptoOffload for InlineOffload {
+    fn verify_all(&self, tasks: Vec<Verification>) -> Vec<bool> {
+        tasks.iter().map(Verification::verify).collect()
+    }
+}
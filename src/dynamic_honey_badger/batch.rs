@@ -3,25 +3,32 @@ use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
-use super::{ChangeState, JoinPlan};
+use super::{Change, ChangeState, JoinPlan};
+use epoch::HbEpoch;
 use {NetworkInfo, NodeIdT};
 
 /// A batch of transactions the algorithm has output.
 #[derive(Clone, Debug)]
-pub struct Batch<C, N> {
+pub struct Batch<C, N: Ord> {
     /// The sequence number: there is exactly one batch in each epoch.
-    pub(super) epoch: u64,
+    pub(super) epoch: HbEpoch,
     /// The user contributions committed in this epoch.
     pub(super) contributions: BTreeMap<N, C>,
     /// The current state of adding or removing a node: whether any is in progress, or completed
     /// this epoch.
     pub(super) change: ChangeState<N>,
+    /// Whether key generation for the change is complete, and the old validators are finishing
+    /// the epochs in progress before the new ones take over.
+    pub(super) draining: bool,
+    /// Whether new observers can join in the next epoch: This is the case if key generation
+    /// begins or a change was completed in this epoch.
+    pub(super) joinable: bool,
     /// The network info that applies to the _next_ epoch.
     pub(super) netinfo: Arc<NetworkInfo<N>>,
 }
 
 impl<C, N: NodeIdT> Batch<C, N> {
-    pub fn epoch(&self) -> u64 {
+    pub fn epoch(&self) -> HbEpoch {
         self.epoch
     }
 
@@ -31,6 +38,34 @@ impl<C, N: NodeIdT> Batch<C, N> {
         &self.change
     }
 
+    /// Returns `true` if key generation for the current change is complete, and the old validators
+    /// are finishing the epochs in progress before the new ones take over.
+    pub fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// Returns `true` if this batch completed a change that made us a validator.
+    ///
+    /// In that case `network_info` contains our new secret key share, and we are expected to
+    /// propose a contribution in the next epoch. Contributions proposed while we were still an
+    /// observer are not included in any batch, and need to be proposed again.
+    pub fn promoted(&self) -> bool {
+        match self.change {
+            ChangeState::Complete(Change::Add(ref id, _), _) => {
+                id == self.netinfo.our_id() && self.netinfo.is_validator()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if this batch completed a change that demoted us to an observer.
+    pub fn demoted(&self) -> bool {
+        match self.change {
+            ChangeState::Complete(Change::Remove(ref id), _) => id == self.netinfo.our_id(),
+            _ => false,
+        }
+    }
+
     /// Returns the `NetworkInfo` containing the information about the validators that will produce
     /// the _next_ epoch after this one.
     pub fn network_info(&self) -> &Arc<NetworkInfo<N>> {
@@ -83,15 +118,17 @@ impl<C, N: NodeIdT> Batch<C, N> {
 
     /// Returns the `JoinPlan` to be sent to new observer nodes, if it is possible to join in the
     /// next epoch.
+    ///
+    /// This is only possible if key generation begins or a change was completed in this epoch.
     pub fn join_plan(&self) -> Option<JoinPlan<N>>
     where
         N: Serialize + for<'r> Deserialize<'r>,
     {
-        if self.change == ChangeState::None {
+        if !self.joinable {
             return None;
         }
         Some(JoinPlan {
-            epoch: self.epoch + 1,
+            epoch: self.epoch.next(),
             change: self.change.clone(),
             pub_key_set: self.netinfo.public_key_set().clone(),
             pub_keys: self.netinfo.public_key_map().clone(),
@@ -107,6 +144,8 @@ impl<C, N: NodeIdT> Batch<C, N> {
         self.epoch == other.epoch
             && self.contributions == other.contributions
             && self.change == other.change
+            && self.draining == other.draining
+            && self.joinable == other.joinable
             && self.netinfo.public_key_set() == other.netinfo.public_key_set()
             && self.netinfo.public_key_map() == other.netinfo.public_key_map()
     }
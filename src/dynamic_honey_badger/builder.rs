@@ -4,11 +4,14 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 
 use crypto::{SecretKey, SecretKeySet, SecretKeyShare};
-use rand::{self, Rand, Rng};
+use rand::{self, Rng};
 use serde::{Deserialize, Serialize};
 
 use super::{ChangeState, DynamicHoneyBadger, JoinPlan, Result, Step, VoteCounter};
-use honey_badger::{HoneyBadger, SubsetHandlingStrategy};
+use epoch::HbEpoch;
+use epoch_gate::EpochGate;
+use honey_badger::{EncryptionSchedule, HoneyBadger, SubsetHandlingStrategy};
+use metrics::{Metrics, NoMetrics};
 use util::SubRng;
 use {Contribution, NetworkInfo, NodeIdT};
 
@@ -17,11 +20,18 @@ use {Contribution, NetworkInfo, NodeIdT};
 pub struct DynamicHoneyBadgerBuilder<C, N> {
     /// The maximum number of future epochs for which we handle messages simultaneously.
     max_future_epochs: usize,
+    /// The number of epochs the old validators finish after key generation for a change has
+    /// completed.
+    drain_epochs: u64,
     /// Random number generator passed on to algorithm instance for key generation. Also used to
     /// instantiate `HoneyBadger`.
     rng: Box<dyn rand::Rng>,
     /// Strategy used to handle the output of the `Subset` algorithm.
     subset_handling_strategy: SubsetHandlingStrategy,
+    /// Determines in which epochs the contributions are encrypted.
+    encryption_schedule: EncryptionSchedule,
+    /// Receiver of the instance's measurements.
+    metrics: Arc<dyn Metrics>,
     _phantom: PhantomData<(C, N)>,
 }
 
@@ -30,8 +40,11 @@ impl<C, N> Default for DynamicHoneyBadgerBuilder<C, N> {
         // TODO: Use the defaults from `HoneyBadgerBuilder`.
         DynamicHoneyBadgerBuilder {
             max_future_epochs: 3,
+            drain_epochs: 3,
             rng: Box::new(rand::thread_rng()),
             subset_handling_strategy: SubsetHandlingStrategy::Incremental,
+            encryption_schedule: EncryptionSchedule::Always,
+            metrics: Arc::new(NoMetrics),
             _phantom: PhantomData,
         }
     }
@@ -40,7 +53,7 @@ impl<C, N> Default for DynamicHoneyBadgerBuilder<C, N> {
 impl<C, N> DynamicHoneyBadgerBuilder<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
 {
     /// Returns a new `DynamicHoneyBadgerBuilder` configured to use the node IDs and cryptographic
     /// keys specified by `netinfo`.
@@ -54,6 +67,14 @@ where
         self
     }
 
+    /// Sets the number of epochs that are still produced by the old validators after key
+    /// generation for a change has completed, so that the epochs already in progress are finished
+    /// before the new validators take over. All nodes must use the same value. The default is 3.
+    pub fn drain_epochs(&mut self, drain_epochs: u64) -> &mut Self {
+        self.drain_epochs = drain_epochs;
+        self
+    }
+
     /// Sets the random number generator to be used to instantiate cryptographic structures.
     pub fn rng<R: rand::Rng + 'static>(&mut self, rng: R) -> &mut Self {
         self.rng = Box::new(rng);
@@ -69,12 +90,28 @@ where
         self
     }
 
+    /// Sets the schedule that determines in which epochs the contributions are encrypted. By
+    /// default, they are always encrypted.
+    pub fn encryption_schedule(&mut self, encryption_schedule: EncryptionSchedule) -> &mut Self {
+        self.encryption_schedule = encryption_schedule;
+        self
+    }
+
+    /// Sets the receiver of measurements. By default, measurements are ignored.
+    pub fn metrics(&mut self, metrics: Arc<dyn Metrics>) -> &mut Self {
+        self.metrics = metrics;
+        self
+    }
+
     /// Creates a new Dynamic Honey Badger instance with an empty buffer.
     pub fn build(&mut self, netinfo: NetworkInfo<N>) -> DynamicHoneyBadger<C, N> {
         let DynamicHoneyBadgerBuilder {
             max_future_epochs,
+            drain_epochs,
             rng,
             subset_handling_strategy,
+            encryption_schedule,
+            metrics,
             _phantom,
         } = self;
         let max_future_epochs = *max_future_epochs;
@@ -83,17 +120,23 @@ where
             .max_future_epochs(max_future_epochs)
             .rng(rng.sub_rng())
             .subset_handling_strategy(subset_handling_strategy.clone())
+            .encryption_schedule(*encryption_schedule)
+            .metrics(metrics.clone())
             .build();
         DynamicHoneyBadger {
             netinfo,
             max_future_epochs,
-            start_epoch: 0,
+            start_epoch: HbEpoch::new(0),
             vote_counter: VoteCounter::new(arc_netinfo, 0),
             key_gen_msg_buffer: Vec::new(),
             honey_badger,
             key_gen_state: None,
-            incoming_queue: Vec::new(),
+            drain_epochs: *drain_epochs,
+            pending_change: None,
+            incoming_queue: EpochGate::new(0, 0),
             rng: Box::new(rng.sub_rng()),
+            encryption_schedule: *encryption_schedule,
+            metrics: metrics.clone(),
         }
     }
 
@@ -126,20 +169,28 @@ where
         let arc_netinfo = Arc::new(netinfo.clone());
         let honey_badger = HoneyBadger::builder(arc_netinfo.clone())
             .max_future_epochs(self.max_future_epochs)
+            .encryption_schedule(self.encryption_schedule)
+            .metrics(self.metrics.clone())
             .build();
         let mut dhb = DynamicHoneyBadger {
             netinfo,
             max_future_epochs: self.max_future_epochs,
             start_epoch: join_plan.epoch,
-            vote_counter: VoteCounter::new(arc_netinfo, join_plan.epoch),
+            vote_counter: VoteCounter::new(arc_netinfo, join_plan.epoch.get()),
             key_gen_msg_buffer: Vec::new(),
             honey_badger,
             key_gen_state: None,
-            incoming_queue: Vec::new(),
+            drain_epochs: self.drain_epochs,
+            pending_change: None,
+            incoming_queue: EpochGate::new(join_plan.epoch.get(), 0),
             rng: Box::new(self.rng.sub_rng()),
+            encryption_schedule: self.encryption_schedule,
+            metrics: self.metrics.clone(),
         };
         let step = match join_plan.change {
-            ChangeState::InProgress(ref change) => dhb.update_key_gen(join_plan.epoch, change)?,
+            ChangeState::InProgress(ref change, ref voters) => {
+                dhb.update_key_gen(join_plan.epoch, change, voters)?
+            }
             ChangeState::None | ChangeState::Complete(..) => Step::default(),
         };
         Ok((dhb, step))
@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use crypto::PublicKey;
 
 /// A node change action: adding or removing a node.
@@ -20,14 +22,38 @@ impl<N> Change<N> {
 }
 
 /// A change status: whether a node addition or removal is currently in progress or completed.
+///
+/// Every batch contains the current state, so that applications can persist changes to the set
+/// of validators together with the batch in which they happened.
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Hash, Debug)]
-pub enum ChangeState<N> {
+pub enum ChangeState<N: Ord> {
     /// No node is currently being considered for addition or removal.
     None,
-    /// A change is currently in progress. If it is an addition, all broadcast messages must be
-    /// sent to the new node, too.
-    InProgress(Change<N>),
-    /// A change has been completed in this epoch. From the next epoch on, the new composition of
-    /// the network will perform the consensus process.
-    Complete(Change<N>),
+    /// A change is currently in progress, together with the validators who voted for it. If it is
+    /// an addition, all broadcast messages must be sent to the new node, too.
+    InProgress(Change<N>, BTreeSet<N>),
+    /// A change has been completed in this epoch, together with the public keys of the new set of
+    /// validators. From the next epoch on, the new composition of the network will perform the
+    /// consensus process.
+    Complete(Change<N>, BTreeMap<N, PublicKey>),
+}
+
+impl<N: Ord> ChangeState<N> {
+    /// Returns the change that is in progress or was completed, if any.
+    pub fn change(&self) -> Option<&Change<N>> {
+        match *self {
+            ChangeState::None => None,
+            ChangeState::InProgress(ref change, _) | ChangeState::Complete(ref change, _) => {
+                Some(change)
+            }
+        }
+    }
+
+    /// Returns the change that was completed, if any.
+    pub fn completed(&self) -> Option<&Change<N>> {
+        match *self {
+            ChangeState::Complete(ref change, _) => Some(change),
+            ChangeState::None | ChangeState::InProgress(..) => None,
+        }
+    }
 }
@@ -1,31 +1,39 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 use std::{fmt, mem};
 
 use bincode;
-use crypto::Signature;
-use rand::{self, Rand};
+use crypto::{PublicKey, Signature};
+use rand;
 use serde::{Deserialize, Serialize};
 
 use super::votes::{SignedVote, VoteCounter};
 use super::{
-    Batch, Change, ChangeState, DynamicHoneyBadgerBuilder, Error, ErrorKind, Input,
-    InternalContrib, KeyGenMessage, KeyGenState, Message, Result, SignedKeyGenMsg, Step,
+    Batch, Change, ChangeState, DynamicHoneyBadgerBuilder, Error, ErrorKind, FaultKind, Input,
+    InternalContrib, KeyGenMessage, KeyGenState, Message, PendingChange, Result, SignedKeyGenMsg,
+    Step, LOG_TARGET,
 };
-use fault_log::{Fault, FaultKind, FaultLog};
-use honey_badger::{self, HoneyBadger, Message as HbMessage};
-use sync_key_gen::{Ack, Part, PartOutcome, SyncKeyGen};
+use fault_log::{Fault, FaultLog};
+use honey_badger::{self, EncryptionSchedule, HoneyBadger, Message as HbMessage};
+use metrics::Metrics;
+use sync_key_gen::{
+    Ack, Complaint, ComplaintOutcome, Justification, JustificationOutcome, Part, PartOutcome,
+    SyncKeyGen,
+};
+use epoch::HbEpoch;
+use epoch_gate::{classify_epoch, EpochGate};
 use util::SubRng;
+use validation::Validity;
 use {Contribution, DistAlgorithm, NetworkInfo, NodeIdT, Target};
 
 /// A Honey Badger instance that can handle adding and removing nodes.
-pub struct DynamicHoneyBadger<C, N: Rand> {
+pub struct DynamicHoneyBadger<C, N> {
     /// Shared network data.
     pub(super) netinfo: NetworkInfo<N>,
     /// The maximum number of future epochs for which we handle messages simultaneously.
     pub(super) max_future_epochs: usize,
     /// The first epoch after the latest node change.
-    pub(super) start_epoch: u64,
+    pub(super) start_epoch: HbEpoch,
     /// The buffer and counter for the pending and committed change votes.
     pub(super) vote_counter: VoteCounter<N>,
     /// Pending node transactions that we will propose in the next epoch.
@@ -34,17 +42,25 @@ pub struct DynamicHoneyBadger<C, N: Rand> {
     pub(super) honey_badger: HoneyBadger<InternalContrib<C, N>, N>,
     /// The current key generation process, and the change it applies to.
     pub(super) key_gen_state: Option<KeyGenState<N>>,
-    /// A queue for messages from future epochs that cannot be handled yet.
-    pub(super) incoming_queue: Vec<(N, Message<N>)>,
+    /// The number of epochs the old validators finish after key generation has completed.
+    pub(super) drain_epochs: u64,
+    /// The change for which key generation has completed, while the in-flight epochs are drained.
+    pub(super) pending_change: Option<PendingChange<N>>,
+    /// The messages for later eras, i.e. with a later `start_epoch`, that cannot be handled yet.
+    pub(super) incoming_queue: EpochGate<N, Message<N>>,
     /// A random number generator used for secret key generation.
     // Boxed to avoid overloading the algorithm's type with more generics.
     pub(super) rng: Box<dyn rand::Rng + Send + Sync>,
+    /// Determines in which epochs the contributions are encrypted.
+    pub(super) encryption_schedule: EncryptionSchedule,
+    /// Receiver of measurements, passed on to the `HoneyBadger` instances.
+    pub(super) metrics: Arc<dyn Metrics>,
 }
 
 impl<C, N> fmt::Debug for DynamicHoneyBadger<C, N>
 where
     C: fmt::Debug,
-    N: Rand + fmt::Debug,
+    N: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("DynamicHoneyBadger")
@@ -55,8 +71,12 @@ where
             .field("key_gen_msg_buffer", &self.key_gen_msg_buffer)
             .field("honey_badger", &self.honey_badger)
             .field("key_gen_state", &self.key_gen_state)
+            .field("drain_epochs", &self.drain_epochs)
+            .field("pending_change", &self.pending_change)
             .field("incoming_queue", &self.incoming_queue)
             .field("rng", &"<RNG>")
+            .field("encryption_schedule", &self.encryption_schedule)
+            .field("metrics", &"<Metrics>")
             .finish()
     }
 }
@@ -64,15 +84,21 @@ where
 impl<C, N> DistAlgorithm for DynamicHoneyBadger<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
 {
     type NodeId = N;
     type Input = Input<C, N>;
     type Output = Batch<C, N>;
     type Message = Message<N>;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<C, N>> {
+        enter_span!(
+            "dynamic_honey_badger",
+            node = self.our_id(),
+            start_epoch = self.start_epoch,
+        );
         // User contributions are forwarded to `HoneyBadger` right away. Votes are signed and
         // broadcast.
         match input {
@@ -82,29 +108,35 @@ where
     }
 
     fn handle_message(&mut self, sender_id: &N, message: Self::Message) -> Result<Step<C, N>> {
-        let epoch = message.start_epoch();
-        if epoch < self.start_epoch {
-            // Obsolete message.
-            Ok(Step::default())
-        } else if epoch > self.start_epoch {
-            // Message cannot be handled yet. Save it for later.
-            let entry = (sender_id.clone(), message);
-            self.incoming_queue.push(entry);
-            Ok(Step::default())
-        } else {
-            match message {
+        enter_span!(
+            "dynamic_honey_badger",
+            node = self.our_id(),
+            start_epoch = self.start_epoch,
+        );
+        let validity: Validity<FaultKind> =
+            classify_epoch(message.start_epoch().get(), self.start_epoch.get(), 0);
+        let mut step = Step::validated(sender_id.clone(), validity);
+        match validity {
+            Validity::Valid => step.extend(match message {
                 Message::HoneyBadger(_, hb_msg) => {
-                    self.handle_honey_badger_message(sender_id, hb_msg)
+                    self.handle_honey_badger_message(sender_id, hb_msg)?
                 }
                 Message::KeyGen(_, kg_msg, sig) => self
-                    .handle_key_gen_message(sender_id, kg_msg, *sig)
-                    .map(FaultLog::into),
+                    .handle_key_gen_message(sender_id, kg_msg, *sig)?
+                    .into(),
                 Message::SignedVote(signed_vote) => self
                     .vote_counter
-                    .add_pending_vote(sender_id, signed_vote)
-                    .map(FaultLog::into),
+                    .add_pending_vote(sender_id, signed_vote)?
+                    .into(),
+            }),
+            // Message cannot be handled yet. Save it for later.
+            Validity::Future => {
+                let epoch = message.start_epoch().get();
+                self.incoming_queue.push(sender_id.clone(), epoch, message)
             }
+            Validity::Obsolete | Validity::Faulty(_) | Validity::Incompatible => (),
         }
+        Ok(step)
     }
 
     fn terminated(&self) -> bool {
@@ -119,7 +151,7 @@ where
 impl<C, N> DynamicHoneyBadger<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
 {
     /// Returns a new `DynamicHoneyBadgerBuilder`.
     pub fn builder() -> DynamicHoneyBadgerBuilder<C, N> {
@@ -131,6 +163,16 @@ where
         self.honey_badger.has_input()
     }
 
+    /// Returns the current epoch, counted from the start of the network.
+    pub fn epoch(&self) -> HbEpoch {
+        self.start_epoch.saturating_add(self.honey_badger.epoch().get())
+    }
+
+    /// Returns the maximum number of future epochs for which we handle messages simultaneously.
+    pub fn max_future_epochs(&self) -> u64 {
+        self.max_future_epochs as u64
+    }
+
     /// Proposes a contribution in the current epoch.
     pub fn propose(&mut self, contrib: C) -> Result<Step<C, N>> {
         let step = self
@@ -153,11 +195,32 @@ where
         Ok(Target::All.message(msg).into())
     }
 
+    /// Casts a vote to add a node as a validator.
+    ///
+    /// This is a shortcut for `vote_for(Change::Add(node_id, pub_key))`.
+    pub fn vote_to_add(&mut self, node_id: N, pub_key: PublicKey) -> Result<Step<C, N>> {
+        self.vote_for(Change::Add(node_id, pub_key))
+    }
+
+    /// Casts a vote to demote a validator to observer.
+    ///
+    /// This is a shortcut for `vote_for(Change::Remove(node_id))`.
+    pub fn vote_to_remove(&mut self, node_id: N) -> Result<Step<C, N>> {
+        self.vote_for(Change::Remove(node_id))
+    }
+
     /// Returns the information about the node IDs in the network, and the cryptographic keys.
     pub fn netinfo(&self) -> &NetworkInfo<N> {
         &self.netinfo
     }
 
+    /// Returns `true` if we are currently a validator, i.e. if our contributions are included in
+    /// the batches. An observer becomes a validator as soon as a batch completes the change that
+    /// adds it: see `Batch::promoted`.
+    pub fn is_validator(&self) -> bool {
+        self.netinfo.is_validator()
+    }
+
     /// Returns `true` if we should make our contribution for the next epoch, even if we don't have
     /// content ourselves, to avoid stalling the network.
     ///
@@ -192,11 +255,7 @@ where
         sender_id: &N,
         message: HbMessage<N>,
     ) -> Result<Step<C, N>> {
-        if !self.netinfo.is_node_validator(sender_id) {
-            info!("Unknown sender {:?} of message {:?}", sender_id, message);
-            return Err(ErrorKind::UnknownSender.into());
-        }
-        // Handle the message.
+        // Messages from unknown senders are reported by `HoneyBadger`.
         let step = self
             .honey_badger
             .handle_message(sender_id, message)
@@ -211,31 +270,38 @@ where
         sender_id: &N,
         kg_msg: KeyGenMessage,
         sig: Signature,
-    ) -> Result<FaultLog<N>> {
+    ) -> Result<FaultLog<N, FaultKind>> {
+        if self.pending_change.is_some() {
+            // Key generation is complete. Messages that arrive late are ignored.
+            return Ok(FaultLog::default());
+        }
         if !self.verify_signature(sender_id, &sig, &kg_msg)? {
-            info!("Invalid signature from {:?} for: {:?}.", sender_id, kg_msg);
+            info_event!("Invalid key gen message signature", sender = sender_id);
             let fault_kind = FaultKind::InvalidKeyGenMessageSignature;
             return Ok(Fault::new(sender_id.clone(), fault_kind).into());
         }
         let kgs = match self.key_gen_state {
             Some(ref mut kgs) => kgs,
             None => {
-                info!(
-                    "Unexpected key gen message from {:?}: {:?}.",
-                    sender_id, kg_msg
-                );
+                info_event!("Unexpected key gen message", sender = sender_id);
                 return Ok(Fault::new(sender_id.clone(), FaultKind::UnexpectedKeyGenMessage).into());
             }
         };
 
+        // A correct node sends a single `Part`, and a single `Ack` for each dealer.
+        if let Some(fault_kind) = kgs.check_received(sender_id, &kg_msg) {
+            info_event!("Unexpected key gen message", sender = sender_id);
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+
         // If the joining node is correct, it will send at most (N + 1)² + 1 key generation
         // messages.
         if Some(sender_id) == kgs.change.candidate() {
             let n = self.netinfo.num_nodes() + 1;
             if kgs.candidate_msg_count > n * n {
-                info!(
-                    "Too many key gen messages from candidate {:?}: {:?}.",
-                    sender_id, kg_msg
+                info_event!(
+                    "Too many key gen messages from candidate",
+                    sender = sender_id
                 );
                 let fault_kind = FaultKind::TooManyCandidateKeyGenMessages;
                 return Ok(Fault::new(sender_id.clone(), fault_kind).into());
@@ -255,9 +321,10 @@ where
     ) -> Result<Step<C, N>> {
         let mut step: Step<C, N> = Step::default();
         let start_epoch = self.start_epoch;
-        let output = step.extend_with(hb_step, |hb_msg| Message::HoneyBadger(start_epoch, hb_msg));
+        let to_msg = |hb_msg| Message::HoneyBadger(start_epoch, hb_msg);
+        let (output, _) = step.extend_with(hb_step, to_msg);
         for hb_batch in output {
-            let batch_epoch = hb_batch.epoch + self.start_epoch;
+            let batch_epoch = self.start_epoch.saturating_add(hb_batch.epoch.get());
             let mut batch_contributions = BTreeMap::new();
 
             // Add the user transactions to `batch` and handle votes and DKG messages.
@@ -274,49 +341,108 @@ where
                     .retain(|skgm| !key_gen_messages.contains(skgm));
                 for SignedKeyGenMsg(epoch, s_id, kg_msg, sig) in key_gen_messages {
                     if epoch < self.start_epoch {
-                        info!("Obsolete key generation message: {:?}.", kg_msg);
+                        info_event!(
+                            "Obsolete key gen message",
+                            proposer = id,
+                            sender = s_id,
+                            epoch = epoch,
+                        );
                         continue;
                     }
+                    if self.pending_change.is_some() {
+                        continue; // Key generation is complete. Late messages are ignored.
+                    }
                     if !self.verify_signature(&s_id, &sig, &kg_msg)? {
-                        info!(
-                            "Invalid signature in {:?}'s batch from {:?} for: {:?}.",
-                            id, s_id, kg_msg
+                        info_event!(
+                            "Invalid key gen message signature in batch",
+                            proposer = id,
+                            sender = s_id,
                         );
                         let fault_kind = FaultKind::InvalidKeyGenMessageSignature;
                         step.fault_log.append(id.clone(), fault_kind);
                         continue;
                     }
+                    // Skip messages that were already committed, and report conflicting ones.
+                    let check = self
+                        .key_gen_state
+                        .as_mut()
+                        .map_or(Ok(true), |kgs| kgs.check_committed(&s_id, &kg_msg));
+                    match check {
+                        Ok(true) => (),
+                        Ok(false) => continue,
+                        Err(fault_kind) => {
+                            info_event!("Conflicting key gen message", sender = s_id);
+                            step.fault_log.append(s_id.clone(), fault_kind);
+                            continue;
+                        }
+                    }
                     step.extend(match kg_msg {
                         KeyGenMessage::Part(part) => self.handle_part(&s_id, part)?,
                         KeyGenMessage::Ack(ack) => self.handle_ack(&s_id, ack)?.into(),
+                        KeyGenMessage::Complaint(complaint) => {
+                            self.handle_complaint(&s_id, complaint)?
+                        }
+                        KeyGenMessage::Justification(justification) => {
+                            self.handle_justification(&s_id, justification)?
+                        }
                     });
                 }
             }
 
-            let change = if let Some(kgs) = self.take_ready_key_gen() {
-                // If DKG completed, apply the change, restart Honey Badger, and inform the user.
-                debug!("{:?} DKG for {:?} complete!", self.our_id(), kgs.change);
-                self.netinfo = kgs.key_gen.into_network_info()?;
-                self.restart_honey_badger(batch_epoch + 1);
-                ChangeState::Complete(kgs.change)
+            if let Some(kgs) = self.take_ready_key_gen() {
+                // If DKG completed, the change takes effect once the in-flight epochs are done.
+                debug_event!("DKG complete", node = self.our_id(), change = kgs.change);
+                self.key_gen_msg_buffer.clear();
+                self.pending_change = Some(PendingChange {
+                    last_epoch: batch_epoch.saturating_add(self.drain_epochs),
+                    netinfo: kgs.key_gen.into_network_info()?,
+                    change: kgs.change,
+                    voters: kgs.voters,
+                });
+            }
+
+            let mut draining = false;
+            let mut joinable = true;
+            let change = if let Some(pending) = self.take_due_change(batch_epoch) {
+                // Apply the change, restart Honey Badger, and inform the user.
+                let was_validator = self.netinfo.is_validator();
+                self.netinfo = pending.netinfo;
+                if !was_validator && self.netinfo.is_validator() {
+                    info_event!("Promoted to validator", node = self.our_id(), epoch = batch_epoch);
+                }
+                self.restart_honey_badger(batch_epoch.next());
+                let pub_keys = self.netinfo.public_key_map().clone();
+                ChangeState::Complete(pending.change, pub_keys)
+            } else if let Some(ref pending) = self.pending_change {
+                // The old validators are still finishing the epochs in progress.
+                draining = true;
+                joinable = false;
+                ChangeState::InProgress(pending.change.clone(), pending.voters.clone())
             } else if let Some(change) = self.vote_counter.compute_winner().cloned() {
                 // If there is a new change, restart DKG. Inform the user about the current change.
-                step.extend(self.update_key_gen(batch_epoch + 1, &change)?);
-                ChangeState::InProgress(change)
+                let voters = self.vote_counter.voters(&change);
+                step.extend(self.update_key_gen(batch_epoch.next(), &change, &voters)?);
+                ChangeState::InProgress(change, voters)
+            } else if let Some(ref kgs) = self.key_gen_state {
+                // DKG is still in progress. New nodes would have missed its beginning.
+                joinable = false;
+                ChangeState::InProgress(kgs.change.clone(), kgs.voters.clone())
             } else {
+                joinable = false;
                 ChangeState::None
             };
             step.output.push_back(Batch {
                 epoch: batch_epoch,
                 change,
+                draining,
+                joinable,
                 netinfo: Arc::new(self.netinfo.clone()),
                 contributions: batch_contributions,
             });
         }
-        // If `start_epoch` changed, we can now handle some queued messages.
-        if start_epoch < self.start_epoch {
-            let queue = mem::replace(&mut self.incoming_queue, Vec::new());
-            for (sender_id, msg) in queue {
+        // If `start_epoch` changed, we can now handle the queued messages for the new era.
+        for (_, messages) in self.incoming_queue.advance(self.start_epoch.get()) {
+            for (sender_id, msg) in messages {
                 step.extend(self.handle_message(&sender_id, msg)?);
             }
         }
@@ -325,18 +451,23 @@ where
 
     /// If the winner of the vote has changed, restarts Key Generation for the set of nodes implied
     /// by the current change.
-    pub(super) fn update_key_gen(&mut self, epoch: u64, change: &Change<N>) -> Result<Step<C, N>> {
+    pub(super) fn update_key_gen(
+        &mut self,
+        epoch: HbEpoch,
+        change: &Change<N>,
+        voters: &BTreeSet<N>,
+    ) -> Result<Step<C, N>> {
         if self.key_gen_state.as_ref().map(|kgs| &kgs.change) == Some(change) {
             return Ok(Step::default()); // The change is the same as before. Continue DKG as is.
         }
-        debug!("{:?} Restarting DKG for {:?}.", self.our_id(), change);
+        debug_event!("Restarting DKG", node = self.our_id(), change = change);
         // Use the existing key shares - with the change applied - as keys for DKG.
         let mut pub_keys = self.netinfo.public_key_map().clone();
         if match *change {
             Change::Remove(ref id) => pub_keys.remove(id).is_none(),
             Change::Add(ref id, ref pk) => pub_keys.insert(id.clone(), pk.clone()).is_some(),
         } {
-            info!("{:?} No-op change: {:?}", self.our_id(), change);
+            info_event!("No-op change", node = self.our_id(), change = change);
         }
         self.restart_honey_badger(epoch);
         // TODO: This needs to be the same as `num_faulty` will be in the _new_
@@ -345,7 +476,7 @@ where
         let sk = self.netinfo.secret_key().clone();
         let our_id = self.our_id().clone();
         let (key_gen, part) = SyncKeyGen::new(&mut self.rng, our_id, sk, pub_keys, threshold)?;
-        self.key_gen_state = Some(KeyGenState::new(key_gen, change.clone()));
+        self.key_gen_state = Some(KeyGenState::new(key_gen, change.clone(), voters.clone()));
         if let Some(part) = part {
             self.send_transaction(KeyGenMessage::Part(part))
         } else {
@@ -354,15 +485,17 @@ where
     }
 
     /// Starts a new `HoneyBadger` instance and resets the vote counter.
-    fn restart_honey_badger(&mut self, epoch: u64) {
+    fn restart_honey_badger(&mut self, epoch: HbEpoch) {
         self.start_epoch = epoch;
         self.key_gen_msg_buffer.retain(|kg_msg| kg_msg.0 >= epoch);
         let netinfo = Arc::new(self.netinfo.clone());
-        let counter = VoteCounter::new(netinfo.clone(), epoch);
+        let counter = VoteCounter::new(netinfo.clone(), epoch.get());
         mem::replace(&mut self.vote_counter, counter);
         self.honey_badger = HoneyBadger::builder(netinfo)
             .max_future_epochs(self.max_future_epochs)
             .rng(self.rng.sub_rng())
+            .encryption_schedule(self.encryption_schedule)
+            .metrics(self.metrics.clone())
             .build();
     }
 
@@ -377,20 +510,70 @@ where
 
         match outcome {
             Some(PartOutcome::Valid(ack)) => self.send_transaction(KeyGenMessage::Ack(ack)),
-            Some(PartOutcome::Invalid(fault_log)) => Ok(fault_log.into()),
+            Some(PartOutcome::Invalid(fault_log)) => {
+                Ok(fault_log.map(FaultKind::SyncKeyGen).into())
+            }
+            Some(PartOutcome::Complaint(complaint)) => {
+                self.send_transaction(KeyGenMessage::Complaint(complaint))
+            }
             None => Ok(Step::default()),
         }
     }
 
     /// Handles an `Ack` message that was output by Honey Badger.
-    fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<FaultLog<N>> {
+    fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<FaultLog<N, FaultKind>> {
         if let Some(kgs) = self.key_gen_state.as_mut() {
-            Ok(kgs.key_gen.handle_ack(sender_id, ack))
+            Ok(kgs.key_gen.handle_ack(sender_id, ack).map(FaultKind::SyncKeyGen))
         } else {
             Ok(FaultLog::new())
         }
     }
 
+    /// Handles a `Complaint` message that was output by Honey Badger.
+    fn handle_complaint(&mut self, sender_id: &N, complaint: Complaint) -> Result<Step<C, N>> {
+        let outcome = if let Some(kgs) = self.key_gen_state.as_mut() {
+            kgs.key_gen.handle_complaint(sender_id, complaint)
+        } else {
+            // No key generation ongoing. Return early.
+            return Ok(Step::default());
+        };
+
+        match outcome {
+            ComplaintOutcome::Valid(Some(justification)) => {
+                self.send_transaction(KeyGenMessage::Justification(justification))
+            }
+            ComplaintOutcome::Valid(None) => Ok(Step::default()),
+            ComplaintOutcome::Invalid(fault_log) => {
+                Ok(fault_log.map(FaultKind::SyncKeyGen).into())
+            }
+        }
+    }
+
+    /// Handles a `Justification` message that was output by Honey Badger.
+    fn handle_justification(
+        &mut self,
+        sender_id: &N,
+        justification: Justification,
+    ) -> Result<Step<C, N>> {
+        let outcome = if let Some(kgs) = self.key_gen_state.as_mut() {
+            kgs.key_gen
+                .handle_justification(&mut self.rng, sender_id, justification)
+        } else {
+            // No key generation ongoing. Return early.
+            return Ok(Step::default());
+        };
+
+        match outcome {
+            JustificationOutcome::Valid(Some(ack)) => {
+                self.send_transaction(KeyGenMessage::Ack(ack))
+            }
+            JustificationOutcome::Valid(None) => Ok(Step::default()),
+            JustificationOutcome::Invalid(fault_log) => {
+                Ok(fault_log.map(FaultKind::SyncKeyGen).into())
+            }
+        }
+    }
+
     /// Signs and sends a `KeyGenMessage` and also tries to commit it.
     fn send_transaction(&mut self, kg_msg: KeyGenMessage) -> Result<Step<C, N>> {
         let ser =
@@ -406,6 +589,19 @@ where
         Ok(Target::All.message(msg).into())
     }
 
+    /// If the in-flight epochs up to `epoch` have been finished, returns the pending change.
+    fn take_due_change(&mut self, epoch: HbEpoch) -> Option<PendingChange<N>> {
+        if self
+            .pending_change
+            .as_ref()
+            .map_or(false, |pending| pending.last_epoch <= epoch)
+        {
+            self.pending_change.take()
+        } else {
+            None
+        }
+    }
+
     /// If the current Key Generation process is ready, returns the `KeyGenState`.
     ///
     /// We require the minimum number of completed proposals (`SyncKeyGen::is_ready`) and if a new
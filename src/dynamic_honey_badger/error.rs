@@ -7,6 +7,47 @@ use failure::{Backtrace, Context, Fail};
 use honey_badger;
 use sync_key_gen;
 
+/// Represents each reason why a Dynamic Honey Badger message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A key generation message had an invalid signature.
+    InvalidKeyGenMessageSignature,
+    /// A key generation message was received while no key generation was in progress.
+    UnexpectedKeyGenMessage,
+    /// The candidate sent more key generation messages than expected.
+    TooManyCandidateKeyGenMessages,
+    /// A key generation message was sent by a node that doesn't take part in the key generation.
+    UnexpectedKeyGenSender,
+    /// A dealer committed multiple `Part` messages.
+    MultipleKeyGenParts,
+    /// A node committed multiple `Ack` messages for the same dealer's `Part`.
+    MultipleKeyGenAcks,
+    /// A node committed multiple `Complaint` messages about the same dealer's `Part`.
+    MultipleKeyGenComplaints,
+    /// A dealer committed multiple `Justification` messages for the same complainer.
+    MultipleKeyGenJustifications,
+    /// A change vote had an invalid signature.
+    InvalidVoteSignature,
+    /// A validator committed an invalid vote.
+    InvalidCommittedVote,
+    /// A fault in the key generation.
+    SyncKeyGen(sync_key_gen::FaultKind),
+    /// A fault in the Honey Badger instance.
+    HoneyBadger(honey_badger::FaultKind),
+}
+
+impl From<sync_key_gen::FaultKind> for FaultKind {
+    fn from(fault_kind: sync_key_gen::FaultKind) -> FaultKind {
+        FaultKind::SyncKeyGen(fault_kind)
+    }
+}
+
+impl From<honey_badger::FaultKind> for FaultKind {
+    fn from(fault_kind: honey_badger::FaultKind) -> FaultKind {
+        FaultKind::HoneyBadger(fault_kind)
+    }
+}
+
 /// Dynamic honey badger error variants.
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
@@ -29,8 +70,22 @@ pub enum ErrorKind {
     HandleHoneyBadgerMessageHoneyBadger(honey_badger::Error),
     #[fail(display = "SyncKeyGen error: {}", _0)]
     SyncKeyGen(sync_key_gen::Error),
-    #[fail(display = "Unknown sender")]
-    UnknownSender,
+}
+
+impl ErrorKind {
+    /// Returns `true` if the instance cannot continue after this error. Transactions and votes
+    /// that cannot be serialized are only rejected.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            ErrorKind::SendTransactionBincode(_) | ErrorKind::SignVoteForBincode(_) => false,
+            ErrorKind::ProposeHoneyBadger(ref err)
+            | ErrorKind::HandleHoneyBadgerMessageHoneyBadger(ref err) => err.is_fatal(),
+            ErrorKind::VerifySignatureBincode(_)
+            | ErrorKind::ValidateBincode(_)
+            | ErrorKind::Crypto(_)
+            | ErrorKind::SyncKeyGen(_) => true,
+        }
+    }
 }
 
 /// A dynamic honey badger error.
@@ -53,6 +108,12 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// Returns `true` if the instance cannot continue after this error. See
+    /// `ErrorKind::is_fatal`.
+    pub fn is_fatal(&self) -> bool {
+        self.kind().is_fatal()
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -13,20 +13,35 @@
 //! `Change` input variant, which contains either a vote `Add(node_id, public_key)`, to add an
 //! existing observer to the set of validators, or `Remove(node_id)` to remove it. Each
 //! validator can have at most one active vote, and casting another vote revokes the previous one.
+//! Votes can also be cast directly, using the `vote_for`, `vote_to_add` and `vote_to_remove`
+//! methods.
 //! Once _f + 1_ validators have the same active vote, a reconfiguration process begins: They
 //! create new cryptographic key shares for the new group of validators.
 //!
 //! The state of that process after each epoch is communicated via the `change` field in `Batch`.
-//! When this contains an `InProgress(..)` value, key generation begins. The joining validator (in
-//! the case of an `Add` change) must be an observer starting in the following epoch or earlier.
-//! When `change` is `Complete(..)`, the following epochs will be produced by the new set of
-//! validators.
+//! When this contains an `InProgress(..)` value, key generation is in progress; it also lists the
+//! validators who voted for the change. The joining validator (in the case of an `Add` change)
+//! must be an observer starting in the following epoch or earlier. When `change` is
+//! `Complete(..)`, the following epochs will be produced by the new set of validators, whose
+//! public keys it contains.
 //!
-//! New observers can only join the network after an epoch where `change` was not `None`. These
-//! epochs' batches contain a `JoinPlan`, which can be sent as an invitation to the new node: The
-//! `DynamicHoneyBadger` instance created from a `JoinPlan` will start as an observer in the
-//! following epoch. All `Target::All` messages from that and later epochs must be sent to the new
-//! node.
+//! The switch doesn't happen immediately after key generation has completed: The old validators
+//! first finish a configurable number of epochs (`DynamicHoneyBadgerBuilder::drain_epochs`), so
+//! that epochs that are already in progress, and e.g. the broadcast and agreement instances that
+//! expect a removed validator's messages, are not abandoned. During these epochs, `change` is
+//! still `InProgress(..)`, and votes are ignored.
+//!
+//! An observer that is being added takes part in key generation, and receives its secret key
+//! share once it completes: In the batch where `change` is `Complete(Add(..))`, the new
+//! validator's `network_info` contains its key share and `Batch::promoted` returns `true`. From
+//! the following epoch on, it proposes contributions like any other validator. No restart is
+//! required, and `DynamicHoneyBadger::is_validator` tells whether a node is currently a validator.
+//!
+//! New observers can only join the network after an epoch where key generation began or a change
+//! was completed. These epochs' batches contain a `JoinPlan`, which can be sent as an invitation
+//! to the new node: The `DynamicHoneyBadger` instance created from a `JoinPlan` will start as an
+//! observer in the following epoch. All `Target::All` messages from that and later epochs must be
+//! sent to the new node.
 //!
 //! Observer nodes can leave the network at any time.
 //!
@@ -63,22 +78,26 @@ mod error;
 mod votes;
 
 use crypto::{PublicKey, PublicKeySet, Signature};
-use rand::Rand;
-use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
 
 use self::votes::{SignedVote, VoteCounter};
+use epoch::HbEpoch;
 use honey_badger::Message as HbMessage;
-use sync_key_gen::{Ack, Part, SyncKeyGen};
-use NodeIdT;
+use sync_key_gen::{Ack, Complaint, Justification, Part, SyncKeyGen};
+use {NetworkInfo, NodeIdT};
 
 pub use self::batch::Batch;
 pub use self::builder::DynamicHoneyBadgerBuilder;
 pub use self::change::{Change, ChangeState};
 pub use self::dynamic_honey_badger::DynamicHoneyBadger;
-pub use self::error::{Error, ErrorKind, Result};
+pub use self::error::{Error, ErrorKind, FaultKind, Result};
 
 pub type Step<C, N> = ::Step<DynamicHoneyBadger<C, N>>;
 
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::dynamic_honey_badger";
+
 /// The user input for `DynamicHoneyBadger`.
 #[derive(Clone, Debug)]
 pub enum Input<C, N> {
@@ -97,33 +116,77 @@ pub enum KeyGenMessage {
     Part(Part),
     /// A `SyncKeyGen::Ack` message for key generation.
     Ack(Ack),
+    /// A `SyncKeyGen::Complaint` message for key generation.
+    Complaint(Complaint),
+    /// A `SyncKeyGen::Justification` message for key generation.
+    Justification(Justification),
+}
+
+impl KeyGenMessage {
+    /// Returns the key that identifies this message among the ones from the same sender. A
+    /// correct node sends at most one message with each key.
+    fn key(&self) -> KeyGenMsgKey {
+        match *self {
+            KeyGenMessage::Part(_) => KeyGenMsgKey::Part,
+            KeyGenMessage::Ack(ref ack) => KeyGenMsgKey::Ack(ack.proposer_index()),
+            KeyGenMessage::Complaint(ref complaint) => {
+                KeyGenMsgKey::Complaint(complaint.proposer_index())
+            }
+            KeyGenMessage::Justification(ref justification) => {
+                KeyGenMsgKey::Justification(justification.complainer_index())
+            }
+        }
+    }
+
+    /// Returns the fault a node commits by sending more than one message with this key.
+    fn duplicate_fault(&self) -> FaultKind {
+        match *self {
+            KeyGenMessage::Part(_) => FaultKind::MultipleKeyGenParts,
+            KeyGenMessage::Ack(_) => FaultKind::MultipleKeyGenAcks,
+            KeyGenMessage::Complaint(_) => FaultKind::MultipleKeyGenComplaints,
+            KeyGenMessage::Justification(_) => FaultKind::MultipleKeyGenJustifications,
+        }
+    }
+}
+
+/// Identifies a key generation message among the ones from the same sender: the kind of message,
+/// together with the index of the dealer for `Ack`s and `Complaint`s, and of the complainer for
+/// `Justification`s.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum KeyGenMsgKey {
+    Part,
+    Ack(u64),
+    Complaint(u64),
+    Justification(u64),
 }
 
 /// A message sent to or received from another node's Honey Badger instance.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Message<N: Rand> {
+pub enum Message<N> {
     /// A message belonging to the `HoneyBadger` algorithm started in the given epoch.
-    HoneyBadger(u64, HbMessage<N>),
+    HoneyBadger(HbEpoch, HbMessage<N>),
     /// A transaction to be committed, signed by a node.
-    KeyGen(u64, KeyGenMessage, Box<Signature>),
+    KeyGen(HbEpoch, KeyGenMessage, Box<Signature>),
     /// A vote to be committed, signed by a validator.
     SignedVote(SignedVote<N>),
 }
 
-impl<N: Rand> Message<N> {
-    fn start_epoch(&self) -> u64 {
+impl<N> Message<N> {
+    fn start_epoch(&self) -> HbEpoch {
         match *self {
             Message::HoneyBadger(epoch, _) => epoch,
             Message::KeyGen(epoch, _, _) => epoch,
-            Message::SignedVote(ref signed_vote) => signed_vote.era(),
+            Message::SignedVote(ref signed_vote) => HbEpoch::new(signed_vote.era()),
         }
     }
 
-    pub fn epoch(&self) -> u64 {
+    pub fn epoch(&self) -> HbEpoch {
         match *self {
-            Message::HoneyBadger(start_epoch, ref msg) => start_epoch + msg.epoch(),
+            Message::HoneyBadger(start_epoch, ref msg) => {
+                start_epoch.saturating_add(msg.epoch().get())
+            }
             Message::KeyGen(epoch, _, _) => epoch,
-            Message::SignedVote(ref signed_vote) => signed_vote.era(),
+            Message::SignedVote(ref signed_vote) => HbEpoch::new(signed_vote.era()),
         }
     }
 }
@@ -134,7 +197,7 @@ impl<N: Rand> Message<N> {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JoinPlan<N: Ord> {
     /// The first epoch the new node will observe.
-    epoch: u64,
+    epoch: HbEpoch,
     /// The current change. If `InProgress`, key generation for it is beginning at `epoch`.
     change: ChangeState<N>,
     /// The current public key set for threshold cryptography.
@@ -143,6 +206,30 @@ pub struct JoinPlan<N: Ord> {
     pub_keys: BTreeMap<N, PublicKey>,
 }
 
+impl<N: Ord> JoinPlan<N> {
+    /// Returns the first epoch the new node will observe.
+    pub fn epoch(&self) -> HbEpoch {
+        self.epoch
+    }
+
+    /// Returns the state of the validator change at the time the plan was created.
+    pub fn change(&self) -> &ChangeState<N> {
+        &self.change
+    }
+
+    /// Returns the current public key set for threshold cryptography.
+    ///
+    /// A new node should check that this matches the network's key before joining.
+    pub fn pub_key_set(&self) -> &PublicKeySet {
+        &self.pub_key_set
+    }
+
+    /// Returns the public keys of the current validators.
+    pub fn pub_keys(&self) -> &BTreeMap<N, PublicKey> {
+        &self.pub_keys
+    }
+}
+
 /// The ongoing key generation, together with information about the validator change.
 #[derive(Debug)]
 struct KeyGenState<N> {
@@ -150,20 +237,60 @@ struct KeyGenState<N> {
     key_gen: SyncKeyGen<N>,
     /// The change for which key generation is performed.
     change: Change<N>,
+    /// The validators who voted for the change.
+    voters: BTreeSet<N>,
     /// The number of key generation messages received from the candidate. At most _N² + 1_ are
     /// accepted.
     candidate_msg_count: usize,
+    /// The senders and keys of the key generation messages we received.
+    received: BTreeSet<(N, KeyGenMsgKey)>,
+    /// The key generation messages that were committed, by sender and key.
+    committed: BTreeMap<(N, KeyGenMsgKey), KeyGenMessage>,
 }
 
 impl<N: NodeIdT> KeyGenState<N> {
-    fn new(key_gen: SyncKeyGen<N>, change: Change<N>) -> Self {
+    fn new(key_gen: SyncKeyGen<N>, change: Change<N>, voters: BTreeSet<N>) -> Self {
         KeyGenState {
             key_gen,
             change,
+            voters,
             candidate_msg_count: 0,
+            received: BTreeSet::new(),
+            committed: BTreeMap::new(),
+        }
+    }
+
+    /// Records a key generation message received from `sender_id`, or returns a fault if the
+    /// sender doesn't take part in the key generation or already sent a `Part`, or an `Ack` or
+    /// `Complaint` for the same dealer, or a `Justification` for the same complainer.
+    fn check_received(&mut self, sender_id: &N, kg_msg: &KeyGenMessage) -> Option<FaultKind> {
+        if !self.key_gen.is_participant(sender_id) {
+            return Some(FaultKind::UnexpectedKeyGenSender);
+        }
+        if self.received.insert((sender_id.clone(), kg_msg.key())) {
+            None
+        } else {
+            Some(kg_msg.duplicate_fault())
         }
     }
 
+    /// Records a committed key generation message signed by `sender_id`. Returns `Ok(true)` if it
+    /// needs to be handled, and `Ok(false)` if the same message was already committed before, e.g.
+    /// because several validators included the candidate's messages in their contributions.
+    /// Returns a fault if the sender doesn't take part in the key generation, or if the message
+    /// conflicts with a message with the same key that the sender committed before.
+    fn check_committed(
+        &mut self,
+        sender_id: &N,
+        kg_msg: &KeyGenMessage,
+    ) -> Result<bool, FaultKind> {
+        if !self.key_gen.is_participant(sender_id) {
+            return Err(FaultKind::UnexpectedKeyGenSender);
+        }
+        let entry = self.committed.entry((sender_id.clone(), kg_msg.key()));
+        commit_once(entry, kg_msg, kg_msg.duplicate_fault())
+    }
+
     /// Returns `true` if the candidate's, if any, as well as enough validators' key generation
     /// parts have been completed.
     fn is_ready(&self) -> bool {
@@ -180,6 +307,37 @@ impl<N: NodeIdT> KeyGenState<N> {
     }
 }
 
+/// A change for which key generation is complete, and which takes effect once the old validators
+/// have finished the epochs that are still in progress.
+#[derive(Debug)]
+struct PendingChange<N> {
+    /// The last epoch that is produced by the old validators.
+    last_epoch: HbEpoch,
+    /// The network info for the new set of validators.
+    netinfo: NetworkInfo<N>,
+    /// The change that is being applied.
+    change: Change<N>,
+    /// The validators who voted for the change.
+    voters: BTreeSet<N>,
+}
+
+/// Inserts the committed message into the entry if it is vacant, and returns `Ok(true)`. Returns
+/// `Ok(false)` if the entry already contains the same message, and the fault otherwise.
+fn commit_once<K: Ord, T: Clone + PartialEq>(
+    entry: Entry<K, T>,
+    msg: &T,
+    fault_kind: FaultKind,
+) -> Result<bool, FaultKind> {
+    match entry {
+        Entry::Vacant(entry) => {
+            entry.insert(msg.clone());
+            Ok(true)
+        }
+        Entry::Occupied(ref entry) if entry.get() == msg => Ok(false),
+        Entry::Occupied(_) => Err(fault_kind),
+    }
+}
+
 /// The contribution for the internal `HoneyBadger` instance: this includes a user-defined
 /// application-level contribution as well as internal signed messages.
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize, Hash)]
@@ -194,4 +352,4 @@ struct InternalContrib<C, N> {
 
 /// A signed internal message.
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Hash, Clone)]
-struct SignedKeyGenMsg<N>(u64, N, KeyGenMessage, Signature);
+struct SignedKeyGenMsg<N>(HbEpoch, N, KeyGenMessage, Signature);
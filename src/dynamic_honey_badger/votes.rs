@@ -1,12 +1,12 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 
 use bincode;
 use crypto::Signature;
 use serde::{Deserialize, Serialize};
 
-use super::{Change, ErrorKind, Result};
-use fault_log::{FaultKind, FaultLog};
+use super::{Change, ErrorKind, FaultKind, Result};
+use fault_log::FaultLog;
 use {NetworkInfo, NodeIdT};
 
 /// A buffer and counter collecting pending and committed votes for validator set changes.
@@ -64,7 +64,7 @@ where
         &mut self,
         sender_id: &N,
         signed_vote: SignedVote<N>,
-    ) -> Result<FaultLog<N>> {
+    ) -> Result<FaultLog<N, FaultKind>> {
         if signed_vote.vote.era != self.era || self
             .pending
             .get(&signed_vote.voter)
@@ -97,7 +97,7 @@ where
         &mut self,
         proposer_id: &N,
         signed_votes: I,
-    ) -> Result<FaultLog<N>>
+    ) -> Result<FaultLog<N, FaultKind>>
     where
         I: IntoIterator<Item = SignedVote<N>>,
     {
@@ -113,7 +113,7 @@ where
         &mut self,
         proposer_id: &N,
         signed_vote: SignedVote<N>,
-    ) -> Result<FaultLog<N>> {
+    ) -> Result<FaultLog<N, FaultKind>> {
         if self
             .committed
             .get(&signed_vote.voter)
@@ -145,6 +145,15 @@ where
         None
     }
 
+    /// Returns the validators whose committed vote is for the given change.
+    pub fn voters(&self, change: &Change<N>) -> BTreeSet<N> {
+        self.committed
+            .iter()
+            .filter(|(_, vote)| vote.change == *change)
+            .map(|(voter, _)| voter.clone())
+            .collect()
+    }
+
     /// Returns `true` if the signature is valid.
     fn validate(&self, signed_vote: &SignedVote<N>) -> Result<bool> {
         let ser_vote = bincode::serialize(&signed_vote.vote)
@@ -189,8 +198,9 @@ mod tests {
 
     use rand;
 
+    use super::super::FaultKind;
     use super::{Change, SignedVote, VoteCounter};
-    use fault_log::{FaultKind, FaultLog};
+    use fault_log::FaultLog;
     use NetworkInfo;
 
     /// Returns a vector of `node_num` `VoteCounter`s, and some signed example votes.
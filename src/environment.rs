@@ -0,0 +1,84 @@
+//! # Restoring algorithm instances
+//!
+//! The algorithm instances implement `Restore`: Their `snapshot` is a serializable copy of their
+//! state, so that it can be persisted and survive a restart, e.g. as part of a
+//! `HoneyBadgerState`. Their environment is not part of the snapshot: The `NetworkInfo` contains
+//! our secret keys, and the crypto offload executor, the metrics receiver and the coin cache are
+//! shared with other instances. `Restore::restore` takes them from the given `Environment`
+//! instead.
+//!
+//! The snapshot only records whether an instance had a custom broadcast codec or a slow epochs
+//! hook. If so, the environment must provide them, otherwise restoring fails: Using the default
+//! codec or silently dropping the hook would change the instance's behavior.
+
+use std::sync::Arc;
+
+use binary_agreement::SlowEpochsHook;
+use broadcast::{self, Codec};
+use coin::CoinCache;
+use crypto_offload::{CryptoOffload, InlineOffload};
+use metrics::{Metrics, NoMetrics};
+use NetworkInfo;
+
+/// An error restoring an algorithm instance from a snapshot.
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum RestoreError {
+    #[fail(display = "The snapshot requires a custom broadcast codec")]
+    MissingCodec,
+    #[fail(display = "The broadcast codec has {} data and {} parity shards", _0, _1)]
+    CodecMismatch(usize, usize),
+    #[fail(display = "Failed to create the broadcast codec: {}", _0)]
+    NewCodec(broadcast::Error),
+    #[fail(display = "The snapshot requires a slow epochs hook")]
+    MissingSlowEpochsHook,
+}
+
+/// The result of restoring an algorithm instance.
+pub type Result<T> = ::std::result::Result<T, RestoreError>;
+
+/// The parts of an algorithm instance that are not in its snapshot, but provided when restoring
+/// it.
+#[derive(Clone)]
+pub struct Environment<N> {
+    /// Shared network data, including our secret keys.
+    pub netinfo: Arc<NetworkInfo<N>>,
+    /// The executor for batches of share verifications.
+    pub crypto_offload: Arc<dyn CryptoOffload>,
+    /// Receiver of measurements.
+    pub metrics: Arc<dyn Metrics>,
+    /// The cache of combined coin signatures, if any.
+    pub coin_cache: Option<Arc<CoinCache>>,
+    /// The custom codec of the `Broadcast` instances, if they had one.
+    pub codec: Option<Arc<dyn Codec>>,
+    /// The slow epochs hook of the `BinaryAgreement` instances, if they had one.
+    pub slow_epochs_hook: Option<SlowEpochsHook<N>>,
+}
+
+impl<N> Environment<N> {
+    /// Returns an environment with the given network data that verifies shares on the calling
+    /// thread, ignores measurements, doesn't cache coin signatures and has neither a custom codec
+    /// nor a slow epochs hook.
+    pub fn new(netinfo: Arc<NetworkInfo<N>>) -> Self {
+        Environment {
+            netinfo,
+            crypto_offload: Arc::new(InlineOffload),
+            metrics: Arc::new(NoMetrics),
+            coin_cache: None,
+            codec: None,
+            slow_epochs_hook: None,
+        }
+    }
+}
+
+/// An algorithm instance whose state can be saved in a serializable snapshot, and restored from
+/// it together with an `Environment`.
+pub trait Restore<N>: Sized {
+    /// The serializable state of an instance, without the parts provided by the environment.
+    type Snapshot;
+
+    /// Returns a snapshot of the instance's current state.
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Creates an instance with the snapshot's state and the environment's shared parts.
+    fn restore(snapshot: Self::Snapshot, env: &Environment<N>) -> Result<Self>;
+}
@@ -0,0 +1,108 @@
+//! # Epoch numbers
+//!
+//! Several algorithms count epochs, and they are independent of each other: A single Honey Badger
+//! epoch runs many Binary Agreement instances, each of which may take several epochs of its own.
+//! To make sure they are never mixed up, each kind of epoch has its own type: `AgreementEpoch`
+//! for Binary Agreement and `HbEpoch` for Honey Badger and the algorithms built on top of it.
+//!
+//! Both are ordered, and only support checked, saturating or explicitly panicking arithmetic, so
+//! that an overflow never silently wraps around. They serialize as a plain `u64`, so the wire
+//! format is the same as with bare integers.
+
+use std::fmt;
+
+/// Defines an epoch number type with the given name.
+macro_rules! epoch_type {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+        #[derive(Serialize, Deserialize)]
+        #[cfg_attr(feature = "testing", derive(Rand))]
+        pub struct $name(u64);
+
+        impl $name {
+            /// Returns the epoch with the given number.
+            pub fn new(epoch: u64) -> Self {
+                $name(epoch)
+            }
+
+            /// Returns the epoch's number.
+            pub fn get(self) -> u64 {
+                self.0
+            }
+
+            /// Returns the epoch `n` epochs after this one, or `None` if that would overflow.
+            pub fn checked_add(self, n: u64) -> Option<Self> {
+                self.0.checked_add(n).map($name)
+            }
+
+            /// Returns the epoch `n` epochs before this one, or `None` if that would be negative.
+            pub fn checked_sub(self, n: u64) -> Option<Self> {
+                self.0.checked_sub(n).map($name)
+            }
+
+            /// Returns the epoch `n` epochs after this one, or the last epoch if that would
+            /// overflow.
+            pub fn saturating_add(self, n: u64) -> Self {
+                $name(self.0.saturating_add(n))
+            }
+
+            /// Returns the epoch `n` epochs before this one, or the first epoch if that would be
+            /// negative.
+            pub fn saturating_sub(self, n: u64) -> Self {
+                $name(self.0.saturating_sub(n))
+            }
+
+            /// Returns the number of epochs from `earlier` to this one, or `None` if `earlier` is
+            /// actually later.
+            pub fn since(self, earlier: Self) -> Option<u64> {
+                self.0.checked_sub(earlier.0)
+            }
+
+            /// Returns the next epoch.
+            ///
+            /// # Panics
+            ///
+            /// Panics if this is the last epoch, `u64::max_value()`.
+            pub fn next(self) -> Self {
+                self.checked_add(1).expect("epoch number overflow")
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(epoch: u64) -> Self {
+                $name(epoch)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(epoch: $name) -> u64 {
+                epoch.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        // Epochs are formatted as plain numbers, so log output and error messages stay readable.
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+epoch_type! {
+    /// A Binary Agreement epoch, i.e. a round of `BVal`, `Aux`, `Conf` and coin messages.
+    AgreementEpoch
+}
+
+epoch_type! {
+    /// A Honey Badger epoch, i.e. the number of a batch. Dynamic and Queueing Honey Badger, the
+    /// sender queue and the batch verifier count the same epochs.
+    HbEpoch
+}
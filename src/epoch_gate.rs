@@ -0,0 +1,216 @@
+//! # Epoch gate
+//!
+//! Epoch-based algorithms like `HoneyBadger` only keep the state of their current epoch and a
+//! limited number of future ones. An `EpochGate` decides what to do with a message for a given
+//! epoch: Messages for earlier epochs are obsolete, messages within the window of the current
+//! epoch and the next `max_future_epochs` ones can be handled right away, and messages for later
+//! epochs are queued until the window reaches them. Optionally, the number of queued messages per
+//! sender and epoch, and the number of distinct epochs per sender, are limited, so that a faulty
+//! node can't fill our memory.
+//!
+//! When the algorithm moves on to a later epoch, `EpochGate::advance` drops the queued messages
+//! that have become obsolete, and returns the ones that can now be handled.
+//!
+//! All epoch arithmetic saturates, so that a `max_future_epochs` of `u64::max_value()` means that
+//! no message is ever postponed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+
+use validation::Validity;
+
+/// Classifies a message for `epoch`, received in epoch `current`, in which we handle messages for
+/// at most `max_future_epochs` later epochs: It is either obsolete, valid or in the future, never
+/// faulty.
+pub fn classify_epoch<F>(epoch: u64, current: u64, max_future_epochs: u64) -> Validity<F> {
+    if epoch < current {
+        Validity::Obsolete
+    } else if epoch > current.saturating_add(max_future_epochs) {
+        Validity::Future
+    } else {
+        Validity::Valid
+    }
+}
+
+/// Decides whether messages for a given epoch are handled, discarded or queued, and keeps the
+/// queued ones until their epoch is reached.
+#[derive(Debug, Clone)]
+pub struct EpochGate<N, M> {
+    /// The current epoch.
+    epoch: u64,
+    /// The number of epochs after the current one whose messages are handled right away.
+    max_future_epochs: u64,
+    /// The maximum number of messages queued per sender for any single epoch.
+    max_queued_per_epoch: Option<usize>,
+    /// The maximum number of distinct epochs for which messages are queued per sender.
+    max_queued_epochs: Option<usize>,
+    /// The messages for epochs after the window, by epoch.
+    queue: BTreeMap<u64, Vec<(N, M)>>,
+    /// The number of queued messages, by sender and epoch.
+    counts: BTreeMap<N, BTreeMap<u64, usize>>,
+    /// The total number of queued messages.
+    queued_len: usize,
+}
+
+impl<N: Ord + Clone, M> EpochGate<N, M> {
+    /// Creates a new gate in the given epoch, which lets through messages for the next
+    /// `max_future_epochs` epochs, and queues an unlimited number of messages for later ones.
+    pub fn new(epoch: u64, max_future_epochs: u64) -> Self {
+        EpochGate {
+            epoch,
+            max_future_epochs,
+            max_queued_per_epoch: None,
+            max_queued_epochs: None,
+            queue: BTreeMap::new(),
+            counts: BTreeMap::new(),
+            queued_len: 0,
+        }
+    }
+
+    /// Sets the maximum number of messages queued per sender for any single epoch. Further ones
+    /// are classified as faulty. `None` means there is no limit, which is the default.
+    pub fn set_max_queued_per_epoch(&mut self, max_queued_per_epoch: Option<usize>) {
+        self.max_queued_per_epoch = max_queued_per_epoch;
+    }
+
+    /// Sets the maximum number of distinct epochs for which messages are queued per sender.
+    /// Messages for further epochs are classified as faulty. `None` means there is no limit,
+    /// which is the default.
+    pub fn set_max_queued_epochs(&mut self, max_queued_epochs: Option<usize>) {
+        self.max_queued_epochs = max_queued_epochs;
+    }
+
+    /// Returns the current epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the number of epochs after the current one whose messages are handled right away.
+    pub fn max_future_epochs(&self) -> u64 {
+        self.max_future_epochs
+    }
+
+    /// Returns the latest epoch whose messages are handled right away.
+    pub fn max_epoch(&self) -> u64 {
+        self.epoch.saturating_add(self.max_future_epochs)
+    }
+
+    /// Classifies a message from `sender_id` for `epoch`: It is obsolete if the epoch is before
+    /// the current one, valid if it is within the window, faulty with the given `queue_full` kind
+    /// if it is after the window and the sender's queue for that epoch is full, or the sender
+    /// already has messages queued for the maximum number of other epochs, and in the future
+    /// otherwise.
+    pub fn classify<F>(&self, sender_id: &N, epoch: u64, queue_full: F) -> Validity<F> {
+        match classify_epoch(epoch, self.epoch, self.max_future_epochs) {
+            Validity::Future if self.is_queue_full(sender_id, epoch) => {
+                Validity::Faulty(queue_full)
+            }
+            validity => validity,
+        }
+    }
+
+    /// Returns `true` if no further message from `sender_id` for `epoch` can be queued.
+    fn is_queue_full(&self, sender_id: &N, epoch: u64) -> bool {
+        let sender_counts = match self.counts.get(sender_id) {
+            None => return false,
+            Some(sender_counts) => sender_counts,
+        };
+        match sender_counts.get(&epoch) {
+            Some(count) => self.max_queued_per_epoch.map_or(false, |max| *count >= max),
+            None => self
+                .max_queued_epochs
+                .map_or(false, |max| sender_counts.len() >= max),
+        }
+    }
+
+    /// Queues a message that was classified as `Validity::Future`, to be returned by `advance`
+    /// once its epoch is within the window.
+    pub fn push(&mut self, sender_id: N, epoch: u64, message: M) {
+        *self
+            .counts
+            .entry(sender_id.clone())
+            .or_insert_with(BTreeMap::new)
+            .entry(epoch)
+            .or_insert(0) += 1;
+        self.queued_len += 1;
+        self.queue
+            .entry(epoch)
+            .or_insert_with(Vec::new)
+            .push((sender_id, message));
+    }
+
+    /// Returns the number of queued messages from `sender_id` for the given epoch.
+    pub fn count_queued(&self, sender_id: &N, epoch: u64) -> usize {
+        self.counts
+            .get(sender_id)
+            .and_then(|sender_counts| sender_counts.get(&epoch))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of distinct epochs for which messages from `sender_id` are queued.
+    pub fn count_queued_epochs(&self, sender_id: &N) -> usize {
+        self.counts.get(sender_id).map_or(0, BTreeMap::len)
+    }
+
+    /// Returns the total number of queued messages.
+    pub fn queued_len(&self) -> usize {
+        self.queued_len
+    }
+
+    /// Returns the queued messages, by epoch.
+    pub fn queued(&self) -> &BTreeMap<u64, Vec<(N, M)>> {
+        &self.queue
+    }
+
+    /// Removes all queued messages from the given senders, e.g. because they are known to be
+    /// faulty.
+    pub fn remove_senders(&mut self, sender_ids: &BTreeSet<&N>) {
+        if !sender_ids.iter().any(|id| self.counts.contains_key(*id)) {
+            return;
+        }
+        for messages in self.queue.values_mut() {
+            messages.retain(|(sender_id, _)| !sender_ids.contains(sender_id));
+        }
+        for sender_id in sender_ids {
+            if let Some(sender_counts) = self.counts.remove(*sender_id) {
+                self.queued_len -= sender_counts.values().sum::<usize>();
+            }
+        }
+    }
+
+    /// Moves on to the given epoch, if it is later than the current one. Drops the queued
+    /// messages for earlier epochs, and returns the ones that are now within the window, by
+    /// epoch, in ascending order.
+    pub fn advance(&mut self, epoch: u64) -> Vec<(u64, Vec<(N, M)>)> {
+        if epoch <= self.epoch {
+            return Vec::new();
+        }
+        self.epoch = epoch;
+        self.queue = self.queue.split_off(&epoch);
+        let ready = match self.max_epoch().checked_add(1) {
+            Some(after_window) => {
+                let later = self.queue.split_off(&after_window);
+                mem::replace(&mut self.queue, later)
+            }
+            None => mem::replace(&mut self.queue, BTreeMap::new()),
+        };
+        // Only the counts for epochs after the window are still needed.
+        let after_window = self.max_epoch().checked_add(1);
+        let counts = mem::replace(&mut self.counts, BTreeMap::new());
+        for (sender_id, mut sender_counts) in counts {
+            let later = match after_window {
+                Some(after_window) => sender_counts.split_off(&after_window),
+                None => BTreeMap::new(),
+            };
+            if !later.is_empty() {
+                self.counts.insert(sender_id, later);
+            }
+        }
+        self.queued_len = self.counts.values().flat_map(BTreeMap::values).sum();
+        ready
+            .into_iter()
+            .filter(|(_, messages)| !messages.is_empty())
+            .collect()
+    }
+}
@@ -4,6 +4,11 @@
 //! Each algorithm can propogate their faulty node logs upwards to a
 //! calling algorithm via `DistAlgorihm`'s `.handle_input()` and
 //! `.handle_message()` trait methods.
+//!
+//! Every algorithm defines the kinds of faults it detects in its own `FaultKind` type, which is
+//! its `DistAlgorithm::FaultKind`. The fault kinds of an algorithm that contains nested instances
+//! of another one wrap the nested algorithm's, so that the faults of a `Broadcast` instance in
+//! `Subset` are e.g. `subset::FaultKind::Broadcast(_)`.
 
 /// A fault log entry.
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Fail)]
@@ -22,103 +27,64 @@ pub enum AckMessageFault {
     ValueInvalid,
 }
 
-/// Represents each reason why a node could be considered faulty.
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum FaultKind {
-    /// `Coin` received a signature share from an unverified sender.
-    UnverifiedSignatureShareSender,
-    /// `HoneyBadger` received a decryption share from an unverified sender.
-    UnverifiedDecryptionShareSender,
-    /// `HoneyBadger` received a decryption share for an unaccepted proposer.
-    UnexpectedDecryptionShare,
-    /// `HoneyBadger` was unable to deserialize a proposer's ciphertext.
-    InvalidCiphertext,
-    /// `HoneyBadger` was unable to decrypt a share received from a proposer.
-    ShareDecryptionFailed,
-    /// `ThresholdDecryption` received multiple shares from the same sender.
-    MultipleDecryptionShares,
-    /// `Broadcast` received a `Value` from a node other than the proposer.
-    ReceivedValueFromNonProposer,
-    /// `Broadcast` recevied an Echo message containing an invalid proof.
-    InvalidProof,
-    /// `HoneyBadger` could not deserialize bytes (i.e. a serialized Batch)
-    /// from a given proposer into a vector of transactions.
-    BatchDeserializationFailed,
-    /// `DynamicHoneyBadger` received a key generation message with an invalid
-    /// signature.
-    InvalidKeyGenMessageSignature,
-    /// `DynamicHoneyBadger` received a key generation message when there was no key generation in
-    /// progress.
-    UnexpectedKeyGenMessage,
-    /// `DynamicHoneyBadger` received more key generation messages from the candidate than expected.
-    TooManyCandidateKeyGenMessages,
-    /// `DynamicHoneyBadger` received a message (Accept, Propose, or Change)
-    /// with an invalid signature.
-    IncorrectPayloadSignature,
-    /// `DynamicHoneyBadger`/`SyncKeyGen` received an invalid Ack message.
-    AckMessage(AckMessageFault),
-    /// `DynamicHoneyBadger`/`SyncKeyGen` received an invalid Part message.
-    InvalidPartMessage,
-    /// `DynamicHoneyBadger` received a change vote with an invalid signature.
-    InvalidVoteSignature,
-    /// A validator committed an invalid vote in `DynamicHoneyBadger`.
-    InvalidCommittedVote,
-    /// `BinaryAgreement` received a duplicate `BVal` message.
-    DuplicateBVal,
-    /// `BinaryAgreement` received a duplicate `Aux` message.
-    DuplicateAux,
-}
-
 /// A structure representing the context of a faulty node. This structure
 /// describes which node is faulty (`node_id`) and which faulty behavior
 /// that the node exhibited ('kind').
 #[derive(Debug, PartialEq)]
-pub struct Fault<N> {
+pub struct Fault<N, F> {
     pub node_id: N,
-    pub kind: FaultKind,
+    pub kind: F,
 }
 
-impl<N> Fault<N> {
-    pub fn new(node_id: N, kind: FaultKind) -> Self {
+impl<N, F> Fault<N, F> {
+    pub fn new(node_id: N, kind: F) -> Self {
         Fault { node_id, kind }
     }
+
+    /// Converts the fault kind, e.g. into the fault kind of an enclosing algorithm.
+    pub fn map<F2, FF>(self, f_fault: FF) -> Fault<N, F2>
+    where
+        FF: FnOnce(F) -> F2,
+    {
+        Fault::new(self.node_id, f_fault(self.kind))
+    }
 }
 
 /// Creates a new `FaultLog` where `self` is the first element in the log
 /// vector.
-impl<N> Into<FaultLog<N>> for Fault<N> {
-    fn into(self) -> FaultLog<N> {
+impl<N, F> Into<FaultLog<N, F>> for Fault<N, F> {
+    fn into(self) -> FaultLog<N, F> {
         FaultLog(vec![self])
     }
 }
 
 /// A structure used to contain reports of faulty node behavior.
 #[derive(Debug, PartialEq)]
-pub struct FaultLog<N>(pub Vec<Fault<N>>);
+pub struct FaultLog<N, F>(pub Vec<Fault<N, F>>);
 
-impl<N> FaultLog<N> {
+impl<N, F> FaultLog<N, F> {
     /// Creates an empty `FaultLog`.
     pub fn new() -> Self {
         FaultLog::default()
     }
 
     /// Creates a new `FaultLog` initialized with a single log.
-    pub fn init(node_id: N, kind: FaultKind) -> Self {
+    pub fn init(node_id: N, kind: F) -> Self {
         Fault::new(node_id, kind).into()
     }
 
     /// Creates a new `Fault` and pushes it onto the fault log.
-    pub fn append(&mut self, node_id: N, kind: FaultKind) {
+    pub fn append(&mut self, node_id: N, kind: F) {
         self.0.push(Fault::new(node_id, kind));
     }
 
     /// Consumes `new_logs`, appending its logs onto the end of `self`.
-    pub fn extend(&mut self, new_logs: FaultLog<N>) {
+    pub fn extend(&mut self, new_logs: FaultLog<N, F>) {
         self.0.extend(new_logs.0);
     }
 
     /// Consumes `self`, appending its logs onto the end of `logs`.
-    pub fn merge_into(self, logs: &mut FaultLog<N>) {
+    pub fn merge_into(self, logs: &mut FaultLog<N, F>) {
         logs.extend(self);
     }
 
@@ -126,9 +92,17 @@ impl<N> FaultLog<N> {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Converts the fault kinds, e.g. into the fault kind of an enclosing algorithm.
+    pub fn map<F2, FF>(self, mut f_fault: FF) -> FaultLog<N, F2>
+    where
+        FF: FnMut(F) -> F2,
+    {
+        FaultLog(self.0.into_iter().map(|fault| fault.map(&mut f_fault)).collect())
+    }
 }
 
-impl<N> Default for FaultLog<N> {
+impl<N, F> Default for FaultLog<N, F> {
     fn default() -> Self {
         FaultLog(vec![])
     }
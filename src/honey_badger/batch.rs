@@ -1,15 +1,65 @@
 use std::collections::BTreeMap;
 
+use bincode;
+use crypto::{PublicKey, Signature};
+use serde::Serialize;
+use tiny_keccak::sha3_256;
+
+use epoch::HbEpoch;
 use NodeIdT;
 
 /// A batch of contributions the algorithm has output.
 #[derive(Clone, Debug)]
 pub struct Batch<C, N> {
-    pub epoch: u64,
+    /// This batch's epoch number.
+    pub epoch: HbEpoch,
+    /// The contributions included in this batch, by proposer.
     pub contributions: BTreeMap<N, C>,
+    /// The validators' threshold signature of the batch's `hash`, if batch signing is enabled.
+    /// Anyone who knows the network's public key can use it to verify the batch.
+    pub signature: Option<Signature>,
 }
 
 impl<C, N: NodeIdT> Batch<C, N> {
+    /// Returns the epoch number of this batch.
+    pub fn epoch(&self) -> HbEpoch {
+        self.epoch
+    }
+
+    /// Returns an iterator over the proposers and their contributions.
+    pub fn proposals(&self) -> impl Iterator<Item = (&N, &C)> {
+        self.contributions.iter()
+    }
+
+    /// Returns the contribution of the given proposer, if it is included in this batch.
+    pub fn contribution(&self, proposer_id: &N) -> Option<&C> {
+        self.contributions.get(proposer_id)
+    }
+
+    /// Returns the SHA3-256 hash of the serialized epoch and contributions. This is the document
+    /// that the validators sign if batch signing is enabled.
+    pub fn hash(&self) -> bincode::Result<[u8; 32]>
+    where
+        C: Serialize,
+        N: Serialize,
+    {
+        let bytes = bincode::serialize(&(self.epoch, &self.contributions))?;
+        Ok(sha3_256(&bytes))
+    }
+
+    /// Returns `true` if the batch carries a valid threshold signature by the validators with the
+    /// given public key.
+    pub fn verify_signature(&self, pk: &PublicKey) -> bool
+    where
+        C: Serialize,
+        N: Serialize,
+    {
+        match (&self.signature, self.hash()) {
+            (Some(sig), Ok(hash)) => pk.verify(sig, hash),
+            _ => false,
+        }
+    }
+
     /// Returns an iterator over references to all transactions included in the batch.
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = <&'a C as IntoIterator>::Item>
     where
@@ -1,12 +1,16 @@
 use std::collections::BTreeMap;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use rand::{self, Rand, Rng};
+use rand::{self, Rng};
 use serde::{Deserialize, Serialize};
 
-use super::HoneyBadger;
-use honey_badger::SubsetHandlingStrategy;
+use super::{HoneyBadger, HoneyBadgerState, Result, Step};
+use coin::CoinCache;
+use crypto_offload::{CryptoOffload, InlineOffload};
+use epoch_gate::EpochGate;
+use honey_badger::{EncryptionSchedule, SubsetHandlingStrategy};
+use metrics::{Metrics, NoMetrics};
 use util::SubRng;
 use {Contribution, NetworkInfo, NodeIdT};
 
@@ -20,13 +24,39 @@ pub struct HoneyBadgerBuilder<C, N> {
     rng: Box<dyn Rng>,
     /// Strategy used to handle the output of the `Subset` algorithm.
     subset_handling_strategy: SubsetHandlingStrategy,
+    /// The maximum size of a serialized contribution, in bytes.
+    contribution_size_limit: Option<usize>,
+    /// The maximum number of messages queued per sender for any single future epoch.
+    max_queued_per_epoch: Option<usize>,
+    /// The maximum number of distinct future epochs for which messages are queued per sender.
+    max_queued_epochs: Option<usize>,
+    /// The maximum number of peers that can lag behind before we stop proposing.
+    max_lagging_peers: Option<usize>,
+    /// Determines in which epochs the contributions are encrypted.
+    encryption_schedule: EncryptionSchedule,
+    /// Receiver of the instance's measurements.
+    metrics: Arc<dyn Metrics>,
+    /// The executor for batches of share verifications.
+    crypto_offload: Arc<dyn CryptoOffload>,
+    /// Whether to combine the Binary Agreement messages in each step.
+    coalesce_messages: bool,
+    /// Whether each batch is signed by the validators before it is output.
+    sign_batches: bool,
+    /// The maximum total size of the shards buffered by the broadcast instances of an epoch.
+    max_buffered_bytes: Option<usize>,
+    /// The maximum number of epochs of each Binary Agreement instance.
+    max_agreement_epochs: Option<u64>,
+    /// The cache of combined coin signatures shared by the Binary Agreement instances.
+    coin_cache: Option<Arc<CoinCache>>,
+    /// The maximum number of finished epochs whose state is kept for lagging peers.
+    retained_epochs: usize,
     _phantom: PhantomData<C>,
 }
 
 impl<C, N> HoneyBadgerBuilder<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Rand,
+    N: NodeIdT,
 {
     /// Returns a new `HoneyBadgerBuilder` configured to use the node IDs and cryptographic keys
     /// specified by `netinfo`.
@@ -36,6 +66,19 @@ where
             max_future_epochs: 3,
             rng: Box::new(rand::thread_rng()),
             subset_handling_strategy: SubsetHandlingStrategy::Incremental,
+            contribution_size_limit: None,
+            max_queued_per_epoch: None,
+            max_queued_epochs: None,
+            max_lagging_peers: None,
+            encryption_schedule: EncryptionSchedule::Always,
+            metrics: Arc::new(NoMetrics),
+            crypto_offload: Arc::new(InlineOffload),
+            coalesce_messages: false,
+            sign_batches: false,
+            max_buffered_bytes: None,
+            max_agreement_epochs: None,
+            coin_cache: None,
+            retained_epochs: 0,
             _phantom: PhantomData,
         }
     }
@@ -61,17 +104,170 @@ where
         self
     }
 
+    /// Sets the maximum size of a serialized contribution, in bytes. Larger contributions are
+    /// rejected with an error if they are our own, and their proposers reported as faulty
+    /// otherwise. By default, there is no limit.
+    ///
+    /// This must be the same in all nodes, otherwise their batches can differ.
+    pub fn contribution_size_limit(&mut self, limit: usize) -> &mut Self {
+        self.contribution_size_limit = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of messages queued per sender for any single epoch that is too
+    /// far in the future to be handled yet. Further messages are dropped and their senders
+    /// reported as faulty. By default, there is no limit.
+    ///
+    /// The limit must exceed the number of messages a correct node sends in an epoch, which grows
+    /// with the number of nodes. The queued messages of nodes that are reported as faulty are
+    /// always dropped.
+    pub fn max_queued_per_epoch(&mut self, max_queued_per_epoch: usize) -> &mut Self {
+        self.max_queued_per_epoch = Some(max_queued_per_epoch);
+        self
+    }
+
+    /// Sets the maximum number of distinct epochs, too far in the future to be handled yet, for
+    /// which messages are queued per sender. Messages for further epochs are dropped and their
+    /// senders reported as faulty. By default, there is no limit.
+    ///
+    /// The limit must exceed the number of epochs a correct node can be ahead of us beyond
+    /// `max_future_epochs`, otherwise slow nodes report fast correct ones as faulty.
+    pub fn max_queued_epochs(&mut self, max_queued_epochs: usize) -> &mut Self {
+        self.max_queued_epochs = Some(max_queued_epochs);
+        self
+    }
+
+    /// Sets the maximum number of peers that can be more than `max_future_epochs` behind us. If
+    /// more are, `propose` fails with `ErrorKind::TooManyLaggingPeers` until enough of them have
+    /// caught up, so that the fast nodes don't race ahead and leave the others with an ever
+    /// growing backlog. By default, proposals are never throttled.
+    ///
+    /// A peer's epoch is the latest one it sent us a message for, so faulty nodes that stay
+    /// silent always appear to be lagging: The limit should be at least the number of faulty
+    /// nodes.
+    pub fn max_lagging_peers(&mut self, max_lagging_peers: usize) -> &mut Self {
+        self.max_lagging_peers = Some(max_lagging_peers);
+        self
+    }
+
+    /// Sets the schedule that determines in which epochs the contributions are encrypted. By
+    /// default, they are always encrypted.
+    ///
+    /// This must be the same in all nodes.
+    pub fn encryption_schedule(&mut self, encryption_schedule: EncryptionSchedule) -> &mut Self {
+        self.encryption_schedule = encryption_schedule;
+        self
+    }
+
+    /// Sets the receiver of measurements. By default, measurements are ignored.
+    pub fn metrics(&mut self, metrics: Arc<dyn Metrics>) -> &mut Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets the executor for batches of decryption share verifications, e.g. to run them in the
+    /// application's thread pool. By default, they are verified sequentially on the calling
+    /// thread.
+    pub fn crypto_offload(&mut self, crypto_offload: Arc<dyn CryptoOffload>) -> &mut Self {
+        self.crypto_offload = crypto_offload;
+        self
+    }
+
+    /// Sets whether the Binary Agreement messages that the `Subset` instance of an epoch produces
+    /// in one step should be combined into a single message per target. This reduces the number
+    /// of messages in large networks, where many agreement instances often send at the same time.
+    /// By default, they are sent separately.
+    pub fn coalesce_messages(&mut self, coalesce_messages: bool) -> &mut Self {
+        self.coalesce_messages = coalesce_messages;
+        self
+    }
+
+    /// Sets whether each batch should carry a threshold signature of its hash by the validators,
+    /// so that light clients and other external systems can verify it without replaying
+    /// consensus. This requires an additional round of signature shares in every epoch, and the
+    /// batch is only output once it is signed. By default, batches are not signed.
+    pub fn sign_batches(&mut self, sign_batches: bool) -> &mut Self {
+        self.sign_batches = sign_batches;
+        self
+    }
+
+    /// Sets the maximum total size in bytes of the shards that the broadcast instances of an
+    /// epoch's `Subset` keep in memory. If it is exceeded, proposals that haven't reached `Ready`
+    /// and haven't been accepted yet are abandoned, and their proposers reported as faulty. By
+    /// default, there is no limit.
+    ///
+    /// The limit must be large enough for the contributions of all correct validators, otherwise
+    /// the epoch may never complete.
+    pub fn max_buffered_bytes(&mut self, max_buffered_bytes: usize) -> &mut Self {
+        self.max_buffered_bytes = Some(max_buffered_bytes);
+        self
+    }
+
+    /// Sets the maximum number of epochs of the Binary Agreement instances. If one of them hasn't
+    /// decided by then, handling the message that would move it beyond the limit fails. By
+    /// default, the Binary Agreement default applies.
+    pub fn max_agreement_epochs(&mut self, max_agreement_epochs: u64) -> &mut Self {
+        self.max_agreement_epochs = Some(max_agreement_epochs);
+        self
+    }
+
+    /// Sets a cache of combined coin signatures, shared by all Binary Agreement instances. With
+    /// it, agreement instances that are restored from a snapshot, or created again for the same
+    /// epoch, don't sign and combine the same coins twice. The cache can be shared with other
+    /// instances with the same public key set. By default, there is no cache.
+    pub fn coin_cache(&mut self, coin_cache: Arc<CoinCache>) -> &mut Self {
+        self.coin_cache = Some(coin_cache);
+        self
+    }
+
+    /// Sets the maximum number of finished epochs whose state is kept in memory after their batch
+    /// has been output, so that we keep handling the messages of peers that are still in them. A
+    /// finished epoch is dropped earlier once every validator has sent us a message that shows it
+    /// has moved past it. By default, finished epochs are dropped right away.
+    pub fn retained_epochs(&mut self, retained_epochs: usize) -> &mut Self {
+        self.retained_epochs = retained_epochs;
+        self
+    }
+
     /// Creates a new Honey Badger instance.
     pub fn build(&mut self) -> HoneyBadger<C, N> {
+        let mut gate = EpochGate::new(0, self.max_future_epochs as u64);
+        gate.set_max_queued_per_epoch(self.max_queued_per_epoch);
+        gate.set_max_queued_epochs(self.max_queued_epochs);
         HoneyBadger {
             netinfo: self.netinfo.clone(),
-            epoch: 0,
-            has_input: false,
+            proposals: BTreeMap::new(),
             epochs: BTreeMap::new(),
-            max_future_epochs: self.max_future_epochs as u64,
-            incoming_queue: BTreeMap::new(),
-            rng: Box::new(self.rng.sub_rng()),
+            gate,
+            peer_epochs: BTreeMap::new(),
+            max_lagging_peers: self.max_lagging_peers,
+            rng: Arc::new(Mutex::new(self.rng.sub_rng())),
             subset_handling_strategy: self.subset_handling_strategy.clone(),
+            contribution_size_limit: self.contribution_size_limit,
+            encryption_schedule: self.encryption_schedule,
+            metrics: self.metrics.clone(),
+            crypto_offload: self.crypto_offload.clone(),
+            coalesce_messages: self.coalesce_messages,
+            sign_batches: self.sign_batches,
+            max_buffered_bytes: self.max_buffered_bytes,
+            max_agreement_epochs: self.max_agreement_epochs,
+            coin_cache: self.coin_cache.clone(),
+            retained_epochs: self.retained_epochs,
         }
     }
+
+    /// Creates a new Honey Badger instance that resumes from a snapshot taken with
+    /// `HoneyBadger::snapshot`. The returned step contains the results of handling the queued
+    /// messages for later epochs that can now be handled.
+    ///
+    /// The builder must be configured with the same `NetworkInfo` as the instance the snapshot was
+    /// taken from.
+    pub fn build_from_snapshot(
+        &mut self,
+        state: HoneyBadgerState<C, N>,
+    ) -> Result<(HoneyBadger<C, N>, Step<C, N>)> {
+        let mut hb = self.build();
+        let step = hb.restore(state)?;
+        Ok((hb, step))
+    }
 }
@@ -6,17 +6,21 @@ use std::sync::Arc;
 
 use bincode;
 use crypto::Ciphertext;
-use rand::Rand;
 use serde::{Deserialize, Serialize};
 
-use super::{Batch, ErrorKind, MessageContent, Result, Step};
-use fault_log::{Fault, FaultKind, FaultLog};
-use subset::{self as cs, Subset, SubsetOutput};
-use threshold_decryption::{self as td, ThresholdDecryption};
+use super::{Batch, ErrorKind, FaultKind, MessageContent, Result, Step, LOG_TARGET};
+use coin::{self, Coin, CoinCache, CoinMessage, CoinSnapshot};
+use crypto_offload::CryptoOffload;
+use environment::{self, Environment, Restore};
+use epoch::HbEpoch;
+use fault_log::{Fault, FaultLog};
+use metrics::{Metrics, NoMetrics};
+use subset::{self as cs, Subset, SubsetOutput, SubsetSnapshot};
+use threshold_decryption::{self as td, ThresholdDecryption, ThresholdDecryptionSnapshot};
 use {Contribution, DistAlgorithm, NetworkInfo, NodeIdT};
 
 /// The status of an encrypted contribution.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum DecryptionState<N> {
     /// Decryption is still ongoing; we are waiting for decryption shares and/or ciphertext.
     Ongoing(Box<ThresholdDecryption<N>>),
@@ -24,13 +28,50 @@ enum DecryptionState<N> {
     Complete(Vec<u8>),
 }
 
+/// A serializable snapshot of a `DecryptionState`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+enum DecryptionSnapshot<N> {
+    Ongoing(ThresholdDecryptionSnapshot<N>),
+    Complete(Vec<u8>),
+}
+
+impl<N: NodeIdT> Restore<N> for DecryptionState<N> {
+    type Snapshot = DecryptionSnapshot<N>;
+
+    fn snapshot(&self) -> DecryptionSnapshot<N> {
+        match self {
+            DecryptionState::Ongoing(ref td) => DecryptionSnapshot::Ongoing(td.snapshot()),
+            DecryptionState::Complete(ref plaintext) => {
+                DecryptionSnapshot::Complete(plaintext.clone())
+            }
+        }
+    }
+
+    fn restore(snapshot: DecryptionSnapshot<N>, env: &Environment<N>) -> environment::Result<Self> {
+        Ok(match snapshot {
+            DecryptionSnapshot::Ongoing(td) => {
+                DecryptionState::Ongoing(Box::new(ThresholdDecryption::restore(td, env)?))
+            }
+            DecryptionSnapshot::Complete(plaintext) => DecryptionState::Complete(plaintext),
+        })
+    }
+}
+
 impl<N> DecryptionState<N>
 where
-    N: NodeIdT + Rand,
+    N: NodeIdT,
 {
     /// Creates a new `ThresholdDecryption` instance, waiting for shares and a ciphertext.
-    fn new(netinfo: Arc<NetworkInfo<N>>) -> Self {
-        DecryptionState::Ongoing(Box::new(ThresholdDecryption::new(netinfo)))
+    fn new(
+        netinfo: Arc<NetworkInfo<N>>,
+        offload: Arc<dyn CryptoOffload>,
+        metrics: Arc<dyn Metrics>,
+    ) -> Self {
+        let mut td = ThresholdDecryption::new(netinfo);
+        td.set_crypto_offload(offload);
+        td.set_metrics(metrics);
+        DecryptionState::Ongoing(Box::new(td))
     }
 
     /// Handles a message containing a decryption share.
@@ -59,17 +100,46 @@ where
 }
 
 /// The status of the subset algorithm.
-#[derive(Debug)]
-enum SubsetState<N: Rand> {
+#[derive(Clone, Debug)]
+enum SubsetState<N> {
     /// The algorithm is ongoing: the set of accepted contributions is still undecided.
     Ongoing(Subset<N>),
     /// The algorithm is complete. This contains the set of accepted proposers.
     Complete(BTreeSet<N>),
 }
 
+/// A serializable snapshot of a `SubsetState`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+enum SubsetStateSnapshot<N> {
+    Ongoing(SubsetSnapshot<N>),
+    Complete(BTreeSet<N>),
+}
+
+impl<N: NodeIdT> Restore<N> for SubsetState<N> {
+    type Snapshot = SubsetStateSnapshot<N>;
+
+    fn snapshot(&self) -> SubsetStateSnapshot<N> {
+        match self {
+            SubsetState::Ongoing(ref cs) => SubsetStateSnapshot::Ongoing(cs.snapshot()),
+            SubsetState::Complete(ref ids) => SubsetStateSnapshot::Complete(ids.clone()),
+        }
+    }
+
+    fn restore(
+        snapshot: SubsetStateSnapshot<N>,
+        env: &Environment<N>,
+    ) -> environment::Result<Self> {
+        Ok(match snapshot {
+            SubsetStateSnapshot::Ongoing(cs) => SubsetState::Ongoing(Subset::restore(cs, env)?),
+            SubsetStateSnapshot::Complete(ids) => SubsetState::Complete(ids),
+        })
+    }
+}
+
 impl<N> SubsetState<N>
 where
-    N: NodeIdT + Rand,
+    N: NodeIdT,
 {
     /// Provides input to the Subset instance, unless it has already completed.
     fn handle_input(&mut self, proposal: Vec<u8>) -> Result<cs::Step<N>> {
@@ -115,9 +185,36 @@ pub enum SubsetHandlingStrategy {
     AllAtEnd,
 }
 
+/// Determines in which epochs the contributions are encrypted before they are proposed.
+///
+/// Encryption prevents the adversary from censoring particular contributions, because the
+/// contents are unknown until the subset has been decided. Without encryption, the adversary can
+/// try to influence the subset based on the contents, but the contributions are output faster
+/// and with much less computation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionSchedule {
+    /// Encrypt the contributions in every epoch.
+    Always,
+    /// Never encrypt the contributions.
+    Never,
+    /// Encrypt the contributions only in every `n`-th epoch, starting with epoch 0.
+    EveryNthEpoch(u64),
+}
+
+impl EncryptionSchedule {
+    /// Returns `true` if the contributions in the given epoch are encrypted.
+    pub fn use_on_epoch(&self, epoch: HbEpoch) -> bool {
+        match *self {
+            EncryptionSchedule::Always => true,
+            EncryptionSchedule::Never => false,
+            EncryptionSchedule::EveryNthEpoch(n) => n != 0 && epoch.get() % n == 0,
+        }
+    }
+}
+
 /// Used in an `EpochState` to encapsulate the state necessary to maintain each
 /// `SubsetHandlingStrategy`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum SubsetHandler<N> {
     Incremental,
     AllAtEnd(Vec<(N, Vec<u8>)>),
@@ -177,10 +274,10 @@ impl<N> From<SubsetHandlingStrategy> for SubsetHandler<N> {
 }
 
 /// The sub-algorithms and their intermediate results for a single epoch.
-#[derive(Debug)]
-pub struct EpochState<C, N: Rand> {
+#[derive(Clone, Debug)]
+pub struct EpochState<C, N> {
     /// Our epoch number.
-    epoch: u64,
+    epoch: HbEpoch,
     /// Shared network data.
     netinfo: Arc<NetworkInfo<N>>,
     /// The status of the subset algorithm.
@@ -191,21 +288,111 @@ pub struct EpochState<C, N: Rand> {
     accepted_proposers: BTreeSet<N>,
     /// Determines the behavior upon receiving proposals from `subset`.
     subset_handler: SubsetHandler<N>,
+    /// The maximum size of a serialized contribution, in bytes.
+    contribution_size_limit: Option<usize>,
+    /// Whether the contributions in this epoch are encrypted.
+    require_decryption: bool,
+    /// The executor for batches of share verifications.
+    crypto_offload: Arc<dyn CryptoOffload>,
+    /// Receiver of the measurements of the sub-algorithms.
+    metrics: Arc<dyn Metrics>,
+    /// Whether the batch must be signed by the validators before it is output.
+    sign_batches: bool,
+    /// The threshold signing of the batch hash. This is started once the batch is known.
+    batch_signing: Option<Coin<N, Vec<u8>>>,
+    /// Batch signature shares received before we knew the batch, by sender.
+    early_signature_shares: BTreeMap<N, CoinMessage>,
+    _phantom: PhantomData<C>,
+}
+
+/// A serializable snapshot of an `EpochState`, including its sub-algorithms, without their
+/// environment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+pub struct EpochSnapshot<C, N> {
+    epoch: HbEpoch,
+    subset: SubsetStateSnapshot<N>,
+    decryption: BTreeMap<N, DecryptionSnapshot<N>>,
+    accepted_proposers: BTreeSet<N>,
+    subset_handler: SubsetHandler<N>,
+    contribution_size_limit: Option<usize>,
+    require_decryption: bool,
+    sign_batches: bool,
+    batch_signing: Option<CoinSnapshot<N, Vec<u8>>>,
+    early_signature_shares: BTreeMap<N, CoinMessage>,
     _phantom: PhantomData<C>,
 }
 
+impl<C, N: NodeIdT> Restore<N> for EpochState<C, N> {
+    type Snapshot = EpochSnapshot<C, N>;
+
+    fn snapshot(&self) -> EpochSnapshot<C, N> {
+        EpochSnapshot {
+            epoch: self.epoch,
+            subset: self.subset.snapshot(),
+            decryption: self
+                .decryption
+                .iter()
+                .map(|(id, state)| (id.clone(), state.snapshot()))
+                .collect(),
+            accepted_proposers: self.accepted_proposers.clone(),
+            subset_handler: self.subset_handler.clone(),
+            contribution_size_limit: self.contribution_size_limit,
+            require_decryption: self.require_decryption,
+            sign_batches: self.sign_batches,
+            batch_signing: self.batch_signing.as_ref().map(Coin::snapshot),
+            early_signature_shares: self.early_signature_shares.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn restore(snapshot: EpochSnapshot<C, N>, env: &Environment<N>) -> environment::Result<Self> {
+        let decryption = snapshot
+            .decryption
+            .into_iter()
+            .map(|(id, state)| Ok((id, DecryptionState::restore(state, env)?)))
+            .collect::<environment::Result<_>>()?;
+        let batch_signing = match snapshot.batch_signing {
+            Some(coin) => Some(Coin::restore(coin, env)?),
+            None => None,
+        };
+        Ok(EpochState {
+            epoch: snapshot.epoch,
+            netinfo: env.netinfo.clone(),
+            subset: SubsetState::restore(snapshot.subset, env)?,
+            decryption,
+            accepted_proposers: snapshot.accepted_proposers,
+            subset_handler: snapshot.subset_handler,
+            contribution_size_limit: snapshot.contribution_size_limit,
+            require_decryption: snapshot.require_decryption,
+            crypto_offload: env.crypto_offload.clone(),
+            metrics: env.metrics.clone(),
+            sign_batches: snapshot.sign_batches,
+            batch_signing,
+            early_signature_shares: snapshot.early_signature_shares,
+            _phantom: PhantomData,
+        })
+    }
+}
+
 impl<C, N> EpochState<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Rand,
+    N: NodeIdT,
 {
     /// Creates a new `Subset` instance.
     pub fn new(
         netinfo: Arc<NetworkInfo<N>>,
-        epoch: u64,
+        epoch: HbEpoch,
         subset_handling_strategy: SubsetHandlingStrategy,
+        contribution_size_limit: Option<usize>,
+        require_decryption: bool,
+        crypto_offload: Arc<dyn CryptoOffload>,
+        coalesce_messages: bool,
     ) -> Result<Self> {
-        let cs = Subset::new(netinfo.clone(), epoch).map_err(ErrorKind::CreateSubset)?;
+        let mut cs =
+            Subset::new(netinfo.clone(), epoch.get()).map_err(ErrorKind::CreateSubset)?;
+        cs.set_coalesce_messages(coalesce_messages);
         Ok(EpochState {
             epoch,
             netinfo,
@@ -213,15 +400,65 @@ where
             decryption: BTreeMap::default(),
             accepted_proposers: Default::default(),
             subset_handler: subset_handling_strategy.into(),
+            contribution_size_limit,
+            require_decryption,
+            crypto_offload,
+            metrics: Arc::new(NoMetrics),
+            sign_batches: false,
+            batch_signing: None,
+            early_signature_shares: BTreeMap::new(),
             _phantom: PhantomData,
         })
     }
 
-    /// If the instance hasn't terminated yet, inputs our encrypted contribution.
-    pub fn propose(&mut self, ciphertext: &Ciphertext) -> Result<Step<C, N>> {
-        let ser_ct =
-            bincode::serialize(ciphertext).map_err(|err| ErrorKind::ProposeBincode(*err))?;
-        let cs_step = self.subset.handle_input(ser_ct)?;
+    /// Sets whether the batch must be signed by the validators before it is output.
+    pub fn set_sign_batches(&mut self, sign_batches: bool) {
+        self.sign_batches = sign_batches;
+    }
+
+    /// Sets the receiver of measurements of the `Subset`, threshold decryption and batch signing
+    /// instances.
+    pub fn set_metrics(&mut self, metrics: &Arc<dyn Metrics>) {
+        if let SubsetState::Ongoing(ref mut cs) = self.subset {
+            cs.set_metrics(metrics);
+        }
+        for state in self.decryption.values_mut() {
+            if let DecryptionState::Ongoing(ref mut td) = state {
+                td.set_metrics(metrics.clone());
+            }
+        }
+        if let Some(ref mut coin) = self.batch_signing {
+            coin.set_metrics(metrics.clone());
+        }
+        self.metrics = metrics.clone();
+    }
+
+    /// Sets the cache of combined coin signatures of the `Subset` instance.
+    pub fn set_coin_cache(&mut self, coin_cache: &Arc<CoinCache>) {
+        if let SubsetState::Ongoing(ref mut cs) = self.subset {
+            cs.set_coin_cache(coin_cache);
+        }
+    }
+
+    /// Sets the maximum number of epochs of the `Subset` instance's Binary Agreement instances.
+    pub fn set_max_agreement_epochs(&mut self, max_epochs: u64) {
+        if let SubsetState::Ongoing(ref mut cs) = self.subset {
+            cs.set_max_agreement_epochs(max_epochs);
+        }
+    }
+
+    /// Sets the maximum total size of the shards buffered by the `Subset` instance's broadcasts.
+    pub fn set_max_buffered_bytes(&mut self, max_buffered_bytes: usize) {
+        if let SubsetState::Ongoing(ref mut cs) = self.subset {
+            cs.set_max_buffered_bytes(max_buffered_bytes);
+        }
+    }
+
+    /// If the instance hasn't terminated yet, inputs our serialized contribution, which must be
+    /// encrypted if the epoch requires decryption.
+    pub fn propose(&mut self, proposal: Vec<u8>) -> Result<Step<C, N>> {
+        enter_span!("epoch", epoch = self.epoch);
+        let cs_step = self.subset.handle_input(proposal)?;
         self.process_subset(cs_step)
     }
 
@@ -237,12 +474,21 @@ where
         sender_id: &N,
         content: MessageContent<N>,
     ) -> Result<Step<C, N>> {
+        enter_span!("epoch", epoch = self.epoch);
         match content {
             MessageContent::Subset(cs_msg) => {
                 let cs_step = self.subset.handle_message(sender_id, cs_msg)?;
                 self.process_subset(cs_step)
             }
             MessageContent::DecryptionShare { proposer_id, share } => {
+                if !self.require_decryption {
+                    let fault_kind = FaultKind::UnexpectedDecryptionShare;
+                    return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+                }
+                if !self.netinfo.is_node_validator(&proposer_id) {
+                    let fault_kind = FaultKind::UnknownProposer;
+                    return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+                }
                 if let Some(ref ids) = self.subset.accepted_ids() {
                     if !ids.contains(&proposer_id) {
                         let fault_kind = FaultKind::UnexpectedDecryptionShare;
@@ -251,19 +497,41 @@ where
                 }
                 let td_step = match self.decryption.entry(proposer_id.clone()) {
                     Entry::Occupied(entry) => entry.into_mut(),
-                    Entry::Vacant(entry) => {
-                        entry.insert(DecryptionState::new(self.netinfo.clone()))
-                    }
+                    Entry::Vacant(entry) => entry.insert(DecryptionState::new(
+                        self.netinfo.clone(),
+                        self.crypto_offload.clone(),
+                        self.metrics.clone(),
+                    )),
                 }.handle_message(sender_id, share)
                 .map_err(ErrorKind::ThresholdDecryption)?;
                 self.process_decryption(proposer_id, td_step)
             }
+            MessageContent::BatchSignatureShare(msg) => {
+                self.handle_batch_signature_share(sender_id, msg)
+            }
+        }
+    }
+
+    /// When contributions of transactions have been decrypted for all valid proposers in this
+    /// epoch, and the batch has been signed if required, returns the batch to be output.
+    pub fn try_output_batch(&self) -> Option<(Batch<C, N>, FaultLog<N, FaultKind>)> {
+        let (mut batch, fault_log) = self.decrypted_batch()?;
+        if self.sign_batches {
+            let signature = self.batch_signing.as_ref()?.signature()?;
+            batch.signature = Some(signature.clone());
         }
+        debug_event!(
+            "Epoch output",
+            node = self.netinfo.our_id(),
+            epoch = self.epoch,
+            proposers = batch.contributions.keys().collect::<Vec<_>>(),
+        );
+        Some((batch, fault_log))
     }
 
     /// When contributions of transactions have been decrypted for all valid proposers in this
-    /// epoch, moves those contributions into a batch, outputs the batch and updates the epoch.
-    pub fn try_output_batch(&self) -> Option<(Batch<C, N>, FaultLog<N>)> {
+    /// epoch, moves those contributions into an unsigned batch.
+    fn decrypted_batch(&self) -> Option<(Batch<C, N>, FaultLog<N, FaultKind>)> {
         let proposer_ids = self.subset.accepted_ids()?;
         let plaintexts: BTreeMap<N, &[u8]> = self
             .decryption
@@ -278,9 +546,17 @@ where
         let mut batch = Batch {
             epoch: self.epoch,
             contributions: BTreeMap::new(),
+            signature: None,
         };
         // Deserialize the output. If it fails, the proposer of that item is faulty.
         for (id, plaintext) in plaintexts {
+            if self
+                .contribution_size_limit
+                .map_or(false, |limit| plaintext.len() > limit)
+            {
+                fault_log.append(id, FaultKind::ContributionTooLarge);
+                continue;
+            }
             match bincode::deserialize::<C>(plaintext) {
                 Ok(contrib) => {
                     batch.contributions.insert(id, contrib);
@@ -288,25 +564,80 @@ where
                 Err(_) => fault_log.append(id, FaultKind::BatchDeserializationFailed),
             }
         }
-        debug!(
-            "{:?} Epoch {} output {:?}",
-            self.netinfo.our_id(),
-            self.epoch,
-            batch.contributions.keys().collect::<Vec<_>>()
-        );
         Some((batch, fault_log))
     }
 
+    /// Handles a signature share of the batch hash. If we don't know the batch yet, the share is
+    /// kept until we do.
+    fn handle_batch_signature_share(
+        &mut self,
+        sender_id: &N,
+        msg: CoinMessage,
+    ) -> Result<Step<C, N>> {
+        if !self.sign_batches {
+            let fault_kind = FaultKind::UnexpectedBatchSignatureShare;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        let coin_step = match self.batch_signing {
+            Some(ref mut coin) => coin.handle_message(sender_id, msg),
+            None => {
+                if !self.netinfo.is_node_validator(sender_id) {
+                    return Ok(Fault::new(sender_id.clone(), FaultKind::UnknownSender).into());
+                }
+                self.early_signature_shares.insert(sender_id.clone(), msg);
+                return Ok(Step::default());
+            }
+        }.map_err(ErrorKind::SignBatch)?;
+        Ok(self.process_batch_signing(coin_step))
+    }
+
+    /// Once all contributions are decrypted, starts signing the batch hash: Sends our signature
+    /// share and handles the shares we received earlier.
+    fn try_sign_batch(&mut self) -> Result<Step<C, N>> {
+        if !self.sign_batches || self.batch_signing.is_some() {
+            return Ok(Step::default());
+        }
+        let hash = match self.decrypted_batch() {
+            Some((batch, _)) => batch.hash().map_err(|err| ErrorKind::HashBatch(*err))?,
+            None => return Ok(Step::default()),
+        };
+        let mut coin = Coin::new(self.netinfo.clone(), hash.to_vec());
+        coin.set_metrics(self.metrics.clone());
+        let mut coin_step = coin.handle_input(()).map_err(ErrorKind::SignBatch)?;
+        let early_shares = replace(&mut self.early_signature_shares, BTreeMap::new());
+        for (sender_id, msg) in early_shares {
+            coin_step.extend(
+                coin.handle_message(&sender_id, msg)
+                    .map_err(ErrorKind::SignBatch)?,
+            );
+        }
+        self.batch_signing = Some(coin);
+        Ok(self.process_batch_signing(coin_step))
+    }
+
+    /// Converts a step of the batch signing into a Honey Badger step. The coin value itself is
+    /// ignored: Only the combined signature is used.
+    fn process_batch_signing(&self, coin_step: coin::Step<N, Vec<u8>>) -> Step<C, N> {
+        let mut step = Step::default();
+        let _: (VecDeque<bool>, _) = step.extend_with(coin_step, |msg| {
+            MessageContent::BatchSignatureShare(msg).with_epoch(self.epoch)
+        });
+        step
+    }
+
     /// Checks whether the subset has output, and if it does, sends out our decryption shares.
     fn process_subset(&mut self, cs_step: cs::Step<N>) -> Result<Step<C, N>> {
         let mut step = Step::default();
-        let cs_outputs: VecDeque<_> = step.extend_with(cs_step, |cs_msg| {
+        let (cs_outputs, _) = step.extend_with(cs_step, |cs_msg| {
             MessageContent::Subset(cs_msg).with_epoch(self.epoch)
         });
         let mut has_seen_done = false;
         for cs_output in cs_outputs {
             if has_seen_done {
-                error!("`SubsetOutput::Done` was not the last `SubsetOutput`");
+                error_event!(
+                    "`SubsetOutput::Done` was not the last `SubsetOutput`",
+                    epoch = self.epoch,
+                );
             }
 
             let SubsetHandleData {
@@ -315,7 +646,12 @@ where
             } = self.subset_handler.handle(cs_output);
 
             for (k, v) in contributions {
-                step.extend(self.send_decryption_share(k.clone(), &v)?);
+                if self.require_decryption {
+                    step.extend(self.send_decryption_share(k.clone(), &v)?);
+                } else {
+                    // The contribution is in plain text: no need to decrypt.
+                    self.decryption.insert(k.clone(), DecryptionState::Complete(v));
+                }
                 self.accepted_proposers.insert(k);
             }
 
@@ -338,13 +674,14 @@ where
                 has_seen_done = true;
             }
         }
+        step.extend(self.try_sign_batch()?);
         Ok(step)
     }
 
     /// Processes a Threshold Decryption step.
     fn process_decryption(&mut self, proposer_id: N, td_step: td::Step<N>) -> Result<Step<C, N>> {
         let mut step = Step::default();
-        let opt_output = step.extend_with(td_step, |share| {
+        let (opt_output, _) = step.extend_with(td_step, |share| {
             MessageContent::DecryptionShare {
                 proposer_id: proposer_id.clone(),
                 share,
@@ -353,6 +690,7 @@ where
         if let Some(output) = opt_output.into_iter().next() {
             self.decryption
                 .insert(proposer_id, DecryptionState::Complete(output));
+            step.extend(self.try_sign_batch()?);
         }
         Ok(step)
     }
@@ -363,21 +701,27 @@ where
         let ciphertext: Ciphertext = match bincode::deserialize(v) {
             Ok(ciphertext) => ciphertext,
             Err(err) => {
-                warn!(
-                    "Cannot deserialize ciphertext from {:?}: {:?}",
-                    proposer_id, err
+                warn_event!(
+                    "Cannot deserialize ciphertext",
+                    epoch = self.epoch,
+                    proposer = proposer_id,
+                    error = err,
                 );
                 return Ok(Fault::new(proposer_id, FaultKind::InvalidCiphertext).into());
             }
         };
         let td_result = match self.decryption.entry(proposer_id.clone()) {
             Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => entry.insert(DecryptionState::new(self.netinfo.clone())),
+            Entry::Vacant(entry) => entry.insert(DecryptionState::new(
+                self.netinfo.clone(),
+                self.crypto_offload.clone(),
+                self.metrics.clone(),
+            )),
         }.set_ciphertext(ciphertext);
         match td_result {
             Ok(td_step) => self.process_decryption(proposer_id, td_step),
             Err(td::Error::InvalidCiphertext(_)) => {
-                warn!("Invalid ciphertext from {:?}", proposer_id);
+                warn_event!("Invalid ciphertext", epoch = self.epoch, proposer = proposer_id);
                 Ok(Fault::new(proposer_id.clone(), FaultKind::ShareDecryptionFailed).into())
             }
             Err(err) => Err(ErrorKind::ThresholdDecryption(err).into()),
@@ -3,6 +3,9 @@ use std::fmt::{self, Display, Formatter};
 use bincode;
 use failure::{Backtrace, Context, Fail};
 
+use coin;
+use environment::RestoreError;
+use epoch::HbEpoch;
 use subset;
 use threshold_decryption;
 
@@ -11,6 +14,12 @@ use threshold_decryption;
 pub enum ErrorKind {
     #[fail(display = "ProposeBincode error: {}", _0)]
     ProposeBincode(bincode::ErrorKind),
+    #[fail(
+        display = "Contribution of {} bytes exceeds the limit of {} bytes",
+        _0,
+        _1
+    )]
+    ContributionTooLarge(usize, usize),
     #[fail(display = "Failed to instantiate Subset: {}", _0)]
     CreateSubset(subset::Error),
     #[fail(display = "Failed to input contribution to Subset: {}", _0)]
@@ -19,8 +28,88 @@ pub enum ErrorKind {
     HandleSubsetMessage(subset::Error),
     #[fail(display = "Threshold decryption error: {}", _0)]
     ThresholdDecryption(threshold_decryption::Error),
-    #[fail(display = "Unknown sender")]
+    #[fail(display = "Failed to hash batch: {}", _0)]
+    HashBatch(bincode::ErrorKind),
+    #[fail(display = "Batch signature error: {}", _0)]
+    SignBatch(coin::Error),
+    #[fail(display = "Too many peers are lagging behind to propose")]
+    TooManyLaggingPeers,
+    #[fail(display = "Epoch {} has already been output", _0)]
+    EpochPassed(HbEpoch),
+    #[fail(display = "Epoch {} is too far in the future to propose", _0)]
+    EpochTooFarAhead(HbEpoch),
+    #[fail(display = "Already proposed in epoch {}", _0)]
+    AlreadyProposed(HbEpoch),
+    #[fail(display = "Failed to restore the epochs' state: {}", _0)]
+    Restore(RestoreError),
+}
+
+impl ErrorKind {
+    /// Returns `true` if the instance cannot continue after this error. Contributions that are
+    /// rejected, e.g. because they are too large or for the wrong epoch, leave it unchanged.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            ErrorKind::ProposeBincode(_)
+            | ErrorKind::ContributionTooLarge(..)
+            | ErrorKind::TooManyLaggingPeers
+            | ErrorKind::EpochPassed(_)
+            | ErrorKind::EpochTooFarAhead(_)
+            | ErrorKind::AlreadyProposed(_) => false,
+            ErrorKind::InputSubset(ref err) | ErrorKind::HandleSubsetMessage(ref err) => {
+                err.is_fatal()
+            }
+            ErrorKind::ThresholdDecryption(ref err) => err.is_fatal(),
+            ErrorKind::SignBatch(ref err) => err.is_fatal(),
+            ErrorKind::CreateSubset(_) | ErrorKind::HashBatch(_) | ErrorKind::Restore(_) => true,
+        }
+    }
+}
+
+/// Represents each reason why a Honey Badger message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message was received from a node that is not a validator.
     UnknownSender,
+    /// A decryption or batch signature share referred to a proposer that is not a validator.
+    UnknownProposer,
+    /// A decryption share was received for a contribution that is not being decrypted.
+    UnexpectedDecryptionShare,
+    /// A batch signature share was received although batches are not signed.
+    UnexpectedBatchSignatureShare,
+    /// A proposer's ciphertext was invalid.
+    InvalidCiphertext,
+    /// A proposer's ciphertext could not be decrypted.
+    ShareDecryptionFailed,
+    /// A proposer's contribution could not be deserialized.
+    BatchDeserializationFailed,
+    /// A proposer's contribution exceeded the size limit.
+    ContributionTooLarge,
+    /// A node sent more messages for a future epoch than are queued.
+    TooManyQueuedMessages,
+    /// A fault in the epoch's Subset instance.
+    Subset(subset::FaultKind),
+    /// A fault in the decryption of a contribution.
+    Decryption(threshold_decryption::FaultKind),
+    /// A fault in the signing of the batch.
+    BatchSignature(coin::FaultKind),
+}
+
+impl From<subset::FaultKind> for FaultKind {
+    fn from(fault_kind: subset::FaultKind) -> FaultKind {
+        FaultKind::Subset(fault_kind)
+    }
+}
+
+impl From<threshold_decryption::FaultKind> for FaultKind {
+    fn from(fault_kind: threshold_decryption::FaultKind) -> FaultKind {
+        FaultKind::Decryption(fault_kind)
+    }
+}
+
+impl From<coin::FaultKind> for FaultKind {
+    fn from(fault_kind: coin::FaultKind) -> FaultKind {
+        FaultKind::BatchSignature(fault_kind)
+    }
 }
 
 /// A honey badger error.
@@ -43,6 +132,12 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// Returns `true` if the instance cannot continue after this error. Otherwise only the input
+    /// or message that caused it was rejected. See `ErrorKind::is_fatal`.
+    pub fn is_fatal(&self) -> bool {
+        self.kind().is_fatal()
+    }
 }
 
 impl From<ErrorKind> for Error {
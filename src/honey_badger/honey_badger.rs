@@ -1,53 +1,104 @@
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
-use std::fmt;
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::{cmp, fmt};
 
 use bincode;
-use rand::{Rand, Rng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use super::epoch_state::EpochState;
-use super::{Batch, Error, ErrorKind, HoneyBadgerBuilder, Message, MessageContent, Result};
+use super::{
+    Batch, Error, ErrorKind, FaultKind, HoneyBadgerBuilder, HoneyBadgerState, Message,
+    MessageContent, Result, LOG_TARGET,
+};
+use coin::CoinCache;
+use crypto_offload::CryptoOffload;
+use environment::{Environment, Restore};
+use epoch::HbEpoch;
+use epoch_gate::{classify_epoch, EpochGate};
+use fault_log::FaultLog;
+use metrics::{self, Metrics};
+use validation::Validity;
 use {Contribution, DistAlgorithm, NetworkInfo, NodeIdT};
 
-pub use super::epoch_state::SubsetHandlingStrategy;
+pub use super::epoch_state::{EncryptionSchedule, SubsetHandlingStrategy};
 
 /// An instance of the Honey Badger Byzantine fault tolerant consensus algorithm.
-pub struct HoneyBadger<C, N: Rand> {
+///
+/// If the contribution type is `Clone`, so is the instance.
+///
+/// If handling a message fails with a fatal error, the epoch's `Subset` instance aborts, so the
+/// instance is never left partially updated.
+#[derive(Clone)]
+pub struct HoneyBadger<C, N> {
     /// Shared network data.
     pub(super) netinfo: Arc<NetworkInfo<N>>,
-    /// The earliest epoch from which we have not yet received output.
-    pub(super) epoch: u64,
-    /// Whether we have already submitted a proposal for the current epoch.
-    pub(super) has_input: bool,
-    /// The subalgorithms for ongoing epochs.
-    pub(super) epochs: BTreeMap<u64, EpochState<C, N>>,
-    /// The maximum number of `Subset` instances that we run simultaneously.
-    pub(super) max_future_epochs: u64,
-    /// Messages for future epochs that couldn't be handled yet.
-    pub(super) incoming_queue: BTreeMap<u64, Vec<(N, MessageContent<N>)>>,
-    /// A random number generator used for secret key generation.
+    /// Our proposals for the current and future epochs, as they were input into `Subset`.
+    pub(super) proposals: BTreeMap<HbEpoch, Vec<u8>>,
+    /// The subalgorithms for ongoing epochs, and for up to `retained_epochs` finished ones.
+    pub(super) epochs: BTreeMap<HbEpoch, EpochState<C, N>>,
+    /// The earliest epoch from which we have not yet received output, the maximum number of
+    /// `Subset` instances that we run simultaneously, and the messages for later epochs that
+    /// couldn't be handled yet.
+    pub(super) gate: EpochGate<N, MessageContent<N>>,
+    /// The latest epoch in which each peer sent us a message.
+    pub(super) peer_epochs: BTreeMap<N, HbEpoch>,
+    /// The maximum number of peers that can lag behind before we stop proposing.
+    pub(super) max_lagging_peers: Option<usize>,
+    /// A random number generator used for secret key generation. It is shared by all clones of
+    /// the instance, so that they don't reuse the same random values.
     // Boxed to avoid overloading the algorithm's type with more generics.
-    pub(super) rng: Box<dyn Rng + Send + Sync>,
+    pub(super) rng: Arc<Mutex<Box<dyn Rng + Send + Sync>>>,
     /// Represents the optimization strategy to use for output of the `Subset` algorithm.
     pub(super) subset_handling_strategy: SubsetHandlingStrategy,
+    /// The maximum size of a serialized contribution, in bytes.
+    pub(super) contribution_size_limit: Option<usize>,
+    /// Determines in which epochs the contributions are encrypted.
+    pub(super) encryption_schedule: EncryptionSchedule,
+    /// Receiver of measurements.
+    pub(super) metrics: Arc<dyn Metrics>,
+    /// The executor for batches of share verifications.
+    pub(super) crypto_offload: Arc<dyn CryptoOffload>,
+    /// Whether to combine the Binary Agreement messages in each step.
+    pub(super) coalesce_messages: bool,
+    /// Whether each batch is signed by the validators before it is output.
+    pub(super) sign_batches: bool,
+    /// The maximum total size of the shards buffered by the broadcast instances of an epoch.
+    pub(super) max_buffered_bytes: Option<usize>,
+    /// The maximum number of epochs of each Binary Agreement instance.
+    pub(super) max_agreement_epochs: Option<u64>,
+    /// The cache of combined coin signatures shared by the Binary Agreement instances, if any.
+    pub(super) coin_cache: Option<Arc<CoinCache>>,
+    /// The maximum number of finished epochs whose state is kept for lagging peers.
+    pub(super) retained_epochs: usize,
 }
 
 impl<C, N> fmt::Debug for HoneyBadger<C, N>
 where
-    N: Rand + fmt::Debug,
+    N: fmt::Debug,
     C: fmt::Debug,
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("HoneyBadger")
             .field("netinfo", &self.netinfo)
-            .field("epoch", &self.epoch)
-            .field("has_input", &self.has_input)
+            .field("proposals", &self.proposals.keys().collect::<Vec<_>>())
             .field("epochs", &self.epochs)
-            .field("max_future_epochs", &self.max_future_epochs)
-            .field("incoming_queue", &self.incoming_queue)
+            .field("gate", &self.gate)
+            .field("peer_epochs", &self.peer_epochs)
+            .field("max_lagging_peers", &self.max_lagging_peers)
+            .field("contribution_size_limit", &self.contribution_size_limit)
+            .field("encryption_schedule", &self.encryption_schedule)
             .field("rng", &"<RNG>")
+            .field("metrics", &"<Metrics>")
+            .field("crypto_offload", &self.crypto_offload)
+            .field("coalesce_messages", &self.coalesce_messages)
+            .field("sign_batches", &self.sign_batches)
+            .field("max_buffered_bytes", &self.max_buffered_bytes)
+            .field("max_agreement_epochs", &self.max_agreement_epochs)
+            .field("coin_cache", &self.coin_cache)
+            .field("retained_epochs", &self.retained_epochs)
             .finish()
     }
 }
@@ -57,20 +108,29 @@ pub type Step<C, N> = ::Step<HoneyBadger<C, N>>;
 impl<C, N> DistAlgorithm for HoneyBadger<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Rand,
+    N: NodeIdT,
 {
     type NodeId = N;
     type Input = C;
     type Output = Batch<C, N>;
     type Message = Message<N>;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<C, N>> {
-        self.propose(&input)
+        enter_span!("honey_badger", node = self.netinfo.our_id(), epoch = self.epoch());
+        let step = self.propose(&input)?;
+        self.record_step(&step);
+        Ok(step)
     }
 
     fn handle_message(&mut self, sender_id: &N, message: Self::Message) -> Result<Step<C, N>> {
-        self.handle_message(sender_id, message)
+        enter_span!("honey_badger", node = self.netinfo.our_id(), epoch = self.epoch());
+        let labels = [("type", message.type_name())];
+        self.metrics.counter(metrics::MESSAGES_RECEIVED, &labels, 1);
+        let step = self.handle_message(sender_id, message)?;
+        self.record_step(&step);
+        Ok(step)
     }
 
     fn terminated(&self) -> bool {
@@ -85,7 +145,7 @@ where
 impl<C, N> HoneyBadger<C, N>
 where
     C: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Rand,
+    N: NodeIdT,
 {
     /// Returns a new `HoneyBadgerBuilder` configured to use the node IDs and cryptographic keys
     /// specified by `netinfo`.
@@ -94,20 +154,59 @@ where
     }
 
     /// Proposes a new item in the current epoch.
+    ///
+    /// This fails with `ErrorKind::AlreadyProposed` if we have already made a proposal for it.
     pub fn propose(&mut self, proposal: &C) -> Result<Step<C, N>> {
+        let epoch = self.epoch();
+        self.propose_for_epoch(epoch, proposal)
+    }
+
+    /// Proposes a new item in the given epoch, which can be the current one or one of the next
+    /// `max_future_epochs`.
+    ///
+    /// This fails with `ErrorKind::EpochPassed` if the epoch has already been output, with
+    /// `ErrorKind::EpochTooFarAhead` if it is later than that, and with
+    /// `ErrorKind::AlreadyProposed` if we have already made a proposal for it. In these cases,
+    /// the instance is unchanged.
+    pub fn propose_for_epoch(&mut self, epoch: HbEpoch, proposal: &C) -> Result<Step<C, N>> {
         if !self.netinfo.is_validator() {
             return Ok(Step::default());
         }
-        self.has_input = true;
+        let validity: Validity<FaultKind> =
+            classify_epoch(epoch.get(), self.gate.epoch(), self.max_future_epochs());
+        match validity {
+            Validity::Obsolete => return Err(ErrorKind::EpochPassed(epoch).into()),
+            Validity::Future => return Err(ErrorKind::EpochTooFarAhead(epoch).into()),
+            _ => (),
+        }
+        if self.proposals.contains_key(&epoch) {
+            return Err(ErrorKind::AlreadyProposed(epoch).into());
+        }
+        if self.is_throttled() {
+            return Err(ErrorKind::TooManyLaggingPeers.into());
+        }
         let ser_prop =
             bincode::serialize(&proposal).map_err(|err| ErrorKind::ProposeBincode(*err))?;
-        let ciphertext = self
-            .netinfo
-            .public_key_set()
-            .public_key()
-            .encrypt_with_rng(&mut self.rng, ser_prop);
-        let epoch = self.epoch;
-        let mut step = self.epoch_state_mut(epoch)?.propose(&ciphertext)?;
+        if let Some(limit) = self.contribution_size_limit {
+            if ser_prop.len() > limit {
+                return Err(ErrorKind::ContributionTooLarge(ser_prop.len(), limit).into());
+            }
+        }
+        let proposal = if self.encryption_schedule.use_on_epoch(epoch) {
+            let ciphertext = {
+                let pub_key = self.netinfo.public_key_set().public_key();
+                let mut rng = self.rng.lock().expect("RNG lock poisoned");
+                let rng = &mut *rng;
+                metrics::time_crypto(&*self.metrics, "encrypt", || {
+                    pub_key.encrypt_with_rng(rng, ser_prop)
+                })
+            };
+            bincode::serialize(&ciphertext).map_err(|err| ErrorKind::ProposeBincode(*err))?
+        } else {
+            ser_prop
+        };
+        self.proposals.insert(epoch, proposal.clone());
+        let mut step = self.epoch_state_mut(epoch)?.propose(proposal)?;
         step.extend(self.try_output_batches()?);
         Ok(step)
     }
@@ -115,48 +214,249 @@ where
     /// Handles a message received from `sender_id`.
     fn handle_message(&mut self, sender_id: &N, message: Message<N>) -> Result<Step<C, N>> {
         if !self.netinfo.is_node_validator(sender_id) {
-            return Err(ErrorKind::UnknownSender.into());
+            let validity = Validity::Faulty(FaultKind::UnknownSender);
+            return Ok(Step::validated(sender_id.clone(), validity));
+        }
+        {
+            let peer_epoch = self
+                .peer_epochs
+                .entry(sender_id.clone())
+                .or_insert_with(HbEpoch::default);
+            *peer_epoch = cmp::max(*peer_epoch, message.epoch);
+        }
+        let mut validity = self.gate.classify(
+            sender_id,
+            message.epoch.get(),
+            FaultKind::TooManyQueuedMessages,
+        );
+        if validity == Validity::Obsolete && self.epochs.contains_key(&message.epoch) {
+            // The epoch is finished, but its state is retained for the peers still working on it.
+            validity = Validity::Valid;
         }
+        let mut step = Step::validated(sender_id.clone(), validity);
         let Message { epoch, content } = message;
-        if epoch > self.epoch + self.max_future_epochs {
+        match validity {
+            Validity::Valid => {
+                step.extend(
+                    self.epoch_state_mut(epoch)?
+                        .handle_message_content(sender_id, content)?,
+                );
+                step.extend(self.try_output_batches()?);
+            }
             // Postpone handling this message.
-            self.incoming_queue
-                .entry(epoch)
-                .or_insert_with(Vec::new)
-                .push((sender_id.clone(), content));
-        } else if self.epoch <= epoch {
-            let mut step = self
-                .epoch_state_mut(epoch)?
-                .handle_message_content(sender_id, content)?;
-            step.extend(self.try_output_batches()?);
-            return Ok(step);
-        } // And ignore all messages from past epochs.
-        Ok(Step::default())
+            Validity::Future => self.gate.push(sender_id.clone(), epoch.get(), content),
+            Validity::Obsolete | Validity::Faulty(_) | Validity::Incompatible => (),
+        }
+        self.evict_faulty(&step.fault_log);
+        self.prune_epochs();
+        Ok(step)
+    }
+
+    /// Removes all queued messages sent by nodes in the fault log: Once a node is known to be
+    /// faulty, there is no point in keeping its messages for later epochs.
+    fn evict_faulty(&mut self, fault_log: &FaultLog<N, FaultKind>) {
+        if fault_log.is_empty() {
+            return;
+        }
+        let faulty: BTreeSet<&N> = fault_log.0.iter().map(|fault| &fault.node_id).collect();
+        self.gate.remove_senders(&faulty);
+    }
+
+    /// Reports the messages, outputs and faults in the step to the `Metrics` instance.
+    fn record_step(&self, step: &Step<C, N>) {
+        for msg in &step.messages {
+            let labels = [("type", msg.message.type_name())];
+            self.metrics.counter(metrics::MESSAGES_SENT, &labels, 1);
+        }
+        for batch in &step.output {
+            self.metrics.counter(metrics::EPOCHS_COMPLETED, &[], 1);
+            let contributions = batch.contributions.len() as f64;
+            self.metrics
+                .histogram(metrics::BATCH_CONTRIBUTIONS, &[], contributions);
+        }
+        if !step.output.is_empty() {
+            self.metrics
+                .gauge(metrics::CURRENT_EPOCH, &[], self.epoch().get() as f64);
+        }
+        for fault in &step.fault_log.0 {
+            let kind = format!("{:?}", fault.kind);
+            self.metrics.counter(metrics::FAULTS, &[("kind", kind.as_str())], 1);
+        }
     }
 
     /// Returns `true` if input for the current epoch has already been provided.
     pub fn has_input(&self) -> bool {
-        !self.netinfo.is_validator() || self.has_input
+        !self.netinfo.is_validator() || self.proposals.contains_key(&self.epoch())
+    }
+
+    /// Returns `true` if we have already made a proposal for the given epoch.
+    pub fn has_proposed(&self, epoch: HbEpoch) -> bool {
+        self.proposals.contains_key(&epoch)
+    }
+
+    /// Returns the earliest epoch from which we have not yet received output.
+    pub fn epoch(&self) -> HbEpoch {
+        HbEpoch::new(self.gate.epoch())
+    }
+
+    /// Returns the maximum number of future epochs for which we handle messages simultaneously.
+    pub fn max_future_epochs(&self) -> u64 {
+        self.gate.max_future_epochs()
+    }
+
+    /// Returns `true` if more peers are lagging behind than allowed, i.e. if more than the
+    /// configured number of validators haven't sent us any message for an epoch that is at most
+    /// `max_future_epochs` before ours. If so, we don't accept new proposals until they catch up.
+    pub fn is_throttled(&self) -> bool {
+        self.max_lagging_peers
+            .map_or(false, |max| self.count_lagging_peers() > max)
+    }
+
+    /// Returns the number of validators that are more than `max_future_epochs` behind us.
+    fn count_lagging_peers(&self) -> usize {
+        let our_id = self.netinfo.our_id();
+        self.netinfo
+            .all_ids()
+            .filter(|id| *id != our_id)
+            .filter(|id| {
+                let peer_epoch = self.peer_epochs.get(id).cloned().unwrap_or_default();
+                peer_epoch.saturating_add(self.max_future_epochs()) < self.epoch()
+            }).count()
+    }
+
+    /// Returns a snapshot of the state that needs to be persisted to resume after a crash. See
+    /// `HoneyBadgerState` for what it includes.
+    pub fn snapshot(&self) -> HoneyBadgerState<C, N> {
+        HoneyBadgerState {
+            epoch: self.epoch(),
+            proposals: self.proposals.clone(),
+            epochs: self
+                .epochs
+                .iter()
+                .map(|(epoch, epoch_state)| (*epoch, epoch_state.snapshot()))
+                .collect(),
+            incoming_queue: self
+                .gate
+                .queued()
+                .iter()
+                .map(|(epoch, messages)| (HbEpoch::new(*epoch), messages.clone()))
+                .collect(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Resumes from the given snapshot: Moves to its epoch, restores the epochs' state and handles
+    /// the queued messages. This must only be called on a new instance.
+    pub(super) fn restore(&mut self, state: HoneyBadgerState<C, N>) -> Result<Step<C, N>> {
+        let HoneyBadgerState {
+            epoch,
+            proposals,
+            epochs,
+            incoming_queue,
+            ..
+        } = state;
+        let mut env = Environment::new(self.netinfo.clone());
+        env.crypto_offload = self.crypto_offload.clone();
+        env.metrics = self.metrics.clone();
+        env.coin_cache = self.coin_cache.clone();
+        for (epoch, snapshot) in epochs {
+            let epoch_state = EpochState::restore(snapshot, &env).map_err(ErrorKind::Restore)?;
+            self.epochs.insert(epoch, epoch_state);
+        }
+        self.proposals = proposals;
+        self.gate.advance(epoch.get());
+        let mut step = Step::default();
+        for (msg_epoch, messages) in incoming_queue {
+            for (sender_id, content) in messages {
+                step.extend(self.handle_message(&sender_id, content.with_epoch(msg_epoch))?);
+            }
+        }
+        step.extend(self.try_output_batches()?);
+        Ok(step)
+    }
+
+    /// Moves on to the given epoch, e.g. after the batches of all earlier epochs have been obtained
+    /// out of band from other nodes: All state of the earlier epochs, including our proposals and
+    /// queued messages, is discarded, and the queued messages for the epochs that are now within
+    /// `max_future_epochs` are handled. Does nothing if we are already in that epoch or later.
+    ///
+    /// No batches are output for the skipped epochs. If the instance is wrapped in a
+    /// `SenderQueue`, the returned step must be passed through `process_step`, which announces the
+    /// new epoch to the peers.
+    pub fn skip_to_epoch(&mut self, epoch: HbEpoch) -> Result<Step<C, N>> {
+        if epoch <= self.epoch() {
+            return Ok(Step::default());
+        }
+        info_event!(
+            "Skipping epochs",
+            node = self.netinfo.our_id(),
+            from = self.epoch(),
+            to = epoch,
+        );
+        self.epochs = self.epochs.split_off(&epoch);
+        self.proposals = self.proposals.split_off(&epoch);
+        let mut step = self.advance_epoch(epoch)?;
+        step.extend(self.try_output_batches()?);
+        Ok(step)
     }
 
     /// Returns the number of validators from which we have already received a proposal for the
     /// current epoch.
     pub(crate) fn received_proposals(&self) -> usize {
         self.epochs
-            .get(&self.epoch)
+            .get(&self.epoch())
             .map_or(0, EpochState::received_proposals)
     }
 
-    /// Increments the epoch number and clears any state that is local to the finished epoch.
+    /// Increments the epoch number and clears the state of the finished epoch that is not
+    /// retained for lagging peers.
     fn update_epoch(&mut self) -> Result<Step<C, N>> {
-        // Clear the state of the old epoch.
-        self.epochs.remove(&self.epoch);
-        self.epoch += 1;
-        self.has_input = false;
-        let max_epoch = self.epoch + self.max_future_epochs;
+        let epoch = self.epoch();
+        self.proposals.remove(&epoch);
+        let step = self.advance_epoch(epoch.next())?;
+        self.prune_epochs();
+        Ok(step)
+    }
+
+    /// Drops the state of the finished epochs that every validator has moved past, and of all but
+    /// the latest `retained_epochs` finished ones.
+    fn prune_epochs(&mut self) {
+        let finished: Vec<HbEpoch> = self
+            .epochs
+            .range(..self.epoch())
+            .map(|(epoch, _)| *epoch)
+            .collect();
+        let keep_from = finished.len().saturating_sub(self.retained_epochs);
+        let min_peer_epoch = self.min_peer_epoch();
+        for (i, epoch) in finished.into_iter().enumerate() {
+            if i < keep_from || epoch < min_peer_epoch {
+                self.epochs.remove(&epoch);
+            }
+        }
+    }
+
+    /// Returns the earliest epoch that all other validators have provably reached: A correct node
+    /// only sends messages for its current epoch and the next `max_future_epochs` ones, so a
+    /// message for epoch `e` shows that its sender is at least in epoch
+    /// `e - max_future_epochs`.
+    fn min_peer_epoch(&self) -> HbEpoch {
+        let our_id = self.netinfo.our_id();
+        self.netinfo
+            .all_ids()
+            .filter(|id| *id != our_id)
+            .map(|id| {
+                let peer_epoch = self.peer_epochs.get(id).cloned().unwrap_or_default();
+                peer_epoch.saturating_sub(self.max_future_epochs())
+            }).min()
+            .unwrap_or_else(|| self.epoch())
+    }
+
+    /// Moves the gate on to the given epoch, and handles the queued messages for the epochs that
+    /// are now within `max_future_epochs`.
+    fn advance_epoch(&mut self, epoch: HbEpoch) -> Result<Step<C, N>> {
         let mut step = Step::default();
-        if let Some(messages) = self.incoming_queue.remove(&max_epoch) {
-            let epoch_state = self.epoch_state_mut(max_epoch)?;
+        for (msg_epoch, messages) in self.gate.advance(epoch.get()) {
+            let epoch_state = self.epoch_state_mut(HbEpoch::new(msg_epoch))?;
             for (sender_id, content) in messages {
                 step.extend(epoch_state.handle_message_content(&sender_id, content)?);
             }
@@ -169,7 +469,7 @@ where
         let mut step = Step::default();
         while let Some((batch, fault_log)) = self
             .epochs
-            .get(&self.epoch)
+            .get(&self.epoch())
             .and_then(EpochState::try_output_batch)
         {
             // Queue the output and advance the epoch.
@@ -182,14 +482,32 @@ where
 
     /// Returns a mutable reference to the state of the given `epoch`. Initializes a new one, if it
     /// doesn't exist yet.
-    fn epoch_state_mut(&mut self, epoch: u64) -> Result<&mut EpochState<C, N>> {
+    fn epoch_state_mut(&mut self, epoch: HbEpoch) -> Result<&mut EpochState<C, N>> {
         Ok(match self.epochs.entry(epoch) {
             Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => entry.insert(EpochState::new(
-                self.netinfo.clone(),
-                epoch,
-                self.subset_handling_strategy.clone(),
-            )?),
+            Entry::Vacant(entry) => {
+                let mut epoch_state = EpochState::new(
+                    self.netinfo.clone(),
+                    epoch,
+                    self.subset_handling_strategy.clone(),
+                    self.contribution_size_limit,
+                    self.encryption_schedule.use_on_epoch(epoch),
+                    self.crypto_offload.clone(),
+                    self.coalesce_messages,
+                )?;
+                epoch_state.set_sign_batches(self.sign_batches);
+                epoch_state.set_metrics(&self.metrics);
+                if let Some(max_buffered_bytes) = self.max_buffered_bytes {
+                    epoch_state.set_max_buffered_bytes(max_buffered_bytes);
+                }
+                if let Some(max_agreement_epochs) = self.max_agreement_epochs {
+                    epoch_state.set_max_agreement_epochs(max_agreement_epochs);
+                }
+                if let Some(ref coin_cache) = self.coin_cache {
+                    epoch_state.set_coin_cache(coin_cache);
+                }
+                entry.insert(epoch_state)
+            }
         })
     }
 }
@@ -1,11 +1,15 @@
-use rand::Rand;
 
+#[cfg(feature = "testing")]
+use rand::{Rand, Rng};
+
+use coin::CoinMessage;
+use epoch::HbEpoch;
 use subset;
 use threshold_decryption;
 
 /// The content of a `HoneyBadger` message. It should be further annotated with an epoch.
-#[derive(Clone, Debug, Deserialize, Rand, Serialize)]
-pub enum MessageContent<N: Rand> {
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum MessageContent<N> {
     /// A message belonging to the subset algorithm in the given epoch.
     Subset(subset::Message<N>),
     /// A decrypted share of the output of `proposer_id`.
@@ -13,10 +17,28 @@ pub enum MessageContent<N: Rand> {
         proposer_id: N,
         share: threshold_decryption::Message,
     },
+    /// A signature share of the epoch's batch hash, if batch signing is enabled.
+    BatchSignatureShare(CoinMessage),
+}
+
+// Random generation impls are provided for test cases, with the `testing` feature. They are
+// implemented manually, so that the node ID type only needs to implement `Rand` for testing.
+#[cfg(feature = "testing")]
+impl<N: Rand> Rand for MessageContent<N> {
+    fn rand<R: Rng>(rng: &mut R) -> Self {
+        match rng.gen_range(0, 3) {
+            0 => MessageContent::Subset(rng.gen()),
+            1 => MessageContent::DecryptionShare {
+                proposer_id: rng.gen(),
+                share: rng.gen(),
+            },
+            _ => MessageContent::BatchSignatureShare(rng.gen()),
+        }
+    }
 }
 
-impl<N: Rand> MessageContent<N> {
-    pub fn with_epoch(self, epoch: u64) -> Message<N> {
+impl<N> MessageContent<N> {
+    pub fn with_epoch(self, epoch: HbEpoch) -> Message<N> {
         Message {
             epoch,
             content: self,
@@ -25,14 +47,40 @@ impl<N: Rand> MessageContent<N> {
 }
 
 /// A message sent to or received from another node's Honey Badger instance.
-#[derive(Clone, Debug, Deserialize, Rand, Serialize)]
-pub struct Message<N: Rand> {
-    pub(super) epoch: u64,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Message<N> {
+    pub(super) epoch: HbEpoch,
     pub(super) content: MessageContent<N>,
 }
 
-impl<N: Rand> Message<N> {
-    pub fn epoch(&self) -> u64 {
+#[cfg(feature = "testing")]
+impl<N: Rand> Rand for Message<N> {
+    fn rand<R: Rng>(rng: &mut R) -> Self {
+        Message {
+            epoch: rng.gen(),
+            content: rng.gen(),
+        }
+    }
+}
+
+impl<N> Message<N> {
+    pub fn epoch(&self) -> HbEpoch {
         self.epoch
     }
+
+    /// Returns the content of the message.
+    pub fn content(&self) -> &MessageContent<N> {
+        &self.content
+    }
+
+    /// Returns a short name of the message's type, e.g. for logging and metrics.
+    pub fn type_name(&self) -> &'static str {
+        match self.content {
+            MessageContent::Subset(subset::Message::Broadcast(..)) => "broadcast",
+            MessageContent::Subset(subset::Message::BinaryAgreement(..))
+            | MessageContent::Subset(subset::Message::BinaryAgreements(..)) => "binary_agreement",
+            MessageContent::DecryptionShare { .. } => "decryption_share",
+            MessageContent::BatchSignatureShare(..) => "batch_signature_share",
+        }
+    }
 }
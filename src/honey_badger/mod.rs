@@ -8,6 +8,10 @@
 //! batch for each epoch. Each validator proposes one contribution per epoch, and every batch will
 //! contain the contributions of at least _N - f_ validators.
 //!
+//! Contributions are serialized before they are proposed. Applications that already hold their
+//! data in encoded form can use `SerializedContribution` as the contribution type: Its bytes are
+//! proposed as they are, and output unchanged in the batch.
+//!
 //! ## How it works
 //!
 //! In every epoch, every validator encrypts their contribution and proposes it to the others.
@@ -21,6 +25,23 @@
 //! encrypted. The encryption makes it harder for an attacker to try and censor a particular value
 //! by influencing the set of proposals that make it into the subset, because they don't
 //! know the decrypted values before the subset is determined.
+//!
+//! If censorship resistance is not required, the `EncryptionSchedule` can be configured to skip
+//! encryption in some or all epochs, which saves a lot of computation. In unencrypted epochs, the
+//! contributions are proposed in plain text and output as soon as the subset is determined. All
+//! validators must use the same schedule.
+//!
+//! With `sign_batches` enabled, once a node knows the epoch's batch, it signs the batch's hash
+//! with its secret key share and sends the signature share to everyone. The batch is output as
+//! soon as _f + 1_ valid shares have been combined into a threshold signature, which is included
+//! in the batch. Light clients and external systems that only know the network's public key can
+//! then use `Batch::verify_signature` to check the batch without replaying consensus.
+//!
+//! Besides the state of the current epoch and of at most `max_future_epochs` later ones, up to
+//! `retained_epochs` finished epochs are kept in memory, so that we can still answer the messages
+//! of peers that haven't completed them yet. A finished epoch's state is dropped as soon as every
+//! validator has provably moved past it, or when it is more than `retained_epochs` epochs old. By
+//! default, none are retained, and messages that arrive for a finished epoch are ignored.
 
 mod batch;
 mod builder;
@@ -28,9 +49,14 @@ mod epoch_state;
 mod error;
 mod honey_badger;
 mod message;
+mod state;
 
 pub use self::batch::Batch;
 pub use self::builder::HoneyBadgerBuilder;
-pub use self::error::{Error, ErrorKind, Result};
-pub use self::honey_badger::{HoneyBadger, Step, SubsetHandlingStrategy};
+pub use self::error::{Error, ErrorKind, FaultKind, Result};
+pub use self::honey_badger::{EncryptionSchedule, HoneyBadger, Step, SubsetHandlingStrategy};
 pub use self::message::{Message, MessageContent};
+pub use self::state::HoneyBadgerState;
+
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::honey_badger";
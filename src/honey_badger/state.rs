@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use super::epoch_state::EpochSnapshot;
+use super::MessageContent;
+use epoch::HbEpoch;
+use wire;
+
+/// A serializable snapshot of a `HoneyBadger` instance's state, created by
+/// `HoneyBadger::snapshot`, from which `HoneyBadgerBuilder::build_from_snapshot` creates a new
+/// instance, e.g. after a crash.
+///
+/// The snapshot contains the current epoch, our own proposals for it and later epochs, the full
+/// state of the ongoing epochs' sub-algorithms, and the queued messages for later epochs. So the
+/// restored instance continues exactly where the snapshot was taken, without depending on the
+/// other nodes to repeat any messages. It doesn't resend any messages either: The snapshot must
+/// only be taken after the messages of all previous steps have been sent.
+///
+/// The environment of the sub-algorithms, including our secret keys, is not part of the snapshot:
+/// it is provided by the builder when restoring. `to_bytes` and `from_bytes` use the `wire`
+/// encoding.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+pub struct HoneyBadgerState<C, N> {
+    /// The earliest epoch from which we have not yet received output.
+    pub(super) epoch: HbEpoch,
+    /// Our proposals for the current and later epochs, as they were input into `Subset`.
+    pub(super) proposals: BTreeMap<HbEpoch, Vec<u8>>,
+    /// The state of the ongoing and retained epochs.
+    pub(super) epochs: BTreeMap<HbEpoch, EpochSnapshot<C, N>>,
+    /// Messages for future epochs that couldn't be handled yet.
+    pub(super) incoming_queue: BTreeMap<HbEpoch, Vec<(N, MessageContent<N>)>>,
+    pub(super) _phantom: PhantomData<C>,
+}
+
+impl<C, N> HoneyBadgerState<C, N> {
+    /// Returns the epoch the snapshot was taken in.
+    pub fn epoch(&self) -> HbEpoch {
+        self.epoch
+    }
+
+    /// Returns `true` if we had already made a proposal in that epoch.
+    pub fn has_input(&self) -> bool {
+        self.proposals.contains_key(&self.epoch)
+    }
+
+    /// Returns the snapshot's encoding, wrapped in an envelope with the current wire version.
+    pub fn to_bytes(&self) -> wire::Result<Vec<u8>>
+    where
+        C: Serialize,
+        N: Serialize,
+    {
+        wire::encode(self)
+    }
+
+    /// Decodes a snapshot encoded with `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> wire::Result<Self>
+    where
+        N: ::NodeIdT + for<'r> Deserialize<'r>,
+    {
+        wire::decode(bytes)
+    }
+}
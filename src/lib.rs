@@ -59,6 +59,11 @@
 //! number of _transactions_, and output a sequence of batches. Each batch contains a set of
 //! transactions that were input by the nodes, and usually multiple transactions from each node.
 //!
+//! [**Sender Queue**](sender_queue/index.html)
+//!
+//! A wrapper around Honey Badger or Dynamic Honey Badger that holds back outgoing messages until
+//! their recipients have reached an epoch in which they can handle them.
+//!
 //! [**Subset**](subset/index.html)
 //!
 //! Each node inputs one item. The output is a set of at least _N - f_ nodes' IDs, together with
@@ -68,6 +73,13 @@
 //! transactions. Using the Subset protocol, they agree on at least _N - f_ of those
 //! proposals. The batch contains the union of these sets of transactions.
 //!
+//! [**Multi-valued Agreement**](mv_agreement/index.html)
+//!
+//! Each node inputs an arbitrary value. All correct nodes output the same value, which was input by
+//! one of the nodes and satisfies an optional validity predicate.
+//!
+//! This uses Subset to agree on a set of proposals, and deterministically picks one of them.
+//!
 //! [**Broadcast**](broadcast/index.html)
 //!
 //! One node, the _proposer_, inputs an item, and every node receives that item as an output. Even
@@ -92,6 +104,12 @@
 //! before at least one correct node has provided input, and is uniformly distributed and
 //! pseudorandom.
 //!
+//! [**Randomness Beacon**](randomness_beacon/index.html)
+//!
+//! Each node inputs a round number. Once _f + 1_ nodes have input the same round, all nodes that
+//! did so receive the same 32-byte random value for it. As with the coin, the value cannot be known
+//! by the adversary before at least one correct node has provided input.
+//!
 //! [**Threshold Decryption**](threshold_decryption/index.html)
 //!
 //! Each node inputs the same ciphertext, encrypted to the public master key. Once _f + 1_
@@ -107,16 +125,54 @@
 //! Unlike the other algorithms, this one is _not_ asynchronous: All nodes must handle the same
 //! messages, in the same order.
 //!
+//! ## Observers
+//!
+//! Nodes that are not validators, i.e. whose `NetworkInfo` does not contain a public key share for
+//! their own ID, are _observers_: They handle all messages and produce the same outputs as the
+//! validators, but never send any messages themselves, and their input is ignored. Messages with
+//! `Target::All` or `Target::AllExcept` must therefore be delivered to observers, too, while
+//! `Target::Observers` is only addressed to them. The set of observers is unknown to the
+//! algorithms and must be tracked by the application.
+//!
 //! ## Serialization
 //!
 //! `hbbft` supports [serde](https://serde.rs/): All message types implement the `Serialize` and
 //! `Deserialize` traits so they can be easily serialized or included as part of other serializable
 //! types.
+//!
+//! Since the format produced by a `serde` serializer is up to that serializer, the
+//! [`wire`](wire/index.html) module defines a canonical, versioned binary encoding for messages
+//! that are sent between independently developed nodes, optionally tagged with the algorithm the
+//! message belongs to.
+//!
+//! ## Light clients
+//!
+//! If Honey Badger is configured to sign its batches, clients that don't run any of the
+//! algorithms can check each batch against the validators' public keys, using the
+//! [`batch_verify`](batch_verify/index.html) module.
+//!
+//! ## Model checking
+//!
+//! With the `state-machine` feature, the [`state_machine`](state_machine/index.html) module
+//! exposes the deterministic algorithms as pure transition functions, so that external tools can
+//! explore their state space.
+//!
+//! ## Logging
+//!
+//! Events are logged via the `log` crate, or, with the `tracing` feature, as structured
+//! [`tracing`](https://docs.rs/tracing) events within spans. Each algorithm uses its own target,
+//! e.g. `hbbft::agreement`, `hbbft::broadcast`, `hbbft::subset` or `hbbft::honey_badger`, so
+//! that their log levels can be configured separately. Events contain fields such as the node
+//! ID, proposer and epoch, but never whole contributions or shards.
+//!
+//! The `disable-logging` feature removes all logging at compile time.
 
 // TODO: Remove this once https://github.com/rust-lang-nursery/error-chain/issues/245 is resolved.
 #![allow(renamed_and_removed_lints)]
 // We put algorithm structs in `src/algorithm/algorithm.rs`.
 #![cfg_attr(feature = "cargo-clippy", allow(module_inception))]
+// PyO3 requires specialization, which is only available on nightly.
+#![cfg_attr(feature = "python", feature(specialization))]
 
 extern crate bincode;
 extern crate byteorder;
@@ -124,37 +180,70 @@ extern crate byteorder;
 extern crate failure;
 extern crate hex_fmt;
 extern crate init_with;
-#[macro_use]
+// The `log` macros are only used if logging is enabled and `tracing` isn't.
+#[cfg_attr(not(any(feature = "tracing", feature = "disable-logging")), macro_use)]
 extern crate log;
+#[cfg(feature = "prometheus")]
+extern crate prometheus;
+#[cfg(feature = "python")]
+#[macro_use]
+extern crate pyo3;
 extern crate rand;
+#[cfg(feature = "testing")]
 #[macro_use]
 extern crate rand_derive;
 extern crate reed_solomon_erasure;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[cfg(feature = "compression")]
+extern crate snap;
 extern crate tiny_keccak;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
 pub extern crate threshold_crypto as crypto;
 
+#[macro_use]
+mod macros;
+mod contribution;
 mod messaging;
 mod network_info;
 mod traits;
 
+pub mod async_driver;
+pub mod batch_verify;
 pub mod binary_agreement;
 pub mod broadcast;
 pub mod coin;
+pub mod crypto_offload;
 pub mod dynamic_honey_badger;
+pub mod environment;
+pub mod epoch;
+pub mod epoch_gate;
 pub mod fault_log;
 pub mod honey_badger;
+pub mod metrics;
+pub mod mv_agreement;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod queueing_honey_badger;
+pub mod randomness_beacon;
+pub mod sender_queue;
+#[cfg(feature = "state-machine")]
+pub mod state_machine;
 pub mod subset;
 pub mod sync_key_gen;
 pub mod threshold_decryption;
 pub mod transaction_queue;
 pub mod util;
+pub mod validation;
+pub mod wire;
 
+pub use contribution::SerializedContribution;
 pub use crypto::pairing;
+pub use epoch::{AgreementEpoch, HbEpoch};
 pub use messaging::{SourcedMessage, Target, TargetedMessage};
-pub use network_info::NetworkInfo;
-pub use traits::{Contribution, DistAlgorithm, Message, NodeIdT, Step};
+pub use network_info::{NetworkInfo, NetworkInfoError, NetworkInfoSecrets, PublicNetworkInfo};
+pub use traits::{Contribution, DistAlgorithm, Message, NodeIdT, Step, TimerRequest};
+pub use wire::PROTOCOL_VERSION;
@@ -0,0 +1,91 @@
+//! Logging macros that support structured output.
+//!
+//! With the `tracing` feature, these emit [`tracing`](https://docs.rs/tracing) spans and events
+//! with structured fields, such as the node ID, epoch and proposer, which makes it possible to
+//! correlate the logs of multiple nodes. Without it, events are logged via the `log` crate, with
+//! the fields appended to the message, and spans are no-ops. With the `disable-logging` feature,
+//! all of them compile to nothing, and the field values are not evaluated.
+//!
+//! Spans and events use the target `LOG_TARGET`, which must be a constant in scope at the call
+//! site, e.g. `"hbbft::agreement"`.
+
+/// Enters a span with the given name and fields, until the end of the current block.
+///
+/// All field values must implement `Debug`.
+macro_rules! enter_span {
+    ($name:tt $(, $field:ident = $value:expr)* $(,)*) => {
+        #[cfg(all(feature = "tracing", not(feature = "disable-logging")))]
+        let _span_guard = ::tracing::span!(
+            target: LOG_TARGET,
+            ::tracing::Level::DEBUG,
+            $name
+            $(, $field = ?$value)*
+        ).entered();
+        #[cfg(any(not(feature = "tracing"), feature = "disable-logging"))]
+        let _ = LOG_TARGET;
+    };
+}
+
+/// Emits an event with the given `tracing` and `log` levels, message and fields.
+macro_rules! log_event {
+    ($tracing_level:ident, $log_level:ident, $msg:tt $(, $field:ident = $value:expr)* $(,)*) => {{
+        #[cfg(all(feature = "tracing", not(feature = "disable-logging")))]
+        ::tracing::event!(
+            target: LOG_TARGET,
+            ::tracing::Level::$tracing_level,
+            $($field = ?$value,)*
+            $msg
+        );
+        #[cfg(all(not(feature = "tracing"), not(feature = "disable-logging")))]
+        log!(
+            target: LOG_TARGET,
+            ::log::Level::$log_level,
+            concat!($msg $(, " ", stringify!($field), "={:?}")*)
+            $(, $value)*
+        );
+        // Keep the values "used", so that disabling logging doesn't cause warnings.
+        #[cfg(feature = "disable-logging")]
+        {
+            if false {
+                let _ = LOG_TARGET;
+                $(let _ = &$value;)*
+            }
+        }
+    }};
+}
+
+/// Emits a debug-level event with the given message and fields.
+///
+/// All field values must implement `Debug`.
+macro_rules! debug_event {
+    ($($args:tt)*) => {
+        log_event!(DEBUG, Debug, $($args)*)
+    };
+}
+
+/// Emits an info-level event with the given message and fields.
+///
+/// All field values must implement `Debug`.
+macro_rules! info_event {
+    ($($args:tt)*) => {
+        log_event!(INFO, Info, $($args)*)
+    };
+}
+
+/// Emits a warn-level event with the given message and fields.
+///
+/// All field values must implement `Debug`.
+macro_rules! warn_event {
+    ($($args:tt)*) => {
+        log_event!(WARN, Warn, $($args)*)
+    };
+}
+
+/// Emits an error-level event with the given message and fields.
+///
+/// All field values must implement `Debug`.
+macro_rules! error_event {
+    ($($args:tt)*) => {
+        log_event!(ERROR, Error, $($args)*)
+    };
+}
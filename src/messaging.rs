@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 /// Message sent by a given source.
 #[derive(Clone, Debug)]
 pub struct SourcedMessage<M, N> {
@@ -5,15 +7,25 @@ pub struct SourcedMessage<M, N> {
     pub message: M,
 }
 
-/// Message destination can be either of the two:
+/// Message destination can be one of the following:
+///
+/// 1) `All`: all remote nodes, validators as well as observers.
+///
+/// 2) `AllExcept(ids)`: all remote nodes except the ones in the set `ids`, validators as well as
+/// observers.
+///
+/// 3) `Node(id)`: remote node `id`.
 ///
-/// 1) `All`: all remote nodes.
+/// 4) `Nodes(ids)`: all remote nodes in the set `ids`.
 ///
-/// 2) `Node(id)`: remote node `id`.
+/// 5) `Observers`: all remote nodes that are not validators.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Target<N> {
     All,
+    AllExcept(BTreeSet<N>),
     Node(N),
+    Nodes(BTreeSet<N>),
+    Observers,
 }
 
 impl<N> Target<N> {
@@ -0,0 +1,85 @@
+//! # Metrics
+//!
+//! Instrumentation hooks for monitoring running instances. An implementation of the `Metrics`
+//! trait can be passed to the algorithms' builders, and is notified at well-defined points, e.g.
+//! whenever a message is sent or received, an epoch is completed or a fault is detected. By
+//! default, `NoMetrics` is used, which ignores all measurements.
+//!
+//! `HoneyBadger` passes its receiver on to its sub-algorithms: Messages, epochs and faults are
+//! counted by `HoneyBadger` itself, `BinaryAgreement` counts its epochs and decisions, and the
+//! duration of every threshold cryptographic operation is measured by the algorithm performing
+//! it. `DynamicHoneyBadger` and `QueueingHoneyBadger` report the measurements of their
+//! `HoneyBadger` instance. When used on their own, `Subset`, `BinaryAgreement`, `Coin` and
+//! `ThresholdDecryption` can be given a receiver with their `set_metrics` methods.
+//!
+//! The names of all reported metrics are defined as constants in this module. Where applicable,
+//! measurements are annotated with _labels_, e.g. the message type, as a list of label names and
+//! values. For each metric, the label names are always the same.
+//!
+//! With the `prometheus` feature, `PrometheusMetrics` exports all measurements to a
+//! [Prometheus](https://prometheus.io/) registry.
+
+#[cfg(feature = "prometheus")]
+mod prometheus_metrics;
+
+use std::fmt;
+use std::time::Instant;
+
+#[cfg(feature = "prometheus")]
+pub use self::prometheus_metrics::PrometheusMetrics;
+
+/// Counter: The number of messages received from other nodes. Label: `type`.
+pub const MESSAGES_RECEIVED: &str = "hbbft_messages_received_total";
+/// Counter: The number of messages sent to other nodes. Broadcast messages are counted once.
+/// Label: `type`.
+pub const MESSAGES_SENT: &str = "hbbft_messages_sent_total";
+/// Counter: The number of completed epochs.
+pub const EPOCHS_COMPLETED: &str = "hbbft_epochs_completed_total";
+/// Gauge: The current epoch.
+pub const CURRENT_EPOCH: &str = "hbbft_current_epoch";
+/// Histogram: The number of contributions in each output batch.
+pub const BATCH_CONTRIBUTIONS: &str = "hbbft_batch_contributions";
+/// Histogram: The duration of cryptographic operations, in seconds. Label: `operation`, one of
+/// `encrypt`, `decrypt_share`, `decrypt_verify`, `decrypt_combine`, `coin_sign`, `coin_verify`,
+/// `coin_combine` and `sign`, for signing a Binary Agreement termination.
+pub const CRYPTO_DURATION: &str = "hbbft_crypto_duration_seconds";
+/// Counter: The number of faults detected in other nodes. Label: `kind`.
+pub const FAULTS: &str = "hbbft_faults_total";
+/// Counter: The number of Binary Agreement epochs that were started after the first one.
+pub const AGREEMENT_EPOCHS: &str = "hbbft_agreement_epochs_total";
+/// Counter: The number of Binary Agreement instances that decided. Label: `value`.
+pub const AGREEMENT_DECISIONS: &str = "hbbft_agreement_decisions_total";
+
+/// A receiver of measurements.
+///
+/// All methods have an empty default implementation, so implementors only need to override the
+/// kinds of measurements they are interested in.
+pub trait Metrics: fmt::Debug + Send + Sync {
+    /// Increments the counter `name` by `value`.
+    fn counter(&self, _name: &'static str, _labels: &[(&'static str, &str)], _value: u64) {}
+
+    /// Sets the gauge `name` to `value`.
+    fn gauge(&self, _name: &'static str, _labels: &[(&'static str, &str)], _value: f64) {}
+
+    /// Records `value` in the histogram `name`.
+    fn histogram(&self, _name: &'static str, _labels: &[(&'static str, &str)], _value: f64) {}
+}
+
+/// A `Metrics` implementation that ignores all measurements.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+/// Runs `f` and records its duration as a `CRYPTO_DURATION` measurement for `operation`.
+pub(crate) fn time_crypto<T, F>(metrics: &dyn Metrics, operation: &str, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+    metrics.histogram(CRYPTO_DURATION, &[("operation", operation)], secs);
+    result
+}
@@ -0,0 +1,124 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use prometheus::core::Collector;
+use prometheus::{self, CounterVec, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
+
+use super::Metrics;
+
+/// The target of log messages.
+const LOG_TARGET: &str = "hbbft::metrics";
+
+/// A `Metrics` implementation that exports all measurements to a Prometheus registry.
+///
+/// The metrics are registered lazily, when they are reported for the first time.
+pub struct PrometheusMetrics {
+    registry: Registry,
+    counters: Mutex<HashMap<&'static str, CounterVec>>,
+    gauges: Mutex<HashMap<&'static str, GaugeVec>>,
+    histograms: Mutex<HashMap<&'static str, HistogramVec>>,
+}
+
+impl fmt::Debug for PrometheusMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PrometheusMetrics")
+            .field("registry", &"<Registry>")
+            .finish()
+    }
+}
+
+impl PrometheusMetrics {
+    /// Creates a new instance that registers its metrics in the given registry.
+    pub fn new(registry: Registry) -> Self {
+        PrometheusMetrics {
+            registry,
+            counters: Mutex::new(HashMap::new()),
+            gauges: Mutex::new(HashMap::new()),
+            histograms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the registry containing the metrics.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        PrometheusMetrics::new(Registry::new())
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn counter(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        let mut counters = self.counters.lock().expect("metrics lock poisoned");
+        let create = |names: &[&str]| CounterVec::new(Opts::new(name, name), names);
+        if let Some(vec) = get_or_register(&mut counters, &self.registry, name, labels, create) {
+            match vec.get_metric_with_label_values(&label_values(labels)) {
+                Ok(counter) => counter.inc_by(value as f64),
+                Err(err) => warn_event!("Invalid labels", metric = name, error = err),
+            }
+        }
+    }
+
+    fn gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut gauges = self.gauges.lock().expect("metrics lock poisoned");
+        let create = |names: &[&str]| GaugeVec::new(Opts::new(name, name), names);
+        if let Some(vec) = get_or_register(&mut gauges, &self.registry, name, labels, create) {
+            match vec.get_metric_with_label_values(&label_values(labels)) {
+                Ok(gauge) => gauge.set(value),
+                Err(err) => warn_event!("Invalid labels", metric = name, error = err),
+            }
+        }
+    }
+
+    fn histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut histograms = self.histograms.lock().expect("metrics lock poisoned");
+        let create = |names: &[&str]| HistogramVec::new(HistogramOpts::new(name, name), names);
+        if let Some(vec) = get_or_register(&mut histograms, &self.registry, name, labels, create) {
+            match vec.get_metric_with_label_values(&label_values(labels)) {
+                Ok(histogram) => histogram.observe(value),
+                Err(err) => warn_event!("Invalid labels", metric = name, error = err),
+            }
+        }
+    }
+}
+
+/// Returns the label values, in the same order as the names.
+fn label_values<'a>(labels: &[(&'static str, &'a str)]) -> Vec<&'a str> {
+    labels.iter().map(|&(_, value)| value).collect()
+}
+
+/// Returns the metric with the given name. If it doesn't exist yet, creates and registers it.
+fn get_or_register<'a, T, F>(
+    map: &'a mut HashMap<&'static str, T>,
+    registry: &Registry,
+    name: &'static str,
+    labels: &[(&'static str, &str)],
+    create: F,
+) -> Option<&'a T>
+where
+    T: Collector + Clone + 'static,
+    F: FnOnce(&[&str]) -> prometheus::Result<T>,
+{
+    match map.entry(name) {
+        Entry::Occupied(entry) => Some(entry.into_mut()),
+        Entry::Vacant(entry) => {
+            let names: Vec<&str> = labels.iter().map(|&(name, _)| name).collect();
+            let metric = match create(&names) {
+                Ok(metric) => metric,
+                Err(err) => {
+                    warn_event!("Failed to create metric", metric = name, error = err);
+                    return None;
+                }
+            };
+            if let Err(err) = registry.register(Box::new(metric.clone())) {
+                warn_event!("Failed to register metric", metric = name, error = err);
+            }
+            Some(entry.insert(metric))
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! # Multi-valued Byzantine Agreement
+//!
+//! Each node inputs an arbitrary serializable value, and all correct nodes output the same value,
+//! which was input by one of the nodes. This is useful e.g. to elect a leader or to agree on a
+//! checkpoint, without running the full Honey Badger batch machinery.
+//!
+//! The agreement can be _validated_: If a validity predicate is set, the output is guaranteed to
+//! satisfy it, as long as the inputs of all correct nodes do. In particular, if only correct nodes
+//! can produce valid values, e.g. because they need to be signed, the output is a value that was
+//! input by a correct node.
+//!
+//! ## How it works
+//!
+//! The algorithm reduces multi-valued agreement to `Subset`, i.e. to one `Broadcast` and one
+//! `BinaryAgreement` instance per node:
+//!
+//! * Every node serializes its value and inputs it into `Subset`.
+//! * `Subset` outputs the same set of at least _N - f_ proposals in every correct node. Proposals
+//! that cannot be deserialized or that don't satisfy the validity predicate are discarded, and
+//! their proposers are reported as faulty.
+//! * Starting with the node whose index is the session ID modulo _N_, and continuing in the order
+//! of the node indices, the first remaining proposal is output. Since at least _f + 1_ of the
+//! proposals in the set are from correct nodes, there always is one.
+//!
+//! Since the starting point depends on the session ID, no single faulty node can win every
+//! session.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use bincode;
+use serde::{Deserialize, Serialize};
+
+use subset::{self, Subset, SubsetOutput};
+use {Contribution, DistAlgorithm, NetworkInfo, NodeIdT};
+
+/// A multi-valued agreement error.
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Error serializing input: {}", _0)]
+    Serialize(bincode::ErrorKind),
+    #[fail(display = "Error creating Subset: {}", _0)]
+    CreateSubset(subset::Error),
+    #[fail(display = "Error handling Subset input or message: {}", _0)]
+    HandleSubset(subset::Error),
+}
+
+impl Error {
+    /// Returns `true` if the instance cannot produce its output anymore. An input that cannot be
+    /// serialized is only rejected.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::Serialize(_) => false,
+            Error::CreateSubset(_) => true,
+            Error::HandleSubset(ref err) => err.is_fatal(),
+        }
+    }
+}
+
+/// Represents each reason why a multi-valued agreement message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// An accepted proposal could not be deserialized or doesn't satisfy the validity predicate.
+    InvalidMvAgreementValue,
+    /// A fault in the Subset instance.
+    Subset(subset::FaultKind),
+}
+
+impl From<subset::FaultKind> for FaultKind {
+    fn from(fault_kind: subset::FaultKind) -> FaultKind {
+        FaultKind::Subset(fault_kind)
+    }
+}
+
+/// A multi-valued agreement result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A message sent to or received from another node's instance.
+pub type Message<N> = subset::Message<N>;
+
+pub type Step<V, N> = ::Step<MvAgreement<V, N>>;
+
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::mv_agreement";
+
+/// A predicate that all correct nodes' input values satisfy.
+type ValidityPredicate<V> = Box<dyn Fn(&V) -> bool + Send + Sync>;
+
+/// A multi-valued agreement instance. On input, proposes the value to the other nodes. Once the
+/// set of accepted proposals is known, outputs one of them and terminates.
+pub struct MvAgreement<V, N> {
+    /// Shared network information.
+    netinfo: Arc<NetworkInfo<N>>,
+    /// The session identifier. It determines the node whose proposal is preferred.
+    session_id: u64,
+    /// The `Subset` instance that decides which proposals are accepted.
+    subset: Subset<N>,
+    /// The accepted proposals that are valid, by proposer.
+    proposals: BTreeMap<N, V>,
+    /// The predicate that valid proposals need to satisfy, if any.
+    validity_predicate: Option<ValidityPredicate<V>>,
+    /// Whether we have already proposed a value.
+    had_input: bool,
+    /// Whether we have already output a value.
+    decided: bool,
+}
+
+impl<V: Debug, N: NodeIdT> Debug for MvAgreement<V, N> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("MvAgreement")
+            .field("netinfo", &self.netinfo)
+            .field("session_id", &self.session_id)
+            .field("subset", &self.subset)
+            .field("proposals", &self.proposals)
+            .field("validity_predicate", &self.validity_predicate.is_some())
+            .field("had_input", &self.had_input)
+            .field("decided", &self.decided)
+            .finish()
+    }
+}
+
+impl<V, N> DistAlgorithm for MvAgreement<V, N>
+where
+    V: Contribution + Serialize + for<'r> Deserialize<'r>,
+    N: NodeIdT,
+{
+    type NodeId = N;
+    type Input = V;
+    type Output = V;
+    type Message = Message<N>;
+    type Error = Error;
+    type FaultKind = FaultKind;
+
+    /// Proposes our value, if we haven't done so yet.
+    fn handle_input(&mut self, input: V) -> Result<Step<V, N>> {
+        if self.had_input {
+            return Ok(Step::default());
+        }
+        self.had_input = true;
+        let ser_input = bincode::serialize(&input).map_err(|err| Error::Serialize(*err))?;
+        let subset_step = self
+            .subset
+            .handle_input(ser_input)
+            .map_err(Error::HandleSubset)?;
+        Ok(self.process_subset(subset_step))
+    }
+
+    /// Handles a message received from `sender_id`.
+    fn handle_message(&mut self, sender_id: &N, message: Message<N>) -> Result<Step<V, N>> {
+        let subset_step = self
+            .subset
+            .handle_message(sender_id, message)
+            .map_err(Error::HandleSubset)?;
+        Ok(self.process_subset(subset_step))
+    }
+
+    fn terminated(&self) -> bool {
+        self.decided && self.subset.terminated()
+    }
+
+    fn our_id(&self) -> &N {
+        self.netinfo.our_id()
+    }
+}
+
+impl<V, N> MvAgreement<V, N>
+where
+    V: Contribution + Serialize + for<'r> Deserialize<'r>,
+    N: NodeIdT,
+{
+    /// Creates a new multi-valued agreement instance with the given session identifier, which
+    /// must be unique among all instances that use the same network keys.
+    pub fn new(netinfo: Arc<NetworkInfo<N>>, session_id: u64) -> Result<Self> {
+        let subset = Subset::new(netinfo.clone(), session_id).map_err(Error::CreateSubset)?;
+        Ok(MvAgreement {
+            netinfo,
+            session_id,
+            subset,
+            proposals: BTreeMap::new(),
+            validity_predicate: None,
+            had_input: false,
+            decided: false,
+        })
+    }
+
+    /// Sets the predicate that valid proposals need to satisfy. Proposals that don't are
+    /// discarded, and their proposers reported as faulty. By default, every value is valid.
+    ///
+    /// All nodes must use the same predicate.
+    pub fn set_validity_predicate<F>(&mut self, predicate: F)
+    where
+        F: Fn(&V) -> bool + Send + Sync + 'static,
+    {
+        self.validity_predicate = Some(Box::new(predicate));
+    }
+
+    /// Returns `true` if the value satisfies the validity predicate.
+    fn is_valid(&self, value: &V) -> bool {
+        self.validity_predicate
+            .as_ref()
+            .map_or(true, |predicate| predicate(value))
+    }
+
+    /// Converts the `Subset` step, records the valid proposals and, once the subset is complete,
+    /// outputs the chosen one.
+    fn process_subset(&mut self, subset_step: subset::Step<N>) -> Step<V, N> {
+        let mut step: Step<V, N> = Step::default();
+        let (output, _) = step.extend_with(subset_step, |msg| msg);
+        for output in output {
+            match output {
+                SubsetOutput::Contribution(proposer_id, ser_value) => {
+                    let opt_value = bincode::deserialize::<V>(&ser_value)
+                        .ok()
+                        .filter(|value| self.is_valid(value));
+                    if let Some(value) = opt_value {
+                        self.proposals.insert(proposer_id, value);
+                    } else {
+                        let fault_kind = FaultKind::InvalidMvAgreementValue;
+                        step.fault_log.append(proposer_id, fault_kind);
+                    }
+                }
+                SubsetOutput::Done => {
+                    if !self.decided {
+                        self.decided = true;
+                        step.output.extend(self.take_chosen_proposal());
+                    }
+                }
+            }
+        }
+        step
+    }
+
+    /// Removes and returns the proposal of the first accepted proposer, starting with the node
+    /// whose index is the session ID modulo the number of nodes.
+    fn take_chosen_proposal(&mut self) -> Option<V> {
+        let chosen_id = {
+            let ids: Vec<&N> = self.netinfo.all_ids().collect();
+            let start = (self.session_id % ids.len() as u64) as usize;
+            let proposals = &self.proposals;
+            (0..ids.len())
+                .map(|i| ids[(start + i) % ids.len()])
+                .find(|id| proposals.contains_key(id))
+                .cloned()
+        };
+        debug_event!(
+            "Chosen proposal",
+            node = self.netinfo.our_id(),
+            proposer = chosen_id,
+        );
+        chosen_id.and_then(|id| self.proposals.remove(&id))
+    }
+}
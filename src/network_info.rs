@@ -1,16 +1,41 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use crypto::{self, PublicKey, PublicKeySet, PublicKeyShare, SecretKey, SecretKeyShare};
+use crypto::{PublicKey, PublicKeySet, PublicKeyShare, SecretKey, SecretKeyShare};
 use rand;
 
 use NodeIdT;
 
+/// An error generating `NetworkInfo`s.
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum NetworkInfoError {
+    #[fail(display = "At least one node ID is required")]
+    NoNodes,
+    #[fail(display = "Threshold {} is invalid for {} nodes", _0, _1)]
+    InvalidThreshold(usize, usize),
+    #[fail(display = "Every validator must have a positive weight")]
+    InvalidWeights,
+    #[fail(display = "The secret keys don't match the public keys")]
+    KeyMismatch,
+}
+
 /// Common data shared between algorithms: the nodes' IDs and key shares.
+///
+/// Validators can have different weights, i.e. shares of the voting power: The quorums in the
+/// algorithms are then computed from the total weight _W_ instead of the number of nodes, and they
+/// are guaranteed to be correct as long as the faulty validators' total weight is at most
+/// _f = (W - 1) / 3_. By default, every validator has weight 1.
+///
+/// To survive restarts, a node can persist the serializable `public_info`, and, separately and
+/// with the necessary care, the `NetworkInfoSecrets` that only `export_secrets` returns. Then
+/// `from_parts` recreates the `NetworkInfo` from both.
 #[derive(Debug, Clone)]
 pub struct NetworkInfo<N> {
     our_id: N,
     num_nodes: usize,
     num_faulty: usize,
+    weights: BTreeMap<N, u64>,
+    total_weight: u64,
+    faulty_weight: u64,
     is_validator: bool,
     // TODO: Should this be an option? It only makes sense for validators.
     secret_key_share: SecretKeyShare,
@@ -22,6 +47,12 @@ pub struct NetworkInfo<N> {
 }
 
 impl<N: NodeIdT> NetworkInfo<N> {
+    /// Creates a new `NetworkInfo` for the node with ID `our_id`.
+    ///
+    /// The validators are the keys of `public_keys`. If `our_id` is not among them, the node is
+    /// only an observer, and its secret key share is ignored. The key set and shares are usually
+    /// the output of a key generation, e.g. `SyncKeyGen`. The same arguments always produce the
+    /// same `NetworkInfo`, so this can also be used to restore it after a restart.
     pub fn new(
         our_id: N,
         secret_key_share: SecretKeyShare,
@@ -40,10 +71,15 @@ impl<N: NodeIdT> NetworkInfo<N> {
             .iter()
             .map(|(id, idx)| (id.clone(), public_key_set.public_key_share(*idx)))
             .collect();
+        let weights = public_keys.keys().map(|id| (id.clone(), 1)).collect();
+        let num_faulty = (num_nodes - 1) / 3;
         NetworkInfo {
             our_id,
             num_nodes,
-            num_faulty: (num_nodes - 1) / 3,
+            num_faulty,
+            weights,
+            total_weight: num_nodes as u64,
+            faulty_weight: num_faulty as u64,
             is_validator,
             secret_key_share,
             secret_key,
@@ -71,6 +107,9 @@ impl<N: NodeIdT> NetworkInfo<N> {
 
     /// The maximum number _f_ of faulty, Byzantine nodes up to which Honey Badger is guaranteed to
     /// be correct.
+    ///
+    /// With weighted validators, this is the largest number of validators whose total weight is at
+    /// most `faulty_weight`.
     pub fn num_faulty(&self) -> usize {
         self.num_faulty
     }
@@ -81,6 +120,62 @@ impl<N: NodeIdT> NetworkInfo<N> {
         self.num_nodes - self.num_faulty
     }
 
+    /// Sets the validators' weights, and returns the updated `NetworkInfo`.
+    ///
+    /// Every validator must have a positive weight. Since the faulty nodes must not be able to sign
+    /// or decrypt on their own, and the correct ones must be, the key set's threshold must be at
+    /// least the new `num_faulty` and less than `num_correct`: see `generate_weighted_map`.
+    pub fn with_weights(mut self, weights: BTreeMap<N, u64>) -> Result<Self, NetworkInfoError> {
+        let is_valid = |(id, w): (&N, &u64)| *w > 0 && self.public_keys.contains_key(id);
+        if weights.len() != self.num_nodes || !weights.iter().all(is_valid) {
+            return Err(NetworkInfoError::InvalidWeights);
+        }
+        let total_weight = weights.values().fold(0, |sum: u64, w| sum.saturating_add(*w));
+        let faulty_weight = (total_weight - 1) / 3;
+        let num_faulty = max_faulty_nodes(&weights, faulty_weight);
+        let threshold = self.public_key_set.threshold();
+        if threshold < num_faulty || threshold >= self.num_nodes - num_faulty {
+            return Err(NetworkInfoError::InvalidThreshold(threshold, self.num_nodes));
+        }
+        self.weights = weights;
+        self.total_weight = total_weight;
+        self.faulty_weight = faulty_weight;
+        self.num_faulty = num_faulty;
+        Ok(self)
+    }
+
+    /// Returns the weight of the given node, or `0` if it is not a validator.
+    pub fn node_weight(&self, id: &N) -> u64 {
+        self.weights.get(id).cloned().unwrap_or(0)
+    }
+
+    /// Returns the total weight of the given nodes. Nodes that are not validators are ignored.
+    pub fn weight_of<'a, I>(&self, ids: I) -> u64
+    where
+        I: IntoIterator<Item = &'a N>,
+        N: 'a,
+    {
+        ids.into_iter()
+            .fold(0, |sum: u64, id| sum.saturating_add(self.node_weight(id)))
+    }
+
+    /// The total weight _W_ of all validators. Without weights, this is _N_.
+    pub fn total_weight(&self) -> u64 {
+        self.total_weight
+    }
+
+    /// The maximum total weight _f_ of faulty validators up to which Honey Badger is guaranteed to
+    /// be correct. Without weights, this is `num_faulty`.
+    pub fn faulty_weight(&self) -> u64 {
+        self.faulty_weight
+    }
+
+    /// The minimum total weight _W - f_ of correct validators with which Honey Badger is
+    /// guaranteed to be correct. Without weights, this is `num_correct`.
+    pub fn correct_weight(&self) -> u64 {
+        self.total_weight - self.faulty_weight
+    }
+
     /// Returns our secret key share for threshold cryptography.
     pub fn secret_key_share(&self) -> &SecretKeyShare {
         &self.secret_key_share
@@ -143,11 +238,138 @@ impl<N: NodeIdT> NetworkInfo<N> {
         self.public_keys.contains_key(id)
     }
 
+    /// Returns the serializable public part of this `NetworkInfo`: our ID, the validators' public
+    /// keys and weights, and the public key set. It doesn't contain any secret keys.
+    pub fn public_info(&self) -> PublicNetworkInfo<N> {
+        let is_weighted = self.weights.values().any(|w| *w != 1);
+        PublicNetworkInfo {
+            our_id: self.our_id.clone(),
+            public_key_set: self.public_key_set.clone(),
+            public_keys: self.public_keys.clone(),
+            weights: if is_weighted {
+                Some(self.weights.clone())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Returns a copy of our secret key and secret key share, which can be serialized.
+    ///
+    /// Anyone who learns them can impersonate this node, and together with the shares of _f_
+    /// other validators, sign and decrypt on behalf of the network. They must only be exported to
+    /// persist them in secure storage.
+    ///
+    /// The returned value serializes to the plaintext key material: Neither `export_secrets` nor
+    /// serialization encrypts it, so that is up to the caller.
+    pub fn export_secrets(&self) -> NetworkInfoSecrets {
+        NetworkInfoSecrets {
+            secret_key_share: self.secret_key_share.clone(),
+            secret_key: self.secret_key.clone(),
+        }
+    }
+
+    /// Recreates a `NetworkInfo` from the values returned by `public_info` and `export_secrets`.
+    ///
+    /// If we are a validator, this fails with `KeyMismatch` unless the secret keys belong to our
+    /// public key and our public key share.
+    pub fn from_parts(
+        public_info: PublicNetworkInfo<N>,
+        secrets: NetworkInfoSecrets,
+    ) -> Result<Self, NetworkInfoError> {
+        let PublicNetworkInfo {
+            our_id,
+            public_key_set,
+            public_keys,
+            weights,
+        } = public_info;
+        let NetworkInfoSecrets {
+            secret_key_share,
+            secret_key,
+        } = secrets;
+        let netinfo = NetworkInfo::new(
+            our_id,
+            secret_key_share,
+            public_key_set,
+            secret_key,
+            public_keys,
+        );
+        if netinfo.is_validator {
+            let our_id = netinfo.our_id();
+            let our_pk_share = netinfo.secret_key_share.public_key_share();
+            if netinfo.public_key(our_id) != Some(&netinfo.secret_key.public_key())
+                || netinfo.public_key_share(our_id) != Some(&our_pk_share)
+            {
+                return Err(NetworkInfoError::KeyMismatch);
+            }
+        }
+        match weights {
+            Some(weights) => netinfo.with_weights(weights),
+            None => Ok(netinfo),
+        }
+    }
+
     /// Generates a map of matching `NetworkInfo`s for testing.
+    ///
+    /// Any _f + 1_ of the nodes can sign or decrypt with the resulting key set. All keys are
+    /// derived from `rng`, so a seeded RNG produces the same keys every time.
     pub fn generate_map<I, R>(
         ids: I,
         rng: &mut R,
-    ) -> Result<BTreeMap<N, NetworkInfo<N>>, crypto::error::Error>
+    ) -> Result<BTreeMap<N, NetworkInfo<N>>, NetworkInfoError>
+    where
+        I: IntoIterator<Item = N>,
+        R: rand::Rng,
+    {
+        let all_ids: BTreeSet<N> = ids.into_iter().collect();
+        if all_ids.is_empty() {
+            return Err(NetworkInfoError::NoNodes);
+        }
+        let num_faulty = (all_ids.len() - 1) / 3;
+        Self::generate_map_with_threshold(all_ids, num_faulty, rng)
+    }
+
+    /// Generates a map of matching `NetworkInfo`s for testing, with the given validators' weights.
+    ///
+    /// The key set requires _f + 1_ shares to sign or decrypt, where _f_ is the maximum number of
+    /// validators with a total weight of at most `faulty_weight`. If the remaining validators
+    /// don't outnumber them, an `InvalidThreshold` error is returned.
+    pub fn generate_weighted_map<R>(
+        weights: BTreeMap<N, u64>,
+        rng: &mut R,
+    ) -> Result<BTreeMap<N, NetworkInfo<N>>, NetworkInfoError>
+    where
+        R: rand::Rng,
+    {
+        if weights.is_empty() {
+            return Err(NetworkInfoError::NoNodes);
+        }
+        if weights.values().any(|w| *w == 0) {
+            return Err(NetworkInfoError::InvalidWeights);
+        }
+        let total_weight = weights.values().fold(0, |sum: u64, w| sum.saturating_add(*w));
+        let num_faulty = max_faulty_nodes(&weights, (total_weight - 1) / 3);
+        if num_faulty >= weights.len() - num_faulty {
+            return Err(NetworkInfoError::InvalidThreshold(num_faulty, weights.len()));
+        }
+        Self::generate_map_with_threshold(weights.keys().cloned(), num_faulty, rng)?
+            .into_iter()
+            .map(|(id, netinfo)| {
+                let weighted = netinfo.with_weights(weights.clone());
+                weighted.map(|netinfo| (id, netinfo))
+            }).collect()
+    }
+
+    /// Generates a map of matching `NetworkInfo`s for testing, with a key set that requires
+    /// `threshold + 1` shares to sign or decrypt.
+    ///
+    /// The threshold must be at least _f_, so that the faulty nodes can't sign on their own, and
+    /// less than _N - f_, so that the correct nodes can.
+    pub fn generate_map_with_threshold<I, R>(
+        ids: I,
+        threshold: usize,
+        rng: &mut R,
+    ) -> Result<BTreeMap<N, NetworkInfo<N>>, NetworkInfoError>
     where
         I: IntoIterator<Item = N>,
         R: rand::Rng,
@@ -155,10 +377,16 @@ impl<N: NodeIdT> NetworkInfo<N> {
         use crypto::SecretKeySet;
 
         let all_ids: BTreeSet<N> = ids.into_iter().collect();
+        if all_ids.is_empty() {
+            return Err(NetworkInfoError::NoNodes);
+        }
         let num_faulty = (all_ids.len() - 1) / 3;
+        if threshold < num_faulty || threshold >= all_ids.len() - num_faulty {
+            return Err(NetworkInfoError::InvalidThreshold(threshold, all_ids.len()));
+        }
 
         // Generate the keys for threshold cryptography.
-        let sk_set = SecretKeySet::random(num_faulty, rng);
+        let sk_set = SecretKeySet::random(threshold, rng);
         let pk_set = sk_set.public_keys();
 
         // Generate keys for individually signing and encrypting messages.
@@ -178,12 +406,57 @@ impl<N: NodeIdT> NetworkInfo<N> {
                 sec_keys[&id].clone(),
                 pub_keys.clone(),
             );
-            Ok((id, netinfo))
+            (id, netinfo)
         };
-        all_ids
+        Ok(all_ids
             .into_iter()
             .enumerate()
             .map(create_netinfo)
-            .collect()
+            .collect())
     }
 }
+
+/// The public part of a `NetworkInfo`, returned by `NetworkInfo::public_info`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublicNetworkInfo<N: Ord> {
+    /// The ID of the node the `NetworkInfo` belongs to.
+    our_id: N,
+    /// The public key set for threshold cryptography.
+    public_key_set: PublicKeySet,
+    /// The validators' public keys.
+    public_keys: BTreeMap<N, PublicKey>,
+    /// The validators' weights, or `None` if they all have weight 1.
+    weights: Option<BTreeMap<N, u64>>,
+}
+
+impl<N: Ord> PublicNetworkInfo<N> {
+    /// Returns the ID of the node the `NetworkInfo` belongs to.
+    pub fn our_id(&self) -> &N {
+        &self.our_id
+    }
+}
+
+/// The secret keys of a node, returned by `NetworkInfo::export_secrets`.
+///
+/// Its serialized form is the plaintext key material, and must be encrypted or otherwise
+/// protected before it is written to disk or sent anywhere.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetworkInfoSecrets {
+    /// Our secret key share for threshold cryptography.
+    secret_key_share: SecretKeyShare,
+    /// Our secret key for encryption and signing.
+    secret_key: SecretKey,
+}
+
+/// Returns the maximum number of validators whose total weight is at most `faulty_weight`.
+fn max_faulty_nodes<N>(weights: &BTreeMap<N, u64>, faulty_weight: u64) -> usize {
+    let mut sorted: Vec<u64> = weights.values().cloned().collect();
+    sorted.sort();
+    sorted
+        .into_iter()
+        .scan(0, |sum: &mut u64, w| {
+            *sum = sum.saturating_add(w);
+            Some(*sum)
+        }).take_while(|sum| *sum <= faulty_weight)
+        .count()
+}
@@ -0,0 +1,381 @@
+//! # Python bindings
+//!
+//! With the `python` feature, this module exposes Honey Badger to Python, using
+//! [PyO3](https://github.com/PyO3/pyo3): A `Node` is a single `HoneyBadger` instance, and a
+//! `Simulation` is a network of them with a queue of undelivered messages. This allows scripting
+//! experiments and analyzing the protocol's behavior, e.g. from a notebook, without writing a Rust
+//! test harness.
+//!
+//! PyO3 requires a nightly compiler. To build the Python module, run:
+//!
+//! ```text
+//! cargo +nightly rustc --release --lib --features python -- --crate-type cdylib
+//! cp target/release/libhbbft.so hbbft.so
+//! ```
+//!
+//! The nodes are identified by the numbers `0` to `num_nodes - 1`, contributions are byte
+//! strings, and messages are represented in the canonical [wire encoding](../wire/index.html):
+//!
+//! ```text
+//! import hbbft
+//!
+//! sim = hbbft.Simulation(4, seed=42)
+//! for node_id in range(4):
+//!     sim.input(node_id, b"contribution from %d" % node_id)
+//! sim.run(100000)
+//! for epoch, contributions in sim.batches(0):
+//!     print(epoch, contributions)
+//! ```
+//!
+//! A `Node` takes the main `HoneyBadgerBuilder` settings as keyword arguments, and returns every
+//! step as a tuple of its messages, output batches and faults. Each message is a list of recipient IDs and
+//! the wire-encoded message, which can be passed to the recipients' `handle_message`. Nodes created
+//! with the same number of nodes and seed share the same keys:
+//!
+//! ```text
+//! nodes = [hbbft.Node(4, node_id, seed=42) for node_id in range(4)]
+//! messages, batches, faults = nodes[0].propose(b"contribution from 0")
+//! for recipients, msg in messages:
+//!     for recipient in recipients:
+//!         nodes[recipient].handle_message(0, msg)
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::sync::Arc;
+
+use pyo3::exc;
+use pyo3::prelude::*;
+use rand::{Isaac64Rng, SeedableRng};
+
+use honey_badger::{Batch, EncryptionSchedule, HoneyBadger, Message, Step};
+use util::SubRng;
+use {wire, DistAlgorithm, NetworkInfo, Target};
+
+/// The Honey Badger instance type exposed to Python.
+type PyHoneyBadger = HoneyBadger<Vec<u8>, usize>;
+
+/// A batch as seen from Python: the epoch and a list of proposer IDs and contributions.
+type PyBatch = (u64, Vec<(usize, Vec<u8>)>);
+
+/// A step as seen from Python: the messages as recipient IDs and wire-encoded message, the output
+/// batches, and the faults as faulty node and a description.
+type PyStep = (Vec<(Vec<usize>, Vec<u8>)>, Vec<PyBatch>, Vec<(usize, String)>);
+
+/// Converts any error into a Python `ValueError`.
+fn value_error<E: Display>(err: E) -> PyErr {
+    exc::ValueError::new(err.to_string())
+}
+
+/// Returns the network infos of `num_nodes` nodes, with keys generated from the given seed, and
+/// the random number generator to create the nodes with.
+fn generate_netinfos(
+    num_nodes: usize,
+    seed: u64,
+) -> PyResult<(BTreeMap<usize, NetworkInfo<usize>>, Isaac64Rng)> {
+    let mut rng = Isaac64Rng::from_seed(&[seed][..]);
+    let netinfos = NetworkInfo::generate_map(0..num_nodes, &mut rng).map_err(value_error)?;
+    Ok((netinfos, rng))
+}
+
+/// Converts a batch into its Python representation.
+fn py_batch(batch: &Batch<Vec<u8>, usize>) -> PyBatch {
+    let contribs = batch.contributions.iter();
+    (batch.epoch.get(), contribs.map(|(id, c)| (*id, c.clone())).collect())
+}
+
+/// Returns the recipients of a message from `sender` with the given target, among `all_ids`.
+fn recipients<'a, I>(target: Target<usize>, sender: usize, all_ids: I) -> Vec<usize>
+where
+    I: IntoIterator<Item = &'a usize>,
+{
+    match target {
+        Target::All => all_ids.into_iter().cloned().filter(|&id| id != sender).collect(),
+        Target::AllExcept(exclude) => all_ids
+            .into_iter()
+            .cloned()
+            .filter(|id| *id != sender && !exclude.contains(id))
+            .collect(),
+        Target::Node(id) => vec![id],
+        Target::Nodes(ids) => ids.into_iter().filter(|&id| id != sender).collect(),
+        Target::Observers => Vec::new(), // All nodes are validators.
+    }
+}
+
+/// A simulated network of Honey Badger nodes, with a single queue of undelivered messages.
+struct Network {
+    /// The nodes' Honey Badger instances.
+    nodes: BTreeMap<usize, PyHoneyBadger>,
+    /// Undelivered messages, as sender, recipient and message. A message to several recipients is
+    /// shared by their entries.
+    queue: VecDeque<(usize, usize, Arc<Message<usize>>)>,
+    /// Each node's output batches.
+    batches: BTreeMap<usize, Vec<Batch<Vec<u8>, usize>>>,
+    /// All faults reported, as reporting node, faulty node and a description.
+    faults: Vec<(usize, usize, String)>,
+}
+
+impl Network {
+    /// Creates a network with `num_nodes` nodes, with keys generated from the given seed.
+    fn new(num_nodes: usize, seed: u64, max_future_epochs: usize) -> PyResult<Self> {
+        let (netinfos, mut rng) = generate_netinfos(num_nodes, seed)?;
+        let nodes = netinfos
+            .into_iter()
+            .map(|(id, netinfo)| {
+                let hb: PyHoneyBadger = HoneyBadger::builder(Arc::new(netinfo))
+                    .rng(rng.sub_rng())
+                    .max_future_epochs(max_future_epochs)
+                    .build();
+                (id, hb)
+            }).collect();
+        Ok(Network {
+            nodes,
+            queue: VecDeque::new(),
+            batches: BTreeMap::new(),
+            faults: Vec::new(),
+        })
+    }
+
+    /// Queues the step's messages and records its outputs and faults.
+    fn process_step(&mut self, sender: usize, step: Step<Vec<u8>, usize>) {
+        for tmsg in step.messages {
+            let message = Arc::new(tmsg.message);
+            for id in recipients(tmsg.target, sender, self.nodes.keys()) {
+                self.queue.push_back((sender, id, message.clone()));
+            }
+        }
+        let faults = step.fault_log.0.into_iter();
+        self.faults.extend(faults.map(|f| (sender, f.node_id, format!("{:?}", f.kind))));
+        self.batches
+            .entry(sender)
+            .or_insert_with(Vec::new)
+            .extend(step.output);
+    }
+
+    /// Returns the node with the given ID, or a `ValueError` if it doesn't exist.
+    fn node_mut(&mut self, id: usize) -> PyResult<&mut PyHoneyBadger> {
+        self.nodes
+            .get_mut(&id)
+            .ok_or_else(|| value_error(format!("unknown node {}", id)))
+    }
+
+    /// Inputs a contribution into the given node.
+    fn input(&mut self, id: usize, contribution: Vec<u8>) -> PyResult<()> {
+        let step = self
+            .node_mut(id)?
+            .handle_input(contribution)
+            .map_err(value_error)?;
+        self.process_step(id, step);
+        Ok(())
+    }
+
+    /// Hands a message to the given recipient.
+    fn deliver(&mut self, sender: usize, recipient: usize, msg: Message<usize>) -> PyResult<()> {
+        let node = self.node_mut(recipient)?;
+        let step = DistAlgorithm::handle_message(node, &sender, msg).map_err(value_error)?;
+        self.process_step(recipient, step);
+        Ok(())
+    }
+}
+
+/// A simulated network of Honey Badger nodes.
+#[pyclass]
+pub struct Simulation {
+    network: Network,
+    token: PyToken,
+}
+
+#[pymethods]
+impl Simulation {
+    /// Creates a network of `num_nodes` nodes, with keys generated from the given seed.
+    #[new]
+    #[args(seed = "0", max_future_epochs = "3")]
+    fn __new__(
+        obj: &PyRawObject,
+        num_nodes: usize,
+        seed: u64,
+        max_future_epochs: usize,
+    ) -> PyResult<()> {
+        let network = Network::new(num_nodes, seed, max_future_epochs)?;
+        obj.init(|token| Simulation { network, token })
+    }
+
+    /// Inputs a contribution into the given node.
+    fn input(&mut self, node_id: usize, contribution: Vec<u8>) -> PyResult<()> {
+        self.network.input(node_id, contribution)
+    }
+
+    /// Delivers the next queued message, and returns its sender and recipient, or `None` if the
+    /// queue is empty.
+    fn crank(&mut self) -> PyResult<Option<(usize, usize)>> {
+        match self.network.queue.pop_front() {
+            None => Ok(None),
+            Some((sender, recipient, msg)) => {
+                // The last recipient takes the message, the others clone it.
+                let msg = Arc::try_unwrap(msg).unwrap_or_else(|msg| (*msg).clone());
+                self.network.deliver(sender, recipient, msg)?;
+                Ok(Some((sender, recipient)))
+            }
+        }
+    }
+
+    /// Delivers queued messages until the queue is empty or `max_cranks` messages have been
+    /// delivered. Returns the number of delivered messages.
+    fn run(&mut self, max_cranks: usize) -> PyResult<usize> {
+        let mut count = 0;
+        while count < max_cranks && self.crank()?.is_some() {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the number of undelivered messages.
+    fn num_queued(&self) -> PyResult<usize> {
+        Ok(self.network.queue.len())
+    }
+
+    /// Returns all undelivered messages as sender, recipient and wire-encoded message, in the
+    /// order in which they will be delivered.
+    fn queued_messages(&self) -> PyResult<Vec<(usize, usize, Vec<u8>)>> {
+        self.network
+            .queue
+            .iter()
+            .map(|&(sender, recipient, ref msg)| {
+                let bytes = wire::encode(&**msg).map_err(value_error)?;
+                Ok((sender, recipient, bytes))
+            }).collect()
+    }
+
+    /// Removes all undelivered messages from the queue. Together with `queued_messages` and
+    /// `handle_message`, this allows implementing custom message schedulers in Python.
+    fn clear_queue(&mut self) -> PyResult<()> {
+        self.network.queue.clear();
+        Ok(())
+    }
+
+    /// Hands a wire-encoded message from `sender_id` to `node_id`.
+    fn handle_message(&mut self, node_id: usize, sender_id: usize, msg: Vec<u8>) -> PyResult<()> {
+        let msg = wire::decode(&msg).map_err(value_error)?;
+        self.network.deliver(sender_id, node_id, msg)
+    }
+
+    /// Returns the batches output by the given node, as epoch and a list of proposer IDs and
+    /// contributions.
+    fn batches(&self, node_id: usize) -> PyResult<Vec<PyBatch>> {
+        let batches = self.network.batches.get(&node_id).map_or(&[][..], Vec::as_slice);
+        Ok(batches.iter().map(py_batch).collect())
+    }
+
+    /// Returns all reported faults, as reporting node, faulty node and a description.
+    fn faults(&self) -> PyResult<Vec<(usize, usize, String)>> {
+        Ok(self.network.faults.clone())
+    }
+}
+
+/// A single Honey Badger node, whose messages are delivered by the caller.
+#[pyclass]
+pub struct Node {
+    hb: PyHoneyBadger,
+    /// The IDs of all nodes in the network.
+    all_ids: Vec<usize>,
+    token: PyToken,
+}
+
+impl Node {
+    /// Converts a step into its Python representation.
+    fn py_step(&self, step: Step<Vec<u8>, usize>) -> PyResult<PyStep> {
+        let our_id = *self.hb.our_id();
+        let messages = step
+            .messages
+            .into_iter()
+            .map(|tmsg| {
+                let bytes = wire::encode(&tmsg.message).map_err(value_error)?;
+                Ok((recipients(tmsg.target, our_id, &self.all_ids), bytes))
+            }).collect::<PyResult<_>>()?;
+        let batches = step.output.iter().map(py_batch).collect();
+        let faults = step.fault_log.0.into_iter();
+        let faults = faults.map(|f| (f.node_id, format!("{:?}", f.kind))).collect();
+        Ok((messages, batches, faults))
+    }
+}
+
+#[pymethods]
+impl Node {
+    /// Creates node `node_id` of a network of `num_nodes` nodes, with keys generated from the
+    /// given seed. The other arguments are passed to the `HoneyBadgerBuilder`. If `encrypt` is
+    /// `False`, contributions are never encrypted.
+    #[new]
+    #[args(
+        seed = "0",
+        max_future_epochs = "3",
+        contribution_size_limit = "None",
+        max_queued_per_epoch = "None",
+        encrypt = "true"
+    )]
+    fn __new__(
+        obj: &PyRawObject,
+        num_nodes: usize,
+        node_id: usize,
+        seed: u64,
+        max_future_epochs: usize,
+        contribution_size_limit: Option<usize>,
+        max_queued_per_epoch: Option<usize>,
+        encrypt: bool,
+    ) -> PyResult<()> {
+        let (mut netinfos, mut rng) = generate_netinfos(num_nodes, seed)?;
+        let all_ids = netinfos.keys().cloned().collect();
+        let netinfo = netinfos
+            .remove(&node_id)
+            .ok_or_else(|| value_error(format!("unknown node {}", node_id)))?;
+        let mut builder = HoneyBadger::builder(Arc::new(netinfo));
+        builder.rng(rng.sub_rng()).max_future_epochs(max_future_epochs);
+        if let Some(limit) = contribution_size_limit {
+            builder.contribution_size_limit(limit);
+        }
+        if let Some(max) = max_queued_per_epoch {
+            builder.max_queued_per_epoch(max);
+        }
+        if !encrypt {
+            builder.encryption_schedule(EncryptionSchedule::Never);
+        }
+        let hb = builder.build();
+        obj.init(|token| Node { hb, all_ids, token })
+    }
+
+    /// Returns the node's ID.
+    fn our_id(&self) -> PyResult<usize> {
+        Ok(*self.hb.our_id())
+    }
+
+    /// Returns the node's current epoch.
+    fn epoch(&self) -> PyResult<u64> {
+        Ok(self.hb.epoch().get())
+    }
+
+    /// Returns `True` if the node has already proposed a contribution in the current epoch.
+    fn has_input(&self) -> PyResult<bool> {
+        Ok(self.hb.has_input())
+    }
+
+    /// Proposes a contribution in the current epoch, and returns the resulting step.
+    fn propose(&mut self, contribution: Vec<u8>) -> PyResult<PyStep> {
+        let step = self.hb.handle_input(contribution).map_err(value_error)?;
+        self.py_step(step)
+    }
+
+    /// Handles a wire-encoded message from `sender_id`, and returns the resulting step.
+    fn handle_message(&mut self, sender_id: usize, msg: Vec<u8>) -> PyResult<PyStep> {
+        let msg: Message<usize> = wire::decode(&msg).map_err(value_error)?;
+        let step =
+            DistAlgorithm::handle_message(&mut self.hb, &sender_id, msg).map_err(value_error)?;
+        self.py_step(step)
+    }
+}
+
+/// The `hbbft` Python module.
+#[pymodinit]
+fn hbbft(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Simulation>()?;
+    m.add_class::<Node>()?;
+    Ok(())
+}
@@ -4,31 +4,40 @@
 //! an epoch is output, it will automatically select a list of pending transactions and propose it
 //! for the next one. The user can continuously add more pending transactions to the queue.
 //!
-//! If there are no pending transactions, no validators in the process of being added or
-//! removed and not enough other nodes have proposed yet, no automatic proposal will be made: The
-//! network then waits until at least _f + 1_ have any content for the next epoch.
+//! If there are fewer pending transactions than the configured `min_queue_depth` (by default
+//! one), no validators in the process of being added or removed and not enough other nodes have
+//! proposed yet, no automatic proposal will be made: The network then waits until at least
+//! _f + 1_ have any content for the next epoch.
 //!
 //! ## How it works
 //!
 //! Queueing Honey Badger runs a Dynamic Honey Badger internally, and automatically inputs a list
 //! of pending transactions as its contribution at the beginning of each epoch. These are selected
-//! by making a random choice of _B / N_ out of the first _B_ entries in the queue, where _B_ is the
-//! configurable `batch_size` parameter, and _N_ is the current number of validators.
+//! by making a random choice of _B / N_ out of the first _W_ entries in the queue, where _B_ is the
+//! configurable `batch_size` parameter, _N_ is the current number of validators, and _W_ is the
+//! `sample_window`, which is equal to _B_ unless configured otherwise.
 //!
 //! After each output, the transactions that made it into the new batch are removed from the queue.
+//! The digests of the most recently committed transactions are remembered, and if they are input
+//! again, they are ignored, just like transactions that are already in the queue. The number of
+//! remembered digests can be configured with `committed_history`; when it is exceeded, the least
+//! recently committed or input one is forgotten.
 //!
 //! The random choice of transactions is made to reduce redundancy even if all validators have
 //! roughly the same entries in their queues. By selecting a random fraction of the first _B_
 //! entries, any two nodes will likely make almost disjoint contributions instead of proposing
 //! the same transaction multiple times.
 
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Display};
 use std::marker::PhantomData;
 use std::{cmp, iter};
 
+use bincode;
 use failure::{Backtrace, Context, Fail};
-use rand::{Rand, Rng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use tiny_keccak::sha3_256;
 
 use dynamic_honey_badger::{self, Batch as DhbBatch, DynamicHoneyBadger, Message};
 use transaction_queue::TransactionQueue;
@@ -47,6 +56,17 @@ pub enum ErrorKind {
     Propose(dynamic_honey_badger::Error),
 }
 
+impl ErrorKind {
+    /// Returns `true` if the instance cannot continue after this error.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            ErrorKind::Input(ref err)
+            | ErrorKind::HandleMessage(ref err)
+            | ErrorKind::Propose(ref err) => err.is_fatal(),
+        }
+    }
+}
+
 /// A queueing honey badger error.
 #[derive(Debug)]
 pub struct Error {
@@ -67,6 +87,12 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// Returns `true` if the instance cannot continue after this error. Otherwise only the input
+    /// or message that caused it was rejected.
+    pub fn is_fatal(&self) -> bool {
+        self.kind().is_fatal()
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -91,15 +117,102 @@ impl Display for Error {
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// The default number of recently committed transactions whose digests are remembered.
+pub const DEFAULT_COMMITTED_HISTORY: usize = 1000;
+
+/// The default target number of transactions per batch.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// The default number of pending transactions required to make a proposal.
+pub const DEFAULT_MIN_QUEUE_DEPTH: usize = 1;
+
+/// The SHA3-256 digest of a transaction's serialized form.
+type Digest = [u8; 32];
+
+/// The digests of the most recently committed transactions, up to a limit. If the limit is
+/// exceeded, the least recently used one, i.e. the one that has been neither committed nor input
+/// again for the longest time, is forgotten.
+#[derive(Debug)]
+struct CommittedHistory {
+    /// The maximum number of digests to remember.
+    limit: usize,
+    /// The number of uses so far, which orders the digests by their last use.
+    uses: u64,
+    /// The digests by their last use, least recent first.
+    by_last_use: BTreeMap<u64, Digest>,
+    /// The last use of each digest.
+    last_use: HashMap<Digest, u64>,
+}
+
+impl CommittedHistory {
+    /// Creates an empty history that remembers up to `limit` digests.
+    fn new(limit: usize) -> Self {
+        CommittedHistory {
+            limit,
+            uses: 0,
+            by_last_use: BTreeMap::new(),
+            last_use: HashMap::new(),
+        }
+    }
+
+    /// Returns the digest of the transaction, or `None` if it can't be serialized.
+    fn digest<T: Serialize>(tx: &T) -> Option<Digest> {
+        bincode::serialize(tx).ok().map(|bytes| sha3_256(&bytes))
+    }
+
+    /// Returns `true` if the transaction has been committed recently, and marks it as used.
+    fn check<T: Serialize>(&mut self, tx: &T) -> bool {
+        match CommittedHistory::digest(tx) {
+            Some(digest) if self.last_use.contains_key(&digest) => {
+                self.touch(digest);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds a committed transaction, and forgets the least recently used one if the limit is
+    /// exceeded.
+    fn insert<T: Serialize>(&mut self, tx: &T) {
+        if self.limit == 0 {
+            return;
+        }
+        if let Some(digest) = CommittedHistory::digest(tx) {
+            self.touch(digest);
+        }
+        if self.last_use.len() > self.limit {
+            let least_recent = self.by_last_use.keys().next().cloned();
+            if let Some(digest) = least_recent.and_then(|i| self.by_last_use.remove(&i)) {
+                self.last_use.remove(&digest);
+            }
+        }
+    }
+
+    /// Marks the digest as the most recently used one.
+    fn touch(&mut self, digest: Digest) {
+        self.uses += 1;
+        if let Some(last_use) = self.last_use.insert(digest, self.uses) {
+            self.by_last_use.remove(&last_use);
+        }
+        self.by_last_use.insert(self.uses, digest);
+    }
+}
+
 /// A Queueing Honey Badger builder, to configure the parameters and create new instances of
 /// `QueueingHoneyBadger`.
-pub struct QueueingHoneyBadgerBuilder<T, N: Rand, Q> {
+pub struct QueueingHoneyBadgerBuilder<T, N, Q> {
     /// Shared network data.
     dyn_hb: DynamicHoneyBadger<Vec<T>, N>,
     /// The target number of transactions to be included in each batch.
     batch_size: usize,
+    /// The number of transactions at the front of the queue that proposals are sampled from.
+    sample_window: Option<usize>,
+    /// The minimum number of pending transactions required to make a proposal.
+    min_queue_depth: usize,
     /// The queue of pending transactions that haven't been output in a batch yet.
     queue: Q,
+    /// The number of recently committed transactions whose digests are remembered.
+    committed_history: usize,
     _phantom: PhantomData<T>,
 }
 
@@ -108,7 +221,7 @@ pub type QueueingHoneyBadgerWithStep<T, N, Q> = (QueueingHoneyBadger<T, N, Q>, S
 impl<T, N, Q> QueueingHoneyBadgerBuilder<T, N, Q>
 where
     T: Contribution + Serialize + for<'r> Deserialize<'r> + Clone,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
     Q: TransactionQueue<T>,
 {
     /// Returns a new `QueueingHoneyBadgerBuilder` configured to use the node IDs and cryptographic
@@ -119,8 +232,11 @@ where
         // TODO: Use the defaults from `HoneyBadgerBuilder`.
         QueueingHoneyBadgerBuilder {
             dyn_hb,
-            batch_size: 100,
+            batch_size: DEFAULT_BATCH_SIZE,
+            sample_window: None,
+            min_queue_depth: DEFAULT_MIN_QUEUE_DEPTH,
             queue: Default::default(),
+            committed_history: DEFAULT_COMMITTED_HISTORY,
             _phantom: PhantomData,
         }
     }
@@ -131,12 +247,35 @@ where
         self
     }
 
+    /// Sets the number of transactions at the front of the queue that each proposal is randomly
+    /// sampled from. By default, this is equal to the batch size.
+    pub fn sample_window(mut self, sample_window: usize) -> Self {
+        self.sample_window = Some(sample_window);
+        self
+    }
+
+    /// Sets the minimum number of pending transactions required before we propose on our own.
+    /// With fewer, we only propose if we are required to in order to avoid stalling the network.
+    /// The default is one, i.e. any pending transaction triggers a proposal.
+    pub fn min_queue_depth(mut self, min_queue_depth: usize) -> Self {
+        self.min_queue_depth = min_queue_depth;
+        self
+    }
+
     /// Sets the transaction queue object.
     pub fn queue(mut self, queue: Q) -> Self {
         self.queue = queue;
         self
     }
 
+    /// Sets the number of recently committed transactions whose digests are remembered. If any of
+    /// them is input again, it is ignored instead of being proposed a second time. If there are
+    /// more, the least recently committed or input one is forgotten.
+    pub fn committed_history(mut self, committed_history: usize) -> Self {
+        self.committed_history = committed_history;
+        self
+    }
+
     /// Creates a new Queueing Honey Badger instance with an empty buffer.
     pub fn build<R>(self, rng: R) -> QueueingHoneyBadgerWithStep<T, N, Q>
     where
@@ -150,7 +289,7 @@ where
     /// Returns a new Queueing Honey Badger instance that starts with the given transactions in its
     /// buffer.
     pub fn build_with_transactions<TI, R>(
-        mut self,
+        self,
         txs: TI,
         rng: R,
     ) -> Result<QueueingHoneyBadgerWithStep<T, N, Q>>
@@ -159,13 +298,18 @@ where
         T: Contribution + Serialize + for<'r> Deserialize<'r>,
         R: 'static + Rng + Send + Sync,
     {
-        self.queue.extend(txs);
         let mut qhb = QueueingHoneyBadger {
             dyn_hb: self.dyn_hb,
             batch_size: self.batch_size,
+            sample_window: self.sample_window.unwrap_or(self.batch_size),
+            min_queue_depth: self.min_queue_depth,
             queue: self.queue,
+            committed: CommittedHistory::new(self.committed_history),
             rng: Box::new(rng),
         };
+        for tx in txs {
+            qhb.push_transaction(tx);
+        }
         let step = qhb.propose()?;
         Ok((qhb, step))
     }
@@ -176,15 +320,21 @@ where
 pub struct QueueingHoneyBadger<T, N, Q>
 where
     T: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
     Q: TransactionQueue<T>,
 {
     /// The target number of transactions to be included in each batch.
     batch_size: usize,
+    /// The number of transactions at the front of the queue that proposals are sampled from.
+    sample_window: usize,
+    /// The minimum number of pending transactions required to make a proposal.
+    min_queue_depth: usize,
     /// The internal `DynamicHoneyBadger` instance.
     dyn_hb: DynamicHoneyBadger<Vec<T>, N>,
     /// The queue of pending transactions that haven't been output in a batch yet.
     queue: Q,
+    /// The digests of the most recently committed transactions.
+    committed: CommittedHistory,
     /// Random number generator used for choosing transactions from the queue.
     rng: Box<dyn Rng + Send + Sync>,
 }
@@ -192,14 +342,17 @@ where
 impl<T, N, Q> fmt::Debug for QueueingHoneyBadger<T, N, Q>
 where
     T: Contribution + Serialize + for<'r> Deserialize<'r>,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
     Q: TransactionQueue<T>,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("QueueingHoneyBadger")
             .field("batch_size", &self.batch_size)
+            .field("sample_window", &self.sample_window)
+            .field("min_queue_depth", &self.min_queue_depth)
             .field("dyn_hb", &self.dyn_hb)
             .field("queue", &self.queue)
+            .field("committed", &self.committed)
             .field("rng", &"<RNG>")
             .finish()
     }
@@ -210,7 +363,7 @@ pub type Step<T, N, Q> = ::Step<QueueingHoneyBadger<T, N, Q>>;
 impl<T, N, Q> DistAlgorithm for QueueingHoneyBadger<T, N, Q>
 where
     T: Contribution + Serialize + for<'r> Deserialize<'r> + Clone,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
     Q: TransactionQueue<T>,
 {
     type NodeId = N;
@@ -218,13 +371,14 @@ where
     type Output = Batch<T, N>;
     type Message = Message<N>;
     type Error = Error;
+    type FaultKind = dynamic_honey_badger::FaultKind;
 
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<T, N, Q>> {
         // User transactions are forwarded to `HoneyBadger` right away. Internal messages are
         // in addition signed and broadcast.
         let mut step = match input {
             Input::User(tx) => {
-                self.queue.extend(iter::once(tx));
+                self.push_transaction(tx);
                 Step::default()
             }
             Input::Change(change) => self
@@ -243,9 +397,7 @@ where
             .handle_message(sender_id, message)
             .map_err(ErrorKind::HandleMessage)?
             .convert::<Self>();
-        for batch in &step.output {
-            self.queue.remove_multiple(batch.iter());
-        }
+        self.remove_committed(&step);
         step.extend(self.propose()?);
         Ok(step)
     }
@@ -262,7 +414,7 @@ where
 impl<T, N, Q> QueueingHoneyBadger<T, N, Q>
 where
     T: Contribution + Serialize + for<'r> Deserialize<'r> + Clone,
-    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + Rand,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
     Q: TransactionQueue<T>,
 {
     /// Returns a new `QueueingHoneyBadgerBuilder` configured to use the node IDs and cryptographic
@@ -276,14 +428,19 @@ where
         &self.dyn_hb
     }
 
+    /// Returns a reference to the queue of pending transactions.
+    pub fn queue(&self) -> &Q {
+        &self.queue
+    }
+
     /// Returns `true` if we are ready to propose our contribution for the next epoch, i.e. if the
-    /// previous epoch has completed and we have either pending transactions or we are required to
-    /// make a proposal to avoid stalling the network.
+    /// previous epoch has completed and we have either at least `min_queue_depth` pending
+    /// transactions or we are required to make a proposal to avoid stalling the network.
     fn can_propose(&self) -> bool {
         if self.dyn_hb.has_input() {
             return false; // Previous epoch is still in progress.
         }
-        !self.queue.is_empty() || self.dyn_hb.should_propose()
+        self.queue.len() >= self.min_queue_depth || self.dyn_hb.should_propose()
     }
 
     /// Initiates the next epoch by proposing a batch from the queue.
@@ -291,16 +448,36 @@ where
         let mut step = Step::default();
         while self.can_propose() {
             let amount = cmp::max(1, self.batch_size / self.dyn_hb.netinfo().num_nodes());
-            let proposal = self.queue.choose(&mut self.rng, amount, self.batch_size);
-            step.extend(
-                self.dyn_hb
-                    .handle_input(Input::User(proposal))
-                    .map_err(ErrorKind::Propose)?
-                    .convert(),
-            );
+            let proposal = self.queue.choose(&mut self.rng, amount, self.sample_window);
+            let propose_step = self
+                .dyn_hb
+                .handle_input(Input::User(proposal))
+                .map_err(ErrorKind::Propose)?
+                .convert();
+            self.remove_committed(&propose_step);
+            step.extend(propose_step);
         }
         Ok(step)
     }
+
+    /// Adds the transaction to the queue, unless it is already pending or has been committed
+    /// recently.
+    fn push_transaction(&mut self, tx: T) {
+        if !self.committed.check(&tx) && !self.queue.contains(&tx) {
+            self.queue.extend(iter::once(tx));
+        }
+    }
+
+    /// Removes the transactions in the step's output batches from the queue, and adds them to the
+    /// committed history.
+    fn remove_committed(&mut self, step: &Step<T, N, Q>) {
+        for batch in &step.output {
+            self.queue.remove_multiple(batch.iter());
+            for tx in batch.iter() {
+                self.committed.insert(tx);
+            }
+        }
+    }
 }
 
 pub type Batch<T, N> = DhbBatch<Vec<T>, N>;
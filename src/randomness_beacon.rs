@@ -0,0 +1,261 @@
+//! # A Randomness Beacon
+//!
+//! The Randomness Beacon produces shared random values: For each _round_, the correct nodes agree
+//! on a 32-byte value that cannot be known beforehand. Applications can use it e.g. for leader
+//! election or for assigning nodes to shards.
+//!
+//! The nodes input a round number, and after _f + 1_ validators have input the same round,
+//! everyone who input it receives that round's value. In particular, the adversary cannot know the
+//! value before at least one correct node has provided input. Rounds are independent of each other
+//! and can be requested in any order.
+//!
+//! ## How it works
+//!
+//! Every round runs a `Coin` instance, with a nonce that is unique for the network, the beacon's
+//! session ID and the round number. Instead of the parity of the coin's threshold signature, the
+//! beacon outputs the SHA3-256 hash of the whole signature. Since there is exactly one valid
+//! signature for each nonce, all nodes output the same value, and since it cannot be computed
+//! without _f + 1_ signature shares, the value is unpredictable and uniformly distributed.
+//!
+//! To bound the number of coins a faulty node can make us create, coins only exist for the rounds
+//! in a window: Messages for rounds more than `max_future_rounds` beyond the highest round we have
+//! requested are rejected, and their senders reported. Messages for rounds below the window's low
+//! end are ignored as obsolete. The low end moves past every round that completes in order, and
+//! `RandomnessBeacon::skip_to_round` moves it explicitly, abandoning the rounds below.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use binary_agreement::encode_document;
+use coin::{self, Coin, CoinMessage};
+use validation::Validity;
+use {DistAlgorithm, NetworkInfo, NodeIdT};
+
+/// A randomness beacon error.
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Error handling coin: {}", _0)]
+    HandleCoin(coin::Error),
+    #[fail(display = "Terminated coin has no signature")]
+    MissingSignature,
+}
+
+impl Error {
+    /// Returns `true` if the beacon cannot produce any more values.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::HandleCoin(ref err) => err.is_fatal(),
+            Error::MissingSignature => false,
+        }
+    }
+}
+
+/// Represents each reason why a randomness beacon message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message was received from a node that is not a validator.
+    UnknownSender,
+    /// A message was for a round too far beyond the ones we requested.
+    BeaconRoundTooFarAhead,
+    /// A fault in one of the rounds' coins.
+    Coin(coin::FaultKind),
+}
+
+impl From<coin::FaultKind> for FaultKind {
+    fn from(fault_kind: coin::FaultKind) -> FaultKind {
+        FaultKind::Coin(fault_kind)
+    }
+}
+
+/// A randomness beacon result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A message belonging to a single round of the randomness beacon.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "testing", derive(Rand))]
+pub struct Message {
+    pub round: u64,
+    pub content: CoinMessage,
+}
+
+/// The random value of a round.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct RandomValue {
+    /// The round number.
+    pub round: u64,
+    /// The SHA3-256 hash of the round's threshold signature.
+    pub value: [u8; 32],
+}
+
+pub type Step<N> = ::Step<RandomnessBeacon<N>>;
+
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::randomness_beacon";
+
+/// The domain tag of the coin nonces, which distinguishes them from other signed documents.
+const NONCE_DOMAIN: &[u8] = b"hbbft randomness beacon nonce";
+
+/// The default maximum number of rounds beyond the highest requested one for which messages are
+/// accepted.
+pub const DEFAULT_MAX_FUTURE_ROUNDS: u64 = 100;
+
+/// A randomness beacon instance. On input of a round number, broadcasts our threshold signature
+/// share for that round. Upon receiving enough shares, outputs the round's random value.
+///
+/// The instance never terminates: It can produce values for any number of rounds.
+#[derive(Debug)]
+pub struct RandomnessBeacon<N> {
+    netinfo: Arc<NetworkInfo<N>>,
+    /// The session identifier. It must be unique among all beacons using the same network keys.
+    session_id: u64,
+    /// The coins of the rounds that have not output a value yet.
+    coins: BTreeMap<u64, Coin<N, Vec<u8>>>,
+    /// The rounds at or above `min_round` that have output a value.
+    completed: BTreeSet<u64>,
+    /// The lowest round that hasn't been completed or abandoned. Messages for earlier rounds are
+    /// obsolete.
+    min_round: u64,
+    /// The highest round we have requested so far.
+    max_input_round: u64,
+    /// The number of rounds beyond `max_input_round` for which messages are accepted.
+    max_future_rounds: u64,
+}
+
+impl<N: NodeIdT> DistAlgorithm for RandomnessBeacon<N> {
+    type NodeId = N;
+    type Input = u64;
+    type Output = RandomValue;
+    type Message = Message;
+    type Error = Error;
+    type FaultKind = FaultKind;
+
+    /// Sends our threshold signature share for the given round, if not yet sent.
+    fn handle_input(&mut self, round: u64) -> Result<Step<N>> {
+        if self.is_completed(round) {
+            return Ok(Step::default());
+        }
+        self.max_input_round = self.max_input_round.max(round);
+        let coin_step = self
+            .coin_mut(round)
+            .handle_input(())
+            .map_err(Error::HandleCoin)?;
+        self.on_coin_step(round, coin_step)
+    }
+
+    /// Handles a message received from `sender_id`.
+    fn handle_message(&mut self, sender_id: &N, message: Message) -> Result<Step<N>> {
+        let Message { round, content } = message;
+        // Validate the sender and the round before a coin is created for it.
+        let validity = if !self.netinfo.is_node_validator(sender_id) {
+            Validity::Faulty(FaultKind::UnknownSender)
+        } else if self.is_completed(round) {
+            Validity::Obsolete
+        } else if round > self.max_input_round.saturating_add(self.max_future_rounds) {
+            Validity::Faulty(FaultKind::BeaconRoundTooFarAhead)
+        } else {
+            Validity::Valid
+        };
+        if !validity.is_valid() {
+            return Ok(Step::validated(sender_id.clone(), validity));
+        }
+        let coin_step = self
+            .coin_mut(round)
+            .handle_message(sender_id, content)
+            .map_err(Error::HandleCoin)?;
+        self.on_coin_step(round, coin_step)
+    }
+
+    fn terminated(&self) -> bool {
+        false
+    }
+
+    fn our_id(&self) -> &N {
+        self.netinfo.our_id()
+    }
+}
+
+impl<N: NodeIdT> RandomnessBeacon<N> {
+    /// Creates a new randomness beacon with the given session identifier, which must be unique
+    /// among all beacons that use the same network keys.
+    pub fn new(netinfo: Arc<NetworkInfo<N>>, session_id: u64) -> Self {
+        RandomnessBeacon {
+            netinfo,
+            session_id,
+            coins: BTreeMap::new(),
+            completed: BTreeSet::new(),
+            min_round: 0,
+            max_input_round: 0,
+            max_future_rounds: DEFAULT_MAX_FUTURE_ROUNDS,
+        }
+    }
+
+    /// Sets the number of rounds beyond the highest one we have requested for which messages are
+    /// accepted. Messages for later rounds are dropped and their senders reported as faulty, so
+    /// the nodes should not request rounds further ahead of each other than that.
+    pub fn set_max_future_rounds(&mut self, max_future_rounds: u64) {
+        self.max_future_rounds = max_future_rounds;
+    }
+
+    /// Returns `true` if the value of the given round has been output already, or if the round
+    /// has been abandoned with `skip_to_round`.
+    pub fn is_completed(&self, round: u64) -> bool {
+        round < self.min_round || self.completed.contains(&round)
+    }
+
+    /// Abandons all rounds below `round`: They won't output a value anymore, their coins are
+    /// dropped and messages for them are ignored. Long-running instances should call this
+    /// regularly if they don't request every round, so that the completed rounds don't pile up.
+    pub fn skip_to_round(&mut self, round: u64) {
+        if round <= self.min_round {
+            return;
+        }
+        self.min_round = round;
+        self.coins = self.coins.split_off(&round);
+        self.completed = self.completed.split_off(&round);
+        self.advance_min_round();
+    }
+
+    /// Moves the low end of the window past the completed rounds at its start, and forgets them.
+    fn advance_min_round(&mut self) {
+        while self.completed.remove(&self.min_round) {
+            self.min_round += 1;
+        }
+    }
+
+    /// Returns the given round's coin, and creates it if it doesn't exist yet.
+    fn coin_mut(&mut self, round: u64) -> &mut Coin<N, Vec<u8>> {
+        let (netinfo, nonce) = (self.netinfo.clone(), self.nonce(round));
+        self.coins
+            .entry(round)
+            .or_insert_with(|| Coin::new(netinfo, nonce))
+    }
+
+    /// Converts the coin's step, and outputs the round's value if the coin has terminated.
+    fn on_coin_step(&mut self, round: u64, coin_step: coin::Step<N, Vec<u8>>) -> Result<Step<N>> {
+        let mut step: Step<N> = Step::default();
+        let to_msg = |content| Message { round, content };
+        if step.extend_with(coin_step, to_msg).0.is_empty() {
+            return Ok(step);
+        }
+        // The coin has terminated: Output the hash of its signature, and drop it.
+        if let Some(coin) = self.coins.remove(&round) {
+            let value = coin.random_value().ok_or(Error::MissingSignature)?;
+            debug_event!("Beacon output", node = self.netinfo.our_id(), round = round);
+            self.completed.insert(round);
+            self.advance_min_round();
+            step.output.push_back(RandomValue { round, value });
+        }
+        Ok(step)
+    }
+
+    /// Returns the nonce of the given round's coin. There is no proposer, so its field in the
+    /// encoding is always 0.
+    fn nonce(&self, round: u64) -> Vec<u8> {
+        let mut session_id = [0; 8];
+        BigEndian::write_u64(&mut session_id, self.session_id);
+        let invocation_id = self.netinfo.invocation_id();
+        encode_document(NONCE_DOMAIN, &invocation_id, &session_id, 0, round)
+    }
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use super::SenderQueueableDistAlgorithm;
+use dynamic_honey_badger::{Batch, Change, ChangeState, DynamicHoneyBadger, FaultKind, Message};
+use epoch::HbEpoch;
+use epoch_gate::classify_epoch;
+use validation::Validity;
+use {Contribution, NodeIdT};
+
+impl<C, N> SenderQueueableDistAlgorithm for DynamicHoneyBadger<C, N>
+where
+    C: Contribution + Serialize + for<'r> Deserialize<'r>,
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r>,
+{
+    fn epoch(&self) -> HbEpoch {
+        self.epoch()
+    }
+
+    fn is_premature(&self, message: &Message<N>, them: HbEpoch) -> bool {
+        let epoch = message.epoch().get();
+        let validity: Validity<FaultKind> =
+            classify_epoch(epoch, them.get(), self.max_future_epochs());
+        validity == Validity::Future
+    }
+
+    fn removed_peers(&self, batch: &Batch<C, N>) -> Vec<N> {
+        match *batch.change() {
+            ChangeState::Complete(Change::Remove(ref id), _) => vec![id.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use super::{SenderQueue, SenderQueueableDistAlgorithm, Step};
+use honey_badger::{FaultKind, HoneyBadger, Message, Result};
+use epoch::HbEpoch;
+use epoch_gate::classify_epoch;
+use validation::Validity;
+use {Contribution, NodeIdT};
+
+impl<C, N> SenderQueueableDistAlgorithm for HoneyBadger<C, N>
+where
+    C: Contribution + Serialize + for<'r> Deserialize<'r>,
+    N: NodeIdT,
+{
+    fn epoch(&self) -> HbEpoch {
+        self.epoch()
+    }
+
+    fn is_premature(&self, message: &Message<N>, them: HbEpoch) -> bool {
+        let epoch = message.epoch().get();
+        let validity: Validity<FaultKind> =
+            classify_epoch(epoch, them.get(), self.max_future_epochs());
+        validity == Validity::Future
+    }
+}
+
+impl<C, N> SenderQueue<HoneyBadger<C, N>>
+where
+    C: Contribution + Serialize + for<'r> Deserialize<'r>,
+    N: NodeIdT,
+{
+    /// Moves the managed `HoneyBadger` instance on to the given epoch, as with
+    /// `HoneyBadger::skip_to_epoch`, and announces the new epoch to the peers.
+    pub fn skip_to_epoch(&mut self, epoch: HbEpoch) -> Result<Step<HoneyBadger<C, N>>> {
+        let step = self.algo.skip_to_epoch(epoch)?;
+        Ok(self.process_step(step))
+    }
+}
@@ -0,0 +1,10 @@
+use epoch::HbEpoch;
+
+/// A message sent to or received from another node's `SenderQueue`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Message<M> {
+    /// The announcement that the sender has reached the given epoch.
+    EpochStarted(HbEpoch),
+    /// A message of the managed algorithm.
+    Algo(M),
+}
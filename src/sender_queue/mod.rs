@@ -0,0 +1,375 @@
+//! # Sender queue
+//!
+//! A `SenderQueue` wraps an epoch-based algorithm, such as `HoneyBadger` or `DynamicHoneyBadger`,
+//! and holds back outgoing messages that their recipients are not ready to handle yet.
+//!
+//! Without it, a node that lags behind the others has to buffer all messages for epochs it
+//! hasn't reached yet, or drop them. With a sender queue, every node instead announces its
+//! current epoch to its peers, and keeps any message that would be _premature_ for a peer until
+//! that peer has announced a sufficiently late epoch. That way, the memory required for buffering
+//! is spent by the sender, not the recipient, and a malicious peer can only waste its own.
+//!
+//! ## How it works
+//!
+//! Every message sent by the wrapped algorithm is wrapped in a `Message::Algo`. Whenever the
+//! algorithm's epoch increases, a `Message::EpochStarted` with the new epoch is sent to everyone.
+//!
+//! For each outgoing message, the sender queue checks for every recipient whether the message is
+//! premature, given the last epoch the recipient announced. If it is, the message is kept in the
+//! recipient's queue, otherwise it is sent right away. Whenever a peer announces a new epoch, all
+//! messages in its queue that are not premature anymore are sent to it.
+//!
+//! Messages with `Target::All` or `Target::AllExcept` are sent to all their recipients
+//! individually if any peer is lagging. In that case, only the peers passed to `SenderQueue::new`,
+//! and nodes that have announced their epoch, will receive them.
+//!
+//! Since every peer's announced epoch is known, `SenderQueue::lagging_peers` can tell which
+//! validators have fallen behind, so that the application can help them catch up out of band,
+//! e.g. by sending them a snapshot. With `SenderQueue::set_lag_threshold`, such peers are also
+//! recorded when they fall behind, and `SenderQueue::take_lag_reports` returns the new reports.
+//!
+//! A peer that never announces a new epoch would make its queue grow forever, so the number of
+//! messages held back per peer can be limited with `SenderQueue::set_max_queued_per_peer`. The
+//! queue and announced epoch of a peer are dropped when it is removed from the network, either by
+//! the managed algorithm's output or with `SenderQueue::remove_peers`.
+
+mod dynamic_honey_badger;
+mod honey_badger;
+mod message;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::sync::Arc;
+
+use epoch::HbEpoch;
+use {DistAlgorithm, NodeIdT, Target, TargetedMessage};
+
+pub use self::message::Message;
+
+/// An algorithm whose messages can be held back by a `SenderQueue` until their recipients are
+/// ready for them.
+pub trait SenderQueueableDistAlgorithm: DistAlgorithm {
+    /// Returns our current epoch.
+    fn epoch(&self) -> HbEpoch;
+
+    /// Returns `true` if a node in epoch `them` cannot handle the `message` yet.
+    fn is_premature(&self, message: &Self::Message, them: HbEpoch) -> bool;
+
+    /// Returns the peers that were removed from the network in the epoch of the given `output`,
+    /// and won't need any further messages. By default, peers are never removed.
+    fn removed_peers(&self, _output: &Self::Output) -> Vec<Self::NodeId> {
+        Vec::new()
+    }
+}
+
+pub type Step<D> = ::Step<SenderQueue<D>>;
+
+/// A wrapper that holds back the messages of the algorithm `D` until their recipients are ready.
+#[derive(Debug)]
+pub struct SenderQueue<D>
+where
+    D: SenderQueueableDistAlgorithm,
+{
+    /// The managed algorithm.
+    algo: D,
+    /// The latest epoch we announced to our peers.
+    epoch: HbEpoch,
+    /// The latest epoch announced by each peer.
+    peer_epochs: BTreeMap<D::NodeId, HbEpoch>,
+    /// The messages that are held back until their recipients reach a later epoch. A message that
+    /// is held back for several recipients is shared by their queues.
+    outgoing_queue: BTreeMap<D::NodeId, Vec<Arc<D::Message>>>,
+    /// The number of epochs a peer can fall behind us before it is reported, if any.
+    lag_threshold: Option<u64>,
+    /// The peers that have been reported as lagging and haven't caught up since.
+    reported_lagging: BTreeSet<D::NodeId>,
+    /// The lagging peers, with their latest announced epoch, that have not been taken yet.
+    pending_lag_reports: Vec<(D::NodeId, HbEpoch)>,
+    /// The maximum number of messages held back for a single peer, if any.
+    max_queued_per_peer: Option<usize>,
+}
+
+impl<D> DistAlgorithm for SenderQueue<D>
+where
+    D: SenderQueueableDistAlgorithm,
+    D::Message: Clone,
+{
+    type NodeId = D::NodeId;
+    type Input = D::Input;
+    type Output = D::Output;
+    type Message = Message<D::Message>;
+    type Error = D::Error;
+    type FaultKind = D::FaultKind;
+
+    fn handle_input(&mut self, input: Self::Input) -> Result<Step<D>, D::Error> {
+        let step = self.algo.handle_input(input)?;
+        Ok(self.process_step(step))
+    }
+
+    fn handle_message(
+        &mut self,
+        sender_id: &D::NodeId,
+        message: Self::Message,
+    ) -> Result<Step<D>, D::Error> {
+        match message {
+            Message::EpochStarted(epoch) => Ok(self.handle_epoch_started(sender_id, epoch)),
+            Message::Algo(msg) => {
+                let step = self.algo.handle_message(sender_id, msg)?;
+                Ok(self.process_step(step))
+            }
+        }
+    }
+
+    fn handle_timeout(&mut self, token: u64) -> Result<Step<D>, D::Error> {
+        let step = self.algo.handle_timeout(token)?;
+        Ok(self.process_step(step))
+    }
+
+    fn terminated(&self) -> bool {
+        self.algo.terminated()
+    }
+
+    fn our_id(&self) -> &D::NodeId {
+        self.algo.our_id()
+    }
+}
+
+impl<D> SenderQueue<D>
+where
+    D: SenderQueueableDistAlgorithm,
+    D::Message: Clone,
+{
+    /// Creates a new sender queue managing `algo`, with the given initial set of peers. Returns
+    /// the new instance, together with a step that announces our epoch to the peers.
+    pub fn new<I>(algo: D, peer_ids: I) -> (Self, Step<D>)
+    where
+        I: IntoIterator<Item = D::NodeId>,
+    {
+        let epoch = algo.epoch();
+        let peer_epochs = peer_ids
+            .into_iter()
+            .filter(|id| id != algo.our_id())
+            .map(|id| (id, HbEpoch::default()))
+            .collect();
+        let sender_queue = SenderQueue {
+            algo,
+            epoch,
+            peer_epochs,
+            outgoing_queue: BTreeMap::new(),
+            lag_threshold: None,
+            reported_lagging: BTreeSet::new(),
+            pending_lag_reports: Vec::new(),
+            max_queued_per_peer: None,
+        };
+        let step = Target::All.message(Message::EpochStarted(epoch)).into();
+        (sender_queue, step)
+    }
+
+    /// Returns a reference to the managed algorithm.
+    pub fn algo(&self) -> &D {
+        &self.algo
+    }
+
+    /// Returns a mutable reference to the managed algorithm.
+    ///
+    /// The messages in steps returned by the algorithm's own methods must be passed through
+    /// `process_step`, so that they are queued correctly.
+    pub fn algo_mut(&mut self) -> &mut D {
+        &mut self.algo
+    }
+
+    /// Returns the latest epoch announced by each peer.
+    pub fn peer_epochs(&self) -> &BTreeMap<D::NodeId, HbEpoch> {
+        &self.peer_epochs
+    }
+
+    /// Returns the peers whose latest announced epoch is more than `threshold` epochs behind ours.
+    pub fn lagging_peers(&self, threshold: u64) -> impl Iterator<Item = &D::NodeId> {
+        let min_epoch = self.epoch.saturating_sub(threshold);
+        self.peer_epochs
+            .iter()
+            .filter(move |&(_, epoch)| *epoch < min_epoch)
+            .map(|(id, _)| id)
+    }
+
+    /// Sets the number of epochs a peer can fall behind us before it is reported by
+    /// `take_lag_reports`. A peer is reported once when it falls behind, and again only if it has
+    /// caught up in between. `None`, the default, disables the reports.
+    pub fn set_lag_threshold(&mut self, lag_threshold: Option<u64>) {
+        self.lag_threshold = lag_threshold;
+    }
+
+    /// Returns the peers that have fallen behind since the last call, with their latest announced
+    /// epoch. The application can help them catch up out of band.
+    pub fn take_lag_reports(&mut self) -> Vec<(D::NodeId, HbEpoch)> {
+        mem::replace(&mut self.pending_lag_reports, Vec::new())
+    }
+
+    /// Sets the maximum number of messages that are held back for a single peer. Further messages
+    /// for a peer whose queue is full are dropped, so it has to catch up out of band, e.g. using a
+    /// snapshot. `None`, the default, means there is no limit.
+    pub fn set_max_queued_per_peer(&mut self, max_queued_per_peer: Option<usize>) {
+        self.max_queued_per_peer = max_queued_per_peer;
+    }
+
+    /// Returns the number of messages that are currently held back.
+    pub fn queued_messages(&self) -> usize {
+        self.outgoing_queue.values().map(Vec::len).sum()
+    }
+
+    /// Drops the announced epochs and queued messages of the given peers, which are not part of
+    /// the network anymore. If they announce a new epoch later, they are tracked again.
+    pub fn remove_peers<I>(&mut self, peer_ids: I)
+    where
+        I: IntoIterator<Item = D::NodeId>,
+    {
+        for id in peer_ids {
+            self.peer_epochs.remove(&id);
+            self.outgoing_queue.remove(&id);
+            self.reported_lagging.remove(&id);
+        }
+    }
+
+    /// Converts a step of the managed algorithm: Wraps the messages that can be sent right away,
+    /// queues the ones that are premature for some recipients, and announces our new epoch, if it
+    /// has changed. The output, fault and validation logs and timer requests are kept unchanged.
+    pub fn process_step(&mut self, step: ::Step<D>) -> Step<D> {
+        let mut sq_step = Step::default();
+        let removed: Vec<D::NodeId> = step
+            .output
+            .iter()
+            .flat_map(|output| self.algo.removed_peers(output))
+            .collect();
+        self.remove_peers(removed);
+        sq_step.output.extend(step.output);
+        sq_step.fault_log.extend(step.fault_log);
+        sq_step.validation.extend(step.validation);
+        sq_step.timers.extend(step.timers);
+        for tmsg in step.messages {
+            self.send_message(tmsg, &mut sq_step);
+        }
+        let epoch = self.algo.epoch();
+        if epoch > self.epoch {
+            self.epoch = epoch;
+            let msg = Target::All.message(Message::EpochStarted(epoch));
+            sq_step.messages.push_back(msg);
+            self.report_lagging_peers();
+        }
+        sq_step
+    }
+
+    /// Adds the peers that have fallen more than `lag_threshold` epochs behind, and haven't been
+    /// reported yet, to the pending reports.
+    fn report_lagging_peers(&mut self) {
+        let threshold = match self.lag_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        let newly_lagging: Vec<D::NodeId> = self
+            .lagging_peers(threshold)
+            .filter(|id| !self.reported_lagging.contains(*id))
+            .cloned()
+            .collect();
+        for id in newly_lagging {
+            let peer_epoch = self.peer_epochs.get(&id).cloned().unwrap_or_default();
+            self.reported_lagging.insert(id.clone());
+            self.pending_lag_reports.push((id, peer_epoch));
+        }
+    }
+
+    /// Records the peer's new epoch and sends it all queued messages that are not premature
+    /// anymore.
+    fn handle_epoch_started(&mut self, sender_id: &D::NodeId, epoch: HbEpoch) -> Step<D> {
+        {
+            let peer_epoch = self
+                .peer_epochs
+                .entry(sender_id.clone())
+                .or_insert_with(HbEpoch::default);
+            if epoch <= *peer_epoch {
+                return Step::default();
+            }
+            *peer_epoch = epoch;
+        }
+        if let Some(threshold) = self.lag_threshold {
+            if epoch >= self.epoch.saturating_sub(threshold) {
+                self.reported_lagging.remove(sender_id);
+            }
+        }
+        let queue = match self.outgoing_queue.remove(sender_id) {
+            None => return Step::default(),
+            Some(queue) => queue,
+        };
+        let mut step = Step::default();
+        let mut remaining = Vec::new();
+        for msg in queue {
+            if self.algo.is_premature(&msg, epoch) {
+                remaining.push(msg);
+            } else {
+                // The last recipient takes the message, the others clone it.
+                let msg = Arc::try_unwrap(msg).unwrap_or_else(|msg| (*msg).clone());
+                let target = Target::Node(sender_id.clone());
+                step.messages.push_back(target.message(Message::Algo(msg)));
+            }
+        }
+        if !remaining.is_empty() {
+            self.outgoing_queue.insert(sender_id.clone(), remaining);
+        }
+        step
+    }
+
+    /// Sends the message to all recipients that are ready for it, and queues it for the others.
+    fn send_message(&mut self, tmsg: TargetedMessage<D::Message, D::NodeId>, step: &mut Step<D>) {
+        let TargetedMessage { target, message } = tmsg;
+        // For messages that are also addressed to observers, the original target is kept if no
+        // recipient is lagging.
+        let (recipients, all_target): (BTreeSet<D::NodeId>, _) = match target {
+            Target::All => (self.peer_epochs.keys().cloned().collect(), Some(Target::All)),
+            Target::AllExcept(exclude) => {
+                let recipients = self
+                    .peer_epochs
+                    .keys()
+                    .filter(|id| !exclude.contains(id))
+                    .cloned()
+                    .collect();
+                (recipients, Some(Target::AllExcept(exclude)))
+            }
+            Target::Node(id) => (Some(id).into_iter().collect(), None),
+            Target::Nodes(ids) => (ids, None),
+            Target::Observers => {
+                // Observers don't take part in the consensus and are not tracked.
+                step.messages
+                    .push_back(Target::Observers.message(Message::Algo(message)));
+                return;
+            }
+        };
+        let (deferred, ready): (BTreeSet<_>, BTreeSet<_>) = recipients
+            .into_iter()
+            .partition(|id| self.is_premature_for(&message, id));
+        if !deferred.is_empty() {
+            let shared = Arc::new(message.clone());
+            let max_queued = self.max_queued_per_peer;
+            for id in &deferred {
+                let queue = self.outgoing_queue.entry(id.clone()).or_insert_with(Vec::new);
+                if max_queued.map_or(true, |max| queue.len() < max) {
+                    queue.push(shared.clone());
+                }
+            }
+        }
+        let target = if let (Some(target), true) = (all_target, deferred.is_empty()) {
+            target
+        } else if ready.is_empty() {
+            return;
+        } else if ready.len() == 1 {
+            Target::Node(ready.into_iter().next().expect("one recipient"))
+        } else {
+            Target::Nodes(ready)
+        };
+        step.messages.push_back(target.message(Message::Algo(message)));
+    }
+
+    /// Returns `true` if the peer is not ready for the message yet.
+    fn is_premature_for(&self, message: &D::Message, id: &D::NodeId) -> bool {
+        let them = self.peer_epochs.get(id).cloned().unwrap_or_default();
+        self.algo.is_premature(message, them)
+    }
+}
@@ -0,0 +1,118 @@
+//! # Pure state machines
+//!
+//! The algorithms' `DistAlgorithm` methods update the instance in place. External tools that
+//! explore an algorithm's state space, like model checkers or trace validators, instead need a
+//! transition function that leaves the old state intact, so that they can try several events in
+//! the same state.
+//!
+//! For the deterministic algorithms, i.e. the ones that implement `PureAlgorithm`, `step`
+//! provides exactly that: It takes a state and an `Event`, and returns the new state together with
+//! the resulting `Step`, i.e. the outputs, messages, faults and timer requests. It doesn't use a
+//! random number generator or the system clock, and given the same state and events, it always
+//! returns the same results.
+//!
+//! The states don't implement `Eq` or `Hash`: Tools that need to detect repeated states should
+//! compare the outputs and messages, or the encoded `Restore` snapshots where available.
+//!
+//! Metrics, the Binary Agreement slow epochs hook, the coin cache and the `CryptoOffload` used by
+//! `Coin` and `ThresholdDecryption` are shared with the surrounding code rather than being part
+//! of the state: A state and its successor refer to the same ones, and snapshots don't include
+//! them. The metrics and the hook are only side effects, but a coin cache makes the transitions
+//! depend on other instances, and a custom `CryptoOffload` can make them depend on earlier
+//! verifications. Neither should be set on instances used with `step`, which should keep the
+//! default `InlineOffload`.
+//!
+//! `HoneyBadger` and the algorithms built on it are not pure: They encrypt contributions with
+//! random values from their random number generator, so the same input results in different
+//! messages every time. That even applies with `EncryptionSchedule::Never`, since the schedule is
+//! a setting, not part of the type. `DynamicHoneyBadger` also uses randomness for key generation.
+//!
+//! This module is only available with the `state-machine` feature.
+
+use std::fmt::{self, Debug};
+
+use binary_agreement::{BinaryAgreement, Nonce};
+use broadcast::Broadcast;
+use coin::{Coin, CoinSource, InsecureCoin};
+use subset::Subset;
+use threshold_decryption::ThresholdDecryption;
+use {DistAlgorithm, NodeIdT, Step};
+
+/// An event that triggers a state transition.
+pub enum Event<D: DistAlgorithm> {
+    /// An input provided by the user.
+    Input(D::Input),
+    /// A message received from the given node.
+    Message(D::NodeId, D::Message),
+    /// The expiry of a timer with the given token.
+    Timeout(u64),
+}
+
+impl<D: DistAlgorithm> Clone for Event<D>
+where
+    D::Input: Clone,
+    D::Message: Clone,
+{
+    fn clone(&self) -> Self {
+        match *self {
+            Event::Input(ref input) => Event::Input(input.clone()),
+            Event::Message(ref sender_id, ref msg) => {
+                Event::Message(sender_id.clone(), msg.clone())
+            }
+            Event::Timeout(token) => Event::Timeout(token),
+        }
+    }
+}
+
+impl<D: DistAlgorithm> Debug for Event<D>
+where
+    D::Input: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Input(ref input) => f.debug_tuple("Input").field(input).finish(),
+            Event::Message(ref sender_id, ref msg) => {
+                f.debug_tuple("Message").field(sender_id).field(msg).finish()
+            }
+            Event::Timeout(token) => f.debug_tuple("Timeout").field(&token).finish(),
+        }
+    }
+}
+
+/// An algorithm whose state transitions only depend on its state and the event: It doesn't use
+/// randomness or the clock, so it can be used with `step`.
+pub trait PureAlgorithm: DistAlgorithm + Clone {}
+
+impl<N, T> PureAlgorithm for Coin<N, T>
+where
+    N: NodeIdT,
+    T: Clone + AsRef<[u8]> + Send + Sync,
+{
+}
+
+impl<N: NodeIdT> PureAlgorithm for InsecureCoin<N> {}
+
+impl<N: NodeIdT> PureAlgorithm for ThresholdDecryption<N> {}
+
+impl<N, S> PureAlgorithm for BinaryAgreement<N, S>
+where
+    N: NodeIdT,
+    S: CoinSource<N, Nonce> + PureAlgorithm,
+{
+}
+
+impl<N: NodeIdT> PureAlgorithm for Broadcast<N> {}
+
+impl<N: NodeIdT> PureAlgorithm for Subset<N> {}
+
+/// Applies the event to a copy of the given state, and returns the new state and the resulting
+/// step. The given state is left unchanged, also if the transition fails.
+pub fn step<D: PureAlgorithm>(state: &D, event: Event<D>) -> Result<(D, Step<D>), D::Error> {
+    let mut next = state.clone();
+    let step = match event {
+        Event::Input(input) => next.handle_input(input)?,
+        Event::Message(sender_id, msg) => next.handle_message(&sender_id, msg)?,
+        Event::Timeout(token) => next.handle_timeout(token)?,
+    };
+    Ok((next, step))
+}
@@ -22,17 +22,38 @@
 //! remaining ones, where we haven't provided input yet.
 //! * Once all `BinaryAgreement` instances have decided, `Subset` returns the set of all proposed
 //! values for which the decision was "yes".
+//!
+//! In large networks, many `BinaryAgreement` instances often send messages at the same time. If
+//! message coalescing is enabled, all Binary Agreement messages with the same target in one step
+//! are sent as a single `BinaryAgreements` message.
+//!
+//! Until a `Broadcast` instance outputs, it keeps the shards it receives in memory. Faulty
+//! proposers can therefore make every node buffer up to _f_ large values that are never decided.
+//! With `Subset::set_max_buffered_bytes`, the total size of the buffered shards in all instances
+//! is limited: If it is exceeded, the instances that haven't sent `Ready` yet and whose proposers
+//! haven't been accepted by Binary Agreement are abandoned, largest first, and their proposers are
+//! reported as faulty.
+//!
+//! With `Subset::set_abort_undecided`, the remaining `BinaryAgreement` instances are aborted
+//! instead of given "no" once _N - f_ instances have decided "yes", and their proposers are
+//! rejected.
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::result;
 use std::sync::Arc;
 
+use byteorder::{BigEndian, ByteOrder};
 use hex_fmt::HexFmt;
 
-use binary_agreement::{self, BinaryAgreement};
-use broadcast::{self, Broadcast};
-use rand::Rand;
-use {DistAlgorithm, NetworkInfo, NodeIdT};
+use binary_agreement::{self, BinaryAgreement, BinaryAgreementSnapshot};
+use broadcast::{self, Broadcast, BroadcastSnapshot};
+use coin::CoinCache;
+use environment::{self, Environment, Restore};
+use metrics::Metrics;
+#[cfg(feature = "testing")]
+use rand::{Rand, Rng};
+use validation::Validity;
+use {DistAlgorithm, NetworkInfo, NodeIdT, Target, TargetedMessage};
 
 /// A subset error.
 #[derive(Clone, PartialEq, Debug, Fail)]
@@ -47,33 +68,98 @@ pub enum Error {
     NewBroadcast(broadcast::Error),
     #[fail(display = "ProcessBroadcastBroadcast error: {}", _0)]
     ProcessBroadcastBroadcast(broadcast::Error),
-    #[fail(display = "Multiple Binary Agreement results")]
-    MultipleBinaryAgreementResults,
     #[fail(display = "No such Binary Agreement instance")]
     NoSuchBinaryAgreementInstance,
     #[fail(display = "No such broadcast instance")]
     NoSuchBroadcastInstance,
 }
 
+impl Error {
+    /// Returns `true` if the instance cannot produce its output anymore. Errors from a single
+    /// Broadcast or Binary Agreement instance are fatal if they are fatal for that instance.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::ProcessBinaryAgreement0(ref err) | Error::ProcessBinaryAgreement1(ref err) => {
+                err.is_fatal()
+            }
+            Error::ProcessBroadcastBroadcast(ref err) => err.is_fatal(),
+            Error::NewBinaryAgreement(_)
+            | Error::NewBroadcast(_)
+            | Error::NoSuchBinaryAgreementInstance
+            | Error::NoSuchBroadcastInstance => true,
+        }
+    }
+}
+
+/// Represents each reason why a Subset message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message referred to a proposer that is not a validator.
+    UnknownProposer,
+    /// A proposer's broadcast exceeded the limit of buffered bytes.
+    BroadcastBufferExceeded,
+    /// A fault in one of the Broadcast instances.
+    Broadcast(broadcast::FaultKind),
+    /// A fault in one of the Binary Agreement instances.
+    BinaryAgreement(binary_agreement::FaultKind),
+}
+
+impl From<broadcast::FaultKind> for FaultKind {
+    fn from(fault_kind: broadcast::FaultKind) -> FaultKind {
+        FaultKind::Broadcast(fault_kind)
+    }
+}
+
+impl From<binary_agreement::FaultKind> for FaultKind {
+    fn from(fault_kind: binary_agreement::FaultKind) -> FaultKind {
+        FaultKind::BinaryAgreement(fault_kind)
+    }
+}
+
 /// A subset result.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::subset";
+
 // TODO: Make this a generic argument of `Subset`.
 type ProposedValue = Vec<u8>;
 
 /// Message from Subset to remote nodes.
-#[derive(Serialize, Deserialize, Clone, Debug, Rand)]
-pub enum Message<N: Rand> {
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Message<N> {
     /// A message for the broadcast algorithm concerning the set element proposed by the given node.
     Broadcast(N, broadcast::Message),
     /// A message for the Binary Agreement algorithm concerning the set element proposed by the given
     /// node.
     BinaryAgreement(N, binary_agreement::Message),
+    /// Several Binary Agreement messages, sent together. They are handled in order, as if they
+    /// had been received separately.
+    BinaryAgreements(Vec<(N, binary_agreement::Message)>),
+}
+
+#[cfg(feature = "testing")]
+impl<N: Rand> Rand for Message<N> {
+    fn rand<R: Rng>(rng: &mut R) -> Self {
+        match rng.gen_range(0, 3) {
+            0 => Message::Broadcast(rng.gen(), rng.gen()),
+            1 => Message::BinaryAgreement(rng.gen(), rng.gen()),
+            _ => {
+                let len = rng.gen_range(0, 4);
+                Message::BinaryAgreements(rng.gen_iter().take(len).collect())
+            }
+        }
+    }
 }
 
 /// Subset algorithm instance
-#[derive(Debug)]
-pub struct Subset<N: Rand> {
+///
+/// Instances can be cloned.
+///
+/// If handling an input or message fails with a fatal error, all Broadcast and Binary Agreement
+/// instances abort, so the instance terminates without output and is never left partially updated.
+#[derive(Clone, Debug)]
+pub struct Subset<N> {
     /// Shared network information.
     netinfo: Arc<NetworkInfo<N>>,
     broadcast_instances: BTreeMap<N, Broadcast<N>>,
@@ -83,20 +169,51 @@ pub struct Subset<N: Rand> {
     ba_results: BTreeMap<N, bool>,
     /// Whether the instance has decided on a value.
     decided: bool,
+    /// Whether to combine the Binary Agreement messages in each step.
+    coalesce_messages: bool,
+    /// The maximum total size of the shards buffered in all broadcast instances, in bytes.
+    max_buffered_bytes: Option<usize>,
+    /// The size of the shards buffered in each broadcast instance, in bytes.
+    buffered_bytes: BTreeMap<N, usize>,
+    /// Whether to abort the undecided Binary Agreement instances once enough have decided "yes".
+    abort_undecided: bool,
+}
+
+/// A serializable snapshot of a `Subset` instance, including its `Broadcast` and
+/// `BinaryAgreement` instances, without its environment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+pub struct SubsetSnapshot<N> {
+    broadcast_instances: BTreeMap<N, BroadcastSnapshot<N>>,
+    ba_instances: BTreeMap<N, BinaryAgreementSnapshot<N>>,
+    broadcast_results: BTreeMap<N, Option<ProposedValue>>,
+    ba_results: BTreeMap<N, bool>,
+    decided: bool,
+    coalesce_messages: bool,
+    max_buffered_bytes: Option<usize>,
+    buffered_bytes: BTreeMap<N, usize>,
+    abort_undecided: bool,
 }
 
 pub type Step<N> = ::Step<Subset<N>>;
 
-impl<N: NodeIdT + Rand> DistAlgorithm for Subset<N> {
+impl<N: NodeIdT> DistAlgorithm for Subset<N> {
     type NodeId = N;
     type Input = ProposedValue;
     type Output = SubsetOutput<N>;
     type Message = Message<N>;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<N>> {
-        debug!("{:?} Proposing {:?}", self.netinfo.our_id(), HexFmt(&input));
-        self.send_proposed_value(input)
+        debug_event!(
+            "Proposing",
+            node = self.netinfo.our_id(),
+            value = HexFmt(&input),
+        );
+        let result = self.send_proposed_value(input);
+        let step = self.abort_on_error(result)?;
+        Ok(self.coalesce(step))
     }
 
     fn handle_message(
@@ -104,12 +221,15 @@ impl<N: NodeIdT + Rand> DistAlgorithm for Subset<N> {
         sender_id: &Self::NodeId,
         message: Self::Message,
     ) -> Result<Step<N>> {
-        match message {
+        let result = match message {
             Message::Broadcast(p_id, b_msg) => self.handle_broadcast(sender_id, &p_id, b_msg),
             Message::BinaryAgreement(p_id, a_msg) => {
                 self.handle_binary_agreement(sender_id, &p_id, a_msg)
             }
-        }
+            Message::BinaryAgreements(msgs) => self.handle_binary_agreements(sender_id, msgs),
+        };
+        let step = self.abort_on_error(result)?;
+        Ok(self.coalesce(step))
     }
 
     fn terminated(&self) -> bool {
@@ -121,13 +241,70 @@ impl<N: NodeIdT + Rand> DistAlgorithm for Subset<N> {
     }
 }
 
+/// An output of the `Subset` algorithm. The accepted contributions are output one by one, as
+/// soon as their broadcast and agreement instances have completed, so that the caller can start
+/// processing them before the whole subset is known.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SubsetOutput<N> {
+    /// The contribution of the given proposer, which is part of the subset.
     Contribution(N, Vec<u8>),
+    /// The subset is complete: All contributions have been output. This is the last output.
     Done,
 }
 
-impl<N: NodeIdT + Rand> Subset<N> {
+impl<N: NodeIdT> Restore<N> for Subset<N> {
+    type Snapshot = SubsetSnapshot<N>;
+
+    fn snapshot(&self) -> SubsetSnapshot<N> {
+        SubsetSnapshot {
+            broadcast_instances: snapshot_all(&self.broadcast_instances),
+            ba_instances: snapshot_all(&self.ba_instances),
+            broadcast_results: self.broadcast_results.clone(),
+            ba_results: self.ba_results.clone(),
+            decided: self.decided,
+            coalesce_messages: self.coalesce_messages,
+            max_buffered_bytes: self.max_buffered_bytes,
+            abort_undecided: self.abort_undecided,
+            buffered_bytes: self.buffered_bytes.clone(),
+        }
+    }
+
+    fn restore(snapshot: SubsetSnapshot<N>, env: &Environment<N>) -> environment::Result<Self> {
+        Ok(Subset {
+            netinfo: env.netinfo.clone(),
+            broadcast_instances: restore_all(snapshot.broadcast_instances, env)?,
+            ba_instances: restore_all(snapshot.ba_instances, env)?,
+            broadcast_results: snapshot.broadcast_results,
+            ba_results: snapshot.ba_results,
+            decided: snapshot.decided,
+            coalesce_messages: snapshot.coalesce_messages,
+            max_buffered_bytes: snapshot.max_buffered_bytes,
+            abort_undecided: snapshot.abort_undecided,
+            buffered_bytes: snapshot.buffered_bytes,
+        })
+    }
+}
+
+/// Returns the snapshots of all instances in the map.
+fn snapshot_all<N: NodeIdT, D: Restore<N>>(instances: &BTreeMap<N, D>) -> BTreeMap<N, D::Snapshot> {
+    instances
+        .iter()
+        .map(|(id, instance)| (id.clone(), instance.snapshot()))
+        .collect()
+}
+
+/// Restores all instances in the map.
+fn restore_all<N: NodeIdT, D: Restore<N>>(
+    snapshots: BTreeMap<N, D::Snapshot>,
+    env: &Environment<N>,
+) -> environment::Result<BTreeMap<N, D>> {
+    snapshots
+        .into_iter()
+        .map(|(id, snapshot)| Ok((id, D::restore(snapshot, env)?)))
+        .collect()
+}
+
+impl<N: NodeIdT> Subset<N> {
     pub fn new(netinfo: Arc<NetworkInfo<N>>, session_id: u64) -> Result<Self> {
         // Create all broadcast instances.
         let mut broadcast_instances: BTreeMap<N, Broadcast<N>> = BTreeMap::new();
@@ -140,11 +317,13 @@ impl<N: NodeIdT + Rand> Subset<N> {
         }
 
         // Create all Binary Agreement instances.
+        let mut ba_session_id = [0; 8];
+        BigEndian::write_u64(&mut ba_session_id, session_id);
         let mut ba_instances: BTreeMap<N, BinaryAgreement<N>> = BTreeMap::new();
         for proposer_id in netinfo.all_ids() {
             ba_instances.insert(
                 proposer_id.clone(),
-                BinaryAgreement::new(netinfo.clone(), session_id, proposer_id.clone())
+                BinaryAgreement::new(netinfo.clone(), ba_session_id, proposer_id.clone())
                     .map_err(Error::NewBinaryAgreement)?,
             );
         }
@@ -156,9 +335,67 @@ impl<N: NodeIdT + Rand> Subset<N> {
             broadcast_results: BTreeMap::new(),
             ba_results: BTreeMap::new(),
             decided: false,
+            coalesce_messages: false,
+            max_buffered_bytes: None,
+            buffered_bytes: BTreeMap::new(),
+            abort_undecided: false,
         })
     }
 
+    /// Sets whether the Binary Agreement messages with the same target that are produced in the
+    /// same step should be combined into a single `BinaryAgreements` message. By default, they
+    /// are sent separately.
+    pub fn set_coalesce_messages(&mut self, coalesce_messages: bool) {
+        self.coalesce_messages = coalesce_messages;
+    }
+
+    /// Sets the maximum total size in bytes of the shards that all broadcast instances together
+    /// keep in memory. By default, there is no limit.
+    ///
+    /// If the limit is exceeded, the instances that haven't sent `Ready` yet and whose proposers
+    /// haven't been accepted are abandoned, largest first, and their proposers are reported as
+    /// faulty. The limit must be large enough for the values of all correct proposers: If an
+    /// abandoned proposal is accepted by the other nodes, this instance never completes.
+    pub fn set_max_buffered_bytes(&mut self, max_buffered_bytes: usize) {
+        self.max_buffered_bytes = Some(max_buffered_bytes);
+    }
+
+    /// Sets whether the Binary Agreement instances that haven't decided yet are aborted, and their
+    /// proposers rejected, once instances with a total weight of at least _W - f_ have decided
+    /// "yes". By default, they are given "no" as input instead, and run until they decide.
+    ///
+    /// Aborting frees the instances' state and stops their messages. However, an instance that we
+    /// abort can still decide "yes" at other nodes, whose output then differs from ours: This must
+    /// only be enabled if the application doesn't rely on all nodes outputting the same set.
+    pub fn set_abort_undecided(&mut self, abort_undecided: bool) {
+        self.abort_undecided = abort_undecided;
+    }
+
+    /// Sets the receiver of measurements of the Binary Agreement instances. By default,
+    /// measurements are ignored.
+    pub fn set_metrics(&mut self, metrics: &Arc<dyn Metrics>) {
+        for ba in self.ba_instances.values_mut() {
+            ba.set_metrics(metrics.clone());
+        }
+    }
+
+    /// Sets the cache of combined coin signatures shared by the Binary Agreement instances. By
+    /// default, there is no cache.
+    pub fn set_coin_cache(&mut self, coin_cache: &Arc<CoinCache>) {
+        for ba in self.ba_instances.values_mut() {
+            ba.set_coin_cache(coin_cache.clone());
+        }
+    }
+
+    /// Sets the maximum number of epochs of each Binary Agreement instance. If one of them hasn't
+    /// decided by then, handling the message that would move it beyond the limit fails with its
+    /// `EpochLimitReached` error, and the instance aborts.
+    pub fn set_max_agreement_epochs(&mut self, max_epochs: u64) {
+        for ba in self.ba_instances.values_mut() {
+            ba.set_max_epochs(max_epochs);
+        }
+    }
+
     /// Subset input message handler. It receives a value for broadcast
     /// and redirects it to the corresponding broadcast instance.
     pub fn send_proposed_value(&mut self, value: ProposedValue) -> Result<Step<N>> {
@@ -170,6 +407,29 @@ impl<N: NodeIdT + Rand> Subset<N> {
         self.process_broadcast(&id, |bc| bc.handle_input(value))
     }
 
+    /// Aborts all Broadcast and Binary Agreement instances if the result is a fatal error, so that
+    /// the instance terminates without output instead of being left partially updated. The
+    /// non-fatal errors are detected before the state is changed.
+    fn abort_on_error(&mut self, result: Result<Step<N>>) -> Result<Step<N>> {
+        if let Err(ref err) = result {
+            if err.is_fatal() {
+                warn_event!(
+                    "Subset aborted",
+                    node = self.netinfo.our_id(),
+                    error = err,
+                );
+                for broadcast in self.broadcast_instances.values_mut() {
+                    broadcast.abandon();
+                }
+                for binary_agreement in self.ba_instances.values_mut() {
+                    binary_agreement.abort();
+                }
+                self.buffered_bytes.clear();
+            }
+        }
+        result
+    }
+
     /// Returns the number of validators from which we have already received a proposal.
     pub(crate) fn received_proposals(&self) -> usize {
         self.broadcast_results.len()
@@ -183,7 +443,62 @@ impl<N: NodeIdT + Rand> Subset<N> {
         proposer_id: &N,
         bmessage: broadcast::Message,
     ) -> Result<Step<N>> {
-        self.process_broadcast(proposer_id, |bc| bc.handle_message(sender_id, bmessage))
+        if !self.broadcast_instances.contains_key(proposer_id) {
+            let validity = Validity::Faulty(FaultKind::UnknownProposer);
+            return Ok(Step::validated(sender_id.clone(), validity));
+        }
+        let mut step =
+            self.process_broadcast(proposer_id, |bc| bc.handle_message(sender_id, bmessage))?;
+        step.extend(self.enforce_buffer_limit());
+        Ok(step)
+    }
+
+    /// If the shards buffered in all broadcast instances exceed the limit, abandons the largest
+    /// instances that haven't sent `Ready` yet and whose proposers haven't been accepted, until
+    /// the total is within the limit again, and reports their proposers.
+    fn enforce_buffer_limit(&mut self) -> Step<N> {
+        let mut step = Step::default();
+        let max_buffered_bytes = match self.max_buffered_bytes {
+            Some(max_buffered_bytes) => max_buffered_bytes,
+            None => return step,
+        };
+        let mut total: usize = self.buffered_bytes.values().sum();
+        if total <= max_buffered_bytes {
+            return step;
+        }
+        let our_id = self.netinfo.our_id();
+        let mut candidates: Vec<(usize, N)> = self
+            .broadcast_instances
+            .iter()
+            .filter(|&(id, bc)| {
+                id != our_id
+                    && !bc.terminated()
+                    && !bc.has_sent_ready()
+                    && self.ba_results.get(id) != Some(&true)
+            }).map(|(id, _)| (self.buffered_bytes.get(id).cloned().unwrap_or(0), id.clone()))
+            .filter(|&(bytes, _)| bytes > 0)
+            .collect();
+        // Evict the largest instances first.
+        candidates.sort_by(|a, b| b.cmp(a));
+        for (bytes, id) in candidates {
+            if total <= max_buffered_bytes {
+                break;
+            }
+            warn_event!(
+                "Broadcast buffer limit exceeded, abandoning proposal",
+                node = our_id,
+                proposer = id,
+                bytes = bytes,
+                total = total,
+            );
+            if let Some(broadcast) = self.broadcast_instances.get_mut(&id) {
+                broadcast.abandon();
+            }
+            self.buffered_bytes.remove(&id);
+            total -= bytes;
+            step.fault_log.append(id, FaultKind::BroadcastBufferExceeded);
+        }
+        step
     }
 
     /// Receives a Binary Agreement message from a remote node `sender_id` concerning
@@ -194,12 +509,29 @@ impl<N: NodeIdT + Rand> Subset<N> {
         proposer_id: &N,
         amessage: binary_agreement::Message,
     ) -> Result<Step<N>> {
+        if !self.ba_instances.contains_key(proposer_id) {
+            let validity = Validity::Faulty(FaultKind::UnknownProposer);
+            return Ok(Step::validated(sender_id.clone(), validity));
+        }
         // Send the message to the local instance of Binary Agreement.
         self.process_binary_agreement(proposer_id, |binary_agreement| {
             binary_agreement.handle_message(sender_id, amessage)
         })
     }
 
+    /// Receives a batch of coalesced Binary Agreement messages from a remote node `sender_id`.
+    fn handle_binary_agreements(
+        &mut self,
+        sender_id: &N,
+        msgs: Vec<(N, binary_agreement::Message)>,
+    ) -> Result<Step<N>> {
+        let mut step = Step::default();
+        for (p_id, a_msg) in msgs {
+            step.extend(self.handle_binary_agreement(sender_id, &p_id, a_msg)?);
+        }
+        Ok(step)
+    }
+
     /// Upon delivery of v_j from RBC_j, if input has not yet been provided to
     /// BA_j, then provide input 1 to BA_j. See Figure 11.
     fn process_broadcast<F>(&mut self, proposer_id: &N, f: F) -> Result<Step<N>>
@@ -213,10 +545,14 @@ impl<N: NodeIdT + Rand> Subset<N> {
                 .get_mut(proposer_id)
                 .ok_or(Error::NoSuchBroadcastInstance)?;
             let to_msg = |b_msg| Message::Broadcast(proposer_id.clone(), b_msg);
-            let output = step.extend_with(
+            let (output, _) = step.extend_with(
                 f(broadcast).map_err(Error::ProcessBroadcastBroadcast)?,
                 to_msg,
             );
+            if self.max_buffered_bytes.is_some() {
+                let bytes = broadcast.buffered_bytes();
+                self.buffered_bytes.insert(proposer_id.clone(), bytes);
+            }
             if let Some(output) = output.into_iter().next() {
                 output
             } else {
@@ -225,7 +561,12 @@ impl<N: NodeIdT + Rand> Subset<N> {
         };
 
         let val_to_insert = if let Some(true) = self.ba_results.get(proposer_id) {
-            debug!("    {:?} → {:?}", proposer_id, HexFmt(&value));
+            debug_event!(
+                "Contribution output",
+                node = self.netinfo.our_id(),
+                proposer = proposer_id,
+                value = HexFmt(&value),
+            );
             step.output
                 .extend(Some(SubsetOutput::Contribution(proposer_id.clone(), value)));
             None
@@ -233,11 +574,15 @@ impl<N: NodeIdT + Rand> Subset<N> {
             Some(value)
         };
 
-        if let Some(inval) = self
+        if self
             .broadcast_results
             .insert(proposer_id.clone(), val_to_insert)
+            .is_some()
         {
-            error!("Duplicate insert in broadcast_results: {:?}", inval)
+            error_event!(
+                "Duplicate insert in broadcast_results",
+                proposer = proposer_id
+            )
         }
         let set_binary_agreement_input = |ba: &mut BinaryAgreement<N>| {
             if ba.accepts_input() {
@@ -266,7 +611,7 @@ impl<N: NodeIdT + Rand> Subset<N> {
                 return Ok(step);
             }
             let to_msg = |a_msg| Message::BinaryAgreement(proposer_id.clone(), a_msg);
-            let output = step.extend_with(
+            let (output, _) = step.extend_with(
                 f(binary_agreement).map_err(Error::ProcessBinaryAgreement0)?,
                 to_msg,
             );
@@ -277,59 +622,116 @@ impl<N: NodeIdT + Rand> Subset<N> {
             }
         };
 
-        // Binary agreement result accepted.
-        if self
-            .ba_results
-            .insert(proposer_id.clone(), accepted)
-            .is_some()
-        {
-            return Err(Error::MultipleBinaryAgreementResults);
-        }
+        // Binary agreement result accepted. An instance outputs at most once, and terminates
+        // when it does, so this is the only result for that proposer.
+        self.ba_results.insert(proposer_id.clone(), accepted);
 
-        debug!(
-            "{:?} Updated Binary Agreement results: {:?}",
-            self.netinfo.our_id(),
-            self.ba_results
+        debug_event!(
+            "Updated Binary Agreement results",
+            node = self.netinfo.our_id(),
+            results = self.ba_results,
         );
 
         if accepted {
-            if self.count_true() == self.netinfo.num_correct() {
-                // Upon delivery of value 1 from at least N − f instances of BA, provide
-                // input 0 to each instance of BA that has not yet been provided input.
+            if self.accepted_weight() >= self.netinfo.correct_weight() {
+                // Upon delivery of value 1 from instances of BA with a total weight of at least
+                // W − f, provide input 0 to each instance of BA that has not yet been provided
+                // input.
                 for (id, binary_agreement) in &mut self.ba_instances {
-                    if binary_agreement.accepts_input() {
+                    if self.abort_undecided && !binary_agreement.terminated() {
+                        // The remaining instances are not needed: Stop them right away.
+                        binary_agreement.abort();
+                        self.ba_results.insert(id.clone(), false);
+                    } else if binary_agreement.accepts_input() {
                         let to_msg = |a_msg| Message::BinaryAgreement(id.clone(), a_msg);
-                        for output in step.extend_with(
-                            binary_agreement
-                                .handle_input(false)
-                                .map_err(Error::ProcessBinaryAgreement1)?,
-                            to_msg,
-                        ) {
-                            if self.ba_results.insert(id.clone(), output).is_some() {
-                                return Err(Error::MultipleBinaryAgreementResults);
-                            }
+                        let ba_step = binary_agreement
+                            .handle_input(false)
+                            .map_err(Error::ProcessBinaryAgreement1)?;
+                        let (output, _) = step.extend_with(ba_step, to_msg);
+                        for output in output {
+                            self.ba_results.insert(id.clone(), output);
                         }
                     }
                 }
             }
             if let Some(Some(value)) = self.broadcast_results.insert(proposer_id.clone(), None) {
-                debug!("    {:?} → {:?}", proposer_id, HexFmt(&value));
+                debug_event!(
+                    "Contribution output",
+                    node = self.netinfo.our_id(),
+                    proposer = proposer_id,
+                    value = HexFmt(&value),
+                );
                 step.output
                     .extend(Some(SubsetOutput::Contribution(proposer_id.clone(), value)));
             }
         }
 
+        // The values of rejected proposers are not needed anymore: Stop waiting for them.
+        for (id, _) in self.ba_results.iter().filter(|&(_, accepted)| !*accepted) {
+            if let Some(broadcast) = self.broadcast_instances.get_mut(id) {
+                broadcast.abandon();
+            }
+            self.buffered_bytes.remove(id);
+            self.broadcast_results.remove(id);
+        }
+
         step.output.extend(self.try_binary_agreement_completion());
         Ok(step)
     }
 
-    /// Returns the number of Binary Agreement instances that have decided "yes".
-    fn count_true(&self) -> usize {
-        self.ba_results.values().filter(|v| **v).count()
+    /// If coalescing is enabled, replaces the Binary Agreement messages in the step with one
+    /// `BinaryAgreements` message per target, in the position of the first one.
+    fn coalesce(&self, mut step: Step<N>) -> Step<N> {
+        if !self.coalesce_messages {
+            return step;
+        }
+        let mut messages = VecDeque::with_capacity(step.messages.len());
+        let mut batches: Vec<(Target<N>, usize, Vec<_>)> = Vec::new();
+        for tm in step.messages.drain(..) {
+            match tm.message {
+                Message::BinaryAgreement(p_id, a_msg) => {
+                    let pos = batches.iter().position(|batch| batch.0 == tm.target);
+                    match pos {
+                        Some(i) => batches[i].2.push((p_id, a_msg)),
+                        None => {
+                            batches.push((tm.target, messages.len(), vec![(p_id, a_msg)]));
+                            // A placeholder, which is replaced below.
+                            messages.push_back(None);
+                        }
+                    }
+                }
+                message => messages.push_back(Some(TargetedMessage {
+                    target: tm.target,
+                    message,
+                })),
+            }
+        }
+        for (target, i, mut msgs) in batches {
+            let message = if msgs.len() == 1 {
+                let (p_id, a_msg) = msgs.remove(0);
+                Message::BinaryAgreement(p_id, a_msg)
+            } else {
+                Message::BinaryAgreements(msgs)
+            };
+            messages[i] = Some(target.message(message));
+        }
+        step.messages = messages.into_iter().flatten().collect();
+        step
+    }
+
+    /// Returns the total weight of the proposers whose Binary Agreement instances have decided
+    /// "yes".
+    fn accepted_weight(&self) -> u64 {
+        let accepted_ids = self
+            .ba_results
+            .iter()
+            .filter(|&(_, accepted)| *accepted)
+            .map(|(id, _)| id);
+        self.netinfo.weight_of(accepted_ids)
     }
 
     fn try_binary_agreement_completion(&mut self) -> Option<SubsetOutput<N>> {
-        if self.decided || self.count_true() < self.netinfo.num_correct() {
+        if self.decided || self.accepted_weight() < self.netinfo.correct_weight() {
             return None;
         }
         // Once all instances of BA have completed, let C ⊂ [1..N] be
@@ -338,9 +740,9 @@ impl<N: NodeIdT + Rand> Subset<N> {
         if self.ba_results.len() < self.netinfo.num_nodes() {
             return None;
         }
-        debug!(
-            "{:?} All Binary Agreement instances have terminated",
-            self.netinfo.our_id()
+        debug_event!(
+            "All Binary Agreement instances have terminated",
+            node = self.netinfo.our_id(),
         );
         // All instances of BinaryAgreement that delivered `true` (or "1" in the paper).
         let delivered_1: BTreeSet<&N> = self
@@ -349,9 +751,10 @@ impl<N: NodeIdT + Rand> Subset<N> {
             .filter(|(_, v)| **v)
             .map(|(k, _)| k)
             .collect();
-        debug!(
-            "Binary Agreement instances that delivered 1: {:?}",
-            delivered_1
+        debug_event!(
+            "Binary Agreement instances that delivered 1",
+            node = self.netinfo.our_id(),
+            proposers = delivered_1,
         );
 
         // Results of Broadcast instances in `delivered_1`
@@ -363,9 +766,9 @@ impl<N: NodeIdT + Rand> Subset<N> {
             .collect();
 
         if delivered_1.len() == broadcast_results.len() {
-            debug!(
-                "{:?} Binary Agreement instances completed:",
-                self.netinfo.our_id()
+            debug_event!(
+                "Binary Agreement instances completed",
+                node = self.netinfo.our_id(),
             );
             self.decided = true;
             Some(SubsetOutput::Done)
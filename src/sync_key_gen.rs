@@ -43,10 +43,40 @@
 //! `SyncKeyGen::is_node_ready` can be used to check whether a particluar node's `Part` is
 //! complete.
 //!
+//! If the row encrypted to a node is malformed or doesn't match the commitment, that node
+//! multicasts a `Complaint` instead of an `Ack`. The dealer must then answer with a
+//! `Justification` that reveals the complainer's row in plain text, so that every node can verify
+//! it against the commitment. If it matches, the complainer sends its `Ack` after all. If it
+//! doesn't, every node marks the dealer's `Part` as faulty. A `Part` with an unanswered
+//! `Complaint` is never considered complete, so all nodes exclude the same dealers from key
+//! generation. See `SyncKeyGen::handle_complaint` and `SyncKeyGen::handle_justification`.
+//!
 //! Finally, observer nodes can also use `SyncKeyGen`. For observers, no `Part` and `Ack`
 //! messages will be created and they do not need to send anything. On completion, they will only
 //! receive the public key set, but no secret key share.
 //!
+//! ## Resharing
+//!
+//! `SyncKeyGen::new` always produces a _new_ master key. To deal shares of an _existing_ master key
+//! to a new set of validators instead, create the instances with `SyncKeyGen::new_resharing`. More
+//! than _t_ old validators, where _t_ is the old key set's threshold, act as dealers. They must all
+//! take part in the new key generation. Each one uses its old secret key share, multiplied by its
+//! Lagrange coefficient, as the constant term of its bivariate polynomial, so that the sum of the
+//! dealers' polynomials has the old master key as its constant term. Since that constant term is
+//! public in the exponent, every node can verify each dealer's `Part` against the old public key
+//! set. The keys can only be generated once _all_ dealers' `Part`s are complete: If one of them is
+//! faulty, the resharing has to be repeated with a different set of dealers.
+//!
+//! ## Proactive share refresh
+//!
+//! To limit the time an adversary has to collect more than _t_ secret key shares, the validators
+//! can periodically replace their shares with new ones, without changing the master key or the
+//! set of validators. Create the instances with `SyncKeyGen::new_refresh` for that: Every
+//! validator deals a sharing of _zero_, i.e. its polynomial's constant term is zero, which every
+//! node verifies in the exponent. `generate` then adds the sum of the complete parts to the
+//! existing keys. The old shares don't combine with the new ones, so they become useless to an
+//! adversary once all correct validators have deleted them.
+//!
 //! ## Example
 //!
 //! ```
@@ -91,6 +121,7 @@
 //!         match node.handle_part(&mut rng, &sender_id, part.clone()) {
 //!             Some(PartOutcome::Valid(ack)) => acks.push((id, ack)),
 //!             Some(PartOutcome::Invalid(faults)) => panic!("Invalid part: {:?}", faults),
+//!             Some(PartOutcome::Complaint(complaint)) => panic!("Bad row: {:?}", complaint),
 //!             None => panic!("We are not an observer, so we should send Ack."),
 //!         }
 //!     }
@@ -166,19 +197,22 @@ use std::fmt::{self, Debug, Formatter};
 use bincode;
 use crypto::{
     error::Error as CryptoError,
-    poly::{BivarCommitment, BivarPoly, Poly},
+    poly::{BivarCommitment, BivarPoly, Commitment, Poly},
     serde_impl::field_vec::FieldWrap,
     Ciphertext, PublicKey, PublicKeySet, SecretKey, SecretKeyShare,
 };
 use crypto::{Fr, G1Affine};
-use pairing::{CurveAffine, Field};
+use pairing::{CurveAffine, CurveProjective, Field};
 use rand;
 
-use fault_log::{AckMessageFault as Fault, FaultKind, FaultLog};
+use fault_log::{self, AckMessageFault as Fault, FaultLog};
 use {NetworkInfo, NodeIdT};
 
 // TODO: No need to send our own row and value to ourselves.
 
+/// The target of log messages and spans.
+const LOG_TARGET: &str = "hbbft::sync_key_gen";
+
 // A sync-key-gen error.
 #[derive(Clone, Eq, PartialEq, Debug, Fail)]
 pub enum Error {
@@ -188,6 +222,31 @@ pub enum Error {
     Generation(CryptoError),
     #[fail(display = "Error acknowledging part: {}", _0)]
     Ack(CryptoError),
+    #[fail(display = "Too few dealers to reshare the existing key")]
+    TooFewDealers,
+    #[fail(display = "A dealer does not take part in the key generation")]
+    UnknownDealer,
+    #[fail(display = "The existing secret key share is missing")]
+    MissingKeyShare,
+    #[fail(display = "Not every dealer's part is complete")]
+    IncompleteResharing,
+    #[fail(display = "The existing keys could not be converted")]
+    KeyConversion,
+}
+
+/// Represents each reason why a key generation message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A `Part` message was invalid.
+    InvalidPartMessage,
+    /// An `Ack` message was invalid.
+    AckMessage(fault_log::AckMessageFault),
+    /// A complaint was sent although the part was valid for the sender, or by a non-validator.
+    UnexpectedComplaint,
+    /// A justification was sent that nobody complained about.
+    UnexpectedJustification,
+    /// A justification did not match the dealer's commitment.
+    InvalidJustification,
 }
 
 /// A submission by a validator for the key generation. It must to be sent to all participating
@@ -196,18 +255,28 @@ pub enum Error {
 /// The message contains a commitment to a bivariate polynomial, and for each node, an encrypted
 /// row of values. If this message receives enough `Ack`s, it will be used as summand to produce
 /// the the key set in the end.
+///
+/// It also contains a public offset that is added to the polynomial's constant term. It is zero
+/// for a new key, and otherwise brings the constant term to the value that the dealer must share.
+/// Since the polynomial's own constant term is random, the offset reveals nothing about it.
 #[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
-pub struct Part(BivarCommitment, Vec<Ciphertext>);
+pub struct Part(BivarCommitment, Vec<Ciphertext>, PartOffset);
 
 impl Debug for Part {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_tuple("Part")
             .field(&format!("<degree {}>", self.0.degree()))
             .field(&format!("<{} rows>", self.1.len()))
+            .field(&format!("<{} bytes>", (self.2).0.len()))
             .finish()
     }
 }
 
+/// The serialized offset in a `Part`. It is empty if the offset is zero, which is always the case
+/// for a new key. Wire versions before 3 can only represent the empty offset.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
+struct PartOffset(Vec<u8>);
+
 /// A confirmation that we have received and verified a validator's part. It must be sent to
 /// all participating nodes and handled by all of them, including ourselves.
 ///
@@ -216,6 +285,13 @@ impl Debug for Part {
 #[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
 pub struct Ack(u64, Vec<Ciphertext>);
 
+impl Ack {
+    /// Returns the index of the dealer whose `Part` this message acknowledges.
+    pub fn proposer_index(&self) -> u64 {
+        self.0
+    }
+}
+
 impl Debug for Ack {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_tuple("Ack")
@@ -225,30 +301,81 @@ impl Debug for Ack {
     }
 }
 
+/// A public accusation that the row a dealer encrypted to us in its `Part` was malformed or didn't
+/// match its commitment. It must be sent to all participating nodes and handled by all of them.
+///
+/// The message contains the index of the accused dealer.
+#[derive(Deserialize, Serialize, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Complaint(u64);
+
+impl Complaint {
+    /// Returns the index of the dealer whose `Part` this message complains about.
+    pub fn proposer_index(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A dealer's answer to a `Complaint`. It must be sent to all participating nodes and handled by
+/// all of them.
+///
+/// The message reveals the complainer's row in plain text, so that everyone can verify it against
+/// the dealer's commitment.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
+pub struct Justification(u64, Vec<u8>);
+
+impl Justification {
+    /// Returns the index of the node whose `Complaint` this message answers.
+    pub fn complainer_index(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Debug for Justification {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple("Justification")
+            .field(&self.0)
+            .field(&format!("<{} bytes>", self.1.len()))
+            .finish()
+    }
+}
+
 /// The information needed to track a single proposer's secret sharing process.
 #[derive(Debug)]
 struct ProposalState {
     /// The proposer's commitment.
     commit: BivarCommitment,
+    /// The public offset that is added to the constant term of the proposer's polynomial.
+    offset: Fr,
     /// The verified values we received from `Ack` messages.
     values: BTreeMap<u64, Fr>,
     /// The nodes which have acked this part, valid or not.
     acks: BTreeSet<u64>,
+    /// The nodes which have complained about this part and haven't received a justification yet.
+    complaints: BTreeSet<u64>,
+    /// The nodes whose complaints have been answered with a justification.
+    justified: BTreeSet<u64>,
+    /// Whether the part is publicly known to be invalid, and must not be used.
+    is_faulty: bool,
 }
 
 impl ProposalState {
-    /// Creates a new part state with a commitment.
-    fn new(commit: BivarCommitment) -> ProposalState {
+    /// Creates a new part state with a commitment and offset.
+    fn new(commit: BivarCommitment, offset: Fr) -> ProposalState {
         ProposalState {
             commit,
+            offset,
             values: BTreeMap::new(),
             acks: BTreeSet::new(),
+            complaints: BTreeSet::new(),
+            justified: BTreeSet::new(),
+            is_faulty: false,
         }
     }
 
-    /// Returns `true` if at least `2 * threshold + 1` nodes have acked.
+    /// Returns `true` if at least `2 * threshold + 1` nodes have acked, and the part is neither
+    /// faulty nor has any unanswered complaints.
     fn is_complete(&self, threshold: usize) -> bool {
-        self.acks.len() > 2 * threshold
+        !self.is_faulty && self.complaints.is_empty() && self.acks.len() > 2 * threshold
     }
 }
 
@@ -257,11 +384,63 @@ pub enum PartOutcome<N: Clone> {
     /// The message was valid: the part of it that was encrypted to us matched the public
     /// commitment, so we can multicast an `Ack` message for it.
     Valid(Ack),
-    // If the Part message passed to `handle_part()` is invalid, the
-    // fault is logged and passed onto the caller.
-    /// The message was invalid: the part encrypted to us was malformed or didn't match the
-    /// commitment. We now know that the proposer is faulty, and dont' send an `Ack`.
-    Invalid(FaultLog<N>),
+    /// The message was invalid: it didn't contain a row for every node. All nodes know that the
+    /// proposer is faulty, and don't send an `Ack`.
+    Invalid(FaultLog<N, FaultKind>),
+    /// The row encrypted to us was malformed or didn't match the commitment. We multicast a
+    /// `Complaint` instead of an `Ack`, so that the proposer has to reveal our row.
+    Complaint(Complaint),
+}
+
+/// The outcome of handling a `Complaint` message.
+pub enum ComplaintOutcome<N: Clone> {
+    /// The complaint was recorded. If it concerns our own `Part`, this contains the
+    /// `Justification` we need to multicast.
+    Valid(Option<Justification>),
+    /// The complaint was unexpected, e.g. it concerns an unknown `Part`, or the sender already
+    /// acknowledged it or complained about it before.
+    Invalid(FaultLog<N, FaultKind>),
+}
+
+/// The outcome of handling a `Justification` message.
+pub enum JustificationOutcome<N: Clone> {
+    /// The revealed row matches the commitment. If we were the complainer, this contains the `Ack`
+    /// we need to multicast.
+    Valid(Option<Ack>),
+    /// The justification was unexpected or the revealed row was invalid. In the latter case, the
+    /// proposer's `Part` will not be used for key generation.
+    Invalid(FaultLog<N, FaultKind>),
+}
+
+/// The existing keys that `SyncKeyGen::new_resharing` deals out to a new set of validators.
+#[derive(Clone, Debug)]
+pub struct OldKeys<N> {
+    /// The existing public key set.
+    pub pk_set: PublicKeySet,
+    /// The old validators that deal their shares, with their indices in `pk_set`. There must be
+    /// more than `pk_set.threshold()` of them, and they must all take part in the new key
+    /// generation.
+    pub dealers: BTreeMap<N, usize>,
+    /// Our existing secret key share, if we are one of the dealers.
+    pub secret_key_share: Option<SecretKeyShare>,
+}
+
+/// The kind of key that is being shared.
+#[derive(Debug)]
+enum Mode {
+    /// A new, random master key.
+    Fresh,
+    /// An existing master key with the given commitment. Only the dealers, given as a map from
+    /// their indices in the new key generation to their indices in the old key set, send parts.
+    Reshare {
+        old_commit: Commitment,
+        dealers: BTreeMap<u64, u64>,
+    },
+    /// Zero, to be added to the existing keys with the given commitment and our secret key share.
+    Refresh {
+        old_commit: Commitment,
+        old_share: Option<SecretKeyShare>,
+    },
 }
 
 /// A synchronous algorithm for dealerless distributed key generation.
@@ -279,8 +458,12 @@ pub struct SyncKeyGen<N> {
     pub_keys: BTreeMap<N, PublicKey>,
     /// Proposed bivariate polynomials.
     parts: BTreeMap<u64, ProposalState>,
+    /// Our own bivariate polynomial, if we are a validator. It is needed to answer complaints.
+    our_part: Option<BivarPoly>,
     /// The degree of the generated polynomial.
     threshold: usize,
+    /// The kind of key that is being shared.
+    mode: Mode,
 }
 
 impl<N: NodeIdT> SyncKeyGen<N> {
@@ -296,39 +479,155 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         pub_keys: BTreeMap<N, PublicKey>,
         threshold: usize,
     ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
+        let key_gen = SyncKeyGen::with_mode(our_id, sec_key, pub_keys, threshold, Mode::Fresh);
+        let target = key_gen.our_idx.map(|_| None);
+        key_gen.with_part(rng, target)
+    }
+
+    /// Creates a new `SyncKeyGen` instance that deals shares of the existing master key in
+    /// `old_keys` to the nodes in `pub_keys`, together with the `Part` message that should be
+    /// multicast to all nodes.
+    ///
+    /// Only the dealers produce a `Part` message. All nodes must use the same `old_keys`, apart
+    /// from the secret key share, which is required for the dealers and ignored otherwise.
+    pub fn new_resharing<R: rand::Rng>(
+        rng: &mut R,
+        our_id: N,
+        sec_key: SecretKey,
+        pub_keys: BTreeMap<N, PublicKey>,
+        threshold: usize,
+        old_keys: OldKeys<N>,
+    ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
+        let OldKeys {
+            pk_set,
+            dealers,
+            secret_key_share,
+        } = old_keys;
+        if dealers.len() <= pk_set.threshold() {
+            return Err(Error::TooFewDealers);
+        }
+        let dealer_indices = {
+            let position = |id: &N| pub_keys.keys().position(|pk_id| pk_id == id);
+            dealers
+                .iter()
+                .map(|(id, old_idx)| Some((position(id)? as u64, *old_idx as u64)))
+                .collect::<Option<BTreeMap<u64, u64>>>()
+                .ok_or(Error::UnknownDealer)?
+        };
+        let old_commit = commitment(&pk_set)?;
+        let mode = Mode::Reshare {
+            old_commit,
+            dealers: dealer_indices,
+        };
+        let key_gen = SyncKeyGen::with_mode(our_id, sec_key, pub_keys, threshold, mode);
+        let target = match key_gen.our_idx.and_then(|idx| key_gen.lagrange_coefficient(idx)) {
+            None => None, // We are not a dealer.
+            Some(coeff) => {
+                let sk_share = secret_key_share.ok_or(Error::MissingKeyShare)?;
+                let mut value = share_value(&sk_share)?;
+                value.mul_assign(&coeff);
+                Some(Some(value))
+            }
+        };
+        key_gen.with_part(rng, target)
+    }
+
+    /// Creates a new `SyncKeyGen` instance that replaces the existing key shares of the public key
+    /// set `pk_set` with new ones for the same master key, together with the `Part` message that
+    /// should be multicast to all nodes.
+    ///
+    /// The nodes in `pub_keys` must be the validators that `pk_set` belongs to. Each validator
+    /// must provide its existing secret key share.
+    pub fn new_refresh<R: rand::Rng>(
+        rng: &mut R,
+        our_id: N,
+        sec_key: SecretKey,
+        pub_keys: BTreeMap<N, PublicKey>,
+        pk_set: PublicKeySet,
+        secret_key_share: Option<SecretKeyShare>,
+    ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
+        let threshold = pk_set.threshold();
+        let is_validator = pub_keys.contains_key(&our_id);
+        if is_validator && secret_key_share.is_none() {
+            return Err(Error::MissingKeyShare);
+        }
+        let mode = Mode::Refresh {
+            old_commit: commitment(&pk_set)?,
+            old_share: secret_key_share.filter(|_| is_validator),
+        };
+        let key_gen = SyncKeyGen::with_mode(our_id, sec_key, pub_keys, threshold, mode);
+        let target = key_gen.our_idx.map(|_| Some(Fr::zero()));
+        key_gen.with_part(rng, target)
+    }
+
+    /// Creates a new instance that shares the given kind of key, without a `Part`.
+    fn with_mode(
+        our_id: N,
+        sec_key: SecretKey,
+        pub_keys: BTreeMap<N, PublicKey>,
+        threshold: usize,
+        mode: Mode,
+    ) -> SyncKeyGen<N> {
         let our_idx = pub_keys
             .keys()
             .position(|id| *id == our_id)
             .map(|idx| idx as u64);
-        let key_gen = SyncKeyGen {
+        SyncKeyGen {
             our_id,
             our_idx,
             sec_key,
             pub_keys,
             parts: BTreeMap::new(),
+            our_part: None,
             threshold,
-        };
-        if our_idx.is_none() {
-            return Ok((key_gen, None)); // No part: we are an observer.
+            mode,
         }
+    }
 
-        let our_part = BivarPoly::random(threshold, rng);
+    /// Creates our `Part`, if `target` is not `None`. If it is `Some(Some(value))`, the constant
+    /// term of our polynomial, including the offset, will be `value`. If it is `Some(None)`, the
+    /// constant term is random.
+    fn with_part<R: rand::Rng>(
+        mut self,
+        rng: &mut R,
+        target: Option<Option<Fr>>,
+    ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
+        let opt_value = match target {
+            None => return Ok((self, None)), // No part: we are an observer or not a dealer.
+            Some(opt_value) => opt_value,
+        };
+        let our_part = BivarPoly::random(self.threshold, rng);
         let commit = our_part.commitment();
-        let encrypt = |(i, pk): (usize, &PublicKey)| {
-            let row = our_part.row(i + 1);
-            let bytes = bincode::serialize(&row).expect("failed to serialize row");
-            Ok(pk.encrypt_with_rng(rng, &bytes))
+        let offset = match opt_value {
+            None => Fr::zero(),
+            Some(mut value) => {
+                value.sub_assign(&our_part.row(0).evaluate(0));
+                value
+            }
         };
-        let rows = key_gen
-            .pub_keys
-            .values()
-            .enumerate()
-            .map(encrypt)
-            .collect::<Result<Vec<_>, Error>>()?;
-        Ok((key_gen, Some(Part(commit, rows))))
+        let ser_offset = if offset.is_zero() {
+            Vec::new()
+        } else {
+            bincode::serialize(&FieldWrap::new(offset)).map_err(|_| Error::KeyConversion)?
+        };
+        let rows = {
+            let encrypt = |(i, pk): (usize, &PublicKey)| {
+                let row = our_part.row(i + 1);
+                let bytes = bincode::serialize(&row).expect("failed to serialize row");
+                Ok(pk.encrypt_with_rng(rng, &bytes))
+            };
+            self.pub_keys
+                .values()
+                .enumerate()
+                .map(encrypt)
+                .collect::<Result<Vec<_>, Error>>()?
+        };
+        self.our_part = Some(our_part);
+        Ok((self, Some(Part(commit, rows, PartOffset(ser_offset)))))
     }
 
-    /// Handles a `Part` message. If it is valid, returns an `Ack` message to be broadcast.
+    /// Handles a `Part` message. If it is valid, returns an `Ack` message to be broadcast. If our
+    /// row is invalid, returns a `Complaint` message to be broadcast instead.
     ///
     /// If we are only an observer, `None` is returned instead and no messages need to be sent.
     ///
@@ -338,62 +637,169 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         &mut self,
         rng: &mut R,
         sender_id: &N,
-        Part(commit, rows): Part,
+        Part(commit, rows, PartOffset(ser_offset)): Part,
     ) -> Option<PartOutcome<N>> {
         let sender_idx = self.node_index(sender_id)?;
         let opt_commit_row = self.our_idx.map(|idx| commit.row(idx + 1));
+        let opt_offset = if ser_offset.is_empty() {
+            Some(Fr::zero())
+        } else {
+            bincode::deserialize::<FieldWrap<Fr, Fr>>(&ser_offset)
+                .ok()
+                .map(FieldWrap::into_inner)
+                .filter(|offset| !offset.is_zero())
+        };
+        let opt_offset =
+            opt_offset.filter(|offset| self.is_valid_constant(sender_idx, &commit, offset));
+        let is_faulty = rows.len() != self.pub_keys.len() || opt_offset.is_none();
         match self.parts.entry(sender_idx) {
             Entry::Occupied(_) => {
-                debug!("Received multiple parts from node {:?}.", sender_id);
+                debug_event!("Received multiple parts", sender = sender_id);
                 return None;
             }
             Entry::Vacant(entry) => {
-                entry.insert(ProposalState::new(commit));
+                let mut part = ProposalState::new(commit, opt_offset.unwrap_or_else(Fr::zero));
+                part.is_faulty = is_faulty;
+                entry.insert(part);
             }
         }
+        if is_faulty {
+            // Every node can see this, so nobody will use the part.
+            error_event!("Invalid rows or constant term in part", sender = sender_id);
+            let fault_log = FaultLog::init(sender_id.clone(), FaultKind::InvalidPartMessage);
+            return Some(PartOutcome::Invalid(fault_log));
+        }
         // If we are only an observer, return `None`. We don't need to send `Ack`.
         let our_idx = self.our_idx?;
         let commit_row = opt_commit_row?;
-        let ser_row = self.sec_key.decrypt(rows.get(our_idx as usize)?)?;
-        let row: Poly = if let Ok(row) = bincode::deserialize(&ser_row) {
-            row
-        } else {
-            // Log the faulty node and ignore invalid messages.
-            let fault_log = FaultLog::init(sender_id.clone(), FaultKind::InvalidPartMessage);
-            return Some(PartOutcome::Invalid(fault_log));
-        };
-        if row.commitment() != commit_row {
-            error!("Invalid part from node {:?}.", sender_id);
-            let fault_log = FaultLog::init(sender_id.clone(), FaultKind::InvalidPartMessage);
-            return Some(PartOutcome::Invalid(fault_log));
+        let opt_row = self
+            .sec_key
+            .decrypt(&rows[our_idx as usize])
+            .and_then(|ser_row| bincode::deserialize::<Poly>(&ser_row).ok())
+            .filter(|row| row.commitment() == commit_row);
+        match opt_row {
+            // The row is valid: now encrypt one value for each node.
+            Some(row) => Some(PartOutcome::Valid(self.ack_row(rng, sender_idx, &row))),
+            None => {
+                // Only we know that our row is invalid: Ask the proposer to reveal it publicly.
+                error_event!("Invalid row in part", sender = sender_id);
+                Some(PartOutcome::Complaint(Complaint(sender_idx)))
+            }
         }
-        // The row is valid: now encrypt one value for each node.
-        let encrypt = |(idx, pk): (usize, &PublicKey)| {
-            let val = row.evaluate(idx + 1);
-            let wrap = FieldWrap::new(val);
-            // TODO: Handle errors.
-            let ser_val = bincode::serialize(&wrap).expect("failed to serialize value");
-            pk.encrypt_with_rng(rng, ser_val)
-        };
-        let values = self.pub_keys.values().enumerate().map(encrypt).collect();
-        Some(PartOutcome::Valid(Ack(sender_idx, values)))
     }
 
     /// Handles an `Ack` message.
     ///
     /// All participating nodes must handle the exact same sequence of messages.
     /// Note that `handle_ack` also needs to explicitly be called with this instance's own `Ack`s.
-    pub fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> FaultLog<N> {
+    pub fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> FaultLog<N, FaultKind> {
         let mut fault_log = FaultLog::new();
         if let Some(sender_idx) = self.node_index(sender_id) {
             if let Err(fault) = self.handle_ack_or_err(sender_idx, ack) {
-                debug!("Invalid ack from node {:?}: {}", sender_id, fault);
+                debug_event!("Invalid ack", sender = sender_id, fault = fault);
                 fault_log.append(sender_id.clone(), FaultKind::AckMessage(fault));
             }
         }
         fault_log
     }
 
+    /// Handles a `Complaint` message. If it concerns our own `Part`, returns a `Justification`
+    /// message to be broadcast.
+    ///
+    /// Until the proposer's `Justification` has been handled, the proposer's `Part` is not
+    /// considered complete.
+    ///
+    /// All participating nodes must handle the exact same sequence of messages.
+    /// Note that `handle_complaint` also needs to explicitly be called with this instance's own
+    /// `Complaint`s.
+    pub fn handle_complaint(
+        &mut self,
+        sender_id: &N,
+        Complaint(proposer_idx): Complaint,
+    ) -> ComplaintOutcome<N> {
+        let sender_idx = match self.node_index(sender_id) {
+            Some(sender_idx) => sender_idx,
+            None => return ComplaintOutcome::Invalid(FaultLog::new()),
+        };
+        let is_expected = match self.parts.get_mut(&proposer_idx) {
+            None => false,
+            Some(part) => {
+                !part.acks.contains(&sender_idx)
+                    && !part.justified.contains(&sender_idx)
+                    && part.complaints.insert(sender_idx)
+            }
+        };
+        if !is_expected {
+            debug_event!("Unexpected complaint", sender = sender_id);
+            let fault_log = FaultLog::init(sender_id.clone(), FaultKind::UnexpectedComplaint);
+            return ComplaintOutcome::Invalid(fault_log);
+        }
+        if self.our_idx != Some(proposer_idx) {
+            return ComplaintOutcome::Valid(None);
+        }
+        // The complaint is about our own part: Reveal the complainer's row.
+        let opt_justification = self.our_part.as_ref().map(|our_part| {
+            let row = our_part.row(sender_idx + 1);
+            let ser_row = bincode::serialize(&row).expect("failed to serialize row");
+            Justification(sender_idx, ser_row)
+        });
+        ComplaintOutcome::Valid(opt_justification)
+    }
+
+    /// Handles a `Justification` message. If it answers our own `Complaint` and the revealed row
+    /// is valid, returns an `Ack` message to be broadcast.
+    ///
+    /// If the revealed row doesn't match the commitment, the proposer's `Part` will not be used
+    /// for key generation.
+    ///
+    /// All participating nodes must handle the exact same sequence of messages.
+    /// Note that `handle_justification` also needs to explicitly be called with this instance's
+    /// own `Justification`s.
+    pub fn handle_justification<R: rand::Rng>(
+        &mut self,
+        rng: &mut R,
+        sender_id: &N,
+        Justification(complainer_idx, ser_row): Justification,
+    ) -> JustificationOutcome<N> {
+        let sender_idx = match self.node_index(sender_id) {
+            Some(sender_idx) => sender_idx,
+            None => return JustificationOutcome::Invalid(FaultLog::new()),
+        };
+        let fault = |kind| JustificationOutcome::Invalid(FaultLog::init(sender_id.clone(), kind));
+        let row = {
+            let part = match self.parts.get_mut(&sender_idx) {
+                Some(part) => part,
+                None => return fault(FaultKind::UnexpectedJustification),
+            };
+            if !part.complaints.remove(&complainer_idx) {
+                return fault(FaultKind::UnexpectedJustification);
+            }
+            part.justified.insert(complainer_idx);
+            let commit_row = part.commit.row(complainer_idx + 1);
+            let opt_row = bincode::deserialize::<Poly>(&ser_row)
+                .ok()
+                .filter(|row| row.commitment() == commit_row);
+            match opt_row {
+                Some(row) => row,
+                None => {
+                    error_event!("Invalid justification", sender = sender_id);
+                    part.is_faulty = true;
+                    return fault(FaultKind::InvalidJustification);
+                }
+            }
+        };
+        if self.our_idx != Some(complainer_idx) {
+            return JustificationOutcome::Valid(None);
+        }
+        // The revealed row is ours, and valid: Now we can acknowledge the part.
+        JustificationOutcome::Valid(Some(self.ack_row(rng, sender_idx, &row)))
+    }
+
+    /// Returns `true` if the node with the given ID takes part in the key generation.
+    pub fn is_participant(&self, node_id: &N) -> bool {
+        self.pub_keys.contains_key(node_id)
+    }
+
     /// Returns the number of complete parts. If this is at least `threshold + 1`, the keys can
     /// be generated, but it is possible to wait for more to increase security.
     pub fn count_complete(&self) -> usize {
@@ -411,8 +817,17 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     }
 
     /// Returns `true` if enough parts are complete to safely generate the new key.
+    ///
+    /// When resharing an existing key, this requires every dealer's part to be complete.
     pub fn is_ready(&self) -> bool {
-        self.count_complete() > self.threshold
+        match self.mode {
+            Mode::Fresh | Mode::Refresh { .. } => self.count_complete() > self.threshold,
+            Mode::Reshare { ref dealers, .. } => dealers.keys().all(|idx| {
+                self.parts
+                    .get(idx)
+                    .map_or(false, |part| part.is_complete(self.threshold))
+            }),
+        }
     }
 
     /// Returns the new secret key share and the public key set.
@@ -425,14 +840,32 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     /// All participating nodes must have handled the exact same sequence of `Part` and `Ack`
     /// messages before calling this method. Otherwise their key shares will not match.
     pub fn generate(&self) -> Result<(PublicKeySet, Option<SecretKeyShare>), Error> {
+        if let Mode::Reshare { .. } = self.mode {
+            if !self.is_ready() {
+                return Err(Error::IncompleteResharing);
+            }
+        }
         let mut pk_commit = Poly::zero().commitment();
         let mut opt_sk_val = self.our_idx.map(|_| Fr::zero());
+        if let Mode::Refresh {
+            ref old_commit,
+            ref old_share,
+        } = self.mode
+        {
+            // The parts are sharings of zero, which we add to the existing keys.
+            pk_commit += old_commit;
+            if let (Some(sk_val), Some(sk_share)) = (opt_sk_val.as_mut(), old_share.as_ref()) {
+                sk_val.add_assign(&share_value(sk_share)?);
+            }
+        }
         let is_complete = |part: &&ProposalState| part.is_complete(self.threshold);
         for part in self.parts.values().filter(is_complete) {
             pk_commit += part.commit.row(0);
+            pk_commit += constant(part.offset).commitment();
             if let Some(sk_val) = opt_sk_val.as_mut() {
                 let row = Poly::interpolate(part.values.iter().take(self.threshold + 1));
                 sk_val.add_assign(&row.evaluate(0));
+                sk_val.add_assign(&part.offset);
             }
         }
         let opt_sk = if let Some(mut fr) = opt_sk_val {
@@ -482,10 +915,7 @@ impl<N: NodeIdT> SyncKeyGen<N> {
             .ok_or_else(|| Fault::ValueDecryption)?;
         let val = bincode::deserialize::<FieldWrap<Fr, Fr>>(&ser_val)
             .map_err(|err| {
-                error!(
-                    "Secure value deserialization failed while handling ack: {:?}",
-                    err
-                );
+                error_event!("Failed to deserialize value in ack", error = err);
                 Fault::ValueDeserialization
             })?.into_inner();
         if part.commit.evaluate(our_idx + 1, sender_idx + 1) != G1Affine::one().mul(val) {
@@ -495,13 +925,94 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         Ok(())
     }
 
+    /// Creates an `Ack` message for the given proposer's part, containing one value of our verified
+    /// row for each node.
+    fn ack_row<R: rand::Rng>(&self, rng: &mut R, proposer_idx: u64, row: &Poly) -> Ack {
+        let encrypt = |(idx, pk): (usize, &PublicKey)| {
+            let val = row.evaluate(idx + 1);
+            let wrap = FieldWrap::new(val);
+            // TODO: Handle errors.
+            let ser_val = bincode::serialize(&wrap).expect("failed to serialize value");
+            pk.encrypt_with_rng(rng, ser_val)
+        };
+        let values = self.pub_keys.values().enumerate().map(encrypt).collect();
+        Ack(proposer_idx, values)
+    }
+
     /// Returns the index of the node, or `None` if it is unknown.
     fn node_index(&self, node_id: &N) -> Option<u64> {
         if let Some(node_idx) = self.pub_keys.keys().position(|id| id == node_id) {
             Some(node_idx as u64)
         } else {
-            error!("Unknown node {:?}", node_id);
+            error_event!("Unknown node", node = node_id);
             None
         }
     }
+
+    /// Returns the Lagrange coefficient of the given dealer's old key share, or `None` if the
+    /// node with that index is not a dealer.
+    ///
+    /// The dealers' old key shares, multiplied by their coefficients, sum up to the master key.
+    fn lagrange_coefficient(&self, dealer_idx: u64) -> Option<Fr> {
+        let dealers = match self.mode {
+            Mode::Reshare { ref dealers, .. } => dealers,
+            Mode::Fresh | Mode::Refresh { .. } => return None,
+        };
+        let old_idx = *dealers.get(&dealer_idx)?;
+        // The coefficient is the value at zero of the polynomial that is `1` at the dealer's index
+        // and `0` at all other dealers' indices.
+        let samples: BTreeMap<u64, Fr> = dealers
+            .values()
+            .map(|idx| (idx + 1, if *idx == old_idx { Fr::one() } else { Fr::zero() }))
+            .collect();
+        Some(Poly::interpolate(samples.iter()).evaluate(0))
+    }
+
+    /// Returns `true` if the constant term of the dealer's polynomial with the given commitment
+    /// and offset is the value that the dealer must share. For a new key, that can be anything,
+    /// and for a refresh, it must be zero.
+    fn is_valid_constant(&self, dealer_idx: u64, commit: &BivarCommitment, offset: &Fr) -> bool {
+        let mut actual = commit.evaluate(0, 0);
+        actual.add_assign(&G1Affine::one().mul(*offset));
+        match self.mode {
+            Mode::Fresh => true,
+            Mode::Refresh { .. } => actual.is_zero(),
+            Mode::Reshare {
+                ref old_commit,
+                ref dealers,
+            } => match (dealers.get(&dealer_idx), self.lagrange_coefficient(dealer_idx)) {
+                (Some(old_idx), Some(coeff)) => {
+                    let old_pk_share = old_commit.evaluate(old_idx + 1);
+                    actual == old_pk_share.into_affine().mul(coeff)
+                }
+                _ => false, // Only dealers are allowed to send a part.
+            },
+        }
+    }
+}
+
+/// Returns the constant polynomial with value `value`.
+fn constant(value: Fr) -> Poly {
+    let samples: BTreeMap<u64, Fr> = Some((0, value)).into_iter().collect();
+    Poly::interpolate(samples.iter())
+}
+
+/// Returns the commitment to the polynomial whose values are the keys in the public key set.
+///
+/// `threshold_crypto` has no accessor for it, but a `PublicKeySet` consists of nothing but its
+/// commitment, and serializes as such.
+fn commitment(pk_set: &PublicKeySet) -> Result<Commitment, Error> {
+    let ser_pk_set = bincode::serialize(pk_set).map_err(|_| Error::KeyConversion)?;
+    bincode::deserialize(&ser_pk_set).map_err(|_| Error::KeyConversion)
+}
+
+/// Returns the field element that constitutes the secret key share.
+///
+/// `threshold_crypto` has no accessor for it, but a `SecretKeyShare` consists of nothing but its
+/// field element, and serializes as such.
+fn share_value(sk_share: &SecretKeyShare) -> Result<Fr, Error> {
+    let ser_share = bincode::serialize(sk_share).map_err(|_| Error::KeyConversion)?;
+    bincode::deserialize::<FieldWrap<Fr, Fr>>(&ser_share)
+        .map(FieldWrap::into_inner)
+        .map_err(|_| Error::KeyConversion)
 }
@@ -9,12 +9,19 @@
 //! key can be collaboratively decrypted by combining at least _f + 1_ decryption shares. Each
 //! validator holds a secret key share, and uses it to produce and multicast a decryption share.
 //! The algorithm outputs as soon as _f + 1_ of them have been received.
+//!
+//! Shares that arrive before the ciphertext cannot be verified yet. Once the ciphertext is known,
+//! they are verified in a single batch by the configured `CryptoOffload`.
 
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use crypto::{self, Ciphertext, DecryptionShare};
-use fault_log::{Fault, FaultKind, FaultLog};
+use crypto_offload::{CryptoOffload, InlineOffload, Verification};
+use environment::{self, Environment, Restore};
+use fault_log::FaultLog;
+use metrics::{self, Metrics, NoMetrics};
+use validation::Validity;
 use {DistAlgorithm, NetworkInfo, NodeIdT, Target};
 
 /// A threshold decryption error.
@@ -24,22 +31,42 @@ pub enum Error {
     MultipleInputs(Box<Ciphertext>),
     #[fail(display = "Invalid ciphertext: {:?}", _0)]
     InvalidCiphertext(Box<Ciphertext>),
-    #[fail(display = "Unknown sender")]
-    UnknownSender,
     #[fail(display = "Decryption failed: {:?}", _0)]
     Decryption(crypto::error::Error),
 }
 
+impl Error {
+    /// Returns `true` if the ciphertext cannot be decrypted anymore. Invalid or redundant inputs
+    /// are only rejected.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::MultipleInputs(_) | Error::InvalidCiphertext(_) => false,
+            Error::Decryption(_) => true,
+        }
+    }
+}
+
 /// A threshold decryption result.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// Represents each reason why a threshold decryption message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// `ThresholdDecryption` received a decryption share from an unverified sender, or from a
+    /// node that is not a validator.
+    UnverifiedDecryptionShareSender,
+    /// `ThresholdDecryption` received multiple shares from the same sender.
+    MultipleDecryptionShares,
+}
+
 /// A Threshold Decryption message.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Rand)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "testing", derive(Rand))]
 pub struct Message(pub DecryptionShare);
 
 /// A Threshold Decryption algorithm instance. If every node inputs the same data, encrypted to the
 /// network's public key, every node will output the decrypted data.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ThresholdDecryption<N> {
     netinfo: Arc<NetworkInfo<N>>,
     /// The encrypted data.
@@ -48,6 +75,19 @@ pub struct ThresholdDecryption<N> {
     shares: BTreeMap<N, DecryptionShare>,
     /// Whether we have already returned the output.
     terminated: bool,
+    /// The executor for batches of share verifications.
+    offload: Arc<dyn CryptoOffload>,
+    /// Receiver of the durations of creating, verifying and combining shares.
+    metrics: Arc<dyn Metrics>,
+}
+
+/// A serializable snapshot of a `ThresholdDecryption` instance, without its environment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: ::NodeIdT + ::serde::Deserialize<'de>"))]
+pub struct ThresholdDecryptionSnapshot<N> {
+    ciphertext: Option<Ciphertext>,
+    shares: BTreeMap<N, DecryptionShare>,
+    terminated: bool,
 }
 
 pub type Step<N> = ::Step<ThresholdDecryption<N>>;
@@ -58,6 +98,7 @@ impl<N: NodeIdT> DistAlgorithm for ThresholdDecryption<N> {
     type Output = Vec<u8>;
     type Message = Message;
     type Error = Error;
+    type FaultKind = FaultKind;
 
     fn handle_input(&mut self, input: Ciphertext) -> Result<Step<N>> {
         self.set_ciphertext(input)
@@ -76,6 +117,32 @@ impl<N: NodeIdT> DistAlgorithm for ThresholdDecryption<N> {
     }
 }
 
+impl<N: NodeIdT> Restore<N> for ThresholdDecryption<N> {
+    type Snapshot = ThresholdDecryptionSnapshot<N>;
+
+    fn snapshot(&self) -> ThresholdDecryptionSnapshot<N> {
+        ThresholdDecryptionSnapshot {
+            ciphertext: self.ciphertext.clone(),
+            shares: self.shares.clone(),
+            terminated: self.terminated,
+        }
+    }
+
+    fn restore(
+        snapshot: ThresholdDecryptionSnapshot<N>,
+        env: &Environment<N>,
+    ) -> environment::Result<Self> {
+        Ok(ThresholdDecryption {
+            netinfo: env.netinfo.clone(),
+            ciphertext: snapshot.ciphertext,
+            shares: snapshot.shares,
+            terminated: snapshot.terminated,
+            offload: env.crypto_offload.clone(),
+            metrics: env.metrics.clone(),
+        })
+    }
+}
+
 impl<N: NodeIdT> ThresholdDecryption<N> {
     /// Creates a new Threshold Decryption instance.
     pub fn new(netinfo: Arc<NetworkInfo<N>>) -> Self {
@@ -84,16 +151,34 @@ impl<N: NodeIdT> ThresholdDecryption<N> {
             ciphertext: None,
             shares: BTreeMap::new(),
             terminated: false,
+            offload: Arc::new(InlineOffload),
+            metrics: Arc::new(NoMetrics),
         }
     }
 
+    /// Sets the executor that verifies the shares received before the ciphertext. By default,
+    /// they are verified sequentially on the calling thread.
+    pub fn set_crypto_offload(&mut self, offload: Arc<dyn CryptoOffload>) {
+        self.offload = offload;
+    }
+
+    /// Sets the receiver of the durations of creating, verifying and combining the decryption
+    /// shares, as `CRYPTO_DURATION` measurements. By default, measurements are ignored.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.metrics = metrics;
+    }
+
     /// Sets the ciphertext, sends the decryption share, and tries to decrypt it.
     /// This must be called exactly once, with the same ciphertext in all participating nodes.
     pub fn set_ciphertext(&mut self, ct: Ciphertext) -> Result<Step<N>> {
         if self.ciphertext.is_some() {
             return Err(Error::MultipleInputs(Box::new(ct)));
         }
-        let share = match self.netinfo.secret_key_share().decrypt_share(&ct) {
+        let share = {
+            let sk_share = self.netinfo.secret_key_share();
+            metrics::time_crypto(&*self.metrics, "decrypt_share", || sk_share.decrypt_share(&ct))
+        };
+        let share = match share {
             None => return Err(Error::InvalidCiphertext(Box::new(ct))),
             Some(share) => share,
         };
@@ -116,28 +201,60 @@ impl<N: NodeIdT> ThresholdDecryption<N> {
     }
 
     fn handle_message(&mut self, sender_id: &N, message: Message) -> Result<Step<N>> {
-        if self.terminated {
-            return Ok(Step::default()); // Don't waste time on redundant shares.
+        let validity = self.validate_message(sender_id, &message);
+        let mut step = Step::validated(sender_id.clone(), validity);
+        if validity.is_valid() {
+            let Message(share) = message;
+            self.shares.insert(sender_id.clone(), share);
+            step.extend(self.try_output()?);
         }
-        let Message(share) = message;
-        if !self.is_share_valid(sender_id, &share) {
-            let fault_kind = FaultKind::UnverifiedDecryptionShareSender;
-            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
-        }
-        if self.shares.insert(sender_id.clone(), share).is_some() {
-            return Ok(Fault::new(sender_id.clone(), FaultKind::MultipleDecryptionShares).into());
+        Ok(step)
+    }
+
+    /// Classifies a message before it is handled.
+    fn validate_message(&self, sender_id: &N, message: &Message) -> Validity<FaultKind> {
+        let Message(ref share) = *message;
+        if self.terminated {
+            Validity::Obsolete // Don't waste time on redundant shares.
+        } else if !self.is_share_valid(sender_id, share) {
+            Validity::Faulty(FaultKind::UnverifiedDecryptionShareSender)
+        } else if self.shares.contains_key(sender_id) {
+            Validity::Faulty(FaultKind::MultipleDecryptionShares)
+        } else {
+            Validity::Valid
         }
-        self.try_output()
     }
 
     /// Removes all shares that are invalid, and returns faults for their senders.
-    fn remove_invalid_shares(&mut self) -> FaultLog<N> {
-        let faulty_senders: Vec<N> = self
-            .shares
-            .iter()
-            .filter(|(id, share)| !self.is_share_valid(id, share))
-            .map(|(id, _)| id.clone())
-            .collect();
+    fn remove_invalid_shares(&mut self) -> FaultLog<N, FaultKind> {
+        let ciphertext = match self.ciphertext {
+            None => return FaultLog::default(), // No ciphertext yet. Verification postponed.
+            Some(ref ct) => Arc::new(ct.clone()),
+        };
+        let mut faulty_senders = Vec::new();
+        let mut ids = Vec::new();
+        let mut tasks = Vec::new();
+        for (id, share) in &self.shares {
+            match self.netinfo.public_key_share(id) {
+                None => faulty_senders.push(id.clone()), // Unknown sender.
+                Some(pk_share) => {
+                    ids.push(id.clone());
+                    tasks.push(Verification::DecryptionShare {
+                        pk_share: pk_share.clone(),
+                        share: share.clone(),
+                        ciphertext: ciphertext.clone(),
+                    });
+                }
+            }
+        }
+        let offload = &self.offload;
+        let results =
+            metrics::time_crypto(&*self.metrics, "decrypt_verify", || offload.verify_all(tasks));
+        for (id, is_valid) in ids.into_iter().zip(results) {
+            if !is_valid {
+                faulty_senders.push(id);
+            }
+        }
         let mut fault_log = FaultLog::default();
         for id in faulty_senders {
             self.shares.remove(&id);
@@ -154,13 +271,15 @@ impl<N: NodeIdT> ThresholdDecryption<N> {
         };
         match self.netinfo.public_key_share(id) {
             None => false, // Unknown sender.
-            Some(pk) => pk.verify_decryption_share(share, ct),
+            Some(pk) => metrics::time_crypto(&*self.metrics, "decrypt_verify", || {
+                pk.verify_decryption_share(share, ct)
+            }),
         }
     }
 
     /// Outputs the decrypted message, if we have the ciphertext and enough shares.
     fn try_output(&mut self) -> Result<Step<N>> {
-        if self.terminated || self.shares.len() <= self.netinfo.num_faulty() {
+        if self.terminated || self.shares.len() <= self.netinfo.public_key_set().threshold() {
             return Ok(Step::default()); // Not enough shares yet, or already terminated.
         }
         let ct = match self.ciphertext {
@@ -177,10 +296,10 @@ impl<N: NodeIdT> ThresholdDecryption<N> {
                 (idx, share)
             };
             let share_itr = self.shares.iter().map(to_idx);
-            self.netinfo
-                .public_key_set()
-                .decrypt(share_itr, ct)
-                .map_err(Error::Decryption)?
+            let pk_set = self.netinfo.public_key_set();
+            metrics::time_crypto(&*self.metrics, "decrypt_combine", || {
+                pk_set.decrypt(share_itr, ct)
+            }).map_err(Error::Decryption)?
         };
         Ok(Step::default().with_output(plaintext))
     }
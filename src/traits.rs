@@ -3,11 +3,12 @@
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::hash::Hash;
-use std::iter::once;
+use std::iter::{once, FromIterator};
 
 use failure::Fail;
 
 use fault_log::{Fault, FaultLog};
+use validation::{ValidationLog, Validity};
 use TargetedMessage;
 
 /// A transaction, user message, etc.
@@ -15,6 +16,10 @@ pub trait Contribution: Eq + Debug + Hash + Send + Sync {}
 impl<C> Contribution for C where C: Eq + Debug + Hash + Send + Sync {}
 
 /// A peer node's unique identifier.
+///
+/// This is implemented for all types with the required bounds, e.g. integers of any size,
+/// fixed-size hashes like `[u8; 32]` or public keys. Dynamic Honey Badger additionally requires
+/// the IDs to be serializable.
 pub trait NodeIdT: Eq + Ord + Clone + Debug + Hash + Send + Sync {}
 impl<N> NodeIdT for N where N: Eq + Ord + Clone + Debug + Hash + Send + Sync {}
 
@@ -22,6 +27,19 @@ impl<N> NodeIdT for N where N: Eq + Ord + Clone + Debug + Hash + Send + Sync {}
 pub trait Message: Debug + Send + Sync {}
 impl<M> Message for M where M: Debug + Send + Sync {}
 
+/// A request to call `DistAlgorithm::handle_timeout` with the given token after the given number
+/// of ticks. The duration of a tick is defined by the application.
+///
+/// Timers are the only way for an algorithm to measure time: The crate itself never reads the
+/// clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerRequest {
+    /// The number of ticks after which the timer expires.
+    pub ticks: u64,
+    /// The token identifying the timer to the algorithm.
+    pub token: u64,
+}
+
 /// Result of one step of the local state machine of a distributed algorithm. Such a result should
 /// be used and never discarded by the client of the algorithm.
 #[must_use = "The algorithm step result must be used."]
@@ -32,8 +50,12 @@ where
     <D as DistAlgorithm>::NodeId: NodeIdT,
 {
     pub output: VecDeque<D::Output>,
-    pub fault_log: FaultLog<D::NodeId>,
+    pub fault_log: FaultLog<D::NodeId, D::FaultKind>,
     pub messages: VecDeque<TargetedMessage<D::Message, D::NodeId>>,
+    /// The classifications of the messages received from remote nodes.
+    pub validation: ValidationLog<D::NodeId, D::FaultKind>,
+    /// Timers that the application must set.
+    pub timers: Vec<TimerRequest>,
 }
 
 impl<D> Default for Step<D>
@@ -46,6 +68,8 @@ where
             output: VecDeque::default(),
             fault_log: FaultLog::default(),
             messages: VecDeque::default(),
+            validation: ValidationLog::default(),
+            timers: Vec::new(),
         }
     }
 }
@@ -57,13 +81,29 @@ where
     /// Creates a new `Step` from the given collections.
     pub fn new(
         output: VecDeque<D::Output>,
-        fault_log: FaultLog<D::NodeId>,
+        fault_log: FaultLog<D::NodeId, D::FaultKind>,
         messages: VecDeque<TargetedMessage<D::Message, D::NodeId>>,
     ) -> Self {
         Step {
             output,
             fault_log,
             messages,
+            validation: ValidationLog::default(),
+            timers: Vec::new(),
+        }
+    }
+
+    /// Creates a step that records the given classification of a message from `sender_id`. If the
+    /// message is `Faulty`, the sender is also reported in the fault log.
+    pub fn validated(sender_id: D::NodeId, validity: Validity<D::FaultKind>) -> Self {
+        let fault_log = match validity {
+            Validity::Faulty(kind) => FaultLog::init(sender_id.clone(), kind),
+            _ => FaultLog::default(),
+        };
+        Step {
+            fault_log,
+            validation: ValidationLog::init(sender_id, validity),
+            ..Step::default()
         }
     }
 
@@ -73,61 +113,143 @@ where
         self
     }
 
-    /// Converts `self` into a step of another type, given conversion methods for output and
-    /// messages.
-    pub fn map<D2, FO, FM>(self, f_out: FO, f_msg: FM) -> Step<D2>
+    /// Converts `self` into a step of another type, given conversion methods for output, fault
+    /// kinds and messages.
+    pub fn map<D2, FO, FF, FM>(self, f_out: FO, f_fault: FF, f_msg: FM) -> Step<D2>
     where
         D2: DistAlgorithm<NodeId = D::NodeId>,
         FO: Fn(D::Output) -> D2::Output,
+        FF: Fn(D::FaultKind) -> D2::FaultKind,
         FM: Fn(D::Message) -> D2::Message,
     {
         Step {
             output: self.output.into_iter().map(f_out).collect(),
+            fault_log: self.fault_log.map(&f_fault),
+            messages: self.messages.into_iter().map(|tm| tm.map(&f_msg)).collect(),
+            validation: self.validation.map(&f_fault),
+            timers: self.timers,
+        }
+    }
+
+    /// Converts `self` into a step of another type with the same output, given a conversion
+    /// method for messages.
+    pub fn map_messages<D2, FM>(self, f_msg: FM) -> Step<D2>
+    where
+        D2: DistAlgorithm<NodeId = D::NodeId, Output = D::Output, FaultKind = D::FaultKind>,
+        FM: Fn(D::Message) -> D2::Message,
+    {
+        Step {
+            output: self.output,
             fault_log: self.fault_log,
             messages: self.messages.into_iter().map(|tm| tm.map(&f_msg)).collect(),
+            validation: self.validation,
+            timers: self.timers,
         }
     }
 
-    /// Extends `self` with `other`s messages and fault logs, and returns `other.output`.
-    pub fn extend_with<D2, FM>(&mut self, other: Step<D2>, f_msg: FM) -> VecDeque<D2::Output>
+    /// Converts `self` into a step of another type with the same messages, given a conversion
+    /// method for outputs.
+    pub fn map_output<D2, FO>(self, f_out: FO) -> Step<D2>
+    where
+        D2: DistAlgorithm<NodeId = D::NodeId, Message = D::Message, FaultKind = D::FaultKind>,
+        FO: FnMut(D::Output) -> D2::Output,
+    {
+        Step {
+            output: self.output.into_iter().map(f_out).collect(),
+            fault_log: self.fault_log,
+            messages: self.messages,
+            validation: self.validation,
+            timers: self.timers,
+        }
+    }
+
+    /// Removes the messages that satisfy the predicate from `self`, and returns them in their
+    /// original order. This can be used to hold back messages that can't be sent yet.
+    pub fn defer_messages<F>(
+        &mut self,
+        mut pred: F,
+    ) -> VecDeque<TargetedMessage<D::Message, D::NodeId>>
+    where
+        F: FnMut(&TargetedMessage<D::Message, D::NodeId>) -> bool,
+    {
+        let (deferred, messages) = self.messages.drain(..).partition(|tm| pred(tm));
+        self.messages = messages;
+        deferred
+    }
+
+    /// Extends `self` with `other`s messages and fault and validation logs, and returns `other`s
+    /// output and timer requests. `other`s fault kinds are converted into `self`'s, which wrap
+    /// them.
+    ///
+    /// The timer requests are returned instead of added to `self`, since their tokens refer to
+    /// `other`'s algorithm: The caller must request them with tokens that allow it to pass the
+    /// timeouts on to the nested instance. None of the algorithms in this crate request timers, so
+    /// their nested steps never contain any.
+    pub fn extend_with<D2, FM>(
+        &mut self,
+        other: Step<D2>,
+        f_msg: FM,
+    ) -> (VecDeque<D2::Output>, Vec<TimerRequest>)
     where
         D2: DistAlgorithm<NodeId = D::NodeId>,
+        D::FaultKind: From<D2::FaultKind>,
         FM: Fn(D2::Message) -> D::Message,
     {
-        self.fault_log.extend(other.fault_log);
+        self.fault_log.extend(other.fault_log.map(From::from));
+        self.validation.extend(other.validation.map(From::from));
         let msgs = other.messages.into_iter().map(|tm| tm.map(&f_msg));
         self.messages.extend(msgs);
-        other.output
+        (other.output, other.timers)
     }
 
-    /// Adds the outputs, fault logs and messages of `other` to `self`.
+    /// Adds the outputs, fault and validation logs, messages and timer requests of `other` to
+    /// `self`.
     pub fn extend(&mut self, other: Self) {
         self.output.extend(other.output);
         self.fault_log.extend(other.fault_log);
         self.messages.extend(other.messages);
+        self.validation.extend(other.validation);
+        self.timers.extend(other.timers);
     }
 
     /// Converts this step into an equivalent step for a different `DistAlgorithm`.
     // This cannot be a `From` impl, because it would conflict with `impl From<T> for T`.
     pub fn convert<D2>(self) -> Step<D2>
     where
-        D2: DistAlgorithm<NodeId = D::NodeId, Output = D::Output, Message = D::Message>,
+        D2: DistAlgorithm<
+            NodeId = D::NodeId,
+            Output = D::Output,
+            Message = D::Message,
+            FaultKind = D::FaultKind,
+        >,
     {
         Step {
             output: self.output,
             fault_log: self.fault_log,
             messages: self.messages,
+            validation: self.validation,
+            timers: self.timers,
         }
     }
 
-    /// Returns `true` if there are now messages, faults or outputs.
+    /// Returns `true` if there are no messages, faults, outputs or timer requests. Validation log
+    /// entries are not taken into account.
     pub fn is_empty(&self) -> bool {
-        self.output.is_empty() && self.fault_log.is_empty() && self.messages.is_empty()
+        self.output.is_empty()
+            && self.fault_log.is_empty()
+            && self.messages.is_empty()
+            && self.timers.is_empty()
+    }
+
+    /// Returns the same step, with the given additional timer request.
+    pub fn with_timer(mut self, ticks: u64, token: u64) -> Self {
+        self.timers.push(TimerRequest { ticks, token });
+        self
     }
 }
 
-impl<D: DistAlgorithm> From<FaultLog<D::NodeId>> for Step<D> {
-    fn from(fault_log: FaultLog<D::NodeId>) -> Self {
+impl<D: DistAlgorithm> From<FaultLog<D::NodeId, D::FaultKind>> for Step<D> {
+    fn from(fault_log: FaultLog<D::NodeId, D::FaultKind>) -> Self {
         Step {
             fault_log,
             ..Step::default()
@@ -135,8 +257,8 @@ impl<D: DistAlgorithm> From<FaultLog<D::NodeId>> for Step<D> {
     }
 }
 
-impl<D: DistAlgorithm> From<Fault<D::NodeId>> for Step<D> {
-    fn from(fault: Fault<D::NodeId>) -> Self {
+impl<D: DistAlgorithm> From<Fault<D::NodeId, D::FaultKind>> for Step<D> {
+    fn from(fault: Fault<D::NodeId, D::FaultKind>) -> Self {
         Step {
             fault_log: fault.into(),
             ..Step::default()
@@ -153,6 +275,24 @@ impl<D: DistAlgorithm> From<TargetedMessage<D::Message, D::NodeId>> for Step<D>
     }
 }
 
+impl<D: DistAlgorithm> From<Vec<TargetedMessage<D::Message, D::NodeId>>> for Step<D> {
+    fn from(msgs: Vec<TargetedMessage<D::Message, D::NodeId>>) -> Self {
+        msgs.into_iter().collect()
+    }
+}
+
+impl<D: DistAlgorithm> FromIterator<TargetedMessage<D::Message, D::NodeId>> for Step<D> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = TargetedMessage<D::Message, D::NodeId>>,
+    {
+        Step {
+            messages: iter.into_iter().collect(),
+            ..Step::default()
+        }
+    }
+}
+
 /// A distributed algorithm that defines a message flow.
 pub trait DistAlgorithm: Send + Sync {
     /// Unique node identifier.
@@ -164,8 +304,15 @@ pub trait DistAlgorithm: Send + Sync {
     type Output;
     /// The messages that need to be exchanged between the instances in the participating nodes.
     type Message: Message;
-    /// The errors that can occur during execution.
+    /// The errors that can occur during execution. These are caused by the local node, e.g. by
+    /// invalid input or configuration, and never by other nodes' messages: Those are reported in
+    /// the fault log instead. The algorithms' error types have an `is_fatal` method that tells
+    /// whether the instance can continue after an error, or only the input was rejected.
     type Error: Fail;
+    /// The kinds of misbehavior of other nodes that the algorithm detects, and reports in the
+    /// fault and validation logs. Algorithms with nested instances of other algorithms wrap their
+    /// fault kinds.
+    type FaultKind: Copy + Debug + PartialEq + Send + Sync;
 
     /// Handles an input provided by the user, and returns
     fn handle_input(&mut self, input: Self::Input) -> Result<Step<Self>, Self::Error>
@@ -173,6 +320,13 @@ pub trait DistAlgorithm: Send + Sync {
         Self: Sized;
 
     /// Handles a message received from node `sender_id`.
+    ///
+    /// If the message is invalid, the sender is reported in the returned step's fault and
+    /// validation logs. A faulty sender must never be able to make this method return an error.
+    ///
+    /// The instance is never left partially updated: Non-fatal errors are detected before the
+    /// state is changed, and after a fatal error the instance aborts, i.e. it terminates without
+    /// further output and ignores all later messages.
     fn handle_message(
         &mut self,
         sender_id: &Self::NodeId,
@@ -181,6 +335,15 @@ pub trait DistAlgorithm: Send + Sync {
     where
         Self: Sized;
 
+    /// Handles the expiry of a timer that was requested with the given token. By default, this
+    /// does nothing.
+    fn handle_timeout(&mut self, _token: u64) -> Result<Step<Self>, Self::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Step::default())
+    }
+
     /// Returns `true` if execution has completed and this instance can be dropped.
     fn terminated(&self) -> bool;
 
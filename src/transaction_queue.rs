@@ -8,11 +8,33 @@ use Contribution;
 /// An interface to the transaction queue. A transaction queue is a structural part of
 /// `QueueingHoneyBadger` that manages enqueueing of transactions for a future batch and dequeueing
 /// of transactions to become part of a current batch.
+///
+/// The implementation for `Vec<T>` keeps the transactions in the order they were added, and
+/// proposes a uniformly random sample of `amount` of the first `batch_size` ones, i.e. of the
+/// oldest transactions. The sample is not in any particular order. Custom implementations can use
+/// any other strategy, e.g. prioritize transactions by their fees.
 pub trait TransactionQueue<T>: fmt::Debug + Default + Extend<T> + Sync + Send {
     /// Checks whether the queue is empty.
     fn is_empty(&self) -> bool;
-    /// Returns a new set of `amount` transactions, randomly chosen from the first `batch_size`.
-    /// No transactions are removed from the queue.
+    /// Returns the number of transactions in the queue.
+    fn len(&self) -> usize;
+    /// Checks whether the given transaction is in the queue.
+    ///
+    /// `QueueingHoneyBadger` calls this for every input transaction, to ignore duplicates. The
+    /// default implementation always returns `false`, so that duplicates are queued. Queues that
+    /// can look up transactions efficiently, e.g. using an index, should override it.
+    fn contains(&self, _tx: &T) -> bool
+    where
+        T: Contribution,
+    {
+        false
+    }
+    /// Returns a new set of at most `amount` transactions to be proposed, chosen from the
+    /// `batch_size` ones that should be committed next. No transactions are removed from the
+    /// queue.
+    ///
+    /// Since every validator proposes `amount` of the first `batch_size` transactions, choosing
+    /// them randomly reduces the number of duplicates in a batch.
     // TODO: Return references, once the `HoneyBadger` API accepts them.
     fn choose<R: Rng>(&mut self, rng: &mut R, amount: usize, batch_size: usize) -> Vec<T>;
     /// Removes the given transactions from the queue.
@@ -31,6 +53,19 @@ where
         self.is_empty()
     }
 
+    #[inline]
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn contains(&self, tx: &T) -> bool
+    where
+        T: Contribution,
+    {
+        self.iter().any(|queued| queued == tx)
+    }
+
     #[inline]
     fn remove_multiple<'a, I>(&mut self, txs: I)
     where
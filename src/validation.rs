@@ -0,0 +1,122 @@
+//! Classification of incoming messages.
+//!
+//! Before an algorithm dispatches a message received from a remote node to its handler, it
+//! validates the message and records the result in the `Step`'s validation log: The message is
+//! either handled, discarded as obsolete, postponed until a future epoch, or discarded as invalid,
+//! in which case the sender is also reported in the fault log. Messages that can't be decoded
+//! because they use an unsupported protocol version are classified by `wire::decode_message_from`
+//! before they reach an algorithm.
+//!
+//! Messages of nested algorithms, e.g. the Binary Agreement messages contained in a Honey Badger
+//! message, are validated by the nested instance, and their entries are included in the outer
+//! algorithm's step. Algorithms that only forward messages to nested instances, like `Subset`,
+//! don't add entries of their own.
+//!
+//! The fault kind `F` of a `Faulty` message is the algorithm's `DistAlgorithm::FaultKind`.
+
+/// The classification of an incoming message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Validity<F> {
+    /// The message is well-formed and was passed on to its handler.
+    Valid,
+    /// The message belongs to an epoch or instance that has already completed, and was discarded.
+    Obsolete,
+    /// The message belongs to a future epoch, and was queued to be handled later.
+    Future,
+    /// The message is invalid and was discarded. The sender is reported as faulty.
+    Faulty(F),
+    /// The message was encoded in a protocol version we don't support, and was discarded. The
+    /// sender is not reported as faulty, since it may just run a different version of this crate.
+    Incompatible,
+}
+
+impl<F> Validity<F> {
+    /// Returns `true` if the message was passed on to its handler.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Validity::Valid => true,
+            Validity::Obsolete
+            | Validity::Future
+            | Validity::Faulty(_)
+            | Validity::Incompatible => false,
+        }
+    }
+
+    /// Converts the fault kind, e.g. into the fault kind of an enclosing algorithm.
+    pub fn map<F2, FF>(self, f_fault: FF) -> Validity<F2>
+    where
+        FF: FnOnce(F) -> F2,
+    {
+        match self {
+            Validity::Valid => Validity::Valid,
+            Validity::Obsolete => Validity::Obsolete,
+            Validity::Future => Validity::Future,
+            Validity::Faulty(kind) => Validity::Faulty(f_fault(kind)),
+            Validity::Incompatible => Validity::Incompatible,
+        }
+    }
+}
+
+/// A validation log entry: the classification of a message received from `sender_id`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Validation<N, F> {
+    pub sender_id: N,
+    pub validity: Validity<F>,
+}
+
+impl<N, F> Validation<N, F> {
+    pub fn new(sender_id: N, validity: Validity<F>) -> Self {
+        Validation {
+            sender_id,
+            validity,
+        }
+    }
+}
+
+/// A structure containing the classifications of all messages handled in a step.
+#[derive(Debug, PartialEq)]
+pub struct ValidationLog<N, F>(pub Vec<Validation<N, F>>);
+
+impl<N, F> ValidationLog<N, F> {
+    /// Creates an empty `ValidationLog`.
+    pub fn new() -> Self {
+        ValidationLog::default()
+    }
+
+    /// Creates a new `ValidationLog` initialized with a single entry.
+    pub fn init(sender_id: N, validity: Validity<F>) -> Self {
+        ValidationLog(vec![Validation::new(sender_id, validity)])
+    }
+
+    /// Creates a new `Validation` and pushes it onto the log.
+    pub fn append(&mut self, sender_id: N, validity: Validity<F>) {
+        self.0.push(Validation::new(sender_id, validity));
+    }
+
+    /// Consumes `new_logs`, appending its entries onto the end of `self`.
+    pub fn extend(&mut self, new_logs: ValidationLog<N, F>) {
+        self.0.extend(new_logs.0);
+    }
+
+    /// Returns `true` if there are no entries in the log.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Converts the fault kinds, e.g. into the fault kind of an enclosing algorithm.
+    pub fn map<F2, FF>(self, mut f_fault: FF) -> ValidationLog<N, F2>
+    where
+        FF: FnMut(F) -> F2,
+    {
+        let map_entry = |entry: Validation<N, F>| {
+            Validation::new(entry.sender_id, entry.validity.map(&mut f_fault))
+        };
+        ValidationLog(self.0.into_iter().map(map_entry).collect())
+    }
+}
+
+impl<N, F> Default for ValidationLog<N, F> {
+    fn default() -> Self {
+        ValidationLog(vec![])
+    }
+}
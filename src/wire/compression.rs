@@ -0,0 +1,28 @@
+use snap;
+
+use super::{Error, Result};
+
+/// The maximum size of a decompressed payload. Larger payloads are rejected without decompressing
+/// them, to protect against compression bombs.
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Compresses the payload using Snappy.
+pub fn compress(payload: &[u8]) -> Result<Vec<u8>> {
+    snap::Encoder::new()
+        .compress_vec(payload)
+        .map_err(|err| Error::Compression(err.to_string()))
+}
+
+/// Decompresses a Snappy-compressed payload.
+pub fn decompress(compressed: &[u8]) -> Result<Vec<u8>> {
+    let len = snap::decompress_len(compressed).map_err(|err| Error::Compression(err.to_string()))?;
+    if len > MAX_DECOMPRESSED_SIZE {
+        return Err(Error::Compression(format!(
+            "decompressed size {} exceeds the limit",
+            len
+        )));
+    }
+    snap::Decoder::new()
+        .decompress_vec(compressed)
+        .map_err(|err| Error::Compression(err.to_string()))
+}
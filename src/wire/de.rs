@@ -0,0 +1,342 @@
+use std::{char, str};
+
+use byteorder::{BigEndian, ByteOrder};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+
+use super::{
+    Error, Result, AGREEMENT_EPOCH_NAME, PART_OFFSET_NAME, PART_OFFSET_VERSION,
+    SORTED_MAP_VERSION, U64_AGREEMENT_EPOCH_VERSION, WIRE_VERSION,
+};
+
+/// A deserializer reading the canonical wire encoding.
+///
+/// Decoding is strict: Every value has exactly one valid encoding, and any other byte sequence is
+/// rejected. Only versions before 3 accept map entries in any order. See the
+/// [module documentation](index.html) for a description of the format.
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+    version: u8,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Creates a deserializer reading the current version of the encoding from the given bytes.
+    pub fn new(input: &'de [u8]) -> Self {
+        Deserializer::with_version(input, WIRE_VERSION)
+    }
+
+    /// Creates a deserializer reading the given version of the encoding from the given bytes.
+    pub fn with_version(input: &'de [u8], version: u8) -> Self {
+        Deserializer { input, version }
+    }
+
+    /// Returns an error if there are any bytes left in the input.
+    pub fn end(&self) -> Result<()> {
+        if self.input.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes(self.input.len()))
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'de [u8]> {
+        let input = self.input;
+        if input.len() < n {
+            return Err(Error::UnexpectedEnd);
+        }
+        let (bytes, rest) = input.split_at(n);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn get_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn get_u16(&mut self) -> Result<u16> {
+        Ok(BigEndian::read_u16(self.take(2)?))
+    }
+
+    fn get_u32(&mut self) -> Result<u32> {
+        Ok(BigEndian::read_u32(self.take(4)?))
+    }
+
+    fn get_u64(&mut self) -> Result<u64> {
+        Ok(BigEndian::read_u64(self.take(8)?))
+    }
+
+    fn get_len(&mut self) -> Result<usize> {
+        Ok(self.get_u32()? as usize)
+    }
+
+    fn get_bytes(&mut self) -> Result<&'de [u8]> {
+        let len = self.get_len()?;
+        self.take(len)
+    }
+
+    fn get_str(&mut self) -> Result<&'de str> {
+        str::from_utf8(self.get_bytes()?).map_err(|_| Error::InvalidUtf8)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::UnsupportedType("any"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.get_u8()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            b => Err(Error::InvalidBool(b)),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(self.get_u8()? as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i16(self.get_u16()? as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i32(self.get_u32()? as i32)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i64(self.get_u64()? as i64)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.get_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u16(self.get_u16()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.get_u32()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(self.get_u64()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_f32(f32::from_bits(self.get_u32()?))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_f64(f64::from_bits(self.get_u64()?))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let c = self.get_u32()?;
+        visitor.visit_char(char::from_u32(c).ok_or(Error::InvalidChar(c))?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.get_str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.get_str()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.get_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.get_bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.get_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            b => Err(Error::InvalidOptionTag(b)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        if name == AGREEMENT_EPOCH_NAME && self.version < U64_AGREEMENT_EPOCH_VERSION {
+            // Older versions encode agreement epochs as `u32`.
+            let epoch = u64::from(self.get_u32()?);
+            return visitor.visit_newtype_struct(epoch.into_deserializer());
+        }
+        if name == PART_OFFSET_NAME && self.version < PART_OFFSET_VERSION {
+            // Older versions have no offset, which is equivalent to the empty one.
+            return visitor.visit_newtype_struct(Vec::<u8>::new().into_deserializer());
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.get_len()?;
+        visitor.visit_seq(Access::new(self, len))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Access::new(self, len))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let len = self.get_len()?;
+        visitor.visit_map(Access::new(self, len))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::UnsupportedType("identifier"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::UnsupportedType("ignored_any"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Access to the elements of a sequence, tuple, struct or map with a known number of entries.
+struct Access<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    len: usize,
+    /// The encoding of the previous key, if this is a map.
+    prev_key: Option<&'de [u8]>,
+}
+
+impl<'a, 'de> Access<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, len: usize) -> Self {
+        Access {
+            de,
+            len,
+            prev_key: None,
+        }
+    }
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+        let input = self.de.input;
+        let key = seed.deserialize(&mut *self.de)?;
+        let key_bytes = &input[..input.len() - self.de.input.len()];
+        if self.de.version >= SORTED_MAP_VERSION && self.prev_key >= Some(key_bytes) {
+            return Err(Error::UnorderedMapKeys);
+        }
+        self.prev_key = Some(key_bytes);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let index: de::value::U32Deserializer<Error> = self.get_u32()?.into_deserializer();
+        let value = seed.deserialize(index)?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
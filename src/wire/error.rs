@@ -0,0 +1,88 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+
+use serde::{de, ser};
+
+/// A wire encoding or decoding error.
+///
+/// This type implements `std::error::Error`, and therefore `failure::Fail` as well.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Error {
+    /// A custom error reported by a `Serialize` or `Deserialize` implementation.
+    Custom(String),
+    /// The input ended before the value was completely decoded.
+    UnexpectedEnd,
+    /// The input contained additional bytes after the decoded value.
+    TrailingBytes(usize),
+    /// A length does not fit into the 32 bit length prefix.
+    LengthOverflow(usize),
+    /// A sequence or map was serialized without knowing its length in advance.
+    UnknownLength,
+    /// A `bool` was encoded as a byte other than `0` or `1`.
+    InvalidBool(u8),
+    /// An `Option` tag was a byte other than `0` or `1`.
+    InvalidOptionTag(u8),
+    /// A `char` was encoded as an invalid Unicode scalar value.
+    InvalidChar(u32),
+    /// A string was not valid UTF-8.
+    InvalidUtf8,
+    /// The type cannot be represented in the canonical encoding.
+    UnsupportedType(&'static str),
+    /// The value of the given type cannot be represented in the given version of the encoding.
+    UnsupportedValue(&'static str, u8),
+    /// The envelope's version is not supported by this implementation.
+    UnsupportedVersion(u8),
+    /// The tagged envelope's algorithm tag doesn't match the expected message type.
+    UnexpectedAlgorithm(u8),
+    /// A map's keys are not in strictly ascending order of their encodings.
+    UnorderedMapKeys,
+    /// A compressed payload could not be compressed or decompressed.
+    #[cfg(feature = "compression")]
+    Compression(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Custom(ref msg) => write!(f, "Custom error: {}", msg),
+            Error::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            Error::TrailingBytes(n) => write!(f, "{} trailing bytes after value", n),
+            Error::LengthOverflow(len) => write!(f, "Length {} exceeds 32 bits", len),
+            Error::UnknownLength => write!(f, "Sequence length must be known in advance"),
+            Error::InvalidBool(b) => write!(f, "Invalid bool byte: {}", b),
+            Error::InvalidOptionTag(b) => write!(f, "Invalid option tag: {}", b),
+            Error::InvalidChar(c) => write!(f, "Invalid char: {}", c),
+            Error::InvalidUtf8 => write!(f, "Invalid UTF-8 string"),
+            Error::UnsupportedType(name) => write!(f, "Unsupported type: {}", name),
+            Error::UnsupportedValue(name, v) => {
+                write!(f, "{} value not supported in wire version {}", name, v)
+            }
+            Error::UnsupportedVersion(v) => write!(f, "Unsupported wire version: {}", v),
+            Error::UnexpectedAlgorithm(tag) => write!(f, "Unexpected algorithm tag: {}", tag),
+            Error::UnorderedMapKeys => write!(f, "Map keys are not in ascending order"),
+            #[cfg(feature = "compression")]
+            Error::Compression(ref msg) => write!(f, "Compression error: {}", msg),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        "wire encoding error"
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+/// A wire encoding result.
+pub type Result<T> = ::std::result::Result<T, Error>;
@@ -0,0 +1,369 @@
+//! # Canonical wire encoding
+//!
+//! A deterministic, versioned binary encoding for all `hbbft` message types. Unlike `bincode`,
+//! whose representation choices are an implementation detail of that crate, the format defined
+//! here is fully specified below, so that independently developed nodes, and future versions of
+//! this crate, can exchange messages.
+//!
+//! ## Format
+//!
+//! Values are encoded recursively via their `serde` implementations:
+//!
+//! * `bool`: one byte, `0` or `1`.
+//! * Integers: fixed width, big-endian, two's complement for signed types. `usize` and `isize`
+//!   are serialized by `serde` as 64 bit integers.
+//! * Floating point numbers: the IEEE 754 bit pattern, as a big-endian integer.
+//! * `char`: the Unicode scalar value, as a big-endian `u32`.
+//! * Strings and byte arrays: a 32 bit big-endian length, followed by the bytes.
+//! * `Option`: a tag byte `0` for `None`, or `1` for `Some`, followed by the value.
+//! * Unit types: nothing.
+//! * Sequences and maps: a 32 bit big-endian number of elements, followed by the elements. Map
+//!   entries are encoded as key, then value, and sorted by the encodings of their keys, compared
+//!   as byte strings.
+//! * Tuples and structs: the fields in declaration order, without any prefix.
+//! * Enums: the variant's index as a 32 bit big-endian integer, followed by its fields.
+//!
+//! Every value has exactly one valid encoding. Decoding is strict and rejects any other byte
+//! sequence, including trailing bytes, invalid UTF-8, `bool` values other than `0` and `1`, and
+//! map keys that are not strictly ascending. For unsigned integer keys, the order of the
+//! encodings is the numerical order, but e.g. for strings, whose encodings start with the length,
+//! it differs from the keys' own order.
+//!
+//! ## Envelopes
+//!
+//! `encode` and `decode` wrap the encoded value in an _envelope_: a single version byte, followed
+//! by the encoded value. The current version is `WIRE_VERSION`.
+//!
+//! ## Versions
+//!
+//! The versions differ as follows:
+//!
+//! * Version 1 encodes Binary Agreement epochs (`AgreementEpoch`) as a `u32`.
+//! * Version 2 encodes them as a `u64`.
+//! * Version 3 adds the offset (`PartOffset`) to key generation `Part`s, which is needed to
+//!   reshare an existing key. Older versions can only encode `Part`s without an offset.
+//!   Version 3 is also the first one in which the order of map entries is checked: Older versions
+//!   allowed any order, so their maps' encodings are not unique.
+//!
+//! `decode` accepts every version from `MIN_WIRE_VERSION` to `WIRE_VERSION`, the range exposed
+//! as `PROTOCOL_VERSIONS`, and decodes the payload according to the envelope's version. During an
+//! upgrade, nodes exchange their supported ranges, e.g. in a handshake, and `negotiate_version`
+//! picks the newest version both of them understand. Messages to that peer are then encoded in
+//! the negotiated version with `encode_with_version` or `encode_message_with_version`, so that
+//! nodes running different versions of this crate can form a network, as long as their ranges
+//! overlap. A value that can't be represented in the negotiated version, e.g. an agreement epoch
+//! above `u32::max_value()` in version 1, fails to encode with `Error::UnsupportedValue`.
+//!
+//! `decode_message_from` decodes a message received from a peer and, instead of an error,
+//! returns the message's `Validation` if it can't be decoded: `Validity::Incompatible` if it uses
+//! a version we don't support, which is not the sender's fault, and `Validity::Faulty` with
+//! `wire::FaultKind::UndecodableMessage` otherwise. An application that decodes the messages
+//! itself can record these alongside the algorithms' own validation and fault logs.
+//!
+//! With the `compression` feature, `encode_compressed` compresses payloads above a given size
+//! using [Snappy](https://github.com/google/snappy). Compressed envelopes have the
+//! `COMPRESSED_FLAG` bit set in their version byte, so that peers without compression support
+//! reject them as an unsupported version instead of misinterpreting them. Senders should
+//! therefore only use `encode_compressed` if all recipients support compression.
+//!
+//! The top-level messages of the algorithms implement `AlgorithmMessage`. `encode_message`
+//! additionally puts the algorithm's tag byte, as defined by `Algorithm`, in front of the encoded
+//! message, and `decode_message` rejects messages with a different tag. This way, a node that
+//! runs several algorithms over the same connection can't mistake a message for another
+//! algorithm's. Compression can be combined with tags using `encode_message_compressed`.
+//!
+//! ## Stability
+//!
+//! For a given version, the encoding of a message type only changes if the type itself changes.
+//! Changes to the message types that are not backwards compatible, such as reordering enum
+//! variants or struct fields, will be accompanied by a new version number. The tests in
+//! `tests/wire.rs` pin the exact encoding of a number of messages.
+
+#[cfg(feature = "compression")]
+mod compression;
+mod de;
+mod error;
+mod ser;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use validation::{Validation, Validity};
+use {binary_agreement, broadcast, dynamic_honey_badger, honey_badger, subset};
+
+pub use self::de::Deserializer;
+pub use self::error::{Error, Result};
+pub use self::ser::Serializer;
+
+/// The current version of the wire encoding.
+pub const WIRE_VERSION: u8 = 3;
+
+/// The oldest version of the wire encoding that is still accepted.
+pub const MIN_WIRE_VERSION: u8 = 1;
+
+/// The newest version of the wire encoding and the protocol implemented by this crate.
+pub const PROTOCOL_VERSION: u8 = WIRE_VERSION;
+
+/// The range of protocol versions this crate can decode, as `(oldest, newest)`.
+pub const PROTOCOL_VERSIONS: (u8, u8) = (MIN_WIRE_VERSION, WIRE_VERSION);
+
+/// The first version that encodes agreement epochs as `u64` instead of `u32`.
+const U64_AGREEMENT_EPOCH_VERSION: u8 = 2;
+
+/// The name under which `AgreementEpoch` is serialized.
+const AGREEMENT_EPOCH_NAME: &str = "AgreementEpoch";
+
+/// The first version that encodes the offset in key generation `Part`s.
+const PART_OFFSET_VERSION: u8 = 3;
+
+/// The name under which the offset in a key generation `Part` is serialized.
+const PART_OFFSET_NAME: &str = "PartOffset";
+
+/// The first version that rejects map keys that are not in ascending order.
+const SORTED_MAP_VERSION: u8 = 3;
+
+/// Represents each reason why an encoded message could be faulty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    /// A message could not be decoded.
+    UndecodableMessage,
+}
+
+/// The bit in the envelope's version byte that indicates a compressed payload.
+pub const COMPRESSED_FLAG: u8 = 0x80;
+
+/// The default size in bytes above which `encode_compressed` compresses payloads.
+#[cfg(feature = "compression")]
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+#[cfg(feature = "compression")]
+pub use self::compression::MAX_DECOMPRESSED_SIZE;
+
+/// Returns the canonical encoding of `value`, without an envelope.
+pub fn to_bytes<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut serializer = Serializer::new(Vec::new());
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_inner())
+}
+
+/// Decodes a value from its canonical encoding, without an envelope.
+///
+/// Returns an error if `bytes` is not exactly the encoding of a `T`.
+pub fn from_bytes<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T> {
+    from_bytes_with_version(bytes, WIRE_VERSION)
+}
+
+/// Decodes a value from the given version of its encoding, without an envelope.
+fn from_bytes_with_version<'a, T: Deserialize<'a>>(bytes: &'a [u8], version: u8) -> Result<T> {
+    let mut deserializer = Deserializer::with_version(bytes, version);
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Returns `true` if the given version, without the `COMPRESSED_FLAG`, is supported.
+fn is_supported(version: u8) -> bool {
+    MIN_WIRE_VERSION <= version && version <= WIRE_VERSION
+}
+
+/// Returns the encoding of `value`, wrapped in an envelope with the current version.
+pub fn encode<T: ?Sized + Serialize>(value: &T) -> Result<Vec<u8>> {
+    encode_with_version(value, WIRE_VERSION)
+}
+
+/// Returns the encoding of `value` in the given version, e.g. the one negotiated with the
+/// recipient, wrapped in an envelope with that version.
+///
+/// Returns an error if the version is not supported, or if the value can't be represented in it.
+pub fn encode_with_version<T: ?Sized + Serialize>(value: &T, version: u8) -> Result<Vec<u8>> {
+    if !is_supported(version) {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let mut serializer = Serializer::with_version(vec![version], version);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_inner())
+}
+
+/// Returns the encoding of `value`, wrapped in an envelope with the current version. If the
+/// encoding is larger than `threshold` bytes, it is compressed, unless that doesn't reduce its
+/// size.
+#[cfg(feature = "compression")]
+pub fn encode_compressed<T: ?Sized + Serialize>(value: &T, threshold: usize) -> Result<Vec<u8>> {
+    encode_compressed_with_version(value, threshold, WIRE_VERSION)
+}
+
+/// Returns the encoding of `value` in the given version, wrapped in an envelope with that
+/// version. If the encoding is larger than `threshold` bytes, it is compressed, unless that
+/// doesn't reduce its size.
+#[cfg(feature = "compression")]
+pub fn encode_compressed_with_version<T: ?Sized + Serialize>(
+    value: &T,
+    threshold: usize,
+    version: u8,
+) -> Result<Vec<u8>> {
+    let bytes = encode_with_version(value, version)?;
+    if bytes.len() - 1 > threshold {
+        let compressed = compression::compress(&bytes[1..])?;
+        if compressed.len() < bytes.len() - 1 {
+            let mut compressed_bytes = Vec::with_capacity(compressed.len() + 1);
+            compressed_bytes.push(version | COMPRESSED_FLAG);
+            compressed_bytes.extend(compressed);
+            return Ok(compressed_bytes);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decodes a value wrapped in an envelope, according to the envelope's version.
+///
+/// Returns an error if the envelope's version is not supported, or if the contents are not
+/// exactly the encoding of a `T`. Compressed envelopes are only supported with the `compression`
+/// feature.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let (version, payload) = bytes.split_first().ok_or(Error::UnexpectedEnd)?;
+    match *version {
+        v if is_supported(v) => from_bytes_with_version(payload, v),
+        #[cfg(feature = "compression")]
+        v if is_supported(v & !COMPRESSED_FLAG) => {
+            let payload = compression::decompress(payload)?;
+            from_bytes_with_version(&payload, v & !COMPRESSED_FLAG)
+        }
+        v => Err(Error::UnsupportedVersion(v)),
+    }
+}
+
+/// The algorithms whose messages can be sent in a tagged envelope. The discriminant is the tag.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Algorithm {
+    Broadcast = 1,
+    BinaryAgreement = 2,
+    Subset = 3,
+    HoneyBadger = 4,
+    DynamicHoneyBadger = 5,
+}
+
+impl Algorithm {
+    /// Returns the algorithm's tag byte.
+    pub fn tag(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the algorithm with the given tag byte, if there is one.
+    pub fn from_tag(tag: u8) -> Option<Algorithm> {
+        match tag {
+            1 => Some(Algorithm::Broadcast),
+            2 => Some(Algorithm::BinaryAgreement),
+            3 => Some(Algorithm::Subset),
+            4 => Some(Algorithm::HoneyBadger),
+            5 => Some(Algorithm::DynamicHoneyBadger),
+            _ => None,
+        }
+    }
+}
+
+/// A top-level message of one of the algorithms, which can be sent in a tagged envelope.
+pub trait AlgorithmMessage: Serialize + DeserializeOwned {
+    /// The algorithm the message belongs to.
+    const ALGORITHM: Algorithm;
+}
+
+impl AlgorithmMessage for broadcast::Message {
+    const ALGORITHM: Algorithm = Algorithm::Broadcast;
+}
+
+impl AlgorithmMessage for binary_agreement::Message {
+    const ALGORITHM: Algorithm = Algorithm::BinaryAgreement;
+}
+
+impl<N: Serialize + DeserializeOwned> AlgorithmMessage for subset::Message<N> {
+    const ALGORITHM: Algorithm = Algorithm::Subset;
+}
+
+impl<N: Serialize + DeserializeOwned> AlgorithmMessage for honey_badger::Message<N> {
+    const ALGORITHM: Algorithm = Algorithm::HoneyBadger;
+}
+
+impl<N> AlgorithmMessage for dynamic_honey_badger::Message<N>
+where
+    N: Serialize + DeserializeOwned,
+{
+    const ALGORITHM: Algorithm = Algorithm::DynamicHoneyBadger;
+}
+
+/// Returns the encoding of `message`, wrapped in an envelope with the current version and the
+/// message's algorithm tag.
+pub fn encode_message<M: AlgorithmMessage>(message: &M) -> Result<Vec<u8>> {
+    encode_message_with_version(message, WIRE_VERSION)
+}
+
+/// Returns the encoding of `message` in the given version, wrapped in an envelope with that
+/// version and the message's algorithm tag.
+pub fn encode_message_with_version<M: AlgorithmMessage>(
+    message: &M,
+    version: u8,
+) -> Result<Vec<u8>> {
+    encode_with_version(&(M::ALGORITHM.tag(), message), version)
+}
+
+/// Returns the encoding of `message`, wrapped in an envelope with the current version and the
+/// message's algorithm tag. If the encoding is larger than `threshold` bytes, it is compressed,
+/// unless that doesn't reduce its size.
+#[cfg(feature = "compression")]
+pub fn encode_message_compressed<M: AlgorithmMessage>(
+    message: &M,
+    threshold: usize,
+) -> Result<Vec<u8>> {
+    encode_message_compressed_with_version(message, threshold, WIRE_VERSION)
+}
+
+/// Returns the encoding of `message` in the given version, wrapped in an envelope with that
+/// version and the message's algorithm tag. If the encoding is larger than `threshold` bytes, it
+/// is compressed, unless that doesn't reduce its size.
+#[cfg(feature = "compression")]
+pub fn encode_message_compressed_with_version<M: AlgorithmMessage>(
+    message: &M,
+    threshold: usize,
+    version: u8,
+) -> Result<Vec<u8>> {
+    encode_compressed_with_version(&(M::ALGORITHM.tag(), message), threshold, version)
+}
+
+/// Decodes a message wrapped in a tagged envelope.
+///
+/// Returns an error if the envelope's version is not supported, if its tag doesn't belong to the
+/// algorithm of `M`, or if the contents are not exactly the encoding of an `M`.
+pub fn decode_message<M: AlgorithmMessage>(bytes: &[u8]) -> Result<M> {
+    let (tag, message): (u8, M) = decode(bytes)?;
+    if tag != M::ALGORITHM.tag() {
+        return Err(Error::UnexpectedAlgorithm(tag));
+    }
+    Ok(message)
+}
+
+/// Returns the newest protocol version supported both by us and by a peer that supports all
+/// versions from `peer_min` to `peer_max`, or `None` if there is none.
+pub fn negotiate_version(peer_min: u8, peer_max: u8) -> Option<u8> {
+    let version = peer_max.min(WIRE_VERSION);
+    if version >= peer_min.max(MIN_WIRE_VERSION) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+/// Decodes a message wrapped in a tagged envelope, received from `sender_id`.
+///
+/// If the message can't be decoded, returns its validation instead: `Incompatible` if the
+/// envelope's version is not supported, and `Faulty` with `UndecodableMessage` otherwise.
+pub fn decode_message_from<M: AlgorithmMessage, N: Clone>(
+    sender_id: &N,
+    bytes: &[u8],
+) -> ::std::result::Result<M, Validation<N, FaultKind>> {
+    decode_message(bytes).map_err(|err| {
+        let validity = match err {
+            Error::UnsupportedVersion(_) => Validity::Incompatible,
+            _ => Validity::Faulty(FaultKind::UndecodableMessage),
+        };
+        Validation::new(sender_id.clone(), validity)
+    })
+}
@@ -0,0 +1,415 @@
+use byteorder::{BigEndian, ByteOrder};
+use serde::ser::{self, Serialize};
+
+use super::{
+    Error, Result, AGREEMENT_EPOCH_NAME, PART_OFFSET_NAME, PART_OFFSET_VERSION,
+    U64_AGREEMENT_EPOCH_VERSION, WIRE_VERSION,
+};
+
+/// A serializer producing the canonical wire encoding.
+///
+/// See the [module documentation](index.html) for a description of the format.
+pub struct Serializer {
+    output: Vec<u8>,
+    version: u8,
+    /// The maps that are currently being serialized, innermost last.
+    maps: Vec<MapEntries>,
+}
+
+/// The positions of a map's entries in the output, so they can be sorted once it is complete.
+struct MapEntries {
+    /// The position of the first entry.
+    start: usize,
+    /// The position of each entry's key and value.
+    entries: Vec<(usize, usize)>,
+}
+
+impl Serializer {
+    /// Creates a serializer that appends to the given buffer, using the current version of the
+    /// encoding.
+    pub fn new(output: Vec<u8>) -> Self {
+        Serializer::with_version(output, WIRE_VERSION)
+    }
+
+    /// Creates a serializer that appends to the given buffer, using the given version of the
+    /// encoding.
+    pub fn with_version(output: Vec<u8>, version: u8) -> Self {
+        Serializer {
+            output,
+            version,
+            maps: Vec::new(),
+        }
+    }
+
+    /// Returns the encoded bytes.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.output
+    }
+
+    fn put_u16(&mut self, v: u16) {
+        let mut buf = [0; 2];
+        BigEndian::write_u16(&mut buf, v);
+        self.output.extend_from_slice(&buf);
+    }
+
+    fn put_u32(&mut self, v: u32) {
+        let mut buf = [0; 4];
+        BigEndian::write_u32(&mut buf, v);
+        self.output.extend_from_slice(&buf);
+    }
+
+    fn put_u64(&mut self, v: u64) {
+        let mut buf = [0; 8];
+        BigEndian::write_u64(&mut buf, v);
+        self.output.extend_from_slice(&buf);
+    }
+
+    /// Writes a length prefix, failing if the length does not fit into 32 bits.
+    fn put_len(&mut self, len: usize) -> Result<()> {
+        if len as u64 > u64::from(u32::max_value()) {
+            return Err(Error::LengthOverflow(len));
+        }
+        self.put_u32(len as u32);
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_u8(v as u8)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_u16(v as u16)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.put_u16(v);
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.put_u32(v);
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.put_u64(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.serialize_u32(v.to_bits())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.serialize_u64(v.to_bits())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.put_len(v.len())?;
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        if name == PART_OFFSET_NAME && self.version < PART_OFFSET_VERSION {
+            // Older versions have no offset, so only the empty one can be encoded.
+            let start = self.output.len();
+            value.serialize(&mut *self)?;
+            if self.output[start..] != [0, 0, 0, 0] {
+                return Err(Error::UnsupportedValue(name, self.version));
+            }
+            self.output.truncate(start);
+            return Ok(());
+        }
+        if name != AGREEMENT_EPOCH_NAME || self.version >= U64_AGREEMENT_EPOCH_VERSION {
+            return value.serialize(self);
+        }
+        // Older versions encode agreement epochs as `u32`.
+        let start = self.output.len();
+        value.serialize(&mut *self)?;
+        if self.output.len() - start != 8 {
+            return Err(Error::UnsupportedType(name));
+        }
+        let epoch = BigEndian::read_u64(&self.output[start..]);
+        if epoch > u64::from(u32::max_value()) {
+            return Err(Error::UnsupportedValue(name, self.version));
+        }
+        self.output.truncate(start);
+        self.put_u32(epoch as u32);
+        Ok(())
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.put_u32(variant_index);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.put_len(len.ok_or(Error::UnknownLength)?)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.put_u32(variant_index);
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        let len = len.ok_or(Error::UnknownLength)?;
+        self.put_len(len)?;
+        self.maps.push(MapEntries {
+            start: self.output.len(),
+            entries: Vec::with_capacity(len),
+        });
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.put_u32(variant_index);
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl<'a> ser::SerializeSeq for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        let key_start = self.output.len();
+        key.serialize(&mut **self)?;
+        let value_start = self.output.len();
+        if let Some(map) = self.maps.last_mut() {
+            map.entries.push((key_start, value_start));
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    /// Sorts the entries by their keys' encodings.
+    fn end(self) -> Result<()> {
+        let map = match self.maps.pop() {
+            Some(map) => map,
+            None => return Ok(()),
+        };
+        let entries = {
+            let output = &self.output;
+            let end = output.len();
+            let mut entries: Vec<(&[u8], &[u8])> = map
+                .entries
+                .iter()
+                .enumerate()
+                .map(|(i, &(key_start, value_start))| {
+                    let entry_end = map.entries.get(i + 1).map_or(end, |entry| entry.0);
+                    (&output[key_start..value_start], &output[key_start..entry_end])
+                }).collect();
+            entries.sort();
+            if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(Error::UnorderedMapKeys);
+            }
+            entries
+                .into_iter()
+                .flat_map(|(_, entry)| entry.iter().cloned())
+                .collect::<Vec<u8>>()
+        };
+        self.output.truncate(map.start);
+        self.output.extend(entries);
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
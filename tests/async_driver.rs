@@ -0,0 +1,151 @@
+#![deny(unused_must_use)]
+//! Tests of the asynchronous driver
+
+extern crate hbbft;
+extern crate rand;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hbbft::async_driver::{Driver, DriverHandle, Event, Status};
+use hbbft::binary_agreement::BinaryAgreement;
+use hbbft::coin;
+use hbbft::{DistAlgorithm, NetworkInfo, Step, Target};
+
+type Ba = BinaryAgreement<usize>;
+
+/// Returns `true` if the message with the given target must be delivered to node `id`.
+fn is_recipient(target: &Target<usize>, sender_id: usize, id: usize) -> bool {
+    id != sender_id && match *target {
+        Target::All => true,
+        Target::AllExcept(ref ids) => !ids.contains(&id),
+        Target::Node(ref node_id) => *node_id == id,
+        Target::Nodes(ref ids) => ids.contains(&id),
+        Target::Observers => false,
+    }
+}
+
+#[test]
+fn test_binary_agreement_drivers() {
+    let netinfos = NetworkInfo::generate_map(0..4, &mut rand::thread_rng())
+        .expect("Failed to create `NetworkInfo` map");
+    let mut drivers: BTreeMap<usize, Driver<Ba>> = BTreeMap::new();
+    let mut handles: BTreeMap<usize, DriverHandle<Ba>> = BTreeMap::new();
+    for (id, netinfo) in netinfos {
+        let ba = BinaryAgreement::new(Arc::new(netinfo), "test", 0).expect("BA instance");
+        let (mut driver, handle) = Driver::new(ba);
+        if id % 2 == 0 {
+            handle.events.send(Event::Input(true)).expect("send input");
+        } else {
+            // The other nodes provide their input via the timer.
+            driver.set_timer(Duration::from_secs(0), |ba: &Ba| {
+                if ba.accepts_input() {
+                    Some(false)
+                } else {
+                    None
+                }
+            });
+        }
+        drivers.insert(id, driver);
+        handles.insert(id, handle);
+    }
+
+    let mut outputs = BTreeMap::new();
+    for _ in 0..1000 {
+        if outputs.len() == handles.len() {
+            break;
+        }
+        for driver in drivers.values_mut() {
+            let _ = driver.poll(Instant::now()).expect("poll driver");
+        }
+        for (&sender_id, handle) in &handles {
+            for tmsg in handle.messages.try_iter() {
+                for (&id, recipient) in &handles {
+                    if is_recipient(&tmsg.target, sender_id, id) {
+                        let event = Event::Message(sender_id, tmsg.message.clone());
+                        recipient.events.send(event).expect("send message");
+                    }
+                }
+            }
+            outputs.extend(handle.outputs.try_iter().map(|b| (sender_id, b)));
+            assert!(handle.faults.try_recv().is_err(), "no faults expected");
+        }
+    }
+
+    assert_eq!(handles.len(), outputs.len(), "all nodes must output");
+    let first = *outputs.values().next().expect("output");
+    assert!(outputs.values().all(|b| *b == first));
+    for driver in drivers.values_mut() {
+        assert_eq!(Status::Terminated, driver.poll(Instant::now()).expect("poll"));
+    }
+}
+
+#[test]
+fn test_driver_disconnected() {
+    let mut netinfos = NetworkInfo::generate_map(0..4, &mut rand::thread_rng())
+        .expect("Failed to create `NetworkInfo` map");
+    let netinfo = Arc::new(netinfos.remove(&0).expect("our `NetworkInfo`"));
+    let ba: Ba = BinaryAgreement::new(netinfo, "test", 0).expect("BA instance");
+    let (mut driver, handle) = Driver::new(ba);
+    handle.events.send(Event::Input(true)).expect("send input");
+    assert_eq!(Status::Pending(None), driver.poll(Instant::now()).expect("poll"));
+    assert!(handle.messages.try_recv().is_ok(), "our `BVal` must be sent");
+
+    // Once the handle is dropped, the blocking loop returns.
+    drop(handle);
+    assert_eq!(Status::Disconnected, driver.run().expect("run"));
+}
+
+/// A test algorithm that requests a timer of `n` ticks on input `n`, and outputs `n` when it
+/// expires.
+#[derive(Debug)]
+struct Ticker;
+
+impl DistAlgorithm for Ticker {
+    type NodeId = usize;
+    type Input = u64;
+    type Output = u64;
+    type Message = ();
+    type Error = coin::Error;
+    type FaultKind = coin::FaultKind;
+
+    fn handle_input(&mut self, ticks: u64) -> Result<Step<Self>, coin::Error> {
+        Ok(Step::default().with_timer(ticks, ticks))
+    }
+
+    fn handle_message(&mut self, _: &usize, _: ()) -> Result<Step<Self>, coin::Error> {
+        Ok(Step::default())
+    }
+
+    fn handle_timeout(&mut self, token: u64) -> Result<Step<Self>, coin::Error> {
+        Ok(Step::default().with_output(token))
+    }
+
+    fn terminated(&self) -> bool {
+        false
+    }
+
+    fn our_id(&self) -> &usize {
+        &0
+    }
+}
+
+#[test]
+fn test_driver_timeouts() {
+    let (mut driver, handle) = Driver::new(Ticker);
+    driver.set_tick_duration(Duration::from_secs(1));
+    handle.events.send(Event::Input(2)).expect("send input");
+    handle.events.send(Event::Input(1)).expect("send input");
+
+    let start = Instant::now();
+    let after = |secs| start + Duration::from_secs(secs);
+    assert_eq!(Status::Pending(Some(after(1))), driver.poll(start).expect("poll"));
+    assert!(handle.outputs.try_recv().is_err());
+
+    assert_eq!(Status::Pending(Some(after(2))), driver.poll(after(1)).expect("poll"));
+    assert_eq!(vec![1], handle.outputs.try_iter().collect::<Vec<_>>());
+
+    assert_eq!(Status::Pending(None), driver.poll(after(5)).expect("poll"));
+    assert_eq!(vec![2], handle.outputs.try_iter().collect::<Vec<_>>());
+}
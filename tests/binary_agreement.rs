@@ -18,6 +18,8 @@ extern crate env_logger;
 extern crate hbbft;
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate proptest;
 extern crate rand;
 #[macro_use]
 extern crate serde_derive;
@@ -28,19 +30,32 @@ extern crate threshold_crypto as crypto;
 mod network;
 
 use std::iter::once;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use proptest::collection::vec;
+use proptest::strategy::Strategy;
 use rand::Rng;
 
-use hbbft::binary_agreement::BinaryAgreement;
-use hbbft::NetworkInfo;
+use hbbft::binary_agreement::{
+    bool_set, BinaryAgreement, CoinSchedule, Error, FaultKind, MessageContent, Nonce,
+    ReceivedCounts, SbvMessage, SlowAgreementReport,
+};
+use hbbft::coin::{CoinMessage, CoinSource, InsecureCoin};
+use hbbft::environment::{Environment, Restore, RestoreError};
+use hbbft::validation::Validity;
+use hbbft::{AgreementEpoch, DistAlgorithm, NetworkInfo, Step, Target};
 
-use network::{Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode};
+use network::{
+    test_netinfos, Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode,
+};
 
-fn test_binary_agreement<A: Adversary<BinaryAgreement<NodeId>>>(
-    mut network: TestNetwork<A, BinaryAgreement<NodeId>>,
+fn test_binary_agreement<A, S>(
+    mut network: TestNetwork<A, BinaryAgreement<NodeId, S>>,
     input: Option<bool>,
-) {
+) where
+    A: Adversary<BinaryAgreement<NodeId, S>>,
+    S: CoinSource<NodeId, Nonce>,
+{
     let ids: Vec<NodeId> = network.nodes.keys().cloned().collect();
     for id in ids {
         network.input(id, input.unwrap_or_else(rand::random));
@@ -85,7 +100,7 @@ where
             );
             let adversary = |_| new_adversary(num_good_nodes, num_faulty_nodes);
             let new_ba = |netinfo: Arc<NetworkInfo<NodeId>>| {
-                BinaryAgreement::new(netinfo, 0, NodeId(0)).expect("Binary Agreement instance")
+                BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance")
             };
             let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_ba);
             test_binary_agreement(network, input);
@@ -108,3 +123,338 @@ fn test_binary_agreement_first_silent() {
     let new_adversary = |_: usize, _: usize| SilentAdversary::new(MessageScheduler::First);
     test_binary_agreement_different_sizes(new_adversary);
 }
+
+#[test]
+fn test_binary_agreement_insecure_coin() {
+    let _ = env_logger::try_init();
+    for &(num_good_nodes, num_faulty_nodes) in &[(1, 0), (3, 1), (7, 2)] {
+        let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+        let new_ba = |netinfo: Arc<NetworkInfo<NodeId>>| -> BinaryAgreement<_, InsecureCoin<_>> {
+            BinaryAgreement::new_with_coin(netinfo, "test", NodeId(0))
+                .expect("Binary Agreement instance")
+        };
+        let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_ba);
+        test_binary_agreement(network, None);
+    }
+}
+
+#[test]
+fn test_binary_agreement_future_epoch_spam() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    ba.set_queue_limits(10, 2);
+
+    let faulty_kinds = |ba: &mut BinaryAgreement<NodeId>, content: MessageContent, epoch: u64| {
+        let msg = content.with_epoch(AgreementEpoch::new(epoch));
+        let step = ba.handle_message(&NodeId(1), msg).expect("handle message");
+        assert!(step.fault_log.0.iter().all(|fault| fault.node_id == NodeId(1)));
+        step.fault_log.0.into_iter().map(|fault| fault.kind).collect::<Vec<_>>()
+    };
+    let conf = || MessageContent::Conf(bool_set::BoolSet::from(true));
+    let term = || MessageContent::Term(true);
+
+    // Messages too far in the future are rejected.
+    assert_eq!(
+        vec![FaultKind::AgreementEpochTooFarAhead],
+        faulty_kinds(&mut ba, conf(), 11)
+    );
+    // A `Term` stays valid in later epochs, so one is queued even beyond the limit, but not two.
+    assert!(faulty_kinds(&mut ba, term(), 11).is_empty());
+    assert_eq!(
+        vec![FaultKind::TooManyAgreementMessages],
+        faulty_kinds(&mut ba, term(), 12)
+    );
+    // Up to two messages per sender and epoch are queued, further ones are rejected.
+    assert!(faulty_kinds(&mut ba, term(), 10).is_empty());
+    assert!(faulty_kinds(&mut ba, conf(), 10).is_empty());
+    assert_eq!(
+        vec![FaultKind::TooManyAgreementMessages],
+        faulty_kinds(&mut ba, conf(), 10)
+    );
+    assert!(faulty_kinds(&mut ba, conf(), 9).is_empty());
+}
+
+#[test]
+fn test_binary_agreement_unexpected_coin_message() {
+    let (netinfo, netinfos) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+
+    // In epoch 0 the coin value is fixed, so nobody should send coin shares.
+    let share = netinfos[&NodeId(1)].secret_key_share().sign("coin");
+    let msg = MessageContent::Coin(Box::new(CoinMessage::new(share)))
+        .with_epoch(AgreementEpoch::new(0));
+    let step = ba.handle_message(&NodeId(1), msg).expect("handle message");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(vec![(NodeId(1), FaultKind::UnexpectedCoinMessage)], faults);
+}
+
+#[test]
+fn test_binary_agreement_epoch_limit() {
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    ba.set_max_epochs(1);
+
+    // The coin value in epoch 0 is `true`, so the input `false` can't be decided in that epoch.
+    match DistAlgorithm::handle_input(&mut ba, false) {
+        Err(err @ Error::EpochLimitReached(1)) => assert!(err.is_fatal()),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_binary_agreement_slow_epochs() {
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let hook_reports = reports.clone();
+    ba.set_slow_epochs(1);
+    ba.set_slow_epochs_hook(Arc::new(move |report: &SlowAgreementReport<NodeId>| {
+        hook_reports.lock().unwrap().push(report.clone());
+    }));
+
+    // The input `false` can't be decided in epoch 0, whose coin value is `true`, but in epoch 1.
+    let step = DistAlgorithm::handle_input(&mut ba, false).expect("input");
+    assert!(step.output.iter().eq(once(&false)));
+    let reports = reports.lock().unwrap();
+    assert_eq!(1, reports.len());
+    let report = &reports[0];
+    assert_eq!(NodeId(0), report.proposer_id);
+    assert_eq!(AgreementEpoch::new(0), report.epoch);
+    assert_eq!(Some(false), report.estimated);
+    assert_eq!(bool_set::FALSE, report.bin_values);
+    assert_eq!([1, 0], report.received_counts.bval);
+    assert_eq!([1, 0], report.received_counts.aux);
+    assert_eq!(CoinSchedule::Fixed(true), report.coin_schedule);
+}
+
+#[test]
+fn test_binary_agreement_restore_requires_hook() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo.clone(), "test", NodeId(0)).expect("Binary Agreement");
+    ba.set_slow_epochs_hook(Arc::new(|_: &SlowAgreementReport<NodeId>| ()));
+    DistAlgorithm::handle_input(&mut ba, true).expect("input");
+    let snapshot = ba.snapshot();
+
+    // Without the hook, restoring would silently change the instance's behavior.
+    let mut env = Environment::new(netinfo);
+    match BinaryAgreement::<NodeId>::restore(snapshot.clone(), &env) {
+        Err(RestoreError::MissingSlowEpochsHook) => (),
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+
+    env.slow_epochs_hook = Some(Arc::new(|_: &SlowAgreementReport<NodeId>| ()));
+    let restored = BinaryAgreement::<NodeId>::restore(snapshot, &env).expect("restore");
+    assert_eq!(ba.epoch(), restored.epoch());
+    assert_eq!(ba.received_counts(), restored.received_counts());
+}
+
+#[test]
+fn test_binary_agreement_abort_on_error() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    ba.set_max_epochs(1);
+    let sbv = |msg| MessageContent::SbvBroadcast(msg).with_epoch(AgreementEpoch::new(0));
+
+    let _ = DistAlgorithm::handle_input(&mut ba, false).expect("input");
+    for &id in &[1, 2] {
+        let _ = ba
+            .handle_message(&NodeId(id), sbv(SbvMessage::BVal(false)))
+            .expect("handle `BVal`");
+    }
+    let _ = ba
+        .handle_message(&NodeId(1), sbv(SbvMessage::Aux(false)))
+        .expect("handle `Aux`");
+    let counts = ba.received_counts();
+    assert_eq!([3, 0], counts.bval);
+    assert_eq!([2, 0], counts.aux);
+
+    // The third `Aux(false)` completes the epoch, but the coin value is `true`, so the instance
+    // would have to move on to epoch 1, which exceeds the limit. The instance aborts.
+    match ba.handle_message(&NodeId(2), sbv(SbvMessage::Aux(false))) {
+        Err(Error::EpochLimitReached(1)) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert!(ba.aborted());
+    assert!(ba.terminated());
+
+    // Later messages are ignored.
+    let step = ba
+        .handle_message(&NodeId(3), sbv(SbvMessage::Aux(false)))
+        .expect("handle `Aux` after abort");
+    assert!(step.output.is_empty());
+    assert!(step.messages.is_empty());
+}
+
+#[test]
+fn test_binary_agreement_abort() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    let step = DistAlgorithm::handle_input(&mut ba, true).expect("input");
+    assert!(!step.messages.is_empty());
+
+    ba.abort();
+    assert!(ba.terminated());
+    assert!(ba.aborted());
+    assert!(!ba.accepts_input());
+
+    // Messages, even from the future, are ignored without error.
+    for epoch in 0..3 {
+        let msg = MessageContent::Term(false).with_epoch(AgreementEpoch::new(epoch));
+        let step = ba.handle_message(&NodeId(1), msg).expect("handle message");
+        assert!(step.output.is_empty());
+        assert!(step.messages.is_empty());
+        assert!(step.fault_log.is_empty());
+    }
+}
+
+#[test]
+fn test_binary_agreement_validation() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    ba.set_queue_limits(10, 2);
+
+    let validity = |ba: &mut BinaryAgreement<NodeId>, epoch: u64| {
+        let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(epoch));
+        let step = ba.handle_message(&NodeId(1), msg).expect("handle message");
+        step.validation
+            .0
+            .into_iter()
+            .map(|validation| (validation.sender_id, validation.validity))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(vec![(NodeId(1), Validity::Valid)], validity(&mut ba, 0));
+    assert_eq!(vec![(NodeId(1), Validity::Future)], validity(&mut ba, 5));
+    let too_far_ahead = Validity::Faulty(FaultKind::AgreementEpochTooFarAhead);
+    assert_eq!(vec![(NodeId(1), too_far_ahead)], validity(&mut ba, 11));
+
+    // Once the instance has terminated, all messages are obsolete.
+    ba.abort();
+    assert_eq!(vec![(NodeId(1), Validity::Obsolete)], validity(&mut ba, 0));
+}
+
+#[test]
+fn test_binary_agreement_justified_termination() {
+    let _ = env_logger::try_init();
+    let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+    let new_ba = |netinfo: Arc<NetworkInfo<NodeId>>| {
+        let mut ba =
+            BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+        ba.set_justified_termination(true);
+        ba
+    };
+    let mut network = TestNetwork::new(3, 1, adversary, new_ba);
+    network.input_all(true);
+    // Handle all messages, including the `Term`s sent after termination.
+    while network.nodes.values().any(|node| !node.queue.is_empty()) {
+        network.step();
+    }
+    let proofs: Vec<_> = network
+        .nodes
+        .values()
+        .map(|node| node.instance().decision_proof().expect("decision proof"))
+        .collect();
+    assert!(proofs.windows(2).all(|pair| pair[0] == pair[1]));
+
+    // Unsigned `Term` messages are rejected.
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    ba.set_justified_termination(true);
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(0));
+    let step = ba.handle_message(&NodeId(1), msg).expect("handle message");
+    let faults: Vec<_> = step.fault_log.0.into_iter().map(|f| (f.node_id, f.kind)).collect();
+    assert_eq!(vec![(NodeId(1), FaultKind::UnsignedTerm)], faults);
+}
+
+/// The invocation ID, session ID, proposer index and epoch of a coin nonce.
+type NonceParams = (Vec<u8>, Vec<u8>, usize, u64);
+
+/// Generates nonce parameters from small ranges, so that equal and similar ones are frequent.
+fn nonce_params() -> impl Strategy<Value = NonceParams> {
+    (vec(0u8..2, 0..3), vec(0u8..2, 0..3), 0usize..2, 0u64..2)
+}
+
+fn nonce(&(ref invocation_id, ref session_id, proposer_id, epoch): &NonceParams) -> Vec<u8> {
+    Nonce::new(invocation_id, session_id, proposer_id, AgreementEpoch::new(epoch))
+        .as_ref()
+        .to_vec()
+}
+
+proptest!{
+    #[test]
+    fn test_nonce_injective(a in nonce_params(), b in nonce_params()) {
+        prop_assert_eq!(a == b, nonce(&a) == nonce(&b));
+    }
+}
+
+#[test]
+fn test_nonce_field_boundaries() {
+    // Moving bytes from the invocation ID to the session ID changes the nonce.
+    let a = (vec![1, 2], vec![3], 0, 0);
+    let b = (vec![1], vec![2, 3], 0, 0);
+    assert_ne!(nonce(&a), nonce(&b));
+}
+
+#[test]
+fn test_binary_agreement_state_inspection() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+    assert_eq!(AgreementEpoch::new(0), ba.epoch());
+    assert_eq!(CoinSchedule::Fixed(true), ba.coin_schedule());
+
+    let _ = ba.handle_input(true).expect("input");
+    let bval = |b| {
+        MessageContent::SbvBroadcast(SbvMessage::BVal(b)).with_epoch(AgreementEpoch::new(0))
+    };
+    let _ = ba.handle_message(&NodeId(1), bval(true)).expect("BVal");
+    let _ = ba.handle_message(&NodeId(2), bval(false)).expect("BVal");
+    assert_eq!(bool_set::NONE, ba.bin_values());
+    let counts = ReceivedCounts {
+        bval: [1, 2],
+        ..ReceivedCounts::default()
+    };
+    assert_eq!(counts, ba.received_counts());
+
+    // With _2 f + 1_ `BVal(true)`, we send `Aux(true)`.
+    let _ = ba.handle_message(&NodeId(3), bval(true)).expect("BVal");
+    assert_eq!(bool_set::TRUE, ba.bin_values());
+    let counts = ReceivedCounts {
+        bval: [1, 3],
+        aux: [0, 1],
+        ..ReceivedCounts::default()
+    };
+    assert_eq!(counts, ba.received_counts());
+    assert_eq!(None, ba.decision());
+}
+
+#[test]
+fn test_step_defer_and_collect_messages() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut ba: BinaryAgreement<NodeId> =
+        BinaryAgreement::new(netinfo, "test", NodeId(0)).expect("Binary Agreement instance");
+
+    // Our `BVal(true)` is sent to all nodes.
+    let mut step = ba.handle_input(true).expect("input");
+    let deferred = step.defer_messages(|msg| msg.target == Target::All);
+    assert!(step.messages.is_empty());
+    assert_eq!(1, deferred.len());
+
+    let step: Step<BinaryAgreement<NodeId>> = deferred.clone().into_iter().collect();
+    assert_eq!(deferred, step.messages);
+    assert!(step.output.is_empty() && step.fault_log.is_empty());
+}
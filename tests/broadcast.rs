@@ -20,10 +20,11 @@ use std::sync::Arc;
 
 use rand::Rng;
 
-use hbbft::broadcast::{Broadcast, Message};
+use hbbft::broadcast::{Broadcast, Error, FaultKind, Message, ReplicationCodec};
+use hbbft::validation::Validity;
 use hbbft::{DistAlgorithm, NetworkInfo, Target, TargetedMessage};
 use network::{
-    Adversary, MessageScheduler, MessageWithSender, NodeId, RandomAdversary, SilentAdversary,
+    test_netinfos, Adversary, MessageScheduler, MessageWithSender, NodeId, SilentAdversary,
     TestNetwork, TestNode,
 };
 
@@ -188,14 +189,365 @@ fn test_broadcast_first_delivery_adv_propose() {
     test_broadcast_different_sizes(new_adversary, b"Foo");
 }
 
+#[cfg(feature = "testing")]
 #[test]
 fn test_broadcast_random_adversary() {
     let new_adversary = |_, _| {
         // Note: Set this to 0.8 to watch 30 gigs of RAM disappear.
-        RandomAdversary::new(0.2, 0.2, || TargetedMessage {
+        network::RandomAdversary::new(0.2, 0.2, || TargetedMessage {
             target: Target::All,
             message: rand::random(),
         })
     };
     test_broadcast_different_sizes(new_adversary, b"RandomFoo");
 }
+
+#[test]
+fn test_broadcast_duplicate_ready() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut broadcast = Broadcast::new(netinfo, NodeId(0)).expect("broadcast instance");
+
+    let step = broadcast
+        .handle_message(&NodeId(1), Message::Ready([1; 32]))
+        .expect("handle first Ready");
+    assert!(step.fault_log.is_empty());
+    let step = broadcast
+        .handle_message(&NodeId(1), Message::Ready([1; 32]))
+        .expect("handle second Ready");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(vec![(NodeId(1), FaultKind::MultipleReadys)], faults);
+}
+
+#[test]
+fn test_broadcast_validation() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut broadcast = Broadcast::new(netinfo, NodeId(0)).expect("broadcast instance");
+
+    let mut validity = |sender_id: NodeId, msg: Message| {
+        let step = broadcast
+            .handle_message(&sender_id, msg)
+            .expect("handle message");
+        step.validation
+            .0
+            .into_iter()
+            .map(|validation| (validation.sender_id, validation.validity))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        vec![(NodeId(1), Validity::Valid)],
+        validity(NodeId(1), Message::Ready([1; 32]))
+    );
+    assert_eq!(
+        vec![(NodeId(1), Validity::Faulty(FaultKind::MultipleReadys))],
+        validity(NodeId(1), Message::Ready([1; 32]))
+    );
+    assert_eq!(
+        vec![(
+            NodeId(2),
+            Validity::Faulty(FaultKind::ReceivedValueFromNonProposer)
+        )],
+        validity(NodeId(2), Message::FullValue(b"Foo".to_vec()))
+    );
+    assert_eq!(
+        vec![(NodeId(7), Validity::Faulty(FaultKind::UnknownSender))],
+        validity(NodeId(7), Message::Ready([1; 32]))
+    );
+}
+
+#[test]
+fn test_broadcast_codecs() {
+    let _ = env_logger::try_init();
+    for &(num_good_nodes, num_faulty_nodes) in &[(1, 0), (3, 1), (5, 2)] {
+        // A single data shard: every shard suffices to reconstruct the value.
+        let new_bc = |netinfo: Arc<NetworkInfo<NodeId>>| {
+            let mut bc = new_broadcast(netinfo);
+            bc.set_data_shard_num(1).expect("set number of data shards");
+            bc
+        };
+        let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+        let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_bc);
+        test_broadcast(network, b"Foo");
+
+        // Every node receives a copy of the whole value.
+        let new_bc = |netinfo: Arc<NetworkInfo<NodeId>>| {
+            let num_nodes = netinfo.num_nodes();
+            let mut bc = new_broadcast(netinfo);
+            bc.set_codec(Box::new(ReplicationCodec::new(num_nodes)))
+                .expect("set codec");
+            bc
+        };
+        let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+        let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_bc);
+        test_broadcast(network, b"Foo");
+    }
+
+    // More than N - 2 f data shards are not enough to reconstruct the value.
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut broadcast = new_broadcast(netinfo);
+    assert_eq!(
+        Err(Error::InvalidCodecParameters),
+        broadcast.set_data_shard_num(3)
+    );
+    assert_eq!(
+        Err(Error::InvalidCodecParameters),
+        broadcast.set_codec(Box::new(ReplicationCodec::new(3)))
+    );
+}
+
+#[test]
+fn test_broadcast_small_value() {
+    let new_adversary = |_: usize, _: usize| SilentAdversary::new(MessageScheduler::Random);
+    let mut rng = rand::thread_rng();
+    for &size in &[1, 4, 7] {
+        let num_faulty_nodes = (size - 1) / 3;
+        let num_good_nodes = size - num_faulty_nodes;
+        let new_bc = |netinfo: Arc<NetworkInfo<NodeId>>| {
+            let mut bc = new_broadcast(netinfo);
+            bc.set_small_value_threshold(16);
+            bc
+        };
+        let adversary = |_| new_adversary(num_good_nodes, num_faulty_nodes);
+        let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_bc);
+        test_broadcast(network, b"Foo");
+
+        // Values above the threshold are still erasure-coded.
+        let value: Vec<u8> = (0..rng.gen_range(16, 64)).map(|_| rng.gen()).collect();
+        let adversary = |_| new_adversary(num_good_nodes, num_faulty_nodes);
+        let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_bc);
+        test_broadcast(network, &value);
+    }
+
+    // Full values that are too large are rejected.
+    let (netinfo, _) = test_netinfos(4, NodeId(1));
+    let mut broadcast = new_broadcast(netinfo);
+    broadcast.set_small_value_threshold(16);
+    let step = broadcast
+        .handle_message(&NodeId(0), Message::FullValue(vec![0; 16]))
+        .expect("handle FullValue");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(vec![(NodeId(0), FaultKind::FullValueTooLarge)], faults);
+}
+
+#[test]
+fn test_broadcast_max_value_size() {
+    let (netinfo, mut netinfos) = test_netinfos(4, NodeId(1));
+    let proposer_netinfo = Arc::new(netinfos.remove(&NodeId(0)).expect("proposer `NetworkInfo`"));
+
+    // The proposer ignores the limit and sends shards of a value that is too large.
+    let mut proposer = new_broadcast(proposer_netinfo);
+    let step = proposer.handle_input(vec![0; 100]).expect("propose");
+    let value_msg = step
+        .messages
+        .into_iter()
+        .find(|msg| msg.target == Target::Node(NodeId(1)))
+        .expect("`Value` message to node 1")
+        .message;
+
+    let mut broadcast = new_broadcast(netinfo);
+    broadcast.set_max_value_size(50);
+    let step = broadcast
+        .handle_message(&NodeId(0), value_msg)
+        .expect("handle Value");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(vec![(NodeId(0), FaultKind::ShardTooLarge)], faults);
+    assert!(step.messages.is_empty());
+
+    proposer.set_max_value_size(50);
+    assert_eq!(
+        Some(Error::InputTooLarge),
+        proposer.handle_input(vec![0; 100]).err()
+    );
+}
+
+#[test]
+fn test_broadcast_abandon() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut broadcast = Broadcast::new(netinfo, NodeId(3)).expect("broadcast instance");
+
+    // The proposer is silent, so only a few `Ready`s arrive, and the instance can't terminate.
+    for i in 1..3 {
+        let step = broadcast
+            .handle_message(&NodeId(i), Message::Ready([1; 32]))
+            .expect("handle Ready");
+        assert!(step.output.is_empty());
+    }
+    assert!(!broadcast.terminated());
+
+    broadcast.abandon();
+    assert!(broadcast.is_abandoned());
+    assert!(broadcast.terminated());
+
+    // Later messages are obsolete, even ones that would otherwise be faulty.
+    let step = broadcast
+        .handle_message(&NodeId(1), Message::Ready([1; 32]))
+        .expect("handle Ready");
+    assert!(step.output.is_empty() && step.messages.is_empty());
+    let validity: Vec<_> = step
+        .validation
+        .0
+        .into_iter()
+        .map(|validation| (validation.sender_id, validation.validity))
+        .collect();
+    assert_eq!(vec![(NodeId(1), Validity::Obsolete)], validity);
+}
+
+#[test]
+fn test_broadcast_echo_targets() {
+    let (netinfo, mut netinfos) = test_netinfos(7, NodeId(1));
+    let proposer_netinfo = Arc::new(netinfos.remove(&NodeId(0)).expect("proposer `NetworkInfo`"));
+
+    let mut proposer = new_broadcast(proposer_netinfo);
+    let step = proposer.handle_input(b"Foo".to_vec()).expect("propose");
+    let value_msg = step
+        .messages
+        .into_iter()
+        .find(|msg| msg.target == Target::Node(NodeId(1)))
+        .expect("`Value` message to node 1")
+        .message;
+    let proof = match value_msg {
+        Message::Value(ref proof) => proof.clone(),
+        _ => panic!("expected a `Value` message"),
+    };
+    let hash = *proof.root_hash();
+
+    // With `f = 2`, three shards suffice to decode, so node 1 only sends its shard to nodes 2
+    // and 3, and the hash to the others.
+    let mut broadcast = new_broadcast(netinfo);
+    let step = broadcast
+        .handle_message(&NodeId(0), value_msg)
+        .expect("handle Value");
+    let hash_ids: BTreeSet<NodeId> = [0, 4, 5, 6].iter().cloned().map(NodeId).collect();
+    let expected = vec![
+        Target::AllExcept(hash_ids.clone()).message(Message::Echo(proof.clone())),
+        Target::Nodes(hash_ids).message(Message::EchoHash(hash)),
+    ];
+    assert_eq!(expected, step.messages.into_iter().collect::<Vec<_>>());
+
+    // Upon `N - f` echos, node 1 sends `Ready`, and its shard to the nodes that have only received
+    // the hash, unless they can decode already.
+    for &id in &[0, 4, 5] {
+        let step = broadcast
+            .handle_message(&NodeId(id), Message::EchoHash(hash))
+            .expect("handle EchoHash");
+        assert!(step.messages.is_empty() && step.fault_log.is_empty());
+    }
+    let step = broadcast
+        .handle_message(&NodeId(4), Message::CanDecode(hash))
+        .expect("handle CanDecode");
+    assert!(step.messages.is_empty() && step.fault_log.is_empty());
+    let step = broadcast
+        .handle_message(&NodeId(6), Message::EchoHash(hash))
+        .expect("handle EchoHash");
+    let fallback_ids: BTreeSet<NodeId> = [0, 5, 6].iter().cloned().map(NodeId).collect();
+    let expected = vec![
+        Target::All.message(Message::Ready(hash)),
+        Target::Nodes(fallback_ids).message(Message::Echo(proof)),
+    ];
+    assert_eq!(expected, step.messages.into_iter().collect::<Vec<_>>());
+
+    // Every node sends only one `EchoHash`.
+    let step = broadcast
+        .handle_message(&NodeId(0), Message::EchoHash(hash))
+        .expect("handle EchoHash");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(vec![(NodeId(0), FaultKind::MultipleEchos)], faults);
+}
+
+#[test]
+fn test_broadcast_echo_request() {
+    let (proposer_netinfo, mut netinfos) = test_netinfos(4, NodeId(0));
+    let mut new_node = |id| new_broadcast(Arc::new(netinfos.remove(&id).expect("`NetworkInfo`")));
+    let mut proposer = new_broadcast(proposer_netinfo);
+    let mut node1 = new_node(NodeId(1));
+    let mut node2 = new_node(NodeId(2));
+
+    let step = proposer.handle_input(b"Foo".to_vec()).expect("propose");
+    let proofs: BTreeMap<NodeId, _> = step
+        .messages
+        .into_iter()
+        .filter_map(|msg| match (msg.target, msg.message) {
+            (Target::Node(id), Message::Value(proof)) => Some((id, proof)),
+            _ => None,
+        }).collect();
+    let hash = *proofs[&NodeId(2)].root_hash();
+
+    // Node 1 has lost all shards, but receives the others' `Ready`s. It asks them for their echos.
+    let step = node1
+        .handle_message(&NodeId(0), Message::Ready(hash))
+        .expect("handle Ready");
+    assert!(step.messages.is_empty());
+    let step = node1
+        .handle_message(&NodeId(2), Message::Ready(hash))
+        .expect("handle Ready");
+    let request_ids: BTreeSet<NodeId> = [0, 2].iter().cloned().map(NodeId).collect();
+    let expected = vec![
+        Target::All.message(Message::Ready(hash)),
+        Target::Nodes(request_ids).message(Message::EchoRequest(hash)),
+    ];
+    assert_eq!(expected, step.messages.into_iter().collect::<Vec<_>>());
+
+    // Node 2 resends its `Echo` on request, but only answers one request per node.
+    let value_msg = Message::Value(proofs[&NodeId(2)].clone());
+    let _ = node2
+        .handle_message(&NodeId(0), value_msg)
+        .expect("handle Value");
+    let step = node2
+        .handle_message(&NodeId(1), Message::EchoRequest(hash))
+        .expect("handle EchoRequest");
+    let echo2 = Message::Echo(proofs[&NodeId(2)].clone());
+    let expected = vec![Target::Node(NodeId(1)).message(echo2.clone())];
+    assert_eq!(expected, step.messages.into_iter().collect::<Vec<_>>());
+    let step = node2
+        .handle_message(&NodeId(1), Message::EchoRequest(hash))
+        .expect("handle EchoRequest");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(vec![(NodeId(1), FaultKind::MultipleEchoRequests)], faults);
+
+    // A resent `Echo` that node 1 already has is ignored.
+    let step = node1
+        .handle_message(&NodeId(2), echo2.clone())
+        .expect("handle Echo");
+    assert!(step.output.is_empty() && step.fault_log.is_empty());
+    let step = node1
+        .handle_message(&NodeId(2), echo2)
+        .expect("handle Echo");
+    assert!(step.fault_log.is_empty());
+
+    // Node 3 is asked once its `Ready` arrives, and its `Echo` completes the second shard.
+    let step = node1
+        .handle_message(&NodeId(3), Message::Ready(hash))
+        .expect("handle Ready");
+    let request_ids: BTreeSet<NodeId> = once(NodeId(3)).collect();
+    let expected = vec![Target::Nodes(request_ids).message(Message::EchoRequest(hash))];
+    assert_eq!(expected, step.messages.into_iter().collect::<Vec<_>>());
+    let step = node1
+        .handle_message(&NodeId(3), Message::Echo(proofs[&NodeId(3)].clone()))
+        .expect("handle Echo");
+    assert!(once(&b"Foo".to_vec()).eq(step.output.iter()));
+}
@@ -14,13 +14,18 @@ extern crate threshold_crypto as crypto;
 
 mod network;
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::iter::once;
+use std::sync::Arc;
 
 use rand::Rng;
 
-use hbbft::coin::Coin;
+use hbbft::coin::{Coin, CoinCache, CoinMessage, FaultKind};
+use hbbft::{DistAlgorithm, NetworkInfo, NetworkInfoError, Step, Target, TargetedMessage};
 
-use network::{Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode};
+use network::{
+    Adversary, MessageScheduler, MessageWithSender, NodeId, SilentAdversary, TestNetwork, TestNode,
+};
 
 /// Tests a network of Coin instances with an optional expected value. Outputs the computed
 /// coin value if the test is successful.
@@ -47,6 +52,14 @@ where
     }
     // Now `expected` is the unique output of all good nodes.
     assert!(expected.iter().eq(network.observer.outputs()));
+    // They also agree on the random value derived from the signature.
+    let random_values: BTreeSet<[u8; 32]> = network
+        .nodes
+        .values()
+        .chain(once(&network.observer))
+        .map(|node| node.instance().random_value().expect("random value"))
+        .collect();
+    assert_eq!(1, random_values.len());
     expected.unwrap()
 }
 
@@ -129,3 +142,263 @@ fn test_coin_first_silent_50_samples() {
     let new_adversary = |_: usize, _: usize| SilentAdversary::new(MessageScheduler::First);
     test_coin_different_sizes(new_adversary, 50);
 }
+
+/// An adversary whose nodes each send a valid share, a share for a different nonce, and the valid
+/// share again to everyone.
+struct ConflictingSharesAdversary {
+    scheduler: MessageScheduler,
+    nonce: String,
+    adv_netinfos: BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>>,
+    sent: bool,
+}
+
+impl ConflictingSharesAdversary {
+    fn new(nonce: String) -> Self {
+        ConflictingSharesAdversary {
+            scheduler: MessageScheduler::Random,
+            nonce,
+            adv_netinfos: BTreeMap::new(),
+            sent: false,
+        }
+    }
+}
+
+impl Adversary<Coin<NodeId, String>> for ConflictingSharesAdversary {
+    fn init(
+        &mut self,
+        _: &BTreeMap<NodeId, TestNode<Coin<NodeId, String>>>,
+        adv_nodes: &BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>>,
+    ) {
+        self.adv_netinfos = adv_nodes.clone();
+    }
+
+    fn pick_node(&self, nodes: &BTreeMap<NodeId, TestNode<Coin<NodeId, String>>>) -> NodeId {
+        self.scheduler.pick_node(nodes)
+    }
+
+    fn push_message(&mut self, _: NodeId, _: TargetedMessage<CoinMessage, NodeId>) {
+        // All messages are ignored.
+    }
+
+    fn step(&mut self) -> Vec<MessageWithSender<Coin<NodeId, String>>> {
+        if self.sent {
+            return vec![];
+        }
+        self.sent = true;
+        let mut msgs = Vec::new();
+        for (id, netinfo) in &self.adv_netinfos {
+            let sk_share = netinfo.secret_key_share();
+            let wrong_nonce = format!("not {}", self.nonce);
+            for msg in &[&self.nonce, &wrong_nonce, &self.nonce] {
+                let coin_msg = CoinMessage::new(sk_share.sign(msg));
+                msgs.push(MessageWithSender::new(*id, Target::All.message(coin_msg)));
+            }
+        }
+        msgs
+    }
+}
+
+#[test]
+fn test_coin_conflicting_shares() {
+    let _ = env_logger::try_init();
+    for &(num_good_nodes, num_faulty_nodes) in &[(3, 1), (5, 2), (7, 3)] {
+        for i in 0..10 {
+            let nonce = format!("conflicting shares nonce {}", i);
+            let adversary = |_| ConflictingSharesAdversary::new(nonce.clone());
+            let new_coin = |netinfo: _| Coin::new(netinfo, nonce.clone());
+            let network = TestNetwork::new(num_good_nodes, num_faulty_nodes, adversary, new_coin);
+            test_coin(network);
+        }
+    }
+}
+
+#[test]
+fn test_coin_multiple_shares() {
+    let nonce = "multiple shares nonce".to_string();
+    let ids = (0..4).map(NodeId);
+    let netinfos = NetworkInfo::generate_map_with_threshold(ids, 2, &mut rand::thread_rng())
+        .expect("Failed to create `NetworkInfo` map");
+    let share = |id: usize, msg: &str| {
+        CoinMessage::new(netinfos[&NodeId(id)].secret_key_share().sign(msg))
+    };
+    let faults = |step: Step<Coin<NodeId, String>>| -> Vec<_> {
+        step.fault_log
+            .0
+            .into_iter()
+            .map(|fault| (fault.node_id, fault.kind))
+            .collect()
+    };
+    let mut coin = Coin::new(Arc::new(netinfos[&NodeId(0)].clone()), nonce.clone());
+    let _ = coin.handle_input(()).expect("input");
+
+    // Shares from unknown senders are ignored.
+    let step = coin
+        .handle_message(&NodeId(9), share(1, &nonce))
+        .expect("handle unknown sender's share");
+    assert_eq!(vec![(NodeId(9), FaultKind::UnknownSender)], faults(step));
+
+    // Node 1 sends an invalid share, then a valid one: The second one is rejected.
+    let step = coin
+        .handle_message(&NodeId(1), share(1, "wrong nonce"))
+        .expect("handle invalid share");
+    assert_eq!(
+        vec![(NodeId(1), FaultKind::UnverifiedSignatureShareSender)],
+        faults(step)
+    );
+    let step = coin
+        .handle_message(&NodeId(1), share(1, &nonce))
+        .expect("handle second share");
+    assert_eq!(
+        vec![(NodeId(1), FaultKind::MultipleSignatureShares)],
+        faults(step)
+    );
+
+    // Node 2 sends its valid share twice: The duplicate is reported and doesn't count.
+    let step = coin
+        .handle_message(&NodeId(2), share(2, &nonce))
+        .expect("handle valid share");
+    assert!(step.output.is_empty());
+    let step = coin
+        .handle_message(&NodeId(2), share(2, &nonce))
+        .expect("handle duplicate share");
+    assert!(step.output.is_empty());
+    assert_eq!(
+        vec![(NodeId(2), FaultKind::MultipleSignatureShares)],
+        faults(step)
+    );
+    assert!(!coin.terminated());
+
+    let step = coin
+        .handle_message(&NodeId(3), share(3, &nonce))
+        .expect("handle last share");
+    assert_eq!(1, step.output.len());
+    assert!(coin.terminated());
+}
+
+#[test]
+fn test_coin_deferred_verification() {
+    let nonce = "deferred verification nonce".to_string();
+    let ids = (0..4).map(NodeId);
+    let netinfos = NetworkInfo::generate_map(ids, &mut rand::thread_rng())
+        .expect("Failed to create `NetworkInfo` map");
+    let share = |id: usize, msg: &str| {
+        CoinMessage::new(netinfos[&NodeId(id)].secret_key_share().sign(msg))
+    };
+    let mut coin = Coin::new(Arc::new(netinfos[&NodeId(0)].clone()), nonce.clone());
+    coin.set_deferred_verification(true);
+
+    let step = coin.handle_input(()).expect("input");
+    assert!(step.output.is_empty());
+
+    // Together with our own share, the invalid one is enough to try combining them.
+    let step = coin
+        .handle_message(&NodeId(2), share(2, "wrong nonce"))
+        .expect("handle invalid share");
+    assert!(step.output.is_empty());
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(
+        vec![(NodeId(2), FaultKind::UnverifiedSignatureShareSender)],
+        faults
+    );
+    assert!(!coin.terminated());
+
+    let step = coin
+        .handle_message(&NodeId(1), share(1, &nonce))
+        .expect("handle valid share");
+    assert_eq!(1, step.output.len());
+    assert!(step.fault_log.is_empty());
+    assert!(coin.terminated());
+}
+
+#[test]
+fn test_coin_higher_threshold() {
+    let nonce = "higher threshold nonce".to_string();
+    let ids = (0..4).map(NodeId);
+    let netinfos = NetworkInfo::generate_map_with_threshold(ids, 2, &mut rand::thread_rng())
+        .expect("Failed to create `NetworkInfo` map");
+    let share = |id: usize| CoinMessage::new(netinfos[&NodeId(id)].secret_key_share().sign(&nonce));
+    let mut coin = Coin::new(Arc::new(netinfos[&NodeId(0)].clone()), nonce.clone());
+
+    // With threshold 2, three shares are needed instead of two.
+    let _ = coin.handle_input(()).expect("input");
+    let step = coin.handle_message(&NodeId(1), share(1)).expect("share 1");
+    assert!(step.output.is_empty());
+    let step = coin.handle_message(&NodeId(2), share(2)).expect("share 2");
+    assert_eq!(1, step.output.len());
+    assert!(coin.terminated());
+}
+
+#[test]
+fn test_coin_cache() {
+    let nonce = "cached nonce".to_string();
+    let ids = (0..4).map(NodeId);
+    let netinfos = NetworkInfo::generate_map(ids, &mut rand::thread_rng())
+        .expect("Failed to create `NetworkInfo` map");
+    let netinfo = Arc::new(netinfos[&NodeId(0)].clone());
+    let share = CoinMessage::new(netinfos[&NodeId(1)].secret_key_share().sign(&nonce));
+    let cache = Arc::new(CoinCache::new());
+
+    let mut coin = Coin::new(netinfo.clone(), nonce.clone());
+    coin.set_cache(cache.clone());
+    let step = coin.handle_input(()).expect("input");
+    assert_eq!(1, step.messages.len());
+    let step = coin.handle_message(&NodeId(1), share).expect("share");
+    assert_eq!(1, step.output.len());
+    let value = step.output[0];
+    assert_eq!(1, cache.len());
+    assert_eq!(coin.signature().cloned(), cache.get(nonce.as_bytes()));
+
+    // A new instance for the same nonce outputs the cached value without sending its share.
+    let mut restored = Coin::new(netinfo.clone(), nonce.clone());
+    restored.set_cache(cache.clone());
+    let step = restored.handle_input(()).expect("input");
+    assert!(step.messages.is_empty());
+    assert!(step.output.iter().eq(once(&value)));
+    assert!(restored.terminated());
+    assert_eq!(coin.signature(), restored.signature());
+
+    // Other nonces are not affected.
+    let mut other = Coin::new(netinfo, "other nonce".to_string());
+    other.set_cache(cache.clone());
+    let step = other.handle_input(()).expect("input");
+    assert_eq!(1, step.messages.len());
+    assert!(step.output.is_empty());
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_generate_map_invalid_parameters() {
+    let mut rng = rand::thread_rng();
+    assert_eq!(
+        Err(NetworkInfoError::NoNodes),
+        NetworkInfo::<NodeId>::generate_map(None, &mut rng).map(|_| ())
+    );
+    // With 4 nodes, one can be faulty: The threshold must be at least 1 and at most 2.
+    for &threshold in &[0, 3] {
+        assert_eq!(
+            Err(NetworkInfoError::InvalidThreshold(threshold, 4)),
+            NetworkInfo::generate_map_with_threshold((0..4).map(NodeId), threshold, &mut rng)
+                .map(|_| ())
+        );
+    }
+}
+
+#[test]
+fn test_generate_map_seeded() {
+    let ids = || (0..4).map(NodeId);
+    let netinfos0 = NetworkInfo::generate_map(ids(), &mut rand::XorShiftRng::new_unseeded())
+        .expect("Failed to create `NetworkInfo` map");
+    let netinfos1 = NetworkInfo::generate_map(ids(), &mut rand::XorShiftRng::new_unseeded())
+        .expect("Failed to create `NetworkInfo` map");
+    assert_eq!(
+        netinfos0[&NodeId(0)].public_key_set(),
+        netinfos1[&NodeId(0)].public_key_set()
+    );
+}
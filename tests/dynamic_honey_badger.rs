@@ -46,12 +46,12 @@ where
     fn has_remove(node: &TestNode<UsizeDhb>) -> bool {
         node.outputs()
             .iter()
-            .any(|batch| *batch.change() == ChangeState::Complete(Change::Remove(NodeId(0))))
+            .any(|batch| batch.change().completed() == Some(&Change::Remove(NodeId(0))))
     }
 
     fn has_add(node: &TestNode<UsizeDhb>) -> bool {
         node.outputs().iter().any(|batch| match *batch.change() {
-            ChangeState::Complete(Change::Add(ref id, _)) => *id == NodeId(0),
+            ChangeState::Complete(Change::Add(ref id, _), _) => *id == NodeId(0),
             _ => false,
         })
     }
@@ -102,6 +102,20 @@ where
         }
     }
     network.verify_batches();
+
+    // Every batch that completes a change must contain a valid `JoinPlan`.
+    for batch in network.nodes.values().flat_map(TestNode::outputs) {
+        match batch.join_plan() {
+            None => assert_eq!(None, batch.change().completed()),
+            Some(join_plan) => {
+                assert_eq!(batch.epoch().next(), join_plan.epoch());
+                assert_eq!(batch.change(), join_plan.change());
+                let netinfo = batch.network_info();
+                assert_eq!(netinfo.public_key_set(), join_plan.pub_key_set());
+                assert_eq!(netinfo.public_key_map(), join_plan.pub_keys());
+            }
+        }
+    }
 }
 
 // Allow passing `netinfo` by value. `TestNetwork` expects this function signature.
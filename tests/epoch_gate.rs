@@ -0,0 +1,124 @@
+#![deny(unused_must_use)]
+//! Tests of the epoch gate
+
+extern crate hbbft;
+
+use std::collections::BTreeSet;
+
+use hbbft::epoch_gate::{classify_epoch, EpochGate};
+use hbbft::validation::Validity;
+
+/// The fault kind the tests pass to the gate for senders whose queue is full.
+const QUEUE_FULL: &str = "queue full";
+
+/// Classifies the epoch, with the fault kind used in the tests.
+fn classify(epoch: u64, current: u64, max_future_epochs: u64) -> Validity<&'static str> {
+    classify_epoch(epoch, current, max_future_epochs)
+}
+
+#[test]
+fn test_classify_epoch() {
+    assert_eq!(Validity::Obsolete, classify(4, 5, 2));
+    assert_eq!(Validity::Valid, classify(5, 5, 2));
+    assert_eq!(Validity::Valid, classify(7, 5, 2));
+    assert_eq!(Validity::Future, classify(8, 5, 2));
+
+    // Without future epochs, only the current one is valid.
+    assert_eq!(Validity::Valid, classify(5, 5, 0));
+    assert_eq!(Validity::Future, classify(6, 5, 0));
+
+    // The window is capped at the largest epoch instead of overflowing.
+    let max = u64::max_value();
+    assert_eq!(Validity::Valid, classify(max, 1, max));
+    assert_eq!(Validity::Valid, classify(max, max - 1, 1));
+    assert_eq!(Validity::Obsolete, classify(max - 1, max, max));
+}
+
+#[test]
+fn test_epoch_gate_queue() {
+    let mut gate: EpochGate<usize, &str> = EpochGate::new(0, 1);
+    gate.set_max_queued_per_epoch(Some(2));
+    assert_eq!(1, gate.max_epoch());
+
+    // Messages after the window are queued, up to two per sender and epoch.
+    assert_eq!(Validity::Valid, gate.classify(&1, 1, QUEUE_FULL));
+    assert_eq!(Validity::Future, gate.classify(&1, 2, QUEUE_FULL));
+    gate.push(1, 2, "a");
+    gate.push(1, 2, "b");
+    assert_eq!(Validity::Faulty(QUEUE_FULL), gate.classify(&1, 2, QUEUE_FULL));
+    assert_eq!(Validity::Future, gate.classify(&1, 3, QUEUE_FULL));
+    assert_eq!(Validity::Future, gate.classify(&2, 2, QUEUE_FULL));
+    gate.push(2, 2, "c");
+    gate.push(2, 3, "d");
+    gate.push(1, 5, "e");
+    assert_eq!(2, gate.count_queued(&1, 2));
+    assert_eq!(5, gate.queued_len());
+
+    // Advancing to epoch 2 returns the messages for the new window's epochs 2 and 3.
+    let ready = gate.advance(2);
+    assert_eq!(
+        vec![
+            (2, vec![(1, "a"), (1, "b"), (2, "c")]),
+            (3, vec![(2, "d")]),
+        ],
+        ready
+    );
+    assert_eq!(2, gate.epoch());
+    assert_eq!(Validity::Obsolete, gate.classify(&1, 1, QUEUE_FULL));
+    assert_eq!(Validity::Valid, gate.classify(&1, 2, QUEUE_FULL));
+    assert_eq!(1, gate.queued_len());
+
+    // Advancing to an earlier epoch does nothing.
+    assert!(gate.advance(1).is_empty());
+    assert_eq!(2, gate.epoch());
+
+    // The messages of faulty senders are dropped.
+    gate.push(2, 6, "f");
+    let faulty: BTreeSet<&usize> = Some(&1).into_iter().collect();
+    gate.remove_senders(&faulty);
+    assert_eq!(1, gate.queued_len());
+
+    // Skipping past a queued epoch drops its messages.
+    gate.push(2, 4, "g");
+    assert_eq!(vec![(6, vec![(2, "f")])], gate.advance(5));
+    assert_eq!(0, gate.queued_len());
+}
+
+#[test]
+fn test_epoch_gate_queued_epochs() {
+    let mut gate: EpochGate<usize, &str> = EpochGate::new(0, 0);
+    gate.set_max_queued_epochs(Some(2));
+
+    // Messages are queued for at most two distinct epochs per sender.
+    gate.push(1, 1, "a");
+    gate.push(1, 3, "b");
+    gate.push(1, 3, "c");
+    assert_eq!(2, gate.count_queued_epochs(&1));
+    assert_eq!(Validity::Future, gate.classify(&1, 3, QUEUE_FULL));
+    assert_eq!(Validity::Faulty(QUEUE_FULL), gate.classify(&1, 2, QUEUE_FULL));
+    assert_eq!(Validity::Future, gate.classify(&2, 2, QUEUE_FULL));
+
+    // Once the window reaches a queued epoch, its slot is free again.
+    assert_eq!(vec![(1, vec![(1, "a")])], gate.advance(1));
+    assert_eq!(1, gate.count_queued_epochs(&1));
+    assert_eq!(2, gate.count_queued(&1, 3));
+    assert_eq!(2, gate.queued_len());
+    assert_eq!(Validity::Future, gate.classify(&1, 2, QUEUE_FULL));
+
+    // Removing a sender drops its counts, too.
+    let faulty: BTreeSet<&usize> = Some(&1).into_iter().collect();
+    gate.remove_senders(&faulty);
+    assert_eq!(0, gate.count_queued_epochs(&1));
+    assert_eq!(0, gate.count_queued(&1, 3));
+    assert_eq!(0, gate.queued_len());
+}
+
+#[test]
+fn test_epoch_gate_unlimited_window() {
+    let mut gate: EpochGate<usize, ()> = EpochGate::new(3, u64::max_value());
+    assert_eq!(u64::max_value(), gate.max_epoch());
+    assert_eq!(Validity::Valid, gate.classify(&0, u64::max_value(), QUEUE_FULL));
+    assert_eq!(Validity::Obsolete, gate.classify(&0, 2, QUEUE_FULL));
+    assert!(gate.advance(u64::max_value()).is_empty());
+    assert_eq!(u64::max_value(), gate.epoch());
+}
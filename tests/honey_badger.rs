@@ -16,18 +16,30 @@ extern crate threshold_crypto as crypto;
 
 mod network;
 
-use std::collections::BTreeMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex};
 
 use itertools::Itertools;
 use rand::Rng;
 
-use hbbft::honey_badger::{self, Batch, HoneyBadger, MessageContent};
+use hbbft::batch_verify::{self, verify_batch};
+use hbbft::binary_agreement::{self, SbvMessage};
+use hbbft::honey_badger::{
+    self, Batch, EncryptionSchedule, FaultKind, HoneyBadger, HoneyBadgerState, Message,
+    MessageContent,
+};
+use hbbft::metrics::{self, Metrics};
+use hbbft::subset;
+use hbbft::subset::Message::{BinaryAgreement, BinaryAgreements};
 use hbbft::transaction_queue::TransactionQueue;
-use hbbft::{threshold_decryption, NetworkInfo, Target, TargetedMessage};
+use hbbft::validation::Validity;
+use hbbft::{
+    threshold_decryption, AgreementEpoch, DistAlgorithm, HbEpoch, NetworkInfo,
+    SerializedContribution, Target, TargetedMessage,
+};
 
 use network::{
-    Adversary, MessageScheduler, MessageWithSender, NodeId, RandomAdversary, SilentAdversary,
+    test_netinfos, Adversary, MessageScheduler, MessageWithSender, NodeId, SilentAdversary,
     TestNetwork, TestNode,
 };
 
@@ -39,7 +51,7 @@ pub struct FaultyShareAdversary {
     num_adv: usize,
     adv_nodes: BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>>,
     scheduler: MessageScheduler,
-    share_triggers: BTreeMap<u64, bool>,
+    share_triggers: BTreeMap<HbEpoch, bool>,
 }
 
 impl FaultyShareAdversary {
@@ -123,7 +135,10 @@ impl Adversary<UsizeHoneyBadger> for FaultyShareAdversary {
 }
 
 /// Proposes `num_txs` values and expects nodes to output and order them.
-fn test_honey_badger<A>(mut network: TestNetwork<A, UsizeHoneyBadger>, num_txs: usize)
+fn test_honey_badger<A>(
+    mut network: TestNetwork<A, UsizeHoneyBadger>,
+    num_txs: usize,
+) -> TestNetwork<A, UsizeHoneyBadger>
 where
     A: Adversary<UsizeHoneyBadger>,
 {
@@ -156,6 +171,7 @@ where
         }
     }
     verify_output_sequence(&network);
+    network
 }
 
 /// Verifies that all instances output the same sequence of batches.
@@ -166,13 +182,14 @@ where
     let mut expected: Option<BTreeMap<&_, &_>> = None;
     for node in network.nodes.values() {
         assert!(!node.outputs().is_empty());
-        let outputs: BTreeMap<&u64, &BTreeMap<NodeId, Vec<usize>>> = node
+        let outputs: BTreeMap<&HbEpoch, &BTreeMap<NodeId, Vec<usize>>> = node
             .outputs()
             .iter()
             .map(
                 |Batch {
                      epoch,
                      contributions,
+                     ..
                  }| (epoch, contributions),
             ).collect();
         if expected.is_none() {
@@ -181,6 +198,13 @@ where
             assert_eq!(expected, &outputs);
         }
     }
+    // The observer must output the same batches, although it may be ahead of the validators.
+    let expected = expected.expect("at least one validator");
+    for batch in network.observer.outputs() {
+        if let Some(contributions) = expected.get(&batch.epoch) {
+            assert_eq!(*contributions, &batch.contributions);
+        }
+    }
 }
 
 fn new_honey_badger(netinfo: Arc<NetworkInfo<NodeId>>) -> UsizeHoneyBadger {
@@ -230,14 +254,464 @@ fn test_honey_badger_faulty_share() {
     test_honey_badger_different_sizes(new_adversary, 8);
 }
 
+#[test]
+fn test_honey_badger_encryption_schedule() {
+    let _ = env_logger::try_init();
+
+    for &schedule in &[EncryptionSchedule::Never, EncryptionSchedule::EveryNthEpoch(2)] {
+        info!("Encryption schedule: {:?}", schedule);
+        let new_hb = move |netinfo: Arc<NetworkInfo<NodeId>>| -> UsizeHoneyBadger {
+            HoneyBadger::builder(netinfo)
+                .encryption_schedule(schedule)
+                .build()
+        };
+        let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+        let network = TestNetwork::new(4, 1, adversary, new_hb);
+        test_honey_badger(network, 10);
+    }
+}
+
+#[cfg(feature = "testing")]
 #[test]
 fn test_honey_badger_random_adversary() {
     let new_adversary = |_, _, _| {
         // A 10% injection chance is roughly ~13k extra messages added.
-        RandomAdversary::new(0.1, 0.1, || TargetedMessage {
+        network::RandomAdversary::new(0.1, 0.1, || TargetedMessage {
             target: Target::All,
             message: rand::random(),
         })
     };
     test_honey_badger_different_sizes(new_adversary, 8);
 }
+
+/// A `Metrics` implementation that sums up all counters, and records which cryptographic
+/// operations were timed.
+#[derive(Debug, Default)]
+struct CountingMetrics {
+    counters: Mutex<BTreeMap<&'static str, u64>>,
+    crypto_operations: Mutex<BTreeSet<String>>,
+}
+
+impl CountingMetrics {
+    fn get(&self, name: &'static str) -> u64 {
+        *self.counters.lock().unwrap().get(name).unwrap_or(&0)
+    }
+
+    fn timed(&self, operation: &str) -> bool {
+        self.crypto_operations.lock().unwrap().contains(operation)
+    }
+}
+
+impl Metrics for CountingMetrics {
+    fn counter(&self, name: &'static str, _labels: &[(&'static str, &str)], value: u64) {
+        *self.counters.lock().unwrap().entry(name).or_insert(0) += value;
+    }
+
+    fn histogram(&self, name: &'static str, labels: &[(&'static str, &str)], _value: f64) {
+        if name == metrics::CRYPTO_DURATION {
+            let mut operations = self.crypto_operations.lock().unwrap();
+            operations.extend(labels.iter().map(|(_, operation)| operation.to_string()));
+        }
+    }
+}
+
+#[test]
+fn test_honey_badger_metrics() {
+    let counts = Arc::new(CountingMetrics::default());
+    let new_hb = {
+        let counts = counts.clone();
+        move |netinfo: Arc<NetworkInfo<NodeId>>| -> UsizeHoneyBadger {
+            HoneyBadger::builder(netinfo).metrics(counts.clone()).build()
+        }
+    };
+    let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+    let network = TestNetwork::new(4, 0, adversary, new_hb);
+    test_honey_badger(network, 8);
+
+    assert!(counts.get(metrics::EPOCHS_COMPLETED) >= 4);
+    assert!(counts.get(metrics::MESSAGES_SENT) > 0);
+    assert!(counts.get(metrics::MESSAGES_RECEIVED) > 0);
+    // A node decides in one Binary Agreement instance per proposer before completing an epoch.
+    let epochs = counts.get(metrics::EPOCHS_COMPLETED);
+    assert!(counts.get(metrics::AGREEMENT_DECISIONS) >= 4 * epochs);
+    assert_eq!(0, counts.get(metrics::FAULTS));
+    // The contributions are encrypted, and decrypted using threshold decryption.
+    assert!(counts.timed("encrypt"));
+    assert!(counts.timed("decrypt_share"));
+    assert!(counts.timed("decrypt_combine"));
+}
+
+#[test]
+fn test_honey_badger_contribution_size_limit() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo)
+        .contribution_size_limit(64)
+        .build();
+
+    let too_large: Vec<usize> = (0..100).collect();
+    match hb.propose(&too_large) {
+        Err(ref err) => match *err.kind() {
+            honey_badger::ErrorKind::ContributionTooLarge(_, 64) => (),
+            ref kind => panic!("unexpected error: {}", kind),
+        },
+        Ok(_) => panic!("oversized contribution was accepted"),
+    }
+    assert!(!hb.has_input());
+
+    let step = hb.propose(&vec![1, 2, 3]).expect("propose");
+    assert!(!step.messages.is_empty());
+    assert!(hb.has_input());
+}
+
+#[test]
+fn test_honey_badger_snapshot() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo.clone()).build();
+
+    hb.propose(&vec![1, 2, 3]).expect("propose");
+    // A message for an epoch too far in the future is queued.
+    let content = MessageContent::Subset(BinaryAgreements(Vec::new()));
+    let msg = content.with_epoch(HbEpoch::new(10));
+    let queue_step = DistAlgorithm::handle_message(&mut hb, &NodeId(1), msg).expect("handle");
+    assert!(queue_step.messages.is_empty());
+
+    let ser_state = hb.snapshot().to_bytes().expect("encode snapshot");
+    let state: HoneyBadgerState<Vec<usize>, NodeId> =
+        HoneyBadgerState::from_bytes(&ser_state).expect("decode snapshot");
+    assert_eq!(HbEpoch::new(0), state.epoch());
+    assert!(state.has_input());
+
+    let (restored, restored_step): (UsizeHoneyBadger, _) = HoneyBadger::builder(netinfo)
+        .build_from_snapshot(state)
+        .expect("restore from snapshot");
+    assert!(restored.has_input());
+    assert_eq!(
+        ser_state,
+        restored.snapshot().to_bytes().expect("encode restored snapshot")
+    );
+    // The restored instance continues with the state of the broadcast of our proposal, so it
+    // doesn't send it again.
+    assert!(restored_step.messages.is_empty());
+}
+
+#[test]
+fn test_honey_badger_propose_for_epoch() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo).build();
+
+    let propose_err = |hb: &mut UsizeHoneyBadger, epoch: u64| match hb
+        .propose_for_epoch(HbEpoch::new(epoch), &vec![epoch as usize])
+    {
+        Err(err) => {
+            // Rejected proposals don't affect the instance.
+            assert!(!err.is_fatal());
+            format!("{:?}", err.kind())
+        }
+        Ok(_) => panic!("proposal for epoch {} was accepted", epoch),
+    };
+
+    // We can propose for a future epoch without proposing for the current one.
+    let epoch2 = HbEpoch::new(2);
+    let step = hb.propose_for_epoch(epoch2, &vec![2]).expect("propose for epoch 2");
+    assert!(step.messages.iter().all(|msg| msg.message.epoch() == epoch2));
+    assert!(hb.has_proposed(epoch2));
+    assert!(!hb.has_input());
+    assert_eq!("AlreadyProposed(2)", propose_err(&mut hb, 2));
+    assert_eq!("EpochTooFarAhead(4)", propose_err(&mut hb, 4));
+
+    hb.propose(&vec![0]).expect("propose for epoch 0");
+    assert!(hb.has_input());
+    assert_eq!("AlreadyProposed(0)", propose_err(&mut hb, 0));
+
+    // A single node outputs its batches as soon as it proposes, in the order of the epochs.
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo).build();
+    let step = hb.propose_for_epoch(HbEpoch::new(1), &vec![1]).expect("propose for epoch 1");
+    assert!(step.output.is_empty());
+    let step = hb.propose(&vec![0]).expect("propose for epoch 0");
+    let epochs: Vec<u64> = step.output.iter().map(|batch| batch.epoch.get()).collect();
+    assert_eq!(vec![0, 1], epochs);
+    assert_eq!(HbEpoch::new(2), hb.epoch());
+    assert_eq!("EpochPassed(1)", propose_err(&mut hb, 1));
+}
+
+#[test]
+fn test_honey_badger_skip_to_epoch() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo)
+        .max_future_epochs(3)
+        .max_queued_per_epoch(1)
+        .build();
+
+    // Returns the validity of a message from node 1 for the given epoch.
+    let validity = |hb: &mut UsizeHoneyBadger, epoch: u64| {
+        let content = MessageContent::Subset(BinaryAgreements(Vec::new()));
+        let msg = content.with_epoch(HbEpoch::new(epoch));
+        let step = DistAlgorithm::handle_message(hb, &NodeId(1), msg).expect("handle");
+        step.validation.0[0].validity
+    };
+
+    hb.propose_for_epoch(HbEpoch::new(2), &vec![2]).expect("propose for epoch 2");
+    assert_eq!(Validity::Future, validity(&mut hb, 7));
+    assert_eq!(Validity::Future, validity(&mut hb, 10));
+
+    let step = hb.skip_to_epoch(HbEpoch::new(5)).expect("skip to epoch 5");
+    assert!(step.output.is_empty());
+    assert_eq!(HbEpoch::new(5), hb.epoch());
+    assert!(!hb.has_proposed(HbEpoch::new(2)));
+    assert!(!hb.has_input());
+
+    // Epoch 7 is not in the future anymore, so its queued message has been handled, but epoch 10
+    // still is, and its queue is full.
+    assert_eq!(Validity::Obsolete, validity(&mut hb, 4));
+    assert_eq!(Validity::Valid, validity(&mut hb, 7));
+    assert_eq!(
+        Validity::Faulty(FaultKind::TooManyQueuedMessages),
+        validity(&mut hb, 10)
+    );
+
+    // Skipping backwards does nothing.
+    let step = hb.skip_to_epoch(HbEpoch::new(3)).expect("skip to epoch 3");
+    assert!(step.messages.is_empty());
+    assert_eq!(HbEpoch::new(5), hb.epoch());
+}
+
+/// Delivers the queued messages, and all messages they cause, to the instances they are
+/// addressed to.
+fn deliver_all(
+    hbs: &mut BTreeMap<NodeId, UsizeHoneyBadger>,
+    mut queue: VecDeque<(NodeId, TargetedMessage<Message<NodeId>, NodeId>)>,
+) {
+    while let Some((sender_id, tmsg)) = queue.pop_front() {
+        for (id, hb) in hbs.iter_mut() {
+            let is_target = *id != sender_id && match tmsg.target {
+                Target::All => true,
+                Target::AllExcept(ref ids) => !ids.contains(id),
+                Target::Node(ref target) => target == id,
+                Target::Nodes(ref ids) => ids.contains(id),
+                Target::Observers => false,
+            };
+            if is_target {
+                let step = DistAlgorithm::handle_message(hb, &sender_id, tmsg.message.clone())
+                    .expect("handle message");
+                queue.extend(step.messages.into_iter().map(|msg| (*id, msg)));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_honey_badger_retained_epochs() {
+    let (netinfo, netinfos) = test_netinfos(4, NodeId(0));
+    let new_hb = |netinfo: Arc<NetworkInfo<NodeId>>| -> UsizeHoneyBadger {
+        HoneyBadger::builder(netinfo)
+            .max_future_epochs(0)
+            .retained_epochs(2)
+            .build()
+    };
+    let mut hbs: BTreeMap<NodeId, UsizeHoneyBadger> = netinfos
+        .into_iter()
+        .map(|(id, netinfo)| (id, new_hb(Arc::new(netinfo))))
+        .collect();
+    hbs.insert(NodeId(0), new_hb(netinfo));
+
+    // Returns the validity of a message from node 1 to node 0 for the given epoch.
+    let validity = |hbs: &mut BTreeMap<NodeId, UsizeHoneyBadger>, epoch: u64| {
+        let content = MessageContent::Subset(BinaryAgreements(Vec::new()));
+        let msg = content.with_epoch(HbEpoch::new(epoch));
+        let hb = hbs.get_mut(&NodeId(0)).expect("node 0");
+        let step = DistAlgorithm::handle_message(hb, &NodeId(1), msg).expect("handle");
+        step.validation.0[0].validity
+    };
+
+    let propose_all = |hbs: &mut BTreeMap<NodeId, UsizeHoneyBadger>, epoch: u64| {
+        let mut queue = VecDeque::new();
+        for (id, hb) in hbs.iter_mut() {
+            let step = hb.propose(&vec![id.0]).expect("propose");
+            queue.extend(step.messages.into_iter().map(|msg| (*id, msg)));
+        }
+        deliver_all(hbs, queue);
+        assert!(hbs.values().all(|hb| hb.epoch() == HbEpoch::new(epoch + 1)));
+    };
+
+    // Epoch 0 is finished, but no peer has sent anything for a later epoch yet, so its state is
+    // retained and messages for it are still handled.
+    propose_all(&mut hbs, 0);
+    assert_eq!(Validity::Valid, validity(&mut hbs, 0));
+
+    // Now every peer has sent messages for epoch 1, so none of them can still be in epoch 0.
+    propose_all(&mut hbs, 1);
+    assert_eq!(Validity::Obsolete, validity(&mut hbs, 0));
+    assert_eq!(Validity::Valid, validity(&mut hbs, 1));
+}
+
+#[test]
+fn test_honey_badger_queue_limit() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo)
+        .max_queued_per_epoch(2)
+        .build();
+
+    let mut faulty_kinds = |sender_id: NodeId, epoch: u64| {
+        let content = MessageContent::Subset(BinaryAgreements(Vec::new()));
+        let msg = content.with_epoch(HbEpoch::new(epoch));
+        let step = DistAlgorithm::handle_message(&mut hb, &sender_id, msg).expect("handle");
+        let faults = step.fault_log.0;
+        assert!(faults.iter().all(|fault| fault.node_id == sender_id));
+        faults
+            .into_iter()
+            .map(|fault| fault.kind)
+            .collect::<Vec<_>>()
+    };
+
+    // Up to two messages per sender and epoch are queued, further ones are rejected.
+    assert!(faulty_kinds(NodeId(1), 10).is_empty());
+    assert!(faulty_kinds(NodeId(1), 10).is_empty());
+    assert_eq!(
+        vec![FaultKind::TooManyQueuedMessages],
+        faulty_kinds(NodeId(1), 10)
+    );
+    assert!(faulty_kinds(NodeId(2), 10).is_empty());
+    assert!(faulty_kinds(NodeId(2), 10).is_empty());
+    assert!(faulty_kinds(NodeId(1), 11).is_empty());
+}
+
+#[test]
+fn test_honey_badger_abort_on_error() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo)
+        .max_agreement_epochs(1)
+        .build();
+    let ba_msg = |msg| {
+        let content = binary_agreement::MessageContent::SbvBroadcast(msg);
+        let ba_msg = BinaryAgreement(NodeId(3), content.with_epoch(AgreementEpoch::new(0)));
+        MessageContent::Subset(ba_msg).with_epoch(HbEpoch::new(0))
+    };
+
+    // Proposer 3's agreement instance receives `BVal(false)` from nodes 1 and 2, so we relay it
+    // and send `Aux(false)`. Node 1's `Aux(false)` is the second one.
+    for &id in &[1, 2] {
+        let _ = hb
+            .handle_message(&NodeId(id), ba_msg(SbvMessage::BVal(false)))
+            .expect("handle `BVal`");
+    }
+    let _ = hb
+        .handle_message(&NodeId(1), ba_msg(SbvMessage::Aux(false)))
+        .expect("handle `Aux`");
+
+    // The third `Aux(false)` would move the instance beyond its only epoch, whose coin value is
+    // `true`. The epoch's `Subset` instance aborts, so retrying the message is ignored.
+    let err = hb
+        .handle_message(&NodeId(2), ba_msg(SbvMessage::Aux(false)))
+        .expect_err("epoch limit");
+    match *err.kind() {
+        honey_badger::ErrorKind::HandleSubsetMessage(subset::Error::ProcessBinaryAgreement0(
+            binary_agreement::Error::EpochLimitReached(1),
+        )) => (),
+        ref kind => panic!("unexpected error: {}", kind),
+    }
+    let step = hb
+        .handle_message(&NodeId(2), ba_msg(SbvMessage::Aux(false)))
+        .expect("handle `Aux` after abort");
+    assert!(step.output.is_empty());
+    assert!(step.messages.is_empty());
+}
+
+#[test]
+fn test_honey_badger_throttling() {
+    let _ = env_logger::try_init();
+
+    let new_hb = |netinfo: Arc<NetworkInfo<NodeId>>| -> UsizeHoneyBadger {
+        HoneyBadger::builder(netinfo)
+            .max_future_epochs(0)
+            .max_lagging_peers(0)
+            .build()
+    };
+    let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+    let mut network = TestNetwork::new(3, 1, adversary, new_hb);
+    assert!(network.nodes.values().all(|node| !node.instance().is_throttled()));
+
+    for id in 0..3 {
+        network.input(NodeId(id), vec![id]);
+    }
+    while network.nodes.values().any(|node| node.outputs().is_empty()) {
+        network.step();
+    }
+
+    // The silent node never sent anything, so it is lagging behind everyone in epoch 1.
+    assert!(network.nodes.values().all(|node| node.instance().is_throttled()));
+}
+
+#[test]
+fn test_honey_badger_serialized_contribution() {
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let mut hb: HoneyBadger<SerializedContribution, NodeId> =
+        HoneyBadger::builder(netinfo).build();
+
+    // The encoded transactions are included in the batch unchanged.
+    let encoded = bincode::serialize(&vec![1usize, 2, 3]).expect("serialize");
+    let step = hb
+        .propose(&SerializedContribution::new(encoded.clone()))
+        .expect("propose");
+    let batch = step.output.into_iter().next().expect("batch");
+    let contribution = batch.contribution(&NodeId(0)).expect("our contribution");
+    assert_eq!(&encoded[..], contribution.as_bytes());
+}
+
+#[test]
+fn test_honey_badger_sign_batches() {
+    let _ = env_logger::try_init();
+
+    let new_hb = |netinfo: Arc<NetworkInfo<NodeId>>| -> UsizeHoneyBadger {
+        HoneyBadger::builder(netinfo).sign_batches(true).build()
+    };
+    let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+    let network = TestNetwork::new(4, 1, adversary, new_hb);
+    let pub_key = network.adv_nodes[&NodeId(4)].public_key_set().public_key();
+    let network = test_honey_badger(network, 10);
+
+    // Every batch, including the observer's, is signed by the validators.
+    let nodes = network.nodes.values().chain(Some(&network.observer));
+    for batch in nodes.flat_map(|node| node.outputs()) {
+        assert!(batch.verify_signature(&pub_key));
+        let mut forged = batch.clone();
+        forged.epoch = forged.epoch.next();
+        assert!(!forged.verify_signature(&pub_key));
+    }
+}
+
+#[test]
+fn test_verify_batch() {
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let pk_set = netinfo.public_key_set().clone();
+    let validators: BTreeSet<NodeId> = netinfo.all_ids().cloned().collect();
+    let mut hb: UsizeHoneyBadger = HoneyBadger::builder(netinfo).sign_batches(true).build();
+
+    let step = hb.propose(&vec![1, 2, 3]).expect("propose");
+    let batch = step.output.into_iter().next().expect("batch");
+    let (epoch0, epoch1) = (HbEpoch::new(0), HbEpoch::new(1));
+    let verified = verify_batch(batch.clone(), epoch0, &validators, &pk_set).expect("valid batch");
+    assert_eq!(batch.contributions, verified.contributions);
+
+    match verify_batch(batch.clone(), epoch1, &validators, &pk_set) {
+        Err(batch_verify::Error::WrongEpoch(expected, actual))
+            if expected == epoch1 && actual == epoch0 => {}
+        result => panic!("unexpected result: {:?}", result),
+    }
+    match verify_batch(batch.clone(), epoch0, &BTreeSet::new(), &pk_set) {
+        Err(batch_verify::Error::UnknownProposer) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    let mut unsigned = batch.clone();
+    unsigned.signature = None;
+    match verify_batch(unsigned, epoch0, &validators, &pk_set) {
+        Err(batch_verify::Error::Unsigned) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    let mut forged = batch;
+    forged.contributions.insert(NodeId(0), vec![4, 5, 6]);
+    match verify_batch(forged, epoch0, &validators, &pk_set) {
+        Err(batch_verify::Error::InvalidSignature) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
@@ -29,19 +29,28 @@
 //! ## Handles
 //!
 //! The adversary manipulates the network and nodes exclusively through handles that ensure they do
-//! not violate the constraints defined above. Handles are either mutable or immutable and can, in
-//! some cases be upgraded to actual references, if the underlying node is faulty (see
-//! `NodeHandle::node()` and `NodeHandle::node_mut()`).
+//! not violate the constraints defined above. Both `NetHandle` and `NetMutHandle` allow reading
+//! the state of every node, so that omniscient adversaries can be implemented, but only a
+//! `NetMutHandle` hands out mutable references, and only to faulty nodes. The state of a correct
+//! node can only be changed by delivering messages to it.
+//!
+//! These constraints are enforced by the types: Only a `FaultyNodeMut` can inject messages, with
+//! itself as the sender, and only a `FaultyMessage`, which the network creates for messages to
+//! faulty nodes, can be dispatched by the adversary.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::Arc;
+use std::{cmp, fmt, ops};
 
-use std::cmp;
+use rand::{Rand, Rng};
 
-use hbbft::{DistAlgorithm, Step};
+use hbbft::{DistAlgorithm, Step, Target, TargetedMessage};
 
-use net::{CrankError, NetMessage, Node, VirtualNet};
+use net::{CrankError, NetMessage, NetworkMessage, Node, VirtualNet};
 
 /// Immutable network handle.
 ///
-/// Allows querying public information of the network or getting immutable handles to any node.
+/// Allows reading the state of every node and every queued message: The adversary is omniscient.
 #[derive(Debug)]
 pub struct NetHandle<'a, D: 'a>(&'a VirtualNet<D>)
 where
@@ -51,39 +60,124 @@ impl<'a, D: 'a> NetHandle<'a, D>
 where
     D: DistAlgorithm,
 {
-    /// Returns a node handle iterator over all nodes in the network.
+    /// Returns an iterator over all nodes in the network.
     #[inline]
-    pub fn nodes(&self) -> impl Iterator<Item = NodeHandle<D>> {
-        self.0.nodes().map(NodeHandle::new)
+    pub fn nodes(&self) -> impl Iterator<Item = &'a Node<D>> {
+        self.0.nodes()
     }
 
     /// Returns an iterator over all faulty nodes in the network.
-    ///
-    /// Instead of a handle, returns the node directly, as the adversary gets full access to all
-    /// nodes in the network.
     #[inline]
-    pub fn faulty_nodes(&self) -> impl Iterator<Item = &Node<D>> {
-        // FIXME: Add an API to handle a step?
-        // Not wrapped in a `NodeHandle`, the adversary gets full access to their own nodes.
+    pub fn faulty_nodes(&self) -> impl Iterator<Item = &'a Node<D>> {
         self.0.faulty_nodes()
     }
 
-    /// Returns a node handle iterator over all correct nodes in the network.
+    /// Returns an iterator over all correct nodes in the network.
     #[inline]
-    pub fn correct_nodes(&self) -> impl Iterator<Item = NodeHandle<D>> {
-        self.0.correct_nodes().map(NodeHandle::new)
+    pub fn correct_nodes(&self) -> impl Iterator<Item = &'a Node<D>> {
+        self.0.correct_nodes()
     }
 
     /// Returns an iterator over all messages in the network.
     #[inline]
-    pub fn messages(&'a self) -> impl Iterator<Item = &'a NetMessage<D>> {
+    pub fn messages(&self) -> impl Iterator<Item = &'a NetMessage<D>> {
         self.0.messages()
     }
 
-    /// Returns a handle to a specific node handle.
+    /// Returns the node with the given ID, if it exists.
+    #[inline]
+    pub fn get(&self, id: D::NodeId) -> Option<&'a Node<D>> {
+        self.0.get(id)
+    }
+}
+
+/// A message addressed to a faulty node.
+///
+/// Only the network creates these, for the messages it hands to `Adversary::tamper`, so an
+/// adversary cannot dispatch messages to correct nodes.
+#[derive(Debug)]
+pub struct FaultyMessage<D: DistAlgorithm>(NetMessage<D>);
+
+impl<D: DistAlgorithm> FaultyMessage<D> {
+    /// Wraps a message whose recipient the caller has checked to be faulty.
+    pub(super) fn new(msg: NetMessage<D>) -> Self {
+        FaultyMessage(msg)
+    }
+
+    /// Returns the wrapped message.
+    #[inline]
+    pub fn into_inner(self) -> NetMessage<D> {
+        self.0
+    }
+}
+
+impl<D: DistAlgorithm> ops::Deref for FaultyMessage<D> {
+    type Target = NetMessage<D>;
+
+    #[inline]
+    fn deref(&self) -> &NetMessage<D> {
+        &self.0
+    }
+}
+
+/// Mutable handle to a single faulty node.
+///
+/// Allows modifying the node and sending messages on its behalf. It can only be obtained from a
+/// `NetMutHandle`, and only for a faulty node.
+#[derive(Debug)]
+pub struct FaultyNodeMut<'a, D: 'a>
+where
+    D: DistAlgorithm,
+{
+    /// The network the node belongs to.
+    net: &'a mut VirtualNet<D>,
+    /// The ID of the faulty node.
+    id: D::NodeId,
+}
+
+impl<'a, D> FaultyNodeMut<'a, D>
+where
+    D: DistAlgorithm,
+    D::NodeId: Clone,
+{
+    /// Returns the node's ID.
+    #[inline]
+    pub fn id(&self) -> &D::NodeId {
+        &self.id
+    }
+
+    /// Returns the node.
     #[inline]
-    pub fn get(&self, id: D::NodeId) -> Option<NodeHandle<D>> {
-        self.0.get(id).map(NodeHandle::new)
+    pub fn node(&self) -> &Node<D> {
+        self.net.get(self.id.clone()).expect("faulty node exists")
+    }
+
+    /// Returns the node, allowing changes.
+    #[inline]
+    pub fn node_mut(&mut self) -> &mut Node<D> {
+        self.net.get_mut(self.id.clone()).expect("faulty node exists")
+    }
+
+    /// Injects a message from this node to `to` at `position` into the message queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `to` does not exist, or if `position` is equal to `Before(idx)`, with `idx` being
+    /// out of bounds.
+    #[inline]
+    pub fn inject_message(&mut self, position: QueuePosition, to: D::NodeId, payload: D::Message) {
+        // Recipient must exist.
+        self.net
+            .get(to.clone())
+            .expect("inject: unknown recipient node");
+
+        let msg = NetworkMessage::new(self.id.clone(), Arc::new(payload), to);
+        // Insert into queue. `insert` will panic on out-of-bounds.
+        match position {
+            QueuePosition::Front => self.net.messages.push_front(msg),
+            QueuePosition::Back => self.net.messages.push_back(msg),
+            QueuePosition::Before(idx) => self.net.messages.insert(idx, msg),
+        }
     }
 }
 
@@ -100,8 +194,10 @@ pub enum QueuePosition {
 
 /// Mutable network handle.
 ///
-/// Allows reordering of messages, injecting new ones into the network queue and getting mutable
-/// handles to nodes.
+/// Like a `NetHandle`, allows reading the state of every node. In addition, allows reordering
+/// messages, injecting new ones from faulty nodes into the network queue and modifying the faulty
+/// nodes. Correct nodes are only ever accessible by shared reference, so an adversary cannot
+/// change their state other than by sending them messages.
 #[derive(Debug)]
 pub struct NetMutHandle<'a, D: 'a>(&'a mut VirtualNet<D>)
 where
@@ -112,68 +208,96 @@ where
     D: DistAlgorithm,
     D::NodeId: Clone,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     pub fn new(net: &'a mut VirtualNet<D>) -> Self {
         NetMutHandle(net)
     }
 
-    /// Returns a mutable node handle iterator over all nodes in the network.
+    /// Returns a new handle to the same network, borrowing this one.
+    ///
+    /// This allows an adversary to pass the handle on to another adversary it wraps.
     #[inline]
-    pub fn nodes_mut(&mut self) -> impl Iterator<Item = NodeMutHandle<D>> {
-        self.0.nodes_mut().map(NodeMutHandle::new)
+    pub fn reborrow(&mut self) -> NetMutHandle<D> {
+        NetMutHandle(&mut *self.0)
+    }
+
+    /// Returns an iterator over all nodes in the network.
+    #[inline]
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<D>> {
+        self.0.nodes()
+    }
+
+    /// Returns an iterator over all correct nodes in the network.
+    #[inline]
+    pub fn correct_nodes(&self) -> impl Iterator<Item = &Node<D>> {
+        self.0.correct_nodes()
+    }
+
+    /// Returns an iterator over all faulty nodes in the network.
+    #[inline]
+    pub fn faulty_nodes(&self) -> impl Iterator<Item = &Node<D>> {
+        self.0.faulty_nodes()
     }
 
     /// Returns an iterator that allows changes to all faulty nodes in the network.
-    ///
-    /// Instead of a handle, returns the node directly, as the adversary gets full access to all
-    /// nodes in the network.
     #[inline]
     pub fn faulty_nodes_mut(&mut self) -> impl Iterator<Item = &mut Node<D>> {
         self.0.faulty_nodes_mut()
     }
 
-    /// Returns a mutable node handle iterator over all nodes in the network.
+    /// Returns the node with the given ID, if it exists.
     #[inline]
-    pub fn correct_nodes_mut(&mut self) -> impl Iterator<Item = NodeMutHandle<D>> {
-        self.0.correct_nodes_mut().map(NodeMutHandle::new)
+    pub fn get(&self, id: D::NodeId) -> Option<&Node<D>> {
+        self.0.get(id)
     }
 
-    /// Normally dispatch a message
-    pub fn dispatch_message(&mut self, msg: NetMessage<D>) -> Result<Step<D>, CrankError<D>> {
-        self.0.dispatch_message(msg)
+    /// Returns a handle to the node with the given ID, if it exists and is faulty.
+    #[inline]
+    pub fn get_faulty_mut(&mut self, id: D::NodeId) -> Option<FaultyNodeMut<D>> {
+        if !self.0.get(id.clone()).map_or(false, Node::is_faulty) {
+            return None;
+        }
+        Some(FaultyNodeMut {
+            net: &mut *self.0,
+            id,
+        })
     }
 
-    /// Injects a message into the network.
-    ///
-    /// Allows the injection of `msg` at `position` into the message queue.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `msg.from` is not a faulty node or either `msg.from` or `msg.to` do not exist.
-    /// Panics if `position` is equal to `Before(idx)`, with `idx` being out of bounds.
+    /// Returns an iterator over the IDs of all nodes in the network.
     #[inline]
-    pub fn inject_message(&mut self, position: QueuePosition, msg: NetMessage<D>) {
-        // Ensure the node is not faulty.
-        assert!(
-            self.0
-                .get(msg.from.clone())
-                .expect("inject: unknown sender node")
-                .is_faulty(),
-            "Tried to inject message not originating from a faulty node."
-        );
+    pub fn node_ids(&self) -> impl Iterator<Item = &D::NodeId> {
+        self.0.nodes.keys()
+    }
+
+    /// Returns an iterator over the IDs of all faulty nodes in the network.
+    #[inline]
+    pub fn faulty_node_ids(&self) -> impl Iterator<Item = &D::NodeId> {
+        self.0.faulty_nodes().map(Node::id)
+    }
 
-        // Sender must exist.
+    /// Returns an iterator over all messages in the network.
+    #[inline]
+    pub fn messages(&self) -> impl Iterator<Item = &NetMessage<D>> {
+        self.0.messages()
+    }
+
+    /// Removes all queued messages sent by faulty nodes.
+    ///
+    /// Since the adversary controls the faulty nodes, it can decide not to send their messages.
+    #[inline]
+    pub fn discard_faulty_messages(&mut self) {
+        let nodes = &self.0.nodes;
         self.0
-            .get(msg.to.clone())
-            .expect("inject: unknown recipient node");
+            .messages
+            .retain(|msg| !nodes.get(&msg.from).map_or(false, Node::is_faulty));
+    }
 
-        // Insert into queue. `insert` will panic on out-of-bounds.
-        match position {
-            QueuePosition::Front => self.0.messages.push_front(msg),
-            QueuePosition::Back => self.0.messages.push_back(msg),
-            QueuePosition::Before(idx) => self.0.messages.insert(idx, msg),
-        }
+    /// Normally dispatch a message to a faulty node.
+    ///
+    /// Messages to correct nodes are only delivered by the network itself.
+    pub fn dispatch_message(&mut self, msg: FaultyMessage<D>) -> Result<Step<D>, CrankError<D>> {
+        self.0.dispatch_message(msg.into_inner())
     }
 
     /// Swap two messages in the message queue.
@@ -186,6 +310,17 @@ where
         self.0.swap_messages(i, j);
     }
 
+    /// Moves the message at index `idx` to the front of the queue, so that it is delivered next.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is out-of-bounds.
+    #[inline]
+    pub fn move_to_front(&mut self, idx: usize) {
+        let msg = self.0.messages.remove(idx).expect("index is out of bounds");
+        self.0.messages.push_front(msg);
+    }
+
     /// Reorder all messages.
     ///
     /// Sorts all message with a comparator function.
@@ -199,97 +334,25 @@ where
     {
         self.0.sort_messages_by(f)
     }
-}
-
-// Downgrade-conversion.
-impl<'a, D> From<NetMutHandle<'a, D>> for NetHandle<'a, D>
-where
-    D: DistAlgorithm,
-{
-    #[inline]
-    fn from(n: NetMutHandle<D>) -> NetHandle<D> {
-        NetHandle(n.0)
-    }
-}
-
-/// Immutable node handle.
-#[derive(Debug)]
-pub struct NodeHandle<'a, D: 'a>(&'a Node<D>)
-where
-    D: DistAlgorithm;
-
-impl<'a, D> NodeHandle<'a, D>
-where
-    D: DistAlgorithm,
-{
-    /// Construct a new immutable node handle.
-    #[inline]
-    fn new(inner: &'a Node<D>) -> Self {
-        NodeHandle(inner)
-    }
-
-    /// Return node ID.
-    #[inline]
-    pub fn id(&self) -> D::NodeId {
-        self.0.id().clone()
-    }
 
-    /// Returns a reference to the faulty node.
+    /// Returns the network's random number generator.
     ///
-    /// # Panics
-    ///
-    /// Panics if the node is not faulty.
+    /// Adversaries that make random decisions should use it, so that test runs can be reproduced
+    /// from the network's seed.
     #[inline]
-    pub fn node(&self) -> &'a Node<D> {
-        self.try_node()
-            .expect("could not access inner node of handle, node is not faulty")
-    }
-
-    /// If the inner node is faulty, returns a reference to it.
-    #[inline]
-    pub fn try_node(&self) -> Option<&'a Node<D>> {
-        if self.0.is_faulty() {
-            Some(self.0)
-        } else {
-            None
-        }
+    pub fn rng(&mut self) -> &mut dyn Rng {
+        &mut *self.0.rng
     }
 }
 
-/// Mutable node handle.
-#[derive(Debug)]
-pub struct NodeMutHandle<'a, D: 'a>(&'a mut Node<D>)
-where
-    D: DistAlgorithm;
-
-impl<'a, D: 'a> NodeMutHandle<'a, D>
+// Downgrade-conversion.
+impl<'a, D> From<NetMutHandle<'a, D>> for NetHandle<'a, D>
 where
     D: DistAlgorithm,
 {
-    /// Construct a new mutable node handle.
-    fn new(inner: &'a mut Node<D>) -> Self {
-        NodeMutHandle(inner)
-    }
-
-    /// Returns a mutable reference to the faulty node.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the node is not faulty.
-    #[inline]
-    pub fn node_mut(&'a mut self) -> &'a mut Node<D> {
-        self.try_node_mut()
-            .expect("could not access inner node of handle, node is not faulty")
-    }
-
-    /// If the inner node is faulty, returns a mutable reference to it.
     #[inline]
-    pub fn try_node_mut(&mut self) -> Option<&mut Node<D>> {
-        if self.0.is_faulty() {
-            Some(self.0)
-        } else {
-            None
-        }
+    fn from(n: NetMutHandle<D>) -> NetHandle<D> {
+        NetHandle(n.0)
     }
 }
 
@@ -298,7 +361,7 @@ pub trait Adversary<D>
 where
     D: DistAlgorithm,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     /// Pre-crank hook.
     ///
@@ -326,7 +389,7 @@ where
     fn tamper(
         &mut self,
         mut net: NetMutHandle<D>,
-        msg: NetMessage<D>,
+        msg: FaultyMessage<D>,
     ) -> Result<Step<D>, CrankError<D>> {
         net.dispatch_message(msg)
     }
@@ -351,7 +414,7 @@ impl<D> Adversary<D> for NullAdversary
 where
     D: DistAlgorithm,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {}
 
 /// Ascending node id message order adversary.
@@ -375,7 +438,7 @@ impl<D> Adversary<D> for NodeOrderAdversary
 where
     D: DistAlgorithm,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     #[inline]
     fn pre_crank(&mut self, mut net: NetMutHandle<D>) {
@@ -383,3 +446,328 @@ where
         net.sort_messages_by(|a, b| a.to.cmp(&b.to))
     }
 }
+
+/// Silent adversary.
+///
+/// The faulty nodes never send anything: Messages they sent before the adversary took control are
+/// discarded, and messages addressed to them are ignored.
+#[derive(Debug, Default)]
+pub struct SilentAdversary;
+
+impl SilentAdversary {
+    /// Create a new `SilentAdversary`.
+    #[inline]
+    pub fn new() -> Self {
+        SilentAdversary {}
+    }
+}
+
+impl<D> Adversary<D> for SilentAdversary
+where
+    D: DistAlgorithm,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    #[inline]
+    fn pre_crank(&mut self, mut net: NetMutHandle<D>) {
+        net.discard_faulty_messages();
+    }
+
+    #[inline]
+    fn tamper(
+        &mut self,
+        _: NetMutHandle<D>,
+        _: FaultyMessage<D>,
+    ) -> Result<Step<D>, CrankError<D>> {
+        Ok(Step::default())
+    }
+}
+
+/// Equivocating adversary.
+///
+/// The faulty nodes run the algorithm, but send conflicting messages to different peers: Whenever
+/// a faulty node handles a message, every outgoing message for which `equivocate` returns an
+/// alternative is sent unchanged to the nodes at even positions in the list of all node IDs, and
+/// replaced by the alternative for the nodes at odd positions. E.g. to equivocate in Binary
+/// Agreement, `equivocate` could negate the value of every `BVal` message.
+///
+/// Messages that faulty nodes send on construction or input are not affected.
+pub struct EquivocatingAdversary<F> {
+    /// Returns a conflicting alternative to the given message, if there is one.
+    equivocate: F,
+}
+
+impl<F> fmt::Debug for EquivocatingAdversary<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EquivocatingAdversary")
+            .field("equivocate", &"<FN>")
+            .finish()
+    }
+}
+
+impl<F> EquivocatingAdversary<F> {
+    /// Creates a new `EquivocatingAdversary` using the given function to create conflicting
+    /// messages.
+    #[inline]
+    pub fn new(equivocate: F) -> Self {
+        EquivocatingAdversary { equivocate }
+    }
+}
+
+impl<D, F> Adversary<D> for EquivocatingAdversary<F>
+where
+    D: DistAlgorithm,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+    F: FnMut(&D::Message) -> Option<D::Message>,
+{
+    fn tamper(
+        &mut self,
+        mut net: NetMutHandle<D>,
+        msg: FaultyMessage<D>,
+    ) -> Result<Step<D>, CrankError<D>> {
+        let odd_ids: BTreeSet<D::NodeId> = net.node_ids().skip(1).step_by(2).cloned().collect();
+        let all_ids: BTreeSet<D::NodeId> = net.node_ids().cloned().collect();
+        let step = net.dispatch_message(msg)?;
+
+        let mut messages = VecDeque::new();
+        for TargetedMessage { target, message } in step.messages {
+            let recipients = match target {
+                Target::All => all_ids.clone(),
+                Target::AllExcept(exclude) => all_ids.difference(&exclude).cloned().collect(),
+                Target::Node(id) => Some(id).into_iter().collect(),
+                Target::Nodes(ids) => ids,
+                Target::Observers => {
+                    messages.push_back(Target::Observers.message(message));
+                    continue;
+                }
+            };
+            let (odd, mut even): (BTreeSet<_>, BTreeSet<_>) = recipients
+                .into_iter()
+                .partition(|id| odd_ids.contains(id));
+            match (self.equivocate)(&message) {
+                Some(alternative) => if !odd.is_empty() {
+                    messages.push_back(Target::Nodes(odd).message(alternative));
+                },
+                None => even.extend(odd),
+            }
+            if !even.is_empty() {
+                messages.push_back(Target::Nodes(even).message(message));
+            }
+        }
+
+        let mut tampered = Step::default();
+        tampered.output = step.output;
+        tampered.fault_log = step.fault_log;
+        tampered.messages = messages;
+        Ok(tampered)
+    }
+}
+
+/// Delaying adversary.
+///
+/// Before each crank, moves a random one of the first `window` queued messages to the front, so
+/// that it is delivered next. Messages further back in the queue are held until enough of the
+/// messages before them have been delivered.
+///
+/// If the network has a `DeliverySchedule`, the order of the queue only affects messages that are
+/// due at the same time.
+#[derive(Debug)]
+pub struct DelayingAdversary {
+    /// The number of messages at the front of the queue that can be reordered.
+    window: usize,
+}
+
+impl DelayingAdversary {
+    /// Creates a new `DelayingAdversary` that reorders messages within the given window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    #[inline]
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "the window must contain at least one message");
+        DelayingAdversary { window }
+    }
+}
+
+impl<D> Adversary<D> for DelayingAdversary
+where
+    D: DistAlgorithm,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    fn pre_crank(&mut self, mut net: NetMutHandle<D>) {
+        let len = cmp::min(self.window, net.messages().count());
+        if len > 1 {
+            let idx = Rng::gen_range(&mut net.rng(), 0, len);
+            net.move_to_front(idx);
+        }
+    }
+}
+
+/// Scripted reordering adversary.
+///
+/// Before each crank, takes the next entry `i` of its script and moves the queued message at index
+/// `i` modulo the queue length to the front, so that it is delivered next. Once the script is
+/// exhausted, messages are delivered in queue order.
+///
+/// Unlike the `DelayingAdversary`, the schedule is determined by the script alone. If a property
+/// test generates the script, a failing schedule is shrunk to a minimal one: a shorter script with
+/// smaller indices reorders fewer messages.
+#[derive(Clone, Debug)]
+pub struct ReorderingAdversary {
+    /// The indices of the messages to deliver next.
+    script: Vec<usize>,
+    /// The position of the next script entry.
+    pos: usize,
+}
+
+impl ReorderingAdversary {
+    /// Creates a new `ReorderingAdversary` that follows the given script.
+    #[inline]
+    pub fn new(script: Vec<usize>) -> Self {
+        ReorderingAdversary { script, pos: 0 }
+    }
+}
+
+impl<D> Adversary<D> for ReorderingAdversary
+where
+    D: DistAlgorithm,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    fn pre_crank(&mut self, mut net: NetMutHandle<D>) {
+        let entry = match self.script.get(self.pos) {
+            Some(&entry) => entry,
+            None => return,
+        };
+        self.pos += 1;
+        let len = net.messages().count();
+        if len > 1 {
+            net.move_to_front(entry % len);
+        }
+    }
+}
+
+/// Rushing adversary.
+///
+/// The strongest adversary model of the Honey Badger paper: Before each crank, all messages
+/// addressed to faulty nodes are moved to the front of the queue, followed by all messages sent by
+/// faulty nodes. So the faulty nodes see every message before any correct node does, and their
+/// responses overtake all traffic between correct nodes, which is only delivered once no message
+/// to or from a faulty node is queued. Apart from that, the order of the queue is preserved.
+///
+/// The faulty nodes' responses are determined by the wrapped adversary's `tamper`, e.g. that of
+/// an `EquivocatingAdversary`. Its `pre_crank` is called first, but the rushing order takes
+/// precedence. If the network has a `DeliverySchedule`, the order of the queue only affects
+/// messages that are due at the same time.
+#[derive(Debug)]
+pub struct RushingAdversary<A> {
+    /// The adversary controlling the faulty nodes.
+    inner: A,
+}
+
+impl<A> RushingAdversary<A> {
+    /// Creates a new `RushingAdversary` whose faulty nodes are controlled by `inner`.
+    #[inline]
+    pub fn new(inner: A) -> Self {
+        RushingAdversary { inner }
+    }
+}
+
+impl<D, A> Adversary<D> for RushingAdversary<A>
+where
+    D: DistAlgorithm,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+    A: Adversary<D>,
+{
+    fn pre_crank(&mut self, mut net: NetMutHandle<D>) {
+        self.inner.pre_crank(net.reborrow());
+        let faulty_ids: BTreeSet<D::NodeId> = net.faulty_node_ids().cloned().collect();
+        let rank = |msg: &NetMessage<D>| {
+            if faulty_ids.contains(&msg.to) {
+                0
+            } else if faulty_ids.contains(&msg.from) {
+                1
+            } else {
+                2
+            }
+        };
+        net.sort_messages_by(|a, b| rank(a).cmp(&rank(b)));
+    }
+
+    #[inline]
+    fn tamper(
+        &mut self,
+        net: NetMutHandle<D>,
+        msg: FaultyMessage<D>,
+    ) -> Result<Step<D>, CrankError<D>> {
+        self.inner.tamper(net, msg)
+    }
+}
+
+/// Random message adversary.
+///
+/// Before each crank, with probability `p_inject`, injects a random message from a random faulty
+/// node to a random other node, at the front or the back of the queue. Apart from that, the faulty
+/// nodes behave like correct ones.
+#[derive(Debug)]
+pub struct RandomAdversary {
+    /// The probability of injecting a message before a crank.
+    p_inject: f32,
+}
+
+impl RandomAdversary {
+    /// Creates a new `RandomAdversary` that injects messages with the given probability.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `0 <= p_inject < 1`: Otherwise, the network would never run out of messages.
+    #[inline]
+    pub fn new(p_inject: f32) -> Self {
+        assert!(
+            0.0 <= p_inject && p_inject < 1.0,
+            "invalid injection probability"
+        );
+        RandomAdversary { p_inject }
+    }
+}
+
+impl<D> Adversary<D> for RandomAdversary
+where
+    D: DistAlgorithm,
+    D::Message: Clone + Rand,
+    D::Output: Clone + fmt::Debug,
+{
+    fn pre_crank(&mut self, mut net: NetMutHandle<D>) {
+        let faulty_ids: Vec<D::NodeId> = net.faulty_node_ids().cloned().collect();
+        let ids: Vec<D::NodeId> = net.node_ids().cloned().collect();
+        let (position, from, to, payload) = {
+            let mut rng = net.rng();
+            if Rng::gen::<f32>(&mut rng) >= self.p_inject {
+                return;
+            }
+            let from = match Rng::choose(&mut rng, &faulty_ids) {
+                Some(from) => from.clone(),
+                None => return,
+            };
+            let others: Vec<&D::NodeId> = ids.iter().filter(|&id| *id != from).collect();
+            let to = match Rng::choose(&mut rng, &others) {
+                Some(to) => (*to).clone(),
+                None => return,
+            };
+            let position = if Rng::gen(&mut rng) {
+                QueuePosition::Front
+            } else {
+                QueuePosition::Back
+            };
+            let payload: D::Message = Rng::gen(&mut rng);
+            (position, from, to, payload)
+        };
+        net.get_faulty_mut(from)
+            .expect("sender is faulty")
+            .inject_message(position, to, payload);
+    }
+}
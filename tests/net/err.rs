@@ -1,11 +1,11 @@
 //! Test network errors
 
-use std::{fmt, time};
+use std::{fmt, io, time};
 
 use failure;
-use hbbft::DistAlgorithm;
+use hbbft::{DistAlgorithm, NetworkInfoError};
 
-use super::NetMessage;
+use super::{NetMessage, QueueStats, TraceEvent};
 
 /// Network crank error.
 ///
@@ -24,11 +24,40 @@ where
     /// the network framework code.
     NodeDisappeared(D::NodeId),
     /// The configured maximum number of cranks has been reached or exceeded.
-    CrankLimitExceeded(usize),
+    CrankLimitExceeded {
+        /// The maximum number of cranks.
+        limit: usize,
+        /// The messages that were still queued.
+        queue: QueueStats<D::NodeId>,
+    },
     /// The configured maximum number of messages has been reached or exceeded.
-    MessageLimitExceeded(usize),
+    MessageLimitExceeded {
+        /// The maximum number of messages.
+        limit: usize,
+        /// The messages that were still queued.
+        queue: QueueStats<D::NodeId>,
+    },
+    /// The configured maximum number of queued messages has been exceeded.
+    QueueLimitExceeded {
+        /// The maximum number of queued messages.
+        limit: usize,
+        /// The messages that were queued.
+        queue: QueueStats<D::NodeId>,
+    },
     /// The execution time limit has been reached or exceeded.
     TimeLimitHit(time::Duration),
+    /// A recorded event could not be replayed, because no matching message was queued.
+    ReplayMismatch(TraceEvent),
+    /// The network did not make the expected progress: It ran out of messages, or reached the
+    /// maximum number of cranks, before the condition was met.
+    Stalled {
+        /// The number of cranks made.
+        cranks: usize,
+        /// The messages that were still queued.
+        pending_messages: Vec<NetMessage<D>>,
+    },
+    /// The outputs of two correct nodes differ.
+    OutputsDiffer(D::NodeId, D::NodeId),
 }
 
 // Note: Deriving [Debug](std::fmt::Debug), [Fail](failure::Fail) and through that,
@@ -56,15 +85,36 @@ where
                 "Node {:?} disappeared or never existed, while it still had incoming messages.",
                 id
             ),
-            CrankError::CrankLimitExceeded(max) => {
-                write!(f, "Maximum number of cranks exceeded: {}", max)
+            CrankError::CrankLimitExceeded { limit, queue } => {
+                write!(f, "Maximum number of cranks exceeded: {}; {}", limit, queue)
+            }
+            CrankError::MessageLimitExceeded { limit, queue } => {
+                write!(f, "Maximum number of messages exceeded: {}; {}", limit, queue)
             }
-            CrankError::MessageLimitExceeded(max) => {
-                write!(f, "Maximum number of messages exceeded: {}", max)
+            CrankError::QueueLimitExceeded { limit, queue } => {
+                write!(f, "Maximum number of queued messages exceeded: {}; {}", limit, queue)
             }
             CrankError::TimeLimitHit(lim) => {
                 write!(f, "Time limit of {} seconds exceeded.", lim.as_secs())
             }
+            CrankError::ReplayMismatch(event) => write!(
+                f,
+                "Could not replay {:?}: no matching message is queued.",
+                event
+            ),
+            CrankError::Stalled {
+                cranks,
+                pending_messages,
+            } => write!(
+                f,
+                "The network stalled after {} cranks, with {} pending messages: {:?}",
+                cranks,
+                pending_messages.len(),
+                pending_messages
+            ),
+            CrankError::OutputsDiffer(id0, id1) => {
+                write!(f, "The outputs of nodes {:?} and {:?} differ.", id0, id1)
+            }
         }
     }
 }
@@ -81,13 +131,38 @@ where
                 .field("err", err)
                 .finish(),
             CrankError::NodeDisappeared(id) => f.debug_tuple("NodeDisappeared").field(id).finish(),
-            CrankError::CrankLimitExceeded(max) => {
-                f.debug_tuple("CrankLimitExceeded").field(max).finish()
-            }
-            CrankError::MessageLimitExceeded(max) => {
-                f.debug_tuple("MessageLimitExceeded").field(max).finish()
-            }
+            CrankError::CrankLimitExceeded { limit, queue } => f
+                .debug_struct("CrankLimitExceeded")
+                .field("limit", limit)
+                .field("queue", queue)
+                .finish(),
+            CrankError::MessageLimitExceeded { limit, queue } => f
+                .debug_struct("MessageLimitExceeded")
+                .field("limit", limit)
+                .field("queue", queue)
+                .finish(),
+            CrankError::QueueLimitExceeded { limit, queue } => f
+                .debug_struct("QueueLimitExceeded")
+                .field("limit", limit)
+                .field("queue", queue)
+                .finish(),
             CrankError::TimeLimitHit(lim) => f.debug_tuple("TimeLimitHit").field(lim).finish(),
+            CrankError::ReplayMismatch(event) => {
+                f.debug_tuple("ReplayMismatch").field(event).finish()
+            }
+            CrankError::Stalled {
+                cranks,
+                pending_messages,
+            } => f
+                .debug_struct("Stalled")
+                .field("cranks", cranks)
+                .field("pending_messages", pending_messages)
+                .finish(),
+            CrankError::OutputsDiffer(id0, id1) => f
+                .debug_tuple("OutputsDiffer")
+                .field(id0)
+                .field(id1)
+                .finish(),
         }
     }
 }
@@ -103,3 +178,69 @@ where
         }
     }
 }
+
+/// Network construction error.
+///
+/// Errors resulting from an invalid `NetBuilder` configuration.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The node keys could not be generated.
+    NetworkInfo(NetworkInfoError),
+    /// The number of faulty nodes is not less than a third of the total number of nodes.
+    TooManyFaulty {
+        /// The total number of nodes.
+        num_nodes: usize,
+        /// The requested number of faulty nodes.
+        num_faulty: usize,
+    },
+    /// Neither `using` nor `using_step` was called, so the nodes cannot be constructed.
+    NoConstructor,
+    /// An environment variable has an invalid value.
+    InvalidEnvVar {
+        /// The name of the variable.
+        name: &'static str,
+        /// The value that could not be parsed.
+        value: String,
+    },
+    /// The trace file could not be created.
+    Trace(io::Error),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::NetworkInfo(err) => write!(f, "Could not generate node keys: {}", err),
+            BuildError::TooManyFaulty {
+                num_nodes,
+                num_faulty,
+            } => write!(
+                f,
+                "Too many faulty nodes: {} of {}, `f` must satisfy `3f < total_nodes`.",
+                num_faulty, num_nodes
+            ),
+            BuildError::NoConstructor => {
+                write!(f, "No constructor function for the nodes was specified.")
+            }
+            BuildError::InvalidEnvVar { name, value } => {
+                write!(f, "Could not parse `{}={}`.", name, value)
+            }
+            BuildError::Trace(err) => write!(f, "Could not create trace file: {}", err),
+        }
+    }
+}
+
+impl failure::Fail for BuildError {
+    fn cause(&self) -> Option<&failure::Fail> {
+        match self {
+            BuildError::NetworkInfo(err) => Some(err),
+            BuildError::Trace(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<NetworkInfoError> for BuildError {
+    fn from(err: NetworkInfoError) -> Self {
+        BuildError::NetworkInfo(err)
+    }
+}
@@ -0,0 +1,51 @@
+//! Lossy networks
+//!
+//! The adversary model assumes that every message is eventually delivered exactly once. A
+//! `VirtualNet` configured with a `LossyNetwork` violates that assumption on purpose: Each message
+//! is randomly dropped or duplicated, to test that algorithms fail gracefully without it, and that
+//! duplicates are never counted twice. The decisions are drawn from the network's random number
+//! generator, so that test runs can be reproduced from the network's seed.
+
+use rand::Rng;
+
+/// Randomly drops and duplicates messages in a `VirtualNet`.
+#[derive(Clone, Copy, Debug)]
+pub struct LossyNetwork {
+    /// The probability that a message is dropped.
+    p_drop: f32,
+    /// The probability that a message that was not dropped is delivered a second time.
+    p_duplicate: f32,
+}
+
+impl LossyNetwork {
+    /// Creates a new lossy network with the given probabilities of dropping and duplicating a
+    /// message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a probability is not between 0 and 1.
+    #[inline]
+    pub fn new(p_drop: f32, p_duplicate: f32) -> Self {
+        assert!(0.0 <= p_drop && p_drop <= 1.0, "invalid drop probability");
+        assert!(
+            0.0 <= p_duplicate && p_duplicate <= 1.0,
+            "invalid duplication probability"
+        );
+        LossyNetwork {
+            p_drop,
+            p_duplicate,
+        }
+    }
+
+    /// Returns `true` if the next message should be dropped.
+    #[inline]
+    pub fn drop_message(&self, mut rng: &mut dyn Rng) -> bool {
+        Rng::gen::<f32>(&mut rng) < self.p_drop
+    }
+
+    /// Returns `true` if the next message should be delivered a second time.
+    #[inline]
+    pub fn duplicate_message(&self, mut rng: &mut dyn Rng) -> bool {
+        Rng::gen::<f32>(&mut rng) < self.p_duplicate
+    }
+}
@@ -0,0 +1,222 @@
+//! Networks of nodes running different algorithms
+//!
+//! A `VirtualNet` hosts nodes of a single `DistAlgorithm` type. To simulate a network in which the
+//! nodes run different implementations, e.g. because one of them still runs an old version with a
+//! different message format, each node's algorithm can be wrapped in a `MixedNode`. It hides the
+//! actual algorithm behind a trait object, and converts its messages to and from a common type `M`,
+//! typically their serialized form. A message that a node can't decode is reported as a
+//! `MixedFault::UndecodableMessage` of its sender, like a real node would do.
+
+use std::any::Any;
+use std::fmt;
+
+use failure;
+use hbbft::validation::Validity;
+use hbbft::{DistAlgorithm, Message, NodeIdT, Step};
+
+/// An error returned by the algorithm of a `MixedNode`, in its `Debug` representation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MixedError(pub String);
+
+impl fmt::Display for MixedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The node's algorithm failed: {}", self.0)
+    }
+}
+
+impl failure::Fail for MixedError {}
+
+/// A fault reported by a `MixedNode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MixedFault {
+    /// The sender's message could not be decoded.
+    UndecodableMessage,
+    /// The wrapped algorithm reported a fault of its own kind.
+    Algorithm,
+}
+
+/// A step of a `MixedNode`.
+pub type MixedStep<N, I, O, M> = Step<MixedNode<N, I, O, M>>;
+
+/// The interface of the algorithm wrapped in a `MixedNode`.
+trait NodeAlgorithm<N, I, O, M>: Send + Sync {
+    fn handle_input(&mut self, input: I) -> Result<MixedStep<N, I, O, M>, MixedError>;
+
+    fn handle_message(
+        &mut self,
+        sender_id: &N,
+        msg: M,
+    ) -> Result<MixedStep<N, I, O, M>, MixedError>;
+
+    fn handle_timeout(&mut self, token: u64) -> Result<MixedStep<N, I, O, M>, MixedError>;
+
+    fn terminated(&self) -> bool;
+
+    fn our_id(&self) -> &N;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// An algorithm together with the functions converting its messages.
+struct Adapter<D: DistAlgorithm, M> {
+    /// The actual algorithm.
+    algorithm: D,
+    /// Converts an outgoing message.
+    encode: Box<dyn Fn(D::Message) -> M + Send + Sync>,
+    /// Converts an incoming message, or returns `None` if it is invalid.
+    decode: Box<dyn Fn(M) -> Option<D::Message> + Send + Sync>,
+}
+
+impl<D, M> Adapter<D, M>
+where
+    D: DistAlgorithm + 'static,
+    M: Message,
+{
+    /// Converts the algorithm's step and error.
+    fn convert(
+        &self,
+        result: Result<Step<D>, D::Error>,
+    ) -> Result<MixedStep<D::NodeId, D::Input, D::Output, M>, MixedError> {
+        let step = result.map_err(|err| MixedError(format!("{:?}", err)))?;
+        Ok(step.map(
+            |output| output,
+            |_| MixedFault::Algorithm,
+            |msg| (self.encode)(msg),
+        ))
+    }
+}
+
+impl<D, M> NodeAlgorithm<D::NodeId, D::Input, D::Output, M> for Adapter<D, M>
+where
+    D: DistAlgorithm + 'static,
+    M: Message,
+{
+    fn handle_input(
+        &mut self,
+        input: D::Input,
+    ) -> Result<MixedStep<D::NodeId, D::Input, D::Output, M>, MixedError> {
+        let result = self.algorithm.handle_input(input);
+        self.convert(result)
+    }
+
+    fn handle_message(
+        &mut self,
+        sender_id: &D::NodeId,
+        msg: M,
+    ) -> Result<MixedStep<D::NodeId, D::Input, D::Output, M>, MixedError> {
+        match (self.decode)(msg) {
+            Some(msg) => {
+                let result = self.algorithm.handle_message(sender_id, msg);
+                self.convert(result)
+            }
+            None => {
+                let validity = Validity::Faulty(MixedFault::UndecodableMessage);
+                Ok(Step::validated(sender_id.clone(), validity))
+            }
+        }
+    }
+
+    fn handle_timeout(
+        &mut self,
+        token: u64,
+    ) -> Result<MixedStep<D::NodeId, D::Input, D::Output, M>, MixedError> {
+        let result = self.algorithm.handle_timeout(token);
+        self.convert(result)
+    }
+
+    fn terminated(&self) -> bool {
+        self.algorithm.terminated()
+    }
+
+    fn our_id(&self) -> &D::NodeId {
+        self.algorithm.our_id()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        &self.algorithm
+    }
+}
+
+/// A node running any algorithm with node IDs `N`, inputs `I` and outputs `O`, whose messages are
+/// converted to and from `M`.
+pub struct MixedNode<N, I, O, M> {
+    /// The wrapped algorithm.
+    inner: Box<dyn NodeAlgorithm<N, I, O, M>>,
+}
+
+impl<N, I, O, M> MixedNode<N, I, O, M>
+where
+    N: NodeIdT,
+    M: Message + 'static,
+{
+    /// Wraps the given algorithm. Its outgoing messages are converted using `encode`, and incoming
+    /// ones using `decode`, which returns `None` if a message is invalid.
+    pub fn new<D, FE, FD>(algorithm: D, encode: FE, decode: FD) -> Self
+    where
+        D: DistAlgorithm<NodeId = N, Input = I, Output = O> + 'static,
+        FE: Fn(D::Message) -> M + Send + Sync + 'static,
+        FD: Fn(M) -> Option<D::Message> + Send + Sync + 'static,
+    {
+        MixedNode {
+            inner: Box::new(Adapter {
+                algorithm,
+                encode: Box::new(encode),
+                decode: Box::new(decode),
+            }),
+        }
+    }
+
+    /// Returns the wrapped algorithm, if it is a `D`.
+    pub fn algorithm<D: Any>(&self) -> Option<&D> {
+        self.inner.as_any().downcast_ref()
+    }
+}
+
+impl<N, I, O, M> fmt::Debug for MixedNode<N, I, O, M>
+where
+    N: NodeIdT,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MixedNode")
+            .field("our_id", self.inner.our_id())
+            .field("terminated", &self.inner.terminated())
+            .finish()
+    }
+}
+
+impl<N, I, O, M> DistAlgorithm for MixedNode<N, I, O, M>
+where
+    N: NodeIdT,
+    M: Message,
+{
+    type NodeId = N;
+    type Input = I;
+    type Output = O;
+    type Message = M;
+    type Error = MixedError;
+    type FaultKind = MixedFault;
+
+    fn handle_input(&mut self, input: I) -> Result<MixedStep<N, I, O, M>, MixedError> {
+        self.inner.handle_input(input)
+    }
+
+    fn handle_message(
+        &mut self,
+        sender_id: &N,
+        msg: M,
+    ) -> Result<MixedStep<N, I, O, M>, MixedError> {
+        self.inner.handle_message(sender_id, msg)
+    }
+
+    fn handle_timeout(&mut self, token: u64) -> Result<MixedStep<N, I, O, M>, MixedError> {
+        self.inner.handle_timeout(token)
+    }
+
+    fn terminated(&self) -> bool {
+        self.inner.terminated()
+    }
+
+    fn our_id(&self) -> &N {
+        self.inner.our_id()
+    }
+}
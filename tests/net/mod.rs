@@ -6,52 +6,52 @@
 //! Networks are "cranked" to move things forward; each crank of a network causes one message to be
 //! delivered to a node.
 
-// We need to allow writes with newlines, resulting from `net_trace!` calls.
-#![cfg_attr(feature = "cargo-clippy", allow(write_with_newline))]
 // Almost all of our types are fairly readable, but trigger the type complexity checks, probably
 // due to associated types.
 #![cfg_attr(feature = "cargo-clippy", allow(type_complexity))]
 
 pub mod adversary;
 pub mod err;
+pub mod lossy;
+pub mod mixed;
 pub mod proptest;
+pub mod schedule;
+pub mod stats;
+pub mod trace;
 #[macro_use]
 pub mod util;
 
-use std::io::Write;
-use std::{cmp, collections, env, fmt, fs, io, ops, process, time};
+use std::sync::Arc;
+use std::{cmp, collections, env, fmt, fs, io, ops, process, thread, time};
 
+use bincode;
 use rand;
-use rand::{Rand, Rng};
-use threshold_crypto as crypto;
+use rand::{Rand, Rng, SeedableRng};
+use serde::Serialize;
 
 use hbbft::dynamic_honey_badger::Batch;
 use hbbft::util::SubRng;
-use hbbft::{self, Contribution, DistAlgorithm, NetworkInfo, NodeIdT, Step};
+use hbbft::{self, Contribution, DistAlgorithm, NetworkInfo, NetworkInfoError, NodeIdT, Step};
 
 pub use self::adversary::Adversary;
-pub use self::err::CrankError;
+pub use self::err::{BuildError, CrankError};
+pub use self::lossy::LossyNetwork;
+use self::proptest::{TestRng, TestRngSeed};
+pub use self::stats::{NetStats, QueueStats};
+pub use self::trace::{TraceEvent, TraceTarget};
+use self::trace::TracedMessage;
+pub use self::schedule::DeliverySchedule;
 
 /// The time limit for any network if none was specified.
 const DEFAULT_TIME_LIMIT: Option<time::Duration> = Some(time::Duration::from_secs(60 * 5));
 
-/// Helper macro for tracing.
-///
-/// If tracing is enabled (that is the `Option` is not `None`), writes out a traced packet.
-macro_rules! net_trace {
-    ($self:expr, $fmt:expr, $($arg:tt)*) => (
-        if let Some(ref mut dest) = $self.trace {
-            write!(dest, $fmt, $($arg)*).expect("could not write to test's trace")
-    });
-}
-
 /// Open trace file for writing.
 fn open_trace() -> Result<io::BufWriter<fs::File>, io::Error> {
     let mut rng = rand::thread_rng();
 
     let exec_path = env::current_exe()?;
     let name = format!(
-        "net-trace_{}_{}_{}.txt",
+        "net-trace_{}_{}_{}.trace",
         exec_path
             .file_name()
             .expect("could not get executable filename")
@@ -64,6 +64,37 @@ fn open_trace() -> Result<io::BufWriter<fs::File>, io::Error> {
     Ok(io::BufWriter::new(fs::File::create(name)?))
 }
 
+/// Returns a random seed for a `TestRng`.
+fn random_seed() -> TestRngSeed {
+    let mut rng = rand::thread_rng();
+    loop {
+        let seed: TestRngSeed = rng.gen();
+        // An all-zero seed is invalid for a `TestRng`.
+        if seed != [0; 4] {
+            return seed;
+        }
+    }
+}
+
+/// Parses a seed formatted by `format_seed`, i.e. as four comma-separated numbers.
+fn parse_seed(s: &str) -> Option<TestRngSeed> {
+    let mut seed = [0; 4];
+    let mut parts = s.split(',');
+    for word in &mut seed {
+        *word = parts.next()?.trim().parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(seed)
+}
+
+/// Formats a seed as four comma-separated numbers.
+fn format_seed(seed: &TestRngSeed) -> String {
+    let words: Vec<String> = seed.iter().map(u32::to_string).collect();
+    words.join(",")
+}
+
 /// A node in the test network.
 pub struct Node<D: DistAlgorithm> {
     /// Algorithm instance of node.
@@ -72,6 +103,10 @@ pub struct Node<D: DistAlgorithm> {
     is_faulty: bool,
     /// Captured algorithm outputs, in order.
     outputs: Vec<D::Output>,
+    /// Network info the node was constructed with, needed to restart it.
+    netinfo: NetworkInfo<D::NodeId>,
+    /// Whether the node has crashed and not been restarted yet.
+    is_dead: bool,
 }
 
 impl<D> fmt::Debug for Node<D>
@@ -83,6 +118,7 @@ where
             .field("algorithm", &"yes")
             .field("is_faulty", &self.is_faulty)
             .field("outputs", &self.outputs.len())
+            .field("is_dead", &self.is_dead)
             .finish()
     }
 }
@@ -90,11 +126,13 @@ where
 impl<D: DistAlgorithm> Node<D> {
     /// Create a new node.
     #[inline]
-    fn new(algorithm: D, is_faulty: bool) -> Self {
+    fn new(algorithm: D, is_faulty: bool, netinfo: NetworkInfo<D::NodeId>) -> Self {
         Node {
             algorithm,
             is_faulty,
             outputs: Vec::new(),
+            netinfo,
+            is_dead: false,
         }
     }
 
@@ -116,6 +154,12 @@ impl<D: DistAlgorithm> Node<D> {
         self.is_faulty
     }
 
+    /// Check whether or not node has crashed and not been restarted yet.
+    #[inline]
+    pub fn is_dead(&self) -> bool {
+        self.is_dead
+    }
+
     /// Get node's ID.
     ///
     /// A node's ID is equal to its underlying algorithm instance's ID.
@@ -124,6 +168,12 @@ impl<D: DistAlgorithm> Node<D> {
         self.algorithm.our_id()
     }
 
+    /// Get the node's network information.
+    #[inline]
+    pub fn netinfo(&self) -> &NetworkInfo<D::NodeId> {
+        &self.netinfo
+    }
+
     /// List outputs so far.
     ///
     /// Any output made by a node is captured by the node for easy comparison.
@@ -142,18 +192,43 @@ pub struct NetworkMessage<M, N> {
     from: N,
     /// Destined receiver.
     to: N,
-    /// The actual message contents.
-    payload: M,
+    /// The actual message contents. A message to several receivers shares a single copy of them.
+    payload: Arc<M>,
+    /// The virtual time at which the message is due, if the network has a `DeliverySchedule`.
+    due: u64,
 }
 
 impl<M, N> NetworkMessage<M, N> {
     /// Create a new network message.
     #[inline]
-    fn new(from: N, payload: M, to: N) -> NetworkMessage<M, N> {
-        NetworkMessage { from, to, payload }
+    fn new(from: N, payload: Arc<M>, to: N) -> NetworkMessage<M, N> {
+        NetworkMessage {
+            from,
+            to,
+            payload,
+            due: 0,
+        }
     }
+
+    /// Returns the virtual time at which the message is due.
+    #[inline]
+    pub fn due(&self) -> u64 {
+        self.due
+    }
+}
+
+/// What happens to messages addressed to a node while it is dead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadNodePolicy {
+    /// The messages are lost.
+    Drop,
+    /// The messages are held back, and delivered once the node is restarted.
+    Buffer,
 }
 
+/// A function returning the kind and serialized size of a message, for the statistics.
+type MeasureFn<M> = Box<dyn Fn(&M) -> (String, u64)>;
+
 /// Mapping from node IDs to actual node instances.
 pub type NodeMap<D> = collections::BTreeMap<<D as DistAlgorithm>::NodeId, Node<D>>;
 
@@ -164,8 +239,9 @@ pub type NetMessage<D> =
 /// Process a step.
 ///
 /// Expands every message in the step by turning all broadcast messages into peer-to-peer messages,
-/// and appends them to the network queue. Additionally, saves a copy of each output to the output
-/// buffer of the `sender` node.
+/// and appends them to the network queue. The peer-to-peer messages share the broadcast message's
+/// payload, which is only copied once it is delivered. Additionally, saves a copy of each output to
+/// the output buffer of the `sender` node.
 ///
 /// At the end, the number of additional messages created by non-faulty nodes is returned.
 ///
@@ -184,7 +260,7 @@ fn process_step<'a, D>(
 where
     D: DistAlgorithm + 'a,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     // For non-faulty nodes, we count the number of messages.
     let faulty = nodes
@@ -195,6 +271,7 @@ where
 
     // Queue all messages for processing.
     for tmsg in &step.messages {
+        let payload = Arc::new(tmsg.message.clone());
         match &tmsg.target {
             // Single target message.
             hbbft::Target::Node(to) => {
@@ -204,7 +281,7 @@ where
 
                 dest.push_back(NetworkMessage::new(
                     sender.clone(),
-                    tmsg.message.clone(),
+                    payload.clone(),
                     to.clone(),
                 ));
             }
@@ -216,10 +293,37 @@ where
 
                 dest.push_back(NetworkMessage::new(
                     sender.clone(),
-                    tmsg.message.clone(),
+                    payload.clone(),
+                    to.clone(),
+                ));
+            },
+            hbbft::Target::AllExcept(exclude) => {
+                for to in nodes.keys().filter(|&to| to != &sender && !exclude.contains(to)) {
+                    if !faulty {
+                        message_count = message_count.saturating_add(1);
+                    }
+
+                    dest.push_back(NetworkMessage::new(
+                        sender.clone(),
+                        payload.clone(),
+                        to.clone(),
+                    ));
+                }
+            }
+            // Multicast messages get expanded into multiple direct messages, too.
+            hbbft::Target::Nodes(ids) => for to in ids.iter().filter(|&to| to != &sender) {
+                if !faulty {
+                    message_count = message_count.saturating_add(1);
+                }
+
+                dest.push_back(NetworkMessage::new(
+                    sender.clone(),
+                    payload.clone(),
                     to.clone(),
                 ));
             },
+            // The virtual network consists of validators only.
+            hbbft::Target::Observers => (),
         }
     }
 
@@ -276,7 +380,7 @@ where
 /// configure the construction process.
 ///
 /// Note that, in addition to the constructor `new`, either `using` or `using_step` must be called,
-/// otherwise the construction will fail and panic.
+/// otherwise `build` returns `BuildError::NoConstructor`.
 pub struct NetBuilder<D, I>
 where
     D: DistAlgorithm,
@@ -285,20 +389,36 @@ where
     node_ids: I,
     /// Number of faulty nodes in the network.
     num_faulty: usize,
+    /// Optional weights of the validators.
+    weights: Option<collections::BTreeMap<D::NodeId, u64>>,
     /// Dist-algorithm constructor function.
     cons: Option<Box<Fn(NewNodeInfo<D>) -> (D, Step<D>)>>,
     /// Network adversary.
     adversary: Option<Box<dyn Adversary<D>>>,
-    /// Trace-enabling flag. `None` means use environment.
-    trace: Option<bool>,
+    /// Whether and where to write the trace. `None` means use environment.
+    trace: Option<TraceTarget>,
+    /// Optional destination of the trace, overriding the trace file.
+    trace_to: Option<Box<dyn io::Write>>,
     /// Optional crank limit.
     crank_limit: Option<usize>,
     /// Optional message limit.
     message_limit: Option<usize>,
+    /// Optional limit of the number of queued messages.
+    queue_limit: Option<usize>,
     /// Optional time limit.
     time_limit: Option<time::Duration>,
     /// Random number generator used to generate keys.
     rng: Option<Box<dyn Rng>>,
+    /// Seed of the random number generator, if it was not passed in directly.
+    seed: Option<TestRngSeed>,
+    /// What happens to messages addressed to dead nodes.
+    dead_node_policy: DeadNodePolicy,
+    /// Optional schedule determining the messages' latencies.
+    schedule: Option<Box<dyn DeliverySchedule<D::NodeId>>>,
+    /// Optional random message loss and duplication.
+    lossy: Option<LossyNetwork>,
+    /// Optional function measuring messages for the statistics.
+    measure: Option<MeasureFn<D::Message>>,
 }
 
 impl<D, I> fmt::Debug for NetBuilder<D, I>
@@ -309,13 +429,21 @@ where
         f.debug_struct("NetBuilder")
             .field("node_ids", &())
             .field("num_faulty", &self.num_faulty)
+            .field("weights", &self.weights)
             .field("cons", &self.cons.is_some())
             .field("adversary", &self.cons.is_some())
             .field("trace", &self.trace)
+            .field("trace_to", &self.trace_to.is_some())
             .field("crank_limit", &self.crank_limit)
             .field("message_limit", &self.message_limit)
+            .field("queue_limit", &self.queue_limit)
             .field("time_limit", &self.time_limit)
             .field("rng", &"<RNG>")
+            .field("seed", &self.seed)
+            .field("dead_node_policy", &self.dead_node_policy)
+            .field("schedule", &self.schedule.is_some())
+            .field("lossy", &self.lossy)
+            .field("measure", &self.measure.is_some())
             .finish()
     }
 }
@@ -324,7 +452,7 @@ impl<D, I> NetBuilder<D, I>
 where
     D: DistAlgorithm,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
     I: IntoIterator<Item = D::NodeId>,
 {
     /// Construct a new network builder.
@@ -340,13 +468,21 @@ where
         NetBuilder {
             node_ids,
             num_faulty: 0,
+            weights: None,
             cons: None,
             adversary: None,
             trace: None,
+            trace_to: None,
             crank_limit: None,
             message_limit: None,
+            queue_limit: None,
             time_limit: DEFAULT_TIME_LIMIT,
             rng: None,
+            seed: None,
+            dead_node_policy: DeadNodePolicy::Drop,
+            schedule: None,
+            lossy: None,
+            measure: None,
         }
     }
 
@@ -384,6 +520,18 @@ where
         self
     }
 
+    /// Queued message limit.
+    ///
+    /// Limit the number of messages that are queued at the same time. If more messages are
+    /// waiting to be delivered, the `crank` function will return a
+    /// `CrankError::QueueLimitExceeded`. Unlike the message limit, this doesn't depend on how
+    /// long the test runs, and catches nodes that flood the network.
+    #[inline]
+    pub fn queue_limit(mut self, queue_limit: usize) -> Self {
+        self.queue_limit = Some(queue_limit);
+        self
+    }
+
     /// Remove the time limit.
     ///
     /// Removes any time limit from the builder.
@@ -402,12 +550,23 @@ where
         self
     }
 
+    /// Validator weights.
+    ///
+    /// If set, the nodes' `NetworkInfo`s are generated with these weights, which must contain
+    /// exactly the node ids.
+    #[inline]
+    pub fn weights(mut self, weights: collections::BTreeMap<D::NodeId, u64>) -> Self {
+        self.weights = Some(weights);
+        self
+    }
+
     /// Random number generator.
     ///
-    /// Overrides the random number generator used. If not specified, a `thread_rng` will be
-    /// used on construction.
+    /// Overrides the random number generator used. If not specified, a `TestRng` is created from
+    /// the `seed`.
     ///
-    /// The passed in generator is used for key generation.
+    /// The passed in generator is used for key generation, for the nodes' random number generators
+    /// and for all random decisions made by the network itself, e.g. by a `LossyNetwork`.
     pub fn rng<R>(mut self, rng: R) -> Self
     where
         R: Rng + 'static,
@@ -416,6 +575,18 @@ where
         self
     }
 
+    /// Random seed.
+    ///
+    /// Sets the seed of the network's random number generator, unless `rng` is specified. If
+    /// neither is, the seed is read from the `HBBFT_TEST_SEED` environment variable or, if that is
+    /// not set either, chosen randomly. It is printed if the test panics, so that failed runs can
+    /// be replayed exactly.
+    #[inline]
+    pub fn seed(mut self, seed: TestRngSeed) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
     /// Time limit.
     ///
     /// Sets the time limit; `crank` will fail if called after this much time as elapsed since
@@ -426,12 +597,64 @@ where
         self
     }
 
+    /// Dead node policy.
+    ///
+    /// Determines what happens to messages addressed to nodes that have been killed using
+    /// `VirtualNet::kill_node`. By default, they are dropped.
+    #[inline]
+    pub fn dead_node_policy(mut self, dead_node_policy: DeadNodePolicy) -> Self {
+        self.dead_node_policy = dead_node_policy;
+        self
+    }
+
+    /// Delivery schedule.
+    ///
+    /// If set, every message is assigned a latency when it is sent, and cranking delivers the
+    /// message that is due earliest. Otherwise, messages are delivered in queue order.
+    #[inline]
+    pub fn delivery_schedule<S>(mut self, schedule: S) -> Self
+    where
+        S: DeliverySchedule<D::NodeId> + 'static,
+    {
+        self.schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// Lossy network.
+    ///
+    /// If set, messages are randomly dropped or duplicated, violating the assumption that every
+    /// message is delivered exactly once.
+    #[inline]
+    pub fn lossy(mut self, lossy: LossyNetwork) -> Self {
+        self.lossy = Some(lossy);
+        self
+    }
+
     /// Override tracing.
     ///
     /// If set, overrides the environment setting of whether or not tracing should be enabled.
+    /// Accepts a `bool`, to write to a new trace file or not at all, or a path to write the trace
+    /// to, e.g.:
+    ///
+    /// ```rust,ignore
+    /// let builder = NetBuilder::new(0..10).trace("ba.trace") // ...
+    /// ```
     #[inline]
-    pub fn trace(mut self, trace: bool) -> Self {
-        self.trace = Some(trace);
+    pub fn trace<T: Into<TraceTarget>>(mut self, trace: T) -> Self {
+        self.trace = Some(trace.into());
+        self
+    }
+
+    /// Trace destination.
+    ///
+    /// If set, the trace is written to `dest` instead of a new trace file, regardless of the
+    /// `trace` setting.
+    #[inline]
+    pub fn trace_to<W>(mut self, dest: W) -> Self
+    where
+        W: io::Write + 'static,
+    {
+        self.trace_to = Some(Box::new(dest));
         self
     }
 
@@ -464,19 +687,40 @@ where
     ///
     /// Finalizes the builder and creates the network.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// If the total number of nodes is not `> 3 * num_faulty`, construction will panic.
+    /// Fails if the total number of nodes is not `> 3 * num_faulty`, if no constructor function
+    /// was specified, if an `HBBFT_*` environment variable is invalid or if the trace file cannot
+    /// be created.
     #[inline]
-    pub fn build(self) -> Result<VirtualNet<D>, crypto::error::Error> {
-        let rng: Box<dyn Rng> = self.rng.unwrap_or_else(|| Box::new(rand::thread_rng()));
+    pub fn build(self) -> Result<VirtualNet<D>, BuildError> {
+        // Unless a generator was passed in, we create one from a seed that we can report later.
+        let (rng, seed): (Box<dyn Rng>, _) = match self.rng {
+            Some(rng) => (rng, None),
+            None => {
+                let seed = match self.seed {
+                    Some(seed) => seed,
+                    None => match env::var("HBBFT_TEST_SEED") {
+                        Ok(value) => parse_seed(&value).ok_or_else(|| BuildError::InvalidEnvVar {
+                            name: "HBBFT_TEST_SEED",
+                            value,
+                        })?,
+                        Err(_) => random_seed(),
+                    },
+                };
+                (Box::new(TestRng::from_seed(seed)), Some(seed))
+            }
+        };
 
-        // The time limit can be overriden through environment variables:
-        let override_time_limit = env::var("HBBFT_NO_TIME_LIMIT")
-            // We fail early, to avoid tricking the user into thinking that they have set the time
-            // limit when they haven't.
-            .map(|s| s.parse().expect("could not parse `HBBFT_NO_TIME_LIMIT`"))
-            .unwrap_or(false);
+        // The time limit can be overriden through environment variables. We fail early, to avoid
+        // tricking the user into thinking that they have set the time limit when they haven't.
+        let override_time_limit = match env::var("HBBFT_NO_TIME_LIMIT") {
+            Ok(value) => value.parse().map_err(|_| BuildError::InvalidEnvVar {
+                name: "HBBFT_NO_TIME_LIMIT",
+                value,
+            })?,
+            Err(_) => false,
+        };
 
         let time_limit = if override_time_limit {
             eprintln!("WARNING: The time limit for individual tests has been manually disabled through `HBBFT_NO_TIME_LIMIT`.");
@@ -485,16 +729,17 @@ where
             self.time_limit
         };
 
-        let cons = self
-            .cons
-            .as_ref()
-            .expect("cannot build network without a constructor function for the nodes");
+        let cons = self.cons.as_ref().ok_or(BuildError::NoConstructor)?;
 
         // Note: Closure is not redundant, won't compile without it.
         #[cfg_attr(feature = "cargo-clippy", allow(redundant_closure))]
-        let mut net = VirtualNet::new(self.node_ids, self.num_faulty as usize, rng, move |node| {
-            cons(node)
-        })?;
+        let mut net = VirtualNet::new(
+            self.node_ids,
+            self.num_faulty as usize,
+            self.weights,
+            rng,
+            move |node| cons(node),
+        )?;
 
         if self.adversary.is_some() {
             net.adversary = self.adversary;
@@ -503,21 +748,101 @@ where
         let trace = self.trace.unwrap_or_else(|| {
             // If the trace setting is not overriden, we use the setting from the environment.
             let setting = env::var("HBBFT_TEST_TRACE").unwrap_or_else(|_| "true".to_string());
-            !(setting == "false" || setting == "0")
+            TraceTarget::from(!(setting == "false" || setting == "0"))
         });
 
-        if trace {
-            net.trace = Some(open_trace().expect("could not open trace file"));
+        if let Some(dest) = self.trace_to {
+            net.trace = Some(dest);
+        } else {
+            match trace {
+                TraceTarget::Off => (),
+                TraceTarget::DefaultFile => {
+                    net.trace = Some(Box::new(open_trace().map_err(BuildError::Trace)?));
+                }
+                TraceTarget::File(path) => {
+                    let file = fs::File::create(path).map_err(BuildError::Trace)?;
+                    net.trace = Some(Box::new(io::BufWriter::new(file)));
+                }
+            }
         }
 
         net.crank_limit = self.crank_limit;
         net.message_limit = self.message_limit;
+        net.queue_limit = self.queue_limit;
         net.time_limit = time_limit;
+        net.dead_node_policy = self.dead_node_policy;
+        net.schedule = self.schedule;
+        net.lossy = self.lossy;
+        net.seed = seed;
+        net.measure = self.measure;
 
         Ok(net)
     }
 }
 
+impl<D> NetBuilder<D, ops::Range<usize>>
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    /// Number of nodes.
+    ///
+    /// Sets the node ids to `0..num_nodes`, e.g.:
+    ///
+    /// ```rust,ignore
+    /// let builder = NetBuilder::default().num_nodes(10) // ...
+    /// ```
+    #[inline]
+    pub fn num_nodes(mut self, num_nodes: usize) -> Self {
+        self.node_ids = 0..num_nodes;
+        self
+    }
+}
+
+impl<D> Default for NetBuilder<D, ops::Range<usize>>
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone,
+    D::Output: Clone + fmt::Debug,
+{
+    /// Creates a builder for a network without any nodes; see `num_nodes`.
+    fn default() -> Self {
+        NetBuilder::new(0..0)
+    }
+}
+
+impl<D, I> NetBuilder<D, I>
+where
+    D: DistAlgorithm,
+    D::Message: Serialize,
+{
+    /// Message statistics.
+    ///
+    /// If set, the network counts all messages by sender, receiver and kind, together with their
+    /// serialized size; see `VirtualNet::stats`. The kind of a message is determined by
+    /// `stats::message_kind`.
+    #[inline]
+    pub fn stats(self) -> Self {
+        self.stats_with(stats::message_kind)
+    }
+
+    /// Message statistics with custom kinds.
+    ///
+    /// Like `stats`, but uses `kind` to determine the kind of a message.
+    #[inline]
+    pub fn stats_with<F>(mut self, kind: F) -> Self
+    where
+        F: Fn(&D::Message) -> String + 'static,
+    {
+        self.measure = Some(Box::new(move |msg| {
+            let size = bincode::serialized_size(msg).expect("could not serialize message");
+            (kind(msg), size)
+        }));
+        self
+    }
+}
+
 /// Virtual network instance.
 pub struct VirtualNet<D>
 where
@@ -532,8 +857,8 @@ where
     /// during execution, allowing a `&mut self` to be passed to the adversary without running afoul
     /// of the borrow checker.
     adversary: Option<Box<dyn Adversary<D>>>,
-    /// Trace output; if active, writes out a log of all messages.
-    trace: Option<io::BufWriter<fs::File>>,
+    /// Trace output; if active, writes out a `TraceEvent` for every network event.
+    trace: Option<Box<dyn io::Write>>,
     /// The number of times the network has been cranked.
     crank_count: usize,
     /// The limit set for cranking the network.
@@ -542,10 +867,34 @@ where
     message_count: usize,
     /// The limit set for the number of messages.
     message_limit: Option<usize>,
+    /// The limit set for the number of queued messages.
+    queue_limit: Option<usize>,
     /// Limits the maximum running time between construction and last call to `crank()`.
     time_limit: Option<time::Duration>,
     /// The instant the network was created.
     start_time: time::Instant,
+    /// What happens to messages addressed to dead nodes.
+    dead_node_policy: DeadNodePolicy,
+    /// Messages held back for dead nodes, to be delivered once they are restarted.
+    held_messages: collections::VecDeque<NetMessage<D>>,
+    /// Optional schedule determining the messages' latencies.
+    schedule: Option<Box<dyn DeliverySchedule<D::NodeId>>>,
+    /// The current virtual time: the time at which the last delivered message was due.
+    time: u64,
+    /// If the network is partitioned, the index of each node's group.
+    partition: Option<collections::BTreeMap<D::NodeId, usize>>,
+    /// Messages held back because they would cross the partition.
+    partitioned_messages: collections::VecDeque<NetMessage<D>>,
+    /// Optional random message loss and duplication.
+    lossy: Option<LossyNetwork>,
+    /// Optional function measuring messages for the statistics.
+    measure: Option<MeasureFn<D::Message>>,
+    /// Message statistics, if enabled.
+    stats: NetStats<D::NodeId>,
+    /// Random number generator used for all random decisions made by the network.
+    rng: Box<dyn Rng>,
+    /// The seed of `rng`, if known.
+    seed: Option<TestRngSeed>,
 }
 
 impl<D> fmt::Debug for VirtualNet<D>
@@ -562,10 +911,40 @@ where
             .field("crank_limit", &self.crank_limit)
             .field("message_count", &self.message_count)
             .field("message_limit", &self.message_limit)
+            .field("queue_limit", &self.queue_limit)
+            .field("dead_node_policy", &self.dead_node_policy)
+            .field("held_messages", &self.held_messages.len())
+            .field("schedule", &self.schedule.is_some())
+            .field("time", &self.time)
+            .field("partition", &self.partition)
+            .field("partitioned_messages", &self.partitioned_messages.len())
+            .field("lossy", &self.lossy)
+            .field("measure", &self.measure.is_some())
+            .field("stats", &self.stats)
+            .field("rng", &"<RNG>")
+            .field("seed", &self.seed)
             .finish()
     }
 }
 
+impl<D> Drop for VirtualNet<D>
+where
+    D: DistAlgorithm,
+{
+    fn drop(&mut self) {
+        // If a test fails, the seed allows replaying it exactly.
+        if let Some(seed) = self.seed {
+            if thread::panicking() {
+                eprintln!(
+                    "VirtualNet seed: {:?}; set `HBBFT_TEST_SEED={}` to replay.",
+                    seed,
+                    format_seed(&seed)
+                );
+            }
+        }
+    }
+}
+
 /// A virtual network
 ///
 /// Virtual networks host a number of nodes that are marked either correct or faulty. Each time a
@@ -632,6 +1011,29 @@ where
         self.nodes.get_mut(&id)
     }
 
+    /// Returns the current virtual time.
+    ///
+    /// This is always `0` unless the network has a `DeliverySchedule`.
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Returns a summary of the messages that are currently queued for delivery.
+    #[inline]
+    pub fn queue_stats(&self) -> QueueStats<D::NodeId> {
+        let queue = self.messages.iter().map(|msg| (&msg.from, &*msg.payload));
+        QueueStats::new(self.crank_count, self.message_count, queue)
+    }
+
+    /// Returns the message statistics.
+    ///
+    /// These are empty unless statistics were enabled using `NetBuilder::stats`.
+    #[inline]
+    pub fn stats(&self) -> &NetStats<D::NodeId> {
+        &self.stats
+    }
+
     /// Returns an iterator over all messages currently queued.
     #[inline]
     pub fn messages(&self) -> impl Iterator<Item = &NetMessage<D>> {
@@ -671,13 +1073,112 @@ where
         msgs.sort_by(f);
         self.messages.extend(msgs.into_iter());
     }
+
+    /// Partition the network.
+    ///
+    /// Splits the nodes into the given groups. Messages between nodes in different groups are held
+    /// back until the partition is healed. Nodes that are not in any group are isolated from all
+    /// others. Replaces any previous partition.
+    pub fn partition<G, I>(&mut self, groups: G)
+    where
+        G: IntoIterator<Item = I>,
+        I: IntoIterator<Item = D::NodeId>,
+    {
+        let partition = groups
+            .into_iter()
+            .enumerate()
+            .flat_map(|(idx, group)| group.into_iter().map(move |id| (id, idx)))
+            .collect();
+        self.partition = Some(partition);
+    }
+
+    /// Heal the partition.
+    ///
+    /// All nodes can communicate again, and the messages that were held back are queued for
+    /// delivery.
+    pub fn heal(&mut self) {
+        self.partition = None;
+        let held = self.partitioned_messages.drain(..);
+        self.messages.extend(held);
+    }
+
+    /// Returns `true` if messages from `from` can currently reach `to`.
+    pub fn is_connected(&self, from: &D::NodeId, to: &D::NodeId) -> bool {
+        match self.partition {
+            None => true,
+            Some(ref partition) => {
+                from == to || match (partition.get(from), partition.get(to)) {
+                    (Some(from_idx), Some(to_idx)) => from_idx == to_idx,
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /// Assigns due times to all queued messages from index `start` on, if there is a schedule,
+    /// and traces and counts them as sent.
+    fn schedule_messages(&mut self, start: usize) {
+        if let Some(ref mut schedule) = self.schedule {
+            let time = self.time;
+            for msg in self.messages.iter_mut().skip(start) {
+                let latency = schedule.latency(&msg.from, &msg.to, &mut *self.rng);
+                msg.due = time.saturating_add(latency);
+            }
+        }
+        if let Some(ref mut dest) = self.trace {
+            for msg in self.messages.iter().skip(start) {
+                let event = TraceEvent::Send(TracedMessage::new(msg));
+                trace::write_event(&mut *dest, &event).expect("could not write to test's trace");
+            }
+        }
+        if let Some(ref measure) = self.measure {
+            for msg in self.messages.iter().skip(start) {
+                let (kind, size) = measure(&*msg.payload);
+                self.stats.record(&msg.from, &msg.to, kind, size);
+            }
+        }
+    }
+
+    /// Writes an event concerning `msg` to the trace, if tracing is enabled.
+    fn trace_message<F>(&mut self, event: F, msg: &NetMessage<D>)
+    where
+        F: FnOnce(TracedMessage) -> TraceEvent,
+    {
+        if let Some(ref mut dest) = self.trace {
+            trace::write_event(dest, &event(TracedMessage::new(msg)))
+                .expect("could not write to test's trace");
+        }
+    }
+
+    /// Removes the first queued message matching the recorded one, if any.
+    fn take_traced_message(&mut self, traced: &TracedMessage) -> Option<NetMessage<D>> {
+        let idx = self.messages.iter().position(|msg| traced.matches(msg))?;
+        self.messages.remove(idx)
+    }
+
+    /// Removes the next message to be delivered from the queue: the first one, or, if there is a
+    /// schedule, the first one of those that are due earliest. Advances the virtual time.
+    fn pop_next_message(&mut self) -> Option<NetMessage<D>> {
+        if self.schedule.is_none() {
+            return self.messages.pop_front();
+        }
+        let idx = self
+            .messages
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, msg)| msg.due)
+            .map(|(idx, _)| idx)?;
+        let msg = self.messages.remove(idx)?;
+        self.time = cmp::max(self.time, msg.due);
+        Some(msg)
+    }
 }
 
 impl<D> VirtualNet<D>
 where
     D: DistAlgorithm,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     /// Create new virtual network with step constructor.
     ///
@@ -689,28 +1190,39 @@ where
     ///
     /// This function is not used directly, instead the `NetBuilder` should be used.
     ///
-    /// # Panics
+    /// # Errors
     ///
     /// The total number of nodes, that is `node_ids.count()` must be `> 3 * faulty`, otherwise
-    /// the construction function will panic.
-    fn new<F, I, R>(
+    /// `BuildError::TooManyFaulty` is returned.
+    fn new<F, I>(
         node_ids: I,
         faulty: usize,
-        mut rng: R,
+        weights: Option<collections::BTreeMap<D::NodeId, u64>>,
+        mut rng: Box<dyn Rng>,
         cons: F,
-    ) -> Result<Self, crypto::error::Error>
+    ) -> Result<Self, BuildError>
     where
         F: Fn(NewNodeInfo<D>) -> (D, Step<D>),
         I: IntoIterator<Item = D::NodeId>,
-        R: rand::Rng,
     {
         // Generate a new set of cryptographic keys for threshold cryptography.
-        let net_infos = NetworkInfo::generate_map(node_ids, &mut rng)?;
+        let net_infos = match weights {
+            None => NetworkInfo::generate_map(node_ids, &mut rng)?,
+            Some(weights) => {
+                let ids: collections::BTreeSet<_> = node_ids.into_iter().collect();
+                if !weights.keys().eq(ids.iter()) {
+                    return Err(NetworkInfoError::InvalidWeights.into());
+                }
+                NetworkInfo::generate_weighted_map(weights, &mut rng)?
+            }
+        };
 
-        assert!(
-            faulty * 3 < net_infos.len(),
-            "Too many faulty nodes requested, `f` must satisfy `3f < total_nodes`."
-        );
+        if faulty * 3 >= net_infos.len() {
+            return Err(BuildError::TooManyFaulty {
+                num_nodes: net_infos.len(),
+                num_faulty: faulty,
+            });
+        }
 
         let mut steps = collections::BTreeMap::new();
         let mut messages = collections::VecDeque::new();
@@ -723,12 +1235,12 @@ where
 
                 let (algorithm, step) = cons(NewNodeInfo {
                     id: id.clone(),
-                    netinfo,
+                    netinfo: netinfo.clone(),
                     faulty: is_faulty,
                     rng: rng.sub_rng(),
                 });
                 steps.insert(id.clone(), step);
-                (id, Node::new(algorithm, is_faulty))
+                (id, Node::new(algorithm, is_faulty, netinfo))
             }).collect();
 
         let mut message_count: usize = 0;
@@ -751,8 +1263,20 @@ where
             crank_limit: None,
             message_count,
             message_limit: None,
+            queue_limit: None,
             time_limit: None,
             start_time: time::Instant::now(),
+            dead_node_policy: DeadNodePolicy::Drop,
+            held_messages: collections::VecDeque::new(),
+            schedule: None,
+            time: 0,
+            partition: None,
+            partitioned_messages: collections::VecDeque::new(),
+            lossy: None,
+            measure: None,
+            stats: NetStats::default(),
+            rng,
+            seed: None,
         })
     }
 
@@ -766,14 +1290,11 @@ where
             .get_mut(&msg.to)
             .ok_or_else(|| CrankError::NodeDisappeared(msg.to.clone()))?;
 
-        // Store a copy of the message, in case we need to pass it to the error variant.
-        // By reducing the information in `CrankError::AlgorithmError`, we could reduce overhead
-        // here if necessary.
-        let msg_copy = msg.clone();
-        let step = node
-            .algorithm
-            .handle_message(&msg.from, msg.payload)
-            .map_err(move |err| CrankError::AlgorithmError { msg: msg_copy, err })?;
+        // The payload may be shared with other receivers, so the node handles its own copy. The
+        // message is kept, in case we need to pass it to the error variant.
+        let payload = (*msg.payload).clone();
+        let result = node.algorithm.handle_message(&msg.from, payload);
+        let step = result.map_err(move |err| CrankError::AlgorithmError { msg, err })?;
 
         Ok(step)
     }
@@ -795,12 +1316,15 @@ where
             .algorithm
             .handle_input(input)?;
 
+        let start = self.messages.len();
         self.message_count = self.message_count.saturating_add(process_step(
             &mut self.nodes,
-            id,
+            id.clone(),
             &step,
             &mut self.messages,
         ));
+        self.schedule_messages(start);
+        self.trace_outputs(&id, &step);
 
         Ok(step)
     }
@@ -817,13 +1341,22 @@ where
         // Check limits.
         if let Some(limit) = self.crank_limit {
             if self.crank_count >= limit {
-                return Some(Err(CrankError::CrankLimitExceeded(limit)));
+                let queue = self.queue_stats();
+                return Some(Err(CrankError::CrankLimitExceeded { limit, queue }));
             }
         }
 
         if let Some(limit) = self.message_limit {
             if self.message_count >= limit {
-                return Some(Err(CrankError::MessageLimitExceeded(limit)));
+                let queue = self.queue_stats();
+                return Some(Err(CrankError::MessageLimitExceeded { limit, queue }));
+            }
+        }
+
+        if let Some(limit) = self.queue_limit {
+            if self.messages.len() > limit {
+                let queue = self.queue_stats();
+                return Some(Err(CrankError::QueueLimitExceeded { limit, queue }));
             }
         }
 
@@ -844,59 +1377,244 @@ where
         self.adversary = adv;
 
         // Step 1: Pick a message from the queue and deliver it; returns `None` if queue is empty.
-        let msg = self.messages.pop_front()?;
-
-        net_trace!(
-            self,
-            "[{:?}] -> [{:?}]: {:?}\n",
-            msg.from,
-            msg.to,
-            msg.payload
-        );
+        let msg = self.pop_next_message()?;
         let receiver = msg.to.clone();
 
-        // Unfortunately, we have to re-borrow the target node further down to make the borrow
-        // checker happy. First, we check if the receiving node is faulty, so we can dispatch
-        // through the adversary if it is.
-        let is_faulty = try_some!(
+        // Messages across the partition are held back until it heals.
+        if !self.is_connected(&msg.from, &msg.to) {
+            self.trace_message(TraceEvent::Hold, &msg);
+            self.partitioned_messages.push_back(msg);
+            self.crank_count += 1;
+            return Some(Ok((receiver, Step::default())));
+        }
+
+        // A lossy network may drop the message, or queue it a second time.
+        if let Some(lossy) = self.lossy {
+            if lossy.drop_message(&mut *self.rng) {
+                self.trace_message(TraceEvent::Drop, &msg);
+                self.crank_count += 1;
+                return Some(Ok((receiver, Step::default())));
+            }
+            if lossy.duplicate_message(&mut *self.rng) {
+                self.trace_message(TraceEvent::Duplicate, &msg);
+                self.messages.push_back(msg.clone());
+            }
+        }
+
+        let is_dead = try_some!(
             self.nodes
                 .get(&msg.to)
+                .map(Node::is_dead)
                 .ok_or_else(|| CrankError::NodeDisappeared(msg.to.clone()))
-        ).is_faulty();
+        );
+
+        // A dead node doesn't handle the message: it is either lost or held back.
+        if is_dead {
+            if self.dead_node_policy == DeadNodePolicy::Buffer {
+                self.trace_message(TraceEvent::Hold, &msg);
+                self.held_messages.push_back(msg);
+            } else {
+                self.trace_message(TraceEvent::Drop, &msg);
+            }
+            self.crank_count += 1;
+            return Some(Ok((receiver, Step::default())));
+        }
+
+        let step = try_some!(self.deliver(msg));
+
+        // Increase the crank count.
+        self.crank_count += 1;
+
+        Some(Ok((receiver, step)))
+    }
+
+    /// Delivers a message to its receiver.
+    ///
+    /// A correct receiver handles the message, while a message to a faulty receiver is handed to
+    /// the adversary. All messages of the resulting step are queued, and the step is returned.
+    fn deliver(&mut self, msg: NetMessage<D>) -> Result<Step<D>, CrankError<D>> {
+        let receiver = msg.to.clone();
+        let is_faulty = self
+            .nodes
+            .get(&receiver)
+            .map(Node::is_faulty)
+            .ok_or_else(|| CrankError::NodeDisappeared(receiver.clone()))?;
 
         let step: Step<_> = if is_faulty {
+            self.trace_message(TraceEvent::Tamper, &msg);
+
             // The swap-dance is painful here, as we are creating an `opt_step` just to avoid
             // borrow issues.
             let mut adv = self.adversary.take();
             let opt_tamper_result = adv.as_mut().map(|adversary| {
                 // If an adversary was set, we let it affect the network now.
+                let msg = adversary::FaultyMessage::new(msg);
                 adversary.tamper(adversary::NetMutHandle::new(self), msg)
             });
             self.adversary = adv;
 
             // A missing adversary here could technically be a panic, but is impossible since we
             // initialize with a `NullAdversary` upon construction.
-            try_some!(
-                opt_tamper_result.expect("No adversary defined (expected at least NullAdversary)")
-            )
+            opt_tamper_result.expect("No adversary defined (expected at least NullAdversary)")?
         } else {
             // A correct node simply handles the message.
-            try_some!(self.dispatch_message(msg))
+            self.trace_message(TraceEvent::Deliver, &msg);
+            self.dispatch_message(msg)?
         };
 
         // All messages are expanded and added to the queue. We opt for copying them, so we can
         // return unaltered step later on for inspection.
+        let start = self.messages.len();
         self.message_count = self.message_count.saturating_add(process_step(
             &mut self.nodes,
             receiver.clone(),
             &step,
             &mut self.messages,
         ));
+        self.schedule_messages(start);
+        self.trace_outputs(&receiver, &step);
 
-        // Increase the crank count.
-        self.crank_count += 1;
+        Ok(step)
+    }
 
-        Some(Ok((receiver, step)))
+    /// Writes the outputs in a node's step to the trace, if tracing is enabled.
+    fn trace_outputs(&mut self, id: &D::NodeId, step: &Step<D>) {
+        if let Some(ref mut dest) = self.trace {
+            for output in &step.output {
+                let event = TraceEvent::Output {
+                    node: format!("{:?}", id),
+                    output: format!("{:?}", output),
+                };
+                trace::write_event(&mut *dest, &event).expect("could not write to test's trace");
+            }
+        }
+    }
+
+    /// Replays a recorded trace.
+    ///
+    /// Re-executes the events in `trace` on this network, which is expected to be freshly
+    /// constructed in the same way as the recorded one, i.e. with the same seed, constructor and
+    /// adversary, and with the same inputs already sent. Recorded deliveries are repeated in the
+    /// same order, regardless of partitions, dead nodes and lossy networks, and the adversary's
+    /// `pre_crank` hook is not called. Recorded drops and duplications are repeated, too. Returns
+    /// the resulting steps.
+    ///
+    /// Messages are identified by their `Debug` representation, so replaying is only reliable if
+    /// that is unique among the queued ones. Messages injected by the adversary and node restarts
+    /// are not recorded, so traces that contain them can generally not be replayed.
+    ///
+    /// Returns a `ReplayMismatch` error if a recorded message is not queued.
+    pub fn replay<'a, I>(&mut self, trace: I) -> Result<Vec<(D::NodeId, Step<D>)>, CrankError<D>>
+    where
+        I: IntoIterator<Item = &'a TraceEvent>,
+    {
+        let mut steps = Vec::new();
+        for event in trace {
+            match *event {
+                TraceEvent::Deliver(ref traced) | TraceEvent::Tamper(ref traced) => {
+                    let msg = self
+                        .take_traced_message(traced)
+                        .ok_or_else(|| CrankError::ReplayMismatch(event.clone()))?;
+                    let receiver = msg.to.clone();
+                    let step = self.deliver(msg)?;
+                    self.crank_count += 1;
+                    steps.push((receiver, step));
+                }
+                TraceEvent::Drop(ref traced) => {
+                    let msg = self
+                        .take_traced_message(traced)
+                        .ok_or_else(|| CrankError::ReplayMismatch(event.clone()))?;
+                    self.trace_message(TraceEvent::Drop, &msg);
+                    self.crank_count += 1;
+                }
+                TraceEvent::Duplicate(ref traced) => {
+                    let msg = self
+                        .messages
+                        .iter()
+                        .find(|msg| traced.matches(*msg))
+                        .cloned()
+                        .ok_or_else(|| CrankError::ReplayMismatch(event.clone()))?;
+                    self.trace_message(TraceEvent::Duplicate, &msg);
+                    self.messages.push_back(msg);
+                }
+                // Messages that were held back stay in the queue until they are delivered.
+                TraceEvent::Send(_) | TraceEvent::Hold(_) | TraceEvent::Output { .. } => (),
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Kill a node.
+    ///
+    /// The node stops handling messages until it is restarted: messages addressed to it are
+    /// dropped or held back, depending on the `DeadNodePolicy`. Its outputs so far are kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` does not name a valid node.
+    #[inline]
+    #[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+    pub fn kill_node(&mut self, id: D::NodeId) {
+        self.nodes
+            .get_mut(&id)
+            .expect("cannot kill non-existing node")
+            .is_dead = true;
+    }
+
+    /// Restart a dead node.
+    ///
+    /// The node's algorithm instance, i.e. all of its volatile state, is replaced by a new one
+    /// constructed by `cons`. It is passed the same information as on network construction, with
+    /// a fresh random number generator, and can e.g. restore the algorithm from a snapshot taken
+    /// before the node was killed. The messages of the resulting step are queued, followed by any
+    /// messages that were held back while the node was dead. The step is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` does not name a valid node, or if the node is not dead.
+    pub fn restart_node<F>(&mut self, id: D::NodeId, cons: F) -> Step<D>
+    where
+        F: FnOnce(NewNodeInfo<D>) -> (D, Step<D>),
+    {
+        let rng = self.rng.sub_rng();
+        let (algorithm, step) = {
+            let node = self
+                .nodes
+                .get(&id)
+                .expect("cannot restart non-existing node");
+            assert!(node.is_dead(), "cannot restart a node that is still running");
+            cons(NewNodeInfo {
+                id: id.clone(),
+                netinfo: node.netinfo.clone(),
+                faulty: node.is_faulty(),
+                rng,
+            })
+        };
+        {
+            let node = self.nodes.get_mut(&id).expect("node disappeared");
+            node.algorithm = algorithm;
+            node.is_dead = false;
+        }
+
+        let start = self.messages.len();
+        self.message_count = self.message_count.saturating_add(process_step(
+            &mut self.nodes,
+            id.clone(),
+            &step,
+            &mut self.messages,
+        ));
+        self.schedule_messages(start);
+        self.trace_outputs(&id, &step);
+
+        // Now the node can receive the messages that were held back for it.
+        let (held, others): (Vec<_>, Vec<_>) = self
+            .held_messages
+            .drain(..)
+            .partition(|msg| msg.to == id);
+        self.held_messages.extend(others);
+        self.messages.extend(held);
+
+        step
     }
 
     /// Convenience function for cranking.
@@ -908,6 +1626,90 @@ where
             .expect("crank: network queue empty")
             .expect("crank: node failed to process step")
     }
+
+    /// Cranks the network until `condition` holds.
+    ///
+    /// Returns the number of cranks it took. If the message queue runs empty or `max_cranks`
+    /// cranks are made without the condition becoming true, a `CrankError::Stalled` containing
+    /// the pending messages is returned instead.
+    pub fn crank_until<F>(
+        &mut self,
+        mut condition: F,
+        max_cranks: usize,
+    ) -> Result<usize, CrankError<D>>
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        let mut cranks = 0;
+        while !condition(self) {
+            if cranks >= max_cranks {
+                return Err(self.stalled(cranks));
+            }
+            match self.crank() {
+                Some(result) => {
+                    let _ = result?;
+                }
+                None => return Err(self.stalled(cranks)),
+            }
+            cranks += 1;
+        }
+        Ok(cranks)
+    }
+
+    /// Cranks the network until every correct node that is not dead has output at least once.
+    ///
+    /// See `crank_until` for the return value.
+    pub fn crank_until_all_output(&mut self, max_cranks: usize) -> Result<usize, CrankError<D>> {
+        self.crank_until(
+            |net| {
+                net.correct_nodes()
+                    .filter(|node| !node.is_dead())
+                    .all(|node| !node.outputs().is_empty())
+            },
+            max_cranks,
+        )
+    }
+
+    /// Returns a `Stalled` error after the given number of cranks.
+    fn stalled(&self, cranks: usize) -> CrankError<D> {
+        CrankError::Stalled {
+            cranks,
+            pending_messages: self.messages.iter().cloned().collect(),
+        }
+    }
+}
+
+impl<D> VirtualNet<D>
+where
+    D: DistAlgorithm,
+    D::Output: PartialEq,
+{
+    /// Checks that the outputs of all correct nodes agree.
+    ///
+    /// Nodes can be at different stages, so only the outputs that both nodes have made so far
+    /// are compared. Returns `CrankError::OutputsDiffer` with the IDs of the first correct node
+    /// and a node that disagrees with it, if there is one.
+    pub fn check_outputs_agree(&self) -> Result<(), CrankError<D>> {
+        let mut correct_nodes = self.correct_nodes();
+        let first = match correct_nodes.next() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        for node in correct_nodes {
+            if first
+                .outputs()
+                .iter()
+                .zip(node.outputs())
+                .any(|(out0, out1)| out0 != out1)
+            {
+                return Err(CrankError::OutputsDiffer(
+                    first.id().clone(),
+                    node.id().clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<D> VirtualNet<D>
@@ -915,7 +1717,7 @@ where
     D: DistAlgorithm,
     D::Message: Clone,
     D::Input: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     /// Send input to all nodes.
     ///
@@ -946,6 +1748,7 @@ where
             }).collect::<Result<_, _>>()?;
 
         // Process all messages from all steps in the queue.
+        let start = self.messages.len();
         steps.iter().for_each(|(id, step)| {
             self.message_count = self.message_count.saturating_add(process_step(
                 &mut self.nodes,
@@ -954,6 +1757,10 @@ where
                 &mut self.messages,
             ));
         });
+        self.schedule_messages(start);
+        for (id, step) in &steps {
+            self.trace_outputs(id, step);
+        }
 
         Ok(steps)
     }
@@ -1019,7 +1826,7 @@ impl<D> Iterator for VirtualNet<D>
 where
     D: DistAlgorithm,
     D::Message: Clone,
-    D::Output: Clone,
+    D::Output: Clone + fmt::Debug,
 {
     type Item = Result<(D::NodeId, Step<D>), CrankError<D>>;
 
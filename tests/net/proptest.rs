@@ -2,13 +2,27 @@
 //!
 //! This module houses strategies to generate (and reduce/expand) various `hbbft` and `net` related
 //! structures.
-
+//!
+//! The message strategies produce arbitrary Binary Agreement, Broadcast and Honey Badger messages,
+//! e.g. to feed them to a node as if they were sent by a faulty peer. Since arbitrary messages are
+//! mostly easy to discard, there are also _mutations_: small modifications that turn a valid
+//! message into an "almost valid" one, like moving it to the next epoch or flipping a bit of a
+//! digest.
+
+use std::fmt;
+
+use hbbft::binary_agreement::bool_set::{self, BoolSet};
+use hbbft::binary_agreement::{self, MessageContent, SbvMessage};
+use hbbft::coin::CoinMessage;
+use hbbft::{broadcast, honey_badger, subset, threshold_decryption, AgreementEpoch, HbEpoch};
 use integer_sqrt::IntegerSquareRoot;
 use proptest::arbitrary::any;
+use proptest::collection::vec;
 use proptest::prelude::Rng;
-use proptest::strategy::{Strategy, ValueTree};
+use proptest::strategy::{BoxedStrategy, Just, Strategy, ValueTree};
 use proptest::test_runner::{Reason, TestRunner};
 use rand::{self, SeedableRng};
+use threshold_crypto::{DecryptionShare, SignatureShare};
 
 /// Random number generator type used in testing.
 pub type TestRng = rand::XorShiftRng;
@@ -245,3 +259,327 @@ impl Strategy for NetworkDimensionStrategy {
         ))
     }
 }
+
+/// Generates a random value using its `rand::Rand` implementation.
+///
+/// The value is not shrinkable. This is meant for types like signature shares, where a "smaller"
+/// value would not be any more meaningful.
+pub fn gen_rand<T>() -> impl Strategy<Value = T>
+where
+    T: rand::Rand + fmt::Debug + 'static,
+{
+    gen_rng().prop_map(|mut rng| rand::Rng::gen(&mut rng))
+}
+
+/// Generates one of the four sets of boolean values.
+pub fn gen_bool_set() -> impl Strategy<Value = BoolSet> {
+    prop_oneof![
+        Just(bool_set::NONE),
+        Just(bool_set::FALSE),
+        Just(bool_set::TRUE),
+        Just(bool_set::BOTH),
+    ]
+}
+
+/// Generates arbitrary Binary Agreement message content.
+pub fn gen_ba_content() -> impl Strategy<Value = MessageContent> {
+    prop_oneof![
+        any::<bool>()
+            .prop_map(|b| MessageContent::SbvBroadcast(SbvMessage::BVal(b)))
+            .boxed(),
+        any::<bool>()
+            .prop_map(|b| MessageContent::SbvBroadcast(SbvMessage::Aux(b)))
+            .boxed(),
+        (any::<bool>(), any::<bool>())
+            .prop_map(|(bval, aux)| MessageContent::SbvBroadcast(SbvMessage::BValAux(bval, aux)))
+            .boxed(),
+        gen_bool_set().prop_map(MessageContent::Conf).boxed(),
+        any::<bool>().prop_map(MessageContent::Term).boxed(),
+        gen_coin_message()
+            .prop_map(|msg| MessageContent::Coin(Box::new(msg)))
+            .boxed(),
+        (any::<bool>(), gen_rand::<SignatureShare>())
+            .prop_map(|(b, share)| MessageContent::SignedTerm(b, Box::new(share)))
+            .boxed(),
+    ]
+}
+
+/// Generates a coin message with a random signature share.
+pub fn gen_coin_message() -> impl Strategy<Value = CoinMessage> {
+    gen_rand::<SignatureShare>().prop_map(CoinMessage::new)
+}
+
+/// Generates an arbitrary Binary Agreement message with an epoch below `max_epoch`.
+pub fn gen_ba_message(max_epoch: u64) -> impl Strategy<Value = binary_agreement::Message> {
+    (0..max_epoch, gen_ba_content())
+        .prop_map(|(epoch, content)| content.with_epoch(AgreementEpoch::new(epoch)))
+}
+
+/// Generates an arbitrary Broadcast message.
+///
+/// `Value` and `Echo` messages contain a random single-leaf Merkle proof.
+pub fn gen_broadcast_message() -> impl Strategy<Value = broadcast::Message> {
+    prop_oneof![
+        gen_random_broadcast_message(),
+        any::<[u8; 32]>().prop_map(broadcast::Message::Ready).boxed(),
+        any::<[u8; 32]>().prop_map(broadcast::Message::EchoHash).boxed(),
+        any::<[u8; 32]>().prop_map(broadcast::Message::CanDecode).boxed(),
+        any::<[u8; 32]>().prop_map(broadcast::Message::EchoRequest).boxed(),
+        vec(any::<u8>(), 0..64)
+            .prop_map(broadcast::Message::FullValue)
+            .boxed(),
+        vec(any::<u8>(), 0..64)
+            .prop_map(broadcast::Message::FullEcho)
+            .boxed(),
+    ]
+}
+
+/// Generates a random Broadcast message of any kind, using its `Rand` implementation.
+#[cfg(feature = "testing")]
+fn gen_random_broadcast_message() -> BoxedStrategy<broadcast::Message> {
+    gen_rand::<broadcast::Message>().boxed()
+}
+
+/// Generates a `Ready` message: Without the `testing` feature, messages containing a Merkle proof
+/// can't be constructed outside the crate.
+#[cfg(not(feature = "testing"))]
+fn gen_random_broadcast_message() -> BoxedStrategy<broadcast::Message> {
+    any::<[u8; 32]>().prop_map(broadcast::Message::Ready).boxed()
+}
+
+/// Generates an arbitrary Honey Badger message in a network with node IDs `0..num_nodes`.
+///
+/// The epoch of the message, and of a contained Binary Agreement message, is below `max_epoch`.
+pub fn gen_hb_message(
+    num_nodes: usize,
+    max_epoch: u64,
+) -> impl Strategy<Value = honey_badger::Message<usize>> {
+    let content = prop_oneof![
+        (0..num_nodes, gen_broadcast_message())
+            .prop_map(|(id, msg)| {
+                honey_badger::MessageContent::Subset(subset::Message::Broadcast(id, msg))
+            }).boxed(),
+        (0..num_nodes, gen_ba_message(max_epoch))
+            .prop_map(|(id, msg)| {
+                honey_badger::MessageContent::Subset(subset::Message::BinaryAgreement(id, msg))
+            }).boxed(),
+        (0..num_nodes, gen_rand::<DecryptionShare>())
+            .prop_map(|(proposer_id, share)| honey_badger::MessageContent::DecryptionShare {
+                proposer_id,
+                share: threshold_decryption::Message(share),
+            }).boxed(),
+        gen_coin_message()
+            .prop_map(honey_badger::MessageContent::BatchSignatureShare)
+            .boxed(),
+    ];
+    (0..max_epoch, content).prop_map(|(epoch, content)| content.with_epoch(HbEpoch::new(epoch)))
+}
+
+/// A modification that turns a valid Binary Agreement message into an "almost valid" one.
+#[derive(Clone, Debug)]
+pub enum BaMutation {
+    /// Moves the message to the next (`true`) or previous (`false`) epoch.
+    ShiftEpoch(bool),
+    /// Negates the value the message is about.
+    Negate,
+    /// Replaces the content, keeping the epoch.
+    Replace(MessageContent),
+}
+
+impl BaMutation {
+    /// Applies the mutation to the given message.
+    pub fn apply(&self, msg: binary_agreement::Message) -> binary_agreement::Message {
+        let binary_agreement::Message { epoch, content } = msg;
+        match *self {
+            BaMutation::ShiftEpoch(true) => content.with_epoch(epoch.saturating_add(1)),
+            BaMutation::ShiftEpoch(false) => content.with_epoch(epoch.saturating_sub(1)),
+            BaMutation::Negate => negate_ba_content(content).with_epoch(epoch),
+            BaMutation::Replace(ref other) => other.clone().with_epoch(epoch),
+        }
+    }
+}
+
+/// Returns the content with the value negated, or the complement of the set of values in the case
+/// of `Conf`. Coin messages are returned unchanged.
+fn negate_ba_content(content: MessageContent) -> MessageContent {
+    match content {
+        MessageContent::SbvBroadcast(SbvMessage::BVal(b)) => {
+            MessageContent::SbvBroadcast(SbvMessage::BVal(!b))
+        }
+        MessageContent::SbvBroadcast(SbvMessage::Aux(b)) => {
+            MessageContent::SbvBroadcast(SbvMessage::Aux(!b))
+        }
+        MessageContent::SbvBroadcast(SbvMessage::BValAux(bval, aux)) => {
+            MessageContent::SbvBroadcast(SbvMessage::BValAux(!bval, !aux))
+        }
+        MessageContent::Conf(set) => {
+            let mut complement = bool_set::NONE;
+            for &b in &[false, true] {
+                if !set.contains(b) {
+                    let _ = complement.insert(b);
+                }
+            }
+            MessageContent::Conf(complement)
+        }
+        MessageContent::Term(b) => MessageContent::Term(!b),
+        MessageContent::SignedTerm(b, share) => MessageContent::SignedTerm(!b, share),
+        content @ MessageContent::Coin(_) => content,
+    }
+}
+
+/// Generates a Binary Agreement message mutation.
+pub fn gen_ba_mutation() -> impl Strategy<Value = BaMutation> {
+    prop_oneof![
+        any::<bool>().prop_map(BaMutation::ShiftEpoch).boxed(),
+        Just(BaMutation::Negate).boxed(),
+        gen_ba_content().prop_map(BaMutation::Replace).boxed(),
+    ]
+}
+
+/// A modification that turns a valid Broadcast message into an "almost valid" one.
+#[derive(Clone, Debug)]
+pub enum BroadcastMutation {
+    /// Turns a `Value` into an `Echo` and vice versa, and a `FullValue` into a `FullEcho` and vice
+    /// versa. A `Ready` message's digest gets its first bit flipped instead.
+    SwapKind,
+    /// Flips the given bit, modulo the number of bits, of the value or digest. `Value` and `Echo`
+    /// messages are replaced by a `FullValue` or `FullEcho` with the modified shard.
+    FlipBit(usize),
+}
+
+impl BroadcastMutation {
+    /// Applies the mutation to the given message.
+    pub fn apply(&self, msg: broadcast::Message) -> broadcast::Message {
+        match *self {
+            BroadcastMutation::SwapKind => match msg {
+                broadcast::Message::Value(proof) => broadcast::Message::Echo(proof),
+                broadcast::Message::Echo(proof) => broadcast::Message::Value(proof),
+                broadcast::Message::FullValue(value) => broadcast::Message::FullEcho(value),
+                broadcast::Message::FullEcho(value) => broadcast::Message::FullValue(value),
+                broadcast::Message::EchoHash(digest) => broadcast::Message::CanDecode(digest),
+                broadcast::Message::CanDecode(digest) => broadcast::Message::EchoRequest(digest),
+                broadcast::Message::EchoRequest(digest) => broadcast::Message::EchoHash(digest),
+                msg @ broadcast::Message::Ready(_) => BroadcastMutation::FlipBit(0).apply(msg),
+            },
+            BroadcastMutation::FlipBit(bit) => match msg {
+                broadcast::Message::Value(proof) => {
+                    broadcast::Message::FullValue(flip_bit(proof.into_value(), bit))
+                }
+                broadcast::Message::Echo(proof) => {
+                    broadcast::Message::FullEcho(flip_bit(proof.into_value(), bit))
+                }
+                broadcast::Message::Ready(digest) => {
+                    broadcast::Message::Ready(flip_digest_bit(digest, bit))
+                }
+                broadcast::Message::EchoHash(digest) => {
+                    broadcast::Message::EchoHash(flip_digest_bit(digest, bit))
+                }
+                broadcast::Message::CanDecode(digest) => {
+                    broadcast::Message::CanDecode(flip_digest_bit(digest, bit))
+                }
+                broadcast::Message::EchoRequest(digest) => {
+                    broadcast::Message::EchoRequest(flip_digest_bit(digest, bit))
+                }
+                broadcast::Message::FullValue(value) => {
+                    broadcast::Message::FullValue(flip_bit(value, bit))
+                }
+                broadcast::Message::FullEcho(value) => {
+                    broadcast::Message::FullEcho(flip_bit(value, bit))
+                }
+            },
+        }
+    }
+}
+
+/// Flips the given bit, modulo the number of bits, of a non-empty byte vector.
+fn flip_bit(mut bytes: Vec<u8>, bit: usize) -> Vec<u8> {
+    let len = bytes.len();
+    if len > 0 {
+        bytes[(bit / 8) % len] ^= 1 << (bit % 8);
+    }
+    bytes
+}
+
+/// Flips the given bit, modulo the number of bits, of a digest.
+fn flip_digest_bit(mut digest: [u8; 32], bit: usize) -> [u8; 32] {
+    digest[(bit / 8) % 32] ^= 1 << (bit % 8);
+    digest
+}
+
+/// Generates a Broadcast message mutation.
+pub fn gen_broadcast_mutation() -> impl Strategy<Value = BroadcastMutation> {
+    prop_oneof![
+        Just(BroadcastMutation::SwapKind).boxed(),
+        any::<usize>().prop_map(BroadcastMutation::FlipBit).boxed(),
+    ]
+}
+
+/// A modification that turns a valid Honey Badger message into an "almost valid" one.
+#[derive(Clone, Debug)]
+pub enum HbMutation {
+    /// Moves the message to the next (`true`) or previous (`false`) epoch.
+    ShiftEpoch(bool),
+    /// Replaces the proposer ID the message is about.
+    Proposer(usize),
+    /// Applies the mutation to a contained Broadcast message.
+    Broadcast(BroadcastMutation),
+    /// Applies the mutation to a contained Binary Agreement message.
+    BinaryAgreement(BaMutation),
+}
+
+impl HbMutation {
+    /// Applies the mutation to the given message.
+    pub fn apply(&self, msg: &honey_badger::Message<usize>) -> honey_badger::Message<usize> {
+        use hbbft::honey_badger::MessageContent as HbContent;
+
+        let epoch = msg.epoch();
+        let content = msg.content().clone();
+        match *self {
+            HbMutation::ShiftEpoch(true) => content.with_epoch(epoch.saturating_add(1)),
+            HbMutation::ShiftEpoch(false) => content.with_epoch(epoch.saturating_sub(1)),
+            HbMutation::Proposer(id) => match content {
+                HbContent::Subset(subset::Message::Broadcast(_, msg)) => {
+                    HbContent::Subset(subset::Message::Broadcast(id, msg))
+                }
+                HbContent::Subset(subset::Message::BinaryAgreement(_, msg)) => {
+                    HbContent::Subset(subset::Message::BinaryAgreement(id, msg))
+                }
+                HbContent::Subset(subset::Message::BinaryAgreements(msgs)) => {
+                    let msgs = msgs.into_iter().map(|(_, msg)| (id, msg)).collect();
+                    HbContent::Subset(subset::Message::BinaryAgreements(msgs))
+                }
+                HbContent::DecryptionShare { share, .. } => HbContent::DecryptionShare {
+                    proposer_id: id,
+                    share,
+                },
+                content @ HbContent::BatchSignatureShare(_) => content,
+            }.with_epoch(epoch),
+            HbMutation::Broadcast(ref mutation) => match content {
+                HbContent::Subset(subset::Message::Broadcast(id, msg)) => {
+                    HbContent::Subset(subset::Message::Broadcast(id, mutation.apply(msg)))
+                }
+                content => content,
+            }.with_epoch(epoch),
+            HbMutation::BinaryAgreement(ref mutation) => match content {
+                HbContent::Subset(subset::Message::BinaryAgreement(id, msg)) => {
+                    HbContent::Subset(subset::Message::BinaryAgreement(id, mutation.apply(msg)))
+                }
+                content => content,
+            }.with_epoch(epoch),
+        }
+    }
+}
+
+/// Generates a Honey Badger message mutation, for a network with node IDs `0..num_nodes`.
+pub fn gen_hb_mutation(num_nodes: usize) -> impl Strategy<Value = HbMutation> {
+    prop_oneof![
+        any::<bool>().prop_map(HbMutation::ShiftEpoch).boxed(),
+        (0..num_nodes).prop_map(HbMutation::Proposer).boxed(),
+        gen_broadcast_mutation()
+            .prop_map(HbMutation::Broadcast)
+            .boxed(),
+        gen_ba_mutation()
+            .prop_map(HbMutation::BinaryAgreement)
+            .boxed(),
+    ]
+}
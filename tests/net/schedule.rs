@@ -0,0 +1,102 @@
+//! Delivery schedules for test networks
+//!
+//! By default, a `VirtualNet` delivers messages in the order they were queued, which the adversary
+//! may change. A `DeliverySchedule` instead assigns a latency to each message when it is sent: The
+//! message is due that many units of virtual time later, and each crank delivers the message that
+//! is due earliest, advancing the network's virtual clock to its due time. Messages that are due
+//! at the same time are delivered in queue order. Messages inserted by the adversary are due
+//! immediately.
+//!
+//! Schedules that need randomness draw it from the network's random number generator, so that test
+//! runs can be reproduced from the network's seed.
+
+use std::collections::BTreeMap;
+
+use rand::distributions::{Exp, IndependentSample};
+use rand::Rng;
+
+/// Determines the latency of every message in a network.
+pub trait DeliverySchedule<N> {
+    /// Returns the latency of a message sent from `from` to `to`, in units of virtual time.
+    fn latency(&mut self, from: &N, to: &N, rng: &mut dyn Rng) -> u64;
+}
+
+/// A schedule in which every message has the same latency.
+#[derive(Clone, Copy, Debug)]
+pub struct UniformLatency(pub u64);
+
+impl<N> DeliverySchedule<N> for UniformLatency {
+    #[inline]
+    fn latency(&mut self, _: &N, _: &N, _: &mut dyn Rng) -> u64 {
+        self.0
+    }
+}
+
+/// A schedule with a fixed latency for each link between two nodes.
+#[derive(Clone, Debug)]
+pub struct LatencyMatrix<N> {
+    /// The latencies of the links, by sender and receiver.
+    latencies: BTreeMap<(N, N), u64>,
+    /// The latency of all other links.
+    default: u64,
+}
+
+impl<N: Ord> LatencyMatrix<N> {
+    /// Creates a new matrix in which all links have the `default` latency.
+    #[inline]
+    pub fn new(default: u64) -> Self {
+        LatencyMatrix {
+            latencies: BTreeMap::new(),
+            default,
+        }
+    }
+
+    /// Sets the latency of messages sent from `from` to `to`.
+    #[inline]
+    pub fn link(mut self, from: N, to: N, latency: u64) -> Self {
+        self.latencies.insert((from, to), latency);
+        self
+    }
+}
+
+impl<N: Ord + Clone> DeliverySchedule<N> for LatencyMatrix<N> {
+    #[inline]
+    fn latency(&mut self, from: &N, to: &N, _: &mut dyn Rng) -> u64 {
+        *self
+            .latencies
+            .get(&(from.clone(), to.clone()))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// A schedule in which every message has a base latency plus an exponentially distributed jitter.
+#[derive(Clone, Copy, Debug)]
+pub struct ExponentialJitter {
+    /// The minimum latency.
+    base: u64,
+    /// The distribution of the jitter.
+    jitter: Exp,
+}
+
+impl ExponentialJitter {
+    /// Creates a new schedule with the given base latency and mean jitter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mean_jitter` is not positive.
+    #[inline]
+    pub fn new(base: u64, mean_jitter: f64) -> Self {
+        assert!(mean_jitter > 0.0, "the mean jitter must be positive");
+        ExponentialJitter {
+            base,
+            jitter: Exp::new(1.0 / mean_jitter),
+        }
+    }
+}
+
+impl<N> DeliverySchedule<N> for ExponentialJitter {
+    #[inline]
+    fn latency(&mut self, _: &N, _: &N, mut rng: &mut dyn Rng) -> u64 {
+        self.base + self.jitter.ind_sample(&mut rng).round() as u64
+    }
+}
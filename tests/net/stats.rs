@@ -0,0 +1,186 @@
+//! Message statistics
+//!
+//! If enabled using `NetBuilder::stats`, a `VirtualNet` counts every message it queues, and its
+//! size in bytes when serialized with `bincode`, by sender, by receiver and by kind. This allows
+//! tests to quantify the message complexity of an algorithm, e.g. to detect an increase from
+//! _O(N²)_ to _O(N³)_ messages.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The number of messages and their total size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counter {
+    /// The number of messages.
+    pub messages: u64,
+    /// The total size of the messages, in bytes.
+    pub bytes: u64,
+}
+
+impl Counter {
+    /// Adds a message of the given size.
+    #[inline]
+    fn add(&mut self, bytes: u64) {
+        self.messages += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Message statistics of a virtual network.
+#[derive(Clone, Debug)]
+pub struct NetStats<N> {
+    /// All messages.
+    total: Counter,
+    /// Messages by sender.
+    by_sender: BTreeMap<N, Counter>,
+    /// Messages by receiver.
+    by_receiver: BTreeMap<N, Counter>,
+    /// Messages by kind.
+    by_kind: BTreeMap<String, Counter>,
+}
+
+impl<N: Ord> Default for NetStats<N> {
+    fn default() -> Self {
+        NetStats {
+            total: Counter::default(),
+            by_sender: BTreeMap::new(),
+            by_receiver: BTreeMap::new(),
+            by_kind: BTreeMap::new(),
+        }
+    }
+}
+
+impl<N: Ord + Clone> NetStats<N> {
+    /// Records a message.
+    pub fn record(&mut self, from: &N, to: &N, kind: String, bytes: u64) {
+        self.total.add(bytes);
+        self.by_sender.entry(from.clone()).or_default().add(bytes);
+        self.by_receiver.entry(to.clone()).or_default().add(bytes);
+        self.by_kind.entry(kind).or_default().add(bytes);
+    }
+
+    /// Returns the total number and size of all messages.
+    #[inline]
+    pub fn total(&self) -> Counter {
+        self.total
+    }
+
+    /// Returns the number and size of the messages sent by each node.
+    #[inline]
+    pub fn by_sender(&self) -> &BTreeMap<N, Counter> {
+        &self.by_sender
+    }
+
+    /// Returns the number and size of the messages sent to each node.
+    #[inline]
+    pub fn by_receiver(&self) -> &BTreeMap<N, Counter> {
+        &self.by_receiver
+    }
+
+    /// Returns the number and size of the messages of each kind.
+    #[inline]
+    pub fn by_kind(&self) -> &BTreeMap<String, Counter> {
+        &self.by_kind
+    }
+
+    /// Returns the number and size of the messages of the given kind.
+    #[inline]
+    pub fn kind(&self, kind: &str) -> Counter {
+        self.by_kind.get(kind).cloned().unwrap_or_default()
+    }
+}
+
+/// A summary of the messages queued in a virtual network.
+///
+/// This is included in the errors returned when a network exceeds one of its limits, to show which
+/// messages kept it busy, e.g. if a bug makes two nodes send each other messages indefinitely.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueStats<N> {
+    /// The number of cranks made so far.
+    pub cranks: usize,
+    /// The number of messages sent so far.
+    pub sent: usize,
+    /// The number of queued messages.
+    pub queued: usize,
+    /// The number of queued messages by sender.
+    pub by_sender: BTreeMap<N, usize>,
+    /// The number of queued messages by kind, as determined by `message_kind`.
+    pub by_kind: BTreeMap<String, usize>,
+}
+
+impl<N: Ord + Clone> QueueStats<N> {
+    /// Creates the summary of the given queued messages, each given by its sender and content.
+    pub fn new<'a, M, I>(cranks: usize, sent: usize, queue: I) -> Self
+    where
+        N: 'a,
+        M: fmt::Debug + 'a,
+        I: IntoIterator<Item = (&'a N, &'a M)>,
+    {
+        let mut stats = QueueStats {
+            cranks,
+            sent,
+            queued: 0,
+            by_sender: BTreeMap::new(),
+            by_kind: BTreeMap::new(),
+        };
+        for (from, msg) in queue {
+            stats.queued += 1;
+            *stats.by_sender.entry(from.clone()).or_insert(0) += 1;
+            *stats.by_kind.entry(message_kind(msg)).or_insert(0) += 1;
+        }
+        stats
+    }
+}
+
+impl<N: fmt::Debug> fmt::Display for QueueStats<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} cranks, {} messages sent, {} queued",
+            self.cranks, self.sent, self.queued
+        )?;
+        // The most frequent kinds are the most likely culprits, so they are listed first.
+        let mut kinds: Vec<_> = self.by_kind.iter().collect();
+        kinds.sort_by(|(_, n0), (_, n1)| n1.cmp(n0));
+        for (i, (kind, n)) in kinds.into_iter().enumerate() {
+            let sep = if i == 0 { " by kind: " } else { ", " };
+            write!(f, "{}{} × {}", sep, n, kind)?;
+        }
+        for (i, (id, n)) in self.by_sender.iter().enumerate() {
+            let sep = if i == 0 { "; by sender: " } else { ", " };
+            write!(f, "{}{} from {:?}", sep, n, id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the kind of a message, derived from its `Debug` representation.
+///
+/// The kind consists of the names of all nested enum variants or tuple structs, i.e. of all
+/// capitalized identifiers that are followed by an opening parenthesis, separated by `::`. E.g. a
+/// Binary Agreement message `Message { epoch: 0, content: SbvBroadcast(BVal(true)) }` is of kind
+/// `SbvBroadcast::BVal`. If there are no such identifiers, the first word is used.
+pub fn message_kind<M: fmt::Debug>(msg: &M) -> String {
+    let text = format!("{:?}", msg);
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut names = Vec::new();
+    let mut ident = String::new();
+    for c in text.chars() {
+        if is_ident_char(c) {
+            ident.push(c);
+            continue;
+        }
+        if c == '(' && ident.chars().next().map_or(false, char::is_uppercase) {
+            names.push(ident.clone());
+        }
+        ident.clear();
+    }
+    if names.is_empty() {
+        text.split(|c: char| !is_ident_char(c))
+            .next()
+            .unwrap_or("")
+            .to_string()
+    } else {
+        names.join("::")
+    }
+}
@@ -0,0 +1,129 @@
+//! Structured network traces
+//!
+//! If tracing is enabled, a `VirtualNet` writes a `TraceEvent` to its trace for every message that
+//! is sent, delivered, handed to the adversary, held back, dropped or duplicated, and for every
+//! output. The events are serialized one after the other using `bincode`, and can be read back
+//! using `read_trace`. Node IDs, messages and outputs are recorded in their `Debug` representation,
+//! so that any `DistAlgorithm` can be traced.
+//!
+//! A recorded trace can be re-executed against a freshly constructed network using
+//! `VirtualNet::replay`.
+
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+use bincode;
+
+use super::NetworkMessage;
+
+/// A message on the virtual network, as recorded in a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TracedMessage {
+    /// Message sender.
+    pub from: String,
+    /// Destined receiver.
+    pub to: String,
+    /// The message contents.
+    pub payload: String,
+}
+
+impl TracedMessage {
+    /// Creates the record of the given message.
+    pub fn new<M: fmt::Debug, N: fmt::Debug>(msg: &NetworkMessage<M, N>) -> Self {
+        TracedMessage {
+            from: format!("{:?}", msg.from),
+            to: format!("{:?}", msg.to),
+            payload: format!("{:?}", msg.payload),
+        }
+    }
+
+    /// Returns `true` if this is the record of the given message.
+    pub fn matches<M: fmt::Debug, N: fmt::Debug>(&self, msg: &NetworkMessage<M, N>) -> bool {
+        *self == TracedMessage::new(msg)
+    }
+}
+
+/// An event in a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceEvent {
+    /// A message was queued for delivery.
+    Send(TracedMessage),
+    /// A message was delivered to a correct node.
+    Deliver(TracedMessage),
+    /// A message to a faulty node was handed to the adversary.
+    Tamper(TracedMessage),
+    /// A message was held back, because its receiver was dead or on the other side of a partition.
+    Hold(TracedMessage),
+    /// A message was lost, because its receiver was dead or the network is lossy.
+    Drop(TracedMessage),
+    /// A lossy network queued a message a second time.
+    Duplicate(TracedMessage),
+    /// A node produced an output.
+    Output {
+        /// The node that produced the output.
+        node: String,
+        /// The output.
+        output: String,
+    },
+}
+
+/// Whether and where a network writes its trace; see `NetBuilder::trace`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceTarget {
+    /// No trace is written.
+    Off,
+    /// The trace is written to a new file in the working directory, with a unique name.
+    DefaultFile,
+    /// The trace is written to the given file, which is overwritten if it exists.
+    File(PathBuf),
+}
+
+impl From<bool> for TraceTarget {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            TraceTarget::DefaultFile
+        } else {
+            TraceTarget::Off
+        }
+    }
+}
+
+impl From<PathBuf> for TraceTarget {
+    fn from(path: PathBuf) -> Self {
+        TraceTarget::File(path)
+    }
+}
+
+impl<'a, P: AsRef<Path> + ?Sized> From<&'a P> for TraceTarget {
+    fn from(path: &'a P) -> Self {
+        TraceTarget::File(path.as_ref().to_path_buf())
+    }
+}
+
+/// Writes a single event to a trace.
+pub fn write_event<W: io::Write>(dest: W, event: &TraceEvent) -> bincode::Result<()> {
+    bincode::serialize_into(dest, event)
+}
+
+/// Reads all events from a trace.
+pub fn read_trace<R: io::Read>(mut src: R) -> bincode::Result<Vec<TraceEvent>> {
+    let mut events = Vec::new();
+    loop {
+        match bincode::deserialize_from(&mut src) {
+            Ok(event) => events.push(event),
+            Err(err) => {
+                // The trace ends when there are no bytes left for another event.
+                let is_eof = match *err {
+                    bincode::ErrorKind::Io(ref io_err) => {
+                        io_err.kind() == io::ErrorKind::UnexpectedEof
+                    }
+                    _ => false,
+                };
+                if is_eof {
+                    return Ok(events);
+                }
+                return Err(err);
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::cell::Cell;
+use std::cmp;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use hbbft::binary_agreement::{self, BinaryAgreement, MessageContent, SbvMessage};
+use hbbft::broadcast::{self, Broadcast};
+use hbbft::{AgreementEpoch, Step};
+
+use net::adversary::{
+    DelayingAdversary, EquivocatingAdversary, FaultyMessage, NetMutHandle, NullAdversary,
+    RushingAdversary, SilentAdversary,
+};
+use net::{Adversary, CrankError, NetBuilder};
+
+/// Runs a broadcast from the correct node 3, with node 0 controlled by the adversary, and checks
+/// that all correct nodes output the proposed value.
+fn test_broadcast<A>(adversary: A)
+where
+    A: Adversary<Broadcast<usize>> + 'static,
+{
+    let mut net = NetBuilder::new(0..4)
+        .num_faulty(1)
+        .adversary(adversary)
+        .trace(false)
+        .using(|node| {
+            Broadcast::new(Arc::new(node.netinfo), 3).expect("could not create broadcast")
+        }).build()
+        .expect("could not construct test network");
+
+    let _ = net
+        .send_input(3, b"Foo".to_vec())
+        .expect("could not send input");
+    while let Some(result) = net.crank() {
+        let _ = result.expect("crank: node failed to process step");
+    }
+    for node in net.correct_nodes() {
+        assert_eq!(node.outputs(), &[b"Foo".to_vec()]);
+    }
+}
+
+/// Runs binary agreement with mixed inputs, with node 0 controlled by the adversary, and checks
+/// that all correct nodes decide on the same value.
+fn test_binary_agreement<A>(adversary: A)
+where
+    A: Adversary<BinaryAgreement<usize>> + 'static,
+{
+    let mut net = NetBuilder::new(0..4)
+        .num_faulty(1)
+        .adversary(adversary)
+        .trace(false)
+        .using(|node| {
+            BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, id % 2 == 0)
+            .expect("could not send input");
+    }
+    while let Some(result) = net.crank() {
+        let _ = result.expect("crank: node failed to process step");
+    }
+    assert!(net.correct_nodes().all(|node| node.outputs().len() == 1));
+    net.check_outputs_agree().expect("correct nodes disagree");
+}
+
+#[test]
+fn broadcast_silent_adversary() {
+    test_broadcast(SilentAdversary::new());
+}
+
+#[test]
+fn broadcast_equivocating_adversary() {
+    test_broadcast(EquivocatingAdversary::new(|msg: &broadcast::Message| {
+        match *msg {
+            broadcast::Message::Ready(ref digest) => {
+                let mut other = *digest;
+                other[0] ^= 1;
+                Some(broadcast::Message::Ready(other))
+            }
+            _ => None,
+        }
+    }));
+}
+
+#[test]
+fn broadcast_delaying_adversary() {
+    test_broadcast(DelayingAdversary::new(5));
+}
+
+#[test]
+fn broadcast_rushing_adversary() {
+    test_broadcast(RushingAdversary::new(NullAdversary::new()));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn broadcast_random_adversary() {
+    test_broadcast(net::adversary::RandomAdversary::new(0.2));
+}
+
+#[test]
+fn binary_agreement_silent_adversary() {
+    test_binary_agreement(SilentAdversary::new());
+}
+
+/// Returns the `BVal` message with the opposite value, for equivocating faulty nodes.
+fn negate_bval(msg: &binary_agreement::Message) -> Option<binary_agreement::Message> {
+    match msg.content {
+        MessageContent::SbvBroadcast(SbvMessage::BVal(b)) => {
+            Some(MessageContent::SbvBroadcast(SbvMessage::BVal(!b)).with_epoch(msg.epoch))
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn binary_agreement_equivocating_adversary() {
+    test_binary_agreement(EquivocatingAdversary::new(negate_bval));
+}
+
+#[test]
+fn binary_agreement_delaying_adversary() {
+    test_binary_agreement(DelayingAdversary::new(5));
+}
+
+/// The faulty node sees every `BVal` and `Aux` message first, and its conflicting messages reach
+/// the correct nodes before each other's: the scenario the `Conf` round guards against.
+#[test]
+fn binary_agreement_rushing_adversary() {
+    test_binary_agreement(RushingAdversary::new(EquivocatingAdversary::new(negate_bval)));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn binary_agreement_random_adversary() {
+    test_binary_agreement(net::adversary::RandomAdversary::new(0.2));
+}
+
+/// A silent adversary that reads the correct nodes' state: It records the latest epoch any of them
+/// has reached.
+#[derive(Default)]
+struct ObservingAdversary {
+    /// The latest epoch of a correct node observed so far.
+    max_epoch: Rc<Cell<AgreementEpoch>>,
+}
+
+impl Adversary<BinaryAgreement<usize>> for ObservingAdversary {
+    fn pre_crank(&mut self, mut net: NetMutHandle<BinaryAgreement<usize>>) {
+        net.discard_faulty_messages();
+        let epochs = net.correct_nodes().map(|node| node.algorithm().epoch());
+        if let Some(epoch) = epochs.max() {
+            self.max_epoch.set(cmp::max(epoch, self.max_epoch.get()));
+        }
+    }
+
+    fn tamper(
+        &mut self,
+        _: NetMutHandle<BinaryAgreement<usize>>,
+        _: FaultyMessage<BinaryAgreement<usize>>,
+    ) -> Result<Step<BinaryAgreement<usize>>, CrankError<BinaryAgreement<usize>>> {
+        Ok(Step::default())
+    }
+}
+
+#[test]
+fn binary_agreement_observing_adversary() {
+    let adversary = ObservingAdversary::default();
+    let max_epoch = adversary.max_epoch.clone();
+    test_binary_agreement(adversary);
+    // Two of the three correct nodes input `false`, which doesn't match the first epoch's fixed
+    // coin value, so the correct nodes need more than one epoch.
+    assert!(max_epoch.get() > AgreementEpoch::new(0));
+}
@@ -0,0 +1,116 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::sync::Arc;
+
+use hbbft::binary_agreement::BinaryAgreement;
+use hbbft::coin::InsecureCoin;
+use hbbft::DistAlgorithm;
+use proptest::arbitrary::any;
+use proptest::collection::vec;
+use proptest::prelude::ProptestConfig;
+
+use net::adversary::ReorderingAdversary;
+use net::proptest::{gen_seed, NetworkDimension, TestRngSeed};
+use net::NetBuilder;
+
+/// The maximum number of epochs after which all correct nodes must have decided. Each epoch in
+/// which the coin is random decides with probability at least 1/2, so the probability that a
+/// schedule that doesn't know the coin values exceeds this is negligible.
+const MAX_EPOCHS: u64 = 100;
+
+/// Binary Agreement with the insecure coin. The scripted schedules don't depend on the coin
+/// values, so safety and termination are the same as with the threshold signature based coin, and
+/// the insecure coin makes thousands of runs affordable.
+type InsecureBa = BinaryAgreement<usize, InsecureCoin<usize>>;
+
+/// Test configuration for Binary Agreement safety tests.
+#[derive(Debug)]
+struct TestConfig {
+    /// The desired network dimension.
+    dimension: NetworkDimension,
+    /// The input of each node.
+    inputs: Vec<bool>,
+    /// The reordering script of the adversary. If a test fails, proptest shrinks it to a minimal
+    /// failing schedule.
+    script: Vec<usize>,
+    /// The seed of the network's random number generator.
+    seed: TestRngSeed,
+}
+
+prop_compose! {
+    /// Strategy to generate a test configuration.
+    fn arb_config()
+                 (dimension in NetworkDimension::range(1, 10),
+                  inputs in vec(any::<bool>(), 10),
+                  script in vec(any::<usize>(), 0..400),
+                  seed in gen_seed())
+                 -> TestConfig {
+        TestConfig { dimension, inputs, script, seed }
+    }
+}
+
+/// Proptest wrapper for `do_agreement_safety`.
+proptest!{
+    #![proptest_config(ProptestConfig {
+        cases: 2000, .. ProptestConfig::default()
+    })]
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn agreement_safety(cfg in arb_config()) {
+        do_agreement_safety(cfg)
+    }
+}
+
+/// Binary Agreement: Runs an instance with the given inputs, with messages reordered according to
+/// the script, and checks that all correct nodes decide the same value, that the value was the
+/// input of a correct node, and that they decide within `MAX_EPOCHS` epochs.
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn do_agreement_safety(cfg: TestConfig) {
+    let size = cfg.dimension.size();
+    let mut net = NetBuilder::new(0..size)
+        .num_faulty(cfg.dimension.faulty())
+        .adversary(ReorderingAdversary::new(cfg.script.clone()))
+        .message_limit(10_000 * size)
+        .seed(cfg.seed)
+        .trace(false)
+        .using(|node| -> InsecureBa {
+            BinaryAgreement::new_with_coin(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+
+    for (id, &input) in cfg.inputs.iter().enumerate().take(size) {
+        let _ = net.send_input(id, input).expect("could not send input");
+    }
+    while let Some(result) = net.crank() {
+        let _ = result.expect("crank: node failed to process step");
+    }
+
+    // Termination: Every correct node decides exactly once, within the epoch limit.
+    for node in net.correct_nodes() {
+        assert_eq!(1, node.outputs().len(), "node {} did not decide", node.id());
+        assert!(node.algorithm().terminated());
+        assert!(node.algorithm().epoch().get() < MAX_EPOCHS);
+    }
+    // Agreement: All correct nodes decide the same value.
+    net.check_outputs_agree().expect("correct nodes disagree");
+    // Validity: The decision was the input of at least one correct node.
+    let decision = net.correct_nodes().next().expect("correct node").outputs()[0];
+    assert!(
+        net.correct_nodes().any(|node| cfg.inputs[*node.id()] == decision),
+        "no correct node had input {}",
+        decision
+    );
+}
@@ -1,21 +1,30 @@
+extern crate bincode;
 extern crate failure;
 extern crate hbbft;
 #[macro_use]
 extern crate proptest;
 extern crate integer_sqrt;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate threshold_crypto;
 
 pub mod net;
 
 use std::{collections, time};
 
-use hbbft::dynamic_honey_badger::{Change, ChangeState, DynamicHoneyBadger, Input};
-use hbbft::DistAlgorithm;
+use hbbft::dynamic_honey_badger::{
+    Batch, Change, ChangeState, DynamicHoneyBadger, FaultKind, Input, Message,
+};
+use hbbft::{NetworkInfo, NodeIdT, Step};
+use net::adversary::{FaultyMessage, NetMutHandle};
 use net::proptest::{gen_seed, NetworkDimension, TestRng, TestRngSeed};
-use net::NetBuilder;
+use net::{Adversary, CrankError, NetBuilder};
 use proptest::prelude::ProptestConfig;
 use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use threshold_crypto::SecretKeyShare;
 
 /// Choose a node's contribution for an epoch.
 ///
@@ -156,7 +165,7 @@ fn do_drop_and_readd(cfg: TestConfig) {
 
         for change in step.output.iter().map(|output| output.change()) {
             match change {
-                ChangeState::Complete(Change::Remove(pivot_node_id)) => {
+                ChangeState::Complete(Change::Remove(pivot_node_id), _) => {
                     println!("Node {:?} done removing.", node_id);
                     // Removal complete, tally:
                     awaiting_removal.remove(&node_id);
@@ -169,11 +178,11 @@ fn do_drop_and_readd(cfg: TestConfig) {
                         .public_key();
                     let _ = net[node_id]
                         .algorithm_mut()
-                        .handle_input(Input::Change(Change::Add(*pivot_node_id, pk)))
+                        .vote_to_add(*pivot_node_id, pk)
                         .expect("failed to send `Add` input");
                 }
 
-                ChangeState::Complete(Change::Add(pivot_node_id, _)) => {
+                ChangeState::Complete(Change::Add(pivot_node_id, _), _) => {
                     println!("Node {:?} done adding.", node_id);
                     // Node added, ensure it has been removed first.
                     if awaiting_removal.contains(&node_id) {
@@ -249,3 +258,296 @@ fn do_drop_and_readd(cfg: TestConfig) {
 
     println!("End result: {:?}", out);
 }
+
+type UsizeDhb = DynamicHoneyBadger<Vec<usize>, usize>;
+
+/// An adversary that lets the faulty nodes run the algorithm, but send each of their key
+/// generation messages twice.
+struct DuplicateKeyGenAdversary;
+
+impl Adversary<UsizeDhb> for DuplicateKeyGenAdversary {
+    fn tamper(
+        &mut self,
+        mut net: NetMutHandle<UsizeDhb>,
+        msg: FaultyMessage<UsizeDhb>,
+    ) -> Result<Step<UsizeDhb>, CrankError<UsizeDhb>> {
+        let mut step = net.dispatch_message(msg)?;
+        let duplicates: Vec<_> = step
+            .messages
+            .iter()
+            .filter(|msg| match msg.message {
+                Message::KeyGen(..) => true,
+                _ => false,
+            }).cloned()
+            .collect();
+        step.messages.extend(duplicates);
+        Ok(step)
+    }
+}
+
+/// Dynamic Honey Badger: A faulty node sends its key generation messages twice while a validator
+/// is removed. The correct nodes report it, and still complete the removal.
+#[test]
+fn duplicate_key_gen_messages() {
+    let mut net = NetBuilder::new(0..5)
+        .num_faulty(1)
+        .seed([1, 2, 3, 4])
+        .adversary(DuplicateKeyGenAdversary)
+        .trace(false)
+        .using(|node| -> UsizeDhb {
+            DynamicHoneyBadger::builder()
+                .rng(node.rng)
+                .build(node.netinfo)
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..5 {
+        let _ = net
+            .send_input(id, Input::User(vec![id]))
+            .expect("could not send initial contribution");
+    }
+    let _ = net
+        .broadcast_input(&Input::Change(Change::Remove(4)))
+        .expect("could not vote for removal");
+
+    let faulty_id = 0;
+    let mut faults = Vec::new();
+    let mut removed = collections::BTreeSet::new();
+    while removed.len() < 4 {
+        let (node_id, step) = net.crank_expect();
+        if !net[node_id].is_faulty() {
+            faults.extend(step.fault_log.0.iter().map(|fault| (fault.node_id, fault.kind)));
+        }
+        let complete = Some(&Change::Remove(4));
+        let is_complete = step
+            .output
+            .iter()
+            .any(|batch| batch.change().completed() == complete);
+        if is_complete && !net[node_id].is_faulty() {
+            removed.insert(node_id);
+        }
+        if !step.output.is_empty() && !removed.contains(&node_id) {
+            let _ = net
+                .send_input(node_id, Input::User(vec![node_id]))
+                .expect("could not send contribution");
+        }
+    }
+
+    assert!(faults.iter().all(|&(id, _)| id == faulty_id));
+    let is_duplicate = |&(_, kind): &(usize, FaultKind)| {
+        kind == FaultKind::MultipleKeyGenParts || kind == FaultKind::MultipleKeyGenAcks
+    };
+    assert!(faults.iter().any(is_duplicate));
+}
+
+/// Dynamic Honey Badger: A node starts as an observer, is voted in as a validator, and afterwards
+/// its contributions are included in the batches, without restarting it.
+#[test]
+fn observer_promotion() {
+    let observer_id = 4;
+    let mut rng = TestRng::from_seed([5, 6, 7, 8]);
+    let validators = NetworkInfo::generate_map(0..observer_id, &mut rng)
+        .expect("could not generate validators' keys");
+    let mut net = NetBuilder::new(0..=observer_id)
+        .seed([1, 2, 3, 4])
+        .trace(false)
+        .using(move |node| -> UsizeDhb {
+            let netinfo = validators.get(&node.id).cloned().unwrap_or_else(|| {
+                // The observer knows the validators' public keys, but has no key share.
+                let validator = validators.values().next().expect("no validators");
+                NetworkInfo::new(
+                    node.id,
+                    SecretKeyShare::default(),
+                    validator.public_key_set().clone(),
+                    node.netinfo.secret_key().clone(),
+                    validator.public_key_map().clone(),
+                )
+            });
+            DynamicHoneyBadger::builder()
+                .rng(node.rng)
+                .build(netinfo)
+        }).build()
+        .expect("could not construct test network");
+    assert!(!net[observer_id].algorithm().is_validator());
+
+    let pk = net[observer_id]
+        .algorithm()
+        .netinfo()
+        .secret_key()
+        .public_key();
+    for id in 0..observer_id {
+        let _ = net
+            .send_input(id, Input::Change(Change::Add(observer_id, pk.clone())))
+            .expect("could not vote");
+    }
+    for id in 0..=observer_id {
+        let _ = net
+            .send_input(id, Input::User(vec![id]))
+            .expect("could not propose");
+    }
+
+    let mut promotion_epoch = None;
+    let has_observer_contribution = |node: &net::Node<UsizeDhb>| {
+        node.outputs()
+            .iter()
+            .any(|batch| batch.contributions().any(|(id, _)| *id == observer_id))
+    };
+    while !net.nodes().all(&has_observer_contribution) {
+        let (node_id, step) = net.crank_expect();
+        if step.output.is_empty() {
+            continue;
+        }
+        for batch in &step.output {
+            if batch.contributions().any(|(id, _)| *id == observer_id) {
+                let epoch = promotion_epoch.expect("contribution included before promotion");
+                assert!(batch.epoch() > epoch);
+            }
+            if node_id == observer_id && batch.promoted() {
+                assert_eq!(None, promotion_epoch);
+                promotion_epoch = Some(batch.epoch());
+            }
+        }
+        if node_id == observer_id && promotion_epoch.is_some() {
+            assert!(net[observer_id].algorithm().is_validator());
+        }
+        // Every node proposes again once it has output a batch. The observer's proposals are
+        // ignored until it has been promoted.
+        let _ = net
+            .send_input(node_id, Input::User(vec![node_id]))
+            .expect("could not propose");
+    }
+
+    // All nodes agree on the batches, and on the epoch in which the observer joined.
+    let promotion_epoch = promotion_epoch.expect("observer was not promoted");
+    for node in net.nodes() {
+        let completed = node.outputs().iter().find(|batch| match batch.change() {
+            ChangeState::Complete(Change::Add(id, _), _) => *id == observer_id,
+            _ => false,
+        });
+        assert_eq!(Some(promotion_epoch), completed.map(|batch| batch.epoch()));
+    }
+    net.verify_batches();
+}
+
+/// Dynamic Honey Badger: After key generation for the removal of a validator has completed, the
+/// old validators still finish the configured number of epochs before the change takes effect.
+#[test]
+fn drain_epochs_after_removal() {
+    let drain_epochs = 2;
+    let mut net = NetBuilder::new(0..5)
+        .seed([3, 1, 4, 1])
+        .trace(false)
+        .using(move |node| -> UsizeDhb {
+            DynamicHoneyBadger::builder()
+                .rng(node.rng)
+                .drain_epochs(drain_epochs)
+                .build(node.netinfo)
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..5 {
+        let _ = net
+            .send_input(id, Input::User(vec![id]))
+            .expect("could not send initial contribution");
+    }
+    let _ = net
+        .broadcast_input(&Input::Change(Change::Remove(4)))
+        .expect("could not vote for removal");
+
+    let remove = Change::Remove(4);
+    let is_complete = |node: &net::Node<UsizeDhb>| {
+        node.outputs()
+            .iter()
+            .any(|batch| batch.change().completed() == Some(&remove))
+    };
+    while !net.nodes().all(&is_complete) {
+        let (node_id, step) = net.crank_expect();
+        if !step.output.is_empty() && !is_complete(&net[node_id]) {
+            let _ = net
+                .send_input(node_id, Input::User(vec![node_id]))
+                .expect("could not send contribution");
+        }
+    }
+
+    // More than _f_ validators must have voted for the change.
+    let is_in_progress = |batch: &&Batch<Vec<usize>, usize>| match *batch.change() {
+        ChangeState::InProgress(ref change, ref voters) => *change == remove && voters.len() > 1,
+        _ => false,
+    };
+    for node in net.nodes() {
+        let batches = node.outputs();
+        let idx = batches
+            .iter()
+            .position(|batch| batch.change().completed() == Some(&remove))
+            .expect("removal not complete");
+        assert!(idx > drain_epochs as usize);
+        let (before, draining) = batches[..idx].split_at(idx - drain_epochs as usize);
+        // When key generation starts, new nodes can join.
+        let start = before
+            .iter()
+            .find(is_in_progress)
+            .expect("key generation never started");
+        assert!(!start.is_draining() && start.join_plan().is_some());
+        // Afterwards, the old validators finish the epochs in progress, and nobody can join.
+        for batch in draining {
+            assert!(is_in_progress(&batch));
+            assert!(batch.is_draining() && batch.join_plan().is_none());
+            assert!(batch.network_info().is_node_validator(&4));
+        }
+        assert!(!batches[idx].network_info().is_node_validator(&4));
+    }
+    net.verify_batches();
+}
+
+/// Runs Dynamic Honey Badger on the given nodes, removes the last one, and checks that the others
+/// agree on the batches.
+fn remove_last_node<N>(ids: Vec<N>)
+where
+    N: NodeIdT + Serialize + for<'r> Deserialize<'r> + 'static,
+{
+    let removed = ids.last().cloned().expect("no nodes");
+    let mut net = NetBuilder::new(ids.clone())
+        .seed([2, 7, 1, 8])
+        .trace(false)
+        .using(|node| -> DynamicHoneyBadger<Vec<u8>, N> {
+            DynamicHoneyBadger::builder()
+                .rng(node.rng)
+                .build(node.netinfo)
+        }).build()
+        .expect("could not construct test network");
+
+    for id in ids {
+        let _ = net
+            .send_input(id, Input::User(vec![0]))
+            .expect("could not send initial contribution");
+    }
+    let remove = Change::Remove(removed);
+    let _ = net
+        .broadcast_input(&Input::Change(remove.clone()))
+        .expect("could not vote for removal");
+
+    let is_complete = |node: &net::Node<DynamicHoneyBadger<Vec<u8>, N>>| {
+        node.outputs()
+            .iter()
+            .any(|batch| batch.change().completed() == Some(&remove))
+    };
+    while !net.nodes().all(&is_complete) {
+        let (node_id, step) = net.crank_expect();
+        if !step.output.is_empty() && !is_complete(&net[node_id.clone()]) {
+            let _ = net
+                .send_input(node_id, Input::User(vec![1]))
+                .expect("could not send contribution");
+        }
+    }
+    net.verify_batches();
+}
+
+/// Dynamic Honey Badger: Node IDs don't need to be `usize`. Other integer types, fixed-size
+/// hashes and even types that don't implement `Rand`, like strings, work, too.
+#[test]
+fn non_usize_node_ids() {
+    remove_last_node((0..4).collect::<Vec<u16>>());
+    remove_last_node(vec![3u64 << 40, 5, 1 << 63, 42]);
+    remove_last_node((0..4).map(|i| [i * 37; 32]).collect::<Vec<[u8; 32]>>());
+    remove_last_node((0..4).map(|i| format!("node-{}", i)).collect::<Vec<String>>());
+}
@@ -0,0 +1,250 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::sync::Arc;
+
+use hbbft::binary_agreement::{self, BinaryAgreement};
+use hbbft::broadcast::{self, Broadcast};
+use hbbft::honey_badger::{self, HoneyBadger};
+use hbbft::{DistAlgorithm, Step};
+use net::proptest::{
+    gen_ba_message, gen_ba_mutation, gen_broadcast_message, gen_broadcast_mutation,
+    gen_hb_message, gen_hb_mutation, gen_seed, BaMutation, BroadcastMutation, HbMutation,
+    TestRngSeed,
+};
+use net::{NetBuilder, VirtualNet};
+use proptest::collection::vec;
+use proptest::prelude::ProptestConfig;
+
+type UsizeHoneyBadger = HoneyBadger<Vec<usize>, usize>;
+
+/// The ID of the faulty node that sends the fuzzed messages.
+const FAULTY_ID: usize = 0;
+
+/// Hands a message from the faulty node to every correct node, and checks that none of them
+/// panics or reports a fault by any other node.
+///
+/// The nodes may return an error, but if they don't, only the faulty node may be blamed. Their
+/// resulting steps are discarded.
+fn fuzz_message<D>(net: &mut VirtualNet<D>, msg: &D::Message)
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone,
+{
+    let correct_ids: Vec<usize> = net.correct_nodes().map(|node| *node.id()).collect();
+    for id in correct_ids {
+        let result = net[id]
+            .algorithm_mut()
+            .handle_message(&FAULTY_ID, msg.clone());
+        if let Ok(step) = result {
+            check_faults(id, &step);
+        }
+    }
+}
+
+/// Checks that the step only reports faults by the faulty node.
+fn check_faults<D: DistAlgorithm<NodeId = usize>>(id: usize, step: &Step<D>) {
+    for fault in &step.fault_log.0 {
+        assert_eq!(
+            fault.node_id, FAULTY_ID,
+            "node {} blamed a correct node: {:?}",
+            id, fault.kind
+        );
+    }
+}
+
+/// Creates a network of four Binary Agreement instances with mixed inputs, where node 0 is faulty.
+fn new_binary_agreement_net(seed: TestRngSeed) -> VirtualNet<BinaryAgreement<usize>> {
+    let mut net = NetBuilder::new(0..4)
+        .num_faulty(1)
+        .seed(seed)
+        .trace(false)
+        .using(|node| {
+            BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, id % 2 == 0)
+            .expect("could not send input");
+    }
+    net
+}
+
+/// Creates a network of four Broadcast instances, where node 0 is faulty and node 1 proposes a
+/// value.
+fn new_broadcast_net(seed: TestRngSeed) -> VirtualNet<Broadcast<usize>> {
+    let mut net = NetBuilder::new(0..4)
+        .num_faulty(1)
+        .seed(seed)
+        .trace(false)
+        .using(|node| {
+            Broadcast::new(Arc::new(node.netinfo), 1).expect("could not create broadcast")
+        }).build()
+        .expect("could not construct test network");
+    let _ = net
+        .send_input(1, b"Foo".to_vec())
+        .expect("could not send input");
+    net
+}
+
+/// Creates a network of four Honey Badger instances that have all proposed, where node 0 is
+/// faulty.
+fn new_honey_badger_net(seed: TestRngSeed) -> VirtualNet<UsizeHoneyBadger> {
+    let mut net = NetBuilder::new(0..4)
+        .num_faulty(1)
+        .seed(seed)
+        .trace(false)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, vec![id])
+            .expect("could not send input");
+    }
+    net
+}
+
+/// Runs the network until no messages are left. After every crank, each message sent by the node
+/// is mutated and then handed to all correct nodes as if it came from the faulty node.
+fn run_with_mutations<D, F>(net: &mut VirtualNet<D>, mut mutate: F)
+where
+    D: DistAlgorithm<NodeId = usize>,
+    D::Message: Clone,
+    F: FnMut(usize, D::Message) -> D::Message,
+{
+    let mut count = 0;
+    while let Some(result) = net.crank() {
+        let (_, step) = result.expect("crank: node failed to process step");
+        for msg in step.messages {
+            let fake = mutate(count, msg.message);
+            count += 1;
+            fuzz_message(net, &fake);
+        }
+    }
+}
+
+fn do_binary_agreement_random_messages(seed: TestRngSeed, msgs: Vec<binary_agreement::Message>) {
+    let mut net = new_binary_agreement_net(seed);
+    for msg in &msgs {
+        fuzz_message(&mut net, msg);
+    }
+}
+
+fn do_binary_agreement_mutated_messages(seed: TestRngSeed, mutations: Vec<BaMutation>) {
+    let mut net = new_binary_agreement_net(seed);
+    run_with_mutations(&mut net, |i, msg| mutations[i % mutations.len()].apply(msg));
+    assert!(net.correct_nodes().all(|node| node.outputs().len() <= 1));
+    net.check_outputs_agree().expect("correct nodes disagree");
+}
+
+fn do_broadcast_random_messages(seed: TestRngSeed, msgs: Vec<broadcast::Message>) {
+    let mut net = new_broadcast_net(seed);
+    for msg in &msgs {
+        fuzz_message(&mut net, msg);
+    }
+}
+
+fn do_broadcast_mutated_messages(seed: TestRngSeed, mutations: Vec<BroadcastMutation>) {
+    let mut net = new_broadcast_net(seed);
+    run_with_mutations(&mut net, |i, msg| mutations[i % mutations.len()].apply(msg));
+    for node in net.correct_nodes() {
+        assert!(node.outputs().len() <= 1);
+        assert!(node.outputs().iter().all(|value| *value == b"Foo".to_vec()));
+    }
+}
+
+fn do_honey_badger_random_messages(seed: TestRngSeed, msgs: Vec<honey_badger::Message<usize>>) {
+    let mut net = new_honey_badger_net(seed);
+    for msg in &msgs {
+        fuzz_message(&mut net, msg);
+    }
+}
+
+fn do_honey_badger_mutated_messages(seed: TestRngSeed, mutations: Vec<HbMutation>) {
+    let mut net = new_honey_badger_net(seed);
+    run_with_mutations(&mut net, |i, msg| mutations[i % mutations.len()].apply(&msg));
+    let mut correct_nodes = net.correct_nodes();
+    let first = correct_nodes.next().expect("no correct nodes");
+    for node in correct_nodes {
+        for (batch, expected) in node.outputs().iter().zip(first.outputs()) {
+            assert_eq!(batch.contributions, expected.contributions);
+        }
+    }
+}
+
+proptest!{
+    #![proptest_config(ProptestConfig {
+        cases: 20, .. ProptestConfig::default()
+    })]
+
+    /// Binary Agreement: Arbitrary messages from a faulty node are handled without panicking, and
+    /// don't cause correct nodes to be blamed.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn binary_agreement_random_messages(seed in gen_seed(),
+                                        msgs in vec(gen_ba_message(3), 1..50)) {
+        do_binary_agreement_random_messages(seed, msgs)
+    }
+
+    /// Binary Agreement: "Almost valid" messages from a faulty node don't make correct nodes panic,
+    /// blame each other or disagree.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn binary_agreement_mutated_messages(seed in gen_seed(),
+                                         mutations in vec(gen_ba_mutation(), 1..10)) {
+        do_binary_agreement_mutated_messages(seed, mutations)
+    }
+
+    /// Broadcast: Arbitrary messages from a faulty node are handled without panicking, and don't
+    /// cause correct nodes to be blamed.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn broadcast_random_messages(seed in gen_seed(),
+                                 msgs in vec(gen_broadcast_message(), 1..50)) {
+        do_broadcast_random_messages(seed, msgs)
+    }
+
+    /// Broadcast: "Almost valid" messages from a faulty node don't make correct nodes panic, blame
+    /// each other or output a different value.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn broadcast_mutated_messages(seed in gen_seed(),
+                                  mutations in vec(gen_broadcast_mutation(), 1..10)) {
+        do_broadcast_mutated_messages(seed, mutations)
+    }
+
+    /// Honey Badger: Arbitrary messages from a faulty node are handled without panicking, and
+    /// don't cause correct nodes to be blamed.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn honey_badger_random_messages(seed in gen_seed(),
+                                    msgs in vec(gen_hb_message(4, 3), 1..50)) {
+        do_honey_badger_random_messages(seed, msgs)
+    }
+
+    /// Honey Badger: "Almost valid" messages from a faulty node don't make correct nodes panic,
+    /// blame each other or output different batches.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn honey_badger_mutated_messages(seed in gen_seed(),
+                                     mutations in vec(gen_hb_mutation(4), 1..10)) {
+        do_honey_badger_mutated_messages(seed, mutations)
+    }
+}
@@ -0,0 +1,233 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hbbft::honey_badger::HoneyBadger;
+use net::schedule::{DeliverySchedule, ExponentialJitter, LatencyMatrix};
+use net::{CrankError, DeadNodePolicy, NetBuilder};
+
+type UsizeHoneyBadger = HoneyBadger<Vec<usize>, usize>;
+
+/// The maximum number of cranks in which a single epoch is expected to complete.
+const MAX_CRANKS: usize = 10_000;
+
+/// Honey Badger: Kill a node after it proposed, let the others finish the epoch, then restart it
+/// from a snapshot and check that it catches up.
+#[test]
+fn honey_badger_crash_recovery() {
+    let mut net = NetBuilder::new(0..4)
+        .dead_node_policy(DeadNodePolicy::Buffer)
+        .trace(false)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, vec![id])
+            .expect("could not send initial contribution");
+    }
+
+    // Node 3 crashes after proposing, but the remaining nodes can complete the epoch without it.
+    let pivot_node_id = 3;
+    let snapshot = net[pivot_node_id].algorithm().snapshot();
+    net.kill_node(pivot_node_id);
+    let _ = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("correct nodes did not output");
+    assert!(net[pivot_node_id].outputs().is_empty());
+
+    // After restarting, it handles the messages it missed and outputs the same batch.
+    let _ = net.restart_node(pivot_node_id, move |node| {
+        HoneyBadger::builder(Arc::new(node.netinfo))
+            .rng(node.rng)
+            .build_from_snapshot(snapshot)
+            .expect("could not restore from snapshot")
+    });
+    while net[pivot_node_id].outputs().is_empty() {
+        let _ = net.crank_expect();
+    }
+    let expected = &net[0].outputs()[0].contributions;
+    assert!(net.nodes().all(|node| node.outputs()[0].contributions == *expected));
+}
+
+/// Honey Badger: Restart a node from a snapshot taken in the middle of an epoch, while another
+/// node is dead. The epoch can then only complete if the restored node remembers the messages it
+/// had already handled, since the other nodes don't send them again.
+#[test]
+fn honey_badger_mid_epoch_restore() {
+    let mut net = NetBuilder::new(0..4)
+        .trace(false)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+
+    // Node 0 is dead, so nodes 1 and 2 are only _f + 1_ other correct nodes.
+    net.kill_node(0);
+    for id in 1..4 {
+        let _ = net
+            .send_input(id, vec![id])
+            .expect("could not send initial contribution");
+    }
+
+    // Let node 3 handle some of the others' messages, then restart it from a snapshot.
+    let pivot_node_id = 3;
+    let mut handled = 0;
+    while handled < 10 {
+        let (receiver, _) = net.crank_expect();
+        if receiver == pivot_node_id {
+            handled += 1;
+        }
+    }
+    assert!(net[pivot_node_id].outputs().is_empty());
+    let snapshot = net[pivot_node_id].algorithm().snapshot();
+    net.kill_node(pivot_node_id);
+    let _ = net.restart_node(pivot_node_id, move |node| {
+        HoneyBadger::builder(Arc::new(node.netinfo))
+            .rng(node.rng)
+            .build_from_snapshot(snapshot)
+            .expect("could not restore from snapshot")
+    });
+
+    let _ = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("correct nodes did not output");
+    let expected = &net[1].outputs()[0].contributions;
+    assert!(
+        net.nodes()
+            .filter(|node| !node.is_dead())
+            .all(|node| node.outputs()[0].contributions == *expected)
+    );
+}
+
+/// Runs a single Honey Badger epoch with the given delivery schedule, and returns the virtual time
+/// it took.
+fn run_honey_badger_with_schedule<S>(schedule: S) -> u64
+where
+    S: DeliverySchedule<usize> + 'static,
+{
+    let mut net = NetBuilder::new(0..4)
+        .delivery_schedule(schedule)
+        .trace(false)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, vec![id])
+            .expect("could not send initial contribution");
+    }
+    let _ = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("correct nodes did not output");
+    let expected = &net[0].outputs()[0].contributions;
+    assert!(net.nodes().all(|node| node.outputs()[0].contributions == *expected));
+    net.time()
+}
+
+/// Honey Badger: Completes an epoch with message latencies, which advance the virtual time.
+#[test]
+fn honey_badger_delivery_schedule() {
+    // Node 3's outgoing links are slow, but the others can complete the epoch without it.
+    let matrix = (0..3).fold(LatencyMatrix::new(1), |matrix, id| matrix.link(3, id, 1000));
+    let time = run_honey_badger_with_schedule(matrix);
+    assert!(time > 0);
+
+    let time = run_honey_badger_with_schedule(ExponentialJitter::new(10, 5.0));
+    assert!(time >= 10);
+}
+
+/// Honey Badger: No node can output while the network is split into two halves, but all of them
+/// output the same batch after the partition heals.
+#[test]
+fn honey_badger_partition() {
+    let mut net = NetBuilder::new(0..4)
+        .trace(false)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+
+    net.partition(vec![vec![0, 1], vec![2, 3]]);
+    assert!(!net.is_connected(&0, &2));
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, vec![id])
+            .expect("could not send initial contribution");
+    }
+    // Neither half has the `N - f` nodes needed to make progress.
+    match net.crank_until_all_output(MAX_CRANKS) {
+        Err(CrankError::Stalled {
+            pending_messages, ..
+        }) => assert!(pending_messages.is_empty()),
+        result => panic!("expected the network to stall, got {:?}", result),
+    }
+    assert!(net.nodes().all(|node| node.outputs().is_empty()));
+
+    net.heal();
+    let _ = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("correct nodes did not output");
+    let expected = &net[0].outputs()[0].contributions;
+    assert!(net.nodes().all(|node| node.outputs()[0].contributions == *expected));
+}
+
+/// Honey Badger: With weighted validators, the nodes whose total weight exceeds two thirds of the
+/// total complete the epoch, even if they are fewer than `N - f`.
+#[test]
+fn honey_badger_weighted_validators() {
+    let weights: BTreeMap<usize, u64> = vec![(0, 1), (1, 2), (2, 2), (3, 2), (4, 3)]
+        .into_iter()
+        .collect();
+    let mut net = NetBuilder::new(0..5)
+        .weights(weights)
+        .trace(false)
+        .using(|node| -> UsizeHoneyBadger {
+            HoneyBadger::builder(Arc::new(node.netinfo))
+                .rng(node.rng)
+                .build()
+        }).build()
+        .expect("could not construct test network");
+    assert_eq!(10, net[0].netinfo().total_weight());
+    assert_eq!(3, net[0].netinfo().faulty_weight());
+
+    // Nodes 0 and 1 have a total weight of 3, so the remaining 3 nodes can make progress.
+    net.kill_node(0);
+    net.kill_node(1);
+    for id in 2..5 {
+        let _ = net
+            .send_input(id, vec![id])
+            .expect("could not send initial contribution");
+    }
+    let _ = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("correct nodes did not output");
+    let expected = &net[2].outputs()[0].contributions;
+    assert_eq!(3, expected.len());
+    assert!((2..5).all(|id| net[id].outputs()[0].contributions == *expected));
+}
@@ -0,0 +1,104 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::sync::Arc;
+
+use hbbft::binary_agreement::BinaryAgreement;
+use hbbft::broadcast::Broadcast;
+
+use net::{LossyNetwork, NetBuilder};
+
+/// Runs a broadcast from node 0 in the given lossy network until no messages are left, and returns
+/// each node's outputs.
+fn run_lossy_broadcast(lossy: LossyNetwork) -> Vec<Vec<Vec<u8>>> {
+    let mut net = NetBuilder::new(0..7)
+        .lossy(lossy)
+        .seed([1, 2, 3, 4])
+        .trace(false)
+        .using(|node| {
+            Broadcast::new(Arc::new(node.netinfo), 0).expect("could not create broadcast")
+        }).build()
+        .expect("could not construct test network");
+
+    let _ = net
+        .send_input(0, b"Foo".to_vec())
+        .expect("could not send input");
+    while let Some(result) = net.crank() {
+        let _ = result.expect("crank: node failed to process step");
+    }
+    net.nodes().map(|node| node.outputs().to_vec()).collect()
+}
+
+/// Broadcast: Duplicated messages are not counted twice, so every node outputs the value once.
+#[test]
+fn broadcast_duplicates() {
+    for outputs in run_lossy_broadcast(LossyNetwork::new(0.0, 0.3)) {
+        assert_eq!(outputs, vec![b"Foo".to_vec()]);
+    }
+}
+
+/// Broadcast: If messages are lost, nodes may fail to terminate, but they never fail, output
+/// twice or output a different value.
+#[test]
+fn broadcast_message_loss() {
+    for outputs in run_lossy_broadcast(LossyNetwork::new(0.2, 0.1)) {
+        assert!(outputs.len() <= 1);
+        assert!(outputs.iter().all(|value| *value == b"Foo".to_vec()));
+    }
+}
+
+/// Runs binary agreement with mixed inputs in the given lossy network until no messages are left,
+/// and returns each node's outputs.
+fn run_lossy_binary_agreement(lossy: LossyNetwork) -> Vec<Vec<bool>> {
+    let mut net = NetBuilder::new(0..7)
+        .lossy(lossy)
+        .seed([1, 2, 3, 4])
+        .trace(false)
+        .using(|node| {
+            BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..7 {
+        let _ = net
+            .send_input(id, id % 2 == 0)
+            .expect("could not send input");
+    }
+    while let Some(result) = net.crank() {
+        let _ = result.expect("crank: node failed to process step");
+    }
+    net.nodes().map(|node| node.outputs().to_vec()).collect()
+}
+
+/// Binary Agreement: Duplicated messages are not counted twice, so all nodes decide on the same
+/// value exactly once.
+#[test]
+fn binary_agreement_duplicates() {
+    let outputs = run_lossy_binary_agreement(LossyNetwork::new(0.0, 0.3));
+    assert_eq!(outputs[0].len(), 1);
+    assert!(outputs.iter().all(|node_outputs| *node_outputs == outputs[0]));
+}
+
+/// Binary Agreement: If messages are lost, nodes may fail to decide, but they never fail, decide
+/// twice or disagree.
+#[test]
+fn binary_agreement_message_loss() {
+    let outputs = run_lossy_binary_agreement(LossyNetwork::new(0.2, 0.1));
+    assert!(outputs.iter().all(|node_outputs| node_outputs.len() <= 1));
+    let mut decisions = outputs.iter().flat_map(|node_outputs| node_outputs.iter());
+    if let Some(first) = decisions.next() {
+        assert!(decisions.all(|decision| decision == first));
+    }
+}
@@ -0,0 +1,97 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use hbbft::binary_agreement::{self, BinaryAgreement};
+use hbbft::{wire, DistAlgorithm};
+
+use net::mixed::{MixedFault, MixedNode};
+use net::NetBuilder;
+
+/// A Binary Agreement node whose messages are serialized.
+type MixedBa = MixedNode<usize, bool, bool, Vec<u8>>;
+
+/// The node that still runs the old version.
+const LEGACY_ID: usize = 3;
+
+/// Encodes a message in the current format, with the algorithm's tag.
+fn encode_tagged(msg: binary_agreement::Message) -> Vec<u8> {
+    wire::encode_message(&msg).expect("could not encode message")
+}
+
+/// Decodes a message in the current format.
+fn decode_tagged(bytes: Vec<u8>) -> Option<binary_agreement::Message> {
+    wire::decode_message(&bytes).ok()
+}
+
+/// Encodes a message in the old format, without a tag.
+fn encode_untagged(msg: binary_agreement::Message) -> Vec<u8> {
+    wire::encode(&msg).expect("could not encode message")
+}
+
+/// Decodes a message in the old format.
+fn decode_untagged(bytes: Vec<u8>) -> Option<binary_agreement::Message> {
+    wire::decode(&bytes).ok()
+}
+
+/// Binary Agreement: One node still uses the old, untagged message format. The upgraded nodes
+/// report all its messages as undecodable, and agree without it.
+#[test]
+fn binary_agreement_legacy_node() {
+    let mut net = NetBuilder::new(0..4)
+        .trace(false)
+        .using(|node| -> MixedBa {
+            let id = node.id;
+            let ba: BinaryAgreement<usize> = BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement");
+            if id == LEGACY_ID {
+                MixedNode::new(ba, encode_untagged, decode_untagged)
+            } else {
+                MixedNode::new(ba, encode_tagged, decode_tagged)
+            }
+        }).build()
+        .expect("could not construct test network");
+
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, id % 2 == 0)
+            .expect("could not send input");
+    }
+    let mut reporters = BTreeSet::new();
+    while let Some(result) = net.crank() {
+        let (id, step) = result.expect("crank: node failed to process step");
+        if id == LEGACY_ID {
+            continue;
+        }
+        for fault in step.fault_log.0 {
+            assert_eq!(LEGACY_ID, fault.node_id);
+            assert_eq!(MixedFault::UndecodableMessage, fault.kind);
+            reporters.insert(id);
+        }
+    }
+
+    assert_eq!((0..LEGACY_ID).collect::<BTreeSet<_>>(), reporters);
+    let decision = net[0].outputs().to_vec();
+    assert_eq!(1, decision.len());
+    for id in 0..LEGACY_ID {
+        assert_eq!(decision, net[id].outputs());
+        let ba: &BinaryAgreement<usize> = net[id]
+            .algorithm()
+            .algorithm()
+            .expect("node is not running Binary Agreement");
+        assert!(ba.terminated());
+    }
+}
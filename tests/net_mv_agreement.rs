@@ -0,0 +1,72 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::sync::Arc;
+
+use hbbft::mv_agreement::MvAgreement;
+use net::proptest::{gen_seed, TestRngSeed};
+use net::{NetBuilder, VirtualNet};
+use proptest::prelude::ProptestConfig;
+
+/// The maximum number of cranks in which the agreement is expected to complete.
+const MAX_CRANKS: usize = 100_000;
+
+type UsizeMvAgreement = MvAgreement<u64, usize>;
+
+/// Creates a network of four nodes, one of them faulty. Only even values are valid.
+fn new_mv_agreement_net(seed: TestRngSeed, session_id: u64) -> VirtualNet<UsizeMvAgreement> {
+    NetBuilder::new(0..4)
+        .num_faulty(1)
+        .seed(seed)
+        .trace(false)
+        .using(move |node| {
+            let mut mva = MvAgreement::new(Arc::new(node.netinfo), session_id)
+                .expect("could not create multi-valued agreement");
+            mva.set_validity_predicate(|value: &u64| value % 2 == 0);
+            mva
+        }).build()
+        .expect("could not construct test network")
+}
+
+/// Every node proposes a different value, and the faulty node's is invalid. All correct nodes
+/// output the same valid value, which was proposed by a correct node.
+fn do_mv_agreement(seed: TestRngSeed, session_id: u64) {
+    let mut net = new_mv_agreement_net(seed, session_id);
+    for id in 0..4 {
+        // The faulty node 0 proposes an odd value.
+        let value = 2 * id as u64 + if id == 0 { 1 } else { 10 };
+        let _ = net.send_input(id, value).expect("could not send input");
+    }
+    let _ = net
+        .crank_until_all_output(MAX_CRANKS)
+        .expect("correct nodes did not output");
+    net.check_outputs_agree().expect("correct nodes disagree");
+    for node in net.correct_nodes() {
+        assert_eq!(1, node.outputs().len());
+        assert!([12, 14, 16].contains(&node.outputs()[0]));
+    }
+}
+
+proptest!{
+    #![proptest_config(ProptestConfig {
+        cases: 10, .. ProptestConfig::default()
+    })]
+
+    /// Multi-valued Agreement: All correct nodes agree on a valid value.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn mv_agreement(seed in gen_seed(), session_id in 0..8u64) {
+        do_mv_agreement(seed, session_id)
+    }
+}
@@ -0,0 +1,172 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use rand::{SeedableRng, XorShiftRng};
+
+use hbbft::randomness_beacon::{FaultKind, RandomnessBeacon, DEFAULT_MAX_FUTURE_ROUNDS};
+use hbbft::validation::{Validation, Validity};
+use hbbft::{DistAlgorithm, NetworkInfo};
+use net::proptest::{gen_seed, TestRngSeed};
+use net::{NetBuilder, VirtualNet};
+use proptest::prelude::ProptestConfig;
+
+/// The maximum number of cranks in which a round is expected to complete.
+const MAX_CRANKS: usize = 10_000;
+
+/// Creates a network of four randomness beacons, one of them faulty.
+fn new_beacon_net(seed: TestRngSeed) -> VirtualNet<RandomnessBeacon<usize>> {
+    NetBuilder::new(0..4)
+        .num_faulty(1)
+        .seed(seed)
+        .trace(false)
+        .using(|node| RandomnessBeacon::new(Arc::new(node.netinfo), 0))
+        .build()
+        .expect("could not construct test network")
+}
+
+/// Returns the values the node output so far, by round.
+fn values_by_round(
+    net: &VirtualNet<RandomnessBeacon<usize>>,
+    id: usize,
+) -> BTreeMap<u64, [u8; 32]> {
+    net[id]
+        .outputs()
+        .iter()
+        .map(|output| (output.round, output.value))
+        .collect()
+}
+
+/// Every node requests the same rounds, in different orders. All nodes output the same value for
+/// each round, and different rounds have different values.
+fn do_beacon_rounds(seed: TestRngSeed) {
+    let mut net = new_beacon_net(seed);
+    let rounds = [5, 0, 1];
+    for id in 0..4 {
+        for i in 0..rounds.len() {
+            let round = rounds[(i + id) % rounds.len()];
+            let _ = net.send_input(id, round).expect("could not send input");
+        }
+    }
+    let _ = net
+        .crank_until(
+            |net| {
+                net.correct_nodes()
+                    .all(|node| node.outputs().len() == rounds.len())
+            },
+            MAX_CRANKS,
+        ).expect("beacon did not output");
+
+    let expected = values_by_round(&net, 3);
+    assert_eq!(rounds.len(), expected.len());
+    for id in 1..3 {
+        assert_eq!(expected, values_by_round(&net, id));
+    }
+    assert_ne!(expected[&0], expected[&1]);
+    assert_ne!(expected[&1], expected[&5]);
+}
+
+/// Only two nodes request a round at first, which suffices for them to output. The others output
+/// the same value once they request it, too.
+fn do_beacon_late_input(seed: TestRngSeed) {
+    let mut net = new_beacon_net(seed);
+    for id in 2..4 {
+        let _ = net.send_input(id, 7).expect("could not send input");
+    }
+    let _ = net
+        .crank_until(
+            |net| (2..4).all(|id| !net[id].outputs().is_empty()),
+            MAX_CRANKS,
+        ).expect("beacon did not output");
+    assert!(net[1].outputs().is_empty());
+    assert!(!net[1].algorithm().is_completed(7));
+
+    let _ = net.send_input(1, 7).expect("could not send input");
+    let _ = net
+        .crank_until(|net| !net[1].outputs().is_empty(), MAX_CRANKS)
+        .expect("late node did not output");
+    assert_eq!(net[1].outputs(), net[2].outputs());
+    assert_eq!(net[1].outputs(), net[3].outputs());
+}
+
+/// Messages for rounds too far beyond the ones we requested are rejected.
+#[test]
+fn beacon_round_too_far_ahead() {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let mut netinfos = NetworkInfo::generate_map(0..4, &mut rng).expect("generate netinfos");
+    let mut beacon = RandomnessBeacon::new(Arc::new(netinfos.remove(&0).expect("netinfo")), 0);
+    let mut sender = RandomnessBeacon::new(Arc::new(netinfos.remove(&1).expect("netinfo")), 0);
+
+    let far_round = DEFAULT_MAX_FUTURE_ROUNDS + 1;
+    let step = sender.handle_input(far_round).expect("request round");
+    let msg = step.messages.into_iter().next().expect("signature share").message;
+    let step = beacon.handle_message(&1, msg.clone()).expect("handle message");
+    let too_far_ahead = Validity::Faulty(FaultKind::BeaconRoundTooFarAhead);
+    assert_eq!(vec![Validation::new(1, too_far_ahead)], step.validation.0);
+
+    // Once we requested a later round ourselves, the message is accepted.
+    let _ = beacon.handle_input(1).expect("request round");
+    let step = beacon.handle_message(&1, msg).expect("handle message");
+    assert_eq!(vec![Validation::new(1, Validity::Valid)], step.validation.0);
+}
+
+/// Messages for abandoned rounds are ignored, and messages from unknown nodes are rejected
+/// before a coin is created for them.
+#[test]
+fn beacon_skip_to_round() {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let mut netinfos = NetworkInfo::generate_map(0..4, &mut rng).expect("generate netinfos");
+    let mut beacon = RandomnessBeacon::new(Arc::new(netinfos.remove(&0).expect("netinfo")), 0);
+    let mut sender = RandomnessBeacon::new(Arc::new(netinfos.remove(&1).expect("netinfo")), 0);
+    let mut share_for = |round| {
+        let step = sender.handle_input(round).expect("request round");
+        step.messages.into_iter().next().expect("signature share").message
+    };
+    let (old_msg, new_msg) = (share_for(3), share_for(5));
+
+    beacon.skip_to_round(5);
+    assert!(beacon.is_completed(3));
+    assert!(!beacon.is_completed(5));
+    let step = beacon.handle_message(&1, old_msg).expect("handle message");
+    assert_eq!(vec![Validation::new(1, Validity::Obsolete)], step.validation.0);
+
+    let step = beacon.handle_message(&9, new_msg.clone()).expect("handle message");
+    let unknown = Validity::Faulty(FaultKind::UnknownSender);
+    assert_eq!(vec![Validation::new(9, unknown)], step.validation.0);
+
+    let step = beacon.handle_message(&1, new_msg).expect("handle message");
+    assert_eq!(vec![Validation::new(1, Validity::Valid)], step.validation.0);
+}
+
+proptest!{
+    #![proptest_config(ProptestConfig {
+        cases: 10, .. ProptestConfig::default()
+    })]
+
+    /// Randomness Beacon: All correct nodes output the same value for each round.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn beacon_rounds(seed in gen_seed()) {
+        do_beacon_rounds(seed)
+    }
+
+    /// Randomness Beacon: Nodes that request a round late output the same value.
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(unnecessary_operation))]
+    fn beacon_late_input(seed in gen_seed()) {
+        do_beacon_late_input(seed)
+    }
+}
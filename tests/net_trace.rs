@@ -0,0 +1,80 @@
+extern crate bincode;
+extern crate failure;
+extern crate hbbft;
+#[macro_use]
+extern crate proptest;
+extern crate integer_sqrt;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto;
+
+pub mod net;
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::{env, fs, process};
+
+use hbbft::binary_agreement::BinaryAgreement;
+
+use net::adversary::DelayingAdversary;
+use net::trace::read_trace;
+use net::{LossyNetwork, NetBuilder, TraceEvent, VirtualNet};
+
+/// Creates a network of four binary agreement instances, one of them faulty, with mixed inputs.
+fn new_binary_agreement_net(
+    builder: NetBuilder<BinaryAgreement<usize>, Range<usize>>,
+) -> VirtualNet<BinaryAgreement<usize>> {
+    let mut net = builder
+        .num_faulty(1)
+        .seed([5, 6, 7, 8])
+        .adversary(DelayingAdversary::new(3))
+        .using(|node| {
+            BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+    for id in 0..4 {
+        let _ = net
+            .send_input(id, id % 2 == 0)
+            .expect("could not send input");
+    }
+    net
+}
+
+/// Records a trace of a binary agreement run with a reordering adversary and message duplication,
+/// and replays it on a new network, which then produces the same outputs.
+#[test]
+fn binary_agreement_trace_replay() {
+    let path = env::temp_dir().join(format!("hbbft-net-trace-replay-{}.trace", process::id()));
+
+    let outputs: Vec<Vec<bool>> = {
+        let mut net = new_binary_agreement_net(
+            NetBuilder::new(0..4)
+                .lossy(LossyNetwork::new(0.0, 0.1))
+                .trace(&path),
+        );
+        while let Some(result) = net.crank() {
+            let _ = result.expect("crank: node failed to process step");
+        }
+        net.nodes().map(|node| node.outputs().to_vec()).collect()
+    };
+
+    let trace = read_trace(fs::File::open(&path).expect("could not open trace file"))
+        .expect("could not read trace");
+    fs::remove_file(&path).expect("could not remove trace file");
+    let output_count = trace
+        .iter()
+        .filter(|event| match event {
+            TraceEvent::Output { .. } => true,
+            _ => false,
+        }).count();
+    assert_eq!(output_count, 4);
+
+    let mut net = new_binary_agreement_net(NetBuilder::new(0..4).trace(false));
+    let _ = net.replay(&trace).expect("could not replay trace");
+    let replayed_outputs: Vec<Vec<bool>> =
+        net.nodes().map(|node| node.outputs().to_vec()).collect();
+    assert_eq!(outputs, replayed_outputs);
+}
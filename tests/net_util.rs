@@ -1,3 +1,4 @@
+extern crate bincode;
 extern crate failure;
 extern crate hbbft;
 #[macro_use]
@@ -5,16 +6,25 @@ extern crate proptest;
 extern crate integer_sqrt;
 extern crate rand;
 extern crate rand_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate threshold_crypto;
 
 pub mod net;
 
+use std::sync::Arc;
+
+use hbbft::binary_agreement::BinaryAgreement;
+use hbbft::broadcast::Broadcast;
 use proptest::arbitrary::any;
 use proptest::prelude::RngCore;
 use proptest::strategy::{Strategy, ValueTree};
 use rand::{Rng as Rng4, SeedableRng as SeedableRng4};
 
 use net::proptest::{max_sum, NetworkDimension, NetworkDimensionTree};
+use net::stats::message_kind;
+use net::{BuildError, CrankError, NetBuilder, NewNodeInfo};
 
 struct RngAdapter4To5<T>(pub T);
 
@@ -218,3 +228,166 @@ fn network_from_u32_is_correct() {
     assert_eq!(NetworkDimension::new(13, 3), NetworkDimension::from(33u32));
     assert_eq!(NetworkDimension::new(13, 4), NetworkDimension::from(34u32));
 }
+
+#[derive(Debug)]
+enum Nested {
+    Outer(Box<Nested>),
+    Inner(bool),
+    Plain,
+}
+
+#[test]
+fn message_kind_names_nested_variants() {
+    let msg = Nested::Outer(Box::new(Nested::Inner(true)));
+    assert_eq!(message_kind(&msg), "Outer::Inner");
+    assert_eq!(message_kind(&Nested::Plain), "Plain");
+}
+
+/// Broadcast sends `N - 1` `Value` messages, and `N * (N - 1)` `Ready` and `CanDecode` messages
+/// each. Every node sends an `Echo` or an `EchoHash` to each other node, and possibly resends its
+/// `Echo` later, as a fallback or in response to an `EchoRequest`.
+#[test]
+fn stats_count_broadcast_messages() {
+    let mut net = NetBuilder::new(0..4)
+        .trace(false)
+        .stats()
+        .using(|node| {
+            Broadcast::new(Arc::new(node.netinfo), 0).expect("could not create broadcast")
+        }).build()
+        .expect("could not construct test network");
+    let _ = net
+        .send_input(0, b"Foo".to_vec())
+        .expect("could not send input");
+    let _ = net
+        .crank_until_all_output(1_000)
+        .expect("nodes did not output");
+    while let Some(result) = net.crank() {
+        let _ = result.expect("crank: node failed to process step");
+    }
+
+    let stats = net.stats();
+    assert_eq!(stats.kind("Value").messages, 3);
+    assert_eq!(stats.kind("Ready").messages, 12);
+    assert_eq!(stats.kind("CanDecode").messages, 12);
+    let echos = stats.kind("Echo").messages + stats.kind("EchoHash").messages;
+    let requests = stats.kind("EchoRequest").messages;
+    assert!(echos >= 12 && echos <= 20 + requests);
+    assert_eq!(stats.total().messages, 3 + 12 + 12 + echos + requests);
+    assert!(stats.total().bytes > 0);
+    assert!(stats.by_receiver().values().all(|counter| counter.bytes > 0));
+}
+
+/// With `f = 0`, a node's own `BVal` immediately makes it send `Aux`, too, so the two are merged
+/// into a single `BValAux` message to each of the other `N - 1` nodes.
+#[test]
+fn stats_count_combined_sbv_messages() {
+    let mut net = NetBuilder::new(0..3)
+        .trace(false)
+        .stats()
+        .using(|node| {
+            BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+                .expect("could not create binary agreement")
+        }).build()
+        .expect("could not construct test network");
+    for id in 0..3 {
+        let _ = net.send_input(id, true).expect("could not send input");
+    }
+    let _ = net
+        .crank_until_all_output(1_000)
+        .expect("nodes did not output");
+
+    let stats = net.stats();
+    assert!(stats.kind("SbvBroadcast::BValAux").messages >= 6);
+}
+
+/// Constructs a Binary Agreement node for the builder tests.
+fn new_binary_agreement(node: NewNodeInfo<BinaryAgreement<usize>>) -> BinaryAgreement<usize> {
+    BinaryAgreement::new(Arc::new(node.netinfo), "test", 0)
+        .expect("could not create binary agreement")
+}
+
+/// Invalid builder configurations are reported as errors, and `num_nodes` creates the nodes
+/// `0..num_nodes`.
+#[test]
+fn builder_reports_invalid_configurations() {
+    let result = NetBuilder::default()
+        .num_nodes(3)
+        .num_faulty(1)
+        .trace(false)
+        .using(new_binary_agreement)
+        .build();
+    match result {
+        Err(BuildError::TooManyFaulty {
+            num_nodes: 3,
+            num_faulty: 1,
+        }) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    match NetBuilder::<BinaryAgreement<usize>, _>::default()
+        .num_nodes(4)
+        .trace(false)
+        .build()
+    {
+        Err(BuildError::NoConstructor) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    let net = NetBuilder::default()
+        .num_nodes(4)
+        .num_faulty(1)
+        .trace(false)
+        .using(new_binary_agreement)
+        .build()
+        .expect("could not construct test network");
+    let ids: Vec<usize> = net.nodes().map(|node| *node.id()).collect();
+    assert_eq!(vec![0, 1, 2, 3], ids);
+    assert_eq!(1, net.faulty_nodes().count());
+}
+
+/// When a limit is exceeded, the error summarizes the queued messages.
+#[test]
+fn limits_report_queue_stats() {
+    let new_broadcast = |limit_cranks: bool| {
+        let builder = NetBuilder::new(0..4).trace(false);
+        let builder = if limit_cranks {
+            builder.crank_limit(5)
+        } else {
+            builder.queue_limit(2)
+        };
+        let mut net = builder
+            .using(|node| {
+                Broadcast::new(Arc::new(node.netinfo), 0).expect("could not create broadcast")
+            }).build()
+            .expect("could not construct test network");
+        let _ = net
+            .send_input(0, b"Foo".to_vec())
+            .expect("could not send input");
+        net
+    };
+
+    // The proposer's three `Value` messages alone exceed the queue limit right away.
+    let mut net = new_broadcast(false);
+    match net.crank() {
+        Some(Err(CrankError::QueueLimitExceeded { limit: 2, queue })) => {
+            assert_eq!(0, queue.cranks);
+            assert_eq!(Some(&3), queue.by_kind.get("Value"));
+            let by_sender: Vec<_> = queue.by_sender.into_iter().collect();
+            assert_eq!(vec![(0, queue.queued)], by_sender);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    let mut net = new_broadcast(true);
+    for _ in 0..5 {
+        let _ = net.crank_expect();
+    }
+    match net.crank() {
+        Some(Err(CrankError::CrankLimitExceeded { limit: 5, queue })) => {
+            assert_eq!(5, queue.cranks);
+            assert_eq!(net.messages().count(), queue.queued);
+            assert_eq!(queue.queued, queue.by_kind.values().sum::<usize>());
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
@@ -4,7 +4,7 @@ use std::mem;
 use std::sync::Arc;
 
 use crypto::SecretKeyShare;
-use rand::{self, Rng};
+use rand::{self, Rng, SeedableRng, XorShiftRng};
 
 use hbbft::dynamic_honey_badger::Batch;
 use hbbft::{Contribution, DistAlgorithm, NetworkInfo, Step, Target, TargetedMessage};
@@ -300,6 +300,8 @@ impl<D: DistAlgorithm, F: Fn() -> TargetedMessage<D::Message, D::NodeId>> Advers
                 // here and use a random one as the origin/sender, this is not done here.
                 return;
             }
+            // Like broadcast messages, these are not replayed.
+            Target::AllExcept(_) | Target::Nodes(_) | Target::Observers => return,
             Target::Node(our_node_id) => {
                 // Choose a new target to send the message to. The unwrap never fails, because we
                 // ensured that `known_node_ids` is non-empty earlier.
@@ -464,6 +466,29 @@ where
                         .push_back((sender_id, msg.message.clone()));
                     self.adversary.push_message(sender_id, msg);
                 }
+                Target::AllExcept(exclude) => {
+                    for node in self.nodes.values_mut() {
+                        if node.id != sender_id && !exclude.contains(&node.id) {
+                            node.queue.push_back((sender_id, msg.message.clone()))
+                        }
+                    }
+                    self.observer
+                        .queue
+                        .push_back((sender_id, msg.message.clone()));
+                    if self.adv_nodes.keys().any(|id| !exclude.contains(id)) {
+                        let tmsg = Target::AllExcept(exclude).message(msg.message);
+                        self.adversary.push_message(sender_id, tmsg);
+                    }
+                }
+                Target::Nodes(ids) => {
+                    for to_id in ids {
+                        let tmsg = Target::Node(to_id).message(msg.message.clone());
+                        self.dispatch_messages(sender_id, Some(tmsg));
+                    }
+                }
+                Target::Observers => {
+                    self.observer.queue.push_back((sender_id, msg.message));
+                }
                 Target::Node(to_id) => {
                     if self.adv_nodes.contains_key(&to_id) {
                         self.adversary.push_message(sender_id, msg);
@@ -566,3 +591,17 @@ where
         }
     }
 }
+
+/// Generates the `NetworkInfo`s of nodes `0` to `num_nodes - 1` from a fixed seed, and returns
+/// the one of `our_id` together with the others.
+#[allow(unused)] // Not used in all tests.
+pub fn test_netinfos(
+    num_nodes: usize,
+    our_id: NodeId,
+) -> (Arc<NetworkInfo<NodeId>>, BTreeMap<NodeId, NetworkInfo<NodeId>>) {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let mut netinfos = NetworkInfo::generate_map((0..num_nodes).map(NodeId), &mut rng)
+        .expect("Failed to create `NetworkInfo` map");
+    let netinfo = Arc::new(netinfos.remove(&our_id).expect("our `NetworkInfo`"));
+    (netinfo, netinfos)
+}
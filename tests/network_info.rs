@@ -0,0 +1,58 @@
+#![deny(unused_must_use)]
+//! Tests of persisting a `NetworkInfo`.
+
+extern crate bincode;
+extern crate hbbft;
+extern crate rand;
+extern crate serde;
+extern crate threshold_crypto as crypto;
+
+use std::collections::BTreeMap;
+
+use crypto::{PublicKeySet, SecretKey, SecretKeyShare};
+use rand::{SeedableRng, XorShiftRng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use hbbft::{NetworkInfo, NetworkInfoError, NetworkInfoSecrets, PublicNetworkInfo};
+
+/// Compiles only if `T` can be serialized and deserialized.
+fn assert_serde<T: Serialize + DeserializeOwned>() {}
+
+/// Persisting a `NetworkInfo` relies on these `threshold_crypto` types implementing `serde`'s
+/// traits, as they do in the version this crate depends on.
+#[test]
+fn test_key_types_are_serializable() {
+    assert_serde::<PublicKeySet>();
+    assert_serde::<SecretKey>();
+    assert_serde::<SecretKeyShare>();
+}
+
+#[test]
+fn test_network_info_round_trip() {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let weights: BTreeMap<usize, u64> = (0..4).map(|id| (id, 1 + id as u64 / 3)).collect();
+    let netinfos =
+        NetworkInfo::generate_weighted_map(weights, &mut rng).expect("generate netinfos");
+    let netinfo = &netinfos[&2];
+
+    let ser_public = bincode::serialize(&netinfo.public_info()).expect("serialize public info");
+    let ser_secrets = bincode::serialize(&netinfo.export_secrets()).expect("serialize secrets");
+    let public_info: PublicNetworkInfo<usize> =
+        bincode::deserialize(&ser_public).expect("deserialize public info");
+    let secrets: NetworkInfoSecrets =
+        bincode::deserialize(&ser_secrets).expect("deserialize secrets");
+    assert_eq!(2, *public_info.our_id());
+
+    let restored = NetworkInfo::from_parts(public_info, secrets).expect("restore netinfo");
+    assert_eq!(netinfo.public_key_set(), restored.public_key_set());
+    assert_eq!(netinfo.public_key_map(), restored.public_key_map());
+    assert_eq!(netinfo.secret_key(), restored.secret_key());
+    assert_eq!(netinfo.secret_key_share(), restored.secret_key_share());
+    assert_eq!(netinfo.node_weight(&3), restored.node_weight(&3));
+    assert_eq!(netinfo.num_faulty(), restored.num_faulty());
+
+    // Another node's secrets don't match our public keys.
+    let result = NetworkInfo::from_parts(netinfo.public_info(), netinfos[&1].export_secrets());
+    assert_eq!(Err(NetworkInfoError::KeyMismatch), result.map(|_| ()));
+}
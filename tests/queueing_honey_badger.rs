@@ -23,9 +23,11 @@ use rand::{Isaac64Rng, Rng};
 
 use hbbft::dynamic_honey_badger::DynamicHoneyBadger;
 use hbbft::queueing_honey_badger::{Batch, Change, ChangeState, Input, QueueingHoneyBadger, Step};
-use hbbft::NetworkInfo;
+use hbbft::{DistAlgorithm, NetworkInfo};
 
-use network::{Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode};
+use network::{
+    test_netinfos, Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode,
+};
 
 type QHB = QueueingHoneyBadger<usize, NodeId, Vec<usize>>;
 
@@ -43,12 +45,12 @@ where
     fn has_remove(node: &TestNode<QHB>) -> bool {
         node.outputs()
             .iter()
-            .any(|batch| *batch.change() == ChangeState::Complete(Change::Remove(NodeId(0))))
+            .any(|batch| batch.change().completed() == Some(&Change::Remove(NodeId(0))))
     }
 
     fn has_add(node: &TestNode<QHB>) -> bool {
         node.outputs().iter().any(|batch| match *batch.change() {
-            ChangeState::Complete(Change::Add(ref id, _)) => *id == NodeId(0),
+            ChangeState::Complete(Change::Add(ref id, _), _) => *id == NodeId(0),
             _ => false,
         })
     }
@@ -132,3 +134,90 @@ fn test_queueing_honey_badger_first_delivery_silent() {
     let new_adversary = |_: usize, _: usize, _| SilentAdversary::new(MessageScheduler::First);
     test_queueing_honey_badger_different_sizes(new_adversary, 30);
 }
+
+#[test]
+fn test_queueing_honey_badger_deduplication() {
+    let mut rng = rand::thread_rng();
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let dyn_hb = DynamicHoneyBadger::builder().build((*netinfo).clone());
+    let (mut qhb, _) = QueueingHoneyBadger::builder(dyn_hb)
+        .batch_size(3)
+        .committed_history(1)
+        .build(rng.gen::<Isaac64Rng>());
+
+    // A single validator outputs its own contribution right away.
+    let committed = |step: Step<usize, NodeId, Vec<usize>>| -> Vec<usize> {
+        step.output.iter().flat_map(Batch::iter).cloned().collect()
+    };
+    let step = qhb.handle_input(Input::User(1)).expect("input 1");
+    assert_eq!(vec![1], committed(step));
+    assert!(qhb.queue().is_empty());
+
+    // Transaction 1 has been committed recently, so it is ignored.
+    let step = qhb.handle_input(Input::User(1)).expect("input 1 again");
+    assert!(committed(step).is_empty());
+    assert!(qhb.queue().is_empty());
+
+    // Transaction 2 replaces 1 in the history, which only has room for one.
+    let step = qhb.handle_input(Input::User(2)).expect("input 2");
+    assert_eq!(vec![2], committed(step));
+    let step = qhb.handle_input(Input::User(1)).expect("input 1 after 2");
+    assert_eq!(vec![1], committed(step));
+}
+
+#[test]
+fn test_queueing_honey_badger_committed_history_is_lru() {
+    let mut rng = rand::thread_rng();
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let dyn_hb = DynamicHoneyBadger::builder().build((*netinfo).clone());
+    let (mut qhb, _) = QueueingHoneyBadger::builder(dyn_hb)
+        .batch_size(3)
+        .committed_history(2)
+        .build(rng.gen::<Isaac64Rng>());
+
+    let committed = |step: Step<usize, NodeId, Vec<usize>>| -> Vec<usize> {
+        step.output.iter().flat_map(Batch::iter).cloned().collect()
+    };
+    for tx in 1..3 {
+        let step = qhb.handle_input(Input::User(tx)).expect("input");
+        assert_eq!(vec![tx], committed(step));
+    }
+
+    // Inputting 1 again makes 2 the least recently used entry, so 3 replaces 2 instead of 1.
+    let step = qhb.handle_input(Input::User(1)).expect("input 1 again");
+    assert!(committed(step).is_empty());
+    let step = qhb.handle_input(Input::User(3)).expect("input 3");
+    assert_eq!(vec![3], committed(step));
+    let step = qhb.handle_input(Input::User(1)).expect("input 1 after 3");
+    assert!(committed(step).is_empty());
+    let step = qhb.handle_input(Input::User(2)).expect("input 2 after 3");
+    assert_eq!(vec![2], committed(step));
+}
+
+#[test]
+fn test_queueing_honey_badger_sample_window_and_min_queue_depth() {
+    let mut rng = rand::thread_rng();
+    let (netinfo, _) = test_netinfos(1, NodeId(0));
+    let dyn_hb = DynamicHoneyBadger::builder().build((*netinfo).clone());
+    let (mut qhb, _) = QueueingHoneyBadger::builder(dyn_hb)
+        .batch_size(3)
+        .sample_window(1)
+        .min_queue_depth(2)
+        .build(rng.gen::<Isaac64Rng>());
+
+    let committed = |step: Step<usize, NodeId, Vec<usize>>| -> Vec<usize> {
+        step.output.iter().flat_map(Batch::iter).cloned().collect()
+    };
+    // A single transaction is not enough to make a proposal.
+    let step = qhb.handle_input(Input::User(1)).expect("input 1");
+    assert!(committed(step).is_empty());
+
+    // With two, we propose, but only sample from the first entry in the queue.
+    let step = qhb.handle_input(Input::User(2)).expect("input 2");
+    assert_eq!(vec![1], committed(step));
+    assert!(!qhb.queue().is_empty());
+
+    let step = qhb.handle_input(Input::User(3)).expect("input 3");
+    assert_eq!(vec![2], committed(step));
+    assert_eq!(vec![3], *qhb.queue());
+}
@@ -0,0 +1,194 @@
+#![deny(unused_must_use)]
+//! Network tests for Honey Badger wrapped in a sender queue.
+
+extern crate env_logger;
+extern crate hbbft;
+#[macro_use]
+extern crate log;
+extern crate rand;
+#[macro_use]
+extern crate rand_derive;
+#[macro_use]
+extern crate serde_derive;
+extern crate threshold_crypto as crypto;
+
+mod network;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use hbbft::honey_badger::{Batch, FaultKind, HoneyBadger, MessageContent};
+use hbbft::sender_queue::{Message, SenderQueue, Step};
+use hbbft::subset::Message::BinaryAgreements;
+use hbbft::validation::{Validation, Validity};
+use hbbft::{DistAlgorithm, HbEpoch, NetworkInfo, Target};
+
+use network::{
+    test_netinfos, Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode,
+};
+
+type UsizeHoneyBadger = HoneyBadger<Vec<usize>, NodeId>;
+type SqHoneyBadger = SenderQueue<UsizeHoneyBadger>;
+
+/// Runs the network until every node has output `num_epochs` batches, and verifies that they all
+/// output the same sequence.
+fn test_sender_queue<A>(mut network: TestNetwork<A, SqHoneyBadger>, num_epochs: usize)
+where
+    A: Adversary<SqHoneyBadger>,
+{
+    let mut rng = rand::thread_rng();
+    let node_busy = |node: &TestNode<SqHoneyBadger>| node.outputs().len() < num_epochs;
+
+    while network.nodes.values().any(node_busy) {
+        // If a node is expecting input, give it a contribution. Otherwise handle a message.
+        let input_ids: Vec<_> = network
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.instance().algo().has_input())
+            .map(|(id, _)| *id)
+            .collect();
+        if let Some(id) = rng.choose(&input_ids) {
+            let contribution = vec![id.0, network.nodes[id].outputs().len()];
+            network.input(*id, contribution);
+        } else {
+            network.step();
+        }
+    }
+
+    let mut expected: Option<Vec<_>> = None;
+    for node in network.nodes.values() {
+        assert!(node.instance().algo().epoch().get() >= num_epochs as u64);
+        // The silent faulty nodes never announce an epoch, so they appear to be lagging.
+        let lagging: Vec<_> = node
+            .instance()
+            .lagging_peers(num_epochs as u64 - 1)
+            .collect();
+        assert!(network.adv_nodes.keys().all(|id| lagging.contains(&id)));
+        let outputs: Vec<_> = node.outputs()[..num_epochs]
+            .iter()
+            .map(|batch: &Batch<_, _>| (batch.epoch, &batch.contributions))
+            .collect();
+        if let Some(ref expected) = expected {
+            assert_eq!(expected, &outputs);
+        }
+        if expected.is_none() {
+            expected = Some(outputs);
+        }
+    }
+}
+
+fn new_sender_queue(netinfo: Arc<NetworkInfo<NodeId>>) -> (SqHoneyBadger, Step<UsizeHoneyBadger>) {
+    let peer_ids: Vec<NodeId> = netinfo.all_ids().cloned().collect();
+    let hb = HoneyBadger::builder(netinfo).max_future_epochs(0).build();
+    SenderQueue::new(hb, peer_ids)
+}
+
+fn test_sender_queue_different_sizes<A, F>(new_adversary: F, num_epochs: usize)
+where
+    A: Adversary<SqHoneyBadger>,
+    F: Fn(usize, usize, BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>>) -> A,
+{
+    // This returns an error in all but the first test.
+    let _ = env_logger::try_init();
+
+    let mut rng = rand::thread_rng();
+    let sizes = vec![1, 2, 3, 5, rng.gen_range(6, 10)];
+    for size in sizes {
+        let num_adv_nodes = (size - 1) / 3;
+        let num_good_nodes = size - num_adv_nodes;
+        info!(
+            "Network size: {} good nodes, {} faulty nodes",
+            num_good_nodes, num_adv_nodes
+        );
+        let adversary = |adv_nodes| new_adversary(num_good_nodes, num_adv_nodes, adv_nodes);
+        let network =
+            TestNetwork::new_with_step(num_good_nodes, num_adv_nodes, adversary, new_sender_queue);
+        test_sender_queue(network, num_epochs);
+    }
+}
+
+#[test]
+fn test_sender_queue_random_delivery_silent() {
+    let new_adversary = |_: usize, _: usize, _| SilentAdversary::new(MessageScheduler::Random);
+    test_sender_queue_different_sizes(new_adversary, 5);
+}
+
+#[test]
+fn test_sender_queue_first_delivery_silent() {
+    let new_adversary = |_: usize, _: usize, _| SilentAdversary::new(MessageScheduler::First);
+    test_sender_queue_different_sizes(new_adversary, 5);
+}
+
+#[test]
+fn test_sender_queue_skip_to_epoch() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let (mut sq, _) = new_sender_queue(netinfo);
+
+    // The new epoch is announced to everyone.
+    let epoch3 = HbEpoch::new(3);
+    let step = sq.skip_to_epoch(epoch3).expect("skip to epoch 3");
+    assert_eq!(epoch3, sq.algo().epoch());
+    let announcements: Vec<_> = step
+        .messages
+        .into_iter()
+        .map(|msg| (msg.target, msg.message))
+        .filter_map(|(target, msg)| match msg {
+            Message::EpochStarted(epoch) => Some((target, epoch)),
+            Message::Algo(_) => None,
+        }).collect();
+    assert_eq!(vec![(Target::All, epoch3)], announcements);
+}
+
+#[test]
+fn test_sender_queue_validation() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let (mut sq, _) = new_sender_queue(netinfo);
+
+    // The wrapped algorithm's validation log is passed on.
+    let content = MessageContent::Subset(BinaryAgreements(Vec::new()));
+    let msg = Message::Algo(content.with_epoch(HbEpoch::new(0)));
+    let step = sq.handle_message(&NodeId(9), msg).expect("handle message");
+    let expected = Validation::new(NodeId(9), Validity::Faulty(FaultKind::UnknownSender));
+    assert_eq!(vec![expected], step.validation.0);
+}
+
+#[test]
+fn test_sender_queue_lag_threshold() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let (mut sq, _) = new_sender_queue(netinfo);
+    sq.set_lag_threshold(Some(1));
+
+    // All peers are still in epoch 0, more than one epoch behind us.
+    let _ = sq.skip_to_epoch(HbEpoch::new(3)).expect("skip to epoch 3");
+    let epoch0 = HbEpoch::new(0);
+    let expected = vec![(NodeId(1), epoch0), (NodeId(2), epoch0), (NodeId(3), epoch0)];
+    assert_eq!(expected, sq.take_lag_reports());
+    assert!(sq.take_lag_reports().is_empty());
+
+    // Node 1 catches up, but falls behind again. The others are not reported a second time.
+    let msg = Message::EpochStarted(HbEpoch::new(3));
+    let _ = sq.handle_message(&NodeId(1), msg).expect("handle epoch started");
+    assert!(sq.take_lag_reports().is_empty());
+    let _ = sq.skip_to_epoch(HbEpoch::new(5)).expect("skip to epoch 5");
+    assert_eq!(vec![(NodeId(1), HbEpoch::new(3))], sq.take_lag_reports());
+}
+
+#[test]
+fn test_sender_queue_max_queued_per_peer() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let (mut sq, _) = new_sender_queue(netinfo);
+    sq.set_max_queued_per_peer(Some(1));
+
+    // The peers are still in epoch 0, so our proposal for epoch 1 is premature for all of them,
+    // but only one message is held back for each.
+    let _ = sq.skip_to_epoch(HbEpoch::new(1)).expect("skip to epoch 1");
+    let _ = sq.handle_input(vec![0]).expect("propose");
+    assert_eq!(3, sq.queued_messages());
+
+    // Removed peers are not tracked anymore.
+    sq.remove_peers(vec![NodeId(1)]);
+    assert_eq!(2, sq.queued_messages());
+    assert!(!sq.peer_epochs().contains_key(&NodeId(1)));
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "state-machine")]
+#![deny(unused_must_use)]
+//! Tests of the pure state machine API
+
+extern crate hbbft;
+extern crate rand;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use hbbft::binary_agreement::BinaryAgreement;
+use hbbft::coin::InsecureCoin;
+use hbbft::state_machine::{step, Event, PureAlgorithm};
+use hbbft::subset::{Subset, SubsetOutput};
+use hbbft::{DistAlgorithm, NetworkInfo, Target};
+
+type InsecureBa = BinaryAgreement<usize, InsecureCoin<usize>>;
+
+const NUM_NODES: usize = 4;
+
+/// Generates the `NetworkInfo`s of all nodes.
+fn netinfos() -> BTreeMap<usize, Arc<NetworkInfo<usize>>> {
+    NetworkInfo::generate_map(0..NUM_NODES, &mut rand::thread_rng())
+        .expect("failed to generate network infos")
+        .into_iter()
+        .map(|(id, netinfo)| (id, Arc::new(netinfo)))
+        .collect()
+}
+
+/// Handles the events, and all resulting messages, only via `step`, and checks that every
+/// transition is deterministic. Returns the nodes' outputs.
+fn run_pure_steps<D>(
+    states: &mut BTreeMap<usize, D>,
+    mut queue: VecDeque<(usize, Event<D>)>,
+) -> BTreeMap<usize, Vec<D::Output>>
+where
+    D: PureAlgorithm<NodeId = usize> + Debug,
+    D::Input: Clone + Debug,
+    D::Message: Clone + Debug,
+    D::Output: Debug,
+{
+    let mut outputs: BTreeMap<usize, Vec<D::Output>> = BTreeMap::new();
+    while let Some((id, event)) = queue.pop_front() {
+        let (next, result) = {
+            let state = &states[&id];
+            let (next, result) = step(state, event.clone()).expect("transition failed");
+            // The same event in the same state always leads to the same result.
+            let (again, result_again) = step(state, event).expect("transition failed");
+            assert_eq!(format!("{:?}", next), format!("{:?}", again));
+            assert_eq!(format!("{:?}", result), format!("{:?}", result_again));
+            (next, result)
+        };
+        for msg in result.messages {
+            let recipients: Vec<usize> = match msg.target {
+                Target::All => (0..NUM_NODES).filter(|r| *r != id).collect(),
+                Target::AllExcept(exclude) => (0..NUM_NODES)
+                    .filter(|r| *r != id && !exclude.contains(r))
+                    .collect(),
+                Target::Node(r) => vec![r],
+                Target::Nodes(ids) => ids.into_iter().collect(),
+                Target::Observers => Vec::new(),
+            };
+            for r in recipients {
+                queue.push_back((r, Event::Message(id, msg.message.clone())));
+            }
+        }
+        outputs.entry(id).or_insert_with(Vec::new).extend(result.output);
+        states.insert(id, next);
+    }
+    outputs
+}
+
+/// Runs Binary Agreement only via `step`, and checks that every transition is deterministic and
+/// that the nodes agree.
+#[test]
+fn test_binary_agreement_pure_steps() {
+    let mut states: BTreeMap<usize, InsecureBa> = netinfos()
+        .into_iter()
+        .map(|(id, netinfo)| {
+            let ba = BinaryAgreement::new_with_coin(netinfo, "test", 0)
+                .expect("could not create binary agreement");
+            (id, ba)
+        }).collect();
+
+    let queue = (0..NUM_NODES)
+        .map(|id| (id, Event::Input(id % 2 == 0)))
+        .collect();
+    let outputs = run_pure_steps(&mut states, queue);
+
+    assert!(states.values().all(|ba| ba.terminated()));
+    let decision = outputs[&0][0];
+    for id in 0..NUM_NODES {
+        assert_eq!(vec![decision], outputs[&id]);
+    }
+}
+
+/// Runs Subset, including its Broadcast and Binary Agreement instances, only via `step`, and
+/// checks that every transition is deterministic and that the nodes output the same set.
+#[test]
+fn test_subset_pure_steps() {
+    let mut states: BTreeMap<usize, Subset<usize>> = netinfos()
+        .into_iter()
+        .map(|(id, netinfo)| {
+            let subset = Subset::new(netinfo, 0).expect("could not create subset");
+            (id, subset)
+        }).collect();
+
+    let queue = (0..NUM_NODES)
+        .map(|id| (id, Event::Input(format!("Proposal {}", id).into_bytes())))
+        .collect();
+    let outputs = run_pure_steps(&mut states, queue);
+
+    assert!(states.values().all(|subset| subset.terminated()));
+    let expected: BTreeSet<SubsetOutput<usize>> = outputs[&0].iter().cloned().collect();
+    assert!(expected.contains(&SubsetOutput::Done));
+    for id in 1..NUM_NODES {
+        assert_eq!(expected, outputs[&id].iter().cloned().collect::<BTreeSet<_>>());
+    }
+}
@@ -18,10 +18,15 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::iter::once;
 use std::sync::Arc;
 
-use hbbft::subset::{Subset, SubsetOutput};
-use hbbft::NetworkInfo;
+use hbbft::binary_agreement::{self, bool_set::BoolSet, MessageContent, SbvMessage};
+use hbbft::broadcast;
+use hbbft::subset::{Error, FaultKind, Message, Subset, SubsetOutput};
+use hbbft::validation::Validity;
+use hbbft::{AgreementEpoch, DistAlgorithm, NetworkInfo, Target};
 
-use network::{Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode};
+use network::{
+    test_netinfos, Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode,
+};
 
 type ProposedValue = Vec<u8>;
 
@@ -128,3 +133,174 @@ fn test_subset_1_node() {
     let network = new_network(1, 0, adversary);
     test_subset(network, &proposals);
 }
+
+#[test]
+fn test_subset_unknown_proposer() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut subset = Subset::new(netinfo, 0).expect("subset instance");
+
+    // A message about a proposer that doesn't exist is reported, but doesn't cause an error.
+    let msg = Message::Broadcast(NodeId(9), broadcast::Message::Ready([1; 32]));
+    let step = subset
+        .handle_message(&NodeId(1), msg)
+        .expect("handle message");
+    let validity: Vec<_> = step
+        .validation
+        .0
+        .into_iter()
+        .map(|validation| (validation.sender_id, validation.validity))
+        .collect();
+    assert_eq!(
+        vec![(NodeId(1), Validity::Faulty(FaultKind::UnknownProposer))],
+        validity
+    );
+    assert_eq!(1, step.fault_log.0.len());
+}
+
+#[test]
+fn test_subset_coalesced_messages() {
+    let _ = env_logger::try_init();
+
+    let proposals: BTreeMap<NodeId, ProposedValue> = (0..5)
+        .map(|i| (NodeId(i), format!("Proposal {}", i).into_bytes()))
+        .collect();
+    let new_subset = |netinfo: Arc<NetworkInfo<NodeId>>| {
+        let mut subset = Subset::new(netinfo, 0).expect("new Subset instance");
+        subset.set_coalesce_messages(true);
+        subset
+    };
+    let adversary = |_| SilentAdversary::new(MessageScheduler::Random);
+    let network = TestNetwork::new(5, 2, adversary, new_subset);
+    test_subset(network, &proposals);
+}
+
+#[test]
+fn test_subset_max_buffered_bytes() {
+    let ids = (0..4).map(NodeId);
+    let mut netinfos: BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>> =
+        NetworkInfo::generate_map(ids, &mut rand::thread_rng())
+            .expect("Failed to create `NetworkInfo` map")
+            .into_iter()
+            .map(|(id, netinfo)| (id, Arc::new(netinfo)))
+            .collect();
+    let mut subset = Subset::new(netinfos[&NodeId(0)].clone(), 0).expect("subset instance");
+    subset.set_max_buffered_bytes(100);
+
+    // Returns the `Value` message that the proposer sends to node 0 for the given proposal.
+    let mut value_for_0 = |proposer_id: NodeId, value: Vec<u8>| {
+        let netinfo = netinfos.remove(&proposer_id).expect("proposer's `NetworkInfo`");
+        let mut bc = broadcast::Broadcast::new(netinfo, proposer_id).expect("broadcast instance");
+        let step = bc.handle_input(value).expect("handle input");
+        let msg = step
+            .messages
+            .into_iter()
+            .find(|tm| tm.target == Target::Node(NodeId(0)))
+            .expect("`Value` for node 0")
+            .message;
+        Message::Broadcast(proposer_id, msg)
+    };
+    let small_msg = value_for_0(NodeId(1), vec![1; 10]);
+    let large_msg = value_for_0(NodeId(2), vec![2; 300]);
+
+    // A small shard is within the limit.
+    let step = subset
+        .handle_message(&NodeId(1), small_msg)
+        .expect("handle small value");
+    assert!(step.fault_log.is_empty());
+
+    // A shard of a 300 bytes value exceeds it, so the instance is abandoned and the proposer
+    // reported.
+    let step = subset
+        .handle_message(&NodeId(2), large_msg.clone())
+        .expect("handle large value");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(
+        vec![(NodeId(2), FaultKind::BroadcastBufferExceeded)],
+        faults
+    );
+
+    // Later messages for the abandoned instance are ignored.
+    let step = subset
+        .handle_message(&NodeId(2), large_msg)
+        .expect("handle large value again");
+    assert!(step.fault_log.is_empty());
+    assert!(step.messages.is_empty());
+}
+
+#[test]
+fn test_subset_abort_on_error() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut subset = Subset::new(netinfo, 0).expect("subset instance");
+    subset.set_max_agreement_epochs(1);
+    let ba_msg = |msg| {
+        let content = MessageContent::SbvBroadcast(msg).with_epoch(AgreementEpoch::new(0));
+        Message::BinaryAgreement(NodeId(3), content)
+    };
+
+    // Two `BVal(false)`s make us relay it, so that we have three and send `Aux(false)`.
+    for &id in &[1, 2] {
+        let _ = subset
+            .handle_message(&NodeId(id), ba_msg(SbvMessage::BVal(false)))
+            .expect("handle `BVal`");
+    }
+    let _ = subset
+        .handle_message(&NodeId(1), ba_msg(SbvMessage::Aux(false)))
+        .expect("handle `Aux`");
+    assert!(!subset.terminated());
+
+    // The third `Aux(false)` completes the agreement epoch, but the coin value is `true`, so
+    // proposer 3's instance would have to move on to epoch 1, which exceeds the limit. All
+    // instances abort, and retrying the message is ignored.
+    let limit_reached =
+        Error::ProcessBinaryAgreement0(binary_agreement::Error::EpochLimitReached(1));
+    let err = subset
+        .handle_message(&NodeId(2), ba_msg(SbvMessage::Aux(false)))
+        .expect_err("epoch limit");
+    assert_eq!(limit_reached, err);
+    assert!(subset.terminated());
+    let step = subset
+        .handle_message(&NodeId(2), ba_msg(SbvMessage::Aux(false)))
+        .expect("handle `Aux` after abort");
+    assert!(step.output.is_empty());
+    assert!(step.messages.is_empty());
+}
+
+#[test]
+fn test_subset_abort_undecided() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let mut subset = Subset::new(netinfo, 0).expect("subset instance");
+    subset.set_abort_undecided(true);
+    let ba_msg = |proposer, content: MessageContent| {
+        Message::BinaryAgreement(NodeId(proposer), content.with_epoch(AgreementEpoch::new(0)))
+    };
+
+    // Nodes 1 and 2 make us decide "yes" for proposers 0, 1 and 2 in epoch 0, whose coin is
+    // `true`.
+    for proposer in 0..3 {
+        let contents = vec![
+            MessageContent::SbvBroadcast(SbvMessage::BVal(true)),
+            MessageContent::SbvBroadcast(SbvMessage::Aux(true)),
+            MessageContent::Conf(BoolSet::from(true)),
+        ];
+        for content in contents {
+            for &id in &[1, 2] {
+                let _ = subset
+                    .handle_message(&NodeId(id), ba_msg(proposer, content.clone()))
+                    .expect("handle Binary Agreement message");
+            }
+        }
+    }
+
+    // That is enough to reject proposer 3 without waiting for its instance: It is aborted, so
+    // all instances have terminated, and messages about proposer 3 are ignored.
+    assert!(subset.terminated());
+    let msg = ba_msg(3, MessageContent::SbvBroadcast(SbvMessage::BVal(true)));
+    let step = subset.handle_message(&NodeId(1), msg).expect("handle `BVal`");
+    assert!(step.output.is_empty());
+    assert!(step.messages.is_empty());
+}
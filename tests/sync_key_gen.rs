@@ -8,9 +8,12 @@ extern crate threshold_crypto as crypto;
 
 use std::collections::BTreeMap;
 
-use crypto::{PublicKey, SecretKey};
+use crypto::{PublicKey, SecretKey, SecretKeySet};
 
-use hbbft::sync_key_gen::{PartOutcome, SyncKeyGen};
+use hbbft::sync_key_gen::{
+    ComplaintOutcome, FaultKind, JustificationOutcome, OldKeys, PartOutcome, SyncKeyGen,
+};
+use hbbft::wire::{self, Error as WireError};
 
 fn test_sync_key_gen_with(threshold: usize, node_num: usize) {
     // Generate individual key pairs for encryption. These are not suitable for threshold schemes.
@@ -99,3 +102,331 @@ fn test_sync_key_gen() {
         test_sync_key_gen_with(threshold, node_num);
     }
 }
+
+/// Creates a network of five nodes with threshold one, where the dealers 0 and 4 encrypt node 1's
+/// row to the wrong key. Node 1 complains about both. Dealer 0 answers with a valid
+/// justification, but dealer 4's justification is forged, so its part is excluded.
+#[test]
+fn test_sync_key_gen_complaints() {
+    let _ = env_logger::try_init();
+
+    let mut rng = rand::thread_rng();
+    let (threshold, node_num) = (1, 5);
+    let bad_dealers = [0, 4];
+    let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(SecretKey::public_key)
+        .enumerate()
+        .collect();
+    let mut wrong_pub_keys = pub_keys.clone();
+    wrong_pub_keys.insert(1, SecretKey::random().public_key());
+
+    // The bad dealers use the wrong key for node 1. They only deal, and don't acknowledge parts.
+    let mut nodes = BTreeMap::new();
+    let mut parts = Vec::new();
+    for (id, sk) in sec_keys.into_iter().enumerate() {
+        let keys = if bad_dealers.contains(&id) {
+            wrong_pub_keys.clone()
+        } else {
+            pub_keys.clone()
+        };
+        let (sync_key_gen, opt_part) = SyncKeyGen::new(&mut rng, id, sk, keys, threshold)
+            .unwrap_or_else(|_| panic!("Failed to create `SyncKeyGen` instance #{}", id));
+        nodes.insert(id, sync_key_gen);
+        parts.push((id, opt_part.expect("part")));
+    }
+    let correct_ids = [1, 2, 3];
+
+    // Node 1 complains about the bad dealers' parts. All other rows are valid.
+    let mut acks = Vec::new();
+    let mut complaints = Vec::new();
+    for (sender_id, part) in parts {
+        for (&id, node) in &mut nodes {
+            if !correct_ids.contains(&id) && id != sender_id {
+                continue;
+            }
+            match node.handle_part(&mut rng, &sender_id, part.clone()) {
+                Some(PartOutcome::Valid(ack)) => {
+                    if correct_ids.contains(&id) {
+                        acks.push((id, ack));
+                    }
+                }
+                Some(PartOutcome::Complaint(complaint)) => {
+                    assert_eq!(1, id);
+                    assert!(bad_dealers.contains(&sender_id));
+                    complaints.push((id, complaint));
+                }
+                _ => panic!("unexpected outcome of handling a part"),
+            }
+        }
+    }
+    assert_eq!(2, complaints.len());
+
+    // The bad dealers answer with their justifications.
+    let mut justifications = BTreeMap::new();
+    for (sender_id, complaint) in complaints {
+        let proposer_id = complaint.proposer_index() as usize;
+        for (&id, node) in &mut nodes {
+            if !correct_ids.contains(&id) && id != proposer_id {
+                continue;
+            }
+            match node.handle_complaint(&sender_id, complaint.clone()) {
+                ComplaintOutcome::Valid(Some(justification)) => {
+                    assert!(bad_dealers.contains(&id));
+                    justifications.insert(id, justification);
+                }
+                ComplaintOutcome::Valid(None) => (),
+                ComplaintOutcome::Invalid(faults) => panic!("Invalid complaint: {:?}", faults),
+            }
+        }
+    }
+    assert_eq!(2, justifications.len());
+
+    for (sender_id, ack) in acks {
+        for &id in &correct_ids {
+            let node = nodes.get_mut(&id).unwrap();
+            let faults = node.handle_ack(&sender_id, ack.clone());
+            assert!(faults.is_empty());
+        }
+    }
+    for &id in &correct_ids {
+        // While the complaints are unanswered, the bad dealers' parts are not complete.
+        assert!(!nodes[&id].is_node_ready(&0));
+        assert!(!nodes[&id].is_node_ready(&4));
+        assert!(nodes[&id].is_node_ready(&2));
+    }
+
+    // Dealer 0 reveals node 1's row, and node 1 acknowledges the part. Dealer 4 sends dealer 0's
+    // justification, which doesn't match its own commitment.
+    let mut late_ack = None;
+    for &id in &correct_ids {
+        let node = nodes.get_mut(&id).unwrap();
+        match node.handle_justification(&mut rng, &0, justifications[&0].clone()) {
+            JustificationOutcome::Valid(Some(ack)) => {
+                assert_eq!(1, id);
+                late_ack = Some(ack);
+            }
+            JustificationOutcome::Valid(None) => assert_ne!(1, id),
+            JustificationOutcome::Invalid(faults) => panic!("Invalid justification: {:?}", faults),
+        }
+        match node.handle_justification(&mut rng, &4, justifications[&0].clone()) {
+            JustificationOutcome::Invalid(faults) => {
+                assert_eq!(1, faults.0.len());
+                assert_eq!(4, faults.0[0].node_id);
+                assert_eq!(FaultKind::InvalidJustification, faults.0[0].kind);
+            }
+            JustificationOutcome::Valid(_) => panic!("forged justification was accepted"),
+        }
+    }
+    let late_ack = late_ack.expect("node 1 acknowledges dealer 0's part");
+    for &id in &correct_ids {
+        let faults = nodes.get_mut(&id).unwrap().handle_ack(&1, late_ack.clone());
+        assert!(faults.is_empty());
+    }
+
+    // All correct nodes use the same parts, excluding dealer 4's.
+    let msg = "Dealer 4 is not invited";
+    let pub_key_set = nodes[&1].generate().expect("generate keys").0;
+    let mut sig_shares = BTreeMap::new();
+    for &id in &correct_ids {
+        let node = &nodes[&id];
+        assert!(node.is_ready());
+        assert!(node.is_node_ready(&0));
+        assert!(!node.is_node_ready(&4));
+        assert_eq!(4, node.count_complete());
+        let (pks, opt_sk) = node.generate().expect("generate keys");
+        assert_eq!(pks, pub_key_set);
+        let sig = opt_sk.expect("new secret key").sign(msg);
+        assert!(pks.public_key_share(id).verify(&sig, msg));
+        sig_shares.insert(id, sig);
+    }
+    let sig = pub_key_set
+        .combine_signatures(sig_shares.iter().take(threshold + 1))
+        .expect("signature shares match");
+    assert!(pub_key_set.public_key().verify(&sig, msg));
+}
+
+/// Deals the master key of a four-node network with threshold one to a new network of six nodes
+/// with threshold one, with the old nodes 1 and 3 as dealers. The new keys must have the same
+/// master key.
+#[test]
+fn test_sync_key_gen_resharing() {
+    let _ = env_logger::try_init();
+
+    let mut rng = rand::thread_rng();
+    let old_sk_set = SecretKeySet::random(1, &mut rng);
+    let old_pk_set = old_sk_set.public_keys();
+    let (threshold, node_num) = (1, 6);
+    let dealers: BTreeMap<usize, usize> = vec![(1, 1), (3, 3)].into_iter().collect();
+    let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(SecretKey::public_key)
+        .enumerate()
+        .collect();
+
+    // Only the dealers create parts.
+    let mut nodes = BTreeMap::new();
+    let mut parts = Vec::new();
+    for (id, sk) in sec_keys.into_iter().enumerate() {
+        let old_keys = OldKeys {
+            pk_set: old_pk_set.clone(),
+            dealers: dealers.clone(),
+            secret_key_share: if id < 4 {
+                Some(old_sk_set.secret_key_share(id))
+            } else {
+                None
+            },
+        };
+        let (sync_key_gen, opt_part) =
+            SyncKeyGen::new_resharing(&mut rng, id, sk, pub_keys.clone(), threshold, old_keys)
+                .unwrap_or_else(|_| panic!("Failed to create `SyncKeyGen` instance #{}", id));
+        assert_eq!(dealers.contains_key(&id), opt_part.is_some());
+        nodes.insert(id, sync_key_gen);
+        parts.extend(opt_part.map(|part| (id, part)));
+    }
+
+    // A part from a node that is not a dealer is rejected by everyone.
+    let fresh_sk = SecretKey::random();
+    let (_, opt_fresh_part) = SyncKeyGen::new(&mut rng, 0, fresh_sk, pub_keys.clone(), threshold)
+        .expect("Failed to create `SyncKeyGen` instance");
+    let fresh_part = opt_fresh_part.expect("part");
+
+    // Parts with an offset need wire version 3, while a fresh key's part can still be sent to
+    // peers running version 2.
+    let result = wire::encode_with_version(&parts[0].1, 2);
+    assert_eq!(Err(WireError::UnsupportedValue("PartOffset", 2)), result);
+    let bytes = wire::encode_with_version(&fresh_part, 2).expect("encode part");
+    assert_eq!(fresh_part, wire::decode(&bytes).expect("decode part"));
+    let bytes = wire::encode(&parts[0].1).expect("encode part");
+    assert_eq!(parts[0].1, wire::decode(&bytes).expect("decode part"));
+
+    for node in nodes.values_mut() {
+        match node.handle_part(&mut rng, &0, fresh_part.clone()) {
+            Some(PartOutcome::Invalid(faults)) => {
+                assert_eq!(FaultKind::InvalidPartMessage, faults.0[0].kind)
+            }
+            _ => panic!("a part from a non-dealer was accepted"),
+        }
+    }
+
+    // Everyone acknowledges the dealers' parts.
+    let mut acks = Vec::new();
+    for (sender_id, part) in parts {
+        for (&id, node) in &mut nodes {
+            match node.handle_part(&mut rng, &sender_id, part.clone()) {
+                Some(PartOutcome::Valid(ack)) => acks.push((id, ack)),
+                _ => panic!("invalid part"),
+            }
+        }
+    }
+    for (sender_id, ack) in acks {
+        for node in nodes.values_mut() {
+            assert!(!node.is_ready());
+            assert!(node.handle_ack(&sender_id, ack.clone()).is_empty());
+        }
+    }
+
+    // The new key shares combine to signatures that are valid for the old master key.
+    let msg = "Same key, new shares";
+    let mut sig_shares = BTreeMap::new();
+    for (&id, node) in &nodes {
+        assert!(node.is_ready());
+        let (pks, opt_sk) = node.generate().expect("generate keys");
+        assert_eq!(old_pk_set.public_key(), pks.public_key());
+        let sig = opt_sk.expect("new secret key").sign(msg);
+        assert!(pks.public_key_share(id).verify(&sig, msg));
+        sig_shares.insert(id, sig);
+    }
+    let pub_key_set = nodes[&0].generate().expect("generate keys").0;
+    let sig = pub_key_set
+        .combine_signatures(sig_shares.iter().skip(3).take(threshold + 1))
+        .expect("signature shares match");
+    assert!(old_pk_set.public_key().verify(&sig, msg));
+}
+
+/// Refreshes the key shares of a four-node network with threshold one. The master key stays the
+/// same, but the shares change.
+#[test]
+fn test_sync_key_gen_refresh() {
+    let _ = env_logger::try_init();
+
+    let mut rng = rand::thread_rng();
+    let (threshold, node_num) = (1, 4);
+    let old_sk_set = SecretKeySet::random(threshold, &mut rng);
+    let old_pk_set = old_sk_set.public_keys();
+    let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(SecretKey::public_key)
+        .enumerate()
+        .collect();
+
+    let mut nodes = BTreeMap::new();
+    let mut parts = Vec::new();
+    for (id, sk) in sec_keys.into_iter().enumerate() {
+        let sk_share = old_sk_set.secret_key_share(id);
+        let (sync_key_gen, opt_part) = SyncKeyGen::new_refresh(
+            &mut rng,
+            id,
+            sk,
+            pub_keys.clone(),
+            old_pk_set.clone(),
+            Some(sk_share),
+        ).unwrap_or_else(|_| panic!("Failed to create `SyncKeyGen` instance #{}", id));
+        nodes.insert(id, sync_key_gen);
+        parts.push((id, opt_part.expect("part")));
+    }
+
+    // A part that doesn't share zero is rejected by everyone.
+    let (_, opt_fresh_part) =
+        SyncKeyGen::new(&mut rng, 0, SecretKey::random(), pub_keys.clone(), threshold)
+            .expect("Failed to create `SyncKeyGen` instance");
+    let fresh_part = opt_fresh_part.expect("part");
+    for node in nodes.values_mut() {
+        match node.handle_part(&mut rng, &0, fresh_part.clone()) {
+            Some(PartOutcome::Invalid(faults)) => {
+                assert_eq!(FaultKind::InvalidPartMessage, faults.0[0].kind)
+            }
+            _ => panic!("a part with a nonzero constant term was accepted"),
+        }
+    }
+
+    // Node 0's refresh part arrives too late: The others' are enough.
+    let mut acks = Vec::new();
+    for (sender_id, part) in parts.into_iter().skip(1) {
+        for (&id, node) in &mut nodes {
+            match node.handle_part(&mut rng, &sender_id, part.clone()) {
+                Some(PartOutcome::Valid(ack)) => acks.push((id, ack)),
+                _ => panic!("invalid part"),
+            }
+        }
+    }
+    for (sender_id, ack) in acks {
+        for node in nodes.values_mut() {
+            assert!(node.handle_ack(&sender_id, ack.clone()).is_empty());
+        }
+    }
+
+    // The new shares differ from the old ones, but they combine to signatures that are valid for
+    // the same master key.
+    let msg = "Fresh shares for an old key";
+    let pub_key_set = nodes[&0].generate().expect("generate keys").0;
+    assert_eq!(old_pk_set.public_key(), pub_key_set.public_key());
+    let mut sig_shares = BTreeMap::new();
+    for (&id, node) in &nodes {
+        assert!(node.is_ready());
+        let (pks, opt_sk) = node.generate().expect("generate keys");
+        assert_eq!(pks, pub_key_set);
+        assert_ne!(old_pk_set.public_key_share(id), pks.public_key_share(id));
+        let sig = opt_sk.expect("new secret key").sign(msg);
+        assert!(pks.public_key_share(id).verify(&sig, msg));
+        assert!(!old_pk_set.public_key_share(id).verify(&sig, msg));
+        sig_shares.insert(id, sig);
+    }
+    let sig = pub_key_set
+        .combine_signatures(sig_shares.iter().take(threshold + 1))
+        .expect("signature shares match");
+    assert!(old_pk_set.public_key().verify(&sig, msg));
+}
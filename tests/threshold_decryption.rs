@@ -0,0 +1,175 @@
+#![deny(unused_must_use)]
+//! Threshold decryption tests
+
+extern crate env_logger;
+extern crate hbbft;
+#[macro_use]
+extern crate log;
+extern crate rand;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate rand_derive;
+extern crate threshold_crypto as crypto;
+
+mod network;
+
+use std::iter::once;
+use std::sync::{Arc, Mutex};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use hbbft::crypto_offload::{CryptoOffload, Verification};
+use hbbft::threshold_decryption::{FaultKind, Message, Step, ThresholdDecryption};
+use hbbft::{DistAlgorithm, NetworkInfo};
+
+use network::{
+    test_netinfos, Adversary, MessageScheduler, NodeId, SilentAdversary, TestNetwork, TestNode,
+};
+
+const PLAINTEXT: &[u8] = b"Hello, threshold decryption!";
+
+/// Creates an instance, and inputs the ciphertext. All nodes use the same seed for encryption, so
+/// they all encrypt to the same ciphertext.
+fn new_decryption(
+    netinfo: Arc<NetworkInfo<NodeId>>,
+) -> (ThresholdDecryption<NodeId>, Step<NodeId>) {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let pub_key = netinfo.public_key_set().public_key();
+    let ciphertext = pub_key.encrypt_with_rng(&mut rng, PLAINTEXT);
+    let mut td = ThresholdDecryption::new(netinfo);
+    let step = td.handle_input(ciphertext).expect("input ciphertext");
+    (td, step)
+}
+
+fn test_threshold_decryption<A>(mut network: TestNetwork<A, ThresholdDecryption<NodeId>>)
+where
+    A: Adversary<ThresholdDecryption<NodeId>>,
+{
+    // Handle messages until all good nodes have terminated.
+    while !network.nodes.values().all(TestNode::terminated) {
+        network.step();
+    }
+    // Verify that all instances output the plaintext.
+    for node in network.nodes.values() {
+        assert!(once(&PLAINTEXT.to_vec()).eq(node.outputs()));
+    }
+    assert!(once(&PLAINTEXT.to_vec()).eq(network.observer.outputs()));
+}
+
+fn test_threshold_decryption_different_sizes<A, F>(new_adversary: F)
+where
+    A: Adversary<ThresholdDecryption<NodeId>>,
+    F: Fn(usize, usize) -> A,
+{
+    // This returns an error in all but the first test.
+    let _ = env_logger::try_init();
+
+    let mut rng = rand::thread_rng();
+    let sizes = (1..6).chain(once(rng.gen_range(6, 20)));
+    for size in sizes {
+        let num_faulty_nodes = (size - 1) / 3;
+        let num_good_nodes = size - num_faulty_nodes;
+        info!(
+            "Network size: {} good nodes, {} faulty nodes",
+            num_good_nodes, num_faulty_nodes
+        );
+        let adversary = |_| new_adversary(num_good_nodes, num_faulty_nodes);
+        let network =
+            TestNetwork::new_with_step(num_good_nodes, num_faulty_nodes, adversary, new_decryption);
+        test_threshold_decryption(network);
+    }
+}
+
+#[test]
+fn test_threshold_decryption_random_silent() {
+    let new_adversary = |_: usize, _: usize| SilentAdversary::new(MessageScheduler::Random);
+    test_threshold_decryption_different_sizes(new_adversary);
+}
+
+#[test]
+fn test_threshold_decryption_first_silent() {
+    let new_adversary = |_: usize, _: usize| SilentAdversary::new(MessageScheduler::First);
+    test_threshold_decryption_different_sizes(new_adversary);
+}
+
+#[test]
+fn test_threshold_decryption_invalid_share() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let (mut td, _) = new_decryption(netinfo);
+
+    // A share for a different ciphertext is rejected, and its sender is reported.
+    let pub_key = netinfos[&NodeId(1)].public_key_set().public_key();
+    let other_ciphertext = pub_key.encrypt(b"Something else");
+    let share = netinfos[&NodeId(1)]
+        .secret_key_share()
+        .decrypt_share(&other_ciphertext)
+        .expect("decryption share");
+    let step = td
+        .handle_message(&NodeId(1), Message(share))
+        .expect("handle message");
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(
+        vec![(NodeId(1), FaultKind::UnverifiedDecryptionShareSender)],
+        faults
+    );
+    assert!(step.output.is_empty());
+}
+
+/// A `CryptoOffload` that counts the verifications it performs.
+#[derive(Debug, Default)]
+struct CountingOffload {
+    count: Mutex<usize>,
+}
+
+impl CryptoOffload for CountingOffload {
+    fn verify_all(&self, tasks: Vec<Verification>) -> Vec<bool> {
+        *self.count.lock().unwrap() += tasks.len();
+        tasks.iter().map(Verification::verify).collect()
+    }
+}
+
+#[test]
+fn test_threshold_decryption_offload() {
+    let (netinfo, _) = test_netinfos(4, NodeId(0));
+    let pub_key = netinfo.public_key_set().public_key();
+    let ciphertext = pub_key.encrypt(PLAINTEXT);
+    let other_ciphertext = pub_key.encrypt(b"Something else");
+    let offload = Arc::new(CountingOffload::default());
+    let mut td = ThresholdDecryption::new(netinfo);
+    td.set_crypto_offload(offload.clone());
+
+    // Shares received before the ciphertext can't be verified yet.
+    for (id, ct) in vec![(NodeId(1), &other_ciphertext), (NodeId(2), &ciphertext)] {
+        let share = netinfos[&id]
+            .secret_key_share()
+            .decrypt_share(ct)
+            .expect("decryption share");
+        let step = td
+            .handle_message(&id, Message(share))
+            .expect("handle message");
+        assert!(step.fault_log.is_empty());
+    }
+    assert_eq!(0, *offload.count.lock().unwrap());
+
+    // Once the ciphertext is known, both are verified in one batch, and the invalid one is
+    // reported.
+    let step = td.handle_input(ciphertext).expect("input ciphertext");
+    assert_eq!(2, *offload.count.lock().unwrap());
+    let faults: Vec<_> = step
+        .fault_log
+        .0
+        .into_iter()
+        .map(|fault| (fault.node_id, fault.kind))
+        .collect();
+    assert_eq!(
+        vec![(NodeId(1), FaultKind::UnverifiedDecryptionShareSender)],
+        faults
+    );
+    assert!(once(&PLAINTEXT.to_vec()).eq(&step.output));
+}
@@ -0,0 +1,228 @@
+#![deny(unused_must_use)]
+//! Tests of the canonical wire encoding.
+
+extern crate hbbft;
+extern crate rand;
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "testing")]
+use rand::Rng;
+
+use hbbft::binary_agreement::{self, MessageContent};
+use hbbft::validation::{Validation, Validity};
+use hbbft::wire::{self, Algorithm, Error, FaultKind, MIN_WIRE_VERSION, WIRE_VERSION};
+use hbbft::broadcast;
+use hbbft::AgreementEpoch;
+
+/// The number of random messages of each type to test.
+#[cfg(feature = "testing")]
+const ROUNDS: usize = 50;
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_binary_agreement_round_trip() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..ROUNDS {
+        let msg: binary_agreement::Message = rng.gen();
+        let bytes = wire::encode(&msg).expect("encode message");
+        assert_eq!(msg, wire::decode(&bytes).expect("decode message"));
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_broadcast_round_trip() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..ROUNDS {
+        let msg: broadcast::Message = rng.gen();
+        let bytes = wire::encode(&msg).expect("encode message");
+        assert_eq!(msg, wire::decode(&bytes).expect("decode message"));
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_crypto_messages_round_trip() {
+    use hbbft::{coin, threshold_decryption};
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..ROUNDS {
+        let msg: coin::CoinMessage = rng.gen();
+        let bytes = wire::encode(&msg).expect("encode message");
+        assert_eq!(msg, wire::decode(&bytes).expect("decode message"));
+
+        let msg: threshold_decryption::Message = rng.gen();
+        let bytes = wire::encode(&msg).expect("encode message");
+        assert_eq!(msg, wire::decode(&bytes).expect("decode message"));
+    }
+}
+
+/// Pins the exact encoding of some messages: If this test fails, the wire format has changed and
+/// `WIRE_VERSION` must be increased.
+#[test]
+fn test_encoding_is_stable() {
+    assert_eq!(3, WIRE_VERSION);
+
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(3));
+    let expected = vec![WIRE_VERSION, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 2, 1];
+    assert_eq!(expected, wire::encode(&msg).expect("encode message"));
+
+    let msg = broadcast::Message::Ready([b'r'; 32]);
+    let mut expected = vec![0, 0, 0, 2];
+    expected.extend_from_slice(&[b'r'; 32]);
+    assert_eq!(expected, wire::to_bytes(&msg).expect("encode message"));
+
+    let value: (Option<u16>, String, Vec<bool>) = (Some(0x0102), "hb".to_string(), vec![false]);
+    let expected = vec![1, 1, 2, 0, 0, 0, 2, b'h', b'b', 0, 0, 0, 1, 0];
+    assert_eq!(expected, wire::to_bytes(&value).expect("encode value"));
+}
+
+#[test]
+fn test_non_canonical_input_is_rejected() {
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(3));
+    let bytes = wire::encode(&msg).expect("encode message");
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    let result: Result<binary_agreement::Message, _> = wire::decode(&trailing);
+    assert_eq!(Err(Error::TrailingBytes(1)), result);
+
+    let mut invalid_bool = bytes.clone();
+    *invalid_bool.last_mut().unwrap() = 2;
+    let result: Result<binary_agreement::Message, _> = wire::decode(&invalid_bool);
+    assert_eq!(Err(Error::InvalidBool(2)), result);
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[0] = WIRE_VERSION + 1;
+    let result: Result<binary_agreement::Message, _> = wire::decode(&wrong_version);
+    assert_eq!(Err(Error::UnsupportedVersion(WIRE_VERSION + 1)), result);
+
+    let result: Result<binary_agreement::Message, _> = wire::decode(&bytes[..bytes.len() - 1]);
+    assert_eq!(Err(Error::UnexpectedEnd), result);
+}
+
+#[test]
+fn test_map_keys_are_sorted() {
+    // The encoding of `"b"` is shorter, so it comes first, although `"aa"` is the smaller key.
+    let map: BTreeMap<String, u8> = vec![("aa".to_string(), 2), ("b".to_string(), 1)]
+        .into_iter()
+        .collect();
+    let bytes = wire::encode(&map).expect("encode map");
+    let entry_b = vec![0, 0, 0, 1, b'b', 1];
+    let entry_aa = vec![0, 0, 0, 2, b'a', b'a', 2];
+    let mut expected = vec![WIRE_VERSION, 0, 0, 0, 2];
+    expected.extend(entry_b.iter().chain(&entry_aa));
+    assert_eq!(expected, bytes);
+    assert_eq!(map, wire::decode(&bytes).expect("decode map"));
+
+    // Any other order, and duplicate keys, are rejected.
+    let mut unordered = vec![WIRE_VERSION, 0, 0, 0, 2];
+    unordered.extend(entry_aa.iter().chain(&entry_b));
+    let result: Result<BTreeMap<String, u8>, _> = wire::decode(&unordered);
+    assert_eq!(Err(Error::UnorderedMapKeys), result);
+    let mut duplicate = vec![WIRE_VERSION, 0, 0, 0, 2];
+    duplicate.extend(entry_b.iter().chain(&entry_b));
+    let result: Result<BTreeMap<String, u8>, _> = wire::decode(&duplicate);
+    assert_eq!(Err(Error::UnorderedMapKeys), result);
+
+    // Version 2 didn't check the order.
+    unordered[0] = 2;
+    assert_eq!(map, wire::decode(&unordered).expect("decode version 2 map"));
+}
+
+#[test]
+fn test_tagged_message_round_trip() {
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(3));
+    let bytes = wire::encode_message(&msg).expect("encode message");
+    let tag = Algorithm::BinaryAgreement.tag();
+    assert_eq!(&[WIRE_VERSION, tag], &bytes[..2]);
+    assert_eq!(&wire::to_bytes(&msg).expect("encode message")[..], &bytes[2..]);
+    assert_eq!(Some(Algorithm::BinaryAgreement), Algorithm::from_tag(tag));
+    assert_eq!(msg, wire::decode_message(&bytes).expect("decode message"));
+
+    // A message can't be decoded as another algorithm's.
+    let result: Result<broadcast::Message, _> = wire::decode_message(&bytes);
+    assert_eq!(Err(Error::UnexpectedAlgorithm(tag)), result);
+}
+
+#[test]
+fn test_version_negotiation() {
+    assert_eq!(Some(WIRE_VERSION), wire::negotiate_version(0, WIRE_VERSION + 5));
+    assert_eq!(Some(WIRE_VERSION), wire::negotiate_version(WIRE_VERSION, WIRE_VERSION));
+    assert_eq!(None, wire::negotiate_version(WIRE_VERSION + 1, WIRE_VERSION + 5));
+    assert_eq!(None, wire::negotiate_version(0, MIN_WIRE_VERSION - 1));
+    assert_eq!(Some(MIN_WIRE_VERSION), wire::negotiate_version(0, MIN_WIRE_VERSION));
+}
+
+/// Pins the encoding of a message in version 1, which encodes agreement epochs as `u32`.
+#[test]
+fn test_version_1_encoding() {
+    assert_eq!(1, MIN_WIRE_VERSION);
+
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(3));
+    let bytes = wire::encode_with_version(&msg, 1).expect("encode message");
+    assert_eq!(vec![1, 0, 0, 0, 3, 0, 0, 0, 2, 1], bytes);
+    assert_eq!(msg, wire::decode(&bytes).expect("decode message"));
+
+    let bytes = wire::encode_message_with_version(&msg, 1).expect("encode message");
+    assert_eq!(&[1, Algorithm::BinaryAgreement.tag()], &bytes[..2]);
+    assert_eq!(msg, wire::decode_message(&bytes).expect("decode message"));
+
+    // Epochs that don't fit into a `u32` can only be sent in later versions.
+    let late_epoch = AgreementEpoch::new(u64::from(u32::max_value()) + 1);
+    let msg = MessageContent::Term(true).with_epoch(late_epoch);
+    let result = wire::encode_with_version(&msg, 1);
+    assert_eq!(Err(Error::UnsupportedValue("AgreementEpoch", 1)), result);
+    let bytes = wire::encode_with_version(&msg, WIRE_VERSION).expect("encode message");
+    assert_eq!(msg, wire::decode(&bytes).expect("decode message"));
+
+    let result = wire::encode_with_version(&msg, MIN_WIRE_VERSION - 1);
+    assert_eq!(Err(Error::UnsupportedVersion(MIN_WIRE_VERSION - 1)), result);
+    let result = wire::encode_with_version(&msg, WIRE_VERSION + 1);
+    assert_eq!(Err(Error::UnsupportedVersion(WIRE_VERSION + 1)), result);
+}
+
+#[test]
+fn test_incompatible_message_validation() {
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(3));
+    let mut bytes = wire::encode_message(&msg).expect("encode message");
+    assert_eq!(Ok(msg), wire::decode_message_from(&7, &bytes));
+
+    bytes[0] = WIRE_VERSION + 1;
+    let result: Result<binary_agreement::Message, _> = wire::decode_message_from(&7, &bytes);
+    assert_eq!(Err(Validation::new(7, Validity::Incompatible)), result);
+
+    bytes[0] = WIRE_VERSION;
+    bytes.pop();
+    let result: Result<binary_agreement::Message, _> = wire::decode_message_from(&7, &bytes);
+    let faulty = Validity::Faulty(FaultKind::UndecodableMessage);
+    assert_eq!(Err(Validation::new(7, faulty)), result);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compressed_round_trip() {
+    use hbbft::wire::{COMPRESSED_FLAG, DEFAULT_COMPRESSION_THRESHOLD};
+
+    // Small values are sent uncompressed.
+    let msg = MessageContent::Term(true).with_epoch(AgreementEpoch::new(3));
+    let bytes = wire::encode_compressed(&msg, DEFAULT_COMPRESSION_THRESHOLD).expect("encode");
+    assert_eq!(wire::encode(&msg).expect("encode message"), bytes);
+
+    // Large, redundant values are compressed.
+    let value = vec![b'x'; 10 * DEFAULT_COMPRESSION_THRESHOLD];
+    let bytes = wire::encode_compressed(&value, DEFAULT_COMPRESSION_THRESHOLD).expect("encode");
+    assert_eq!(WIRE_VERSION | COMPRESSED_FLAG, bytes[0]);
+    assert!(bytes.len() < value.len());
+    let decoded: Vec<u8> = wire::decode(&bytes).expect("decode value");
+    assert_eq!(value, decoded);
+
+    // Compressed envelopes of older versions are decoded according to their version.
+    let msgs = vec![MessageContent::Term(true).with_epoch(AgreementEpoch::new(3)); 1000];
+    let bytes = wire::encode_compressed_with_version(&msgs, DEFAULT_COMPRESSION_THRESHOLD, 1)
+        .expect("encode messages");
+    assert_eq!(1 | COMPRESSED_FLAG, bytes[0]);
+    let decoded: Vec<binary_agreement::Message> = wire::decode(&bytes).expect("decode messages");
+    assert_eq!(msgs, decoded);
+}